@@ -12,37 +12,42 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::owner_session::OwnerSessions;
 use crate::router::{Handler, HandlerObj, ResponseFuture};
 use crate::web::response;
+use chrono::Utc;
 use futures::future::ok;
 use hyper::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
 use hyper::{Body, Request, Response, StatusCode};
 use ring::constant_time::verify_slices_are_equal;
+use std::sync::Arc;
 
 lazy_static! {
 	pub static ref MWC_BASIC_REALM: HeaderValue =
 		HeaderValue::from_str("Basic realm=MWC-API").unwrap();
 	pub static ref MWC_FOREIGN_BASIC_REALM: HeaderValue =
 		HeaderValue::from_str("Basic realm=MWCForeignAPI").unwrap();
+	pub static ref MWC_OWNER_BEARER_REALM: HeaderValue =
+		HeaderValue::from_str("Bearer realm=MWC-Owner-API").unwrap();
 }
 
 // Basic Authentication Middleware
 pub struct BasicAuthMiddleware {
 	api_basic_auth: String,
 	basic_realm: &'static HeaderValue,
-	ignore_uri: Option<String>,
+	ignore_uris: Vec<String>,
 }
 
 impl BasicAuthMiddleware {
 	pub fn new(
 		api_basic_auth: String,
 		basic_realm: &'static HeaderValue,
-		ignore_uri: Option<String>,
+		ignore_uris: Vec<String>,
 	) -> BasicAuthMiddleware {
 		BasicAuthMiddleware {
 			api_basic_auth,
 			basic_realm,
-			ignore_uri,
+			ignore_uris,
 		}
 	}
 }
@@ -60,10 +65,8 @@ impl Handler for BasicAuthMiddleware {
 		if req.method().as_str() == "OPTIONS" {
 			return next_handler.call(req, handlers);
 		}
-		if let Some(u) = self.ignore_uri.as_ref() {
-			if req.uri().path() == u {
-				return next_handler.call(req, handlers);
-			}
+		if uri_matches(req.uri().path(), &self.ignore_uris) {
+			return next_handler.call(req, handlers);
 		}
 		if req.headers().contains_key(AUTHORIZATION)
 			&& verify_slices_are_equal(
@@ -133,6 +136,70 @@ impl Handler for BasicAuthURIMiddleware {
 	}
 }
 
+// Owner API session token middleware
+pub struct OwnerSessionMiddleware {
+	sessions: Arc<OwnerSessions>,
+	target_uris: Vec<String>,
+	// Handlers of these URIs don't know about the sessions, the calls are recorded here
+	recorded_uris: Vec<String>,
+}
+
+impl OwnerSessionMiddleware {
+	pub fn new(
+		sessions: Arc<OwnerSessions>,
+		target_uris: Vec<String>,
+		recorded_uris: Vec<String>,
+	) -> OwnerSessionMiddleware {
+		OwnerSessionMiddleware {
+			sessions,
+			target_uris,
+			recorded_uris,
+		}
+	}
+}
+
+impl Handler for OwnerSessionMiddleware {
+	fn call(
+		&self,
+		mut req: Request<Body>,
+		mut handlers: Box<dyn Iterator<Item = HandlerObj>>,
+	) -> ResponseFuture {
+		let next_handler = match handlers.next() {
+			Some(h) => h,
+			None => return response(StatusCode::INTERNAL_SERVER_ERROR, "no handler found"),
+		};
+		if req.method().as_str() == "OPTIONS" || !uri_matches(req.uri().path(), &self.target_uris) {
+			return next_handler.call(req, handlers);
+		}
+		let now = Utc::now().timestamp();
+		let session = req
+			.headers()
+			.get(AUTHORIZATION)
+			.and_then(|h| h.to_str().ok())
+			.and_then(|h| h.strip_prefix("Bearer "))
+			.and_then(|token| self.sessions.validate(token.trim(), now));
+		match session {
+			Some(session) => {
+				if uri_matches(req.uri().path(), &self.recorded_uris) {
+					let action = format!("{} {}", req.method(), req.uri().path());
+					self.sessions.record(&session, &action, now);
+				}
+				// Handlers record the owner actions with the session
+				req.extensions_mut().insert(session);
+				next_handler.call(req, handlers)
+			}
+			// Unauthorized 401
+			None => unauthorized_response(&MWC_OWNER_BEARER_REALM),
+		}
+	}
+}
+
+/// Check the path against the URIs. The URI that ends with '/' matches all paths under it.
+pub fn uri_matches(path: &str, uris: &[String]) -> bool {
+	uris.iter()
+		.any(|u| path == u || (u.ends_with('/') && path.starts_with(u.as_str())))
+}
+
 fn unauthorized_response(basic_realm: &HeaderValue) -> ResponseFuture {
 	let response = Response::builder()
 		.status(StatusCode::UNAUTHORIZED)
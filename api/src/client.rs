@@ -15,6 +15,7 @@
 //! High level JSON/HTTP client API
 
 use crate::core::global;
+use crate::outbound::OutboundConnector;
 use crate::rest::{Error, ErrorKind};
 use crate::util::to_base64;
 use failure::Fail;
@@ -22,7 +23,6 @@ use http::uri::{InvalidUri, Uri};
 use hyper::body;
 use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
 use hyper::{Body, Client, Request};
-use hyper_rustls;
 use hyper_timeout::TimeoutConnector;
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -278,8 +278,7 @@ where
 }
 
 async fn send_request_async(req: Request<Body>, timeout: TimeOut) -> Result<String, Error> {
-	let https = hyper_rustls::HttpsConnector::new();
-	let mut connector = TimeoutConnector::new(https);
+	let mut connector = TimeoutConnector::new(OutboundConnector::new());
 	let (connect, read, write) = (
 		Some(timeout.connect),
 		Some(timeout.read),
@@ -0,0 +1,52 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cold storage mode. The node serves the chain from the exported snapshot without
+//! any network activity: the headers, blocks, kernels, outputs and merkle proofs are
+//! available, the calls that would change the chain or the pool are rejected.
+
+use crate::rest::{Error, ErrorKind};
+use crate::util::RwLock;
+
+lazy_static! {
+	// Snapshot directory, None if the node is not in the cold storage mode
+	static ref COLD_STORAGE: RwLock<Option<String>> = RwLock::new(None);
+}
+
+/// Start the node in the cold storage mode over the snapshot directory
+pub fn init_cold_storage(snapshot_dir: &str) {
+	*COLD_STORAGE.write() = Some(snapshot_dir.to_string());
+}
+
+/// True if the node serves the snapshot in the cold storage mode
+pub fn is_cold_storage() -> bool {
+	COLD_STORAGE.read().is_some()
+}
+
+/// Snapshot directory of the cold storage mode
+pub fn cold_storage_snapshot() -> Option<String> {
+	COLD_STORAGE.read().clone()
+}
+
+/// Error for the calls that are not available in the cold storage mode
+pub fn check_not_cold_storage(call: &str) -> Result<(), Error> {
+	if is_cold_storage() {
+		return Err(ErrorKind::ColdStorage(format!(
+			"{} is not available, the node serves the read-only snapshot",
+			call
+		))
+		.into());
+	}
+	Ok(())
+}
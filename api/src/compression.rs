@@ -0,0 +1,286 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Response compression of the API server. Large payloads (blocks, output ranges,
+//! txhashset metadata) are compressed with brotli or gzip if the client accepts it.
+//! It matters the most for the APIs that are served over Tor.
+
+use crate::router::{Handler, HandlerObj, ResponseFuture};
+use crate::util::RwLock;
+use crate::web::response;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, ETAG, VARY};
+use hyper::{Body, Request, Response, StatusCode};
+use std::io::{self, Write};
+
+// Brotli quality 5 is close to the gzip speed with a better ratio
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+lazy_static! {
+	static ref COMPRESSION_STATS: RwLock<ApiCompressionStats> =
+		RwLock::new(ApiCompressionStats::default());
+}
+
+/// Content encoding of the compressed API response
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentEncoding {
+	Gzip,
+	Brotli,
+}
+
+impl ContentEncoding {
+	/// Content-Encoding header value
+	pub fn name(&self) -> &'static str {
+		match self {
+			ContentEncoding::Gzip => "gzip",
+			ContentEncoding::Brotli => "br",
+		}
+	}
+
+	/// Compress the data with this encoding
+	pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, io::Error> {
+		match self {
+			ContentEncoding::Gzip => {
+				let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+				encoder.write_all(data)?;
+				encoder.finish()
+			}
+			ContentEncoding::Brotli => {
+				let mut encoder = brotli::CompressorWriter::new(
+					Vec::new(),
+					BROTLI_BUFFER_SIZE,
+					BROTLI_QUALITY,
+					BROTLI_WINDOW,
+				);
+				encoder.write_all(data)?;
+				encoder.flush()?;
+				Ok(encoder.into_inner())
+			}
+		}
+	}
+}
+
+/// Select the response encoding from the Accept-Encoding header value. The encoding
+/// with the highest quality value wins, brotli is preferred over gzip with the same one.
+/// None if the client accepts neither of them.
+pub fn negotiate_encoding(accept_encoding: &str) -> Option<ContentEncoding> {
+	let mut brotli_q = None;
+	let mut gzip_q = None;
+	let mut any_q = None;
+	for item in accept_encoding.split(',') {
+		let mut params = item.split(';');
+		let name = params.next().unwrap_or("").trim().to_lowercase();
+		let q = params
+			.filter_map(|p| {
+				let p = p.trim();
+				if p.starts_with("q=") || p.starts_with("Q=") {
+					p[2..].trim().parse::<f32>().ok()
+				} else {
+					None
+				}
+			})
+			.next()
+			.unwrap_or(1.0);
+		match name.as_str() {
+			"br" => brotli_q = Some(q),
+			"gzip" | "x-gzip" => gzip_q = Some(q),
+			"*" => any_q = Some(q),
+			_ => {}
+		}
+	}
+
+	// Explicitly listed encodings override the wildcard
+	let brotli_q = brotli_q.or(any_q).unwrap_or(0.0);
+	let gzip_q = gzip_q.or(any_q).unwrap_or(0.0);
+	if brotli_q > 0.0 && brotli_q >= gzip_q {
+		Some(ContentEncoding::Brotli)
+	} else if gzip_q > 0.0 {
+		Some(ContentEncoding::Gzip)
+	} else {
+		None
+	}
+}
+
+/// API response compression statistics since the node start
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ApiCompressionStats {
+	/// Number of the responses compressed with gzip
+	pub gzip_responses: u64,
+	/// Number of the responses compressed with brotli
+	pub brotli_responses: u64,
+	/// Size of the compressed responses before the compression
+	pub uncompressed_bytes: u64,
+	/// Size of the compressed responses after the compression
+	pub compressed_bytes: u64,
+	/// Bytes saved by the compression
+	pub bytes_saved: u64,
+}
+
+impl ApiCompressionStats {
+	fn record(&mut self, encoding: ContentEncoding, uncompressed: usize, compressed: usize) {
+		match encoding {
+			ContentEncoding::Gzip => self.gzip_responses += 1,
+			ContentEncoding::Brotli => self.brotli_responses += 1,
+		}
+		self.uncompressed_bytes += uncompressed as u64;
+		self.compressed_bytes += compressed as u64;
+		self.bytes_saved += uncompressed.saturating_sub(compressed) as u64;
+	}
+}
+
+/// Response compression statistics of the API server
+pub fn api_compression_stats() -> ApiCompressionStats {
+	COMPRESSION_STATS.read().clone()
+}
+
+/// Middleware that compresses the responses that are larger than the threshold with
+/// the encoding negotiated from the Accept-Encoding request header. Streamed responses
+/// (the event streams) are never compressed, they don't have a known size.
+pub struct CompressionMiddleware {
+	min_size: u64,
+}
+
+impl CompressionMiddleware {
+	/// New middleware that compresses the responses of min_size bytes or larger
+	pub fn new(min_size: u64) -> CompressionMiddleware {
+		CompressionMiddleware { min_size }
+	}
+}
+
+impl Handler for CompressionMiddleware {
+	fn call(
+		&self,
+		req: Request<Body>,
+		mut handlers: Box<dyn Iterator<Item = HandlerObj>>,
+	) -> ResponseFuture {
+		let encoding = req
+			.headers()
+			.get(ACCEPT_ENCODING)
+			.and_then(|v| v.to_str().ok())
+			.and_then(negotiate_encoding);
+		let next = match handlers.next() {
+			Some(handler) => handler.call(req, handlers),
+			None => return response(StatusCode::INTERNAL_SERVER_ERROR, "no handler found"),
+		};
+		match encoding {
+			Some(encoding) => {
+				let min_size = self.min_size;
+				Box::pin(async move { compress_response(next.await?, encoding, min_size).await })
+			}
+			None => next,
+		}
+	}
+}
+
+async fn compress_response(
+	resp: Response<Body>,
+	encoding: ContentEncoding,
+	min_size: u64,
+) -> Result<Response<Body>, hyper::Error> {
+	let size = resp.body().size_hint().exact();
+	if size.map(|s| s < min_size).unwrap_or(true)
+		|| resp.status() == StatusCode::NOT_MODIFIED
+		|| resp.headers().contains_key(CONTENT_ENCODING)
+	{
+		return Ok(resp);
+	}
+
+	let (mut parts, body) = resp.into_parts();
+	let data = hyper::body::to_bytes(body).await?;
+	let compressed = match encoding.compress(&data) {
+		Ok(compressed) if compressed.len() < data.len() => compressed,
+		Ok(_) => return Ok(Response::from_parts(parts, Body::from(data))),
+		Err(e) => {
+			warn!(
+				"Unable to compress the API response with {}, {}",
+				encoding.name(),
+				e
+			);
+			return Ok(Response::from_parts(parts, Body::from(data)));
+		}
+	};
+	COMPRESSION_STATS
+		.write()
+		.record(encoding, data.len(), compressed.len());
+
+	let headers = &mut parts.headers;
+	headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+	headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+	headers.remove(CONTENT_LENGTH);
+	// The compressed body is not byte identical to the uncompressed one, so the strong
+	// validator becomes a weak one. etag_matches ignores the weak prefix.
+	let weak_etag = headers
+		.get(ETAG)
+		.and_then(|v| v.to_str().ok())
+		.filter(|etag| !etag.starts_with("W/"))
+		.and_then(|etag| HeaderValue::from_str(&format!("W/{}", etag)).ok());
+	if let Some(etag) = weak_etag {
+		headers.insert(ETAG, etag);
+	}
+	Ok(Response::from_parts(parts, Body::from(compressed)))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use flate2::read::GzDecoder;
+	use std::io::Read;
+
+	#[test]
+	fn accept_encoding_negotiation() {
+		assert_eq!(negotiate_encoding("gzip"), Some(ContentEncoding::Gzip));
+		assert_eq!(
+			negotiate_encoding("gzip, deflate, br"),
+			Some(ContentEncoding::Brotli)
+		);
+		assert_eq!(
+			negotiate_encoding("br;q=0.5, gzip;q=0.8"),
+			Some(ContentEncoding::Gzip)
+		);
+		assert_eq!(
+			negotiate_encoding("br;q=0, gzip"),
+			Some(ContentEncoding::Gzip)
+		);
+		assert_eq!(negotiate_encoding("*"), Some(ContentEncoding::Brotli));
+		assert_eq!(negotiate_encoding("*, br;q=0"), Some(ContentEncoding::Gzip));
+		assert_eq!(negotiate_encoding("identity"), None);
+		assert_eq!(negotiate_encoding("gzip;q=0, *;q=0"), None);
+		assert_eq!(negotiate_encoding(""), None);
+	}
+
+	#[test]
+	fn compress_roundtrip() {
+		let data = "{\"outputs\": [1, 2, 3]}".repeat(100);
+
+		let gzip = ContentEncoding::Gzip.compress(data.as_bytes()).unwrap();
+		assert!(gzip.len() < data.len());
+		let mut decoded = String::new();
+		GzDecoder::new(&gzip[..])
+			.read_to_string(&mut decoded)
+			.unwrap();
+		assert_eq!(decoded, data);
+
+		let br = ContentEncoding::Brotli.compress(data.as_bytes()).unwrap();
+		assert!(br.len() < data.len());
+		let mut decoded = String::new();
+		brotli::Decompressor::new(&br[..], 4096)
+			.read_to_string(&mut decoded)
+			.unwrap();
+		assert_eq!(decoded, data);
+	}
+}
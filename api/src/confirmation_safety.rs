@@ -0,0 +1,183 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reorg safety of the confirmed kernel. Merchants ask how safe the kernel is instead of
+//! waiting for the fixed number of blocks. The answer is based on the confirmations,
+//! the work accumulated on top of the kernel block and the fork activity the node has
+//! seen recently: a reorg as deep as the kernel confirmations would have dropped it.
+
+use crate::chain::BlockStatus;
+use crate::util::Mutex;
+use std::collections::VecDeque;
+
+/// Fork activity older than that is not taken into account, in seconds
+pub const FORK_ACTIVITY_WINDOW_SECS: i64 = 24 * 3600;
+/// Kernel with that many confirmations or less is at the high risk even without forks
+pub const HIGH_RISK_CONFIRMATIONS: u64 = 2;
+/// Confirmations of the low risk if there were no reorgs. Every block of the deepest
+/// recent reorg adds two more.
+pub const LOW_RISK_CONFIRMATIONS: u64 = 10;
+/// Max number of the fork events we keep
+const MAX_FORK_EVENTS: usize = 1_000;
+
+/// Risk that the kernel is dropped by a reorg
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ConfirmationRisk {
+	/// Recent reorgs were as deep as the kernel confirmations
+	High,
+	/// Deeper than the recent reorgs, but below the recommended confirmations
+	Medium,
+	/// Recommended confirmations are reached
+	Low,
+}
+
+/// Reorg safety of the kernel
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfirmationSafety {
+	/// Kernel excess, hex
+	pub excess: String,
+	/// Height of the block with the kernel
+	pub height: u64,
+	/// Hash of the block with the kernel
+	pub block_hash: String,
+	/// Number of blocks from the kernel block to the head, the kernel block included
+	pub confirmations: u64,
+	/// Total difficulty accumulated on top of the kernel block
+	pub work_on_top: u64,
+	/// Reorgs seen during the last 24 hours
+	pub recent_reorgs: u32,
+	/// Depth of the deepest reorg during the last 24 hours
+	pub max_recent_reorg_depth: u64,
+	/// Fork blocks seen during the last 24 hours
+	pub recent_fork_blocks: u32,
+	/// Confirmations of the low risk with the current fork activity
+	pub recommended_confirmations: u64,
+	/// Risk classification
+	pub risk: ConfirmationRisk,
+}
+
+/// Fork activity for the window
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ForkActivitySummary {
+	/// Number of the reorgs
+	pub reorgs: u32,
+	/// Depth of the deepest reorg
+	pub max_reorg_depth: u64,
+	/// Number of the fork blocks
+	pub fork_blocks: u32,
+}
+
+/// Reorgs and fork blocks seen by the node
+pub struct ForkActivity {
+	// Time and depth of the reorgs, oldest first
+	reorgs: VecDeque<(i64, u64)>,
+	// Time of the fork blocks, oldest first
+	fork_blocks: VecDeque<i64>,
+}
+
+impl ForkActivity {
+	/// No activity
+	pub fn new() -> ForkActivity {
+		ForkActivity {
+			reorgs: VecDeque::new(),
+			fork_blocks: VecDeque::new(),
+		}
+	}
+
+	/// Record the status of the accepted block
+	pub fn block_accepted(&mut self, status: &BlockStatus, now: i64) {
+		match status {
+			BlockStatus::Next { .. } => return,
+			BlockStatus::Fork { .. } => {
+				self.fork_blocks.push_back(now);
+				if self.fork_blocks.len() > MAX_FORK_EVENTS {
+					self.fork_blocks.pop_front();
+				}
+			}
+			BlockStatus::Reorg {
+				prev_head,
+				fork_point,
+				..
+			} => {
+				let depth = prev_head.height.saturating_sub(fork_point.height);
+				self.reorgs.push_back((now, depth));
+				if self.reorgs.len() > MAX_FORK_EVENTS {
+					self.reorgs.pop_front();
+				}
+			}
+		}
+		self.expire(now);
+	}
+
+	fn expire(&mut self, now: i64) {
+		let since = now - FORK_ACTIVITY_WINDOW_SECS;
+		while self
+			.reorgs
+			.front()
+			.map(|(t, _)| *t < since)
+			.unwrap_or(false)
+		{
+			self.reorgs.pop_front();
+		}
+		while self
+			.fork_blocks
+			.front()
+			.map(|t| *t < since)
+			.unwrap_or(false)
+		{
+			self.fork_blocks.pop_front();
+		}
+	}
+
+	/// Activity during the last 24 hours
+	pub fn summary(&mut self, now: i64) -> ForkActivitySummary {
+		self.expire(now);
+		ForkActivitySummary {
+			reorgs: self.reorgs.len() as u32,
+			max_reorg_depth: self.reorgs.iter().map(|(_, d)| *d).max().unwrap_or(0),
+			fork_blocks: self.fork_blocks.len() as u32,
+		}
+	}
+}
+
+/// Confirmations of the low risk when the recent reorgs were that deep
+pub fn recommended_confirmations(max_reorg_depth: u64) -> u64 {
+	LOW_RISK_CONFIRMATIONS + 2 * max_reorg_depth
+}
+
+/// Risk of the kernel with the confirmations when the recent reorgs were that deep
+pub fn classify_risk(confirmations: u64, max_reorg_depth: u64) -> ConfirmationRisk {
+	if confirmations <= HIGH_RISK_CONFIRMATIONS.max(max_reorg_depth) {
+		ConfirmationRisk::High
+	} else if confirmations < recommended_confirmations(max_reorg_depth) {
+		ConfirmationRisk::Medium
+	} else {
+		ConfirmationRisk::Low
+	}
+}
+
+lazy_static! {
+	// Fork activity of the node, updated by the chain hooks
+	static ref FORK_ACTIVITY: Mutex<ForkActivity> = Mutex::new(ForkActivity::new());
+}
+
+/// Record the status of the block accepted by the chain
+pub fn record_fork_activity(status: &BlockStatus, now: i64) {
+	FORK_ACTIVITY.lock().block_accepted(status, now);
+}
+
+/// Fork activity of the node during the last 24 hours
+pub fn fork_activity_summary(now: i64) -> ForkActivitySummary {
+	FORK_ACTIVITY.lock().summary(now)
+}
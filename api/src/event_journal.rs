@@ -0,0 +1,418 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistent journal of the events sent to the websocket subscribers. Every stream
+//! numbers its events with the sequence that continues across the node restarts, so
+//! the subscriber that lost the connection reconnects with `?resume_from=<seq>` and gets
+//! the events it missed, then the live ones, without gaps and duplicates.
+//!
+//! Reorgs don't rewrite the journal. The events of the blocks that left the chain stay
+//! as they were and the reorg itself is reported with the new events, so the consumer
+//! that applies the events in the sequence order always ends up in the node state.
+//! The journal is rolling, the oldest events above the size limit are pruned. Resume
+//! from the pruned sequence is refused, the consumer must reload its state.
+
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::rest::{Error, ErrorKind};
+use crate::util::{Mutex, RwLock};
+use futures::channel::mpsc;
+use grin_store::{self, to_key, u64_to_key};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const DB_NAME: &str = "event_journal";
+const STORE_SUBPATH: &str = "event_journal";
+
+/// Default max number of the journal events of every stream
+pub const DEFAULT_EVENT_JOURNAL_MAX_ENTRIES: u64 = 100_000;
+
+/// Events buffered per subscriber. Subscriber that is that far behind is disconnected.
+const SUBSCRIBER_BUFFER_SIZE: usize = 10_000;
+// Pruning runs after that many events
+const PRUNE_INTERVAL: u64 = 1000;
+// Serialized event is a small json, anything above is a broken record
+const MAX_EVENT_SIZE: u64 = 1_000_000;
+
+/// Event streams recorded in the journal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventStream {
+	/// Transaction pool events, `/v2/pool/events`
+	TxPool,
+	/// Kernel watch events, `/v2/kernels/events`
+	KernelWatch,
+}
+
+impl EventStream {
+	fn prefix(&self) -> u8 {
+		match self {
+			EventStream::TxPool => b'P',
+			EventStream::KernelWatch => b'K',
+		}
+	}
+}
+
+// Serialized event message as it was sent to the subscribers
+struct JournalEntry(String);
+
+impl Writeable for JournalEntry {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(self.0.as_bytes())
+	}
+}
+
+impl Readable for JournalEntry {
+	fn read<R: Reader>(reader: &mut R) -> Result<JournalEntry, ser::Error> {
+		let len = reader.read_u64()?;
+		if len > MAX_EVENT_SIZE {
+			return Err(ser::Error::TooLargeReadErr(format!(
+				"event of {} bytes, the limit is {}",
+				len, MAX_EVENT_SIZE
+			)));
+		}
+		let bytes = reader.read_fixed_bytes(len as usize)?;
+		let msg = String::from_utf8(bytes)
+			.map_err(|e| ser::Error::CorruptedData(format!("event is not utf8, {}", e)))?;
+		Ok(JournalEntry(msg))
+	}
+}
+
+/// Persistent journal of the event streams
+pub struct EventJournal {
+	db: Arc<grin_store::Store>,
+	max_entries: u64,
+}
+
+impl EventJournal {
+	/// Open the journal under the provided root path
+	pub fn new(db_root: &str, max_entries: u64) -> Result<EventJournal, grin_store::Error> {
+		let db = grin_store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		Ok(EventJournal {
+			db: Arc::new(db),
+			max_entries,
+		})
+	}
+
+	/// Journal of the single stream, the sequence continues from the last stored event
+	pub fn stream(&self, stream: EventStream) -> Result<EventLog, grin_store::Error> {
+		let prefix = stream.prefix();
+		let seqs: Vec<u64> = self
+			.db
+			.iter::<JournalEntry>(&to_key(prefix, ""))?
+			.map(|(k, _)| key_seq(&k))
+			.collect();
+		let next_seq = seqs.last().map(|s| s + 1).unwrap_or(0);
+		Ok(EventLog {
+			db: self.db.clone(),
+			prefix,
+			max_entries: self.max_entries,
+			first_seq: AtomicU64::new(seqs.first().cloned().unwrap_or(next_seq)),
+			next_seq: AtomicU64::new(next_seq),
+			count: AtomicU64::new(seqs.len() as u64),
+		})
+	}
+}
+
+// Sequence number is the last 8 bytes of the key
+fn key_seq(key: &[u8]) -> u64 {
+	let mut buf = [0u8; 8];
+	if key.len() >= 8 {
+		buf.copy_from_slice(&key[key.len() - 8..]);
+	}
+	u64::from_be_bytes(buf)
+}
+
+/// Journal of the single event stream
+pub struct EventLog {
+	db: Arc<grin_store::Store>,
+	prefix: u8,
+	max_entries: u64,
+	first_seq: AtomicU64,
+	next_seq: AtomicU64,
+	count: AtomicU64,
+}
+
+impl EventLog {
+	/// Sequence number of the oldest retained event
+	pub fn first_seq(&self) -> u64 {
+		self.first_seq.load(Ordering::SeqCst)
+	}
+
+	/// Sequence number of the next event
+	pub fn next_seq(&self) -> u64 {
+		self.next_seq.load(Ordering::SeqCst)
+	}
+
+	/// Store the event message with its sequence number
+	pub fn append(&self, seq: u64, msg: &str) -> Result<(), grin_store::Error> {
+		let batch = self.db.batch()?;
+		batch.put_ser(
+			&u64_to_key(self.prefix, seq),
+			&JournalEntry(msg.to_string()),
+		)?;
+		batch.commit()?;
+		if self.count.load(Ordering::SeqCst) == 0 {
+			self.first_seq.store(seq, Ordering::SeqCst);
+		}
+		self.next_seq.store(seq + 1, Ordering::SeqCst);
+		let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+		if count > self.max_entries + PRUNE_INTERVAL {
+			self.prune()?;
+		}
+		Ok(())
+	}
+
+	/// Delete the oldest events above the size limit. Returns the number of the deleted events.
+	pub fn prune(&self) -> Result<u64, grin_store::Error> {
+		let keys: Vec<Vec<u8>> = self
+			.db
+			.iter::<JournalEntry>(&to_key(self.prefix, ""))?
+			.map(|(k, _)| k)
+			.collect();
+		let over_limit = (keys.len() as u64).saturating_sub(self.max_entries) as usize;
+		if over_limit == 0 {
+			return Ok(0);
+		}
+		let batch = self.db.batch()?;
+		for key in &keys[..over_limit] {
+			batch.delete(key)?;
+		}
+		batch.commit()?;
+		self.first_seq
+			.store(key_seq(&keys[over_limit]), Ordering::SeqCst);
+		self.count
+			.store((keys.len() - over_limit) as u64, Ordering::SeqCst);
+		debug!("Event journal pruned, {} events deleted", over_limit);
+		Ok(over_limit as u64)
+	}
+
+	/// Stored event messages starting from the sequence number, the oldest first
+	pub fn since(&self, seq: u64) -> Result<Vec<Arc<String>>, grin_store::Error> {
+		Ok(self
+			.db
+			.iter::<JournalEntry>(&to_key(self.prefix, ""))?
+			.filter(|(k, _)| key_seq(k) >= seq)
+			.map(|(_, v)| Arc::new(v.0))
+			.collect())
+	}
+}
+
+struct FeedState {
+	next_seq: u64,
+	subscribers: Vec<mpsc::Sender<Arc<String>>>,
+}
+
+/// Numbers the events of the stream, records them in the journal and sends them to the
+/// websocket subscribers. Shared by the event hubs.
+pub(crate) struct EventFeed {
+	name: &'static str,
+	state: Mutex<FeedState>,
+	log: RwLock<Option<Arc<EventLog>>>,
+}
+
+impl EventFeed {
+	pub fn new(name: &'static str) -> EventFeed {
+		EventFeed {
+			name,
+			state: Mutex::new(FeedState {
+				next_seq: 0,
+				subscribers: vec![],
+			}),
+			log: RwLock::new(None),
+		}
+	}
+
+	/// Record the events in the journal, the sequence continues from the journal
+	pub fn set_journal(&self, log: EventLog) {
+		let mut state = self.state.lock();
+		state.next_seq = state.next_seq.max(log.next_seq());
+		*self.log.write() = Some(Arc::new(log));
+	}
+
+	/// Publish the event built with its sequence number. Never blocks, the subscribers
+	/// that can't keep up are disconnected.
+	pub fn publish<T, F>(&self, event: F)
+	where
+		T: Serialize,
+		F: FnOnce(u64) -> T,
+	{
+		// Everything happens under the lock, so the subscription that replays the journal
+		// sees every event exactly once
+		let mut state = self.state.lock();
+		// Sequence is incremented even without subscribers, so the gaps are real gaps
+		let seq = state.next_seq;
+		state.next_seq += 1;
+		let log = self.log.read().clone();
+		if state.subscribers.is_empty() && log.is_none() {
+			return;
+		}
+		let msg = match serde_json::to_string(&event(seq)) {
+			Ok(msg) => Arc::new(msg),
+			Err(e) => {
+				error!("Unable to serialize {} event, {}", self.name, e);
+				return;
+			}
+		};
+		if let Some(log) = log {
+			if let Err(e) = log.append(seq, &msg) {
+				error!("Unable to record {} event {}, {}", self.name, seq, e);
+			}
+		}
+		state.subscribers.retain(|s| !s.is_closed());
+		for subscriber in state.subscribers.iter_mut() {
+			if let Err(e) = subscriber.try_send(msg.clone()) {
+				if e.is_full() {
+					warn!("{} subscriber is too slow, disconnecting it", self.name);
+				}
+				subscriber.close_channel();
+			}
+		}
+	}
+
+	/// Subscribe to the events. With `resume_from` the journal events starting from that
+	/// sequence number are sent first, then the live events.
+	pub fn subscribe(
+		&self,
+		resume_from: Option<u64>,
+	) -> Result<mpsc::Receiver<Arc<String>>, Error> {
+		let mut state = self.state.lock();
+		let replay = match resume_from {
+			Some(seq) if seq < state.next_seq => {
+				let log = self.log.read().clone();
+				match log {
+					Some(ref log) if seq >= log.first_seq() => log.since(seq).map_err(|e| {
+						ErrorKind::Internal(format!("Unable to read {} journal, {}", self.name, e))
+					})?,
+					_ => {
+						return Err(ErrorKind::NotFound(format!(
+							"{} events from {} are not retained, the first available is {}",
+							self.name,
+							seq,
+							log.map(|l| l.first_seq()).unwrap_or(state.next_seq)
+						))
+						.into())
+					}
+				}
+			}
+			Some(seq) if seq > state.next_seq => {
+				return Err(ErrorKind::Argument(format!(
+					"{} sequence {} is not published yet, the next is {}",
+					self.name, seq, state.next_seq
+				))
+				.into())
+			}
+			_ => vec![],
+		};
+		let (mut tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER_SIZE + replay.len());
+		for msg in replay {
+			let _ = tx.try_send(msg);
+		}
+		state.subscribers.push(tx);
+		Ok(rx)
+	}
+
+	/// Sequence number of the next event
+	pub fn next_seq(&self) -> u64 {
+		self.state.lock().next_seq
+	}
+
+	/// Number of the connected subscribers
+	pub fn subscribers_count(&self) -> usize {
+		let mut state = self.state.lock();
+		state.subscribers.retain(|s| !s.is_closed());
+		state.subscribers.len()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[derive(Serialize)]
+	struct TestEvent {
+		seq: u64,
+	}
+
+	fn messages(rx: &mut mpsc::Receiver<Arc<String>>) -> Vec<String> {
+		let mut res = vec![];
+		while let Ok(Some(msg)) = rx.try_next() {
+			res.push(msg.to_string());
+		}
+		res
+	}
+
+	#[test]
+	fn resume_across_restarts() {
+		let db_root = ".grin_event_journal";
+		let _ = std::fs::remove_dir_all(db_root);
+		{
+			let journal = EventJournal::new(db_root, 3).unwrap();
+			let feed = EventFeed::new("Test");
+			feed.set_journal(journal.stream(EventStream::TxPool).unwrap());
+			for _ in 0..3 {
+				feed.publish(|seq| TestEvent { seq });
+			}
+			let mut rx = feed.subscribe(Some(1)).unwrap();
+			feed.publish(|seq| TestEvent { seq });
+			assert_eq!(
+				messages(&mut rx),
+				vec!["{\"seq\":1}", "{\"seq\":2}", "{\"seq\":3}"]
+			);
+		}
+		{
+			// Sequence continues after the restart, streams are independent
+			let journal = EventJournal::new(db_root, 3).unwrap();
+			let kernels = journal.stream(EventStream::KernelWatch).unwrap();
+			assert_eq!(kernels.next_seq(), 0);
+			let feed = EventFeed::new("Test");
+			feed.set_journal(journal.stream(EventStream::TxPool).unwrap());
+			assert_eq!(feed.next_seq(), 4);
+			let mut rx = feed.subscribe(Some(2)).unwrap();
+			assert_eq!(messages(&mut rx), vec!["{\"seq\":2}", "{\"seq\":3}"]);
+
+			// Nothing missed, nothing to replay
+			let mut rx = feed.subscribe(Some(4)).unwrap();
+			assert!(messages(&mut rx).is_empty());
+			assert!(feed.subscribe(Some(5)).is_err());
+
+			// Pruned events can't be replayed
+			let log = journal.stream(EventStream::TxPool).unwrap();
+			assert_eq!(log.prune().unwrap(), 1);
+			assert_eq!(log.first_seq(), 1);
+			let feed = EventFeed::new("Test");
+			feed.set_journal(log);
+			assert!(feed.subscribe(Some(0)).is_err());
+			assert_eq!(
+				feed.subscribe(Some(1))
+					.unwrap()
+					.try_next()
+					.unwrap()
+					.unwrap()
+					.as_str(),
+				"{\"seq\":1}"
+			);
+		}
+		let _ = std::fs::remove_dir_all(db_root);
+	}
+
+	#[test]
+	fn resume_without_journal() {
+		let feed = EventFeed::new("Test");
+		feed.publish(|seq| TestEvent { seq });
+		assert!(feed.subscribe(Some(0)).is_err());
+		let mut rx = feed.subscribe(Some(1)).unwrap();
+		feed.publish(|seq| TestEvent { seq });
+		assert_eq!(messages(&mut rx), vec!["{\"seq\":1}"]);
+	}
+}
@@ -15,24 +15,30 @@
 //! Foreign API External Definition
 
 use crate::chain::{Chain, SyncState};
+use crate::confirmation_safety::ConfirmationSafety;
 use crate::core::core::hash::Hash;
 use crate::core::core::hash::Hashed;
 use crate::core::core::transaction::Transaction;
 use crate::core::core::verifier_cache::VerifierCache;
-use crate::handlers::blocks_api::{BlockHandler, HeaderHandler};
-use crate::handlers::chain_api::{ChainHandler, KernelHandler, OutputHandler};
+use crate::handlers::blocks_api::{BlockFullnessHandler, BlockHandler, HeaderHandler};
+use crate::handlers::chain_api::{
+	ChainHandler, KernelFeeHandler, KernelHandler, OutputHandler, OwnershipProofHandler,
+};
 use crate::handlers::pool_api::PoolHandler;
 use crate::handlers::transactions_api::TxHashSetHandler;
-use crate::handlers::version_api::VersionHandler;
+use crate::handlers::version_api::{ProtocolScheduleHandler, VersionHandler};
+use crate::kernel_watch::{kernel_watch_hub, KernelWatch};
 use crate::pool::{self, BlockChain, PoolAdapter, PoolEntry};
 use crate::rest::*;
 use crate::types::{
-	BlockHeaderPrintable, BlockPrintable, LocatedTxKernel, OutputListing, OutputPrintable, Tip,
-	Version,
+	BlockFullness, BlockHeaderPrintable, BlockPrintable, FeeHistogramBucket, HeaderChainProof,
+	KernelFeeIndexStatus, KernelFeePrintable, LocatedTxKernel, OutputListing, OutputPrintable,
+	OwnershipProof, OwnershipProofVerification, PoolTxState, ProtocolSchedule, PushedTransaction,
+	Tip, Version,
 };
 use crate::util::RwLock;
 #[cfg(feature = "marketplace")]
-use crate::{Libp2pMessages, Libp2pPeers};
+use crate::{Libp2pGossipDiagnostics, Libp2pMessages, Libp2pPeers};
 #[cfg(feature = "marketplace")]
 use chrono::Utc;
 #[cfg(feature = "marketplace")]
@@ -145,6 +151,7 @@ where
 	) -> Result<BlockPrintable, Error> {
 		let block_handler = BlockHandler {
 			chain: self.chain.clone(),
+			peers: self.peers.clone(),
 		};
 		let hash = block_handler.parse_inputs(height, hash, commit)?;
 		block_handler.get_block(
@@ -169,6 +176,23 @@ where
 		version_handler.get_version()
 	}
 
+	/// Returns the hard fork schedule: activation heights, expected header versions
+	/// for every era and whether this node supports them. Integrations can use it to
+	/// prepare for the upcoming upgrades.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`ProtocolSchedule`](types/struct.ProtocolSchedule.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_protocol_schedule(&self) -> Result<ProtocolSchedule, Error> {
+		let protocol_schedule_handler = ProtocolScheduleHandler {
+			chain: self.chain.clone(),
+		};
+		protocol_schedule_handler.get_protocol_schedule()
+	}
+
 	/// Returns details about the state of the current fork tip.
 	///
 	/// # Returns
@@ -213,6 +237,91 @@ where
 		kernel_handler.get_kernel_v2(excess, min_height, max_height)
 	}
 
+	/// Returns the reorg safety of the kernel: the confirmations, the work accumulated on
+	/// top of its block and the risk classification based on the reorgs and the fork
+	/// blocks the node has seen during the last 24 hours. Merchants can require more
+	/// confirmations while the network is unstable instead of the fixed block count.
+	///
+	/// # Arguments
+	/// * `kernel_excess` - kernel excess, hex encoded.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`ConfirmationSafety`](struct.ConfirmationSafety.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered, `NotFound` if the
+	/// kernel is not in the chain.
+	///
+	pub fn get_confirmation_safety(
+		&self,
+		kernel_excess: String,
+	) -> Result<ConfirmationSafety, Error> {
+		let kernel_handler = KernelHandler {
+			chain: self.chain.clone(),
+		};
+		kernel_handler.get_confirmation_safety(kernel_excess)
+	}
+
+	/// Register a watch list of the kernels. When a watched kernel appears in a block,
+	/// the `kernel_confirmed` event is published for this block and every next block,
+	/// until the kernel has `depth` confirmations. The events are published to the
+	/// `/v2/kernels/events` websocket subscribers and the `kernel_watch` webhooks.
+	/// Kernels that are already in the recent blocks are reported from the next block.
+	///
+	/// # Arguments
+	/// * `excess_list` - kernel excesses to watch, hex encoded.
+	/// * `depth` - number of confirmations to watch for. Default: node `kernel_watch_depth`
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`KernelWatch`](struct.KernelWatch.html) with the watch id
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+	pub fn watch_kernels(
+		&self,
+		excess_list: Vec<String>,
+		depth: Option<u64>,
+	) -> Result<KernelWatch, Error> {
+		let kernel_handler = KernelHandler {
+			chain: self.chain.clone(),
+		};
+		kernel_handler.watch_kernels(excess_list, depth)
+	}
+
+	/// Returns the state of the kernel watch. Kernels that reached the watch depth are
+	/// not listed, the watch is removed when all its kernels reached the depth.
+	///
+	/// # Arguments
+	/// * `watch_id` - id of the watch returned by `watch_kernels`.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`KernelWatch`](struct.KernelWatch.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+	pub fn get_kernel_watch(&self, watch_id: u64) -> Result<KernelWatch, Error> {
+		kernel_watch_hub()
+			.get_watch(watch_id)
+			.ok_or_else(|| ErrorKind::NotFound(format!("kernel watch {}", watch_id)).into())
+	}
+
+	/// Remove the kernel watch.
+	///
+	/// # Arguments
+	/// * `watch_id` - id of the watch returned by `watch_kernels`.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the watch was removed
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+	pub fn unwatch_kernels(&self, watch_id: u64) -> Result<(), Error> {
+		if kernel_watch_hub().unwatch(watch_id) {
+			Ok(())
+		} else {
+			Err(ErrorKind::NotFound(format!("kernel watch {}", watch_id)).into())
+		}
+	}
+
 	/// Retrieves details about specifics outputs. Supports retrieval of multiple outputs in a single request.
 	/// Support retrieval by both commitment string and block height.
 	///
@@ -276,6 +385,29 @@ where
 		output_handler.get_unspent_outputs(start_index, end_index, max, include_proof)
 	}
 
+	/// Verifies the ownership proof of the unspent output. The owner signs the challenge
+	/// with the output blinding factor and reveals the amount, so exchanges can prove
+	/// their reserves without spending the outputs.
+	///
+	/// # Arguments
+	/// * `proof` - the [`OwnershipProof`](types/struct.OwnershipProof.html) to verify.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * An [`OwnershipProofVerification`](types/struct.OwnershipProofVerification.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn verify_ownership_proof(
+		&self,
+		proof: OwnershipProof,
+	) -> Result<OwnershipProofVerification, Error> {
+		let ownership_handler = OwnershipProofHandler {
+			chain: self.chain.clone(),
+		};
+		ownership_handler.verify_ownership_proof(proof)
+	}
+
 	/// Retrieves the PMMR indices based on the provided block height(s).
 	///
 	/// # Arguments
@@ -373,6 +505,172 @@ where
 		}
 	}
 
+	/// Push new transaction to our local transaction pool and report its state in the pool.
+	/// Pool queries made after this call returns see the transaction: it is returned by
+	/// `get_unconfirmed_transactions` if the state is `Txpool`. Stem transactions stay in the
+	/// private stempool until they are fluffed, use `get_transaction_state` to poll them.
+	///
+	/// # Arguments
+	/// * `tx` - the Grin transaction to push.
+	/// * `fluff` - boolean to bypass Dandelion relay.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`PushedTransaction`](types/struct.PushedTransaction.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+	pub fn push_transaction_sync(
+		&self,
+		tx: Transaction,
+		fluff: Option<bool>,
+	) -> Result<PushedTransaction, Error> {
+		let tx_hash = tx.hash();
+		let pool_handler = PoolHandler {
+			tx_pool: self.tx_pool.clone(),
+		};
+		pool_handler.push_transaction_sync(tx, fluff).map_err(|e| {
+			warn!(
+				"Unable to push transaction {} into the pool, {}",
+				tx_hash, e
+			);
+			e
+		})
+	}
+
+	/// Returns the state of the transaction in the pool.
+	///
+	/// # Arguments
+	/// * `excess` - kernel excess of the transaction, hex encoded.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`PoolTxState`](types/enum.PoolTxState.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+	pub fn get_transaction_state(&self, excess: String) -> Result<PoolTxState, Error> {
+		let pool_handler = PoolHandler {
+			tx_pool: self.tx_pool.clone(),
+		};
+		pool_handler.get_transaction_state(excess)
+	}
+
+	/// Returns the kernels with fee not less than `min_fee` in the height range.
+	/// Requires the kernel fee index (`kernel_fee_index` in the node config).
+	///
+	/// # Arguments
+	/// * `min_fee` - minimal kernel fee.
+	/// * `start_height` - start height of the range.
+	/// * `end_height` - end height of the range, last indexed block if None.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`KernelFeePrintable`](types/struct.KernelFeePrintable.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_kernels_by_fee(
+		&self,
+		min_fee: u64,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<Vec<KernelFeePrintable>, Error> {
+		let kernel_fee_handler = KernelFeeHandler {
+			chain: self.chain.clone(),
+		};
+		kernel_fee_handler.get_kernels_by_fee(min_fee, start_height, end_height)
+	}
+
+	/// Returns the histogram of kernel fees in the height range. Empty buckets are skipped.
+	/// Requires the kernel fee index (`kernel_fee_index` in the node config).
+	///
+	/// # Arguments
+	/// * `start_height` - start height of the range.
+	/// * `end_height` - end height of the range, last indexed block if None.
+	/// * `bucket_size` - fee range of a single histogram bucket.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`FeeHistogramBucket`](types/struct.FeeHistogramBucket.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_fee_histogram(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+		bucket_size: u64,
+	) -> Result<Vec<FeeHistogramBucket>, Error> {
+		let kernel_fee_handler = KernelFeeHandler {
+			chain: self.chain.clone(),
+		};
+		kernel_fee_handler.get_fee_histogram(start_height, end_height, bucket_size)
+	}
+
+	/// Returns the kernel fee index building progress.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`KernelFeeIndexStatus`](types/struct.KernelFeeIndexStatus.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_kernel_fee_index_status(&self) -> Result<KernelFeeIndexStatus, Error> {
+		let kernel_fee_handler = KernelFeeHandler {
+			chain: self.chain.clone(),
+		};
+		kernel_fee_handler.get_index_status()
+	}
+
+	/// Returns the serialized header chain between two heights with the total difficulty
+	/// at both ends. Light clients use it to verify the ancestry and the accumulated work.
+	///
+	/// # Arguments
+	/// * `from_height` - height of the first header.
+	/// * `to_height` - height of the last header, at most 2000 headers are returned.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`HeaderChainProof`](types/struct.HeaderChainProof.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_header_proof(
+		&self,
+		from_height: u64,
+		to_height: u64,
+	) -> Result<HeaderChainProof, Error> {
+		let header_handler = HeaderHandler {
+			chain: self.chain.clone(),
+		};
+		header_handler.get_header_proof(from_height, to_height)
+	}
+
+	/// Returns the weight utilization of the blocks in the height range together with the
+	/// rolling fullness, the average utilization of the `window` blocks ending with the block.
+	///
+	/// # Arguments
+	/// * `start_height` - start height of the range, the last hour of blocks if None.
+	/// * `end_height` - end height of the range, the chain tip if None.
+	/// * `window` - number of the blocks in the rolling fullness, 60 if None.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`BlockFullness`](types/struct.BlockFullness.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_block_fullness(
+		&self,
+		start_height: Option<u64>,
+		end_height: Option<u64>,
+		window: Option<u64>,
+	) -> Result<Vec<BlockFullness>, Error> {
+		let fullness_handler = BlockFullnessHandler {
+			chain: self.chain.clone(),
+		};
+		fullness_handler.get_block_fullness(start_height, end_height, window)
+	}
+
 	#[cfg(feature = "marketplace")]
 	/// Get TOR address on this node. Return none if TOR is not running.
 	pub fn get_libp2p_peers(&self) -> Result<Libp2pPeers, Error> {
@@ -409,4 +707,13 @@ where
 				.collect(),
 		})
 	}
+	#[cfg(feature = "marketplace")]
+	/// Get gossipsub diagnostics: mesh members, grafts/prunes, received messages with
+	/// the validation results and the application level repeat rate for every topic.
+	pub fn get_libp2p_gossip_diagnostics(&self) -> Result<Libp2pGossipDiagnostics, Error> {
+		Ok(Libp2pGossipDiagnostics {
+			current_time: Utc::now().timestamp(),
+			topics: libp2p_connection::get_gossip_diagnostics(),
+		})
+	}
 }
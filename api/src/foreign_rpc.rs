@@ -14,20 +14,24 @@
 
 //! JSON-RPC Stub generation for the Foreign API
 
+use crate::confirmation_safety::ConfirmationSafety;
 use crate::core::core::hash::Hash;
 use crate::core::core::transaction::Transaction;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::foreign::Foreign;
+use crate::kernel_watch::KernelWatch;
 use crate::pool::PoolEntry;
 use crate::pool::{BlockChain, PoolAdapter};
 use crate::rest::ErrorKind;
 use crate::types::{
-	BlockHeaderPrintable, BlockPrintable, LocatedTxKernel, OutputListing, OutputPrintable, Tip,
-	Version,
+	BlockFullness, BlockHeaderPrintable, BlockPrintable, FeeHistogramBucket, HeaderChainProof,
+	KernelFeeIndexStatus, KernelFeePrintable, LocatedTxKernel, OutputListing, OutputPrintable,
+	OwnershipProof, OwnershipProofVerification, PoolTxState, ProtocolSchedule, PushedTransaction,
+	Tip, Version,
 };
 use crate::util;
 #[cfg(feature = "marketplace")]
-use crate::{Libp2pMessages, Libp2pPeers};
+use crate::{Libp2pGossipDiagnostics, Libp2pMessages, Libp2pPeers};
 
 /// Public definition used to generate Node jsonrpc api.
 /// * When running `grin` with defaults, the V2 api is available at
@@ -403,6 +407,57 @@ pub trait ForeignRpc: Sync + Send {
 	 */
 	fn get_version(&self) -> Result<Version, ErrorKind>;
 
+	/**
+	Networked version of [Foreign::get_protocol_schedule](struct.Foreign.html#method.get_protocol_schedule).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_protocol_schedule",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"chain_type": "Mainnet",
+				"height": 1012345,
+				"header_version": 2,
+				"max_supported_header_version": 2,
+				"eras": [
+					{
+						"start_height": 0,
+						"end_height": 202499,
+						"header_version": 1,
+						"status": "Past",
+						"blocks_until_activation": 0
+					},
+					{
+						"start_height": 202500,
+						"end_height": null,
+						"header_version": 2,
+						"status": "Active",
+						"blocks_until_activation": 0
+					}
+				]
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_protocol_schedule(&self) -> Result<ProtocolSchedule, ErrorKind>;
+
 	/**
 	Networked version of [Foreign::get_tip](struct.Foreign.html#method.get_tip).
 
@@ -481,6 +536,166 @@ pub trait ForeignRpc: Sync + Send {
 		max_height: Option<u64>,
 	) -> Result<LocatedTxKernel, ErrorKind>;
 
+	/**
+	Networked version of [Foreign::watch_kernels](struct.Foreign.html#method.watch_kernels).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "watch_kernels",
+		"params": [["09c868a2fed619580f296e91d2819b6b3ae61ab734bf3d9c3eafa6d9700f00361b"], 10],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"watch_id": 1,
+				"depth": 10,
+				"created": 1612345678,
+				"kernels": [
+					{
+						"excess": "09c868a2fed619580f296e91d2819b6b3ae61ab734bf3d9c3eafa6d9700f00361b",
+						"height": null,
+						"block_hash": null
+					}
+				]
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn watch_kernels(
+		&self,
+		excess_list: Vec<String>,
+		depth: Option<u64>,
+	) -> Result<KernelWatch, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_confirmation_safety](struct.Foreign.html#method.get_confirmation_safety).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_confirmation_safety",
+		"params": ["09c868a2fed619580f296e91d2819b6b3ae61ab734bf3d9c3eafa6d9700f00361b"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"excess": "09c868a2fed619580f296e91d2819b6b3ae61ab734bf3d9c3eafa6d9700f00361b",
+				"height": 374557,
+				"block_hash": "0001a5ba6d1d3c8ee2d2ff40ef4da9ae4cd8e0e1d4bb7e48bcf3b6a2a3c6e0fd",
+				"confirmations": 6,
+				"work_on_top": 2733564352,
+				"recent_reorgs": 1,
+				"max_recent_reorg_depth": 2,
+				"recent_fork_blocks": 3,
+				"recommended_confirmations": 14,
+				"risk": "Medium"
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_confirmation_safety(
+		&self,
+		kernel_excess: String,
+	) -> Result<ConfirmationSafety, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_kernel_watch](struct.Foreign.html#method.get_kernel_watch).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_kernel_watch",
+		"params": [1],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"watch_id": 1,
+				"depth": 10,
+				"created": 1612345678,
+				"kernels": [
+					{
+						"excess": "09c868a2fed619580f296e91d2819b6b3ae61ab734bf3d9c3eafa6d9700f00361b",
+						"height": 374557,
+						"block_hash": "0001a5ba6d1d3c8ee2d2ff40ef4da9ae4cd8e0e1d4bb7e48bcf3b6a2a3c6e0fd"
+					}
+				]
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_kernel_watch(&self, watch_id: u64) -> Result<KernelWatch, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::unwatch_kernels](struct.Foreign.html#method.unwatch_kernels).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "unwatch_kernels",
+		"params": [1],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn unwatch_kernels(&self, watch_id: u64) -> Result<(), ErrorKind>;
+
 	/**
 	Networked version of [Foreign::get_outputs](struct.Foreign.html#method.get_outputs).
 
@@ -666,6 +881,50 @@ pub trait ForeignRpc: Sync + Send {
 		end_block_height: Option<u64>,
 	) -> Result<OutputListing, ErrorKind>;
 
+	/**
+	Networked version of [Foreign::verify_ownership_proof](struct.Foreign.html#method.verify_ownership_proof).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "verify_ownership_proof",
+		"params": [{
+			"commit": "09cba5290aa8ed28a5bd1ae1087fb50e356cbeef2b06b9a7cc6c5a0c5a5e2f1d9b",
+			"amount": 1000000000,
+			"challenge": "reserves 2020-10-01",
+			"signature": "6b6bc1f2b7f3bca39fb35b1f5ca39b6a8ac35e6e7cf2a2c1a2ca1f1d8e5d38e8fd8b3f2c5e4e1a5a2d8d3c2e2b1a1d4c2b3f8a8e1c2b5d9e4f1a2c3b4d5e6f70"
+		}],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"commit": "09cba5290aa8ed28a5bd1ae1087fb50e356cbeef2b06b9a7cc6c5a0c5a5e2f1d9b",
+				"amount": 1000000000,
+				"unspent": false,
+				"height": null,
+				"valid": false
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn verify_ownership_proof(
+		&self,
+		proof: OwnershipProof,
+	) -> Result<OwnershipProofVerification, ErrorKind>;
+
 	/**
 	Networked version of [Foreign::get_pool_size](struct.Foreign.html#method.get_pool_size).
 
@@ -863,6 +1122,272 @@ pub trait ForeignRpc: Sync + Send {
 	```
 	 */
 	fn push_transaction(&self, tx: Transaction, fluff: Option<bool>) -> Result<(), ErrorKind>;
+
+	/**
+	Networked version of [Foreign::push_transaction_sync](struct.Foreign.html#method.push_transaction_sync).
+	Takes the same parameters as `push_transaction`.
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "push_transaction_sync",
+		"params": [ { "body": { "inputs": [], "kernels": [], "outputs": [] }, "offset": "0ec14d3875ad5a366418256fe65bad2a4d4ff1914e1b9488db72dd355138ca3a" }, true ],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"tx_hash": "4a8e8ed9b8f67a4b1d5bb4ac6c1e3e5c0a4a6bb1d1b2c3f3c5dbb8d9a2c5f1e0",
+				"kernels": [
+					"08385257d22f1b8a758903f78ae12545245d620cffc50e7ee7bc852c5815513dc7"
+				],
+				"state": "Txpool"
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn push_transaction_sync(
+		&self,
+		tx: Transaction,
+		fluff: Option<bool>,
+	) -> Result<PushedTransaction, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_transaction_state](struct.Foreign.html#method.get_transaction_state).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_transaction_state",
+		"params": ["08385257d22f1b8a758903f78ae12545245d620cffc50e7ee7bc852c5815513dc7"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": "Stempool"
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_transaction_state(&self, excess: String) -> Result<PoolTxState, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_kernels_by_fee](struct.Foreign.html#method.get_kernels_by_fee).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_kernels_by_fee",
+		"params": [10000000, 1, 5],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"excess": "08d3f1eee2fdd1a0a5e9eee4d8f9e15ad17ab3ba8d3dc1ef9bc2dd1e2b6ab7ed2a",
+					"fee": 12000000,
+					"height": 4
+				}
+			]
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_kernels_by_fee(
+		&self,
+		min_fee: u64,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<Vec<KernelFeePrintable>, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_fee_histogram](struct.Foreign.html#method.get_fee_histogram).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_fee_histogram",
+		"params": [1, 5, 5000000],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"min_fee": 5000000,
+					"max_fee": 9999999,
+					"count": 3
+				},
+				{
+					"min_fee": 10000000,
+					"max_fee": 14999999,
+					"count": 1
+				}
+			]
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_fee_histogram(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+		bucket_size: u64,
+	) -> Result<Vec<FeeHistogramBucket>, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_kernel_fee_index_status](struct.Foreign.html#method.get_kernel_fee_index_status).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_kernel_fee_index_status",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"start_height": 0,
+				"height": 5,
+				"chain_height": 5,
+				"progress": 100
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_kernel_fee_index_status(&self) -> Result<KernelFeeIndexStatus, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_header_proof](struct.Foreign.html#method.get_header_proof).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_header_proof",
+		"params": [5, 2],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Err": {
+				"Argument": "from_height 5 is above to_height 2"
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_header_proof(
+		&self,
+		from_height: u64,
+		to_height: u64,
+	) -> Result<HeaderChainProof, ErrorKind>;
+
+	/**
+	Networked version of [Foreign::get_block_fullness](struct.Foreign.html#method.get_block_fullness).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_foreign_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_block_fullness",
+		"params": [5, 2, null],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Err": {
+				"Argument": "start_height 5 is above end_height 2"
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_block_fullness(
+		&self,
+		start_height: Option<u64>,
+		end_height: Option<u64>,
+		window: Option<u64>,
+	) -> Result<Vec<BlockFullness>, ErrorKind>;
 	/*
 	/**
 	Networked version of [Owner::get_libp2p_peers](struct.Owner.html#method.get_libp2p_peers).
@@ -905,6 +1430,13 @@ pub trait ForeignRpc: Sync + Send {
 		// No example because if current time dynamic nature.
 	*/
 	fn get_libp2p_messages(&self) -> Result<Libp2pMessages, ErrorKind>;
+
+	/**
+		Networked version of [Foreign::get_libp2p_gossip_diagnostics](struct.Foreign.html#method.get_libp2p_gossip_diagnostics).
+
+		// No example because if current time dynamic nature.
+	*/
+	fn get_libp2p_gossip_diagnostics(&self) -> Result<Libp2pGossipDiagnostics, ErrorKind>;
 	*/
 }
 
@@ -966,6 +1498,10 @@ where
 		Foreign::get_version(self).map_err(|e| e.kind().clone())
 	}
 
+	fn get_protocol_schedule(&self) -> Result<ProtocolSchedule, ErrorKind> {
+		Foreign::get_protocol_schedule(self).map_err(|e| e.kind().clone())
+	}
+
 	fn get_tip(&self) -> Result<Tip, ErrorKind> {
 		Foreign::get_tip(self).map_err(|e| e.kind().clone())
 	}
@@ -979,6 +1515,29 @@ where
 		Foreign::get_kernel(self, excess, min_height, max_height).map_err(|e| e.kind().clone())
 	}
 
+	fn watch_kernels(
+		&self,
+		excess_list: Vec<String>,
+		depth: Option<u64>,
+	) -> Result<KernelWatch, ErrorKind> {
+		Foreign::watch_kernels(self, excess_list, depth).map_err(|e| e.kind().clone())
+	}
+
+	fn get_confirmation_safety(
+		&self,
+		kernel_excess: String,
+	) -> Result<ConfirmationSafety, ErrorKind> {
+		Foreign::get_confirmation_safety(self, kernel_excess).map_err(|e| e.kind().clone())
+	}
+
+	fn get_kernel_watch(&self, watch_id: u64) -> Result<KernelWatch, ErrorKind> {
+		Foreign::get_kernel_watch(self, watch_id).map_err(|e| e.kind().clone())
+	}
+
+	fn unwatch_kernels(&self, watch_id: u64) -> Result<(), ErrorKind> {
+		Foreign::unwatch_kernels(self, watch_id).map_err(|e| e.kind().clone())
+	}
+
 	fn get_outputs(
 		&self,
 		commits: Option<Vec<String>>,
@@ -1018,6 +1577,13 @@ where
 			.map_err(|e| e.kind().clone())
 	}
 
+	fn verify_ownership_proof(
+		&self,
+		proof: OwnershipProof,
+	) -> Result<OwnershipProofVerification, ErrorKind> {
+		Foreign::verify_ownership_proof(self, proof).map_err(|e| e.kind().clone())
+	}
+
 	fn get_pool_size(&self) -> Result<usize, ErrorKind> {
 		Foreign::get_pool_size(self).map_err(|e| e.kind().clone())
 	}
@@ -1032,6 +1598,60 @@ where
 	fn push_transaction(&self, tx: Transaction, fluff: Option<bool>) -> Result<(), ErrorKind> {
 		Foreign::push_transaction(self, tx, fluff).map_err(|e| e.kind().clone())
 	}
+
+	fn push_transaction_sync(
+		&self,
+		tx: Transaction,
+		fluff: Option<bool>,
+	) -> Result<PushedTransaction, ErrorKind> {
+		Foreign::push_transaction_sync(self, tx, fluff).map_err(|e| e.kind().clone())
+	}
+
+	fn get_transaction_state(&self, excess: String) -> Result<PoolTxState, ErrorKind> {
+		Foreign::get_transaction_state(self, excess).map_err(|e| e.kind().clone())
+	}
+
+	fn get_kernels_by_fee(
+		&self,
+		min_fee: u64,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<Vec<KernelFeePrintable>, ErrorKind> {
+		Foreign::get_kernels_by_fee(self, min_fee, start_height, end_height)
+			.map_err(|e| e.kind().clone())
+	}
+
+	fn get_fee_histogram(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+		bucket_size: u64,
+	) -> Result<Vec<FeeHistogramBucket>, ErrorKind> {
+		Foreign::get_fee_histogram(self, start_height, end_height, bucket_size)
+			.map_err(|e| e.kind().clone())
+	}
+
+	fn get_kernel_fee_index_status(&self) -> Result<KernelFeeIndexStatus, ErrorKind> {
+		Foreign::get_kernel_fee_index_status(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_header_proof(
+		&self,
+		from_height: u64,
+		to_height: u64,
+	) -> Result<HeaderChainProof, ErrorKind> {
+		Foreign::get_header_proof(self, from_height, to_height).map_err(|e| e.kind().clone())
+	}
+
+	fn get_block_fullness(
+		&self,
+		start_height: Option<u64>,
+		end_height: Option<u64>,
+		window: Option<u64>,
+	) -> Result<Vec<BlockFullness>, ErrorKind> {
+		Foreign::get_block_fullness(self, start_height, end_height, window)
+			.map_err(|e| e.kind().clone())
+	}
 	/*
 	#[cfg(feature = "marketplace")]
 	fn get_libp2p_peers(&self) -> Result<Libp2pPeers, ErrorKind> {
@@ -1041,6 +1661,10 @@ where
 	fn get_libp2p_messages(&self) -> Result<Libp2pMessages, ErrorKind> {
 		Foreign::get_libp2p_messages(self).map_err(|e| e.kind().clone())
 	}
+	#[cfg(feature = "marketplace")]
+	fn get_libp2p_gossip_diagnostics(&self) -> Result<Libp2pGossipDiagnostics, ErrorKind> {
+		Foreign::get_libp2p_gossip_diagnostics(self).map_err(|e| e.kind().clone())
+	}
 	*/
 }
 
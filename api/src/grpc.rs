@@ -0,0 +1,538 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! gRPC server next to the REST and JSON-RPC APIs, for the backends that generate
+//! typed clients from the protobuf definitions. The definitions are in
+//! `api/proto/mwc_node.proto`. The services are thin wrappers of the `Foreign` and
+//! `Owner` APIs, the secrets are the same as the ones of the HTTP APIs.
+//! The server is built with the `grpc` feature only.
+
+/// gRPC server configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GrpcConfig {
+	/// Whether to run the gRPC server
+	#[serde(default)]
+	pub enable_grpc: bool,
+	/// Listening address of the gRPC server
+	#[serde(default = "default_grpc_addr")]
+	pub grpc_addr: String,
+}
+
+fn default_grpc_addr() -> String {
+	"127.0.0.1:3419".to_string()
+}
+
+impl Default for GrpcConfig {
+	fn default() -> GrpcConfig {
+		GrpcConfig {
+			enable_grpc: false,
+			grpc_addr: default_grpc_addr(),
+		}
+	}
+}
+
+#[cfg(feature = "grpc")]
+pub use self::server::{proto, start_grpc_server, to_proto_event, ForeignGrpc, OwnerGrpc};
+
+#[cfg(feature = "grpc")]
+mod server {
+	use crate::chain::{Chain, SyncState};
+	use crate::core::core::hash::{Hash, Hashed};
+	use crate::core::core::verifier_cache::VerifierCache;
+	use crate::core::core::Transaction;
+	use crate::core::ser::{self, ProtocolVersion};
+	use crate::foreign::Foreign;
+	use crate::handlers::basic_auth_user;
+	use crate::kernel_watch::kernel_watch_hub;
+	use crate::owner::Owner;
+	use crate::p2p;
+	use crate::pool::{BlockChain, PoolAdapter, TransactionPool};
+	use crate::pool_events::TxPoolEventsHub;
+	use crate::rest::{Error, ErrorKind};
+	use crate::standby::is_standby;
+	use crate::types::{
+		BlockHeaderPrintable, BlockPrintable, OutputPrintable, OutputType, Status, Tip,
+		TxKernelPrintable,
+	};
+	use crate::util::{self, to_base64, RwLock, StopState, ToHex};
+	use futures::{Stream, StreamExt};
+	use ring::constant_time::verify_slices_are_equal;
+	use std::net::SocketAddr;
+	use std::pin::Pin;
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+	use tokio::sync::mpsc;
+	use tonic::transport::Server;
+	use tonic::{Code, Request, Response, Status as GrpcStatus};
+
+	use self::proto::foreign_server::{Foreign as ForeignService, ForeignServer};
+	use self::proto::owner_server::{Owner as OwnerService, OwnerServer};
+
+	/// Generated protobuf messages and services
+	pub mod proto {
+		tonic::include_proto!("mwc.node.v1");
+	}
+
+	// Subscriber that doesn't read the events is disconnected when this many are queued
+	const TIP_STREAM_CAPACITY: usize = 16;
+	const TIP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+	type EventStream =
+		Pin<Box<dyn Stream<Item = Result<proto::Event, GrpcStatus>> + Send + Sync + 'static>>;
+	type TipStream =
+		Pin<Box<dyn Stream<Item = Result<proto::Tip, GrpcStatus>> + Send + Sync + 'static>>;
+
+	fn to_status(e: Error) -> GrpcStatus {
+		let code = match e.kind() {
+			ErrorKind::Argument(_) | ErrorKind::RequestError(_) => Code::InvalidArgument,
+			ErrorKind::NotFound(_) => Code::NotFound,
+			ErrorKind::Standby(_) | ErrorKind::ColdStorage(_) => Code::Unavailable,
+			_ => Code::Internal,
+		};
+		GrpcStatus::new(code, e.to_string())
+	}
+
+	fn non_zero(value: u64) -> Option<u64> {
+		if value == 0 {
+			None
+		} else {
+			Some(value)
+		}
+	}
+
+	impl From<Tip> for proto::Tip {
+		fn from(tip: Tip) -> proto::Tip {
+			proto::Tip {
+				height: tip.height,
+				last_block_pushed: tip.last_block_pushed,
+				prev_block_to_last: tip.prev_block_to_last,
+				total_difficulty: tip.total_difficulty,
+			}
+		}
+	}
+
+	impl From<BlockHeaderPrintable> for proto::BlockHeader {
+		fn from(header: BlockHeaderPrintable) -> proto::BlockHeader {
+			proto::BlockHeader {
+				hash: header.hash,
+				version: header.version as u32,
+				height: header.height,
+				previous: header.previous,
+				prev_root: header.prev_root,
+				timestamp: header.timestamp,
+				output_root: header.output_root,
+				output_mmr_size: header.output_mmr_size,
+				range_proof_root: header.range_proof_root,
+				kernel_root: header.kernel_root,
+				kernel_mmr_size: header.kernel_mmr_size,
+				nonce: header.nonce,
+				edge_bits: header.edge_bits as u32,
+				cuckoo_solution: header.cuckoo_solution,
+				total_difficulty: header.total_difficulty,
+				secondary_scaling: header.secondary_scaling,
+				total_kernel_offset: header.total_kernel_offset,
+			}
+		}
+	}
+
+	impl From<OutputPrintable> for proto::Output {
+		fn from(output: OutputPrintable) -> proto::Output {
+			let output_type = match output.output_type {
+				OutputType::Coinbase => proto::OutputType::Coinbase,
+				OutputType::Transaction => proto::OutputType::Transaction,
+			};
+			proto::Output {
+				output_type: output_type as i32,
+				commit: output.commit.to_hex(),
+				spent: output.spent,
+				proof: output.proof.unwrap_or_default(),
+				proof_hash: output.proof_hash,
+				block_height: output.block_height.unwrap_or(0),
+				merkle_proof: output.merkle_proof.map(|p| p.to_hex()).unwrap_or_default(),
+				mmr_index: output.mmr_index,
+			}
+		}
+	}
+
+	impl From<TxKernelPrintable> for proto::Kernel {
+		fn from(kernel: TxKernelPrintable) -> proto::Kernel {
+			proto::Kernel {
+				features: kernel.features,
+				fee: kernel.fee,
+				lock_height: kernel.lock_height,
+				excess: kernel.excess,
+				excess_sig: kernel.excess_sig,
+			}
+		}
+	}
+
+	impl From<BlockPrintable> for proto::Block {
+		fn from(block: BlockPrintable) -> proto::Block {
+			proto::Block {
+				header: Some(block.header.into()),
+				inputs: block.inputs,
+				outputs: block.outputs.into_iter().map(|o| o.into()).collect(),
+				kernels: block.kernels.into_iter().map(|k| k.into()).collect(),
+			}
+		}
+	}
+
+	impl From<Status> for proto::Status {
+		fn from(status: Status) -> proto::Status {
+			proto::Status {
+				protocol_version: status.protocol_version,
+				user_agent: status.user_agent,
+				connections: status.connections,
+				tip: Some(status.tip.into()),
+				sync_status: status.sync_status,
+				sync_info: status
+					.sync_info
+					.map(|info| info.to_string())
+					.unwrap_or_default(),
+				standby: is_standby(),
+			}
+		}
+	}
+
+	/// Event of the websocket feed, the json message has the "seq" and "event" fields
+	pub fn to_proto_event(json: &str) -> proto::Event {
+		let value: serde_json::Value = serde_json::from_str(json).unwrap_or_default();
+		proto::Event {
+			seq: value["seq"].as_u64().unwrap_or(0),
+			event: value["event"].as_str().unwrap_or_default().to_string(),
+			json: json.to_string(),
+		}
+	}
+
+	fn event_stream(events: mpsc::Receiver<Arc<String>>) -> EventStream {
+		Box::pin(events.map(|json| Ok(to_proto_event(&json))))
+	}
+
+	/// Foreign API service
+	pub struct ForeignGrpc<B, P, V>
+	where
+		B: BlockChain,
+		P: PoolAdapter,
+		V: VerifierCache + 'static,
+	{
+		foreign: Foreign<B, P, V>,
+		pool_events: Arc<TxPoolEventsHub>,
+	}
+
+	impl<B, P, V> ForeignGrpc<B, P, V>
+	where
+		B: BlockChain,
+		P: PoolAdapter,
+		V: VerifierCache + 'static,
+	{
+		/// Service of the foreign API instance
+		pub fn new(foreign: Foreign<B, P, V>, pool_events: Arc<TxPoolEventsHub>) -> Self {
+			ForeignGrpc {
+				foreign,
+				pool_events,
+			}
+		}
+	}
+
+	#[tonic::async_trait]
+	impl<B, P, V> ForeignService for ForeignGrpc<B, P, V>
+	where
+		B: BlockChain + 'static,
+		P: PoolAdapter + 'static,
+		V: VerifierCache + 'static,
+	{
+		type SubscribeTipStream = TipStream;
+		type SubscribePoolEventsStream = EventStream;
+		type SubscribeKernelEventsStream = EventStream;
+
+		async fn get_tip(
+			&self,
+			_request: Request<proto::GetTipRequest>,
+		) -> Result<Response<proto::Tip>, GrpcStatus> {
+			let tip = self.foreign.get_tip().map_err(to_status)?;
+			Ok(Response::new(tip.into()))
+		}
+
+		async fn get_block(
+			&self,
+			request: Request<proto::GetBlockRequest>,
+		) -> Result<Response<proto::Block>, GrpcStatus> {
+			let request = request.into_inner();
+			let (height, hash, commit) = match request.query {
+				Some(proto::get_block_request::Query::Height(height)) => (Some(height), None, None),
+				Some(proto::get_block_request::Query::Hash(hash)) => {
+					let hash = Hash::from_hex(&hash).map_err(|e| {
+						GrpcStatus::invalid_argument(format!("Invalid hash {}, {}", hash, e))
+					})?;
+					(None, Some(hash), None)
+				}
+				Some(proto::get_block_request::Query::Commit(commit)) => (None, None, Some(commit)),
+				None => {
+					return Err(GrpcStatus::invalid_argument(
+						"height, hash or commit is expected",
+					))
+				}
+			};
+			let block = self
+				.foreign
+				.get_block(
+					height,
+					hash,
+					commit,
+					Some(request.include_proof),
+					Some(request.include_merkle_proof),
+				)
+				.map_err(to_status)?;
+			Ok(Response::new(block.into()))
+		}
+
+		async fn get_outputs(
+			&self,
+			request: Request<proto::GetOutputsRequest>,
+		) -> Result<Response<proto::GetOutputsResponse>, GrpcStatus> {
+			let request = request.into_inner();
+			let commits = if request.commits.is_empty() {
+				None
+			} else {
+				Some(request.commits)
+			};
+			let outputs = self
+				.foreign
+				.get_outputs(
+					commits,
+					non_zero(request.start_height),
+					non_zero(request.end_height),
+					Some(request.include_proof),
+					Some(request.include_merkle_proof),
+				)
+				.map_err(to_status)?;
+			Ok(Response::new(proto::GetOutputsResponse {
+				outputs: outputs.into_iter().map(|o| o.into()).collect(),
+			}))
+		}
+
+		async fn push_transaction(
+			&self,
+			request: Request<proto::PushTransactionRequest>,
+		) -> Result<Response<proto::PushTransactionResponse>, GrpcStatus> {
+			let request = request.into_inner();
+			let tx_bin = util::from_hex(&request.tx_hex).map_err(|e| {
+				GrpcStatus::invalid_argument(format!("Unable to decode transaction hex, {}", e))
+			})?;
+			// All wallet api interaction explicitly uses protocol version 1 for now.
+			let tx: Transaction =
+				ser::deserialize(&mut &tx_bin[..], ProtocolVersion(1)).map_err(|e| {
+					GrpcStatus::invalid_argument(format!(
+						"Unable to deserialize transaction, {}",
+						e
+					))
+				})?;
+			let tx_hash = tx.hash().to_hex();
+			self.foreign
+				.push_transaction(tx, Some(request.fluff))
+				.map_err(to_status)?;
+			Ok(Response::new(proto::PushTransactionResponse { tx_hash }))
+		}
+
+		async fn subscribe_tip(
+			&self,
+			_request: Request<proto::SubscribeTipRequest>,
+		) -> Result<Response<TipStream>, GrpcStatus> {
+			let chain = self.foreign.chain.clone();
+			let (mut sender, receiver) = mpsc::channel(TIP_STREAM_CAPACITY);
+			tokio::spawn(async move {
+				let mut last_hash = None;
+				loop {
+					let head = match chain.upgrade().map(|c| c.head()) {
+						Some(Ok(head)) => head,
+						Some(Err(e)) => {
+							debug!("gRPC tip subscriber, unable to read the head, {}", e);
+							tokio::time::delay_for(TIP_POLL_INTERVAL).await;
+							continue;
+						}
+						// Node is stopped
+						None => break,
+					};
+					if last_hash != Some(head.last_block_h) {
+						last_hash = Some(head.last_block_h);
+						let tip = proto::Tip::from(Tip::from_tip(head));
+						if sender.send(Ok(tip)).await.is_err() {
+							// Subscriber is gone
+							break;
+						}
+					}
+					tokio::time::delay_for(TIP_POLL_INTERVAL).await;
+				}
+			});
+			Ok(Response::new(Box::pin(receiver)))
+		}
+
+		async fn subscribe_pool_events(
+			&self,
+			request: Request<proto::SubscribeEventsRequest>,
+		) -> Result<Response<EventStream>, GrpcStatus> {
+			let resume_from = non_zero(request.into_inner().resume_from);
+			let events = self.pool_events.subscribe(resume_from).map_err(to_status)?;
+			Ok(Response::new(event_stream(events)))
+		}
+
+		async fn subscribe_kernel_events(
+			&self,
+			request: Request<proto::SubscribeEventsRequest>,
+		) -> Result<Response<EventStream>, GrpcStatus> {
+			let resume_from = non_zero(request.into_inner().resume_from);
+			let events = kernel_watch_hub()
+				.subscribe(resume_from)
+				.map_err(to_status)?;
+			Ok(Response::new(event_stream(events)))
+		}
+	}
+
+	/// Owner API service
+	pub struct OwnerGrpc {
+		owner: Owner,
+	}
+
+	impl OwnerGrpc {
+		/// Service of the owner API instance
+		pub fn new(owner: Owner) -> OwnerGrpc {
+			OwnerGrpc { owner }
+		}
+	}
+
+	#[tonic::async_trait]
+	impl OwnerService for OwnerGrpc {
+		async fn get_status(
+			&self,
+			_request: Request<proto::GetStatusRequest>,
+		) -> Result<Response<proto::Status>, GrpcStatus> {
+			let status = self.owner.get_status().map_err(to_status)?;
+			Ok(Response::new(status.into()))
+		}
+	}
+
+	// Requests must carry the same basic authorization header as the HTTP API requests
+	fn basic_auth(
+		secret: Option<String>,
+	) -> impl Fn(Request<()>) -> Result<Request<()>, GrpcStatus> + Clone {
+		let expected = secret.map(|secret| {
+			format!(
+				"Basic {}",
+				to_base64(&format!("{}:{}", basic_auth_user(), secret))
+			)
+		});
+		move |request: Request<()>| match &expected {
+			None => Ok(request),
+			Some(expected) => match request.metadata().get("authorization") {
+				Some(value)
+					if verify_slices_are_equal(value.as_bytes(), expected.as_bytes()).is_ok() =>
+				{
+					Ok(request)
+				}
+				_ => Err(GrpcStatus::unauthenticated("Invalid authorization")),
+			},
+		}
+	}
+
+	/// Start the gRPC server on its own thread, it is stopped with the node.
+	/// Owner service accepts the api secret only, so it is not started when the
+	/// owner API requires the session tokens.
+	pub fn start_grpc_server<B, P, V>(
+		addr: &str,
+		chain: Arc<Chain>,
+		tx_pool: Arc<RwLock<TransactionPool<B, P, V>>>,
+		peers: Arc<p2p::Peers>,
+		sync_state: Arc<SyncState>,
+		pool_events: Arc<TxPoolEventsHub>,
+		api_secret: Option<String>,
+		foreign_api_secret: Option<String>,
+		owner_sessions: bool,
+		stop_state: Arc<StopState>,
+	) -> Result<thread::JoinHandle<()>, Error>
+	where
+		B: BlockChain + 'static,
+		P: PoolAdapter + 'static,
+		V: VerifierCache + 'static,
+	{
+		let socket_addr: SocketAddr = addr
+			.parse()
+			.map_err(|e| ErrorKind::Argument(format!("Invalid gRPC address {}, {}", addr, e)))?;
+		let foreign = Foreign::new(
+			Arc::downgrade(&peers),
+			Arc::downgrade(&chain),
+			Arc::downgrade(&tx_pool),
+			Arc::downgrade(&sync_state),
+		);
+		let owner = Owner::new(
+			Arc::downgrade(&chain),
+			Arc::downgrade(&peers),
+			Arc::downgrade(&sync_state),
+			None,
+		);
+		let foreign = ForeignServer::with_interceptor(
+			ForeignGrpc::new(foreign, pool_events),
+			basic_auth(foreign_api_secret),
+		);
+		let owner = if owner_sessions && api_secret.is_some() {
+			warn!("gRPC Owner service is disabled because the owner API sessions are enabled");
+			None
+		} else {
+			Some(OwnerServer::with_interceptor(
+				OwnerGrpc::new(owner),
+				basic_auth(api_secret),
+			))
+		};
+
+		thread::Builder::new()
+			.name("grpc_server".to_string())
+			.spawn(move || {
+				let mut runtime = match tokio::runtime::Builder::new()
+					.threaded_scheduler()
+					.enable_all()
+					.build()
+				{
+					Ok(runtime) => runtime,
+					Err(e) => {
+						error!("Unable to start gRPC server runtime, {}", e);
+						return;
+					}
+				};
+				let stopped = async move {
+					while !stop_state.is_stopped() {
+						tokio::time::delay_for(Duration::from_millis(100)).await;
+					}
+				};
+				warn!("Starting gRPC server at {}", socket_addr);
+				let res = match owner {
+					Some(owner) => runtime.block_on(
+						Server::builder()
+							.add_service(foreign)
+							.add_service(owner)
+							.serve_with_shutdown(socket_addr, stopped),
+					),
+					None => runtime.block_on(
+						Server::builder()
+							.add_service(foreign)
+							.serve_with_shutdown(socket_addr, stopped),
+					),
+				};
+				match res {
+					Ok(_) => info!("gRPC server stopped"),
+					Err(e) => error!("gRPC server failed, {}", e),
+				}
+			})
+			.map_err(|e| ErrorKind::Internal(format!("Unable to start gRPC server, {}", e)).into())
+	}
+}
@@ -31,27 +31,38 @@ use self::chain_api::OutputHandler;
 use self::peers_api::PeerHandler;
 use self::peers_api::PeersAllHandler;
 use self::peers_api::PeersConnectedHandler;
-use self::pool_api::PoolInfoHandler;
 use self::pool_api::PoolPushHandler;
+use self::pool_api::{DandelionControl, DandelionHandler, PoolInfoHandler};
 use self::server_api::IndexHandler;
 use self::server_api::StatusHandler;
+use self::server_api::{HealthHandler, ReadyHandler};
 use self::transactions_api::TxHashSetHandler;
 use self::version_api::VersionHandler;
 use crate::auth::{
-	BasicAuthMiddleware, BasicAuthURIMiddleware, MWC_BASIC_REALM, MWC_FOREIGN_BASIC_REALM,
+	BasicAuthMiddleware, BasicAuthURIMiddleware, OwnerSessionMiddleware, MWC_BASIC_REALM,
+	MWC_FOREIGN_BASIC_REALM,
 };
 use crate::chain;
 use crate::chain::{Chain, SyncState};
+use crate::compression::CompressionMiddleware;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::global;
 use crate::core::stratum;
 use crate::foreign::Foreign;
 use crate::foreign_rpc::ForeignRpc;
+use crate::http_config::{BodyLimitMiddleware, HttpConfig};
+use crate::kernel_watch::{kernel_watch_hub, KernelWatchEventsHandler};
+use crate::load_shedding::{create_busy_response, LoadSheddingConfig, LoadSheddingMiddleware};
 use crate::owner::Owner;
 use crate::owner_rpc::OwnerRpc;
+use crate::owner_session::{
+	owner_request_methods, OwnerAuditHandler, OwnerLoginHandler, OwnerLogoutHandler, OwnerSession,
+	OwnerSessionConfig, OwnerSessions, OwnerSessionsHandler,
+};
 use crate::p2p;
 use crate::pool;
 use crate::pool::{BlockChain, PoolAdapter};
+use crate::pool_events::{TxPoolEventsHandler, TxPoolEventsHub};
 use crate::rest::{ApiServer, Error, ErrorKind, TLSConfig};
 use crate::router::ResponseFuture;
 use crate::router::{Router, RouterError};
@@ -82,6 +93,11 @@ pub fn node_apis<B, P, V>(
 	tls_config: Option<TLSConfig>,
 	allow_to_stop: bool,
 	stratum_ip_pool: Arc<stratum::connections::StratumIpPool>,
+	load_shedding: LoadSheddingConfig,
+	http_config: HttpConfig,
+	ready_max_blocks_behind: u64,
+	owner_session_config: OwnerSessionConfig,
+	pool_events: Arc<TxPoolEventsHub>,
 	api_chan: &'static mut (oneshot::Sender<()>, oneshot::Receiver<()>),
 	stop_state: Arc<StopState>,
 ) -> Result<(), Error>
@@ -101,13 +117,54 @@ where
 	)
 	.expect("unable to build API router");
 
-	let basic_auth_key = if global::is_mainnet() {
-		"mwcmain"
-	} else if global::is_floonet() {
-		"mwcfloo"
+	if http_config.max_request_body_size > 0 {
+		router.add_middleware(Arc::new(BodyLimitMiddleware::new(
+			http_config.max_request_body_size,
+		)));
+	}
+	if http_config.enable_compression {
+		router.add_middleware(Arc::new(CompressionMiddleware::new(
+			http_config.compression_min_size,
+		)));
+	}
+
+	let basic_auth_key = basic_auth_user();
+
+	// Health checks are polled by the load balancers, they don't need the secret
+	let health_handler = HealthHandler {
+		chain: Arc::downgrade(&chain),
+		peers: Arc::downgrade(&peers),
+	};
+	router.add_route("/healthz", Arc::new(health_handler))?;
+	let ready_handler = ReadyHandler {
+		chain: Arc::downgrade(&chain),
+		peers: Arc::downgrade(&peers),
+		sync_state: Arc::downgrade(&sync_state),
+		max_blocks_behind: ready_max_blocks_behind,
+	};
+	router.add_route("/readyz", Arc::new(ready_handler))?;
+
+	// Owner API session tokens are issued for the api secret, without the secret
+	// there is nothing to log in with
+	let owner_sessions = if owner_session_config.enable_owner_sessions {
+		if api_secret.is_some() {
+			Some(Arc::new(OwnerSessions::new(owner_session_config)))
+		} else {
+			warn!("Owner API sessions are disabled because api secret is not set");
+			None
+		}
 	} else {
-		"mwc"
+		None
 	};
+	// URIs that require the session token instead of the api secret. v1 API exposes the
+	// same owner data and actions, it doesn't accept the secret either.
+	let owner_session_uris: Vec<String> = vec![
+		"/v1/".into(),
+		"/v2/owner".into(),
+		"/v2/owner/logout".into(),
+		"/v2/owner/sessions".into(),
+		"/v2/owner/audit".into(),
+	];
 
 	// Add basic auth to v1 API and owner v2 API
 	if let Some(api_secret) = api_secret {
@@ -116,24 +173,74 @@ where
 			to_base64(&format!("{}:{}", basic_auth_key, api_secret))
 		);
 
+		let mut ignore_uris: Vec<String> =
+			vec!["/v2/foreign".into(), "/healthz".into(), "/readyz".into()];
+		if owner_sessions.is_some() {
+			ignore_uris.extend(owner_session_uris.iter().cloned());
+		}
 		let basic_auth_middleware = Arc::new(BasicAuthMiddleware::new(
 			api_basic_auth,
 			&MWC_BASIC_REALM,
-			Some("/v2/foreign".into()),
+			ignore_uris,
 		));
 		router.add_middleware(basic_auth_middleware);
 	}
 
+	if let Some(sessions) = &owner_sessions {
+		router.add_middleware(Arc::new(OwnerSessionMiddleware::new(
+			sessions.clone(),
+			owner_session_uris,
+			vec!["/v1/".into()],
+		)));
+		router.add_route(
+			"/v2/owner/login",
+			Arc::new(OwnerLoginHandler {
+				sessions: sessions.clone(),
+			}),
+		)?;
+		router.add_route(
+			"/v2/owner/logout",
+			Arc::new(OwnerLogoutHandler {
+				sessions: sessions.clone(),
+			}),
+		)?;
+		router.add_route(
+			"/v2/owner/sessions",
+			Arc::new(OwnerSessionsHandler {
+				sessions: sessions.clone(),
+			}),
+		)?;
+		router.add_route(
+			"/v2/owner/audit",
+			Arc::new(OwnerAuditHandler {
+				sessions: sessions.clone(),
+			}),
+		)?;
+	}
+
+	let dandelion_handler: Arc<dyn DandelionControl> = Arc::new(DandelionHandler {
+		tx_pool: Arc::downgrade(&tx_pool),
+	});
 	let api_handler_v2 = OwnerAPIHandlerV2::new(
 		Arc::downgrade(&chain),
 		Arc::downgrade(&peers),
 		Arc::downgrade(&sync_state),
+		Some(dandelion_handler),
+		owner_sessions,
 	);
 	router.add_route("/v2/owner", Arc::new(api_handler_v2))?;
 
 	let stratum_handler_v2 = StratumAPIHandlerV2::new(stratum_ip_pool);
 	router.add_route("/v2/stratum", Arc::new(stratum_handler_v2))?;
 
+	let pool_events_handler = TxPoolEventsHandler { hub: pool_events };
+	router.add_route("/v2/pool/events", Arc::new(pool_events_handler))?;
+
+	let kernel_watch_handler = KernelWatchEventsHandler {
+		hub: kernel_watch_hub(),
+	};
+	router.add_route("/v2/kernels/events", Arc::new(kernel_watch_handler))?;
+
 	// Add basic auth to v2 foreign API only
 	if let Some(api_secret) = foreign_api_secret {
 		let api_basic_auth = format!(
@@ -149,15 +256,22 @@ where
 		router.add_middleware(basic_auth_middleware);
 	}
 
+	// Heavy v1 routes are shed after the authorization, same as the foreign API calls
+	router.add_middleware(Arc::new(LoadSheddingMiddleware::new(
+		load_shedding.clone(),
+		Arc::downgrade(&sync_state),
+	)));
+
 	let api_handler_v2 = ForeignAPIHandlerV2::new(
 		Arc::downgrade(&peers),
 		Arc::downgrade(&chain),
 		Arc::downgrade(&tx_pool),
 		Arc::downgrade(&sync_state),
+		load_shedding,
 	);
 	router.add_route("/v2/foreign", Arc::new(api_handler_v2))?;
 
-	let mut apis = ApiServer::new();
+	let mut apis = ApiServer::with_http_config(http_config);
 	warn!("Starting HTTP Node APIs server at {}.", addr);
 	let socket_addr: SocketAddr = addr.parse().expect("unable to parse socket address");
 	let api_thread = apis.start(socket_addr, router, tls_config, api_chan);
@@ -187,20 +301,41 @@ where
 	}
 }
 
+/// User name of the basic authorization, the secret is the password
+pub(crate) fn basic_auth_user() -> &'static str {
+	if global::is_mainnet() {
+		"mwcmain"
+	} else if global::is_floonet() {
+		"mwcfloo"
+	} else {
+		"mwc"
+	}
+}
+
 /// V2 API Handler/Wrapper for owner functions
 pub struct OwnerAPIHandlerV2 {
 	pub chain: Weak<Chain>,
 	pub peers: Weak<p2p::Peers>,
 	pub sync_state: Weak<SyncState>,
+	pub dandelion: Option<Arc<dyn DandelionControl>>,
+	pub sessions: Option<Arc<OwnerSessions>>,
 }
 
 impl OwnerAPIHandlerV2 {
 	/// Create a new owner API handler for GET methods
-	pub fn new(chain: Weak<Chain>, peers: Weak<p2p::Peers>, sync_state: Weak<SyncState>) -> Self {
+	pub fn new(
+		chain: Weak<Chain>,
+		peers: Weak<p2p::Peers>,
+		sync_state: Weak<SyncState>,
+		dandelion: Option<Arc<dyn DandelionControl>>,
+		sessions: Option<Arc<OwnerSessions>>,
+	) -> Self {
 		OwnerAPIHandlerV2 {
 			chain,
 			peers,
 			sync_state,
+			dandelion,
+			sessions,
 		}
 	}
 }
@@ -211,11 +346,23 @@ impl crate::router::Handler for OwnerAPIHandlerV2 {
 			self.chain.clone(),
 			self.peers.clone(),
 			self.sync_state.clone(),
+			self.dandelion.clone(),
 		);
+		// Session of the token the request is authorized with
+		let audit = match (&self.sessions, req.extensions().get::<OwnerSession>()) {
+			(Some(sessions), Some(session)) => Some((sessions.clone(), session.clone())),
+			_ => None,
+		};
 
 		Box::pin(async move {
 			match parse_body(req).await {
 				Ok(val) => {
+					if let Some((sessions, session)) = audit {
+						let now = chrono::Utc::now().timestamp();
+						for method in owner_request_methods(&val) {
+							sessions.record(&session, &method, now);
+						}
+					}
 					let owner_api = &api as &dyn OwnerRpc;
 					let res = match owner_api.handle_request(val) {
 						MaybeReply::Reply(r) => r,
@@ -251,6 +398,7 @@ where
 	pub chain: Weak<Chain>,
 	pub tx_pool: Weak<RwLock<pool::TransactionPool<B, P, V>>>,
 	pub sync_state: Weak<SyncState>,
+	pub load_shedding: LoadSheddingConfig,
 }
 
 impl<B, P, V> ForeignAPIHandlerV2<B, P, V>
@@ -265,12 +413,22 @@ where
 		chain: Weak<Chain>,
 		tx_pool: Weak<RwLock<pool::TransactionPool<B, P, V>>>,
 		sync_state: Weak<SyncState>,
+		load_shedding: LoadSheddingConfig,
 	) -> Self {
 		ForeignAPIHandlerV2 {
 			peers,
 			chain,
 			tx_pool,
 			sync_state,
+			load_shedding,
+		}
+	}
+
+	// Heavy requests are rejected while the sync critical stages run
+	fn is_shedding(&self) -> bool {
+		match self.sync_state.upgrade() {
+			Some(sync_state) => self.load_shedding.is_shedding(&sync_state.status()),
+			None => false,
 		}
 	}
 }
@@ -288,10 +446,21 @@ where
 			self.tx_pool.clone(),
 			self.sync_state.clone(),
 		);
+		let shedding_config = if self.is_shedding() {
+			Some(self.load_shedding.clone())
+		} else {
+			None
+		};
 
 		Box::pin(async move {
 			match parse_body(req).await {
 				Ok(val) => {
+					if let Some(config) = shedding_config {
+						if config.is_heavy_request(&val) {
+							debug!("Foreign API request is rejected by load shedding");
+							return Ok(create_busy_response(config.load_shedding_retry_after_secs));
+						}
+					}
 					let foreign_api = &api as &dyn ForeignRpc;
 					let res = match foreign_api.handle_request(val) {
 						MaybeReply::Reply(r) => r,
@@ -459,6 +628,7 @@ where
 		"post peers/a.b.c.d:p/unban".to_string(),
 		"get peers/all".to_string(),
 		"get peers/connected".to_string(),
+		"get peers/census?upgrade_target=x.y.z".to_string(),
 		"get peers/a.b.c.d".to_string(),
 		"get version".to_string(),
 	];
@@ -472,6 +642,7 @@ where
 	};
 	let block_handler = BlockHandler {
 		chain: Arc::downgrade(&chain),
+		peers: Arc::downgrade(&peers),
 	};
 	let header_handler = HeaderHandler {
 		chain: Arc::downgrade(&chain),
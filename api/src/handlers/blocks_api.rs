@@ -15,20 +15,34 @@ use super::utils::{get_output, get_output_v2, w};
 use crate::chain;
 use crate::core::core::hash::Hash;
 use crate::core::core::hash::Hashed;
+use crate::core::core::{Block, BlockHeader};
+use crate::core::global;
+use crate::core::ser::{self, ProtocolVersion};
+use crate::p2p;
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
 use crate::types::*;
-use crate::util;
+use crate::util::{self, ToHex};
 use crate::web::*;
 use hyper::{Body, Request, StatusCode};
 use regex::Regex;
+use std::collections::VecDeque;
 use std::sync::Weak;
 
+/// Max number of the headers in the header chain proof
+pub const MAX_HEADER_PROOF_HEADERS: u64 = 2000;
+/// Max number of the blocks in the fullness series
+pub const MAX_FULLNESS_BLOCKS: u64 = 1440;
+/// Default rolling window of the fullness series, one hour of blocks
+pub const DEFAULT_FULLNESS_WINDOW: u64 = 60;
+
 /// Gets block headers given either a hash or height or an output commit.
 /// GET /v1/headers/<hash>
 /// GET /v1/headers/<height>
 /// GET /v1/headers/<output commit>
 ///
+/// Responses have the ETag, the request with the matching If-None-Match gets
+/// 304 Not Modified.
 pub struct HeaderHandler {
 	pub chain: Weak<chain::Chain>,
 }
@@ -86,6 +100,90 @@ impl HeaderHandler {
 		return Ok(BlockHeaderPrintable::from_header(&header));
 	}
 
+	/// Serialized header chain from `from_height` to `to_height` inclusive. Headers are
+	/// read from one chain snapshot, so the chain stays consistent during a reorg.
+	pub fn get_header_proof(
+		&self,
+		from_height: u64,
+		to_height: u64,
+	) -> Result<HeaderChainProof, Error> {
+		if from_height > to_height {
+			return Err(ErrorKind::Argument(format!(
+				"from_height {} is above to_height {}",
+				from_height, to_height
+			)))?;
+		}
+		if to_height - from_height >= MAX_HEADER_PROOF_HEADERS {
+			return Err(ErrorKind::Argument(format!(
+				"Too many headers requested, max is {}",
+				MAX_HEADER_PROOF_HEADERS
+			)))?;
+		}
+		let snapshot = w(&self.chain)?
+			.snapshot()
+			.map_err(|e| ErrorKind::Internal(format!("Unable to read the chain, {}", e)))?;
+		if to_height > snapshot.head().height {
+			return Err(ErrorKind::NotFound(format!(
+				"Header for height {}, chain height is {}",
+				to_height,
+				snapshot.head().height
+			)))?;
+		}
+
+		// Walking back by prev_hash, every header is linked to the previous one
+		let mut header = snapshot
+			.get_header_by_height(to_height)
+			.map_err(|e| ErrorKind::NotFound(format!("Header for height {}, {}", to_height, e)))?;
+		let last_hash = header.hash();
+		let total_difficulty = header.total_difficulty().to_num();
+		let mut headers = vec![];
+		loop {
+			let prev_hash = header.prev_hash;
+			let height = header.height;
+			headers.push(header);
+			if height <= from_height {
+				break;
+			}
+			header = snapshot
+				.get_block_header(&prev_hash)
+				.map_err(|e| ErrorKind::NotFound(format!("Header {}, {}", prev_hash, e)))?;
+		}
+		headers.reverse();
+
+		let first = &headers[0];
+		let start_total_difficulty = if first.height == 0 {
+			0
+		} else {
+			snapshot
+				.get_block_header(&first.prev_hash)
+				.map_err(|e| ErrorKind::NotFound(format!("Header {}, {}", first.prev_hash, e)))?
+				.total_difficulty()
+				.to_num()
+		};
+		let prev_hash = first.prev_hash.to_hex();
+
+		let version = ProtocolVersion::local();
+		let mut data = vec![];
+		for header in &headers {
+			data.extend(
+				ser::ser_vec(header, version).map_err(|e| {
+					ErrorKind::Internal(format!("Unable to serialize header, {}", e))
+				})?,
+			);
+		}
+
+		Ok(HeaderChainProof {
+			from_height,
+			to_height,
+			protocol_version: version.into(),
+			prev_hash,
+			start_total_difficulty,
+			total_difficulty,
+			last_hash: last_hash.to_hex(),
+			headers: util::to_hex(&data),
+		})
+	}
+
 	// Try to get hash from height, hash or output commit
 	pub fn parse_inputs(
 		&self,
@@ -131,10 +229,93 @@ impl HeaderHandler {
 	}
 }
 
+/// Block weight utilization and the rolling fullness series
+pub struct BlockFullnessHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl BlockFullnessHandler {
+	pub fn get_block_fullness(
+		&self,
+		start_height: Option<u64>,
+		end_height: Option<u64>,
+		window: Option<u64>,
+	) -> Result<Vec<BlockFullness>, Error> {
+		let window = window.unwrap_or(DEFAULT_FULLNESS_WINDOW).max(1);
+		if window > MAX_FULLNESS_BLOCKS {
+			return Err(ErrorKind::Argument(format!(
+				"Window is too large, max is {}",
+				MAX_FULLNESS_BLOCKS
+			)))?;
+		}
+		let chain = w(&self.chain)?;
+		let head = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("Unable to get chain tip, {}", e)))?;
+		let end_height = end_height.unwrap_or(head.height).min(head.height);
+		let start_height =
+			start_height.unwrap_or(end_height.saturating_sub(DEFAULT_FULLNESS_WINDOW - 1));
+		if start_height > end_height {
+			return Err(ErrorKind::Argument(format!(
+				"start_height {} is above end_height {}",
+				start_height, end_height
+			)))?;
+		}
+		if end_height - start_height >= MAX_FULLNESS_BLOCKS {
+			return Err(ErrorKind::Argument(format!(
+				"Too many blocks requested, max is {}",
+				MAX_FULLNESS_BLOCKS
+			)))?;
+		}
+
+		let max_weight = global::max_block_weight();
+		let utilization = |weight: u64| weight as f64 * 100.0 / max_weight as f64;
+		// Weights of the window before the first block are needed for the rolling value
+		let first_height = start_height.saturating_sub(window - 1);
+		let mut weights: VecDeque<Option<u64>> = VecDeque::new();
+		let mut res = vec![];
+		for height in first_height..=end_height {
+			let header = chain
+				.get_header_by_height(height)
+				.map_err(|e| ErrorKind::NotFound(format!("Header for height {}, {}", height, e)))?;
+			let weight = chain.get_block_weight(&header).ok();
+			weights.push_back(weight);
+			if weights.len() as u64 > window {
+				weights.pop_front();
+			}
+			if height < start_height {
+				continue;
+			}
+			let known: Vec<u64> = weights.iter().filter_map(|w| *w).collect();
+			let rolling_fullness = if known.is_empty() {
+				None
+			} else {
+				Some(utilization(known.iter().sum::<u64>()) / known.len() as f64)
+			};
+			res.push(BlockFullness {
+				height,
+				hash: header.hash().to_hex(),
+				weight,
+				max_weight,
+				utilization: weight.map(utilization),
+				rolling_fullness,
+			});
+		}
+		Ok(res)
+	}
+}
+
 impl Handler for HeaderHandler {
 	fn get(&self, req: Request<Body>) -> ResponseFuture {
 		let el = right_path_element!(req);
-		result_to_response(self.get_header(el.to_string()))
+		match self.get_header(el.to_string()) {
+			// Header never changes, the hash is a perfect ETag
+			Ok(header) => {
+				let etag = make_etag(&[&header.hash]);
+				etag_response(&req, &etag, || Ok(header))
+			}
+			Err(e) => result_to_response::<BlockHeaderPrintable>(Err(e)),
+		}
 	}
 }
 
@@ -148,11 +329,50 @@ impl Handler for HeaderHandler {
 ///
 /// Optionally turn off the Merkle proof extraction by passing "?no_merkle_proof" query
 /// param GET /v1/blocks/<hash>?no_merkle_proof
+///
+/// Pruned node can fetch the blocks it doesn't store any more from the peers,
+/// see the fetch_pruned_blocks p2p config parameter.
+///
+/// Responses have the ETag, the request with the matching If-None-Match gets
+/// 304 Not Modified without loading the block.
 pub struct BlockHandler {
 	pub chain: Weak<chain::Chain>,
+	pub peers: Weak<p2p::Peers>,
 }
 
 impl BlockHandler {
+	// Block from the db, or from the peers if we don't store it any more.
+	// Block and header are read from the same snapshot, so a block that is being applied
+	// is either fully visible or not visible at all.
+	fn get_stored_or_fetched_block(&self, h: &Hash) -> Result<Block, Error> {
+		let chain = w(&self.chain)?;
+		let snapshot = chain
+			.snapshot()
+			.map_err(|e| ErrorKind::Internal(format!("chain snapshot error, {}", e)))?;
+		let err = match snapshot.get_block(h) {
+			Ok(block) => return Ok(block),
+			Err(e) => e,
+		};
+		let header = match snapshot.get_block_header(h) {
+			Ok(header) => header,
+			Err(_) => {
+				return Err(ErrorKind::NotFound(format!(
+					"Block for hash {}, {}",
+					h, err
+				)))?
+			}
+		};
+		w(&self.peers)?
+			.fetch_block(*h, header.height, |b| chain.verify_fetched_block(b))
+			.map_err(|e| {
+				ErrorKind::NotFound(format!(
+					"Block for hash {}, {}, fetch from peers failed, {}",
+					h, err, e
+				))
+				.into()
+			})
+	}
+
 	pub fn get_block(
 		&self,
 		h: &Hash,
@@ -160,9 +380,7 @@ impl BlockHandler {
 		include_merkle_proof: bool,
 	) -> Result<BlockPrintable, Error> {
 		let chain = w(&self.chain)?;
-		let block = chain
-			.get_block(h)
-			.map_err(|e| ErrorKind::NotFound(format!("Block for hash {}, {}", h, e)))?;
+		let block = self.get_stored_or_fetched_block(h)?;
 		BlockPrintable::from_block(&block, &chain, include_proof, include_merkle_proof).map_err(
 			|e| {
 				ErrorKind::Internal(format!("chain error, broken block for hash {}. {}", h, e))
@@ -173,9 +391,7 @@ impl BlockHandler {
 
 	fn get_compact_block(&self, h: &Hash) -> Result<CompactBlockPrintable, Error> {
 		let chain = w(&self.chain)?;
-		let block = chain
-			.get_block(h)
-			.map_err(|e| ErrorKind::NotFound(format!("Block for hash {}, {}", h, e)))?;
+		let block = self.get_stored_or_fetched_block(h)?;
 		CompactBlockPrintable::from_compact_block(&block.into(), &chain).map_err(|e| {
 			ErrorKind::Internal(format!(
 				"chain error, broken compact block for hash {}, {}",
@@ -185,10 +401,16 @@ impl BlockHandler {
 		})
 	}
 
+	// Height is resolved on the chain snapshot, so the height of the block that is being
+	// applied never points to a block without the body.
+	fn header_by_height(chain: &chain::Chain, height: u64) -> Result<BlockHeader, chain::Error> {
+		chain.snapshot()?.get_header_by_height(height)
+	}
+
 	// Try to decode the string as a height or a hash.
 	fn parse_input(&self, input: String) -> Result<Hash, Error> {
 		if let Ok(height) = input.parse() {
-			match w(&self.chain)?.get_header_by_height(height) {
+			match Self::header_by_height(&w(&self.chain)?, height) {
 				Ok(header) => return Ok(header.hash()),
 				Err(e) => {
 					return Err(ErrorKind::NotFound(format!(
@@ -212,7 +434,7 @@ impl BlockHandler {
 		commit: Option<String>,
 	) -> Result<Hash, Error> {
 		if let Some(height) = height {
-			match w(&self.chain)?.get_header_by_height(height) {
+			match Self::header_by_height(&w(&self.chain)?, height) {
 				Ok(header) => return Ok(header.hash()),
 				Err(e) => {
 					return Err(ErrorKind::NotFound(format!(
@@ -275,9 +497,9 @@ impl Handler for BlockHandler {
 
 		let mut include_proof = false;
 		let mut include_merkle_proof = true;
+		let mut compact = false;
 		if let Some(params) = req.uri().query() {
 			let query = url::form_urlencoded::parse(params.as_bytes());
-			let mut compact = false;
 			for (param, _) in query {
 				match param.as_ref() {
 					"compact" => compact = true,
@@ -291,11 +513,30 @@ impl Handler for BlockHandler {
 					}
 				}
 			}
+		}
 
-			if compact {
-				return result_to_response(self.get_compact_block(&h));
+		// Outputs of the block get spent and their Merkle proofs change while the chain
+		// grows, so the ETag depends on the chain tip as well
+		let head = match w_fut!(&self.chain).head() {
+			Ok(head) => head,
+			Err(e) => {
+				return response(
+					StatusCode::INTERNAL_SERVER_ERROR,
+					format!("can't get tip: {}", e),
+				)
 			}
+		};
+		let variant = format!(
+			"{}{}{}",
+			compact as u8, include_proof as u8, include_merkle_proof as u8
+		);
+		let etag = make_etag(&[&h.to_hex(), &head.last_block_h.to_hex(), &variant]);
+		if compact {
+			etag_response(&req, &etag, || self.get_compact_block(&h))
+		} else {
+			etag_response(&req, &etag, || {
+				self.get_block(&h, include_proof, include_merkle_proof)
+			})
 		}
-		result_to_response(self.get_block(&h, include_proof, include_merkle_proof))
 	}
 }
@@ -14,18 +14,30 @@
 
 use super::utils::{get_output, get_output_v2, w};
 use crate::chain;
+use crate::cold_storage::check_not_cold_storage;
+use crate::confirmation_safety::{
+	classify_risk, fork_activity_summary, recommended_confirmations, ConfirmationSafety,
+};
 use crate::core::core::hash::Hashed;
+use crate::core::libtx::ownership;
+use crate::kernel_watch::{kernel_watch_hub, KernelWatch, MAX_KERNEL_WATCH_DEPTH};
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
 use crate::types::*;
 use crate::util;
 use crate::util::secp::pedersen::Commitment;
+use crate::util::secp::Signature;
+use crate::util::static_secp_instance;
+use crate::util::ToHex;
 use crate::web::*;
+use chrono::{Duration, Utc};
 use hyper::{Body, Request, StatusCode};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Weak;
 
 /// Chain handler. Get the head details.
 /// GET /v1/chain
+/// Supports the conditional requests, the ETag is the tip hash.
 pub struct ChainHandler {
 	pub chain: Weak<chain::Chain>,
 }
@@ -40,8 +52,14 @@ impl ChainHandler {
 }
 
 impl Handler for ChainHandler {
-	fn get(&self, _req: Request<Body>) -> ResponseFuture {
-		result_to_response(self.get_tip())
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		match self.get_tip() {
+			Ok(tip) => {
+				let etag = make_etag(&[&tip.last_block_pushed]);
+				etag_response(&req, &etag, || Ok(tip))
+			}
+			Err(e) => result_to_response::<Tip>(Err(e)),
+		}
 	}
 }
 
@@ -79,17 +97,45 @@ pub struct ChainCompactHandler {
 }
 
 impl ChainCompactHandler {
-	pub fn compact_chain(&self) -> Result<(), Error> {
+	pub fn compact_chain(&self) -> Result<chain::CompactionStats, Error> {
+		check_not_cold_storage("compact_chain")?;
 		w(&self.chain)?
 			.compact()
 			.map_err(|e| ErrorKind::Internal(format!("compact chain error {}", e)).into())
 	}
 }
 
+/// Anti deep reorg rule handler. Operator can allow the deep reorgs for a while.
+pub struct ReorgGuardHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl ReorgGuardHandler {
+	pub fn get_reorg_guard(&self) -> Result<ReorgGuard, Error> {
+		let chain = w(&self.chain)?;
+		Ok(ReorgGuard {
+			max_reorg_depth: chain.max_reorg_depth(),
+			deep_reorgs_allowed_until: chain.deep_reorgs_allowed_until().map(|t| t.timestamp()),
+		})
+	}
+
+	pub fn allow_deep_reorgs(&self, duration_secs: u64) -> Result<ReorgGuard, Error> {
+		let until = match duration_secs {
+			0 => None,
+			secs => Some(Utc::now() + Duration::seconds(secs as i64)),
+		};
+		w(&self.chain)?.allow_deep_reorgs(until);
+		self.get_reorg_guard()
+	}
+}
+
 impl Handler for ChainCompactHandler {
 	fn post(&self, _req: Request<Body>) -> ResponseFuture {
+		if let Err(e) = check_not_cold_storage("compact_chain") {
+			return result_to_response::<()>(Err(e));
+		}
 		match w_fut!(&self.chain).compact() {
-			Ok(_) => response(StatusCode::OK, "{}"),
+			Ok(stats) => json_response(&stats),
 			Err(e) => response(
 				StatusCode::INTERNAL_SERVER_ERROR,
 				format!("chain compact failed: {}", e),
@@ -516,6 +562,88 @@ impl KernelHandler {
 			)))
 		})
 	}
+
+	/// Reorg safety of the kernel in the chain: the confirmations, the work on top of
+	/// its block and the risk with the recent fork activity
+	pub fn get_confirmation_safety(&self, excess_s: String) -> Result<ConfirmationSafety, Error> {
+		let kernel = self.get_kernel_v2(excess_s.clone(), None, None)?;
+		let chain = w(&self.chain)?;
+		let head = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("Unable to get the chain head, {}", e)))?;
+		let header = chain.get_header_by_height(kernel.height).map_err(|e| {
+			ErrorKind::Internal(format!(
+				"Unable to get the header at {}, {}",
+				kernel.height, e
+			))
+		})?;
+		let activity = fork_activity_summary(Utc::now().timestamp());
+		let confirmations = head.height.saturating_sub(kernel.height) + 1;
+		Ok(ConfirmationSafety {
+			excess: excess_s,
+			height: kernel.height,
+			block_hash: header.hash().to_hex(),
+			confirmations,
+			work_on_top: head
+				.total_difficulty
+				.to_num()
+				.saturating_sub(header.total_difficulty().to_num()),
+			recent_reorgs: activity.reorgs,
+			max_recent_reorg_depth: activity.max_reorg_depth,
+			recent_fork_blocks: activity.fork_blocks,
+			recommended_confirmations: recommended_confirmations(activity.max_reorg_depth),
+			risk: classify_risk(confirmations, activity.max_reorg_depth),
+		})
+	}
+
+	/// Register the kernel watch. Kernels that are already in the recent blocks are
+	/// registered with their blocks.
+	pub fn watch_kernels(
+		&self,
+		excess_list: Vec<String>,
+		depth: Option<u64>,
+	) -> Result<KernelWatch, Error> {
+		let mut kernels = vec![];
+		for excess_s in &excess_list {
+			let excess = util::from_hex(excess_s).map_err(|e| {
+				ErrorKind::RequestError(format!("invalid excess hex {}, {}", excess_s, e))
+			})?;
+			if excess.len() != 33 {
+				return Err(ErrorKind::RequestError(format!(
+					"invalid excess {}, get length {}, expected 33",
+					excess_s,
+					excess.len()
+				))
+				.into());
+			}
+			kernels.push(Commitment::from_vec(excess));
+		}
+
+		let chain = w(&self.chain)?;
+		let head = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("Unable to get chain head, {}", e)))?;
+		let min_height = head.height.saturating_sub(MAX_KERNEL_WATCH_DEPTH);
+		let mut found = HashMap::new();
+		for excess in &kernels {
+			let located = chain
+				.get_kernel_height(excess, Some(min_height), None)
+				.map_err(|e| {
+					ErrorKind::Internal(format!(
+						"Unable to get a height for excess {}, {}",
+						excess.to_hex(),
+						e
+					))
+				})?;
+			if let Some((_, height, _)) = located {
+				let header = chain.get_header_by_height(height).map_err(|e| {
+					ErrorKind::Internal(format!("Unable to get header at {}, {}", height, e))
+				})?;
+				found.insert(excess.clone(), (height, header.hash()));
+			}
+		}
+		kernel_watch_hub().watch(kernels, depth, found)
+	}
 }
 
 impl Handler for KernelHandler {
@@ -523,3 +651,206 @@ impl Handler for KernelHandler {
 		result_to_response(self.get_kernel(req))
 	}
 }
+
+/// Output ownership proof verification, used for the proof of reserves
+pub struct OwnershipProofHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl OwnershipProofHandler {
+	pub fn verify_ownership_proof(
+		&self,
+		proof: OwnershipProof,
+	) -> Result<OwnershipProofVerification, Error> {
+		let commit_v = util::from_hex(&proof.commit).map_err(|e| {
+			ErrorKind::RequestError(format!("invalid commit hex {}, {}", proof.commit, e))
+		})?;
+		if commit_v.len() != 33 {
+			return Err(ErrorKind::RequestError(format!(
+				"invalid commit {}, get length {}, expected 33",
+				proof.commit,
+				commit_v.len()
+			))
+			.into());
+		}
+		let commit = Commitment::from_vec(commit_v);
+
+		let sig_v = util::from_hex(&proof.signature).map_err(|e| {
+			ErrorKind::RequestError(format!("invalid signature hex {}, {}", proof.signature, e))
+		})?;
+		if sig_v.len() != 64 {
+			return Err(ErrorKind::RequestError(format!(
+				"invalid signature {}, get length {}, expected 64",
+				proof.signature,
+				sig_v.len()
+			))
+			.into());
+		}
+		let mut sig_data = [0u8; 64];
+		sig_data.copy_from_slice(&sig_v);
+		let sig = Signature::from_compact(&sig_data).map_err(|e| {
+			ErrorKind::RequestError(format!("invalid signature {}, {}", proof.signature, e))
+		})?;
+
+		let chain = w(&self.chain)?;
+		let height = chain
+			.get_unspent(commit)
+			.map_err(|e| {
+				ErrorKind::Internal(format!(
+					"Unable to check the output {}, {}",
+					proof.commit, e
+				))
+			})?
+			.map(|(_, pos)| pos.height);
+
+		let valid = match height {
+			Some(_) => {
+				let secp = static_secp_instance();
+				let secp = secp.lock();
+				ownership::verify_ownership_proof(
+					&secp,
+					&commit,
+					proof.amount,
+					&proof.challenge,
+					&sig,
+				)
+				.is_ok()
+			}
+			None => false,
+		};
+
+		Ok(OwnershipProofVerification {
+			commit: proof.commit,
+			amount: proof.amount,
+			unspent: height.is_some(),
+			height,
+			valid,
+		})
+	}
+}
+
+/// Max number of blocks that can be requested from the kernel fee index at once
+const MAX_FEE_QUERY_BLOCKS: u64 = 10_080;
+
+/// Kernel fee handler, queries the kernel fee index. The index is built by the node
+/// in background if `kernel_fee_index` is enabled in the config.
+pub struct KernelFeeHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl KernelFeeHandler {
+	pub fn get_index_status(&self) -> Result<KernelFeeIndexStatus, Error> {
+		let chain = w(&self.chain)?;
+		let index_head = chain
+			.store()
+			.kernel_fee_index_head()
+			.map_err(|e| ErrorKind::NotFound(format!("Kernel fee index is not built, {}", e)))?;
+		let chain_height = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("can't get tip: {}", e)))?
+			.height;
+		let progress = if chain_height > index_head.start_height {
+			(index_head.height.saturating_sub(index_head.start_height) * 100
+				/ (chain_height - index_head.start_height)) as u8
+		} else {
+			100
+		};
+		Ok(KernelFeeIndexStatus {
+			start_height: index_head.start_height,
+			height: index_head.height,
+			chain_height,
+			progress: std::cmp::min(progress, 100),
+		})
+	}
+
+	// Read the index entries for the height range. Entries of the orphaned blocks are skipped.
+	fn get_block_fees(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<Vec<chain::BlockKernelFees>, Error> {
+		let status = self.get_index_status()?;
+		let end_height = end_height.unwrap_or(status.height);
+		if start_height > end_height {
+			return Err(ErrorKind::Argument(format!(
+				"Invalid height range {} - {}",
+				start_height, end_height
+			))
+			.into());
+		}
+		if end_height - start_height >= MAX_FEE_QUERY_BLOCKS {
+			return Err(ErrorKind::Argument(format!(
+				"Height range is too large, max {} blocks are allowed",
+				MAX_FEE_QUERY_BLOCKS
+			))
+			.into());
+		}
+		if start_height < status.start_height || end_height > status.height {
+			return Err(ErrorKind::NotFound(format!(
+				"Height range {} - {} is not indexed, index covers {} - {}",
+				start_height, end_height, status.start_height, status.height
+			))
+			.into());
+		}
+
+		let chain = w(&self.chain)?;
+		let store = chain.store();
+		let mut res = vec![];
+		for height in start_height..=end_height {
+			let fees = store.get_block_kernel_fees(height).map_err(|e| {
+				ErrorKind::Internal(format!("Unable to read kernel fees at {}, {}", height, e))
+			})?;
+			let header = chain.get_header_by_height(height).map_err(|e| {
+				ErrorKind::Internal(format!("Unable to get header at {}, {}", height, e))
+			})?;
+			if header.hash() == fees.hash {
+				res.push(fees);
+			}
+		}
+		Ok(res)
+	}
+
+	pub fn get_kernels_by_fee(
+		&self,
+		min_fee: u64,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<Vec<KernelFeePrintable>, Error> {
+		let mut res = vec![];
+		for block_fees in self.get_block_fees(start_height, end_height)? {
+			for k in block_fees.kernels.iter().filter(|k| k.fee >= min_fee) {
+				res.push(KernelFeePrintable {
+					excess: k.excess.to_hex(),
+					fee: k.fee,
+					height: block_fees.height,
+				});
+			}
+		}
+		Ok(res)
+	}
+
+	pub fn get_fee_histogram(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+		bucket_size: u64,
+	) -> Result<Vec<FeeHistogramBucket>, Error> {
+		if bucket_size == 0 {
+			return Err(ErrorKind::Argument("bucket_size can't be 0".to_string()).into());
+		}
+		let mut buckets: BTreeMap<u64, u64> = BTreeMap::new();
+		for block_fees in self.get_block_fees(start_height, end_height)? {
+			for k in &block_fees.kernels {
+				*buckets.entry(k.fee / bucket_size).or_insert(0) += 1;
+			}
+		}
+		Ok(buckets
+			.into_iter()
+			.map(|(bucket, count)| FeeHistogramBucket {
+				min_fee: bucket * bucket_size,
+				max_fee: bucket * bucket_size + bucket_size - 1,
+				count,
+			})
+			.collect())
+	}
+}
@@ -14,7 +14,11 @@
 
 use super::utils::w;
 use crate::p2p::types::{PeerAddr, PeerInfoDisplay, ReasonForBan};
-use crate::p2p::{self, PeerData};
+use crate::p2p::version_census;
+use crate::p2p::{
+	self, Libp2pPeerAccess, Libp2pPeerBan, PeerData, PeerSnapshot, PeerSnapshotImport,
+	PeerSyncData, QuarantinedPeer, VersionCensusReport,
+};
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
 use crate::web::*;
@@ -122,6 +126,7 @@ impl Handler for PeersConnectedHandler {
 
 /// Peer operations
 /// GET /v1/peers/10.12.12.13
+/// GET /v1/peers/census?upgrade_target=5.0.0
 /// POST /v1/peers/10.12.12.13/ban
 /// POST /v1/peers/10.12.12.13/unban
 pub struct PeerHandler {
@@ -167,12 +172,139 @@ impl PeerHandler {
 			.into()
 		})
 	}
+
+	pub fn sync_from_peer(&self, addr: String) -> Result<(), Error> {
+		let peer_addr = PeerAddr::from_str(&addr);
+		w(&self.peers)?
+			.set_sync_peer(peer_addr.clone())
+			.map_err(|e| {
+				ErrorKind::Argument(format!(
+					"Unable to sync from peer with address {}, {}",
+					peer_addr, e
+				))
+				.into()
+			})
+	}
+
+	pub fn get_libp2p_peer_access(&self) -> Result<Vec<Libp2pPeerAccess>, Error> {
+		w(&self.peers)?.libp2p_peer_access().map_err(|e| {
+			ErrorKind::Internal(format!("Unable to read libp2p peer access rules, {}", e)).into()
+		})
+	}
+
+	pub fn set_libp2p_peer_access(&self, address: String, allowed: bool) -> Result<(), Error> {
+		w(&self.peers)?
+			.set_libp2p_peer_access(&address, allowed)
+			.map_err(|e| {
+				ErrorKind::Argument(format!(
+					"Unable to set libp2p access rule for address {}, {}",
+					address, e
+				))
+				.into()
+			})
+	}
+
+	pub fn remove_libp2p_peer_access(&self, address: String) -> Result<(), Error> {
+		w(&self.peers)?
+			.remove_libp2p_peer_access(&address)
+			.map_err(|e| {
+				ErrorKind::Internal(format!(
+					"Unable to remove libp2p access rule for address {}, {}",
+					address, e
+				))
+				.into()
+			})
+	}
+
+	pub fn get_libp2p_bans(&self) -> Result<Vec<Libp2pPeerBan>, Error> {
+		w(&self.peers)?.libp2p_bans().map_err(|e| {
+			ErrorKind::Internal(format!("Unable to read libp2p peer bans, {}", e)).into()
+		})
+	}
+
+	pub fn ban_libp2p_peer(
+		&self,
+		address: String,
+		duration_secs: Option<i64>,
+	) -> Result<Libp2pPeerBan, Error> {
+		w(&self.peers)?
+			.ban_libp2p_peer(&address, ReasonForBan::ManualBan, duration_secs)
+			.map_err(|e| {
+				ErrorKind::Argument(format!("Unable to ban libp2p peer {}, {}", address, e)).into()
+			})
+	}
+
+	pub fn unban_libp2p_peer(&self, address: String) -> Result<(), Error> {
+		w(&self.peers)?.unban_libp2p_peer(&address).map_err(|e| {
+			ErrorKind::Argument(format!("Unable to unban libp2p peer {}, {}", address, e)).into()
+		})
+	}
+
+	pub fn get_quarantined_peers(&self) -> Result<Vec<QuarantinedPeer>, Error> {
+		Ok(p2p::peer_quarantine::quarantined_peers())
+	}
+
+	pub fn release_quarantined_peer(&self, address: String) -> Result<(), Error> {
+		if p2p::peer_quarantine::release_peer(&address) {
+			Ok(())
+		} else {
+			Err(ErrorKind::Argument(format!("Peer {} is not quarantined", address)).into())
+		}
+	}
+
+	pub fn get_sync_data(&self) -> Result<Vec<PeerSyncData>, Error> {
+		Ok(w(&self.peers)?.sync_data())
+	}
+
+	pub fn export_peer_snapshot(&self) -> Result<PeerSnapshot, Error> {
+		w(&self.peers)?.export_snapshot().map_err(|e| {
+			ErrorKind::Internal(format!("Unable to export peer snapshot, {}", e)).into()
+		})
+	}
+
+	pub fn import_peer_snapshot(
+		&self,
+		snapshot: PeerSnapshot,
+		trusted_key: Option<String>,
+	) -> Result<PeerSnapshotImport, Error> {
+		w(&self.peers)?
+			.import_snapshot(&snapshot, trusted_key.as_ref().map(|k| k.as_str()))
+			.map_err(|e| {
+				ErrorKind::Argument(format!("Unable to import peer snapshot, {}", e)).into()
+			})
+	}
+
+	pub fn get_version_census(
+		&self,
+		upgrade_target: Option<String>,
+	) -> Result<VersionCensusReport, Error> {
+		if let Some(target) = &upgrade_target {
+			if version_census::parse_node_version(target).is_none() {
+				return Err(ErrorKind::Argument(format!(
+					"Invalid upgrade target version {}, expected x.y.z",
+					target
+				))
+				.into());
+			}
+		}
+		Ok(version_census::version_census(
+			upgrade_target.as_ref().map(|t| t.as_str()),
+		))
+	}
 }
 
 impl Handler for PeerHandler {
 	fn get(&self, req: Request<Body>) -> ResponseFuture {
 		let command = right_path_element!(req);
 
+		if command == "census" {
+			let upgrade_target = req
+				.uri()
+				.query()
+				.and_then(|q| QueryParams::from(q).get("upgrade_target").cloned());
+			return result_to_response(self.get_version_census(upgrade_target));
+		}
+
 		// We support both "ip" and "ip:port" here for peer_addr.
 		// "ip:port" is only really useful for local usernet testing on loopback address.
 		// Normally we map peers to ip and only allow a single peer per ip address.
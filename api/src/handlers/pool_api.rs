@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use super::utils::w;
+use crate::cold_storage::check_not_cold_storage;
 use crate::core::core::hash::Hashed;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::Transaction;
@@ -20,9 +21,11 @@ use crate::core::ser::{self, ProtocolVersion};
 use crate::pool::{self, BlockChain, PoolAdapter, PoolEntry};
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
+use crate::standby::check_not_standby;
 use crate::types::*;
 use crate::util;
-use crate::util::RwLock;
+use crate::util::secp::pedersen::Commitment;
+use crate::util::{RwLock, ToHex};
 use crate::web::*;
 use hyper::{Body, Request, StatusCode};
 use std::sync::Weak;
@@ -86,6 +89,8 @@ where
 		Ok(txpool.txpool.entries.clone())
 	}
 	pub fn push_transaction(&self, tx: Transaction, fluff: Option<bool>) -> Result<(), Error> {
+		check_not_standby("push_transaction")?;
+		check_not_cold_storage("push_transaction")?;
 		let pool_arc = w(&self.tx_pool)?;
 		let source = pool::TxSource::PushApi;
 		info!(
@@ -112,6 +117,167 @@ where
 
 		Ok(())
 	}
+
+	/// Push the transaction and report its state in the pool. The state is read under
+	/// the same pool lock, so the following pool queries see the transaction.
+	pub fn push_transaction_sync(
+		&self,
+		tx: Transaction,
+		fluff: Option<bool>,
+	) -> Result<PushedTransaction, Error> {
+		check_not_standby("push_transaction")?;
+		check_not_cold_storage("push_transaction")?;
+		let pool_arc = w(&self.tx_pool)?;
+		let tx_hash = tx.hash();
+		let kernels: Vec<Commitment> = tx.kernels().iter().map(|k| k.excess).collect();
+		let excess = kernels
+			.first()
+			.cloned()
+			.ok_or_else(|| ErrorKind::RequestError("transaction has no kernels".to_string()))?;
+		info!(
+			"Pushing transaction {} to pool synchronously (inputs: {}, outputs: {}, kernels: {}, fluff: {:?})",
+			tx_hash,
+			tx.inputs().len(),
+			tx.outputs().len(),
+			tx.kernels().len(),
+			fluff,
+		);
+
+		let mut tx_pool = pool_arc.write();
+		let header = tx_pool
+			.blockchain
+			.chain_head()
+			.map_err(|e| ErrorKind::Internal(format!("Failed to get chain head, {}", e)))?;
+		tx_pool
+			.add_to_pool(
+				pool::TxSource::PushApi,
+				tx,
+				!fluff.unwrap_or(false),
+				&header,
+			)
+			.map_err(|e| ErrorKind::Internal(format!("Failed to update pool, {}", e)))?;
+		let state = tx_state(&tx_pool, &excess);
+
+		info!(
+			"transaction {} was added to the pool, state {:?}",
+			tx_hash, state
+		);
+
+		Ok(PushedTransaction {
+			tx_hash: tx_hash.to_hex(),
+			kernels: kernels.iter().map(|k| k.to_hex()).collect(),
+			state,
+		})
+	}
+
+	/// State of the transaction with the kernel excess in the pool
+	pub fn get_transaction_state(&self, excess_s: String) -> Result<PoolTxState, Error> {
+		let excess = util::from_hex(&excess_s).map_err(|e| {
+			ErrorKind::RequestError(format!("invalid excess hex {}, {}", excess_s, e))
+		})?;
+		if excess.len() != 33 {
+			return Err(ErrorKind::RequestError(format!(
+				"invalid excess {}, get length {}, expected 33",
+				excess_s,
+				excess.len()
+			))
+			.into());
+		}
+		let excess = Commitment::from_vec(excess);
+
+		let pool_arc = w(&self.tx_pool)?;
+		let tx_pool = pool_arc.read();
+		Ok(tx_state(&tx_pool, &excess))
+	}
+}
+
+/// Dandelion state of the node for the owner API. The owner API is not generic over the
+/// pool types, so it works with the pool through this trait.
+pub trait DandelionControl: Send + Sync {
+	/// Current epoch and the stempool transactions. The relay peer address is hidden
+	/// if `redact_relay` is true.
+	fn get_dandelion_status(&self, redact_relay: bool) -> Result<DandelionStatus, Error>;
+
+	/// Fluff all the stempool transactions right away. Returns the number of the
+	/// fluffed transactions.
+	fn fluff_all(&self) -> Result<usize, Error>;
+}
+
+pub struct DandelionHandler<B, P, V>
+where
+	B: BlockChain,
+	P: PoolAdapter,
+	V: VerifierCache + 'static,
+{
+	pub tx_pool: Weak<RwLock<pool::TransactionPool<B, P, V>>>,
+}
+
+impl<B, P, V> DandelionControl for DandelionHandler<B, P, V>
+where
+	B: BlockChain,
+	P: PoolAdapter,
+	V: VerifierCache + 'static,
+{
+	fn get_dandelion_status(&self, redact_relay: bool) -> Result<DandelionStatus, Error> {
+		let pool_arc = w(&self.tx_pool)?;
+		let tx_pool = pool_arc.read();
+		let mut epoch = tx_pool.adapter.dandelion_epoch();
+		if redact_relay {
+			if let Some(epoch) = &mut epoch {
+				epoch.relay_peer = epoch.relay_peer.as_ref().map(|_| "redacted".to_string());
+			}
+		}
+		let embargo_secs = epoch.as_ref().map(|e| e.embargo_secs as i64).unwrap_or(0);
+		let mut stem_txs: Vec<StemTransaction> = tx_pool
+			.stempool
+			.entries
+			.iter()
+			.map(|e| StemTransaction {
+				tx_hash: e.tx.hash().to_hex(),
+				kernels: e.tx.kernels().iter().map(|k| k.excess.to_hex()).collect(),
+				src: e.src,
+				received: e.tx_at.timestamp(),
+				embargo_expiry: e.tx_at.timestamp() + embargo_secs,
+			})
+			.collect();
+		stem_txs.sort_by_key(|t| t.received);
+		Ok(DandelionStatus { epoch, stem_txs })
+	}
+
+	fn fluff_all(&self) -> Result<usize, Error> {
+		let pool_arc = w(&self.tx_pool)?;
+		let fluffed = pool_arc
+			.write()
+			.fluff_stempool()
+			.map_err(|e| ErrorKind::Internal(format!("Failed to fluff stempool, {}", e)))?;
+		info!(
+			"{} stem transactions are fluffed by the owner request",
+			fluffed
+		);
+		Ok(fluffed)
+	}
+}
+
+// Stempool is private, only the transactions pushed with the api are reported from there.
+fn tx_state<B, P, V>(tx_pool: &pool::TransactionPool<B, P, V>, excess: &Commitment) -> PoolTxState
+where
+	B: BlockChain,
+	P: PoolAdapter,
+	V: VerifierCache + 'static,
+{
+	let has_kernel = |e: &PoolEntry| e.tx.kernels().iter().any(|k| k.excess == *excess);
+	if tx_pool.txpool.entries.iter().any(|e| has_kernel(e)) {
+		PoolTxState::Txpool
+	} else if tx_pool
+		.stempool
+		.entries
+		.iter()
+		.any(|e| e.src.is_pushed() && has_kernel(e))
+	{
+		PoolTxState::Stempool
+	} else {
+		PoolTxState::NotFound
+	}
 }
 /// Dummy wrapper for the hex-encoded serialized transaction.
 #[derive(Serialize, Deserialize)]
@@ -139,6 +305,8 @@ where
 	P: PoolAdapter,
 	V: VerifierCache + 'static,
 {
+	check_not_standby("push transaction")?;
+	check_not_cold_storage("push transaction")?;
 	let pool = w(&pool)?;
 	let params = QueryParams::from(req.uri().query());
 	let fluff = params.get("fluff").is_some();
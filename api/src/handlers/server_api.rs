@@ -13,17 +13,25 @@
 // limitations under the License.
 
 use super::utils::w;
-use crate::chain::{Chain, SyncState, SyncStatus};
+use crate::chain::{self, Chain, ErrorClass, MetricsSnapshot, SyncState, SyncStatus, UptimeRecord};
 use crate::p2p;
+use crate::p2p::integrity_budget;
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
+use crate::stratum_audit::{stratum_audit, StratumAuditQuery};
 use crate::types::*;
+use crate::util::secp::constants::PEDERSEN_COMMITMENT_SIZE;
+use crate::util::secp::pedersen::Commitment;
+use crate::util::{self, ToHex};
 use crate::web::*;
+use chrono::Utc;
 use grin_core::global;
 use hyper::{Body, Request, StatusCode};
 use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::atomic::Ordering;
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 
 // RESTful index of available api endpoints
 // GET /v1/
@@ -41,6 +49,7 @@ impl Handler for IndexHandler {
 
 /// Status handler. Post a summary of the server status
 /// GET /v1/status
+/// Supports the conditional requests with the ETag and If-None-Match.
 pub struct StatusHandler {
 	pub chain: Weak<Chain>,
 	pub peers: Weak<p2p::Peers>,
@@ -50,17 +59,289 @@ pub struct StatusHandler {
 
 impl StatusHandler {
 	pub fn get_status(&self) -> Result<Status, Error> {
-		let head = w(&self.chain)?
+		let chain = w(&self.chain)?;
+		let head = chain
 			.head()
 			.map_err(|e| ErrorKind::Internal(format!("Unable to get chain tip, {}", e)))?;
-		let sync_status = w(&self.sync_state)?.status();
-		let (api_sync_status, api_sync_info) = sync_status_to_api(sync_status);
-		Ok(Status::from_tip_and_peers(
+		let sync_state = w(&self.sync_state)?;
+		let (api_sync_status, api_sync_info) = sync_status_to_api(sync_state.status());
+		let mut status = Status::from_tip_and_peers(
 			head,
 			w(&self.peers)?.peer_count(),
 			api_sync_status,
 			api_sync_info,
+		);
+		status.chain_error = sync_state.last_chain_error();
+		status.sync_source = sync_state.sync_source_decision();
+		status.utxo_digest = chain.utxo_set_digest();
+		status.rangeproof_validation = chain.rangeproof_validation().map_err(|e| {
+			ErrorKind::Internal(format!("Unable to get rangeproofs validation, {}", e))
+		})?;
+		Ok(status)
+	}
+}
+
+// ETag of the node status. It changes with the tip, the connections and the sync state.
+// Optional parts are present mostly during the sync or on errors, they are small.
+fn status_etag(status: &Status) -> String {
+	let mut hasher = DefaultHasher::new();
+	status.tip.last_block_pushed.hash(&mut hasher);
+	// Digest follows the tip, but it may lag a block behind
+	if let Some(utxo_digest) = &status.utxo_digest {
+		utxo_digest.digest.hash(&mut hasher);
+	}
+	status.connections.hash(&mut hasher);
+	status.sync_status.hash(&mut hasher);
+	if let Some(sync_info) = &status.sync_info {
+		sync_info.to_string().hash(&mut hasher);
+	}
+	if status.tor_pow_defense.is_some()
+		|| status.chain_error.is_some()
+		|| status.sync_source.is_some()
+		|| status.rangeproof_validation.is_some()
+	{
+		serde_json::to_string(&(
+			&status.tor_pow_defense,
+			&status.chain_error,
+			&status.sync_source,
+			&status.rangeproof_validation,
 		))
+		.unwrap_or_default()
+		.hash(&mut hasher);
+	}
+	make_etag(&[
+		&status.tip.height.to_string(),
+		&format!("{:016x}", hasher.finish()),
+	])
+}
+
+/// Node uptime history handler. Node runs with the sync stages durations.
+pub struct UptimeHistoryHandler {
+	pub chain: Weak<Chain>,
+}
+
+impl UptimeHistoryHandler {
+	pub fn get_uptime_history(&self) -> Result<Vec<UptimeRecord>, Error> {
+		w(&self.chain)?.store().uptime_history().map_err(|e| {
+			ErrorKind::Internal(format!("Unable to read uptime history, {}", e)).into()
+		})
+	}
+}
+
+/// Persisted metrics snapshots handler
+pub struct MetricsHistoryHandler {
+	pub chain: Weak<Chain>,
+}
+
+impl MetricsHistoryHandler {
+	pub fn get_metrics_history(
+		&self,
+		from: Option<i64>,
+		to: Option<i64>,
+	) -> Result<Vec<MetricsSnapshot>, Error> {
+		if let (Some(from), Some(to)) = (from, to) {
+			if from > to {
+				return Err(ErrorKind::Argument(format!(
+					"Invalid time range, from {} is after to {}",
+					from, to
+				))
+				.into());
+			}
+		}
+		w(&self.chain)?
+			.store()
+			.metrics_history(from, to)
+			.map_err(|e| {
+				ErrorKind::Internal(format!("Unable to read metrics history, {}", e)).into()
+			})
+	}
+}
+
+/// Integrity kernels budget handler. The kernels are registered by the operator,
+/// their validity is checked against the chain.
+pub struct IntegrityBudgetHandler {
+	pub chain: Weak<Chain>,
+}
+
+impl IntegrityBudgetHandler {
+	fn parse_excess(excess: &str) -> Result<Commitment, Error> {
+		let bytes = util::from_hex(excess)
+			.map_err(|e| ErrorKind::Argument(format!("Invalid kernel excess {}, {}", excess, e)))?;
+		if bytes.len() != PEDERSEN_COMMITMENT_SIZE {
+			return Err(ErrorKind::Argument(format!("Invalid kernel excess {}", excess)).into());
+		}
+		let commit = Commitment::from_vec(bytes);
+		commit
+			.to_pubkey()
+			.map_err(|e| ErrorKind::Argument(format!("Invalid kernel excess {}, {}", excess, e)))?;
+		Ok(commit)
+	}
+
+	pub fn register_integrity_kernel(&self, excess: String) -> Result<(), Error> {
+		let commit = Self::parse_excess(&excess)?;
+		if !integrity_budget::register_integrity_kernel(commit) {
+			return Err(ErrorKind::Argument(format!(
+				"Integrity kernel {} is already registered",
+				excess
+			))
+			.into());
+		}
+		Ok(())
+	}
+
+	pub fn remove_integrity_kernel(&self, excess: String) -> Result<(), Error> {
+		let commit = Self::parse_excess(&excess)?;
+		if !integrity_budget::remove_integrity_kernel(&commit) {
+			return Err(ErrorKind::NotFound(format!(
+				"Integrity kernel {} is not registered",
+				excess
+			))
+			.into());
+		}
+		Ok(())
+	}
+
+	pub fn get_integrity_budget(&self) -> Result<IntegrityBudget, Error> {
+		let chain = w(&self.chain)?;
+		let tip_height = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("Unable to get the chain head, {}", e)))?
+			.height;
+		let min_height = tip_height.saturating_sub(integrity_budget::INTEGRITY_FEE_VALID_BLOCKS);
+		let now = Utc::now().timestamp();
+
+		let mut kernels = vec![];
+		for usage in integrity_budget::integrity_kernels() {
+			let found = chain
+				.get_kernel_height(&usage.excess, Some(min_height), None)
+				.map_err(|e| ErrorKind::Internal(format!("Unable to find the kernel, {}", e)))?;
+			let rate_budget = integrity_budget::publish_rate_budget(&usage.recent_publishes, now);
+			let mut alerts = vec![];
+			let (height, fee, blocks_left) = match found {
+				Some((kernel, height, _)) => {
+					let blocks_left = (height + integrity_budget::INTEGRITY_FEE_VALID_BLOCKS)
+						.saturating_sub(tip_height);
+					if blocks_left < integrity_budget::INTEGRITY_EXPIRY_ALERT_BLOCKS {
+						alerts.push(IntegrityBudgetAlert::ExpiresSoon);
+					}
+					(Some(height), kernel.features.get_fee(), blocks_left)
+				}
+				None => {
+					alerts.push(IntegrityBudgetAlert::NotInValidityWindow);
+					(None, 0, 0)
+				}
+			};
+			if rate_budget == 0 {
+				alerts.push(IntegrityBudgetAlert::RateLimited);
+			}
+			kernels.push(IntegrityKernelBudget {
+				excess: usage.excess.to_hex(),
+				height,
+				fee,
+				blocks_left,
+				messages_published: usage.messages_published,
+				rate_budget,
+				alerts,
+			});
+		}
+
+		let top_up_needed = kernels
+			.iter()
+			.all(|k| k.blocks_left < integrity_budget::INTEGRITY_EXPIRY_ALERT_BLOCKS);
+		if top_up_needed {
+			warn!("Integrity kernels budget is nearly exhausted, the wallet should top up");
+		}
+		Ok(IntegrityBudget {
+			tip_height,
+			kernels,
+			top_up_needed,
+		})
+	}
+}
+
+/// Gossip messages audit log handler.
+pub struct GossipAuditHandler {}
+
+impl GossipAuditHandler {
+	pub fn get_gossip_audit(&self, query: p2p::GossipAuditQuery) -> Result<GossipAudit, Error> {
+		let audit = match p2p::gossip_audit::gossip_audit() {
+			Some(audit) => audit,
+			None => {
+				return Ok(GossipAudit {
+					enabled: false,
+					entries: vec![],
+				})
+			}
+		};
+		let entries = audit
+			.query(&query)
+			.map_err(|e| ErrorKind::Internal(format!("Unable to read gossip audit log, {}", e)))?;
+		Ok(GossipAudit {
+			enabled: true,
+			entries,
+		})
+	}
+}
+
+/// Stratum jobs and shares log handler.
+pub struct StratumAuditHandler {}
+
+impl StratumAuditHandler {
+	pub fn get_stratum_audit(&self, query: StratumAuditQuery) -> Result<StratumAudit, Error> {
+		let audit = match stratum_audit() {
+			Some(audit) => audit,
+			None => {
+				return Ok(StratumAudit {
+					enabled: false,
+					jobs: vec![],
+					shares: vec![],
+				})
+			}
+		};
+		let (jobs, shares) = audit
+			.query(&query)
+			.map_err(|e| ErrorKind::Internal(format!("Unable to read stratum log, {}", e)))?;
+		Ok(StratumAudit {
+			enabled: true,
+			jobs,
+			shares,
+		})
+	}
+}
+
+/// Gossip mesh latency probes handler.
+pub struct GossipProbeHandler {}
+
+impl GossipProbeHandler {
+	pub fn get_gossip_probe_stats(&self) -> Result<p2p::GossipProbeStats, Error> {
+		Ok(p2p::gossip_probe::gossip_probe_stats())
+	}
+}
+
+/// Block acceptance profiling handler. Timing breakdown for the last processed blocks.
+pub struct BlockProfileHandler {}
+
+impl BlockProfileHandler {
+	pub fn get_block_timings(
+		&self,
+		count: Option<u32>,
+	) -> Result<Vec<BlockTimingsPrintable>, Error> {
+		let count = count
+			.map(|c| c as usize)
+			.unwrap_or(chain::block_profile::BLOCK_PROFILE_HISTORY);
+		Ok(chain::block_profile::last_blocks(count)
+			.iter()
+			.map(BlockTimingsPrintable::from_timings)
+			.collect())
+	}
+}
+
+/// Threads, tasks and memory estimates of the node subsystems.
+pub struct SubsystemUsageHandler {}
+
+impl SubsystemUsageHandler {
+	pub fn get_subsystem_usage(&self) -> Result<Vec<util::subsystem::SubsystemUsage>, Error> {
+		Ok(util::subsystem::subsystem_usage())
 	}
 }
 
@@ -79,8 +360,14 @@ impl StatusOutput {
 }
 
 impl Handler for StatusHandler {
-	fn get(&self, _req: Request<Body>) -> ResponseFuture {
-		result_to_response(self.get_status())
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		match self.get_status() {
+			Ok(status) => {
+				let etag = status_etag(&status);
+				etag_response(&req, &etag, || Ok(status))
+			}
+			Err(e) => result_to_response::<Status>(Err(e)),
+		}
 	}
 
 	fn post(&self, req: Request<Body>) -> ResponseFuture {
@@ -110,6 +397,188 @@ impl Handler for StatusHandler {
 	}
 }
 
+// Recent corruption makes the node not ready, the heads repair may still be running
+const READY_CORRUPTION_WINDOW_SECS: i64 = 300;
+
+fn health_response(status: HealthStatus) -> ResponseFuture {
+	let code = if status.ok {
+		StatusCode::OK
+	} else {
+		StatusCode::SERVICE_UNAVAILABLE
+	};
+	match serde_json::to_string(&status) {
+		Ok(json) => response(code, json),
+		Err(e) => response(
+			StatusCode::INTERNAL_SERVER_ERROR,
+			format!("Unable to build respond json, {}", e),
+		),
+	}
+}
+
+// Node is running and the chain db and the header MMR can be read
+fn check_chain_stores(chain: &Weak<Chain>, status: &mut HealthStatus) -> Option<Arc<Chain>> {
+	let chain = match w(chain) {
+		Ok(chain) => chain,
+		Err(_) => {
+			status.failures.push("Chain is stopped".to_string());
+			return None;
+		}
+	};
+	match chain.head_header() {
+		Ok(header) => {
+			status.height = Some(header.height);
+			if let Err(e) = chain.get_header_by_height(header.height) {
+				status
+					.failures
+					.push(format!("Unable to read the header MMR, {}", e));
+			}
+		}
+		Err(e) => status
+			.failures
+			.push(format!("Unable to read the chain head, {}", e)),
+	}
+	Some(chain)
+}
+
+/// Liveness check for the load balancers and orchestration, no auth required
+/// GET /healthz
+/// 200 if the node is running and its stores can be read, 503 otherwise
+pub struct HealthHandler {
+	pub chain: Weak<Chain>,
+	pub peers: Weak<p2p::Peers>,
+}
+
+impl HealthHandler {
+	pub fn check(&self) -> HealthStatus {
+		let mut status = HealthStatus {
+			ok: false,
+			height: None,
+			peer_height: None,
+			rangeproof_validation: None,
+			failures: vec![],
+		};
+		check_chain_stores(&self.chain, &mut status);
+		if w(&self.peers).is_err() {
+			status.failures.push("P2P server is stopped".to_string());
+		}
+		status.ok = status.failures.is_empty();
+		status
+	}
+}
+
+impl Handler for HealthHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		health_response(self.check())
+	}
+}
+
+/// Readiness check for the load balancers and orchestration, no auth required
+/// GET /readyz
+/// 200 if the node is healthy, synced within `max_blocks_behind` blocks of the connected
+/// peers and can serve the consistent chain data, 503 otherwise
+pub struct ReadyHandler {
+	pub chain: Weak<Chain>,
+	pub peers: Weak<p2p::Peers>,
+	pub sync_state: Weak<SyncState>,
+	pub max_blocks_behind: u64,
+}
+
+impl ReadyHandler {
+	pub fn check(&self) -> HealthStatus {
+		let mut status = HealthStatus {
+			ok: false,
+			height: None,
+			peer_height: None,
+			rangeproof_validation: None,
+			failures: vec![],
+		};
+		let chain = check_chain_stores(&self.chain, &mut status);
+
+		match w(&self.sync_state) {
+			Ok(sync_state) => {
+				match sync_state.status() {
+					SyncStatus::NoSync
+					| SyncStatus::HeaderSync { .. }
+					| SyncStatus::BodySync { .. } => (),
+					other => {
+						let (name, _) = sync_status_to_api(other);
+						status
+							.failures
+							.push(format!("Node is not synced, sync status {}", name));
+					}
+				}
+				if let Some(error) = sync_state.last_chain_error() {
+					if error.class == ErrorClass::Corruption
+						&& Utc::now().timestamp() - error.time.timestamp()
+							< READY_CORRUPTION_WINDOW_SECS
+					{
+						status
+							.failures
+							.push(format!("Recent chain data corruption, {}", error.error));
+					}
+				}
+			}
+			Err(_) => status.failures.push("Sync is stopped".to_string()),
+		}
+
+		match w(&self.peers) {
+			Ok(peers) => {
+				status.peer_height = peers
+					.connected_peers()
+					.iter()
+					.filter(|p| p.info.serves_bodies())
+					.map(|p| p.info.height())
+					.max();
+				match (status.height, status.peer_height) {
+					(_, None) => status.failures.push("No connected peers".to_string()),
+					(Some(height), Some(peer_height))
+						if height + self.max_blocks_behind < peer_height =>
+					{
+						status.failures.push(format!(
+							"Chain is {} blocks behind the peers",
+							peer_height - height
+						))
+					}
+					_ => (),
+				}
+			}
+			Err(_) => status.failures.push("P2P server is stopped".to_string()),
+		}
+
+		// Snapshot is what the API reads the chain data from
+		if let Some(chain) = chain {
+			if let Err(e) = chain.snapshot() {
+				status
+					.failures
+					.push(format!("Unable to read a consistent chain snapshot, {}", e));
+			}
+			// Reduced validation is reported, the state with an invalid rangeproof is not served
+			match chain.rangeproof_validation() {
+				Ok(validation) => {
+					if validation.as_ref().map(|v| v.verification_failed) == Some(true) {
+						status
+							.failures
+							.push("Invalid rangeproof in the chain state".to_string());
+					}
+					status.rangeproof_validation = validation;
+				}
+				Err(e) => status
+					.failures
+					.push(format!("Unable to read the rangeproofs validation, {}", e)),
+			}
+		}
+
+		status.ok = status.failures.is_empty();
+		status
+	}
+}
+
+impl Handler for ReadyHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		health_response(self.check())
+	}
+}
+
 /// Convert a SyncStatus in a readable API representation
 fn sync_status_to_api(sync_status: SyncStatus) -> (String, Option<serde_json::Value>) {
 	match sync_status {
@@ -14,9 +14,10 @@
 
 use super::utils::w;
 use crate::chain;
+use crate::core::consensus;
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
-use crate::types::Version;
+use crate::types::{ProtocolSchedule, Version};
 use crate::web::*;
 use hyper::{Body, Request};
 use std::sync::Weak;
@@ -47,3 +48,22 @@ impl Handler for VersionHandler {
 		result_to_response(self.get_version())
 	}
 }
+
+/// Hard fork schedule and the expected header versions
+pub struct ProtocolScheduleHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl ProtocolScheduleHandler {
+	pub fn get_protocol_schedule(&self) -> Result<ProtocolSchedule, Error> {
+		let head = w(&self.chain)?
+			.head_header()
+			.map_err(|e| ErrorKind::Internal(format!("can't get chain tip, {}", e)))?;
+
+		Ok(ProtocolSchedule::from_schedule(
+			&consensus::hard_fork_schedule(),
+			head.height,
+			head.version.into(),
+		))
+	}
+}
@@ -0,0 +1,211 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! HTTP tuning of the API server. Explorers issue many small queries, with HTTP/2 and
+//! keep-alive they go over a single connection instead of a connection per query.
+
+use crate::router::{Handler, HandlerObj, ResponseFuture};
+use crate::web::response;
+use futures::future;
+use futures::TryStreamExt;
+use hyper::server::Builder;
+use hyper::{header, Body, Request, StatusCode};
+use std::io;
+use std::time::Duration;
+
+/// API server HTTP configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HttpConfig {
+	/// Accept HTTP/2 connections. With TLS it is negotiated with ALPN, without TLS
+	/// the clients must use the prior knowledge (h2c).
+	#[serde(default = "default_enable_http2")]
+	pub enable_http2: bool,
+
+	/// Keep the HTTP/1 connections alive between the requests
+	#[serde(default = "default_http1_keep_alive")]
+	pub http1_keep_alive: bool,
+
+	/// TCP keep-alive of the API connections, in seconds. 0 - disabled.
+	#[serde(default = "default_tcp_keep_alive_secs")]
+	pub tcp_keep_alive_secs: u64,
+
+	/// Interval of the HTTP/2 keep-alive pings, in seconds. 0 - disabled.
+	#[serde(default = "default_http2_keep_alive_interval_secs")]
+	pub http2_keep_alive_interval_secs: u64,
+
+	/// Connection is closed if the HTTP/2 keep-alive ping is not acknowledged in time
+	#[serde(default = "default_http2_keep_alive_timeout_secs")]
+	pub http2_keep_alive_timeout_secs: u64,
+
+	/// Max number of the concurrent HTTP/2 streams per connection
+	#[serde(default = "default_http2_max_concurrent_streams")]
+	pub http2_max_concurrent_streams: u32,
+
+	/// Max size of the request body in bytes, larger requests are rejected with 413.
+	/// 0 - no limit.
+	#[serde(default = "default_max_request_body_size")]
+	pub max_request_body_size: u64,
+
+	/// Compress the responses with gzip or brotli if the client accepts it
+	#[serde(default = "default_enable_compression")]
+	pub enable_compression: bool,
+
+	/// Responses smaller than this size in bytes are not compressed
+	#[serde(default = "default_compression_min_size")]
+	pub compression_min_size: u64,
+}
+
+impl Default for HttpConfig {
+	fn default() -> HttpConfig {
+		HttpConfig {
+			enable_http2: default_enable_http2(),
+			http1_keep_alive: default_http1_keep_alive(),
+			tcp_keep_alive_secs: default_tcp_keep_alive_secs(),
+			http2_keep_alive_interval_secs: default_http2_keep_alive_interval_secs(),
+			http2_keep_alive_timeout_secs: default_http2_keep_alive_timeout_secs(),
+			http2_max_concurrent_streams: default_http2_max_concurrent_streams(),
+			max_request_body_size: default_max_request_body_size(),
+			enable_compression: default_enable_compression(),
+			compression_min_size: default_compression_min_size(),
+		}
+	}
+}
+
+fn default_enable_http2() -> bool {
+	true
+}
+
+fn default_http1_keep_alive() -> bool {
+	true
+}
+
+fn default_tcp_keep_alive_secs() -> u64 {
+	60
+}
+
+fn default_http2_keep_alive_interval_secs() -> u64 {
+	30
+}
+
+fn default_http2_keep_alive_timeout_secs() -> u64 {
+	20
+}
+
+fn default_http2_max_concurrent_streams() -> u32 {
+	100
+}
+
+fn default_max_request_body_size() -> u64 {
+	// Enough for a transaction with a few thousands of outputs
+	10 * 1024 * 1024
+}
+
+fn default_enable_compression() -> bool {
+	true
+}
+
+fn default_compression_min_size() -> u64 {
+	// Smaller responses fit a few TCP packets anyway
+	4096
+}
+
+impl HttpConfig {
+	/// TCP keep-alive duration, None if disabled
+	pub fn tcp_keep_alive(&self) -> Option<Duration> {
+		Some(self.tcp_keep_alive_secs)
+			.filter(|s| *s > 0)
+			.map(Duration::from_secs)
+	}
+
+	/// ALPN protocols advertised by the TLS server
+	pub fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+		if self.enable_http2 {
+			vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+		} else {
+			vec![b"http/1.1".to_vec()]
+		}
+	}
+
+	/// Apply the configuration to the hyper server builder
+	pub fn configure<I, E>(&self, builder: Builder<I, E>) -> Builder<I, E> {
+		let builder = builder
+			.http1_keepalive(self.http1_keep_alive)
+			.http1_only(!self.enable_http2)
+			.http2_max_concurrent_streams(self.http2_max_concurrent_streams);
+		if self.http2_keep_alive_interval_secs > 0 {
+			builder
+				.http2_keep_alive_interval(Duration::from_secs(self.http2_keep_alive_interval_secs))
+				.http2_keep_alive_timeout(Duration::from_secs(self.http2_keep_alive_timeout_secs))
+		} else {
+			builder.http2_keep_alive_interval(None::<Duration>)
+		}
+	}
+}
+
+/// Middleware that rejects the requests with the body larger than the limit. Requests
+/// with the Content-Length over the limit are rejected right away, the streamed bodies
+/// fail once the limit is reached.
+pub struct BodyLimitMiddleware {
+	max_body_size: u64,
+}
+
+impl BodyLimitMiddleware {
+	/// New middleware with the body size limit in bytes
+	pub fn new(max_body_size: u64) -> BodyLimitMiddleware {
+		BodyLimitMiddleware { max_body_size }
+	}
+}
+
+impl Handler for BodyLimitMiddleware {
+	fn call(
+		&self,
+		req: Request<Body>,
+		mut handlers: Box<dyn Iterator<Item = HandlerObj>>,
+	) -> ResponseFuture {
+		let max_body_size = self.max_body_size;
+		let content_length = req
+			.headers()
+			.get(header::CONTENT_LENGTH)
+			.and_then(|v| v.to_str().ok())
+			.and_then(|v| v.parse::<u64>().ok());
+		if content_length.map(|l| l > max_body_size) == Some(true) {
+			return response(
+				StatusCode::PAYLOAD_TOO_LARGE,
+				format!("request body is larger than {} bytes", max_body_size),
+			);
+		}
+
+		let (parts, body) = req.into_parts();
+		let mut received = 0u64;
+		let body = body
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+			.and_then(move |chunk| {
+				received += chunk.len() as u64;
+				future::ready(if received > max_body_size {
+					Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						format!("request body is larger than {} bytes", max_body_size),
+					))
+				} else {
+					Ok(chunk)
+				})
+			});
+		let req = Request::from_parts(parts, Body::wrap_stream(body));
+
+		match handlers.next() {
+			Some(handler) => handler.call(req, handlers),
+			None => response(StatusCode::INTERNAL_SERVER_ERROR, "no handler found"),
+		}
+	}
+}
@@ -0,0 +1,563 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kernel watch lists. Exchanges register the kernels of their withdrawals with
+//! `watch_kernels` and get notified when a watched kernel appears in a block and on every
+//! block after that, until the kernel has the requested number of confirmations.
+//! Subscribers connect with websocket to `/v2/kernels/events`, the same events are
+//! delivered to the `kernel_watch` webhooks.
+//!
+//! When a reorg drops the block with the watched kernel, `kernel_reorged` event is sent
+//! and the kernel is watched again. The subscriber that lost the connection reconnects
+//! with `?resume_from=<seq>` and gets the missed events from the event journal first.
+
+use crate::chain::BlockStatus;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::Block;
+use crate::event_journal::{EventFeed, EventLog};
+use crate::pool_events::websocket_subscribe;
+use crate::rest::{Error, ErrorKind};
+use crate::router::{Handler, ResponseFuture};
+use crate::util::secp::pedersen::Commitment;
+use crate::util::{Mutex, ToHex};
+use chrono::Utc;
+use futures::channel::mpsc;
+use hyper::{Body, Request};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Confirmations depth of the watch if it is not specified
+pub const DEFAULT_KERNEL_WATCH_DEPTH: u64 = 10;
+/// Max confirmations depth of the watch, one day of blocks
+pub const MAX_KERNEL_WATCH_DEPTH: u64 = 1440;
+/// Max number of the kernels in a single watch
+pub const MAX_KERNELS_PER_WATCH: usize = 1000;
+/// Max number of the active watches
+pub const MAX_KERNEL_WATCHES: usize = 1000;
+
+/// Fork blocks are kept for the reorg processing while they are that close to the head
+const FORK_BLOCKS_DEPTH: u64 = 60;
+
+/// Kernel watch event
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum KernelWatchEvent {
+	/// Watched kernel is in the chain. Sent for the block with the kernel and for every
+	/// next block until the watch depth is reached.
+	KernelConfirmed {
+		/// Watch id
+		watch_id: u64,
+		/// Kernel excess, hex encoded
+		excess: String,
+		/// Height of the block with the kernel
+		height: u64,
+		/// Hash of the block with the kernel
+		block_hash: String,
+		/// Number of confirmations, 1 for the block with the kernel
+		confirmations: u64,
+		/// Kernel reached the watch depth, it is not watched any more
+		final_confirmation: bool,
+	},
+	/// Block with the watched kernel was dropped by a reorg, the kernel is watched again
+	KernelReorged {
+		/// Watch id
+		watch_id: u64,
+		/// Kernel excess, hex encoded
+		excess: String,
+		/// Height of the dropped block
+		height: u64,
+		/// Hash of the dropped block
+		block_hash: String,
+	},
+}
+
+/// Event with its sequence number, the message sent to the subscribers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KernelWatchEventMessage {
+	/// Sequence number, continues across the node restarts if the event journal is enabled
+	pub seq: u64,
+	/// The event
+	#[serde(flatten)]
+	pub event: KernelWatchEvent,
+}
+
+/// Registered kernel watch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KernelWatch {
+	/// Watch id, the events of this watch have it
+	pub watch_id: u64,
+	/// Kernels are watched until they have that many confirmations
+	pub depth: u64,
+	/// Unix time when the watch was registered
+	pub created: i64,
+	/// Watched kernels
+	pub kernels: Vec<WatchedKernel>,
+}
+
+/// State of the watched kernel
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WatchedKernel {
+	/// Kernel excess, hex encoded
+	pub excess: String,
+	/// Height of the block with the kernel, None if it is not in the chain yet
+	pub height: Option<u64>,
+	/// Hash of the block with the kernel
+	pub block_hash: Option<String>,
+}
+
+struct Watch {
+	depth: u64,
+	created: i64,
+	// Kernel excess => (height, block hash) of the block with the kernel
+	kernels: HashMap<Commitment, Option<(u64, Hash)>>,
+}
+
+// Fork block that may become a part of the chain with the next reorg
+struct ForkBlock {
+	height: u64,
+	prev_hash: Hash,
+	kernels: Vec<Commitment>,
+}
+
+struct WatchState {
+	next_id: u64,
+	default_depth: u64,
+	watches: HashMap<u64, Watch>,
+	fork_blocks: HashMap<Hash, ForkBlock>,
+}
+
+/// Tracks the kernel watches and publishes their events to the subscribers
+pub struct KernelWatchHub {
+	feed: EventFeed,
+	state: Mutex<WatchState>,
+}
+
+impl KernelWatchHub {
+	/// New hub without watches and subscribers
+	pub fn new() -> KernelWatchHub {
+		KernelWatchHub {
+			feed: EventFeed::new("Kernel watch"),
+			state: Mutex::new(WatchState {
+				next_id: 1,
+				default_depth: DEFAULT_KERNEL_WATCH_DEPTH,
+				watches: HashMap::new(),
+				fork_blocks: HashMap::new(),
+			}),
+		}
+	}
+
+	/// Confirmations depth for the watches registered without the depth
+	pub fn set_default_depth(&self, depth: u64) {
+		self.state.lock().default_depth = depth.max(1).min(MAX_KERNEL_WATCH_DEPTH);
+	}
+
+	/// Register the watch. `found` are the kernels that are already in the chain, with
+	/// the height and hash of their blocks.
+	pub fn watch(
+		&self,
+		kernels: Vec<Commitment>,
+		depth: Option<u64>,
+		found: HashMap<Commitment, (u64, Hash)>,
+	) -> Result<KernelWatch, Error> {
+		if kernels.is_empty() || kernels.len() > MAX_KERNELS_PER_WATCH {
+			return Err(ErrorKind::Argument(format!(
+				"Watch must have from 1 to {} kernels, get {}",
+				MAX_KERNELS_PER_WATCH,
+				kernels.len()
+			))
+			.into());
+		}
+		let mut state = self.state.lock();
+		if state.watches.len() >= MAX_KERNEL_WATCHES {
+			return Err(ErrorKind::RequestError(format!(
+				"Too many kernel watches, the limit is {}",
+				MAX_KERNEL_WATCHES
+			))
+			.into());
+		}
+		let depth = depth
+			.unwrap_or(state.default_depth)
+			.max(1)
+			.min(MAX_KERNEL_WATCH_DEPTH);
+		let watch = Watch {
+			depth,
+			created: Utc::now().timestamp(),
+			kernels: kernels
+				.into_iter()
+				.map(|k| {
+					let block = found.get(&k).cloned();
+					(k, block)
+				})
+				.collect(),
+		};
+		let watch_id = state.next_id;
+		state.next_id += 1;
+		let res = watch_info(watch_id, &watch);
+		state.watches.insert(watch_id, watch);
+		Ok(res)
+	}
+
+	/// Remove the watch. Returns false if there is no such watch.
+	pub fn unwatch(&self, watch_id: u64) -> bool {
+		self.state.lock().watches.remove(&watch_id).is_some()
+	}
+
+	/// Current state of the watch
+	pub fn get_watch(&self, watch_id: u64) -> Option<KernelWatch> {
+		self.state
+			.lock()
+			.watches
+			.get(&watch_id)
+			.map(|w| watch_info(watch_id, w))
+	}
+
+	/// Process the block accepted by the chain and publish the events. Returns the
+	/// published events.
+	pub fn block_accepted(&self, block: &Block, status: &BlockStatus) -> Vec<KernelWatchEvent> {
+		let kernels: Vec<Commitment> = block.kernels().iter().map(|k| k.excess).collect();
+		let events = self.process_block(
+			block.header.height,
+			block.hash(),
+			block.header.prev_hash,
+			&kernels,
+			status,
+		);
+		for event in &events {
+			self.publish(event.clone());
+		}
+		events
+	}
+
+	fn process_block(
+		&self,
+		height: u64,
+		hash: Hash,
+		prev_hash: Hash,
+		kernels: &[Commitment],
+		status: &BlockStatus,
+	) -> Vec<KernelWatchEvent> {
+		let mut state = self.state.lock();
+		if state.watches.is_empty() {
+			state.fork_blocks.clear();
+			return vec![];
+		}
+		let watched: Vec<Commitment> = kernels
+			.iter()
+			.filter(|k| state.watches.values().any(|w| w.kernels.contains_key(k)))
+			.cloned()
+			.collect();
+
+		let mut events = vec![];
+		match status {
+			BlockStatus::Fork { .. } => {
+				state.fork_blocks.insert(
+					hash,
+					ForkBlock {
+						height,
+						prev_hash,
+						kernels: watched,
+					},
+				);
+				return events;
+			}
+			BlockStatus::Next { .. } => {}
+			BlockStatus::Reorg { fork_point, .. } => {
+				// Blocks above the fork point are not in the chain any more
+				for (watch_id, watch) in state.watches.iter_mut() {
+					for (excess, block) in watch.kernels.iter_mut() {
+						if let Some((kernel_height, block_hash)) = *block {
+							if kernel_height > fork_point.height {
+								events.push(KernelWatchEvent::KernelReorged {
+									watch_id: *watch_id,
+									excess: excess.to_hex(),
+									height: kernel_height,
+									block_hash: block_hash.to_hex(),
+								});
+								*block = None;
+							}
+						}
+					}
+				}
+				// The fork blocks between the fork point and this block are in the chain now
+				let mut fork_path = vec![];
+				let mut cur = prev_hash;
+				while cur != fork_point.last_block_h {
+					match state.fork_blocks.remove(&cur) {
+						Some(b) => {
+							let prev = b.prev_hash;
+							fork_path.push((cur, b));
+							cur = prev;
+						}
+						None => break,
+					}
+				}
+				for (fork_hash, b) in fork_path.into_iter().rev() {
+					mark_found(&mut state.watches, b.height, fork_hash, &b.kernels);
+				}
+			}
+		}
+		mark_found(&mut state.watches, height, hash, &watched);
+		state
+			.fork_blocks
+			.retain(|_, b| b.height + FORK_BLOCKS_DEPTH > height);
+
+		// Confirmations for the new head
+		for (watch_id, watch) in state.watches.iter_mut() {
+			let depth = watch.depth;
+			let mut confirmed = vec![];
+			for (excess, block) in watch.kernels.iter() {
+				if let Some((kernel_height, block_hash)) = *block {
+					if kernel_height > height {
+						continue;
+					}
+					let confirmations = height - kernel_height + 1;
+					let final_confirmation = confirmations >= depth;
+					events.push(KernelWatchEvent::KernelConfirmed {
+						watch_id: *watch_id,
+						excess: excess.to_hex(),
+						height: kernel_height,
+						block_hash: block_hash.to_hex(),
+						confirmations,
+						final_confirmation,
+					});
+					if final_confirmation {
+						confirmed.push(excess.clone());
+					}
+				}
+			}
+			for excess in confirmed {
+				watch.kernels.remove(&excess);
+			}
+		}
+		state.watches.retain(|_, w| !w.kernels.is_empty());
+		events
+	}
+
+	/// Publish the event to all the subscribers. Never blocks, the subscribers that
+	/// can't keep up are disconnected.
+	fn publish(&self, event: KernelWatchEvent) {
+		self.feed
+			.publish(|seq| KernelWatchEventMessage { seq, event });
+	}
+
+	/// Record the events in the journal, the sequence continues from the journal
+	pub fn set_journal(&self, log: EventLog) {
+		self.feed.set_journal(log);
+	}
+
+	/// Subscribe to the events published from now on. With `resume_from` the journal
+	/// events starting from that sequence number are sent first.
+	pub fn subscribe(
+		&self,
+		resume_from: Option<u64>,
+	) -> Result<mpsc::Receiver<Arc<String>>, Error> {
+		self.feed.subscribe(resume_from)
+	}
+}
+
+fn mark_found(watches: &mut HashMap<u64, Watch>, height: u64, hash: Hash, kernels: &[Commitment]) {
+	for watch in watches.values_mut() {
+		for excess in kernels {
+			if let Some(block) = watch.kernels.get_mut(excess) {
+				if block.is_none() {
+					*block = Some((height, hash));
+				}
+			}
+		}
+	}
+}
+
+fn watch_info(watch_id: u64, watch: &Watch) -> KernelWatch {
+	let mut kernels: Vec<WatchedKernel> = watch
+		.kernels
+		.iter()
+		.map(|(excess, block)| WatchedKernel {
+			excess: excess.to_hex(),
+			height: block.map(|(h, _)| h),
+			block_hash: block.map(|(_, hash)| hash.to_hex()),
+		})
+		.collect();
+	kernels.sort_by(|a, b| a.excess.cmp(&b.excess));
+	KernelWatch {
+		watch_id,
+		depth: watch.depth,
+		created: watch.created,
+		kernels,
+	}
+}
+
+lazy_static! {
+	static ref KERNEL_WATCH_HUB: Arc<KernelWatchHub> = Arc::new(KernelWatchHub::new());
+}
+
+/// Hub shared by the foreign API, the websocket endpoint and the chain hooks
+pub fn kernel_watch_hub() -> Arc<KernelWatchHub> {
+	KERNEL_WATCH_HUB.clone()
+}
+
+/// Websocket endpoint of the kernel watch events
+pub struct KernelWatchEventsHandler {
+	pub hub: Arc<KernelWatchHub>,
+}
+
+impl Handler for KernelWatchEventsHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let hub = self.hub.clone();
+		websocket_subscribe(req, "Kernel watch", move |resume_from| {
+			hub.subscribe(resume_from)
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::chain::Tip;
+	use crate::core::pow::Difficulty;
+
+	fn tip(height: u64, hash: Hash) -> Tip {
+		Tip {
+			height,
+			last_block_h: hash,
+			prev_block_h: Hash::default(),
+			total_difficulty: Difficulty::min(),
+		}
+	}
+
+	fn hash(n: u8) -> Hash {
+		Hash::from_vec(&[n; 32])
+	}
+
+	fn next(prev: u64) -> BlockStatus {
+		BlockStatus::Next {
+			prev: tip(prev, hash(prev as u8)),
+		}
+	}
+
+	fn confirmations(events: &[KernelWatchEvent]) -> Vec<(u64, bool)> {
+		events
+			.iter()
+			.filter_map(|e| match e {
+				KernelWatchEvent::KernelConfirmed {
+					confirmations,
+					final_confirmation,
+					..
+				} => Some((*confirmations, *final_confirmation)),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn kernel_watch_confirmations() {
+		let hub = KernelWatchHub::new();
+		let k1 = Commitment::from_vec(vec![1; 33]);
+		let k2 = Commitment::from_vec(vec![2; 33]);
+		let watch = hub
+			.watch(vec![k1.clone(), k2.clone()], Some(2), HashMap::new())
+			.unwrap();
+		assert_eq!(watch.depth, 2);
+		assert!(hub.watch(vec![], None, HashMap::new()).is_err());
+
+		// Block without the watched kernels
+		let events = hub.process_block(10, hash(10), hash(9), &[], &next(9));
+		assert!(events.is_empty());
+
+		let events = hub.process_block(11, hash(11), hash(10), &[k1.clone()], &next(10));
+		assert_eq!(confirmations(&events), vec![(1, false)]);
+		let state = hub.get_watch(watch.watch_id).unwrap();
+		assert!(state.kernels.iter().any(|k| k.height == Some(11)));
+
+		let events = hub.process_block(12, hash(12), hash(11), &[], &next(11));
+		assert_eq!(confirmations(&events), vec![(2, true)]);
+		// k1 is done, k2 is still watched
+		let state = hub.get_watch(watch.watch_id).unwrap();
+		assert_eq!(state.kernels.len(), 1);
+		assert_eq!(state.kernels[0].excess, k2.to_hex());
+
+		// k2 in the fork block, then the fork wins
+		let events = hub.process_block(
+			12,
+			hash(112),
+			hash(11),
+			&[k2.clone()],
+			&BlockStatus::Fork {
+				prev: tip(11, hash(11)),
+				head: tip(12, hash(12)),
+				fork_point: tip(11, hash(11)),
+			},
+		);
+		assert!(events.is_empty());
+		let events = hub.process_block(
+			13,
+			hash(113),
+			hash(112),
+			&[],
+			&BlockStatus::Reorg {
+				prev: tip(12, hash(112)),
+				prev_head: tip(12, hash(12)),
+				fork_point: tip(11, hash(11)),
+			},
+		);
+		assert_eq!(confirmations(&events), vec![(2, true)]);
+		match &events[0] {
+			KernelWatchEvent::KernelConfirmed {
+				height, block_hash, ..
+			} => {
+				assert_eq!(*height, 12);
+				assert_eq!(*block_hash, hash(112).to_hex());
+			}
+			e => panic!("unexpected event {:?}", e),
+		}
+		// All kernels are confirmed, the watch is done
+		assert!(hub.get_watch(watch.watch_id).is_none());
+	}
+
+	#[test]
+	fn kernel_watch_reorg() {
+		let hub = KernelWatchHub::new();
+		let k1 = Commitment::from_vec(vec![1; 33]);
+		let mut found = HashMap::new();
+		found.insert(k1.clone(), (20, hash(20)));
+		let watch = hub.watch(vec![k1.clone()], Some(5), found).unwrap();
+		assert_eq!(watch.kernels[0].height, Some(20));
+
+		let events = hub.process_block(
+			21,
+			hash(121),
+			hash(119),
+			&[],
+			&BlockStatus::Reorg {
+				prev: tip(20, hash(120)),
+				prev_head: tip(20, hash(20)),
+				fork_point: tip(19, hash(19)),
+			},
+		);
+		assert_eq!(
+			events,
+			vec![KernelWatchEvent::KernelReorged {
+				watch_id: watch.watch_id,
+				excess: k1.to_hex(),
+				height: 20,
+				block_hash: hash(20).to_hex(),
+			}]
+		);
+		assert_eq!(
+			hub.get_watch(watch.watch_id).unwrap().kernels[0].height,
+			None
+		);
+		assert!(hub.unwatch(watch.watch_id));
+		assert!(!hub.unwatch(watch.watch_id));
+	}
+}
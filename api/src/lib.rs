@@ -35,30 +35,85 @@ extern crate log;
 mod web;
 pub mod auth;
 pub mod client;
+mod cold_storage;
+mod compression;
+mod confirmation_safety;
+mod event_journal;
 mod foreign;
 mod foreign_rpc;
+mod grpc;
 mod handlers;
+mod http_config;
 pub mod json_rpc;
+mod kernel_watch;
+mod load_shedding;
+pub mod outbound;
 mod owner;
 mod owner_rpc;
+mod owner_session;
+mod pool_events;
 mod rest;
 mod router;
+mod standby;
 mod stratum;
+mod stratum_audit;
 mod stratum_rpc;
 pub mod types;
 
 pub use crate::auth::{
-	BasicAuthMiddleware, BasicAuthURIMiddleware, MWC_BASIC_REALM, MWC_FOREIGN_BASIC_REALM,
+	BasicAuthMiddleware, BasicAuthURIMiddleware, OwnerSessionMiddleware, MWC_BASIC_REALM,
+	MWC_FOREIGN_BASIC_REALM, MWC_OWNER_BEARER_REALM,
+};
+pub use crate::cold_storage::{
+	check_not_cold_storage, cold_storage_snapshot, init_cold_storage, is_cold_storage,
+};
+pub use crate::compression::{
+	api_compression_stats, negotiate_encoding, ApiCompressionStats, CompressionMiddleware,
+	ContentEncoding,
+};
+pub use crate::confirmation_safety::{
+	classify_risk, fork_activity_summary, recommended_confirmations, record_fork_activity,
+	ConfirmationRisk, ConfirmationSafety, ForkActivity, ForkActivitySummary,
+};
+pub use crate::event_journal::{
+	EventJournal, EventLog, EventStream, DEFAULT_EVENT_JOURNAL_MAX_ENTRIES,
 };
 pub use crate::foreign::Foreign;
 pub use crate::foreign_rpc::ForeignRpc;
+pub use crate::grpc::GrpcConfig;
+#[cfg(feature = "grpc")]
+pub use crate::grpc::{proto, start_grpc_server, to_proto_event, ForeignGrpc, OwnerGrpc};
 pub use crate::handlers::node_apis;
+pub use crate::http_config::{BodyLimitMiddleware, HttpConfig};
+pub use crate::kernel_watch::{
+	kernel_watch_hub, KernelWatch, KernelWatchEvent, KernelWatchEventMessage, KernelWatchHub,
+	WatchedKernel,
+};
+pub use crate::load_shedding::LoadSheddingConfig;
+pub use crate::outbound::{
+	outbound_http, set_outbound_http, OutboundConnector, OutboundHttpConfig, OutboundProxy,
+};
 pub use crate::owner::Owner;
 pub use crate::owner::{
-	get_server_onion_address, reset_server_onion_address, set_server_onion_address,
+	get_server_onion_address, get_tor_pow_defense, reset_server_onion_address,
+	set_server_onion_address, set_tor_pow_defense,
 };
 pub use crate::owner_rpc::OwnerRpc;
+pub use crate::owner_session::{
+	OwnerAuditEntry, OwnerLoginRequest, OwnerSession, OwnerSessionConfig, OwnerSessionToken,
+	OwnerSessions,
+};
+pub use crate::pool_events::{TxPoolEvent, TxPoolEventMessage, TxPoolEventsHub};
 pub use crate::rest::*;
 pub use crate::router::*;
+pub use crate::standby::{
+	check_not_standby, init_standby, is_standby, promote_to_primary, record_primary_check,
+	standby_status, NodeRole, StandbyStatus,
+};
+pub use crate::stratum_audit::{
+	init_stratum_audit, stratum_audit, StratumAuditLog, StratumAuditQuery, StratumJobRecord,
+	StratumShareRecord, StratumShareResult, DEFAULT_STRATUM_AUDIT_MAX_ENTRIES,
+	DEFAULT_STRATUM_AUDIT_RETENTION_DAYS,
+};
 pub use crate::types::*;
 pub use crate::web::*;
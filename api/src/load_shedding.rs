@@ -0,0 +1,225 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Load shedding for the foreign API and the heavy v1 REST routes. Expensive calls are
+//! rejected with 503 while the node runs the sync critical stages, so the state validation
+//! is not slowed down.
+
+use crate::chain::{SyncState, SyncStatus};
+use crate::router::{Handler, HandlerObj, ResponseFuture};
+use crate::web::response;
+use futures::future;
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::Value;
+use std::sync::Weak;
+
+/// Foreign API load shedding configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LoadSheddingConfig {
+	/// Reject the heavy foreign API calls during the txhashset setup, validation and save
+	#[serde(default = "default_enable_load_shedding")]
+	pub enable_load_shedding: bool,
+
+	/// Reject the heavy calls during the headers and blocks download as well
+	#[serde(default = "default_shed_during_block_sync")]
+	pub shed_during_block_sync: bool,
+
+	/// Foreign API methods that are rejected
+	#[serde(default = "default_load_shedding_heavy_methods")]
+	pub load_shedding_heavy_methods: Vec<String>,
+
+	/// Prefixes of the v1 REST routes that are rejected
+	#[serde(default = "default_load_shedding_heavy_uris")]
+	pub load_shedding_heavy_uris: Vec<String>,
+
+	/// Value of the Retry-After header of the rejected responses
+	#[serde(default = "default_load_shedding_retry_after_secs")]
+	pub load_shedding_retry_after_secs: u64,
+}
+
+impl Default for LoadSheddingConfig {
+	fn default() -> LoadSheddingConfig {
+		LoadSheddingConfig {
+			enable_load_shedding: default_enable_load_shedding(),
+			shed_during_block_sync: default_shed_during_block_sync(),
+			load_shedding_heavy_methods: default_load_shedding_heavy_methods(),
+			load_shedding_heavy_uris: default_load_shedding_heavy_uris(),
+			load_shedding_retry_after_secs: default_load_shedding_retry_after_secs(),
+		}
+	}
+}
+
+fn default_enable_load_shedding() -> bool {
+	true
+}
+
+fn default_shed_during_block_sync() -> bool {
+	false
+}
+
+fn default_load_shedding_heavy_methods() -> Vec<String> {
+	vec![
+		"get_block".to_string(),
+		"get_kernel".to_string(),
+		"get_outputs".to_string(),
+		"get_unspent_outputs".to_string(),
+		"get_pmmr_indices".to_string(),
+		"get_kernels_by_fee".to_string(),
+		"get_fee_histogram".to_string(),
+	]
+}
+
+fn default_load_shedding_heavy_uris() -> Vec<String> {
+	vec![
+		"/v1/txhashset/".to_string(),
+		"/v1/blocks/".to_string(),
+		"/v1/chain/outputs/".to_string(),
+	]
+}
+
+fn default_load_shedding_retry_after_secs() -> u64 {
+	30
+}
+
+impl LoadSheddingConfig {
+	/// Check if the sync status is critical enough to reject the heavy calls
+	pub fn is_shedding(&self, status: &SyncStatus) -> bool {
+		if !self.enable_load_shedding {
+			return false;
+		}
+		match status {
+			SyncStatus::TxHashsetSetup
+			| SyncStatus::TxHashsetKernelsValidation { .. }
+			| SyncStatus::TxHashsetRangeProofsValidation { .. }
+			| SyncStatus::TxHashsetSave => true,
+			SyncStatus::HeaderSync { .. }
+			| SyncStatus::TxHashsetDownload(_)
+			| SyncStatus::BodySync { .. } => self.shed_during_block_sync,
+			_ => false,
+		}
+	}
+
+	/// Check if the json-rpc request (single or batch) calls any heavy method
+	pub fn is_heavy_request(&self, request: &Value) -> bool {
+		match request {
+			Value::Array(requests) => requests.iter().any(|r| self.is_heavy_request(r)),
+			Value::Object(_) => match request.get("method").and_then(|m| m.as_str()) {
+				Some(method) => self.load_shedding_heavy_methods.iter().any(|m| m == method),
+				None => false,
+			},
+			_ => false,
+		}
+	}
+
+	/// Check if the REST request path belongs to a heavy route
+	pub fn is_heavy_uri(&self, path: &str) -> bool {
+		self.load_shedding_heavy_uris
+			.iter()
+			.any(|prefix| path.starts_with(prefix.as_str()))
+	}
+}
+
+/// Response for the requests rejected by the load shedding
+pub(crate) fn create_busy_response(retry_after_secs: u64) -> Response<Body> {
+	Response::builder()
+		.status(StatusCode::SERVICE_UNAVAILABLE)
+		.header("access-control-allow-origin", "*")
+		.header(
+			"access-control-allow-headers",
+			"Content-Type, Authorization",
+		)
+		.header(hyper::header::RETRY_AFTER, retry_after_secs.to_string())
+		.body("Node is busy with the chain sync, please retry later".into())
+		.unwrap()
+}
+
+/// Rejects the heavy v1 REST requests while the sync critical stages run
+pub struct LoadSheddingMiddleware {
+	config: LoadSheddingConfig,
+	sync_state: Weak<SyncState>,
+}
+
+impl LoadSheddingMiddleware {
+	/// New middleware for the sync state of the node
+	pub fn new(config: LoadSheddingConfig, sync_state: Weak<SyncState>) -> LoadSheddingMiddleware {
+		LoadSheddingMiddleware { config, sync_state }
+	}
+
+	fn is_shedding(&self) -> bool {
+		match self.sync_state.upgrade() {
+			Some(sync_state) => self.config.is_shedding(&sync_state.status()),
+			None => false,
+		}
+	}
+}
+
+impl Handler for LoadSheddingMiddleware {
+	fn call(
+		&self,
+		req: Request<Body>,
+		mut handlers: Box<dyn Iterator<Item = HandlerObj>>,
+	) -> ResponseFuture {
+		if self.config.is_heavy_uri(req.uri().path()) && self.is_shedding() {
+			debug!(
+				"REST API request {} is rejected by load shedding",
+				req.uri().path()
+			);
+			return Box::pin(future::ok(create_busy_response(
+				self.config.load_shedding_retry_after_secs,
+			)));
+		}
+
+		match handlers.next() {
+			Some(handler) => handler.call(req, handlers),
+			None => response(StatusCode::INTERNAL_SERVER_ERROR, "no handler found"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn shed_heavy_requests() {
+		let config = LoadSheddingConfig::default();
+
+		assert!(config.is_shedding(&SyncStatus::TxHashsetSetup));
+		assert!(!config.is_shedding(&SyncStatus::NoSync));
+		assert!(!config.is_shedding(&SyncStatus::BodySync {
+			current_height: 1,
+			highest_height: 2
+		}));
+
+		let heavy = json!({"jsonrpc": "2.0", "method": "get_outputs", "params": [], "id": 1});
+		let light = json!({"jsonrpc": "2.0", "method": "get_tip", "params": [], "id": 1});
+		assert!(config.is_heavy_request(&heavy));
+		assert!(!config.is_heavy_request(&light));
+		assert!(config.is_heavy_request(&json!([light.clone(), heavy])));
+		assert!(!config.is_heavy_request(&json!([light])));
+
+		assert!(config.is_heavy_uri("/v1/txhashset/outputs?start_index=1&max=800"));
+		assert!(config.is_heavy_uri("/v1/blocks/1000"));
+		assert!(config.is_heavy_uri("/v1/chain/outputs/byheight?start_height=1"));
+		assert!(!config.is_heavy_uri("/v1/chain"));
+		assert!(!config.is_heavy_uri("/v1/status"));
+
+		let config = LoadSheddingConfig {
+			enable_load_shedding: false,
+			..LoadSheddingConfig::default()
+		};
+		assert!(!config.is_shedding(&SyncStatus::TxHashsetSave));
+	}
+}
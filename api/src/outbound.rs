@@ -0,0 +1,373 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Connector for the outbound HTTP requests of the node (webhooks, wallet listener
+//! calls). Requests can be routed through a SOCKS5 proxy (Tor) or an HTTP proxy.
+//! In the onion only mode the requests are never sent directly, only through the proxy,
+//! so Tor routed deployments don't leak the clearnet connections. Requests to the
+//! loopback addresses are always direct.
+
+use crate::rest::{Error, ErrorKind};
+use crate::util::RwLock;
+use http::uri::Uri;
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper_rustls::{HttpsConnector, MaybeHttpsStream};
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use tokio_socks::tcp::Socks5Stream;
+
+// Max size of the HTTP proxy CONNECT response headers
+const MAX_CONNECT_RESPONSE: usize = 8192;
+
+/// Proxy for the outbound HTTP requests
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutboundProxy {
+	/// SOCKS5 proxy 'host:port', the host names are resolved by the proxy
+	Socks5(String),
+	/// HTTP proxy 'host:port', the connections are tunneled with CONNECT
+	Http(String),
+}
+
+impl OutboundProxy {
+	/// Parse the proxy url, 'socks5://host:port', 'socks5h://host:port' or 'http://host:port'
+	pub fn parse(url: &str) -> Result<OutboundProxy, Error> {
+		let (scheme, addr) = match url.find("://") {
+			Some(i) => (&url[..i], url[i + 3..].trim_end_matches('/')),
+			None => {
+				return Err(ErrorKind::Argument(format!(
+					"Invalid proxy url {}, expected scheme://host:port",
+					url
+				))
+				.into())
+			}
+		};
+		if addr.is_empty() || !addr.contains(':') {
+			return Err(ErrorKind::Argument(format!("Invalid proxy address in {}", url)).into());
+		}
+		match scheme {
+			"socks5" | "socks5h" => Ok(OutboundProxy::Socks5(addr.to_string())),
+			"http" => Ok(OutboundProxy::Http(addr.to_string())),
+			_ => Err(ErrorKind::Argument(format!("Unsupported proxy scheme {}", scheme)).into()),
+		}
+	}
+}
+
+/// Outbound HTTP settings of the node
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutboundHttpConfig {
+	/// Proxy for the requests, direct connections if None
+	pub proxy: Option<OutboundProxy>,
+	/// Never connect directly, the requests without a proxy are refused
+	pub onion_only: bool,
+}
+
+lazy_static! {
+	static ref OUTBOUND_HTTP: RwLock<OutboundHttpConfig> =
+		RwLock::new(OutboundHttpConfig::default());
+	static ref PROXY_TLS_CONFIG: Arc<ClientConfig> = {
+		let mut config = ClientConfig::new();
+		config
+			.root_store
+			.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+		Arc::new(config)
+	};
+}
+
+/// Set the outbound HTTP settings of the node
+pub fn set_outbound_http(config: OutboundHttpConfig) {
+	*OUTBOUND_HTTP.write() = config;
+}
+
+/// Outbound HTTP settings of the node
+pub fn outbound_http() -> OutboundHttpConfig {
+	OUTBOUND_HTTP.read().clone()
+}
+
+/// True for the hosts that are always connected directly
+pub fn is_local_host(host: &str) -> bool {
+	if host.eq_ignore_ascii_case("localhost") {
+		return true;
+	}
+	host.trim_start_matches('[')
+		.trim_end_matches(']')
+		.parse::<IpAddr>()
+		.map(|ip| ip.is_loopback())
+		.unwrap_or(false)
+}
+
+/// Stream of the outbound connection
+pub enum OutboundStream {
+	/// Direct connection, plain or TLS
+	Direct(MaybeHttpsStream<TcpStream>),
+	/// Plain connection through the proxy
+	Proxied(TcpStream),
+	/// TLS connection through the proxy
+	ProxiedTls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for OutboundStream {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<io::Result<usize>> {
+		match self.get_mut() {
+			OutboundStream::Direct(s) => Pin::new(s).poll_read(cx, buf),
+			OutboundStream::Proxied(s) => Pin::new(s).poll_read(cx, buf),
+			OutboundStream::ProxiedTls(s) => Pin::new(s).poll_read(cx, buf),
+		}
+	}
+}
+
+impl AsyncWrite for OutboundStream {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		match self.get_mut() {
+			OutboundStream::Direct(s) => Pin::new(s).poll_write(cx, buf),
+			OutboundStream::Proxied(s) => Pin::new(s).poll_write(cx, buf),
+			OutboundStream::ProxiedTls(s) => Pin::new(s).poll_write(cx, buf),
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.get_mut() {
+			OutboundStream::Direct(s) => Pin::new(s).poll_flush(cx),
+			OutboundStream::Proxied(s) => Pin::new(s).poll_flush(cx),
+			OutboundStream::ProxiedTls(s) => Pin::new(s).poll_flush(cx),
+		}
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.get_mut() {
+			OutboundStream::Direct(s) => Pin::new(s).poll_shutdown(cx),
+			OutboundStream::Proxied(s) => Pin::new(s).poll_shutdown(cx),
+			OutboundStream::ProxiedTls(s) => Pin::new(s).poll_shutdown(cx),
+		}
+	}
+}
+
+impl Connection for OutboundStream {
+	fn connected(&self) -> Connected {
+		match self {
+			OutboundStream::Direct(s) => s.connected(),
+			_ => Connected::new(),
+		}
+	}
+}
+
+/// Hyper connector that applies the outbound HTTP settings of the node
+#[derive(Clone)]
+pub struct OutboundConnector {
+	https: HttpsConnector<HttpConnector>,
+}
+
+impl OutboundConnector {
+	/// New connector, the settings are applied to every connection
+	pub fn new() -> OutboundConnector {
+		OutboundConnector {
+			https: HttpsConnector::new(),
+		}
+	}
+}
+
+impl Default for OutboundConnector {
+	fn default() -> OutboundConnector {
+		OutboundConnector::new()
+	}
+}
+
+impl Service<Uri> for OutboundConnector {
+	type Response = OutboundStream;
+	type Error = io::Error;
+	type Future = Pin<Box<dyn Future<Output = Result<OutboundStream, io::Error>> + Send>>;
+
+	fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn call(&mut self, uri: Uri) -> Self::Future {
+		let https = self.https.clone();
+		Box::pin(connect(outbound_http(), https, uri))
+	}
+}
+
+async fn connect(
+	config: OutboundHttpConfig,
+	mut https: HttpsConnector<HttpConnector>,
+	uri: Uri,
+) -> Result<OutboundStream, io::Error> {
+	let host = uri
+		.host()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URI without a host"))?
+		.trim_start_matches('[')
+		.trim_end_matches(']')
+		.to_string();
+	let is_https = uri.scheme_str() == Some("https");
+	let port = uri.port_u16().unwrap_or(if is_https { 443 } else { 80 });
+
+	let proxy = match &config.proxy {
+		Some(proxy) if !is_local_host(&host) => proxy,
+		_ => {
+			if !is_local_host(&host) && (config.onion_only || host.ends_with(".onion")) {
+				return Err(io::Error::new(
+					io::ErrorKind::PermissionDenied,
+					format!(
+						"Connection to {} is refused, the onion only mode requires a proxy",
+						host
+					),
+				));
+			}
+			return https
+				.call(uri)
+				.await
+				.map(OutboundStream::Direct)
+				.map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+		}
+	};
+
+	let stream = match proxy {
+		OutboundProxy::Socks5(addr) => Socks5Stream::connect(addr.as_str(), (host.as_str(), port))
+			.await
+			.map_err(|e| {
+				io::Error::new(
+					io::ErrorKind::Other,
+					format!("SOCKS5 proxy {} failed to connect {}, {}", addr, host, e),
+				)
+			})?
+			.into_inner(),
+		OutboundProxy::Http(addr) => http_connect(addr, &host, port).await?,
+	};
+
+	if is_https {
+		let dns_name = DNSNameRef::try_from_ascii_str(&host).map_err(|e| {
+			io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("Invalid TLS host name {}, {}", host, e),
+			)
+		})?;
+		let tls = TlsConnector::from(PROXY_TLS_CONFIG.clone())
+			.connect(dns_name, stream)
+			.await?;
+		Ok(OutboundStream::ProxiedTls(Box::new(tls)))
+	} else {
+		Ok(OutboundStream::Proxied(stream))
+	}
+}
+
+// Open the tunnel through the HTTP proxy
+async fn http_connect(proxy: &str, host: &str, port: u16) -> Result<TcpStream, io::Error> {
+	let target = if host.contains(':') {
+		format!("[{}]:{}", host, port)
+	} else {
+		format!("{}:{}", host, port)
+	};
+	let mut stream = TcpStream::connect(proxy).await?;
+	stream
+		.write_all(format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, target).as_bytes())
+		.await?;
+
+	// Reading byte by byte, the tunnel data must stay in the stream
+	let mut response = Vec::new();
+	let mut byte = [0u8; 1];
+	while !response.ends_with(b"\r\n\r\n") {
+		if response.len() >= MAX_CONNECT_RESPONSE || stream.read(&mut byte).await? == 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("HTTP proxy {} sent an invalid CONNECT response", proxy),
+			));
+		}
+		response.push(byte[0]);
+	}
+	let response = String::from_utf8_lossy(&response);
+	let status = response
+		.lines()
+		.next()
+		.and_then(|line| line.split_whitespace().nth(1))
+		.unwrap_or("");
+	if status != "200" {
+		return Err(io::Error::new(
+			io::ErrorKind::Other,
+			format!(
+				"HTTP proxy {} refused to connect {}, status {}",
+				proxy, target, status
+			),
+		));
+	}
+	Ok(stream)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use tokio::runtime::Builder;
+
+	#[test]
+	fn outbound_proxy_settings() {
+		assert_eq!(
+			OutboundProxy::parse("socks5://127.0.0.1:51234").unwrap(),
+			OutboundProxy::Socks5("127.0.0.1:51234".to_string())
+		);
+		assert_eq!(
+			OutboundProxy::parse("socks5h://proxy.local:1080/").unwrap(),
+			OutboundProxy::Socks5("proxy.local:1080".to_string())
+		);
+		assert_eq!(
+			OutboundProxy::parse("http://10.0.0.1:3128").unwrap(),
+			OutboundProxy::Http("10.0.0.1:3128".to_string())
+		);
+		assert!(OutboundProxy::parse("127.0.0.1:1080").is_err());
+		assert!(OutboundProxy::parse("ftp://127.0.0.1:21").is_err());
+		assert!(OutboundProxy::parse("socks5://").is_err());
+
+		assert!(is_local_host("localhost"));
+		assert!(is_local_host("127.0.0.1"));
+		assert!(is_local_host("[::1]"));
+		assert!(!is_local_host("example.com"));
+		assert!(!is_local_host("10.0.0.1"));
+
+		// Onion only mode without a proxy never connects directly
+		let mut rt = Builder::new()
+			.basic_scheduler()
+			.enable_all()
+			.build()
+			.unwrap();
+		let config = OutboundHttpConfig {
+			proxy: None,
+			onion_only: true,
+		};
+		let res = rt.block_on(connect(
+			config,
+			HttpsConnector::new(),
+			"http://example.com/hook".parse().unwrap(),
+		));
+		assert_eq!(
+			res.err().map(|e| e.kind()),
+			Some(io::ErrorKind::PermissionDenied)
+		);
+	}
+}
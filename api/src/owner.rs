@@ -14,20 +14,38 @@
 
 //! Owner API External Definition
 
-use crate::chain::{Chain, SyncState};
-use crate::handlers::chain_api::{ChainCompactHandler, ChainValidationHandler};
+use crate::chain::{Chain, CompactionStats, MetricsSnapshot, SyncState, UptimeRecord};
+use crate::compression::{api_compression_stats, ApiCompressionStats};
+use crate::handlers::chain_api::{ChainCompactHandler, ChainValidationHandler, ReorgGuardHandler};
 use crate::handlers::peers_api::{PeerHandler, PeersConnectedHandler};
-use crate::handlers::server_api::StatusHandler;
-use crate::p2p::{self, PeerData};
+use crate::handlers::pool_api::DandelionControl;
+use crate::handlers::server_api::{
+	BlockProfileHandler, GossipAuditHandler, GossipProbeHandler, IntegrityBudgetHandler,
+	MetricsHistoryHandler, StatusHandler, StratumAuditHandler, SubsystemUsageHandler,
+	UptimeHistoryHandler,
+};
+use crate::p2p::{
+	self, GossipAuditQuery, GossipProbeStats, Libp2pConnection, Libp2pPeerAccess, Libp2pPeerBan,
+	Libp2pStats, PeerData, PeerSnapshot, PeerSnapshotImport, PeerSyncData, QuarantinedPeer,
+	VersionCensusReport,
+};
 use crate::rest::*;
-use crate::types::Status;
+use crate::standby::{self, StandbyStatus};
+use crate::stratum_audit::StratumAuditQuery;
+use crate::types::{
+	BlockTimingsPrintable, DandelionStatus, GossipAudit, IntegrityBudget, ReorgGuard, Status,
+	StratumAudit, TorPowDefenseStatus,
+};
+use chrono::Utc;
 use grin_p2p::types::PeerInfoDisplayLegacy;
+use grin_util::subsystem::SubsystemUsage;
 use grin_util::Mutex;
 use std::net::SocketAddr;
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 
 lazy_static! {
 	static ref SERVER_ONION_ADDRESS: Mutex<Option<String>> = Mutex::new(None);
+	static ref TOR_POW_DEFENSE: Mutex<Option<TorPowDefenseStatus>> = Mutex::new(None);
 }
 
 pub fn get_server_onion_address() -> Option<String> {
@@ -43,6 +61,14 @@ pub fn reset_server_onion_address() {
 	SERVER_ONION_ADDRESS.lock().take();
 }
 
+pub fn get_tor_pow_defense() -> Option<TorPowDefenseStatus> {
+	TOR_POW_DEFENSE.lock().clone()
+}
+
+pub fn set_tor_pow_defense(status: TorPowDefenseStatus) {
+	TOR_POW_DEFENSE.lock().replace(status);
+}
+
 /// Main interface into all node API functions.
 /// Node APIs are split into two seperate blocks of functionality
 /// called the ['Owner'](struct.Owner.html) and ['Foreign'](struct.Foreign.html) APIs
@@ -54,6 +80,7 @@ pub struct Owner {
 	pub chain: Weak<Chain>,
 	pub peers: Weak<p2p::Peers>,
 	pub sync_state: Weak<SyncState>,
+	pub dandelion: Option<Arc<dyn DandelionControl>>,
 }
 
 impl Owner {
@@ -65,16 +92,23 @@ impl Owner {
 	/// * `tx_pool` - A non-owning reference of the transaction pool.
 	/// * `peers` - A non-owning reference of the peers.
 	/// * `sync_state` - A non-owning reference of the `sync_state`.
+	/// * `dandelion` - Dandelion state of the transaction pool, None if not available.
 	///
 	/// # Returns
 	/// * An instance of the Node holding references to the current chain, transaction pool, peers and sync_state.
 	///
 
-	pub fn new(chain: Weak<Chain>, peers: Weak<p2p::Peers>, sync_state: Weak<SyncState>) -> Self {
+	pub fn new(
+		chain: Weak<Chain>,
+		peers: Weak<p2p::Peers>,
+		sync_state: Weak<SyncState>,
+		dandelion: Option<Arc<dyn DandelionControl>>,
+	) -> Self {
 		Owner {
 			chain,
 			peers,
 			sync_state,
+			dandelion,
 		}
 	}
 
@@ -114,20 +148,57 @@ impl Owner {
 	}
 
 	/// Trigger a compaction of the chain state to regain storage space.
+	/// Blocks and headers of the abandoned forks older than the horizon are removed as well.
 	///
 	/// # Returns
 	/// * Result Containing:
-	/// * `Ok(())` if the compaction was done successfully
+	/// * A [`CompactionStats`](types/struct.CompactionStats.html) with the number of removed fork blocks and headers
 	/// * or [`Error`](struct.Error.html) if an error is encountered.
 	///
 
-	pub fn compact_chain(&self) -> Result<(), Error> {
+	pub fn compact_chain(&self) -> Result<CompactionStats, Error> {
 		let chain_compact_handler = ChainCompactHandler {
 			chain: self.chain.clone(),
 		};
 		chain_compact_handler.compact_chain()
 	}
 
+	/// Retrieves the state of the anti deep reorg rule: the max reorg depth from the config
+	/// (`max_reorg_depth`) and the operator override.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`ReorgGuard`](types/struct.ReorgGuard.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_reorg_guard(&self) -> Result<ReorgGuard, Error> {
+		let reorg_guard_handler = ReorgGuardHandler {
+			chain: self.chain.clone(),
+		};
+		reorg_guard_handler.get_reorg_guard()
+	}
+
+	/// Operator override of the anti deep reorg rule. Reorgs deeper than `max_reorg_depth`
+	/// are accepted for the provided time. Use it when the node rejected the deep reorg
+	/// and the fork is verified to be legit.
+	///
+	/// # Arguments
+	/// * `duration_secs` - how long the deep reorgs are accepted, 0 cancels the override.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`ReorgGuard`](types/struct.ReorgGuard.html) with the updated state
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn allow_deep_reorgs(&self, duration_secs: u64) -> Result<ReorgGuard, Error> {
+		let reorg_guard_handler = ReorgGuardHandler {
+			chain: self.chain.clone(),
+		};
+		reorg_guard_handler.allow_deep_reorgs(duration_secs)
+	}
+
 	/// Retrieves information about stored peers.
 	/// If `None` is provided, will list all stored peers.
 	///
@@ -197,4 +268,545 @@ impl Owner {
 		};
 		peer_handler.unban_peer(addr)
 	}
+
+	/// Forces the sync to use the connected peer as the download source for the next
+	/// header and body sync cycle. Useful to debug the node that follows a stale tip.
+	///
+	/// # Arguments
+	/// * `addr` - the ip:port or the onion address of the connected peer.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the peer was accepted as the sync source
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn sync_from_peer(&self, addr: String) -> Result<(), Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.sync_from_peer(addr)
+	}
+
+	/// Retrieves the explicit libp2p access rules for the onion peers.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`Libp2pPeerAccess`](types/struct.Libp2pPeerAccess.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_libp2p_peer_access(&self) -> Result<Vec<Libp2pPeerAccess>, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.get_libp2p_peer_access()
+	}
+
+	/// Allows or denies libp2p dialing and inbound connections for the onion address.
+	/// Rule is persistent and applied to the running libp2p node. If any peer is allowed,
+	/// only allowed peers are dialed and accepted.
+	///
+	/// # Arguments
+	/// * `address` - the onion address of the peer, '.onion' suffix is optional.
+	/// * `allowed` - true to allow the peer, false to deny it.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the rule was saved
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn set_libp2p_peer_access(&self, address: String, allowed: bool) -> Result<(), Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.set_libp2p_peer_access(address, allowed)
+	}
+
+	/// Removes the libp2p access rule for the onion address.
+	///
+	/// # Arguments
+	/// * `address` - the onion address of the peer.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the rule was removed
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn remove_libp2p_peer_access(&self, address: String) -> Result<(), Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.remove_libp2p_peer_access(address)
+	}
+
+	/// Retrieves the active bans of the libp2p peers. Bans are made manually or by the node
+	/// for the invalid gossip, they survive the node restart.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`Libp2pPeerBan`](types/struct.Libp2pPeerBan.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_libp2p_bans(&self) -> Result<Vec<Libp2pPeerBan>, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.get_libp2p_bans()
+	}
+
+	/// Bans the libp2p peer by the onion address. The peer is disconnected, it is not dialed
+	/// or accepted until the ban expires. Ban is persistent.
+	///
+	/// # Arguments
+	/// * `address` - the onion address of the peer, '.onion' suffix is optional.
+	/// * `duration_secs` - ban duration in seconds. If not set, the p2p ban window is used,
+	/// zero bans the peer permanently.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * The saved [`Libp2pPeerBan`](types/struct.Libp2pPeerBan.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn ban_libp2p_peer(
+		&self,
+		address: String,
+		duration_secs: Option<i64>,
+	) -> Result<Libp2pPeerBan, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.ban_libp2p_peer(address, duration_secs)
+	}
+
+	/// Unbans the libp2p peer by the onion address.
+	///
+	/// # Arguments
+	/// * `address` - the onion address of the peer.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the peer was unbanned
+	/// * or [`Error`](struct.Error.html) if the peer is not banned or an error is encountered.
+	///
+
+	pub fn unban_libp2p_peer(&self, address: String) -> Result<(), Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.unban_libp2p_peer(address)
+	}
+
+	/// Retrieves the libp2p peers that are quarantined because they originated the invalid
+	/// gossip messages. Quarantined peers are not dialed or accepted, their messages are
+	/// not relayed. The period is set with `libp2p_quarantine_secs` config.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`QuarantinedPeer`](../grin_p2p/peer_quarantine/struct.QuarantinedPeer.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_quarantined_peers(&self) -> Result<Vec<QuarantinedPeer>, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.get_quarantined_peers()
+	}
+
+	/// Releases the quarantined libp2p peer before its quarantine expires.
+	///
+	/// # Arguments
+	/// * `address` - the onion address of the peer.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the peer was released
+	/// * or [`Error`](struct.Error.html) if the peer is not quarantined.
+	///
+
+	pub fn release_quarantined_peer(&self, address: String) -> Result<(), Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.release_quarantined_peer(address)
+	}
+
+	/// Retrieves the number of bytes the header, block and TxHashSet sync stages accepted
+	/// from every peer since the start. A peer that fed more than the stage cap
+	/// (`sync_headers_cap_mb`, `sync_blocks_cap_mb`, `sync_txhashset_cap_mb` config) is
+	/// not used as the source of that stage while the other peers are below the cap.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`PeerSyncData`](../grin_p2p/sync_data/struct.PeerSyncData.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_sync_data(&self) -> Result<Vec<PeerSyncData>, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.get_sync_data()
+	}
+
+	/// Exports the known peers (address, capabilities, last connection time, ban state)
+	/// as a snapshot signed with the node snapshot key. A fresh node can be bootstrapped
+	/// from it with `import_peer_snapshot`.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`PeerSnapshot`](types/struct.PeerSnapshot.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn export_peer_snapshot(&self) -> Result<PeerSnapshot, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.export_peer_snapshot()
+	}
+
+	/// Imports the peers from the snapshot exported by another node. The peers that are
+	/// already known are not changed.
+	///
+	/// # Arguments
+	/// * `snapshot` - the snapshot returned by `export_peer_snapshot`.
+	/// * `trusted_key` - if set, the snapshot must be signed with this public key (hex).
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`PeerSnapshotImport`](types/struct.PeerSnapshotImport.html)
+	/// * or [`Error`](struct.Error.html) if the snapshot is not valid.
+	///
+
+	pub fn import_peer_snapshot(
+		&self,
+		snapshot: PeerSnapshot,
+		trusted_key: Option<String>,
+	) -> Result<PeerSnapshotImport, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.import_peer_snapshot(snapshot, trusted_key)
+	}
+
+	/// Retrieves the census of the peers seen in the last 24 hours: peers by user agent
+	/// and by protocol version, with the hourly trend samples for the last 30 days.
+	/// The census is collected from the handshakes and is not persistent.
+	///
+	/// # Arguments
+	/// * `upgrade_target` - if set, the node version like "5.0.0". The peers that run this
+	/// version or newer are reported as upgraded.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`VersionCensusReport`](types/struct.VersionCensusReport.html)
+	/// * or [`Error`](struct.Error.html) if the upgrade target is not valid.
+	///
+
+	pub fn get_peer_version_census(
+		&self,
+		upgrade_target: Option<String>,
+	) -> Result<VersionCensusReport, Error> {
+		let peer_handler = PeerHandler {
+			peers: self.peers.clone(),
+		};
+		peer_handler.get_version_census(upgrade_target)
+	}
+
+	/// Registers the integrity kernel the node publishes its own gossip messages with, so
+	/// its validity window and rate budget are tracked. Registration is not persistent.
+	///
+	/// # Arguments
+	/// * `excess` - the kernel excess commitment, hex.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the kernel was registered
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn register_integrity_kernel(&self, excess: String) -> Result<(), Error> {
+		let integrity_handler = IntegrityBudgetHandler {
+			chain: self.chain.clone(),
+		};
+		integrity_handler.register_integrity_kernel(excess)
+	}
+
+	/// Stops tracking the integrity kernel.
+	///
+	/// # Arguments
+	/// * `excess` - the kernel excess commitment, hex.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * `Ok(())` if the kernel was removed
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn remove_integrity_kernel(&self, excess: String) -> Result<(), Error> {
+		let integrity_handler = IntegrityBudgetHandler {
+			chain: self.chain.clone(),
+		};
+		integrity_handler.remove_integrity_kernel(excess)
+	}
+
+	/// Retrieves the budget of the registered integrity kernels: blocks left in the
+	/// validity window, published messages, messages that can be published now without
+	/// hitting the peers rate limit, and the alerts when the wallet should top up.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * An [`IntegrityBudget`](types/struct.IntegrityBudget.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_integrity_budget(&self) -> Result<IntegrityBudget, Error> {
+		let integrity_handler = IntegrityBudgetHandler {
+			chain: self.chain.clone(),
+		};
+		integrity_handler.get_integrity_budget()
+	}
+
+	/// Retrieves the audit log records of the accepted gossip messages. The audit is
+	/// enabled for the topics from the `libp2p_audit_topics` config.
+	///
+	/// # Arguments
+	/// * `query` - topic, sender, payload hash and time range filters, all optional.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`GossipAudit`](types/struct.GossipAudit.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_gossip_audit(&self, query: GossipAuditQuery) -> Result<GossipAudit, Error> {
+		let audit_handler = GossipAuditHandler {};
+		audit_handler.get_gossip_audit(query)
+	}
+
+	/// Retrieves the gossip mesh latency measured by the probes. The probes are enabled
+	/// with `libp2p_probe_interval_secs` config, the round trip time of the echoes from
+	/// the cooperating nodes is aggregated for the direct neighbours and the relayed paths.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`GossipProbeStats`](../grin_p2p/gossip_probe/struct.GossipProbeStats.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_gossip_probe_stats(&self) -> Result<GossipProbeStats, Error> {
+		let probe_handler = GossipProbeHandler {};
+		probe_handler.get_gossip_probe_stats()
+	}
+
+	/// Retrieves the response compression statistics of the API server: number of the
+	/// gzip and brotli compressed responses and the bytes saved. The compression is
+	/// configured with `enable_compression` and `compression_min_size` config.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`ApiCompressionStats`](struct.ApiCompressionStats.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_api_compression_stats(&self) -> Result<ApiCompressionStats, Error> {
+		Ok(api_compression_stats())
+	}
+
+	/// Retrieves the libp2p gossip traffic counters: received messages and their
+	/// validation results per topic, bytes per peer and the integrity fees of the
+	/// accepted messages.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`Libp2pStats`](../grin_p2p/libp2p_stats/struct.Libp2pStats.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_libp2p_stats(&self) -> Result<Libp2pStats, Error> {
+		Ok(p2p::get_libp2p_stats())
+	}
+
+	/// Retrieves the established libp2p connections: the onion address of every peer,
+	/// the connection age, the gossip topics the peer is subscribed to and the traffic
+	/// counters. Connections are sampled every few seconds, the list is empty if the
+	/// libp2p node is not running.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`Libp2pConnection`](../grin_p2p/libp2p_stats/struct.Libp2pConnection.html), the oldest connection first
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_libp2p_peers(&self) -> Result<Vec<Libp2pConnection>, Error> {
+		Ok(p2p::get_libp2p_connections_info(Utc::now().timestamp()))
+	}
+
+	/// Retrieves the stratum jobs and the submitted shares with their validation results.
+	/// The log is enabled with the stratum `share_audit` config. Jobs have the pre-pow
+	/// data, so the disputed shares can be validated again.
+	///
+	/// # Arguments
+	/// * `query` - height, job id, worker login and time range filters, all optional.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`StratumAudit`](types/struct.StratumAudit.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_stratum_audit(&self, query: StratumAuditQuery) -> Result<StratumAudit, Error> {
+		let audit_handler = StratumAuditHandler {};
+		audit_handler.get_stratum_audit(query)
+	}
+
+	/// Retrieves the node uptime history: start and stop time of every node run,
+	/// sync completion time and the time spent in every sync stage.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`UptimeRecord`](types/struct.UptimeRecord.html) ordered by the start time
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_uptime_history(&self) -> Result<Vec<UptimeRecord>, Error> {
+		let uptime_handler = UptimeHistoryHandler {
+			chain: self.chain.clone(),
+		};
+		uptime_handler.get_uptime_history()
+	}
+
+	/// Retrieves the persisted snapshots of the key node metrics: peer counts, tip height,
+	/// mempool size and block validation timings. Snapshots are taken periodically, see
+	/// `metrics_history_config`, and are kept even if the node wasn't monitored externally.
+	///
+	/// # Arguments
+	/// * `from` - unix timestamp of the first snapshot, the oldest stored snapshot if None.
+	/// * `to` - unix timestamp of the last snapshot, the most recent snapshot if None.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`MetricsSnapshot`](types/struct.MetricsSnapshot.html) ordered by the snapshot time
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_metrics_history(
+		&self,
+		from: Option<i64>,
+		to: Option<i64>,
+	) -> Result<Vec<MetricsSnapshot>, Error> {
+		let metrics_handler = MetricsHistoryHandler {
+			chain: self.chain.clone(),
+		};
+		metrics_handler.get_metrics_history(from, to)
+	}
+
+	/// Debug API. Retrieves the processing time breakdown (deserialization, contextual checks,
+	/// rangeproofs and kernel signatures batch verification, MMR apply, db commit)
+	/// for the last accepted blocks. Node keeps the timings for the last 100 blocks in memory.
+	///
+	/// # Arguments
+	/// * `count` - number of the blocks to return, all retained blocks if None.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`BlockTimingsPrintable`](types/struct.BlockTimingsPrintable.html), most recent block first
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_block_timings(
+		&self,
+		count: Option<u32>,
+	) -> Result<Vec<BlockTimingsPrintable>, Error> {
+		let profile_handler = BlockProfileHandler {};
+		profile_handler.get_block_timings(count)
+	}
+
+	/// Debug API. Retrieves the running threads, async tasks and the estimated memory
+	/// of the node subsystems (sync, pool, api, stratum, libp2p, tor). Helps to find
+	/// the component that is responsible for the RSS growth.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A vector of [`SubsystemUsage`](../grin_util/subsystem/struct.SubsystemUsage.html), sorted by name
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_subsystem_usage(&self) -> Result<Vec<SubsystemUsage>, Error> {
+		let usage_handler = SubsystemUsageHandler {};
+		usage_handler.get_subsystem_usage()
+	}
+
+	/// Retrieves the Dandelion state of the node: the current epoch with the stem relay
+	/// peer and the transactions waiting in the stempool with their embargo expiry.
+	///
+	/// # Arguments
+	/// * `redact_relay` - hide the relay peer address, false by default.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`DandelionStatus`](types/struct.DandelionStatus.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_dandelion_status(
+		&self,
+		redact_relay: Option<bool>,
+	) -> Result<DandelionStatus, Error> {
+		self.dandelion_control()?
+			.get_dandelion_status(redact_relay.unwrap_or(false))
+	}
+
+	/// Fluffs all the transactions from the stempool right away, for example before
+	/// the node shutdown. The transactions are aggregated and broadcasted to the peers.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * Number of the fluffed transactions
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn fluff_all(&self) -> Result<usize, Error> {
+		self.dandelion_control()?.fluff_all()
+	}
+
+	/// Retrieves the role of the node. The standby node syncs and validates the chain,
+	/// but doesn't serve the miners, the transaction pushes and the webhooks until it is
+	/// promoted.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`StandbyStatus`](struct.StandbyStatus.html)
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn get_standby_status(&self) -> Result<StandbyStatus, Error> {
+		Ok(standby::standby_status())
+	}
+
+	/// Promotes the standby node to the primary. The stratum server starts, the transaction
+	/// pushes are accepted and the webhooks are emitted within a second. Promotion of the
+	/// primary node does nothing.
+	///
+	/// # Returns
+	/// * Result Containing:
+	/// * A [`StandbyStatus`](struct.StandbyStatus.html) after the promotion
+	/// * or [`Error`](struct.Error.html) if an error is encountered.
+	///
+
+	pub fn promote_to_primary(&self) -> Result<StandbyStatus, Error> {
+		Ok(standby::promote_to_primary("owner_api"))
+	}
+
+	fn dandelion_control(&self) -> Result<&Arc<dyn DandelionControl>, Error> {
+		self.dandelion.as_ref().ok_or_else(|| {
+			ErrorKind::Internal("Transaction pool is not available".to_string()).into()
+		})
+	}
 }
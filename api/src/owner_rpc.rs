@@ -14,10 +14,22 @@
 
 //! JSON-RPC Stub generation for the Owner API
 
+use crate::chain::{CompactionStats, MetricsSnapshot, UptimeRecord};
+use crate::compression::ApiCompressionStats;
 use crate::owner::Owner;
-use crate::p2p::PeerData;
+use crate::p2p::{
+	GossipAuditQuery, GossipProbeStats, Libp2pConnection, Libp2pPeerAccess, Libp2pPeerBan,
+	Libp2pStats, PeerData, PeerSnapshot, PeerSnapshotImport, PeerSyncData, QuarantinedPeer,
+	VersionCensusReport,
+};
 use crate::rest::ErrorKind;
-use crate::types::Status;
+use crate::standby::StandbyStatus;
+use crate::stratum_audit::StratumAuditQuery;
+use crate::types::{
+	BlockTimingsPrintable, DandelionStatus, GossipAudit, IntegrityBudget, ReorgGuard, Status,
+	StratumAudit,
+};
+use crate::util::subsystem::SubsystemUsage;
 use grin_p2p::types::PeerInfoDisplayLegacy;
 use std::net::SocketAddr;
 
@@ -123,14 +135,83 @@ pub trait OwnerRpc: Sync + Send {
 		"id": 1,
 		"jsonrpc": "2.0",
 		"result": {
-			"Ok": null
+			"Ok": {
+				"fork_blocks": 2,
+				"fork_headers": 2
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn compact_chain(&self) -> Result<CompactionStats, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_reorg_guard](struct.Owner.html#method.get_reorg_guard).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_reorg_guard",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"max_reorg_depth": 500,
+				"deep_reorgs_allowed_until": null
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_reorg_guard(&self) -> Result<ReorgGuard, ErrorKind>;
+
+	/**
+	Networked version of [Owner::allow_deep_reorgs](struct.Owner.html#method.allow_deep_reorgs).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "allow_deep_reorgs",
+		"params": [3600],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"max_reorg_depth": 500,
+				"deep_reorgs_allowed_until": 1602198000
+			}
 		}
 	}
 	# "#
 	# );
 	```
 	 */
-	fn compact_chain(&self) -> Result<(), ErrorKind>;
+	fn allow_deep_reorgs(&self, duration_secs: u64) -> Result<ReorgGuard, ErrorKind>;
 
 	/**
 	Networked version of [Owner::get_peers](struct.Owner.html#method.get_peers).
@@ -352,35 +433,1441 @@ pub trait OwnerRpc: Sync + Send {
 	```
 	 */
 	fn unban_peer(&self, peer_addr: SocketAddr) -> Result<(), ErrorKind>;
-}
 
-impl OwnerRpc for Owner {
-	fn get_status(&self) -> Result<Status, ErrorKind> {
-		Owner::get_status(self).map_err(|e| e.kind().clone())
+	/**
+	Networked version of [Owner::sync_from_peer](struct.Owner.html#method.sync_from_peer).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "sync_from_peer",
+		"params": ["70.50.33.130:3414"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
 	}
+	# "#
+	# );
+	```
+	 */
+	fn sync_from_peer(&self, peer_addr: String) -> Result<(), ErrorKind>;
 
-	fn validate_chain(&self, assume_valid_rangeproofs_kernels: bool) -> Result<(), ErrorKind> {
-		Owner::validate_chain(self, assume_valid_rangeproofs_kernels).map_err(|e| e.kind().clone())
+	/**
+	Networked version of [Owner::get_libp2p_peer_access](struct.Owner.html#method.get_libp2p_peer_access).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_libp2p_peer_access",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"address": "2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid",
+					"allowed": true
+				},
+				{
+					"address": "xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad",
+					"allowed": false
+				}
+			]
+		}
 	}
+	# "#
+	# );
+	```
+	 */
+	fn get_libp2p_peer_access(&self) -> Result<Vec<Libp2pPeerAccess>, ErrorKind>;
 
-	fn compact_chain(&self) -> Result<(), ErrorKind> {
-		Owner::compact_chain(self).map_err(|e| e.kind().clone())
+	/**
+	Networked version of [Owner::set_libp2p_peer_access](struct.Owner.html#method.set_libp2p_peer_access).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "set_libp2p_peer_access",
+		"params": ["xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad.onion", false],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
 	}
+	# "#
+	# );
+	```
+	 */
+	fn set_libp2p_peer_access(&self, address: String, allowed: bool) -> Result<(), ErrorKind>;
 
-	fn get_peers(&self, addr: Option<SocketAddr>) -> Result<Vec<PeerData>, ErrorKind> {
-		Owner::get_peers(self, addr).map_err(|e| e.kind().clone())
+	/**
+	Networked version of [Owner::remove_libp2p_peer_access](struct.Owner.html#method.remove_libp2p_peer_access).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "remove_libp2p_peer_access",
+		"params": ["xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
 	}
+	# "#
+	# );
+	```
+	 */
+	fn remove_libp2p_peer_access(&self, address: String) -> Result<(), ErrorKind>;
 
-	fn get_connected_peers(&self) -> Result<Vec<PeerInfoDisplayLegacy>, ErrorKind> {
-		Owner::get_connected_peers(self).map_err(|e| e.kind().clone())
+	/**
+	Networked version of [Owner::get_libp2p_bans](struct.Owner.html#method.get_libp2p_bans).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_libp2p_bans",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"address": "xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad",
+					"reason": "BadGossip",
+					"banned_at": 1633000000,
+					"expires_at": 1633010800
+				},
+				{
+					"address": "2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid",
+					"reason": "ManualBan",
+					"banned_at": 1633005000,
+					"expires_at": null
+				}
+			]
+		}
 	}
+	# "#
+	# );
+	```
+	 */
+	fn get_libp2p_bans(&self) -> Result<Vec<Libp2pPeerBan>, ErrorKind>;
 
-	fn ban_peer(&self, addr: SocketAddr) -> Result<(), ErrorKind> {
-		Owner::ban_peer(self, addr).map_err(|e| e.kind().clone())
+	/**
+	Networked version of [Owner::ban_libp2p_peer](struct.Owner.html#method.ban_libp2p_peer).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "ban_libp2p_peer",
+		"params": ["2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid.onion", 0],
+		"id": 1
 	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"address": "2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid",
+				"reason": "ManualBan",
+				"banned_at": 1633005000,
+				"expires_at": null
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn ban_libp2p_peer(
+		&self,
+		address: String,
+		duration_secs: Option<i64>,
+	) -> Result<Libp2pPeerBan, ErrorKind>;
 
-	fn unban_peer(&self, addr: SocketAddr) -> Result<(), ErrorKind> {
-		Owner::unban_peer(self, addr).map_err(|e| e.kind().clone())
+	/**
+	Networked version of [Owner::unban_libp2p_peer](struct.Owner.html#method.unban_libp2p_peer).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "unban_libp2p_peer",
+		"params": ["2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn unban_libp2p_peer(&self, address: String) -> Result<(), ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_quarantined_peers](struct.Owner.html#method.get_quarantined_peers).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_quarantined_peers",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": []
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_quarantined_peers(&self) -> Result<Vec<QuarantinedPeer>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::release_quarantined_peer](struct.Owner.html#method.release_quarantined_peer).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "release_quarantined_peer",
+		"params": ["xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Err": {
+				"Argument": "Peer xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad is not quarantined"
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn release_quarantined_peer(&self, address: String) -> Result<(), ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_sync_data](struct.Owner.html#method.get_sync_data).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_sync_data",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": []
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_sync_data(&self) -> Result<Vec<PeerSyncData>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::export_peer_snapshot](struct.Owner.html#method.export_peer_snapshot).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "export_peer_snapshot",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"version": 1,
+				"created": 1602054000,
+				"peers": [
+					{
+						"addr": "192.168.0.1:3414",
+						"ban_reason": "None",
+						"capabilities": {
+							"bits": 15
+						},
+						"flags": "Healthy",
+						"last_banned": 0,
+						"last_connected": 1602053400,
+						"user_agent": "MW/MWC 4.4.2"
+					}
+				],
+				"public_key": "02e89cce4499ac1e9bb498dab9e3fab93cc40cd3d26c04a0292e00f4bf272499ec",
+				"signature": "8a8ea1d5ec8e1d4b2d3b60b5f5e4b4e94c8f1b4a0f2b1f64cf9a8d2b8dd5b1c26f9bd70a7e9b4e73a0ba9ff0a3a3f7b5e5c0c1a4d2a5b3c0bd6ad4f0a0b1c2d3"
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn export_peer_snapshot(&self) -> Result<PeerSnapshot, ErrorKind>;
+
+	/**
+	Networked version of [Owner::import_peer_snapshot](struct.Owner.html#method.import_peer_snapshot).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "import_peer_snapshot",
+		"params": [
+			{
+				"version": 1,
+				"created": 1602054000,
+				"peers": [],
+				"public_key": "02e89cce4499ac1e9bb498dab9e3fab93cc40cd3d26c04a0292e00f4bf272499ec",
+				"signature": "8a8ea1d5ec8e1d4b2d3b60b5f5e4b4e94c8f1b4a0f2b1f64cf9a8d2b8dd5b1c26f9bd70a7e9b4e73a0ba9ff0a3a3f7b5e5c0c1a4d2a5b3c0bd6ad4f0a0b1c2d3"
+			},
+			"02e89cce4499ac1e9bb498dab9e3fab93cc40cd3d26c04a0292e00f4bf272499ec"
+		],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"imported": 0,
+				"skipped": 0
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn import_peer_snapshot(
+		&self,
+		snapshot: PeerSnapshot,
+		trusted_key: Option<String>,
+	) -> Result<PeerSnapshotImport, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_peer_version_census](struct.Owner.html#method.get_peer_version_census).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_peer_version_census",
+		"params": ["5.0.0"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"window_secs": 86400,
+				"total_peers": 0,
+				"user_agents": [],
+				"protocol_versions": [],
+				"upgrade_target": "5.0.0",
+				"upgraded_peers": 0,
+				"upgraded_fraction": 0.0,
+				"trend": []
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_peer_version_census(
+		&self,
+		upgrade_target: Option<String>,
+	) -> Result<VersionCensusReport, ErrorKind>;
+
+	/**
+	Networked version of [Owner::register_integrity_kernel](struct.Owner.html#method.register_integrity_kernel).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "register_integrity_kernel",
+		"params": ["08a8f99853d65cee63c973a78a005f4646b777262440a8bfa090694a339a388865"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn register_integrity_kernel(&self, excess: String) -> Result<(), ErrorKind>;
+
+	/**
+	Networked version of [Owner::remove_integrity_kernel](struct.Owner.html#method.remove_integrity_kernel).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "remove_integrity_kernel",
+		"params": ["08a8f99853d65cee63c973a78a005f4646b777262440a8bfa090694a339a388865"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn remove_integrity_kernel(&self, excess: String) -> Result<(), ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_integrity_budget](struct.Owner.html#method.get_integrity_budget).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_integrity_budget",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"tip_height": 661298,
+				"kernels": [
+					{
+						"excess": "08a8f99853d65cee63c973a78a005f4646b777262440a8bfa090694a339a388865",
+						"height": 660021,
+						"fee": 10000000,
+						"blocks_left": 166,
+						"messages_published": 12,
+						"rate_budget": 3,
+						"alerts": []
+					},
+					{
+						"excess": "09c1fd4a1b7e9b4d8e1b0c6a9a6f1f2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d",
+						"height": 659880,
+						"fee": 10000000,
+						"blocks_left": 25,
+						"messages_published": 40,
+						"rate_budget": 0,
+						"alerts": ["ExpiresSoon", "RateLimited"]
+					}
+				],
+				"top_up_needed": false
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_integrity_budget(&self) -> Result<IntegrityBudget, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_gossip_audit](struct.Owner.html#method.get_gossip_audit).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_gossip_audit",
+		"params": [
+			{
+				"topic": "SwapMarketplace",
+				"sender_address": null,
+				"payload_hash": null,
+				"from_time": 1602054000,
+				"to_time": null,
+				"limit": 100
+			}
+		],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"enabled": false,
+				"entries": []
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_gossip_audit(&self, query: GossipAuditQuery) -> Result<GossipAudit, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_gossip_probe_stats](struct.Owner.html#method.get_gossip_probe_stats).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_gossip_probe_stats",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"enabled": false,
+				"interval_secs": 0,
+				"probes_sent": 0,
+				"echoes_received": 0,
+				"responders": 0,
+				"all": null,
+				"direct": null,
+				"relayed": null
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_gossip_probe_stats(&self) -> Result<GossipProbeStats, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_api_compression_stats](struct.Owner.html#method.get_api_compression_stats).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_api_compression_stats",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"gzip_responses": 12,
+				"brotli_responses": 140,
+				"uncompressed_bytes": 18874368,
+				"compressed_bytes": 4194304,
+				"bytes_saved": 14680064
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_api_compression_stats(&self) -> Result<ApiCompressionStats, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_libp2p_stats](struct.Owner.html#method.get_libp2p_stats).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_libp2p_stats",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"since": 1615200000,
+				"messages_received": 3,
+				"bytes_received": 1536,
+				"bytes_sent": 4608,
+				"integrity_messages": 2,
+				"integrity_fees_total": 20000000,
+				"topics": [
+					{
+						"topic": "SwapMarketplace",
+						"messages_received": 3,
+						"accepted": 2,
+						"rejected": 1,
+						"ignored": 0,
+						"bytes_received": 1536,
+						"messages_published": 0,
+						"bytes_sent": 4608,
+						"integrity_fees": 20000000
+					}
+				],
+				"peers": [
+					{
+						"peer_id": "xe1kxs1WTnP1QTv4oNkFjYKm9L1JwUmh1MQzYkN6k2Qs5Pm",
+						"messages_received": 3,
+						"bytes_received": 1536,
+						"rejected": 1,
+						"messages_sent": 0,
+						"bytes_sent": 0,
+						"last_activity": 1615200060
+					}
+				],
+				"dials": {
+					"attempts": 5,
+					"succeeded": 3,
+					"proxy_down": 0,
+					"target_unreachable": 1,
+					"timeouts": 1,
+					"cancelled": 0,
+					"busy": 0,
+					"peers_in_backoff": 2,
+					"peers_in_cooldown": 0,
+					"proxy_down_until": null
+				},
+				"kademlia": {
+					"enabled": true,
+					"routing_table_peers": 14,
+					"provided_topics": 2,
+					"queries": 6,
+					"failed_queries": 1,
+					"discovered_peers": 23
+				}
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_libp2p_stats(&self) -> Result<Libp2pStats, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_libp2p_peers](struct.Owner.html#method.get_libp2p_peers).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_libp2p_peers",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"peer_id": "xe1kxs1WTnP1QTv4oNkFjYKm9L1JwUmh1MQzYkN6k2Qs5Pm",
+					"onion_address": "fzgcznfj5t6jzxbmgc6jsdrzfrcqbvkjn5ejz6zsvmbnbzoyeuvqapad",
+					"connected_since": 1615199400,
+					"age_secs": 660,
+					"topics": [
+						"SwapMarketplace",
+						"testing"
+					],
+					"messages_received": 3,
+					"bytes_received": 1536,
+					"rejected": 1,
+					"messages_sent": 0,
+					"bytes_sent": 0
+				}
+			]
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_libp2p_peers(&self) -> Result<Vec<Libp2pConnection>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_stratum_audit](struct.Owner.html#method.get_stratum_audit).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_stratum_audit",
+		"params": [
+			{
+				"height": 1000,
+				"job_id": 2,
+				"login": "pool_worker1",
+				"from_time": null,
+				"to_time": null,
+				"limit": 100
+			}
+		],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"enabled": false,
+				"jobs": [],
+				"shares": []
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_stratum_audit(&self, query: StratumAuditQuery) -> Result<StratumAudit, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_uptime_history](struct.Owner.html#method.get_uptime_history).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_uptime_history",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"start_time": 1602108000,
+					"stop_time": 1602194400,
+					"sync_done_time": 1602111634,
+					"sync_stages": [
+						{
+							"stage": "header_sync",
+							"start_time": 1602108031,
+							"duration": 1320
+						},
+						{
+							"stage": "txhashset",
+							"start_time": 1602109351,
+							"duration": 1845
+						},
+						{
+							"stage": "body_sync",
+							"start_time": 1602111196,
+							"duration": 438
+						}
+					]
+				}
+			]
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_uptime_history(&self) -> Result<Vec<UptimeRecord>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_metrics_history](struct.Owner.html#method.get_metrics_history).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_metrics_history",
+		"params": [1602108000, 1602108120],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"timestamp": 1602108060,
+					"height": 374274,
+					"header_height": 374274,
+					"peers": 12,
+					"inbound_peers": 4,
+					"outbound_peers": 8,
+					"txpool_size": 3,
+					"stempool_size": 0,
+					"blocks_validated": 1,
+					"avg_block_validation_us": 18250,
+					"max_block_validation_us": 18250
+				},
+				{
+					"timestamp": 1602108120,
+					"height": 374275,
+					"header_height": 374275,
+					"peers": 12,
+					"inbound_peers": 4,
+					"outbound_peers": 8,
+					"txpool_size": 1,
+					"stempool_size": 0,
+					"blocks_validated": 1,
+					"avg_block_validation_us": 20410,
+					"max_block_validation_us": 20410
+				}
+			]
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_metrics_history(
+		&self,
+		from: Option<i64>,
+		to: Option<i64>,
+	) -> Result<Vec<MetricsSnapshot>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_block_timings](struct.Owner.html#method.get_block_timings).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_block_timings",
+		"params": [1],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"hash": "0000d4b7f1b6a59cc2e34e4e6fb8e1c5a6b2d7e3e8f0e1d2c3b4a5968778695a",
+					"height": 661298,
+					"timestamp": 1602194400,
+					"inputs": 2,
+					"outputs": 3,
+					"kernels": 2,
+					"deserialize": 412,
+					"contextual_checks": 3870,
+					"rangeproof_batch": 9514,
+					"kernel_sig_batch": 702,
+					"mmr_apply": 2261,
+					"db_commit": 1315,
+					"total": 18244
+				}
+			]
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_block_timings(
+		&self,
+		count: Option<u32>,
+	) -> Result<Vec<BlockTimingsPrintable>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_subsystem_usage](struct.Owner.html#method.get_subsystem_usage).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_subsystem_usage",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": [
+				{
+					"name": "api",
+					"threads": 2,
+					"tasks": 0,
+					"memory_bytes": 0
+				},
+				{
+					"name": "libp2p",
+					"threads": 1,
+					"tasks": 14,
+					"memory_bytes": 1872000
+				},
+				{
+					"name": "pool",
+					"threads": 1,
+					"tasks": 0,
+					"memory_bytes": 24716
+				},
+				{
+					"name": "stratum",
+					"threads": 0,
+					"tasks": 0,
+					"memory_bytes": 0
+				},
+				{
+					"name": "sync",
+					"threads": 1,
+					"tasks": 0,
+					"memory_bytes": 0
+				},
+				{
+					"name": "tor",
+					"threads": 1,
+					"tasks": 0,
+					"memory_bytes": 0
+				}
+			]
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_subsystem_usage(&self) -> Result<Vec<SubsystemUsage>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_dandelion_status](struct.Owner.html#method.get_dandelion_status).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_dandelion_status",
+		"params": [true],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"epoch": {
+					"start_time": 1602194400,
+					"expiry_time": 1602195000,
+					"is_stem": true,
+					"relay_peer": "redacted",
+					"embargo_secs": 180
+				},
+				"stem_txs": [
+					{
+						"tx_hash": "9e2b4f2a4a3b8cb4a1e8d4f0a5b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3",
+						"kernels": [
+							"08a8f99853d65cee63c973a78a005f4646b777262440a8bfa090694a339a388865"
+						],
+						"src": "PushApi",
+						"received": 1602194520,
+						"embargo_expiry": 1602194700
+					}
+				]
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_dandelion_status(
+		&self,
+		redact_relay: Option<bool>,
+	) -> Result<DandelionStatus, ErrorKind>;
+
+	/**
+	Networked version of [Owner::fluff_all](struct.Owner.html#method.fluff_all).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "fluff_all",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": 1
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn fluff_all(&self) -> Result<usize, ErrorKind>;
+
+	/**
+	Networked version of [Owner::get_standby_status](struct.Owner.html#method.get_standby_status).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "get_standby_status",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"role": "standby",
+				"since": 1602194400,
+				"promoted_by": null,
+				"primary_url": "http://10.0.0.2:3413",
+				"primary_reachable": true,
+				"primary_failures": 0,
+				"last_primary_check": 1602194520
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn get_standby_status(&self) -> Result<StandbyStatus, ErrorKind>;
+
+	/**
+	Networked version of [Owner::promote_to_primary](struct.Owner.html#method.promote_to_primary).
+
+	# Json rpc example
+
+	```
+	# grin_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "promote_to_primary",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"role": "primary",
+				"since": 1602194580,
+				"promoted_by": "owner_api",
+				"primary_url": "http://10.0.0.2:3413",
+				"primary_reachable": false,
+				"primary_failures": 1,
+				"last_primary_check": 1602194575
+			}
+		}
+	}
+	# "#
+	# );
+	```
+	 */
+	fn promote_to_primary(&self) -> Result<StandbyStatus, ErrorKind>;
+}
+
+impl OwnerRpc for Owner {
+	fn get_status(&self) -> Result<Status, ErrorKind> {
+		Owner::get_status(self).map_err(|e| e.kind().clone())
+	}
+
+	fn validate_chain(&self, assume_valid_rangeproofs_kernels: bool) -> Result<(), ErrorKind> {
+		Owner::validate_chain(self, assume_valid_rangeproofs_kernels).map_err(|e| e.kind().clone())
+	}
+
+	fn compact_chain(&self) -> Result<CompactionStats, ErrorKind> {
+		Owner::compact_chain(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_reorg_guard(&self) -> Result<ReorgGuard, ErrorKind> {
+		Owner::get_reorg_guard(self).map_err(|e| e.kind().clone())
+	}
+
+	fn allow_deep_reorgs(&self, duration_secs: u64) -> Result<ReorgGuard, ErrorKind> {
+		Owner::allow_deep_reorgs(self, duration_secs).map_err(|e| e.kind().clone())
+	}
+
+	fn get_peers(&self, addr: Option<SocketAddr>) -> Result<Vec<PeerData>, ErrorKind> {
+		Owner::get_peers(self, addr).map_err(|e| e.kind().clone())
+	}
+
+	fn get_connected_peers(&self) -> Result<Vec<PeerInfoDisplayLegacy>, ErrorKind> {
+		Owner::get_connected_peers(self).map_err(|e| e.kind().clone())
+	}
+
+	fn ban_peer(&self, addr: SocketAddr) -> Result<(), ErrorKind> {
+		Owner::ban_peer(self, addr).map_err(|e| e.kind().clone())
+	}
+
+	fn unban_peer(&self, addr: SocketAddr) -> Result<(), ErrorKind> {
+		Owner::unban_peer(self, addr).map_err(|e| e.kind().clone())
+	}
+
+	fn sync_from_peer(&self, addr: String) -> Result<(), ErrorKind> {
+		Owner::sync_from_peer(self, addr).map_err(|e| e.kind().clone())
+	}
+
+	fn get_libp2p_peer_access(&self) -> Result<Vec<Libp2pPeerAccess>, ErrorKind> {
+		Owner::get_libp2p_peer_access(self).map_err(|e| e.kind().clone())
+	}
+
+	fn set_libp2p_peer_access(&self, address: String, allowed: bool) -> Result<(), ErrorKind> {
+		Owner::set_libp2p_peer_access(self, address, allowed).map_err(|e| e.kind().clone())
+	}
+
+	fn remove_libp2p_peer_access(&self, address: String) -> Result<(), ErrorKind> {
+		Owner::remove_libp2p_peer_access(self, address).map_err(|e| e.kind().clone())
+	}
+
+	fn get_libp2p_bans(&self) -> Result<Vec<Libp2pPeerBan>, ErrorKind> {
+		Owner::get_libp2p_bans(self).map_err(|e| e.kind().clone())
+	}
+
+	fn ban_libp2p_peer(
+		&self,
+		address: String,
+		duration_secs: Option<i64>,
+	) -> Result<Libp2pPeerBan, ErrorKind> {
+		Owner::ban_libp2p_peer(self, address, duration_secs).map_err(|e| e.kind().clone())
+	}
+
+	fn unban_libp2p_peer(&self, address: String) -> Result<(), ErrorKind> {
+		Owner::unban_libp2p_peer(self, address).map_err(|e| e.kind().clone())
+	}
+
+	fn get_quarantined_peers(&self) -> Result<Vec<QuarantinedPeer>, ErrorKind> {
+		Owner::get_quarantined_peers(self).map_err(|e| e.kind().clone())
+	}
+
+	fn release_quarantined_peer(&self, address: String) -> Result<(), ErrorKind> {
+		Owner::release_quarantined_peer(self, address).map_err(|e| e.kind().clone())
+	}
+
+	fn get_sync_data(&self) -> Result<Vec<PeerSyncData>, ErrorKind> {
+		Owner::get_sync_data(self).map_err(|e| e.kind().clone())
+	}
+
+	fn export_peer_snapshot(&self) -> Result<PeerSnapshot, ErrorKind> {
+		Owner::export_peer_snapshot(self).map_err(|e| e.kind().clone())
+	}
+
+	fn import_peer_snapshot(
+		&self,
+		snapshot: PeerSnapshot,
+		trusted_key: Option<String>,
+	) -> Result<PeerSnapshotImport, ErrorKind> {
+		Owner::import_peer_snapshot(self, snapshot, trusted_key).map_err(|e| e.kind().clone())
+	}
+
+	fn get_peer_version_census(
+		&self,
+		upgrade_target: Option<String>,
+	) -> Result<VersionCensusReport, ErrorKind> {
+		Owner::get_peer_version_census(self, upgrade_target).map_err(|e| e.kind().clone())
+	}
+
+	fn register_integrity_kernel(&self, excess: String) -> Result<(), ErrorKind> {
+		Owner::register_integrity_kernel(self, excess).map_err(|e| e.kind().clone())
+	}
+
+	fn remove_integrity_kernel(&self, excess: String) -> Result<(), ErrorKind> {
+		Owner::remove_integrity_kernel(self, excess).map_err(|e| e.kind().clone())
+	}
+
+	fn get_integrity_budget(&self) -> Result<IntegrityBudget, ErrorKind> {
+		Owner::get_integrity_budget(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_gossip_audit(&self, query: GossipAuditQuery) -> Result<GossipAudit, ErrorKind> {
+		Owner::get_gossip_audit(self, query).map_err(|e| e.kind().clone())
+	}
+
+	fn get_gossip_probe_stats(&self) -> Result<GossipProbeStats, ErrorKind> {
+		Owner::get_gossip_probe_stats(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_api_compression_stats(&self) -> Result<ApiCompressionStats, ErrorKind> {
+		Owner::get_api_compression_stats(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_libp2p_stats(&self) -> Result<Libp2pStats, ErrorKind> {
+		Owner::get_libp2p_stats(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_libp2p_peers(&self) -> Result<Vec<Libp2pConnection>, ErrorKind> {
+		Owner::get_libp2p_peers(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_stratum_audit(&self, query: StratumAuditQuery) -> Result<StratumAudit, ErrorKind> {
+		Owner::get_stratum_audit(self, query).map_err(|e| e.kind().clone())
+	}
+
+	fn get_uptime_history(&self) -> Result<Vec<UptimeRecord>, ErrorKind> {
+		Owner::get_uptime_history(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_metrics_history(
+		&self,
+		from: Option<i64>,
+		to: Option<i64>,
+	) -> Result<Vec<MetricsSnapshot>, ErrorKind> {
+		Owner::get_metrics_history(self, from, to).map_err(|e| e.kind().clone())
+	}
+
+	fn get_block_timings(
+		&self,
+		count: Option<u32>,
+	) -> Result<Vec<BlockTimingsPrintable>, ErrorKind> {
+		Owner::get_block_timings(self, count).map_err(|e| e.kind().clone())
+	}
+
+	fn get_subsystem_usage(&self) -> Result<Vec<SubsystemUsage>, ErrorKind> {
+		Owner::get_subsystem_usage(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_dandelion_status(
+		&self,
+		redact_relay: Option<bool>,
+	) -> Result<DandelionStatus, ErrorKind> {
+		Owner::get_dandelion_status(self, redact_relay).map_err(|e| e.kind().clone())
+	}
+
+	fn fluff_all(&self) -> Result<usize, ErrorKind> {
+		Owner::fluff_all(self).map_err(|e| e.kind().clone())
+	}
+
+	fn get_standby_status(&self) -> Result<StandbyStatus, ErrorKind> {
+		Owner::get_standby_status(self).map_err(|e| e.kind().clone())
+	}
+
+	fn promote_to_primary(&self) -> Result<StandbyStatus, ErrorKind> {
+		Owner::promote_to_primary(self).map_err(|e| e.kind().clone())
 	}
 }
 
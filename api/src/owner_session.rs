@@ -0,0 +1,417 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Session tokens of the owner API. With the sessions enabled the api secret is accepted
+//! by `/v2/owner/login` only. The login issues a short-lived token, the owner API and
+//! v1 API calls pass it as `Authorization: Bearer <token>`. Every team member logs in with an own
+//! label, the owner calls are recorded in the audit log with the session they were made
+//! with. Sessions can be revoked before they expire, the expired sessions are dropped.
+
+use crate::rest::{Error, ErrorKind};
+use crate::router::{Handler, ResponseFuture};
+use crate::util::{Mutex, ToHex};
+use crate::web::*;
+use chrono::Utc;
+use hyper::{Body, Request, StatusCode};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Max length of the session label
+pub const MAX_SESSION_LABEL_LEN: usize = 64;
+/// Max number of the audit records returned by a single query
+pub const OWNER_AUDIT_QUERY_LIMIT: usize = 1000;
+
+/// Owner API sessions configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OwnerSessionConfig {
+	/// Owner API requires the session tokens, the api secret is accepted by the login only
+	#[serde(default = "default_enable_owner_sessions")]
+	pub enable_owner_sessions: bool,
+
+	/// Lifetime of the session token in seconds. The login can request a shorter one.
+	#[serde(default = "default_owner_session_ttl_secs")]
+	pub owner_session_ttl_secs: u64,
+
+	/// Max number of the active sessions, login fails when the limit is reached
+	#[serde(default = "default_owner_max_sessions")]
+	pub owner_max_sessions: usize,
+
+	/// Number of the audit records kept in memory, the oldest are dropped
+	#[serde(default = "default_owner_audit_log_size")]
+	pub owner_audit_log_size: usize,
+}
+
+impl Default for OwnerSessionConfig {
+	fn default() -> OwnerSessionConfig {
+		OwnerSessionConfig {
+			enable_owner_sessions: default_enable_owner_sessions(),
+			owner_session_ttl_secs: default_owner_session_ttl_secs(),
+			owner_max_sessions: default_owner_max_sessions(),
+			owner_audit_log_size: default_owner_audit_log_size(),
+		}
+	}
+}
+
+fn default_enable_owner_sessions() -> bool {
+	false
+}
+
+fn default_owner_session_ttl_secs() -> u64 {
+	3600
+}
+
+fn default_owner_max_sessions() -> usize {
+	100
+}
+
+fn default_owner_audit_log_size() -> usize {
+	10_000
+}
+
+/// Active owner API session, the token itself is not exposed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OwnerSession {
+	/// Session id, used in the audit log and for the revocation
+	pub id: String,
+	/// Label provided at the login, usually the name of the operator
+	pub label: String,
+	/// Unix time of the login
+	pub created: i64,
+	/// Unix time when the token expires
+	pub expires: i64,
+}
+
+/// Issued session token, returned by the login
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OwnerSessionToken {
+	/// Bearer token for the owner API calls
+	pub token: String,
+	/// Session of the token
+	pub session: OwnerSession,
+}
+
+/// Owner action performed with a session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OwnerAuditEntry {
+	/// Unix time of the action
+	pub timestamp: i64,
+	/// Session id
+	pub session_id: String,
+	/// Session label
+	pub label: String,
+	/// Owner API method, or login, logout and revoke for the session management
+	pub action: String,
+}
+
+/// Login request, all fields are optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct OwnerLoginRequest {
+	/// Label of the session
+	pub label: Option<String>,
+	/// Requested lifetime in seconds, limited by the configured one
+	pub ttl_secs: Option<u64>,
+}
+
+/// Active sessions and the audit log of the owner API
+pub struct OwnerSessions {
+	config: OwnerSessionConfig,
+	rng: SystemRandom,
+	// Token -> session
+	sessions: Mutex<HashMap<String, OwnerSession>>,
+	audit: Mutex<VecDeque<OwnerAuditEntry>>,
+}
+
+impl OwnerSessions {
+	/// No sessions
+	pub fn new(config: OwnerSessionConfig) -> OwnerSessions {
+		OwnerSessions {
+			config,
+			rng: SystemRandom::new(),
+			sessions: Mutex::new(HashMap::new()),
+			audit: Mutex::new(VecDeque::new()),
+		}
+	}
+
+	fn random_hex(&self, len: usize) -> Result<String, Error> {
+		let mut buf = vec![0u8; len];
+		self.rng
+			.fill(&mut buf)
+			.map_err(|_| ErrorKind::Internal("Unable to generate session token".to_string()))?;
+		Ok(buf.to_hex())
+	}
+
+	/// Issue a new session token
+	pub fn login(&self, request: &OwnerLoginRequest, now: i64) -> Result<OwnerSessionToken, Error> {
+		let label = request.label.clone().unwrap_or_default();
+		if label.len() > MAX_SESSION_LABEL_LEN {
+			return Err(ErrorKind::Argument(format!(
+				"Session label is longer than {} characters",
+				MAX_SESSION_LABEL_LEN
+			))
+			.into());
+		}
+		let ttl = request
+			.ttl_secs
+			.unwrap_or(self.config.owner_session_ttl_secs)
+			.min(self.config.owner_session_ttl_secs);
+		if ttl == 0 {
+			return Err(ErrorKind::Argument("Session lifetime can't be 0".to_string()).into());
+		}
+
+		self.gc(now);
+		let session = OwnerSession {
+			id: self.random_hex(8)?,
+			label,
+			created: now,
+			expires: now + ttl as i64,
+		};
+		let token = self.random_hex(32)?;
+		{
+			let mut sessions = self.sessions.lock();
+			if sessions.len() >= self.config.owner_max_sessions {
+				return Err(ErrorKind::Argument(format!(
+					"Too many active sessions, the limit is {}",
+					self.config.owner_max_sessions
+				))
+				.into());
+			}
+			sessions.insert(token.clone(), session.clone());
+		}
+		self.record(&session, "login", now);
+		Ok(OwnerSessionToken { token, session })
+	}
+
+	/// Session of the token, None if the token is unknown, expired or revoked
+	pub fn validate(&self, token: &str, now: i64) -> Option<OwnerSession> {
+		let mut sessions = self.sessions.lock();
+		match sessions.get(token) {
+			Some(session) if session.expires > now => Some(session.clone()),
+			Some(_) => {
+				sessions.remove(token);
+				None
+			}
+			None => None,
+		}
+	}
+
+	/// Revoke the session. Returns false if there is no such active session.
+	pub fn revoke(&self, session_id: &str, by: &OwnerSession, now: i64) -> bool {
+		let revoked = {
+			let mut sessions = self.sessions.lock();
+			let token = sessions
+				.iter()
+				.find(|(_, s)| s.id == session_id)
+				.map(|(t, _)| t.clone());
+			token.and_then(|t| sessions.remove(&t))
+		};
+		match revoked {
+			Some(revoked) => {
+				let action = if revoked.id == by.id {
+					"logout".to_string()
+				} else {
+					format!("revoke {}", revoked.id)
+				};
+				self.record(by, &action, now);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Drop the expired sessions
+	pub fn gc(&self, now: i64) {
+		self.sessions.lock().retain(|_, s| s.expires > now);
+	}
+
+	/// Active sessions, the oldest first
+	pub fn sessions(&self, now: i64) -> Vec<OwnerSession> {
+		self.gc(now);
+		let mut res: Vec<OwnerSession> = self.sessions.lock().values().cloned().collect();
+		res.sort_by_key(|s| s.created);
+		res
+	}
+
+	/// Record the action performed with the session
+	pub fn record(&self, session: &OwnerSession, action: &str, now: i64) {
+		let mut audit = self.audit.lock();
+		audit.push_back(OwnerAuditEntry {
+			timestamp: now,
+			session_id: session.id.clone(),
+			label: session.label.clone(),
+			action: action.to_string(),
+		});
+		while audit.len() > self.config.owner_audit_log_size {
+			audit.pop_front();
+		}
+	}
+
+	/// Audit records of the session or of all sessions, the most recent `limit` records,
+	/// the oldest first
+	pub fn audit(&self, session_id: Option<&str>, limit: usize) -> Vec<OwnerAuditEntry> {
+		let limit = limit.min(OWNER_AUDIT_QUERY_LIMIT);
+		let mut res: Vec<OwnerAuditEntry> = self
+			.audit
+			.lock()
+			.iter()
+			.rev()
+			.filter(|e| session_id.map_or(true, |id| e.session_id == id))
+			.take(limit)
+			.cloned()
+			.collect();
+		res.reverse();
+		res
+	}
+}
+
+/// Owner API methods of the json-rpc request, batch requests have many
+pub fn owner_request_methods(request: &Value) -> Vec<String> {
+	let method = |v: &Value| {
+		v.get("method")
+			.and_then(|m| m.as_str())
+			.unwrap_or("unknown")
+			.to_string()
+	};
+	match request {
+		Value::Array(requests) => requests.iter().map(method).collect(),
+		request => vec![method(request)],
+	}
+}
+
+// Session of the request, set by the session middleware
+fn request_session(req: &Request<Body>) -> Result<OwnerSession, Error> {
+	req.extensions()
+		.get::<OwnerSession>()
+		.cloned()
+		.ok_or_else(|| ErrorKind::Internal("Request has no owner session".to_string()).into())
+}
+
+/// Login handler, issues the session tokens. The api secret is checked by the basic
+/// auth middleware.
+pub struct OwnerLoginHandler {
+	pub sessions: Arc<OwnerSessions>,
+}
+
+impl Handler for OwnerLoginHandler {
+	fn post(&self, req: Request<Body>) -> ResponseFuture {
+		let sessions = self.sessions.clone();
+		Box::pin(async move {
+			// Body is optional, the login without it gets the default session
+			let raw = hyper::body::to_bytes(req.into_body()).await?;
+			let request = if raw.is_empty() {
+				OwnerLoginRequest::default()
+			} else {
+				match serde_json::from_slice::<OwnerLoginRequest>(&raw) {
+					Ok(r) => r,
+					Err(e) => {
+						return Ok(just_response(
+							StatusCode::BAD_REQUEST,
+							format!("Invalid login request, {}", e),
+						))
+					}
+				}
+			};
+			match sessions.login(&request, Utc::now().timestamp()) {
+				Ok(token) => {
+					info!(
+						"Owner API session {} '{}' is opened",
+						token.session.id, token.session.label
+					);
+					json_response_pretty(&token).await
+				}
+				Err(e) => Ok(just_response(StatusCode::BAD_REQUEST, format!("{}", e))),
+			}
+		})
+	}
+}
+
+/// Logout handler, revokes the token of the request
+pub struct OwnerLogoutHandler {
+	pub sessions: Arc<OwnerSessions>,
+}
+
+impl Handler for OwnerLogoutHandler {
+	fn post(&self, req: Request<Body>) -> ResponseFuture {
+		let session = match request_session(&req) {
+			Ok(s) => s,
+			Err(e) => return response(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)),
+		};
+		self.sessions
+			.revoke(&session.id, &session, Utc::now().timestamp());
+		info!(
+			"Owner API session {} '{}' is closed",
+			session.id, session.label
+		);
+		response(StatusCode::OK, "")
+	}
+}
+
+/// Sessions handler. GET lists the active sessions, DELETE with `?id=<session id>`
+/// revokes the session.
+pub struct OwnerSessionsHandler {
+	pub sessions: Arc<OwnerSessions>,
+}
+
+impl Handler for OwnerSessionsHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		json_response_pretty(&self.sessions.sessions(Utc::now().timestamp()))
+	}
+
+	fn delete(&self, req: Request<Body>) -> ResponseFuture {
+		let session = match request_session(&req) {
+			Ok(s) => s,
+			Err(e) => return response(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)),
+		};
+		let params = QueryParams::from(req.uri().query());
+		let id = match params.get("id") {
+			Some(id) => id.clone(),
+			None => return response(StatusCode::BAD_REQUEST, "id parameter is required"),
+		};
+		if self.sessions.revoke(&id, &session, Utc::now().timestamp()) {
+			warn!(
+				"Owner API session {} is revoked by {} '{}'",
+				id, session.id, session.label
+			);
+			response(StatusCode::OK, "")
+		} else {
+			response(
+				StatusCode::NOT_FOUND,
+				format!("session {} is not found", id),
+			)
+		}
+	}
+}
+
+/// Audit log handler. Optional parameters: `session` - records of that session only,
+/// `limit` - number of the most recent records, default and max is 1000.
+pub struct OwnerAuditHandler {
+	pub sessions: Arc<OwnerSessions>,
+}
+
+impl Handler for OwnerAuditHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let params = QueryParams::from(req.uri().query());
+		let limit = match params.get("limit").map(|l| l.parse::<usize>()) {
+			None => OWNER_AUDIT_QUERY_LIMIT,
+			Some(Ok(l)) => l,
+			Some(Err(_)) => return response(StatusCode::BAD_REQUEST, "invalid limit"),
+		};
+		json_response_pretty(
+			&self
+				.sessions
+				.audit(params.get("session").map(|s| s.as_str()), limit),
+		)
+	}
+}
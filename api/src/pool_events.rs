@@ -0,0 +1,419 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transaction pool events subscription. Mining pools maintain a mirror of the txpool
+//! from the delta events instead of polling it. Subscribers connect with websocket to
+//! `/v2/pool/events` and receive every event as a json text message.
+//!
+//! Every event has a sequence number, a gap in the sequence means the events were lost
+//! and the mirror must be reloaded. To build the initial mirror, subscribe first and then
+//! load the pool with `get_unconfirmed_transactions`. The subscriber that lost the
+//! connection reconnects with `/v2/pool/events?resume_from=<seq>`, where seq is the next
+//! event it expects, and gets the missed events from the event journal first.
+
+use crate::core::core::hash::Hashed;
+use crate::event_journal::{EventFeed, EventLog};
+use crate::pool::{PoolEntry, TxRemovalReason, TxSource};
+use crate::rest::{Error, ErrorKind};
+use crate::router::{Handler, ResponseFuture};
+use crate::util::ToHex;
+use crate::web::{response, QueryParams};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use hyper::header::{HeaderValue, CONNECTION, UPGRADE};
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Request, Response, StatusCode};
+use ring::digest;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf};
+
+/// Max payload of the control frames we accept from the subscriber
+const MAX_CLIENT_FRAME_SIZE: u64 = 4096;
+
+/// Websocket accept key magic, RFC 6455
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Transaction pool delta event
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TxPoolEvent {
+	/// Transaction is added to the txpool
+	TxAdded {
+		/// Transaction hash
+		tx_hash: String,
+		/// Kernel excesses, hex encoded
+		kernels: Vec<String>,
+		/// Total fee
+		fee: u64,
+		/// Transaction weight
+		weight: u64,
+		/// Fee per 1000 weight units, the txpool ranks the transactions by it
+		fee_to_weight: u64,
+		/// Where the transaction came from
+		src: TxSource,
+		/// Transaction is added back after the reorg
+		restored: bool,
+	},
+	/// Transaction is removed from the txpool
+	TxRemoved {
+		/// Transaction hash
+		tx_hash: String,
+		/// Kernel excesses, hex encoded
+		kernels: Vec<String>,
+		/// Why the transaction was removed
+		reason: TxRemovalReason,
+	},
+}
+
+impl TxPoolEvent {
+	/// Event for the transaction added to the txpool
+	pub fn tx_added(entry: &PoolEntry, restored: bool) -> TxPoolEvent {
+		TxPoolEvent::TxAdded {
+			tx_hash: entry.tx.hash().to_hex(),
+			kernels: kernel_excesses(entry),
+			fee: entry.tx.fee(),
+			weight: entry.tx.tx_weight(),
+			fee_to_weight: entry.tx.fee_to_weight(),
+			src: entry.src,
+			restored,
+		}
+	}
+
+	/// Event for the transaction removed from the txpool
+	pub fn tx_removed(entry: &PoolEntry, reason: TxRemovalReason) -> TxPoolEvent {
+		TxPoolEvent::TxRemoved {
+			tx_hash: entry.tx.hash().to_hex(),
+			kernels: kernel_excesses(entry),
+			reason,
+		}
+	}
+}
+
+fn kernel_excesses(entry: &PoolEntry) -> Vec<String> {
+	entry
+		.tx
+		.kernels()
+		.iter()
+		.map(|k| k.excess.to_hex())
+		.collect()
+}
+
+/// Event with its sequence number, the message sent to the subscribers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxPoolEventMessage {
+	/// Sequence number, continues across the node restarts if the event journal is enabled
+	pub seq: u64,
+	/// The event
+	#[serde(flatten)]
+	pub event: TxPoolEvent,
+}
+
+/// Publishes the transaction pool events to the subscribers
+pub struct TxPoolEventsHub {
+	feed: EventFeed,
+}
+
+impl TxPoolEventsHub {
+	/// New hub without subscribers
+	pub fn new() -> TxPoolEventsHub {
+		TxPoolEventsHub {
+			feed: EventFeed::new("Tx pool events"),
+		}
+	}
+
+	/// Record the events in the journal, the sequence continues from the journal
+	pub fn set_journal(&self, log: EventLog) {
+		self.feed.set_journal(log);
+	}
+
+	/// Publish the event to all the subscribers. Never blocks, the subscribers that
+	/// can't keep up are disconnected.
+	pub fn publish(&self, event: TxPoolEvent) {
+		self.feed.publish(|seq| TxPoolEventMessage { seq, event });
+	}
+
+	/// Subscribe to the events published from now on. With `resume_from` the journal
+	/// events starting from that sequence number are sent first.
+	pub fn subscribe(
+		&self,
+		resume_from: Option<u64>,
+	) -> Result<mpsc::Receiver<Arc<String>>, Error> {
+		self.feed.subscribe(resume_from)
+	}
+
+	/// Number of the connected subscribers
+	pub fn subscribers_count(&self) -> usize {
+		self.feed.subscribers_count()
+	}
+}
+
+/// Websocket endpoint of the transaction pool events
+pub struct TxPoolEventsHandler {
+	pub hub: Arc<TxPoolEventsHub>,
+}
+
+impl Handler for TxPoolEventsHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let hub = self.hub.clone();
+		websocket_subscribe(req, "Tx pool events", move |resume_from| {
+			hub.subscribe(resume_from)
+		})
+	}
+}
+
+/// Upgrade the request to websocket and stream the events of the subscription to it.
+/// The `resume_from` query parameter is passed to the subscription.
+pub(crate) fn websocket_subscribe<F>(
+	req: Request<Body>,
+	name: &'static str,
+	subscribe: F,
+) -> ResponseFuture
+where
+	F: FnOnce(Option<u64>) -> Result<mpsc::Receiver<Arc<String>>, Error>,
+{
+	let accept_key = match websocket_accept_key(&req) {
+		Some(key) => key,
+		None => return response(StatusCode::BAD_REQUEST, "websocket upgrade is expected"),
+	};
+	let resume_from = match QueryParams::from(req.uri().query()).get("resume_from") {
+		Some(seq) => match seq.parse::<u64>() {
+			Ok(seq) => Some(seq),
+			Err(_) => {
+				return response(
+					StatusCode::BAD_REQUEST,
+					format!("invalid resume_from value: {}", seq),
+				)
+			}
+		},
+		None => None,
+	};
+	let events = match subscribe(resume_from) {
+		Ok(events) => events,
+		// The missed events are pruned, the subscriber must reload its state
+		Err(e) => match e.kind() {
+			ErrorKind::NotFound(msg) => return response(StatusCode::GONE, msg.clone()),
+			ErrorKind::Argument(msg) => return response(StatusCode::BAD_REQUEST, msg.clone()),
+			_ => return response(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)),
+		},
+	};
+	Box::pin(async move {
+		tokio::spawn(async move {
+			match req.into_body().on_upgrade().await {
+				Ok(upgraded) => {
+					debug!("{} subscriber is connected", name);
+					if let Err(e) = serve_subscriber(upgraded, events).await {
+						debug!("{} subscriber is disconnected, {}", name, e);
+					}
+				}
+				Err(e) => warn!("{} websocket upgrade failed, {}", name, e),
+			}
+		});
+		let mut resp = Response::new(Body::empty());
+		*resp.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+		let headers = resp.headers_mut();
+		headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+		headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+		if let Ok(key) = HeaderValue::from_str(&accept_key) {
+			headers.insert("sec-websocket-accept", key);
+		}
+		Ok(resp)
+	})
+}
+
+// Sec-WebSocket-Accept value for the websocket upgrade request, None for other requests
+fn websocket_accept_key(req: &Request<Body>) -> Option<String> {
+	let headers = req.headers();
+	let is_upgrade = headers
+		.get(UPGRADE)
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.eq_ignore_ascii_case("websocket"))
+		.unwrap_or(false);
+	if !is_upgrade {
+		return None;
+	}
+	let key = headers.get("sec-websocket-key")?.to_str().ok()?;
+	let hash = digest::digest(
+		&digest::SHA1_FOR_LEGACY_USE_ONLY,
+		format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes(),
+	);
+	Some(base64::encode(hash.as_ref()))
+}
+
+// Control frames from the subscriber that the writer must respond to
+enum Control {
+	Pong(Vec<u8>),
+	Close,
+}
+
+async fn serve_subscriber(
+	upgraded: Upgraded,
+	mut events: mpsc::Receiver<Arc<String>>,
+) -> Result<(), std::io::Error> {
+	let (reader, mut writer) = tokio::io::split(upgraded);
+	let (control_tx, mut control_rx) = mpsc::channel::<Control>(16);
+	tokio::spawn(read_subscriber(reader, control_tx));
+
+	loop {
+		tokio::select! {
+			event = events.next() => match event {
+				Some(msg) => writer.write_all(&encode_frame(OPCODE_TEXT, msg.as_bytes())).await?,
+				None => {
+					// Dropped by the hub, the subscriber is too slow
+					let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &[])).await;
+					return Ok(());
+				}
+			},
+			control = control_rx.next() => match control {
+				Some(Control::Pong(payload)) => {
+					writer.write_all(&encode_frame(OPCODE_PONG, &payload)).await?
+				}
+				Some(Control::Close) | None => {
+					let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &[])).await;
+					return Ok(());
+				}
+			},
+		}
+	}
+}
+
+// Subscribers don't send the data, only the control frames are processed
+async fn read_subscriber(mut reader: ReadHalf<Upgraded>, mut control: mpsc::Sender<Control>) {
+	loop {
+		let mut header = [0u8; 2];
+		if reader.read_exact(&mut header).await.is_err() {
+			break;
+		}
+		let opcode = header[0] & 0x0F;
+		let masked = header[1] & 0x80 != 0;
+		let len = match header[1] & 0x7F {
+			126 => {
+				let mut buf = [0u8; 2];
+				if reader.read_exact(&mut buf).await.is_err() {
+					break;
+				}
+				u16::from_be_bytes(buf) as u64
+			}
+			127 => {
+				let mut buf = [0u8; 8];
+				if reader.read_exact(&mut buf).await.is_err() {
+					break;
+				}
+				u64::from_be_bytes(buf)
+			}
+			len => len as u64,
+		};
+		if len > MAX_CLIENT_FRAME_SIZE {
+			break;
+		}
+		let mut mask = [0u8; 4];
+		if masked && reader.read_exact(&mut mask).await.is_err() {
+			break;
+		}
+		let mut payload = vec![0u8; len as usize];
+		if reader.read_exact(&mut payload).await.is_err() {
+			break;
+		}
+		if masked {
+			for (i, b) in payload.iter_mut().enumerate() {
+				*b ^= mask[i % 4];
+			}
+		}
+		match opcode {
+			OPCODE_CLOSE => break,
+			OPCODE_PING => {
+				if control.send(Control::Pong(payload)).await.is_err() {
+					return;
+				}
+			}
+			_ => {}
+		}
+	}
+	let _ = control.send(Control::Close).await;
+}
+
+// Single unmasked final frame, the server never masks its frames
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+	let mut frame = Vec::with_capacity(payload.len() + 10);
+	frame.push(0x80 | opcode);
+	let len = payload.len();
+	if len < 126 {
+		frame.push(len as u8);
+	} else if len <= u16::max_value() as usize {
+		frame.push(126);
+		frame.extend_from_slice(&(len as u16).to_be_bytes());
+	} else {
+		frame.push(127);
+		frame.extend_from_slice(&(len as u64).to_be_bytes());
+	}
+	frame.extend_from_slice(payload);
+	frame
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn websocket_handshake_and_frames() {
+		// Example from RFC 6455
+		let req = Request::builder()
+			.header(UPGRADE, "websocket")
+			.header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+			.body(Body::empty())
+			.unwrap();
+		assert_eq!(
+			websocket_accept_key(&req),
+			Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string())
+		);
+		let req = Request::builder().body(Body::empty()).unwrap();
+		assert_eq!(websocket_accept_key(&req), None);
+
+		assert_eq!(
+			encode_frame(OPCODE_TEXT, b"Hello"),
+			b"\x81\x05Hello".to_vec()
+		);
+		let frame = encode_frame(OPCODE_TEXT, &[0u8; 300]);
+		assert_eq!(&frame[..4], &[0x81, 126, 0x01, 0x2C]);
+		assert_eq!(frame.len(), 304);
+	}
+
+	#[test]
+	fn publish_to_subscribers() {
+		let hub = TxPoolEventsHub::new();
+		let mut events = hub.subscribe(None).unwrap();
+		assert_eq!(hub.subscribers_count(), 1);
+		let event = TxPoolEvent::TxRemoved {
+			tx_hash: "00".to_string(),
+			kernels: vec![],
+			reason: TxRemovalReason::Block,
+		};
+		hub.publish(event.clone());
+		let msg = events.try_next().unwrap().unwrap();
+		let msg: TxPoolEventMessage = serde_json::from_str(&msg).unwrap();
+		assert_eq!(msg, TxPoolEventMessage { seq: 0, event });
+
+		drop(events);
+		assert_eq!(hub.subscribers_count(), 0);
+
+		// Resume replays nothing without the journal, the missed event is gone
+		assert!(hub.subscribe(Some(0)).is_err());
+		let mut events = hub.subscribe(Some(1)).unwrap();
+		assert!(events.try_next().is_err());
+	}
+}
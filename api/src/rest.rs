@@ -18,11 +18,14 @@
 //! To use it, just have your service(s) implement the ApiEndpoint trait and
 //! register them on a ApiServer.
 
-use crate::p2p::Error as P2pError;
+use crate::http_config::HttpConfig;
+use crate::p2p::{self, Error as P2pError};
 use crate::router::{Handler, HandlerObj, ResponseFuture, Router, RouterError};
+use crate::util::subsystem::{self, SUBSYSTEM_API};
 use crate::web::response;
 use failure::{Backtrace, Context, Fail};
 use futures::channel::oneshot;
+use futures::future;
 use futures::TryStreamExt;
 use hyper::server::accept;
 use hyper::service::make_service_fn;
@@ -62,6 +65,10 @@ pub enum ErrorKind {
 	Router(RouterError),
 	#[fail(display = "API P2P error: {}", _0)]
 	P2pError(String),
+	#[fail(display = "API Node is in standby mode: {}", _0)]
+	Standby(String),
+	#[fail(display = "API Node is in cold storage mode: {}", _0)]
+	ColdStorage(String),
 }
 
 impl Fail for Error {
@@ -185,14 +192,21 @@ impl TLSConfig {
 /// HTTP server allowing the registration of ApiEndpoint implementations.
 pub struct ApiServer {
 	shutdown_sender: Option<oneshot::Sender<()>>,
+	http_config: HttpConfig,
 }
 
 impl ApiServer {
 	/// Creates a new ApiServer that will serve ApiEndpoint implementations
 	/// under the root URL.
 	pub fn new() -> ApiServer {
+		ApiServer::with_http_config(HttpConfig::default())
+	}
+
+	/// Creates a new ApiServer with the HTTP tuning
+	pub fn with_http_config(http_config: HttpConfig) -> ApiServer {
 		ApiServer {
 			shutdown_sender: None,
+			http_config,
 		}
 	}
 
@@ -231,17 +245,29 @@ impl ApiServer {
 		let m = oneshot::channel::<()>();
 		let tx = std::mem::replace(tx, m.0);
 		self.shutdown_sender = Some(tx);
+		let http_config = self.http_config.clone();
+		// Listener might be taken over from the previous node on upgrade
+		let listener = p2p::handoff::bind_listener(addr).map_err(|e| {
+			ErrorKind::Internal(format!("failed to bind API listener at {}, {}", addr, e))
+		})?;
+		let handoff = p2p::handoff::handoff_signal();
 		thread::Builder::new()
 			.name("apis".to_string())
 			.spawn(move || {
+				let _usage = subsystem::track_thread(SUBSYSTEM_API);
 				let server = async move {
-					let server = Server::bind(&addr)
+					let builder = Server::from_tcp(listener)?
+						.tcp_keepalive(http_config.tcp_keep_alive())
+						.tcp_nodelay(true);
+					let server = http_config
+						.configure(builder)
 						.serve(make_service_fn(move |_| {
 							let router = router.clone();
 							async move { Ok::<_, Infallible>(router) }
 						}))
 						.with_graceful_shutdown(async {
-							rx.await.ok();
+							// New node took over the listener, stop accepting
+							future::select(rx, handoff).await;
 						});
 
 					server.await
@@ -286,22 +312,38 @@ impl ApiServer {
 		config
 			.set_single_cert(certs, keys)
 			.expect("invalid key or certificate");
+		config.set_protocols(&self.http_config.alpn_protocols());
 		let acceptor = TlsAcceptor::from(Arc::new(config));
+		let http_config = self.http_config.clone();
+		let listener = p2p::handoff::bind_listener(addr)
+			.and_then(|l| l.set_nonblocking(true).map(|_| l))
+			.map_err(|e| {
+				ErrorKind::Internal(format!("failed to bind API listener at {}, {}", addr, e))
+			})?;
+		let handoff = p2p::handoff::handoff_signal();
 
 		thread::Builder::new()
 			.name("apis".to_string())
 			.spawn(move || {
+				let _usage = subsystem::track_thread(SUBSYSTEM_API);
 				let server = async move {
-					let mut listener = TcpListener::bind(&addr).await.expect("failed to bind");
-					let listener = listener.incoming().and_then(move |s| acceptor.accept(s));
-
-					let server = Server::builder(accept::from_stream(listener))
+					let mut listener = TcpListener::from_std(listener).expect("failed to bind");
+					let tcp_keep_alive = http_config.tcp_keep_alive();
+					let listener = listener.incoming().and_then(move |s| {
+						let _ = s.set_keepalive(tcp_keep_alive);
+						let _ = s.set_nodelay(true);
+						acceptor.accept(s)
+					});
+
+					let server = http_config
+						.configure(Server::builder(accept::from_stream(listener)))
 						.serve(make_service_fn(move |_| {
 							let router = router.clone();
 							async move { Ok::<_, Infallible>(router) }
 						}))
 						.with_graceful_shutdown(async {
-							rx.await.ok();
+							// New node took over the listener, stop accepting
+							future::select(rx, handoff).await;
 						});
 
 					server.await
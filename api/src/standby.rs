@@ -0,0 +1,127 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Role of the node in a redundant pair. The standby node syncs and validates the chain
+//! like the primary does, but it doesn't run the stratum server, rejects the transaction
+//! pushes and doesn't emit the webhooks. Everything is ready, so the promotion only
+//! flips the role: the stratum server starts listening and the rest resumes within
+//! a second. The role is not persisted, the restarted node takes the role from its config.
+
+use crate::rest::{Error, ErrorKind};
+use crate::util::RwLock;
+use chrono::Utc;
+
+/// Role of the node
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeRole {
+	/// Node serves the miners, the transaction pushes and the webhooks
+	Primary,
+	/// Hot spare, it waits for the promotion
+	Standby,
+}
+
+/// Role of the node and the health of the primary, as seen by the standby
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StandbyStatus {
+	/// Current role
+	pub role: NodeRole,
+	/// Unix time when the node took the current role
+	pub since: i64,
+	/// Who promoted the node: "owner_api" or "failover". None if it started as primary.
+	pub promoted_by: Option<String>,
+	/// Foreign API url of the primary that is watched by the standby
+	pub primary_url: Option<String>,
+	/// Result of the last health check of the primary
+	pub primary_reachable: Option<bool>,
+	/// Number of the failed health checks in a row
+	pub primary_failures: u32,
+	/// Unix time of the last health check of the primary
+	pub last_primary_check: Option<i64>,
+}
+
+impl Default for StandbyStatus {
+	fn default() -> StandbyStatus {
+		StandbyStatus {
+			role: NodeRole::Primary,
+			since: Utc::now().timestamp(),
+			promoted_by: None,
+			primary_url: None,
+			primary_reachable: None,
+			primary_failures: 0,
+			last_primary_check: None,
+		}
+	}
+}
+
+lazy_static! {
+	static ref STANDBY: RwLock<StandbyStatus> = RwLock::new(StandbyStatus::default());
+}
+
+/// Start the node as the standby of the primary
+pub fn init_standby(primary_url: Option<String>) {
+	let mut status = STANDBY.write();
+	*status = StandbyStatus {
+		role: NodeRole::Standby,
+		primary_url,
+		..StandbyStatus::default()
+	};
+}
+
+/// True until the standby node is promoted
+pub fn is_standby() -> bool {
+	STANDBY.read().role == NodeRole::Standby
+}
+
+/// Error for the calls the standby node doesn't serve
+pub fn check_not_standby(call: &str) -> Result<(), Error> {
+	if is_standby() {
+		return Err(ErrorKind::Standby(format!(
+			"{} is not available until the node is promoted",
+			call
+		))
+		.into());
+	}
+	Ok(())
+}
+
+/// Promote the standby node to the primary. The primary stays as it is.
+pub fn promote_to_primary(promoted_by: &str) -> StandbyStatus {
+	let mut status = STANDBY.write();
+	if status.role == NodeRole::Standby {
+		warn!("Node is promoted to primary by {}", promoted_by);
+		status.role = NodeRole::Primary;
+		status.since = Utc::now().timestamp();
+		status.promoted_by = Some(promoted_by.to_string());
+	}
+	status.clone()
+}
+
+/// Record the health check of the primary. Returns the number of the failed checks in a row.
+pub fn record_primary_check(reachable: bool) -> u32 {
+	let mut status = STANDBY.write();
+	status.primary_reachable = Some(reachable);
+	status.last_primary_check = Some(Utc::now().timestamp());
+	if reachable {
+		status.primary_failures = 0;
+	} else {
+		status.primary_failures += 1;
+	}
+	status.primary_failures
+}
+
+/// Current role of the node
+pub fn standby_status() -> StandbyStatus {
+	STANDBY.read().clone()
+}
@@ -0,0 +1,438 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistent work log of the stratum server. Every job sent to the workers and every
+//! submitted share with its validation result is recorded. The job has the pre-pow data
+//! and the share has the nonce and the proof, so a disputed share can be validated again
+//! with the data the worker got. The log is rolling, the records older than the
+//! retention period and the oldest records above the size limit are pruned.
+
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::util::RwLock;
+use grin_store::{self, to_key, to_key_u64, Error};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const DB_NAME: &str = "stratum_audit";
+const STORE_SUBPATH: &str = "stratum_audit";
+
+const JOB_PREFIX: u8 = b'J';
+const SHARE_PREFIX: u8 = b'S';
+
+/// Default retention period of the stratum log records
+pub const DEFAULT_STRATUM_AUDIT_RETENTION_DAYS: u32 = 7;
+/// Default max number of the stratum log records, jobs and shares together
+pub const DEFAULT_STRATUM_AUDIT_MAX_ENTRIES: u64 = 1_000_000;
+/// Max number of the records of every kind returned by the query
+pub const STRATUM_AUDIT_QUERY_LIMIT: usize = 1000;
+// Pruning runs after that many records
+const PRUNE_INTERVAL: u64 = 1000;
+// Proof has 42 nonces, some room for the other proof sizes
+const MAX_PROOF_SIZE: u64 = 64;
+
+/// Job sent to the workers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StratumJobRecord {
+	/// Unix time when the job was sent
+	pub timestamp: i64,
+	/// Height of the block
+	pub height: u64,
+	/// Job id, the version of the block at this height
+	pub job_id: u64,
+	/// Min share difficulty of the job
+	pub share_difficulty: u64,
+	/// Network difficulty, the share with it is a block
+	pub network_difficulty: u64,
+	/// Pre-pow header data sent to the workers, hex
+	pub pre_pow: String,
+}
+
+/// Validation result of the submitted share
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum StratumShareResult {
+	/// Valid share
+	Accepted,
+	/// Valid share that is a block solution, the block is accepted by the chain
+	BlockFound,
+	/// Share for the job that is not current any more
+	Stale,
+	/// Solution of the unsupported size
+	InvalidSize,
+	/// Share difficulty is below the job min share difficulty
+	LowDifficulty,
+	/// Proof of work is not valid
+	InvalidProof,
+	/// Block solution was not accepted by the chain
+	BlockRejected,
+}
+
+impl StratumShareResult {
+	fn to_u8(&self) -> u8 {
+		match self {
+			StratumShareResult::Accepted => 0,
+			StratumShareResult::BlockFound => 1,
+			StratumShareResult::Stale => 2,
+			StratumShareResult::InvalidSize => 3,
+			StratumShareResult::LowDifficulty => 4,
+			StratumShareResult::InvalidProof => 5,
+			StratumShareResult::BlockRejected => 6,
+		}
+	}
+
+	fn from_u8(v: u8) -> Result<StratumShareResult, ser::Error> {
+		Ok(match v {
+			0 => StratumShareResult::Accepted,
+			1 => StratumShareResult::BlockFound,
+			2 => StratumShareResult::Stale,
+			3 => StratumShareResult::InvalidSize,
+			4 => StratumShareResult::LowDifficulty,
+			5 => StratumShareResult::InvalidProof,
+			6 => StratumShareResult::BlockRejected,
+			_ => {
+				return Err(ser::Error::CorruptedData(format!(
+					"Unknown share result {}",
+					v
+				)))
+			}
+		})
+	}
+}
+
+/// Share submitted by the worker
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StratumShareRecord {
+	/// Unix time when the share was submitted
+	pub timestamp: i64,
+	/// Worker connection id
+	pub worker_id: u64,
+	/// Worker login, None if the worker didn't log in
+	pub login: Option<String>,
+	/// Worker IP address
+	pub ip: String,
+	/// Height from the share
+	pub height: u64,
+	/// Job id from the share
+	pub job_id: u64,
+	/// Nonce from the share
+	pub nonce: u64,
+	/// Edge bits from the share
+	pub edge_bits: u32,
+	/// Proof nonces from the share
+	pub pow: Vec<u64>,
+	/// Difficulty of the share, 0 if it was not calculated
+	pub difficulty: u64,
+	/// Hash of the header with the share, hex. None if the job was not found.
+	pub hash: Option<String>,
+	/// Validation result
+	pub result: StratumShareResult,
+}
+
+fn write_str<W: Writer>(writer: &mut W, s: &str) -> Result<(), ser::Error> {
+	if s.len() > 10_000 {
+		return Err(ser::Error::TooLargeWriteErr(format!(
+			"Unreasonable long stratum log field, length is {}",
+			s.len()
+		)));
+	}
+	writer.write_bytes(&s)
+}
+
+fn read_str<R: Reader>(reader: &mut R) -> Result<String, ser::Error> {
+	String::from_utf8(reader.read_bytes_len_prefix()?)
+		.map_err(|e| ser::Error::CorruptedData(format!("Fail to read stratum log field, {}", e)))
+}
+
+fn write_opt_str<W: Writer>(writer: &mut W, s: &Option<String>) -> Result<(), ser::Error> {
+	match s {
+		Some(s) => {
+			writer.write_u8(1)?;
+			write_str(writer, s)
+		}
+		None => writer.write_u8(0),
+	}
+}
+
+fn read_opt_str<R: Reader>(reader: &mut R) -> Result<Option<String>, ser::Error> {
+	match reader.read_u8()? {
+		0 => Ok(None),
+		_ => Ok(Some(read_str(reader)?)),
+	}
+}
+
+impl Writeable for StratumJobRecord {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_i64(self.timestamp)?;
+		writer.write_u64(self.height)?;
+		writer.write_u64(self.job_id)?;
+		writer.write_u64(self.share_difficulty)?;
+		writer.write_u64(self.network_difficulty)?;
+		write_str(writer, &self.pre_pow)
+	}
+}
+
+impl Readable for StratumJobRecord {
+	fn read<R: Reader>(reader: &mut R) -> Result<StratumJobRecord, ser::Error> {
+		Ok(StratumJobRecord {
+			timestamp: reader.read_i64()?,
+			height: reader.read_u64()?,
+			job_id: reader.read_u64()?,
+			share_difficulty: reader.read_u64()?,
+			network_difficulty: reader.read_u64()?,
+			pre_pow: read_str(reader)?,
+		})
+	}
+}
+
+impl Writeable for StratumShareRecord {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_i64(self.timestamp)?;
+		writer.write_u64(self.worker_id)?;
+		write_opt_str(writer, &self.login)?;
+		write_str(writer, &self.ip)?;
+		writer.write_u64(self.height)?;
+		writer.write_u64(self.job_id)?;
+		writer.write_u64(self.nonce)?;
+		writer.write_u32(self.edge_bits)?;
+		if self.pow.len() as u64 > MAX_PROOF_SIZE {
+			return Err(ser::Error::TooLargeWriteErr(format!(
+				"Unreasonable long proof, length is {}",
+				self.pow.len()
+			)));
+		}
+		writer.write_u64(self.pow.len() as u64)?;
+		for nonce in &self.pow {
+			writer.write_u64(*nonce)?;
+		}
+		writer.write_u64(self.difficulty)?;
+		write_opt_str(writer, &self.hash)?;
+		writer.write_u8(self.result.to_u8())
+	}
+}
+
+impl Readable for StratumShareRecord {
+	fn read<R: Reader>(reader: &mut R) -> Result<StratumShareRecord, ser::Error> {
+		let timestamp = reader.read_i64()?;
+		let worker_id = reader.read_u64()?;
+		let login = read_opt_str(reader)?;
+		let ip = read_str(reader)?;
+		let height = reader.read_u64()?;
+		let job_id = reader.read_u64()?;
+		let nonce = reader.read_u64()?;
+		let edge_bits = reader.read_u32()?;
+		let pow_len = reader.read_u64()?;
+		if pow_len > MAX_PROOF_SIZE {
+			return Err(ser::Error::TooLargeReadErr(format!(
+				"Unreasonable long proof, length is {}",
+				pow_len
+			)));
+		}
+		let mut pow = Vec::with_capacity(pow_len as usize);
+		for _ in 0..pow_len {
+			pow.push(reader.read_u64()?);
+		}
+		let difficulty = reader.read_u64()?;
+		let hash = read_opt_str(reader)?;
+		let result = StratumShareResult::from_u8(reader.read_u8()?)?;
+		Ok(StratumShareRecord {
+			timestamp,
+			worker_id,
+			login,
+			ip,
+			height,
+			job_id,
+			nonce,
+			edge_bits,
+			pow,
+			difficulty,
+			hash,
+			result,
+		})
+	}
+}
+
+/// Stratum log query, all conditions are optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StratumAuditQuery {
+	/// Records of this height only
+	pub height: Option<u64>,
+	/// Records of this job id only, usually together with the height
+	pub job_id: Option<u64>,
+	/// Shares of this worker login only
+	pub login: Option<String>,
+	/// Records at this unix time or later
+	pub from_time: Option<i64>,
+	/// Records at this unix time or earlier
+	pub to_time: Option<i64>,
+	/// Max number of the records of every kind, the most recent are returned.
+	/// Default and max is 1000.
+	pub limit: Option<usize>,
+}
+
+impl StratumAuditQuery {
+	fn matches(&self, timestamp: i64, height: u64, job_id: u64) -> bool {
+		self.height.map_or(true, |h| h == height)
+			&& self.job_id.map_or(true, |j| j == job_id)
+			&& self.from_time.map_or(true, |t| timestamp >= t)
+			&& self.to_time.map_or(true, |t| timestamp <= t)
+	}
+
+	fn matches_job(&self, job: &StratumJobRecord) -> bool {
+		self.matches(job.timestamp, job.height, job.job_id)
+	}
+
+	fn matches_share(&self, share: &StratumShareRecord) -> bool {
+		self.matches(share.timestamp, share.height, share.job_id)
+			&& self
+				.login
+				.as_ref()
+				.map_or(true, |l| Some(l) == share.login.as_ref())
+	}
+}
+
+/// Persistent log of the stratum jobs and shares
+pub struct StratumAuditLog {
+	db: grin_store::Store,
+	retention_secs: i64,
+	max_entries: u64,
+	count: AtomicU64,
+	seq: AtomicU64,
+}
+
+impl StratumAuditLog {
+	/// Open the log under the provided root path
+	pub fn new(
+		db_root: &str,
+		retention_days: u32,
+		max_entries: u64,
+	) -> Result<StratumAuditLog, Error> {
+		let db = grin_store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		let count = db
+			.iter::<StratumJobRecord>(&to_key(JOB_PREFIX, ""))?
+			.count() as u64
+			+ db.iter::<StratumShareRecord>(&to_key(SHARE_PREFIX, ""))?
+				.count() as u64;
+		Ok(StratumAuditLog {
+			db,
+			retention_secs: retention_days as i64 * 24 * 3600,
+			max_entries,
+			count: AtomicU64::new(count),
+			seq: AtomicU64::new(0),
+		})
+	}
+
+	fn put<T: Writeable>(&self, prefix: u8, timestamp: i64, record: &T) -> Result<(), Error> {
+		// Sequence number keeps the records of the same second apart and in order
+		let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+		let key = to_key_u64(prefix, &timestamp.to_be_bytes(), seq);
+		let batch = self.db.batch()?;
+		batch.put_ser(&key, record)?;
+		batch.commit()?;
+		let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+		if seq % PRUNE_INTERVAL == 0 || count > self.max_entries + PRUNE_INTERVAL {
+			self.prune(timestamp)?;
+		}
+		Ok(())
+	}
+
+	/// Record the job sent to the workers
+	pub fn record_job(&self, job: &StratumJobRecord) -> Result<(), Error> {
+		self.put(JOB_PREFIX, job.timestamp, job)
+	}
+
+	/// Record the submitted share
+	pub fn record_share(&self, share: &StratumShareRecord) -> Result<(), Error> {
+		self.put(SHARE_PREFIX, share.timestamp, share)
+	}
+
+	/// Delete the records older than the retention period and the oldest records
+	/// above the size limit. Returns the number of the deleted records.
+	pub fn prune(&self, now: i64) -> Result<u64, Error> {
+		let min_time = now - self.retention_secs;
+		let mut entries: Vec<(Vec<u8>, i64)> = self
+			.db
+			.iter::<StratumJobRecord>(&to_key(JOB_PREFIX, ""))?
+			.map(|(k, v)| (k, v.timestamp))
+			.collect();
+		entries.extend(
+			self.db
+				.iter::<StratumShareRecord>(&to_key(SHARE_PREFIX, ""))?
+				.map(|(k, v)| (k, v.timestamp)),
+		);
+		// Jobs and shares are pruned together, the oldest first
+		entries.sort_by_key(|(_, timestamp)| *timestamp);
+		let over_limit = (entries.len() as u64).saturating_sub(self.max_entries);
+		let batch = self.db.batch()?;
+		let mut deleted = 0;
+		for (i, (key, timestamp)) in entries.iter().enumerate() {
+			if (i as u64) < over_limit || *timestamp < min_time {
+				batch.delete(key)?;
+				deleted += 1;
+			} else {
+				break;
+			}
+		}
+		batch.commit()?;
+		self.count
+			.store(entries.len() as u64 - deleted, Ordering::Relaxed);
+		if deleted > 0 {
+			debug!("Stratum log pruned, {} records deleted", deleted);
+		}
+		Ok(deleted)
+	}
+
+	/// Jobs and shares that match the query, the oldest first
+	pub fn query(
+		&self,
+		query: &StratumAuditQuery,
+	) -> Result<(Vec<StratumJobRecord>, Vec<StratumShareRecord>), Error> {
+		let limit = query
+			.limit
+			.unwrap_or(STRATUM_AUDIT_QUERY_LIMIT)
+			.min(STRATUM_AUDIT_QUERY_LIMIT);
+		let mut jobs: Vec<StratumJobRecord> = self
+			.db
+			.iter::<StratumJobRecord>(&to_key(JOB_PREFIX, ""))?
+			.map(|(_, v)| v)
+			.filter(|j| query.matches_job(j))
+			.collect();
+		if jobs.len() > limit {
+			jobs.drain(..jobs.len() - limit);
+		}
+		let mut shares: Vec<StratumShareRecord> = self
+			.db
+			.iter::<StratumShareRecord>(&to_key(SHARE_PREFIX, ""))?
+			.map(|(_, v)| v)
+			.filter(|s| query.matches_share(s))
+			.collect();
+		if shares.len() > limit {
+			shares.drain(..shares.len() - limit);
+		}
+		Ok((jobs, shares))
+	}
+}
+
+lazy_static! {
+	// Stratum log of the node, None if it is not enabled
+	static ref STRATUM_AUDIT: RwLock<Option<Arc<StratumAuditLog>>> = RwLock::new(None);
+}
+
+/// Set the stratum log of the node
+pub fn init_stratum_audit(log: StratumAuditLog) {
+	*STRATUM_AUDIT.write() = Some(Arc::new(log));
+}
+
+/// Stratum log of the node, None if it is not enabled
+pub fn stratum_audit() -> Option<Arc<StratumAuditLog>> {
+	STRATUM_AUDIT.read().clone()
+}
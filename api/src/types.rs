@@ -16,8 +16,10 @@ use crate::chain;
 use crate::core::core::hash::Hashed;
 use crate::core::core::merkle_proof::MerkleProof;
 use crate::core::core::{KernelFeatures, TxKernel};
-use crate::core::{core, ser};
+use crate::core::{consensus, core, global, ser};
 use crate::p2p;
+use crate::pool;
+use crate::stratum_audit::{StratumJobRecord, StratumShareRecord};
 use crate::util::secp::pedersen;
 use crate::util::{self, ToHex};
 #[cfg(feature = "marketplace")]
@@ -44,6 +46,91 @@ pub struct Version {
 	pub block_header_version: u16,
 }
 
+/// Status of the hard fork era relative to the chain head
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ProtocolEraStatus {
+	/// Era is finished, the next hard fork is activated
+	Past,
+	/// Chain head is in this era
+	Active,
+	/// Hard fork is not activated yet
+	Upcoming,
+}
+
+/// Hard fork era of the protocol schedule
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProtocolEra {
+	/// Activation height of the era
+	pub start_height: u64,
+	/// Last height of the era, None for the last scheduled era
+	pub end_height: Option<u64>,
+	/// Block header version expected during the era
+	pub header_version: u16,
+	/// Era status relative to the chain head
+	pub status: ProtocolEraStatus,
+	/// Number of blocks until the activation, 0 for the activated eras
+	pub blocks_until_activation: u64,
+}
+
+/// Hard fork schedule known to the node
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProtocolSchedule {
+	/// Chain type of the node (Mainnet, Floonet, ...)
+	pub chain_type: String,
+	/// Height of the chain head
+	pub height: u64,
+	/// Block header version of the chain head
+	pub header_version: u16,
+	/// Max block header version that this node can validate, the version of the last
+	/// scheduled era of the chain type
+	pub max_supported_header_version: u16,
+	/// Scheduled eras, ordered by the activation height
+	pub eras: Vec<ProtocolEra>,
+}
+
+impl ProtocolSchedule {
+	/// Build the schedule for the chain head height from the consensus hard fork schedule
+	pub fn from_schedule(
+		schedule: &[consensus::HardFork],
+		height: u64,
+		header_version: u16,
+	) -> ProtocolSchedule {
+		let eras = schedule
+			.iter()
+			.enumerate()
+			.map(|(i, fork)| {
+				let end_height = schedule.get(i + 1).map(|next| next.height - 1);
+				let status = if fork.height > height {
+					ProtocolEraStatus::Upcoming
+				} else if end_height.map(|h| h < height).unwrap_or(false) {
+					ProtocolEraStatus::Past
+				} else {
+					ProtocolEraStatus::Active
+				};
+				ProtocolEra {
+					start_height: fork.height,
+					end_height,
+					header_version: fork.version.into(),
+					status,
+					blocks_until_activation: fork.height.saturating_sub(height),
+				}
+			})
+			.collect::<Vec<_>>();
+
+		ProtocolSchedule {
+			chain_type: format!("{:?}", global::get_chain_type()),
+			height,
+			header_version,
+			max_supported_header_version: schedule
+				.iter()
+				.map(|fork| fork.version.into())
+				.max()
+				.unwrap_or(header_version),
+			eras,
+		}
+	}
+}
+
 /// The state of the current fork tip
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Tip {
@@ -68,6 +155,24 @@ impl Tip {
 	}
 }
 
+/// Result of the node health or readiness check
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HealthStatus {
+	/// True if all checks passed
+	pub ok: bool,
+	/// Height of the chain head, None if it can't be read
+	pub height: Option<u64>,
+	/// Height of the best connected peer, readiness check only
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub peer_height: Option<u64>,
+	/// Rangeproofs validation if the chain state is validated with the spot-check,
+	/// readiness check only
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub rangeproof_validation: Option<chain::RangeproofValidation>,
+	/// Failed checks
+	pub failures: Vec<String>,
+}
+
 /// Status page containing different server information
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Status {
@@ -84,6 +189,21 @@ pub struct Status {
 	// Additional sync information
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub sync_info: Option<serde_json::Value>,
+	// Onion service PoW defense state, if it is enabled
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tor_pow_defense: Option<TorPowDefenseStatus>,
+	// Last chain error that needs attention, with the recovery hint
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub chain_error: Option<chain::ChainErrorInfo>,
+	// How the last state sync source was selected
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sync_source: Option<chain::SyncSourceDecision>,
+	// Digest of the UTXO set at the tip, if it is enabled
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub utxo_digest: Option<chain::UtxoSetDigest>,
+	// Rangeproofs validation, if the chain state is validated with the spot-check
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub rangeproof_validation: Option<chain::RangeproofValidation>,
 }
 
 impl Status {
@@ -100,10 +220,30 @@ impl Status {
 			tip: Tip::from_tip(current_tip),
 			sync_status,
 			sync_info,
+			tor_pow_defense: crate::owner::get_tor_pow_defense(),
+			chain_error: None,
+			sync_source: None,
+			utxo_digest: None,
+			rangeproof_validation: None,
 		}
 	}
 }
 
+/// Onion service PoW defense state
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TorPowDefenseStatus {
+	/// Defense level, 0 - no extra effort is required
+	pub level: u8,
+	/// Current intro queue rate
+	pub queue_rate: u32,
+	/// Current intro queue burst
+	pub queue_burst: u32,
+	/// Inbound connections per minute on the onion listener during the last window
+	pub inbound_per_min: u32,
+	/// Unix time when the level was changed last time
+	pub since: i64,
+}
+
 /// TxHashSet
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TxHashSet {
@@ -758,12 +898,283 @@ pub struct LocatedTxKernel {
 	pub mmr_index: u64,
 }
 
+/// Kernel from the kernel fee index
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KernelFeePrintable {
+	/// Kernel excess commitment, hex
+	pub excess: String,
+	/// Kernel fee
+	pub fee: u64,
+	/// Height of the block that contains the kernel
+	pub height: u64,
+}
+
+/// Number of kernels with fee in the range [min_fee, max_fee]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeeHistogramBucket {
+	pub min_fee: u64,
+	pub max_fee: u64,
+	pub count: u64,
+}
+
+/// Kernel fee index building progress
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KernelFeeIndexStatus {
+	/// First indexed height
+	pub start_height: u64,
+	/// Last indexed height
+	pub height: u64,
+	/// Current chain height
+	pub chain_height: u64,
+	/// Indexing progress in percents
+	pub progress: u8,
+}
+
+/// Block weight utilization with the rolling fullness of the preceding blocks
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlockFullness {
+	/// Block height
+	pub height: u64,
+	/// Block hash
+	pub hash: String,
+	/// Block consensus weight, None if the block is compacted away
+	pub weight: Option<u64>,
+	/// Max block weight
+	pub max_weight: u64,
+	/// Part of the max weight used by the block, percents
+	pub utilization: Option<f64>,
+	/// Average utilization of the window ending with this block, percents. The compacted
+	/// blocks are not counted.
+	pub rolling_fullness: Option<f64>,
+}
+
+/// Header chain between two heights, for the light clients. Headers are serialized back to
+/// back with the local protocol version, so the client can deserialize them one by one,
+/// check that every header links to the previous one and that the total difficulty grows
+/// by the difficulty of every header.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HeaderChainProof {
+	/// Height of the first header
+	pub from_height: u64,
+	/// Height of the last header
+	pub to_height: u64,
+	/// Protocol version of the headers serialization
+	pub protocol_version: u32,
+	/// Hash of the header before the first one, the chain starts from it
+	pub prev_hash: String,
+	/// Total difficulty of the header before the first one, 0 for genesis
+	pub start_total_difficulty: u64,
+	/// Total difficulty of the last header
+	pub total_difficulty: u64,
+	/// Hash of the last header
+	pub last_hash: String,
+	/// Serialized headers, hex
+	pub headers: String,
+}
+
+/// Output ownership proof. The challenge is signed with the output blinding factor,
+/// the amount is revealed, so the proofs can be used for the proof of reserves.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OwnershipProof {
+	/// Output commitment, hex
+	pub commit: String,
+	/// Output amount
+	pub amount: u64,
+	/// Challenge provided by the verifier
+	pub challenge: String,
+	/// Compact signature of the challenge, hex
+	pub signature: String,
+}
+
+/// Result of the output ownership proof verification
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OwnershipProofVerification {
+	/// Output commitment, hex
+	pub commit: String,
+	/// Output amount
+	pub amount: u64,
+	/// Whether the output is in the UTXO set
+	pub unspent: bool,
+	/// Height of the block that contains the output, None if the output is not unspent
+	pub height: Option<u64>,
+	/// True if the output is unspent and the signature is valid for the commitment and amount
+	pub valid: bool,
+}
+
+/// State of the anti deep reorg rule
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReorgGuard {
+	/// Max reorg depth the node accepts, None if the reorgs are not limited
+	pub max_reorg_depth: Option<u64>,
+	/// Reorgs of any depth are accepted until this time (unix timestamp), operator override
+	pub deep_reorgs_allowed_until: Option<i64>,
+}
+
+/// Timing breakdown of the accepted block processing, durations in microseconds
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlockTimingsPrintable {
+	/// Block hash, hex
+	pub hash: String,
+	/// Block height
+	pub height: u64,
+	/// Time when the block processing was finished
+	pub timestamp: i64,
+	/// Number of inputs, outputs and kernels in the block
+	pub inputs: usize,
+	pub outputs: usize,
+	pub kernels: usize,
+	/// Block deserialization, 0 if block wasn't received from the peer
+	pub deserialize: u64,
+	/// Header processing, block rules and contextual (UTXO, maturity, sums) checks
+	pub contextual_checks: u64,
+	/// Rangeproofs batch verification
+	pub rangeproof_batch: u64,
+	/// Kernel signatures batch verification
+	pub kernel_sig_batch: u64,
+	/// Applying the block to the MMRs
+	pub mmr_apply: u64,
+	/// LMDB batch commit
+	pub db_commit: u64,
+	/// Total processing time
+	pub total: u64,
+}
+
+impl BlockTimingsPrintable {
+	pub fn from_timings(timings: &chain::BlockTimings) -> BlockTimingsPrintable {
+		BlockTimingsPrintable {
+			hash: timings.hash.to_hex(),
+			height: timings.height,
+			timestamp: timings.timestamp,
+			inputs: timings.inputs,
+			outputs: timings.outputs,
+			kernels: timings.kernels,
+			deserialize: timings.deserialize,
+			contextual_checks: timings.contextual_checks,
+			rangeproof_batch: timings.rangeproof_batch,
+			kernel_sig_batch: timings.kernel_sig_batch,
+			mmr_apply: timings.mmr_apply,
+			db_commit: timings.db_commit,
+			total: timings.total,
+		}
+	}
+}
+
+/// Integrity kernel budget alert
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum IntegrityBudgetAlert {
+	/// Kernel is not found in the last INTEGRITY_FEE_VALID_BLOCKS blocks. It is not mined
+	/// yet or it is expired, the peers don't accept its messages.
+	NotInValidityWindow,
+	/// Kernel will expire soon
+	ExpiresSoon,
+	/// Kernel was used too often, the peers would reject the next message
+	RateLimited,
+}
+
+/// Integrity kernel registered by the node operator for publishing the gossip messages
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct IntegrityKernelBudget {
+	/// Kernel excess, hex
+	pub excess: String,
+	/// Height of the block with the kernel, None if it is not in the validity window
+	pub height: Option<u64>,
+	/// Kernel fee
+	pub fee: u64,
+	/// Number of the blocks the kernel is still accepted by the peers
+	pub blocks_left: u64,
+	/// Number of the messages published with the kernel
+	pub messages_published: u64,
+	/// Number of the messages that can be published now without hitting the peers rate limit
+	pub rate_budget: usize,
+	/// Kernel problems the operator should be aware of
+	pub alerts: Vec<IntegrityBudgetAlert>,
+}
+
+/// Integrity kernels budget of the node
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct IntegrityBudget {
+	/// Current chain height
+	pub tip_height: u64,
+	/// Registered kernels
+	pub kernels: Vec<IntegrityKernelBudget>,
+	/// True if none of the kernels will stay valid for long, the wallet should top up
+	pub top_up_needed: bool,
+}
+
+/// Gossip messages audit log query result
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GossipAudit {
+	/// False if the audit log is not enabled with the libp2p_audit_topics config
+	pub enabled: bool,
+	/// Records that match the query, the oldest first
+	pub entries: Vec<p2p::GossipAuditEntry>,
+}
+
+/// Stratum jobs and shares log query result
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StratumAudit {
+	/// False if the log is not enabled with the stratum share_audit config
+	pub enabled: bool,
+	/// Jobs that match the query, the oldest first
+	pub jobs: Vec<StratumJobRecord>,
+	/// Shares that match the query, the oldest first
+	pub shares: Vec<StratumShareRecord>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct PoolInfo {
 	/// Size of the pool
 	pub pool_size: usize,
 }
 
+/// State of the transaction in the pool
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum PoolTxState {
+	/// Transaction is in the txpool, it is returned by get_unconfirmed_transactions
+	Txpool,
+	/// Transaction is in the stempool, it is relayed with Dandelion and moves to the
+	/// txpool once it is fluffed. Reported for the transactions pushed with the api only.
+	Stempool,
+	/// Transaction is not in the pool. It was never accepted, it is mined or evicted.
+	NotFound,
+}
+
+/// Result of the synchronous transaction push
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PushedTransaction {
+	/// Hash of the pushed transaction
+	pub tx_hash: String,
+	/// Kernel excesses of the transaction, any of them can be used to poll the state
+	pub kernels: Vec<String>,
+	/// State of the transaction in the pool right after the push
+	pub state: PoolTxState,
+}
+
+/// Transaction waiting in the stempool
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StemTransaction {
+	/// Hash of the transaction
+	pub tx_hash: String,
+	/// Kernel excesses of the transaction
+	pub kernels: Vec<String>,
+	/// Where the transaction came from
+	pub src: pool::TxSource,
+	/// Unix time when the transaction was added to the stempool
+	pub received: i64,
+	/// Unix time when the embargo expires and the transaction is fluffed. The Dandelion
+	/// monitor adds up to 30 seconds of the random delay.
+	pub embargo_expiry: i64,
+}
+
+/// Dandelion state of the node
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DandelionStatus {
+	/// Current epoch, None if the node doesn't relay the stem transactions
+	pub epoch: Option<pool::DandelionEpochInfo>,
+	/// Transactions in the stempool, the oldest first
+	pub stem_txs: Vec<StemTransaction>,
+}
+
 /// Libp2p peers from the node
 /// There are libp2p peers node  is connected to and node peers with tor addresses
 /// libp2p peers are preferable, nodes wit tor addresses can be used to expand the network
@@ -783,6 +1194,15 @@ pub struct Libp2pMessages {
 	/// Other nodes. There is a high chance that they are running libp2p network
 	pub libp2p_messages: Vec<libp2p_connection::ReceivedMessage>,
 }
+#[cfg(feature = "marketplace")]
+/// Libp2p gossipsub diagnostics from this node
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Libp2pGossipDiagnostics {
+	/// Time when diagnostics was collected
+	pub current_time: i64,
+	/// Per topic mesh and message stats
+	pub topics: Vec<libp2p_connection::TopicDiagnostics>,
+}
 
 #[cfg(test)]
 mod test {
@@ -817,4 +1237,34 @@ mod test {
 		let serialized = serde_json::to_string(&deserialized).unwrap();
 		assert_eq!(serialized, hex_commit);
 	}
+
+	#[test]
+	fn protocol_schedule_eras() {
+		global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+		let schedule = consensus::hard_fork_schedule();
+
+		let res = ProtocolSchedule::from_schedule(&schedule, 7, 3);
+		assert_eq!(res.max_supported_header_version, 4);
+		let status: Vec<ProtocolEraStatus> = res.eras.iter().map(|e| e.status).collect();
+		assert_eq!(
+			status,
+			vec![
+				ProtocolEraStatus::Past,
+				ProtocolEraStatus::Past,
+				ProtocolEraStatus::Active,
+				ProtocolEraStatus::Upcoming
+			]
+		);
+		assert_eq!(res.eras[2].end_height, Some(8));
+		assert_eq!(res.eras[3].end_height, None);
+		assert_eq!(res.eras[3].blocks_until_activation, 2);
+
+		// Mainnet schedule doesn't have the testing hard forks
+		global::set_local_chain_type(global::ChainTypes::Mainnet);
+		let schedule = consensus::hard_fork_schedule();
+		let res = ProtocolSchedule::from_schedule(&schedule, 100, 1);
+		assert_eq!(res.max_supported_header_version, 2);
+		assert_eq!(res.eras.len(), 2);
+		assert_eq!(res.eras[1].status, ProtocolEraStatus::Upcoming);
+	}
 }
@@ -3,6 +3,7 @@ use crate::router::ResponseFuture;
 use bytes::Buf;
 use futures::future::ok;
 use hyper::body;
+use hyper::header::{HeaderValue, ETAG, IF_NONE_MATCH};
 use hyper::{Body, Request, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -48,10 +49,68 @@ where
 				StatusCode::INTERNAL_SERVER_ERROR,
 				format!("P2P Error, {}", err),
 			),
+			ErrorKind::Standby(msg) => response(StatusCode::SERVICE_UNAVAILABLE, msg.clone()),
+			ErrorKind::ColdStorage(msg) => response(StatusCode::SERVICE_UNAVAILABLE, msg.clone()),
 		},
 	}
 }
 
+/// Quoted ETag value built from the parts, e.g. the block hash and the response variant
+pub fn make_etag(parts: &[&str]) -> String {
+	format!("\"{}\"", parts.join("-"))
+}
+
+/// True if the If-None-Match header of the request has the ETag. Weak comparison is used,
+/// as RFC 7232 requires for If-None-Match.
+pub fn etag_matches(req: &Request<Body>, etag: &str) -> bool {
+	let etag = etag.trim_start_matches("W/");
+	req.headers()
+		.get_all(IF_NONE_MATCH)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.map(|v| v.trim())
+		.any(|v| v == "*" || v.trim_start_matches("W/") == etag)
+}
+
+/// 304 Not Modified response with the ETag
+pub fn not_modified_response(etag: &str) -> ResponseFuture {
+	let mut resp = just_response(StatusCode::NOT_MODIFIED, Body::empty());
+	if let Ok(value) = HeaderValue::from_str(etag) {
+		resp.headers_mut().insert(ETAG, value);
+	}
+	Box::pin(ok(resp))
+}
+
+/// Conditional response for the resource with the ETag. 304 Not Modified is returned
+/// if the client already has it, otherwise the result is serialized and sent with the
+/// ETag header. Errors are sent without the ETag.
+pub fn etag_response<T, F>(req: &Request<Body>, etag: &str, f: F) -> ResponseFuture
+where
+	T: Serialize,
+	F: FnOnce() -> Result<T, Error>,
+{
+	if etag_matches(req, etag) {
+		return not_modified_response(etag);
+	}
+	match f() {
+		Ok(s) => match serde_json::to_string_pretty(&s) {
+			Ok(json) => {
+				let mut resp = just_response(StatusCode::OK, json);
+				if let Ok(value) = HeaderValue::from_str(etag) {
+					resp.headers_mut().insert(ETAG, value);
+				}
+				Box::pin(ok(resp))
+			}
+			Err(e) => response(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				format!("can't create json response: {}", e),
+			),
+		},
+		Err(e) => result_to_response::<T>(Err(e)),
+	}
+}
+
 /// Utility to serialize a struct into JSON and produce a sensible Response
 /// out of it.
 pub fn json_response<T>(s: &T) -> ResponseFuture
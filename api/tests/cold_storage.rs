@@ -0,0 +1,38 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_api as api;
+
+use crate::api::ErrorKind;
+
+#[test]
+fn cold_storage_rejects_writes() {
+	assert!(!api::is_cold_storage());
+	assert_eq!(api::cold_storage_snapshot(), None);
+	assert!(api::check_not_cold_storage("push_transaction").is_ok());
+
+	api::init_cold_storage("/audit/chain_data");
+	assert!(api::is_cold_storage());
+	assert_eq!(
+		api::cold_storage_snapshot(),
+		Some("/audit/chain_data".to_string())
+	);
+	match api::check_not_cold_storage("push_transaction") {
+		Err(e) => match e.kind() {
+			ErrorKind::ColdStorage(msg) => assert!(msg.starts_with("push_transaction")),
+			kind => panic!("unexpected error {:?}", kind),
+		},
+		Ok(_) => panic!("push_transaction must be rejected in cold storage mode"),
+	}
+}
@@ -0,0 +1,82 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_api as api;
+use grin_chain as chain;
+
+use crate::api::{classify_risk, recommended_confirmations, ConfirmationRisk, ForkActivity};
+use crate::chain::{BlockStatus, Tip};
+
+fn tip(height: u64) -> Tip {
+	Tip {
+		height,
+		..Tip::default()
+	}
+}
+
+fn reorg(prev_head: u64, fork_point: u64) -> BlockStatus {
+	BlockStatus::Reorg {
+		prev: tip(fork_point),
+		prev_head: tip(prev_head),
+		fork_point: tip(fork_point),
+	}
+}
+
+fn fork() -> BlockStatus {
+	BlockStatus::Fork {
+		prev: tip(99),
+		head: tip(100),
+		fork_point: tip(99),
+	}
+}
+
+#[test]
+fn test_fork_activity() {
+	let mut activity = ForkActivity::new();
+	let now = 1_600_000_000;
+
+	activity.block_accepted(&BlockStatus::Next { prev: tip(99) }, now);
+	assert_eq!(activity.summary(now).reorgs, 0);
+
+	activity.block_accepted(&fork(), now);
+	activity.block_accepted(&reorg(100, 97), now);
+	activity.block_accepted(&reorg(120, 119), now + 3600);
+	let summary = activity.summary(now + 3600);
+	assert_eq!(summary.reorgs, 2);
+	assert_eq!(summary.max_reorg_depth, 3);
+	assert_eq!(summary.fork_blocks, 1);
+
+	// The first reorg and the fork block are out of the window
+	let summary = activity.summary(now + 24 * 3600 + 1);
+	assert_eq!(summary.reorgs, 1);
+	assert_eq!(summary.max_reorg_depth, 1);
+	assert_eq!(summary.fork_blocks, 0);
+}
+
+#[test]
+fn test_confirmation_risk() {
+	// No reorgs
+	assert_eq!(recommended_confirmations(0), 10);
+	assert_eq!(classify_risk(1, 0), ConfirmationRisk::High);
+	assert_eq!(classify_risk(2, 0), ConfirmationRisk::High);
+	assert_eq!(classify_risk(3, 0), ConfirmationRisk::Medium);
+	assert_eq!(classify_risk(10, 0), ConfirmationRisk::Low);
+
+	// Recent reorg 5 blocks deep
+	assert_eq!(recommended_confirmations(5), 20);
+	assert_eq!(classify_risk(5, 5), ConfirmationRisk::High);
+	assert_eq!(classify_risk(6, 5), ConfirmationRisk::Medium);
+	assert_eq!(classify_risk(19, 5), ConfirmationRisk::Medium);
+	assert_eq!(classify_risk(20, 5), ConfirmationRisk::Low);
+}
@@ -0,0 +1,70 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_api as api;
+
+use crate::api::*;
+use futures::executor::block_on;
+use hyper::header::{ETAG, IF_NONE_MATCH};
+use hyper::{Body, Request, StatusCode};
+
+fn request(if_none_match: Option<&str>) -> Request<Body> {
+	let mut builder = Request::builder().uri("/v1/headers/100");
+	if let Some(value) = if_none_match {
+		builder = builder.header(IF_NONE_MATCH, value);
+	}
+	builder.body(Body::empty()).unwrap()
+}
+
+#[test]
+fn test_etag_matches() {
+	let etag = make_etag(&["abc", "def"]);
+	assert_eq!(etag, "\"abc-def\"");
+	assert!(!etag_matches(&request(None), &etag));
+	assert!(etag_matches(&request(Some("\"abc-def\"")), &etag));
+	assert!(etag_matches(
+		&request(Some("\"xyz\", W/\"abc-def\"")),
+		&etag
+	));
+	assert!(etag_matches(&request(Some("*")), &etag));
+	assert!(!etag_matches(&request(Some("\"abc\"")), &etag));
+}
+
+#[test]
+fn test_etag_response() {
+	let etag = make_etag(&["abc"]);
+
+	let resp = block_on(etag_response(&request(None), &etag, || Ok(vec![1, 2, 3]))).unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(resp.headers().get(ETAG).unwrap(), "\"abc\"");
+
+	// Resource is not built for the client that already has it
+	let resp = block_on(etag_response(
+		&request(Some("\"abc\"")),
+		&etag,
+		|| -> Result<Vec<u32>, Error> { panic!("must not be called") },
+	))
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+	assert_eq!(resp.headers().get(ETAG).unwrap(), "\"abc\"");
+
+	let resp = block_on(etag_response(
+		&request(None),
+		&etag,
+		|| -> Result<u32, Error> { Err(ErrorKind::NotFound("header".to_string()).into()) },
+	))
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+	assert!(resp.headers().get(ETAG).is_none());
+}
@@ -0,0 +1,48 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_api as api;
+
+use crate::api::*;
+#[cfg(feature = "grpc")]
+use grin_pool::TxRemovalReason;
+
+#[test]
+fn test_grpc_config() {
+	let config = GrpcConfig::default();
+	assert!(!config.enable_grpc);
+	assert_eq!(config.grpc_addr, "127.0.0.1:3419");
+
+	let config: GrpcConfig = serde_json::from_str(r#"{"enable_grpc": true}"#).unwrap();
+	assert!(config.enable_grpc);
+	assert_eq!(config.grpc_addr, GrpcConfig::default().grpc_addr);
+}
+
+#[cfg(feature = "grpc")]
+#[test]
+fn test_grpc_events() {
+	let message = TxPoolEventMessage {
+		seq: 7,
+		event: TxPoolEvent::TxRemoved {
+			tx_hash: "9e2b".to_string(),
+			kernels: vec!["08a8".to_string()],
+			reason: TxRemovalReason::Block,
+		},
+	};
+	let json = serde_json::to_string(&message).unwrap();
+	let event = to_proto_event(&json);
+	assert_eq!(event.seq, 7);
+	assert_eq!(event.event, "tx_removed");
+	assert_eq!(event.json, json);
+}
@@ -0,0 +1,205 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_api as api;
+
+use crate::api::auth::uri_matches;
+use crate::api::{response, BasicAuthMiddleware, MWC_BASIC_REALM};
+use crate::api::{
+	Handler, HandlerObj, OwnerLoginRequest, OwnerSessionConfig, OwnerSessionMiddleware,
+	OwnerSessions, ResponseFuture,
+};
+use chrono::Utc;
+use futures::executor::block_on;
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Request, StatusCode};
+use std::sync::Arc;
+
+fn sessions() -> OwnerSessions {
+	OwnerSessions::new(OwnerSessionConfig {
+		enable_owner_sessions: true,
+		owner_session_ttl_secs: 100,
+		owner_max_sessions: 2,
+		owner_audit_log_size: 3,
+	})
+}
+
+fn login(sessions: &OwnerSessions, label: &str, ttl_secs: Option<u64>, now: i64) -> String {
+	sessions
+		.login(
+			&OwnerLoginRequest {
+				label: Some(label.to_string()),
+				ttl_secs,
+			},
+			now,
+		)
+		.unwrap()
+		.token
+}
+
+#[test]
+fn test_session_expiry() {
+	let sessions = sessions();
+	let token = login(&sessions, "alice", None, 1000);
+	let session = sessions.validate(&token, 1000).unwrap();
+	assert_eq!(session.label, "alice");
+	assert_eq!(session.expires, 1100);
+	assert!(sessions.validate(&token, 1099).is_some());
+	assert!(sessions.validate(&token, 1100).is_none());
+	assert!(sessions.validate("unknown", 1000).is_none());
+
+	// Requested lifetime is limited by the configured one
+	let token = login(&sessions, "bob", Some(10), 1000);
+	assert_eq!(sessions.validate(&token, 1000).unwrap().expires, 1010);
+	let token = login(&sessions, "bob", Some(1000), 1000);
+	assert_eq!(sessions.validate(&token, 1000).unwrap().expires, 1100);
+}
+
+#[test]
+fn test_session_limit_and_gc() {
+	let sessions = sessions();
+	login(&sessions, "alice", None, 1000);
+	login(&sessions, "bob", Some(10), 1000);
+	assert!(sessions.login(&OwnerLoginRequest::default(), 1005).is_err());
+	// Expired session is dropped and frees the slot
+	assert!(sessions.login(&OwnerLoginRequest::default(), 1010).is_ok());
+	assert_eq!(sessions.sessions(1010).len(), 2);
+	assert_eq!(sessions.sessions(1100).len(), 1);
+}
+
+#[test]
+fn test_session_revoke_and_audit() {
+	let sessions = sessions();
+	let alice_token = login(&sessions, "alice", None, 1000);
+	let bob_token = login(&sessions, "bob", None, 1000);
+	let alice = sessions.validate(&alice_token, 1000).unwrap();
+	let bob = sessions.validate(&bob_token, 1000).unwrap();
+
+	sessions.record(&bob, "get_status", 1001);
+	assert!(sessions.revoke(&bob.id, &alice, 1002));
+	assert!(sessions.validate(&bob_token, 1002).is_none());
+	assert!(!sessions.revoke(&bob.id, &alice, 1003));
+
+	// Audit log keeps the most recent records
+	let audit = sessions.audit(None, 10);
+	let actions: Vec<&str> = audit.iter().map(|e| e.action.as_str()).collect();
+	let revoke = format!("revoke {}", bob.id);
+	assert_eq!(actions, vec!["login", "get_status", revoke.as_str()]);
+	let audit = sessions.audit(Some(&bob.id), 10);
+	assert_eq!(audit.len(), 2);
+	assert!(audit.iter().all(|e| e.label == "bob"));
+	assert_eq!(sessions.audit(None, 1)[0].action, revoke);
+
+	assert!(sessions.revoke(&alice.id, &alice, 1004));
+	assert_eq!(
+		sessions.audit(Some(&alice.id), 10).last().unwrap().action,
+		"logout"
+	);
+	assert!(sessions.sessions(1004).is_empty());
+}
+
+struct StatusHandler {}
+
+impl Handler for StatusHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		response(StatusCode::OK, "")
+	}
+}
+
+fn call(middlewares: &[HandlerObj], uri: &str, authorization: Option<&str>) -> StatusCode {
+	let mut req = Request::builder().method("GET").uri(uri);
+	if let Some(authorization) = authorization {
+		req = req.header(AUTHORIZATION, authorization);
+	}
+	let req = req.body(Body::empty()).unwrap();
+	let mut handlers: Vec<HandlerObj> = middlewares.to_vec();
+	handlers.push(Arc::new(StatusHandler {}));
+	let mut handlers = Box::new(handlers.into_iter());
+	let first = handlers.next().unwrap();
+	block_on(first.call(req, handlers)).unwrap().status()
+}
+
+#[test]
+fn test_session_uris() {
+	let uris: Vec<String> = vec!["/v1/".into(), "/v2/owner".into()];
+	assert!(uri_matches("/v1/status", &uris));
+	assert!(uri_matches("/v1/chain/outputs/byids", &uris));
+	assert!(uri_matches("/v2/owner", &uris));
+	assert!(!uri_matches("/v2/owner/login", &uris));
+	assert!(!uri_matches("/v2/foreign", &uris));
+	assert!(!uri_matches("/v1", &uris));
+}
+
+#[test]
+fn test_v1_requires_session() {
+	let sessions = Arc::new(sessions());
+	let session_uris: Vec<String> = vec!["/v1/".into(), "/v2/owner".into()];
+	let mut ignore_uris: Vec<String> = vec!["/v2/foreign".into()];
+	ignore_uris.extend(session_uris.iter().cloned());
+	let secret = "Basic c2VjcmV0";
+	let middlewares: Vec<HandlerObj> = vec![
+		Arc::new(BasicAuthMiddleware::new(
+			secret.to_string(),
+			&MWC_BASIC_REALM,
+			ignore_uris,
+		)),
+		Arc::new(OwnerSessionMiddleware::new(
+			sessions.clone(),
+			session_uris,
+			vec!["/v1/".into()],
+		)),
+	];
+
+	// The api secret is not accepted by v1 and owner API
+	assert_eq!(
+		call(&middlewares, "/v1/status", Some(secret)),
+		StatusCode::UNAUTHORIZED
+	);
+	assert_eq!(
+		call(&middlewares, "/v2/owner", Some(secret)),
+		StatusCode::UNAUTHORIZED
+	);
+	assert_eq!(
+		call(&middlewares, "/v1/status", None),
+		StatusCode::UNAUTHORIZED
+	);
+	// Other URIs still need the secret
+	assert_eq!(
+		call(&middlewares, "/v2/stratum", Some(secret)),
+		StatusCode::OK
+	);
+	assert_eq!(
+		call(&middlewares, "/v2/stratum", None),
+		StatusCode::UNAUTHORIZED
+	);
+
+	let token = login(&sessions, "alice", None, Utc::now().timestamp());
+	let bearer = format!("Bearer {}", token);
+	assert_eq!(
+		call(&middlewares, "/v1/status", Some(&bearer)),
+		StatusCode::OK
+	);
+	assert_eq!(
+		call(&middlewares, "/v2/owner", Some(&bearer)),
+		StatusCode::OK
+	);
+
+	// v1 calls are recorded by the middleware, the owner API handler records its methods
+	let actions: Vec<String> = sessions
+		.audit(None, 10)
+		.into_iter()
+		.map(|e| e.action)
+		.collect();
+	assert_eq!(actions, vec!["login", "GET /v1/status"]);
+}
@@ -0,0 +1,139 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_api as api;
+
+use crate::api::{
+	StratumAuditLog, StratumAuditQuery, StratumJobRecord, StratumShareRecord, StratumShareResult,
+};
+use std::fs;
+
+fn job(timestamp: i64, height: u64, job_id: u64) -> StratumJobRecord {
+	StratumJobRecord {
+		timestamp,
+		height,
+		job_id,
+		share_difficulty: 1,
+		network_difficulty: 1000,
+		pre_pow: format!("{:0100x}", height),
+	}
+}
+
+fn share(
+	timestamp: i64,
+	height: u64,
+	job_id: u64,
+	login: Option<&str>,
+	result: StratumShareResult,
+) -> StratumShareRecord {
+	StratumShareRecord {
+		timestamp,
+		worker_id: 1,
+		login: login.map(|l| l.to_string()),
+		ip: "10.0.0.1".to_string(),
+		height,
+		job_id,
+		nonce: timestamp as u64,
+		edge_bits: 31,
+		pow: (0..42).collect(),
+		difficulty: 5,
+		hash: Some(format!("{:064x}", timestamp)),
+		result,
+	}
+}
+
+#[test]
+fn test_stratum_audit_log() {
+	let db_root = std::env::temp_dir().join(".mwc_stratum_audit_test");
+	let _ = fs::remove_dir_all(&db_root);
+	let now = 1_600_000_000;
+	let day = 24 * 3600;
+
+	{
+		let log = StratumAuditLog::new(db_root.to_str().unwrap(), 7, 5).unwrap();
+		log.record_job(&job(now - 10 * day, 90, 0)).unwrap();
+		log.record_job(&job(now - 20, 100, 0)).unwrap();
+		log.record_job(&job(now - 10, 100, 1)).unwrap();
+		log.record_share(&share(
+			now - 5,
+			100,
+			1,
+			Some("alice"),
+			StratumShareResult::Accepted,
+		))
+		.unwrap();
+		log.record_share(&share(
+			now - 4,
+			100,
+			0,
+			Some("bob"),
+			StratumShareResult::Stale,
+		))
+		.unwrap();
+		log.record_share(&share(now, 100, 1, None, StratumShareResult::LowDifficulty))
+			.unwrap();
+
+		let (jobs, shares) = log.query(&StratumAuditQuery::default()).unwrap();
+		assert_eq!(jobs.len(), 3);
+		assert_eq!(shares.len(), 3);
+		// Oldest first
+		assert_eq!(jobs[0].height, 90);
+		assert_eq!(shares[2].result, StratumShareResult::LowDifficulty);
+		assert_eq!(shares[0].pow.len(), 42);
+
+		// Disputed share with its job
+		let query = StratumAuditQuery {
+			height: Some(100),
+			job_id: Some(1),
+			login: Some("alice".to_string()),
+			..Default::default()
+		};
+		let (jobs, shares) = log.query(&query).unwrap();
+		assert_eq!(jobs, vec![job(now - 10, 100, 1)]);
+		assert_eq!(
+			shares,
+			vec![share(
+				now - 5,
+				100,
+				1,
+				Some("alice"),
+				StratumShareResult::Accepted
+			)]
+		);
+
+		// Retention and the size limit
+		assert_eq!(log.prune(now).unwrap(), 1);
+		let (jobs, shares) = log.query(&StratumAuditQuery::default()).unwrap();
+		assert_eq!(jobs.len(), 2);
+		assert_eq!(shares.len(), 3);
+	}
+
+	// The log is persistent
+	{
+		let log = StratumAuditLog::new(db_root.to_str().unwrap(), 7, 3).unwrap();
+		let query = StratumAuditQuery {
+			limit: Some(1),
+			..Default::default()
+		};
+		let (jobs, shares) = log.query(&query).unwrap();
+		assert_eq!(jobs[0].job_id, 1);
+		assert_eq!(shares[0].timestamp, now);
+		// Oldest records above the limit are pruned, jobs and shares together
+		assert_eq!(log.prune(now).unwrap(), 2);
+		let (jobs, shares) = log.query(&StratumAuditQuery::default()).unwrap();
+		assert!(jobs.is_empty());
+		assert_eq!(shares.len(), 3);
+	}
+	let _ = fs::remove_dir_all(&db_root);
+}
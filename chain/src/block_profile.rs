@@ -0,0 +1,113 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Block acceptance profiling. Timing breakdown for the last accepted blocks
+//! is retained in memory so the performance regressions can be spotted in the field.
+
+use crate::core::core::hash::Hash;
+use crate::util::RwLock;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Number of the last processed blocks with the timings we keep
+pub const BLOCK_PROFILE_HISTORY: usize = 100;
+
+/// Max number of the deserialization timings waiting for the block processing
+const MAX_PENDING_DESERIALIZE: usize = 500;
+
+/// Timing breakdown of the single block processing. All durations are in microseconds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BlockTimings {
+	/// Block hash
+	pub hash: Hash,
+	/// Block height
+	pub height: u64,
+	/// Time when the block processing was finished (unix timestamp, seconds)
+	pub timestamp: i64,
+	/// Number of the block inputs
+	pub inputs: usize,
+	/// Number of the block outputs
+	pub outputs: usize,
+	/// Number of the block kernels
+	pub kernels: usize,
+	/// Block deserialization from the wire, 0 for the blocks not received from the peers
+	pub deserialize: u64,
+	/// Header processing, block rules and contextual (UTXO, maturity, sums) checks
+	pub contextual_checks: u64,
+	/// Rangeproofs batch verification
+	pub rangeproof_batch: u64,
+	/// Kernel signatures batch verification
+	pub kernel_sig_batch: u64,
+	/// Applying the block (and the fork if needed) to the MMRs
+	pub mmr_apply: u64,
+	/// LMDB batch commit
+	pub db_commit: u64,
+	/// Total block processing time
+	pub total: u64,
+}
+
+/// Convert duration into microseconds for BlockTimings
+pub fn to_micros(duration: Duration) -> u64 {
+	duration.as_secs() * 1_000_000 + duration.subsec_micros() as u64
+}
+
+struct BlockProfiler {
+	// Deserialization time of the received blocks that are not processed yet
+	pending_deserialize: HashMap<Hash, u64>,
+	blocks: VecDeque<BlockTimings>,
+}
+
+lazy_static! {
+	static ref BLOCK_PROFILER: RwLock<BlockProfiler> = RwLock::new(BlockProfiler {
+		pending_deserialize: HashMap::new(),
+		blocks: VecDeque::with_capacity(BLOCK_PROFILE_HISTORY),
+	});
+}
+
+/// Register the deserialization time of the block received from the peer.
+/// It is attached to the block timings once the block is processed.
+pub fn record_deserialize(hash: Hash, duration: Duration) {
+	let mut profiler = BLOCK_PROFILER.write();
+	// Blocks that were never processed (orphans, duplicates) shouldn't accumulate
+	if profiler.pending_deserialize.len() >= MAX_PENDING_DESERIALIZE {
+		profiler.pending_deserialize.clear();
+	}
+	profiler
+		.pending_deserialize
+		.insert(hash, to_micros(duration));
+}
+
+/// Register the timings of the processed block
+pub fn record_block(mut timings: BlockTimings) {
+	let mut profiler = BLOCK_PROFILER.write();
+	if let Some(deserialize) = profiler.pending_deserialize.remove(&timings.hash) {
+		timings.deserialize = deserialize;
+	}
+	if profiler.blocks.len() >= BLOCK_PROFILE_HISTORY {
+		profiler.blocks.pop_front();
+	}
+	profiler.blocks.push_back(timings);
+}
+
+/// Timings of the last processed blocks, most recent first
+pub fn last_blocks(count: usize) -> Vec<BlockTimings> {
+	BLOCK_PROFILER
+		.read()
+		.blocks
+		.iter()
+		.rev()
+		.take(count)
+		.cloned()
+		.collect()
+}
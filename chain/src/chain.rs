@@ -15,28 +15,40 @@
 //! Facade and handler for the rest of the blockchain implementation
 //! and mostly the chain pipeline.
 
+use crate::block_profile::{self, to_micros, BlockTimings};
+use crate::core::consensus::{self, HeaderInfo};
 use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
 use crate::core::core::merkle_proof::MerkleProof;
+use crate::core::core::pmmr;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::{
 	Block, BlockHeader, BlockSums, Committed, Inputs, KernelFeatures, Output, OutputIdentifier,
-	Transaction, TxKernel,
+	Transaction, TransactionBody, TxKernel,
 };
 use crate::core::global;
 use crate::core::pow;
 use crate::core::ser::ProtocolVersion;
+use crate::difficulty_cache::DifficultyCache;
 use crate::error::{Error, ErrorKind};
 use crate::pipe;
+use crate::rproof_spot_check;
 use crate::store;
 use crate::txhashset;
 use crate::txhashset::{PMMRHandle, TxHashSet};
 use crate::types::{
-	BlockStatus, ChainAdapter, CommitPos, NoStatus, Options, Tip, TxHashsetWriteStatus,
+	BlockStatus, ChainAdapter, CommitPos, CompactionStats, DeepReorgAlert, DeferredRangeproofs,
+	NoStatus, Options, RangeproofValidation, Tip, TxHashsetWriteStatus,
 };
 use crate::util::secp::pedersen::{Commitment, RangeProof};
-use crate::{util::RwLock, ChainStore};
+use crate::utxo_digest::{UtxoAccumulator, UtxoSetDigest, MAX_UTXO_DIGEST_CATCH_UP};
+use crate::{
+	util::{Mutex, RwLock},
+	ChainStore,
+};
+use chrono::prelude::{DateTime, Utc};
 use grin_store::Error::NotFoundErr;
 use grin_util::ToHex;
+use std::cmp;
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
@@ -50,6 +62,10 @@ pub const MAX_ORPHAN_SIZE: usize = 200;
 /// When evicting, very old orphans are evicted first
 const MAX_ORPHAN_AGE_SECS: u64 = 300;
 
+/// Max number of the blocks that are deleted at startup while the MMRs are repaired.
+/// Deeper damage requires the resync.
+const MAX_INIT_REPAIR_DEPTH: u64 = 60;
+
 /// Banned block. We don't accept any blockchain with this has
 pub const BLOCK_TO_BAN: &str = "00020440a401086e57e1b7a92ebb0277c7f7fd47a38269ecc6789c2a80333725";
 
@@ -152,13 +168,16 @@ pub struct Chain {
 	adapter: Arc<dyn ChainAdapter + Send + Sync>,
 	orphans: Arc<OrphanBlockPool>,
 	txhashset: Arc<RwLock<txhashset::TxHashSet>>,
-	header_pmmr: Arc<RwLock<txhashset::PMMRHandle<BlockHeader>>>,
+	header_pmmr: Arc<RwLock<PMMRHandle<BlockHeader>>>,
 	sync_pmmr: Arc<RwLock<txhashset::PMMRHandle<BlockHeader>>>,
 	verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	// POW verification function
 	pow_verifier: fn(&BlockHeader) -> Result<(), pow::Error>,
 	archive_mode: bool,
 	genesis: BlockHeader,
+	// UTXO set digest with the head it is computed for, None if it is not enabled
+	utxo_digest: Mutex<Option<(Tip, UtxoAccumulator)>>,
+	difficulty_cache: Arc<DifficultyCache>,
 }
 
 impl Chain {
@@ -175,10 +194,29 @@ impl Chain {
 	) -> Result<Chain, Error> {
 		let store = Arc::new(store::ChainStore::new(&db_root)?);
 
+		// Chain state with an invalid rangeproof is not served, it must be synced again
+		if let Some(deferred) = store.deferred_rangeproofs()? {
+			if deferred.failed {
+				rproof_spot_check::set_verification_failed();
+				return Err(ErrorKind::Other(format!(
+					"Invalid rangeproof at the output position {} in the chain state that was accepted with the spot-check validation. Delete the chain data and sync again with the full validation",
+					deferred.next_pos
+				))
+				.into());
+			}
+		}
+
 		// DB migrations to be run prior to the chain being used.
 		// Migrate full blocks to protocol version v3.
 		Chain::migrate_db_v2_v3(&store)?;
 
+		// Index the headers saved by the older versions by height, for the compaction.
+		{
+			let batch = store.batch()?;
+			batch.init_header_heights()?;
+			batch.commit()?;
+		}
+
 		// open the txhashset, creating a new one if necessary
 		let mut txhashset = txhashset::TxHashSet::open(db_root.clone(), store.clone(), None)?;
 
@@ -224,6 +262,8 @@ impl Chain {
 			verifier_cache,
 			archive_mode,
 			genesis: genesis.header,
+			utxo_digest: Mutex::new(None),
+			difficulty_cache: Arc::new(DifficultyCache::new()),
 		};
 
 		// If known bad block exists on "current chain" then rewind prior to this.
@@ -238,6 +278,56 @@ impl Chain {
 		Ok(chain)
 	}
 
+	/// Initializes the blockchain over an existing chain_data copy without writing
+	/// to it. Nothing is migrated or repaired, if the heads, the MMRs and the db
+	/// are not consistent an error is returned instead.
+	pub fn init_read_only(
+		db_root: String,
+		adapter: Arc<dyn ChainAdapter + Send + Sync>,
+		genesis: Block,
+		pow_verifier: fn(&BlockHeader) -> Result<(), pow::Error>,
+		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
+		archive_mode: bool,
+	) -> Result<Chain, Error> {
+		let store = Arc::new(store::ChainStore::open_read_only(&db_root)?);
+
+		let txhashset = txhashset::TxHashSet::open(db_root.clone(), store.clone(), None)?;
+		let mut header_pmmr = PMMRHandle::new(
+			Path::new(&db_root).join("header").join("header_head"),
+			false,
+			ProtocolVersion(1),
+			None,
+		)?;
+		let sync_pmmr = PMMRHandle::new(
+			Path::new(&db_root).join("header").join("sync_head"),
+			false,
+			ProtocolVersion(1),
+			None,
+		)?;
+
+		check_head(&genesis, &store, &mut header_pmmr, &txhashset)?;
+
+		let chain = Chain {
+			db_root,
+			store,
+			adapter,
+			orphans: Arc::new(OrphanBlockPool::new()),
+			txhashset: Arc::new(RwLock::new(txhashset)),
+			header_pmmr: Arc::new(RwLock::new(header_pmmr)),
+			sync_pmmr: Arc::new(RwLock::new(sync_pmmr)),
+			pow_verifier,
+			verifier_cache,
+			archive_mode,
+			genesis: genesis.header,
+			utxo_digest: Mutex::new(None),
+			difficulty_cache: Arc::new(DifficultyCache::new()),
+		};
+
+		chain.log_heads()?;
+
+		Ok(chain)
+	}
+
 	/// Return our shared header MMR handle.
 	pub fn header_pmmr(&self) -> Arc<RwLock<PMMRHandle<BlockHeader>>> {
 		self.header_pmmr.clone()
@@ -498,6 +588,8 @@ impl Chain {
 	/// Returns true if it has been added to the longest chain
 	/// or false if it has added to a fork (or orphan?).
 	fn process_block_single(&self, b: Block, opts: Options) -> Result<Option<Tip>, Error> {
+		let start = Instant::now();
+
 		// Check if we already know about this block.
 		self.is_known(&b.header)?;
 
@@ -529,7 +621,18 @@ impl Chain {
 			// A node shutdown at this point can be catastrophic...
 			// We prevent this via the stop_lock (see above).
 			if maybe_new_head.is_ok() {
+				let commit_start = Instant::now();
+				let mut timings = ctx.timings.clone();
 				ctx.batch.commit()?;
+				timings.db_commit = to_micros(commit_start.elapsed());
+				timings.total = to_micros(start.elapsed());
+				timings.hash = b.hash();
+				timings.height = b.header.height;
+				timings.timestamp = Utc::now().timestamp();
+				timings.inputs = b.inputs().len();
+				timings.outputs = b.outputs().len();
+				timings.kernels = b.kernels().len();
+				block_profile::record_block(timings);
 			}
 
 			// release the lock and let the batch go before post-processing
@@ -545,10 +648,17 @@ impl Chain {
 					prev_head,
 					Tip::from_header(&fork_point),
 				);
+				if let BlockStatus::Reorg { .. } = status {
+					self.difficulty_cache.invalidate();
+				}
 
 				// notifying other parts of the system of the update
 				self.adapter.block_accepted(&b, status, opts);
 
+				if head.is_some() {
+					self.update_utxo_digest();
+				}
+
 				Ok(head)
 			}
 			Err(e) => match e.kind() {
@@ -561,6 +671,7 @@ impl Chain {
 					);
 					Err(e)
 				}
+				ErrorKind::DeepReorg { .. } => Err(self.report_deep_reorg(e)),
 				ErrorKind::Unfit(ref msg) => {
 					debug!(
 						"Block {} at {} is unfit at this time: {}",
@@ -591,11 +702,57 @@ impl Chain {
 		let mut txhashset = self.txhashset.write();
 		let batch = self.store.batch()?;
 		let mut ctx = self.new_ctx(opts, batch, &mut header_pmmr, &mut txhashset)?;
-		pipe::process_block_header(bh, &mut ctx)?;
+		pipe::process_block_header(bh, &mut ctx).map_err(|e| self.report_deep_reorg(e))?;
 		ctx.batch.commit()?;
 		Ok(())
 	}
 
+	// Rejected deep reorg is reported loudly, the error is returned as it is
+	fn report_deep_reorg(&self, e: Error) -> Error {
+		if let ErrorKind::DeepReorg {
+			max_depth,
+			fork_tip,
+			fork_tip_height,
+			head_height,
+		} = e.kind()
+		{
+			if pipe::deep_reorg_alert_due() {
+				error!(
+					"DEEP REORG REJECTED: fork {} at {} would reorg more than {} blocks from our head at {}. \
+					Use the owner API allow_deep_reorgs if this fork is legit.",
+					fork_tip, fork_tip_height, max_depth, head_height
+				);
+				self.adapter.deep_reorg_rejected(&DeepReorgAlert {
+					max_depth,
+					fork_tip: fork_tip.to_hex(),
+					fork_tip_height,
+					head_height,
+				});
+			}
+		}
+		e
+	}
+
+	/// Max reorg depth the chain accepts, None if reorgs are not limited
+	pub fn max_reorg_depth(&self) -> Option<u64> {
+		pipe::max_reorg_depth()
+	}
+
+	/// Operator override of the anti deep reorg rule, the reorgs of any depth are
+	/// accepted until the provided time. None cancels the override.
+	pub fn allow_deep_reorgs(&self, until: Option<DateTime<Utc>>) {
+		match until {
+			Some(until) => warn!("Reorgs of any depth are allowed until {}", until),
+			None => info!("Deep reorgs override is cancelled"),
+		}
+		pipe::allow_deep_reorgs(until);
+	}
+
+	/// Time until the reorgs of any depth are accepted, None if there is no override
+	pub fn deep_reorgs_allowed_until(&self) -> Option<DateTime<Utc>> {
+		pipe::deep_reorgs_allowed_until()
+	}
+
 	/// Attempt to add new headers to the header chain (or fork).
 	/// This is only ever used during sync and is based on sync_head.
 	/// We update header_head here if our total work increases.
@@ -616,7 +773,7 @@ impl Chain {
 		if let Some(header) = headers.last() {
 			let batch = self.store.batch()?;
 			let mut ctx = self.new_ctx(opts, batch, &mut header_pmmr, &mut txhashset)?;
-			pipe::process_block_header(header, &mut ctx)?;
+			pipe::process_block_header(header, &mut ctx).map_err(|e| self.report_deep_reorg(e))?;
 			ctx.batch.commit()?;
 		}
 
@@ -638,6 +795,8 @@ impl Chain {
 			header_pmmr,
 			txhashset,
 			batch,
+			timings: BlockTimings::default(),
+			difficulty_cache: self.difficulty_cache.clone(),
 		})
 	}
 
@@ -836,7 +995,7 @@ impl Chain {
 		txhashset::extending_readonly(&mut header_pmmr, &mut txhashset, |ext, batch| {
 			pipe::rewind_and_apply_fork(&header, ext, batch)?;
 			ext.extension
-				.validate(&self.genesis, fast_validation, &NoStatus, &header)?;
+				.validate(&self.genesis, fast_validation, 100, &NoStatus, &header)?;
 			Ok(())
 		})
 	}
@@ -1016,6 +1175,24 @@ impl Chain {
 		Ok(())
 	}
 
+	/// Repair the header head and the header MMR if they are inconsistent, the same
+	/// check that runs at the node start. Sync MMR is rebuilt from the repaired head.
+	/// Returns true if the repair was needed.
+	pub fn repair_heads(&self) -> Result<bool, Error> {
+		let repaired = {
+			let mut header_pmmr = self.header_pmmr.write();
+			let mut batch = self.store.batch()?;
+			let repaired = setup_header_head(&mut header_pmmr, &mut batch)?;
+			batch.commit()?;
+			repaired
+		};
+		if repaired {
+			let head = self.header_head()?;
+			self.rebuild_sync_mmr(&head)?;
+		}
+		Ok(repaired)
+	}
+
 	/// Check chain status whether a txhashset downloading is needed
 	pub fn check_txhashset_needed(
 		&self,
@@ -1148,6 +1325,85 @@ impl Chain {
 		tmp
 	}
 
+	/// Rangeproofs that were skipped by the spot-check state validation and are still
+	/// not verified. None if all the rangeproofs are verified.
+	pub fn deferred_rangeproofs(&self) -> Result<Option<DeferredRangeproofs>, Error> {
+		Ok(self.store.deferred_rangeproofs()?)
+	}
+
+	/// Rangeproofs validation of the chain state. None if the state is fully validated
+	/// and the spot-check mode is off.
+	pub fn rangeproof_validation(&self) -> Result<Option<RangeproofValidation>, Error> {
+		let deferred = self.store.deferred_rangeproofs()?;
+		let failed = rproof_spot_check::is_verification_failed()
+			|| deferred.map(|d| d.failed).unwrap_or(false);
+		if deferred.is_none() && !failed && !rproof_spot_check::is_enabled() {
+			return Ok(None);
+		}
+		Ok(Some(RangeproofValidation {
+			reduced_validation: deferred.is_some() || failed,
+			sample_percent: rproof_spot_check::sample_percent(),
+			deferred_rangeproofs: deferred,
+			verification_failed: failed,
+		}))
+	}
+
+	/// Verify the next chunk of the rangeproofs skipped by the spot-check state validation,
+	/// up to `max_count` of them. Returns the remaining rangeproofs, None once all of them
+	/// are verified. Invalid rangeproof means the chain state can't be trusted, the failure
+	/// is saved, so the chain is not opened again, and the error is returned.
+	pub fn verify_deferred_rangeproofs(
+		&self,
+		max_count: usize,
+	) -> Result<Option<DeferredRangeproofs>, Error> {
+		let deferred = match self.store.deferred_rangeproofs()? {
+			Some(deferred) => deferred,
+			None => return Ok(None),
+		};
+		if deferred.failed {
+			rproof_spot_check::set_verification_failed();
+			return Err(ErrorKind::Other(format!(
+				"Invalid rangeproof at the output position {}",
+				deferred.next_pos
+			))
+			.into());
+		}
+
+		let res = {
+			let txhashset = self.txhashset.read();
+			txhashset.verify_rangeproofs_range(deferred.next_pos, deferred.last_pos, max_count)
+		};
+		let (next_pos, _) = match res {
+			Ok(res) => res,
+			Err(e) => {
+				rproof_spot_check::set_verification_failed();
+				let batch = self.store.batch()?;
+				batch.save_deferred_rangeproofs(&DeferredRangeproofs {
+					failed: true,
+					..deferred
+				})?;
+				batch.commit()?;
+				return Err(e);
+			}
+		};
+
+		let batch = self.store.batch()?;
+		let res = if next_pos > deferred.last_pos {
+			batch.delete_deferred_rangeproofs()?;
+			None
+		} else {
+			let deferred = DeferredRangeproofs {
+				next_pos,
+				last_pos: deferred.last_pos,
+				failed: false,
+			};
+			batch.save_deferred_rangeproofs(&deferred)?;
+			Some(deferred)
+		};
+		batch.commit()?;
+		Ok(res)
+	}
+
 	/// Writes a reading view on a txhashset state that's been provided to us.
 	/// If we're willing to accept that new state, the data stream will be
 	/// read as a zip file, unzipped and the resulting state files should be
@@ -1206,6 +1462,7 @@ impl Chain {
 
 		let mut header_pmmr = self.header_pmmr.write();
 		let mut batch = self.store.batch()?;
+		let rproof_sample_percent = rproof_spot_check::sample_percent();
 		txhashset::extending(
 			&mut header_pmmr,
 			&mut txhashset,
@@ -1216,8 +1473,14 @@ impl Chain {
 
 				// Validate the extension, generating the utxo_sum and kernel_sum.
 				// Full validation, including rangeproofs and kernel signature verification.
-				let (utxo_sum, kernel_sum) =
-					extension.validate(&self.genesis, false, status, &header)?;
+				// In the spot-check mode only a sample of the rangeproofs is verified.
+				let (utxo_sum, kernel_sum) = extension.validate(
+					&self.genesis,
+					false,
+					rproof_sample_percent,
+					status,
+					&header,
+				)?;
 
 				// Save the block_sums (utxo_sum, kernel_sum) to the db for use later.
 				batch.save_block_sums(
@@ -1245,6 +1508,17 @@ impl Chain {
 			batch.save_body_tail(&tip)?;
 		}
 
+		// Skipped rangeproofs are verified in background once the node is online
+		if rproof_sample_percent < 100 {
+			batch.save_deferred_rangeproofs(&DeferredRangeproofs {
+				next_pos: 1,
+				last_pos: header.output_mmr_size,
+				failed: false,
+			})?;
+		} else if self.store.deferred_rangeproofs()?.is_some() {
+			batch.delete_deferred_rangeproofs()?;
+		}
+
 		// Rebuild our output_pos index in the db based on fresh UTXO set.
 		txhashset.init_output_pos_index(&header_pmmr, &batch)?;
 
@@ -1339,12 +1613,61 @@ impl Chain {
 		Ok(())
 	}
 
+	/// Remove the blocks of the abandoned forks that are older than the horizon, together with
+	/// their headers, the fork headers without a block as well. Such forks can't be reorged to
+	/// anymore, but the data would stay in the db forever. Applies to the archive nodes as well.
+	/// The headers below the horizon are walked with the headers by height index and checked
+	/// against the header MMR. Checked headers are removed from the index, so every header is
+	/// checked once and the compaction doesn't read the whole chain.
+	fn remove_fork_data(
+		&self,
+		header_pmmr: &txhashset::PMMRHandle<BlockHeader>,
+		batch: &store::Batch<'_>,
+	) -> Result<CompactionStats, Error> {
+		let mut stats = CompactionStats::default();
+
+		let horizon = global::cut_through_horizon() as u64;
+		let head = batch.head()?;
+		let cutoff = head.height.saturating_sub(horizon);
+		if cutoff == 0 {
+			return Ok(stats);
+		}
+
+		let mut checked = 0;
+		for (height, hash) in batch.header_heights_iter()? {
+			if height >= cutoff {
+				break;
+			}
+			if header_pmmr.get_header_hash_by_height(height)? != hash {
+				if batch.block_exists(&hash)? {
+					batch.delete_block(&hash)?;
+					stats.fork_blocks += 1;
+				}
+				if batch.block_header_exists(&hash)? {
+					batch.delete_block_header(&hash)?;
+					stats.fork_headers += 1;
+				}
+			}
+			batch.delete_header_height(height, &hash)?;
+			checked += 1;
+		}
+
+		debug!(
+			"remove_fork_data: checked {} headers below the height {}, removed {} blocks and {} headers",
+			checked, cutoff, stats.fork_blocks, stats.fork_headers
+		);
+
+		Ok(stats)
+	}
+
 	/// Triggers chain compaction.
 	///
 	/// * compacts the txhashset based on current prune_list
+	/// * removes the blocks and headers of the abandoned forks older than the horizon
 	/// * removes historical blocks and associated data from the db (unless archive mode)
 	///
-	pub fn compact(&self) -> Result<(), Error> {
+	/// Returns the amount of the removed fork data.
+	pub fn compact(&self) -> Result<CompactionStats, Error> {
 		// A node may be restarted multiple times in a short period of time.
 		// We compact at most once per 60 blocks in this situation by comparing
 		// current "head" and "tail" height to our cut-through horizon and
@@ -1358,7 +1681,7 @@ impl Chain {
 					"compact: skipping startup compaction (next at {})",
 					next_compact
 				);
-				return Ok(());
+				return Ok(CompactionStats::default());
 			}
 		}
 
@@ -1368,7 +1691,10 @@ impl Chain {
 		let batch = self.store.batch()?;
 
 		// Compact the txhashset itself (rewriting the pruned backend files).
-		{
+		// Spent outputs keep their data until the deferred rangeproofs are verified.
+		if self.store.deferred_rangeproofs()?.is_some() {
+			info!("compact: txhashset compaction is postponed until the deferred rangeproofs are verified");
+		} else {
 			let head_header = batch.head_header()?;
 			let current_height = head_header.height;
 			let horizon_height =
@@ -1379,6 +1705,9 @@ impl Chain {
 			txhashset.compact(&horizon_header, &batch)?;
 		}
 
+		// Remove the abandoned forks data before the historical blocks, so fork blocks are counted.
+		let stats = self.remove_fork_data(&header_pmmr, &batch)?;
+
 		// If we are not in archival mode remove historical blocks from the db.
 		if !self.archive_mode {
 			self.remove_historical_blocks(&header_pmmr, &batch)?;
@@ -1393,7 +1722,12 @@ impl Chain {
 		// Commit all the above db changes.
 		batch.commit()?;
 
-		Ok(())
+		info!(
+			"compact: removed {} fork blocks and {} fork headers",
+			stats.fork_blocks, stats.fork_headers
+		);
+
+		Ok(stats)
 	}
 
 	/// returns the last n nodes inserted into the output sum tree
@@ -1411,6 +1745,92 @@ impl Chain {
 		self.txhashset.read().last_n_kernel(distance)
 	}
 
+	/// Compute the UTXO set digest and keep it updated with every block
+	pub fn enable_utxo_digest(&self) -> Result<UtxoSetDigest, Error> {
+		let (head, acc) = self.scan_utxo_set()?;
+		*self.utxo_digest.lock() = Some((head, acc));
+		Ok(acc.to_digest(head.height, &head.last_block_h))
+	}
+
+	/// Digest of the UTXO set at the chain head, None if it is not enabled
+	pub fn utxo_set_digest(&self) -> Option<UtxoSetDigest> {
+		self.utxo_digest
+			.lock()
+			.as_ref()
+			.map(|(tip, acc)| acc.to_digest(tip.height, &tip.last_block_h))
+	}
+
+	// Accumulator of the whole UTXO set at the chain head
+	fn scan_utxo_set(&self) -> Result<(Tip, UtxoAccumulator), Error> {
+		let txhashset = self.txhashset.read();
+		// Head is committed under the txhashset lock, so it matches the UTXO set
+		let head = self.head()?;
+		let last_pos = txhashset.highest_output_insertion_index();
+		let mut acc = UtxoAccumulator::new();
+		let mut pos = 1;
+		while pos <= last_pos {
+			let (last, outputs) = txhashset.outputs_by_pmmr_index(pos, 1000, None);
+			for out in outputs {
+				acc.add(&out.commit);
+			}
+			pos = last + 1;
+		}
+		Ok((head, acc))
+	}
+
+	// Bring the UTXO set digest to the chain head. The blocks that left the chain are
+	// undone and the new ones are applied, the UTXO set is scanned only if the head is
+	// too far or the blocks are not available.
+	fn update_utxo_digest(&self) {
+		let mut utxo_digest = self.utxo_digest.lock();
+		let (tip, acc) = match *utxo_digest {
+			Some(state) => state,
+			None => return,
+		};
+		let updated = match self.catch_up_utxo_digest(&tip, acc) {
+			Ok(Some(state)) => Ok(state),
+			Ok(None) => self.scan_utxo_set(),
+			Err(e) => {
+				debug!("UTXO set digest can't catch up with the head, {}", e);
+				self.scan_utxo_set()
+			}
+		};
+		match updated {
+			Ok(state) => *utxo_digest = Some(state),
+			Err(e) => warn!("Unable to update the UTXO set digest, {}", e),
+		}
+	}
+
+	fn catch_up_utxo_digest(
+		&self,
+		from: &Tip,
+		mut acc: UtxoAccumulator,
+	) -> Result<Option<(Tip, UtxoAccumulator)>, Error> {
+		let head = self.head()?;
+		let mut old = self.get_block_header(&from.last_block_h)?;
+		let mut new = self.get_block_header(&head.last_block_h)?;
+		let mut to_apply = vec![];
+		let mut steps = 0;
+		// Walk both chains back to the fork point
+		while old.hash() != new.hash() {
+			steps += 1;
+			if steps > MAX_UTXO_DIGEST_CATCH_UP {
+				return Ok(None);
+			}
+			if new.height >= old.height {
+				to_apply.push(new.hash());
+				new = self.get_previous_header(&new)?;
+			} else {
+				acc.undo_block(&self.get_block(&old.hash())?);
+				old = self.get_previous_header(&old)?;
+			}
+		}
+		for hash in to_apply.iter().rev() {
+			acc.apply_block(&self.get_block(hash)?);
+		}
+		Ok(Some((head, acc)))
+	}
+
 	/// Return Commit's MMR position
 	pub fn get_output_pos(&self, commit: &Commitment) -> Result<u64, Error> {
 		Ok(self.txhashset.read().get_output_pos(commit)?)
@@ -1518,6 +1938,32 @@ impl Chain {
 			.map_err(|e| ErrorKind::StoreErr(e, "chain get previous header".to_owned()).into())
 	}
 
+	/// Consensus weight of the block. Outputs and kernels are counted from the MMR sizes
+	/// and the inputs from the spent index, so the block body is read only if the spent
+	/// index is missing. Fails for the blocks that are compacted away.
+	pub fn get_block_weight(&self, header: &BlockHeader) -> Result<u64, Error> {
+		let (prev_output_mmr_size, prev_kernel_mmr_size) = if header.height == 0 {
+			(0, 0)
+		} else {
+			let prev = self.get_previous_header(header)?;
+			(prev.output_mmr_size, prev.kernel_mmr_size)
+		};
+		let num_outputs = pmmr::n_leaves(header.output_mmr_size)
+			.saturating_sub(pmmr::n_leaves(prev_output_mmr_size));
+		let num_kernels = pmmr::n_leaves(header.kernel_mmr_size)
+			.saturating_sub(pmmr::n_leaves(prev_kernel_mmr_size));
+		let num_inputs = match self.store.get_spent_index(&header.hash()) {
+			Ok(spent) => spent.len() as u64,
+			Err(NotFoundErr(_)) => self.get_block(&header.hash())?.inputs().len() as u64,
+			Err(e) => return Err(ErrorKind::StoreErr(e, "chain get spent index".to_owned()).into()),
+		};
+		Ok(TransactionBody::weight_as_block(
+			num_inputs,
+			num_outputs,
+			num_kernels,
+		))
+	}
+
 	/// Get block_sums by header hash.
 	pub fn get_block_sums(&self, h: &Hash) -> Result<BlockSums, Error> {
 		self.store
@@ -1670,6 +2116,22 @@ impl Chain {
 		}
 	}
 
+	/// Consistent read-only view of the chain, see `ReadSnapshot`.
+	pub fn snapshot(&self) -> Result<ReadSnapshot, Error> {
+		// Blocks are committed to the db while the header MMR write lock is held, the
+		// snapshot taken under the read lock matches the header MMR state
+		let _header_pmmr = self.header_pmmr.read();
+		let store = self.store.snapshot()?;
+		let head = store
+			.head()
+			.map_err(|e| ErrorKind::StoreErr(e, "snapshot head".to_owned()))?;
+		Ok(ReadSnapshot {
+			store,
+			head,
+			header_pmmr: self.header_pmmr.clone(),
+		})
+	}
+
 	/// Get the tip of the current "sync" header chain.
 	/// This may be significantly different to current header chain.
 	pub fn get_sync_head(&self) -> Result<Tip, Error> {
@@ -1697,6 +2159,53 @@ impl Chain {
 		Ok(store::DifficultyIter::from(head.last_block_h, store))
 	}
 
+	/// Difficulty adjustment window that ends at the current chain head, the latest
+	/// first. Same data as the difficulty_iter yields, served from the cache.
+	pub fn difficulty_window(&self) -> Result<Vec<HeaderInfo>, Error> {
+		let head = self.head()?;
+		Ok(self
+			.difficulty_cache
+			.window(&head.last_block_h, |h| self.store.get_block_header(h).ok()))
+	}
+
+	/// Difficulty and secondary scaling of the next block on top of the current head
+	pub fn next_difficulty(&self) -> Result<HeaderInfo, Error> {
+		let head = self.head()?;
+		let window = self
+			.difficulty_cache
+			.window(&head.last_block_h, |h| self.store.get_block_header(h).ok());
+		Ok(consensus::next_difficulty(head.height + 1, window))
+	}
+
+	/// Verify the block that was fetched from a peer because we don't store it any more.
+	/// The block must be on our header chain, be valid, and its kernels must match the
+	/// kernels of the block in the kernel MMR (kernels are never pruned).
+	pub fn verify_fetched_block(&self, block: &Block) -> Result<(), Error> {
+		let header = self.get_block_header(&block.hash())?;
+		let prev = self.get_previous_header(&header)?;
+		block
+			.validate(&prev.total_kernel_offset, self.verifier_cache.clone())
+			.map_err(ErrorKind::InvalidBlockProof)?;
+
+		let kernels = self
+			.txhashset
+			.read()
+			.kernels_by_pmmr_range(prev.kernel_mmr_size + 1, header.kernel_mmr_size);
+		if kernels.len() != block.kernels().len()
+			|| kernels
+				.iter()
+				.zip(block.kernels())
+				.any(|(k1, k2)| k1.hash() != k2.hash())
+		{
+			return Err(ErrorKind::InvalidRoot(format!(
+				"kernels of the fetched block {} don't match the kernel MMR",
+				header.hash()
+			))
+			.into());
+		}
+		Ok(())
+	}
+
 	/// Check whether we have a block without reading it
 	pub fn block_exists(&self, h: Hash) -> Result<bool, Error> {
 		self.store
@@ -1705,6 +2214,229 @@ impl Chain {
 	}
 }
 
+/// Consistent read-only view of the chain for the long API reads. The reads see the chain
+/// either before or after the block is applied, never the state in between. Headers by
+/// height are resolved within the body chain of the snapshot, so a header is never returned
+/// without its block. Holds the db read transaction, so it should not be kept for long.
+pub struct ReadSnapshot {
+	store: store::ChainSnapshot,
+	head: Tip,
+	header_pmmr: Arc<RwLock<PMMRHandle<BlockHeader>>>,
+}
+
+impl ReadSnapshot {
+	/// Chain head of the snapshot
+	pub fn head(&self) -> Tip {
+		self.head.clone()
+	}
+
+	/// Header of the snapshot chain head
+	pub fn head_header(&self) -> Result<BlockHeader, Error> {
+		self.get_block_header(&self.head.last_block_h)
+	}
+
+	/// Gets a block by hash
+	pub fn get_block(&self, h: &Hash) -> Result<Block, Error> {
+		self.store
+			.get_block(h)
+			.map_err(|e| ErrorKind::StoreErr(e, "snapshot get block".to_owned()).into())
+	}
+
+	/// Gets a block header by hash
+	pub fn get_block_header(&self, h: &Hash) -> Result<BlockHeader, Error> {
+		self.store
+			.get_block_header(h)
+			.map_err(|e| ErrorKind::StoreErr(e, "snapshot get header".to_owned()).into())
+	}
+
+	/// Gets the output position and height of the block that has it
+	pub fn get_output_pos_height(&self, commit: &Commitment) -> Result<Option<CommitPos>, Error> {
+		self.store
+			.get_output_pos_height(commit)
+			.map_err(|e| ErrorKind::StoreErr(e, "snapshot get output pos".to_owned()).into())
+	}
+
+	/// Gets the block header of the snapshot chain at the provided height.
+	pub fn get_header_by_height(&self, height: u64) -> Result<BlockHeader, Error> {
+		if height > self.head.height {
+			return Err(ErrorKind::Other(format!(
+				"height {} is above the snapshot head {}",
+				height, self.head.height
+			))
+			.into());
+		}
+		let hash = {
+			let header_pmmr = self.header_pmmr.read();
+			// Header MMR still has the snapshot head, so it has all its ancestors too
+			match header_pmmr.get_header_hash_by_height(self.head.height) {
+				Ok(head_hash) if head_hash == self.head.last_block_h => {
+					Some(header_pmmr.get_header_hash_by_height(height)?)
+				}
+				_ => None,
+			}
+		};
+		match hash {
+			Some(hash) => self.get_block_header(&hash),
+			None => {
+				// Reorg since the snapshot was taken, walk back from the snapshot head
+				let mut header = self.head_header()?;
+				while header.height > height {
+					header = self.get_block_header(&header.prev_hash)?;
+				}
+				Ok(header)
+			}
+		}
+	}
+
+	/// Verifies the header is on the snapshot chain.
+	pub fn is_on_current_chain(&self, header: &BlockHeader) -> Result<(), Error> {
+		let chain_header = self.get_header_by_height(header.height)?;
+		if chain_header.hash() == header.hash() {
+			Ok(())
+		} else {
+			Err(ErrorKind::Other("header is not on snapshot chain".to_owned()).into())
+		}
+	}
+}
+
+/// Find the highest header (up to max_height) that is consistent between the header MMR
+/// and the db, the header MMR has its hash at its height and the db has the header itself.
+fn find_consistent_header(
+	header_pmmr: &txhashset::PMMRHandle<BlockHeader>,
+	batch: &store::Batch<'_>,
+	max_height: u64,
+) -> Result<BlockHeader, Error> {
+	let pmmr_height = pmmr::n_leaves(header_pmmr.last_pos).saturating_sub(1);
+	let mut height = cmp::min(max_height, pmmr_height);
+	loop {
+		if let Ok(hash) = header_pmmr.get_header_hash_by_height(height) {
+			if let Ok(header) = batch.get_block_header(&hash) {
+				return Ok(header);
+			}
+		}
+		if height == 0 {
+			return Err(ErrorKind::Other(
+				"no consistent header found in the header MMR".to_string(),
+			)
+			.into());
+		}
+		height -= 1;
+	}
+}
+
+/// Find the highest block (up to max_height) on the header chain that we have in the db.
+fn find_consistent_body_head(
+	header_pmmr: &txhashset::PMMRHandle<BlockHeader>,
+	batch: &store::Batch<'_>,
+	max_height: u64,
+) -> Result<BlockHeader, Error> {
+	let mut height = max_height;
+	loop {
+		let header = find_consistent_header(header_pmmr, batch, height)?;
+		if batch.block_exists(&header.hash())? {
+			return Ok(header);
+		}
+		if header.height == 0 {
+			return Err(ErrorKind::Other(
+				"no consistent block found on the header chain".to_string(),
+			)
+			.into());
+		}
+		height = header.height - 1;
+	}
+}
+
+/// Make sure our header PMMR is consistent with header_head from db if it exists.
+/// If header_head is missing in db then use head of header PMMR.
+/// Returns true if the header head was repaired.
+fn setup_header_head(
+	header_pmmr: &mut txhashset::PMMRHandle<BlockHeader>,
+	batch: &mut store::Batch<'_>,
+) -> Result<bool, Error> {
+	let mut repaired = false;
+	if let Ok(head) = batch.header_head() {
+		let head = match header_pmmr.init_head(&head) {
+			Ok(_) if batch.get_block_header(&head.hash()).is_ok() => head,
+			res => {
+				// Header MMR files and db are out of sync, probably the node was killed
+				// in the middle of the write. Use the highest header they both agree on.
+				warn!(
+					"header head {} at {} is inconsistent with the header MMR ({:?}), repairing",
+					head.last_block_h, head.height, res
+				);
+				let header = find_consistent_header(header_pmmr, batch, head.height)?;
+				let new_head = Tip::from_header(&header);
+				header_pmmr.init_head(&new_head)?;
+				batch.save_header_head(&new_head)?;
+				warn!(
+					"header head is rewound to {} at {}",
+					new_head.last_block_h, new_head.height
+				);
+				repaired = true;
+				new_head
+			}
+		};
+		txhashset::header_extending(header_pmmr, batch, |ext, batch| {
+			let header = batch.get_block_header(&head.hash())?;
+			ext.rewind(&header)
+		})?;
+	} else {
+		let hash = header_pmmr.head_hash()?;
+		let header = batch.get_block_header(&hash)?;
+		batch.save_header_head(&Tip::from_header(&header))?;
+		repaired = true;
+	}
+	Ok(repaired)
+}
+
+/// Same checks as setup_head does, for the chain that can't be repaired.
+/// Header MMR is only positioned at the header head, nothing is written.
+fn check_head(
+	genesis: &Block,
+	store: &store::ChainStore,
+	header_pmmr: &mut txhashset::PMMRHandle<BlockHeader>,
+	txhashset: &txhashset::TxHashSet,
+) -> Result<(), Error> {
+	let inconsistent = |msg: String| -> Error { ErrorKind::Other(msg).into() };
+
+	if store.get_block_header(&genesis.hash()).is_err() {
+		return Err(inconsistent(format!(
+			"genesis {} is not in the db",
+			genesis.hash()
+		)));
+	}
+
+	let header_head = store.header_head()?;
+	match header_pmmr.init_head(&header_head) {
+		Ok(_) if store.get_block_header(&header_head.hash()).is_ok() => {}
+		res => {
+			return Err(inconsistent(format!(
+				"header head {} at {} is inconsistent with the header MMR ({:?})",
+				header_head.last_block_h, header_head.height, res
+			)))
+		}
+	}
+
+	let head = store.head()?;
+	if !store.block_exists(&head.last_block_h)? {
+		return Err(inconsistent(format!(
+			"body head {} at {} is missing",
+			head.last_block_h, head.height
+		)));
+	}
+	if head.total_difficulty > header_head.total_difficulty
+		|| header_pmmr.get_header_hash_by_height(head.height)? != head.last_block_h
+	{
+		return Err(inconsistent(format!(
+			"body head {} at {} is not on the header chain",
+			head.last_block_h, head.height
+		)));
+	}
+
+	let header = store.get_block_header(&head.last_block_h)?;
+	txhashset.validate_head(&header)
+}
+
 fn setup_head(
 	genesis: &Block,
 	store: &store::ChainStore,
@@ -1733,18 +2465,17 @@ fn setup_head(
 		}
 	}
 
-	// Make sure our header PMMR is consistent with header_head from db if it exists.
-	// If header_head is missing in db then use head of header PMMR.
-	if let Ok(head) = batch.header_head() {
-		header_pmmr.init_head(&head)?;
-		txhashset::header_extending(header_pmmr, &mut batch, |ext, batch| {
-			let header = batch.get_block_header(&head.hash())?;
-			ext.rewind(&header)
-		})?;
-	} else {
-		let hash = header_pmmr.head_hash()?;
-		let header = batch.get_block_header(&hash)?;
-		batch.save_header_head(&Tip::from_header(&header))?;
+	setup_header_head(header_pmmr, &mut batch)?;
+
+	// Sync MMR is rebuilt from the header head once the chain is initialized,
+	// but it must have a valid head to be rebuilt from.
+	let sync_head_valid = match sync_pmmr.head_hash() {
+		Ok(hash) => batch.get_block_header(&hash).is_ok(),
+		Err(_) => false,
+	};
+	if !sync_head_valid {
+		warn!("init: sync MMR head is inconsistent, rewinding sync MMR to genesis");
+		txhashset::header_extending(sync_pmmr, &mut batch, |ext, _| ext.rewind(&genesis.header))?;
 	}
 
 	// check if we have a head in store, otherwise the genesis block is it
@@ -1753,6 +2484,46 @@ fn setup_head(
 	match head_res {
 		Ok(h) => {
 			head = h;
+
+			// Body head must have its block in the db. If it doesn't, use the highest
+			// block on the header chain we have.
+			if batch.get_block_header(&head.last_block_h).is_err()
+				|| !batch.block_exists(&head.last_block_h)?
+			{
+				let header_head = batch.header_head()?;
+				let header = find_consistent_body_head(
+					header_pmmr,
+					&batch,
+					cmp::min(head.height, header_head.height),
+				)?;
+				warn!(
+					"init: body head {} at {} is missing, rewinding to {} at {}",
+					head.last_block_h,
+					head.height,
+					header.hash(),
+					header.height
+				);
+				head = Tip::from_header(&header);
+				batch.save_body_head(&head)?;
+			}
+
+			// Header head can't have less work than the body head. It happens when the
+			// header head was rewound above, so bring the header chain to the body head.
+			let header_head = batch.header_head()?;
+			if head.total_difficulty > header_head.total_difficulty {
+				warn!(
+					"init: header head {} at {} is behind the body head {} at {}, repairing",
+					header_head.last_block_h, header_head.height, head.last_block_h, head.height
+				);
+				let header = batch.get_block_header(&head.last_block_h)?;
+				txhashset::header_extending(header_pmmr, &mut batch, |ext, batch| {
+					pipe::rewind_and_apply_header_fork(&header, ext, batch)
+				})?;
+				batch.save_header_head(&head)?;
+			}
+
+			let repair_tail_height = batch.tail().map(|t| t.height).unwrap_or(0);
+			let mut repaired_blocks = 0;
 			loop {
 				// Use current chain tip if we have one.
 				// Note: We are rewinding and validating against a writeable extension.
@@ -1765,6 +2536,7 @@ fn setup_head(
 
 					let extension = &mut ext.extension;
 
+					extension.validate_sizes(&header)?;
 					extension.validate_roots(&header)?;
 
 					// now check we have the "block sums" for the block in question
@@ -1800,17 +2572,50 @@ fn setup_head(
 					Ok(())
 				});
 
-				if res.is_ok() {
-					break;
-				} else {
+				if let Err(e) = res {
 					// We may have corrupted the MMR backend files last time we stopped the
 					// node. If this happens we rewind to the previous header,
 					// delete the "bad" block and try again.
+					if header.height == 0 {
+						error!("init: unable to validate the MMRs at genesis, {}", e);
+						return Err(e);
+					}
+					if repaired_blocks >= MAX_INIT_REPAIR_DEPTH
+						|| header.height <= repair_tail_height
+					{
+						error!(
+							"init: MMRs are inconsistent with the block {} at {}, unable to repair, {}",
+							header.hash(),
+							header.height,
+							e
+						);
+						return Err(ErrorKind::Other(format!(
+							"Unable to repair the chain state after {} blocks, please resync the node",
+							repaired_blocks
+						))
+						.into());
+					}
+					warn!(
+						"init: MMRs are inconsistent with the block {} at {}, rewinding, {}",
+						header.hash(),
+						header.height,
+						e
+					);
 					let prev_header = batch.get_block_header(&head.prev_block_h)?;
 
-					txhashset::extending(header_pmmr, txhashset, &mut batch, |ext, batch| {
-						pipe::rewind_and_apply_fork(&prev_header, ext, batch)
-					})?;
+					// Prev block is validated on the next iteration, so the failure is not fatal here.
+					// The repair is bounded by the body tail and MAX_INIT_REPAIR_DEPTH.
+					if let Err(e) =
+						txhashset::extending(header_pmmr, txhashset, &mut batch, |ext, batch| {
+							pipe::rewind_and_apply_fork(&prev_header, ext, batch)
+						}) {
+						warn!(
+							"init: unable to rewind to {} at {}, {}",
+							prev_header.hash(),
+							prev_header.height,
+							e
+						);
+					}
 
 					// Now "undo" the latest block and forget it ever existed.
 					// We will request it from a peer during sync as necessary.
@@ -1818,7 +2623,10 @@ fn setup_head(
 						let _ = batch.delete_block(&header.hash());
 						head = Tip::from_header(&prev_header);
 						batch.save_body_head(&head)?;
+						repaired_blocks += 1;
 					}
+				} else {
+					break;
 				}
 			}
 		}
@@ -0,0 +1,147 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache of the difficulty adjustment windows. Next difficulty needs the last
+//! DIFFICULTY_ADJUST_WINDOW + 1 headers, and every validated header and every mining
+//! template reads them again. The window of a block is built from the cached window of
+//! its parent and a single header read.
+//!
+//! Windows are keyed by the block hash, the window of a block never changes. The cache
+//! is cleared on reorg anyway, the windows of the abandoned fork won't be asked for again
+//! and would only push out the useful ones.
+
+use crate::core::consensus::HeaderInfo;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::BlockHeader;
+use crate::core::global;
+use crate::core::pow::Difficulty;
+use crate::util::Mutex;
+use lru_cache::LruCache;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Number of the cached windows
+pub const DIFFICULTY_CACHE_SIZE: usize = 64;
+
+struct CachedWindow {
+	// Total difficulty of the last block of the window
+	total_difficulty: Difficulty,
+	// Difficulty data, the latest first
+	infos: Vec<HeaderInfo>,
+}
+
+/// Difficulty windows by the hash of their last block
+pub struct DifficultyCache {
+	windows: Mutex<LruCache<Hash, Arc<CachedWindow>>>,
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+impl DifficultyCache {
+	/// Empty cache
+	pub fn new() -> DifficultyCache {
+		DifficultyCache {
+			windows: Mutex::new(LruCache::new(DIFFICULTY_CACHE_SIZE)),
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+		}
+	}
+
+	/// Difficulty data of the window that ends at the block, the latest first, the same
+	/// that `DifficultyIter` yields. Headers are read with get_header only if the window
+	/// is not cached, the single header if the window of its parent is cached.
+	pub fn window<F>(&self, start: &Hash, get_header: F) -> Vec<HeaderInfo>
+	where
+		F: Fn(&Hash) -> Option<BlockHeader>,
+	{
+		if let Some(window) = self.windows.lock().get_mut(start) {
+			self.hits.fetch_add(1, Ordering::Relaxed);
+			return window.infos.clone();
+		}
+		self.misses.fetch_add(1, Ordering::Relaxed);
+
+		let header = match get_header(start) {
+			Some(header) => header,
+			None => return vec![],
+		};
+		let needed = global::difficulty_adjust_window() as usize + 1;
+		let parent = self.windows.lock().get_mut(&header.prev_hash).cloned();
+		let window = match parent {
+			Some(parent) => {
+				let mut infos = Vec::with_capacity(needed);
+				infos.push(header_info(&header, parent.total_difficulty));
+				infos.extend(parent.infos.iter().take(needed - 1).cloned());
+				CachedWindow {
+					total_difficulty: header.total_difficulty(),
+					infos,
+				}
+			}
+			None => walk_window(header, needed, get_header),
+		};
+
+		let infos = window.infos.clone();
+		self.windows.lock().insert(*start, Arc::new(window));
+		infos
+	}
+
+	/// Drop all the windows
+	pub fn invalidate(&self) {
+		self.windows.lock().clear();
+	}
+
+	/// Number of the windows served from the cache
+	pub fn hits(&self) -> u64 {
+		self.hits.load(Ordering::Relaxed)
+	}
+
+	/// Number of the windows that were built
+	pub fn misses(&self) -> u64 {
+		self.misses.load(Ordering::Relaxed)
+	}
+}
+
+// Read the window header by header, like DifficultyIter does
+fn walk_window<F>(header: BlockHeader, needed: usize, get_header: F) -> CachedWindow
+where
+	F: Fn(&Hash) -> Option<BlockHeader>,
+{
+	let total_difficulty = header.total_difficulty();
+	let mut infos = Vec::with_capacity(needed);
+	let mut header = header;
+	loop {
+		let prev = get_header(&header.prev_hash);
+		let prev_difficulty = prev
+			.as_ref()
+			.map_or(Difficulty::zero(), |p| p.total_difficulty());
+		infos.push(header_info(&header, prev_difficulty));
+		match prev {
+			Some(prev) if infos.len() < needed => header = prev,
+			_ => break,
+		}
+	}
+	CachedWindow {
+		total_difficulty,
+		infos,
+	}
+}
+
+fn header_info(header: &BlockHeader, prev_difficulty: Difficulty) -> HeaderInfo {
+	HeaderInfo::new(
+		header.hash(),
+		header.timestamp.timestamp() as u64,
+		header.total_difficulty() - prev_difficulty,
+		header.pow.secondary_scaling,
+		header.pow.is_secondary(),
+	)
+}
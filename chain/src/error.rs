@@ -13,6 +13,7 @@
 // limitations under the License.
 
 //! Error types for chain
+use crate::core::core::hash::Hash;
 use crate::core::core::{block, committed, transaction};
 use crate::core::ser;
 use crate::keychain;
@@ -59,6 +60,21 @@ pub enum ErrorKind {
 	/// Peer abusively sending us an old block we already have
 	#[fail(display = "Old Block")]
 	OldBlock,
+	/// Switching to the fork would reorg more blocks than allowed
+	#[fail(
+		display = "Reorg deeper than {} blocks is rejected, fork tip {} at {}, head at {}",
+		max_depth, fork_tip, fork_tip_height, head_height
+	)]
+	DeepReorg {
+		/// Max reorg depth allowed
+		max_depth: u64,
+		/// Tip of the fork we refused to switch to
+		fork_tip: Hash,
+		/// Height of the fork tip
+		fork_tip_height: u64,
+		/// Height of our head
+		head_height: u64,
+	},
 	/// The block doesn't sum correctly or a tx signature is invalid
 	#[fail(display = "Invalid Block Proof, {}", _0)]
 	InvalidBlockProof(block::Error),
@@ -157,6 +173,72 @@ pub enum ErrorKind {
 	SyncError(String),
 }
 
+/// Class of the chain error, it defines how the node should recover from it
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ErrorClass {
+	/// IO or state issue that is expected to go away, the operation can be retried
+	Transient,
+	/// Data supplied by the peer is invalid, the peer should be banned
+	BadData,
+	/// Local chain data is corrupted, the node should repair it
+	Corruption,
+	/// Valid data that doesn't fit our chain right now (orphan, fork, too deep reorg)
+	Rejected,
+	/// Unexpected internal failure
+	Internal,
+}
+
+impl ErrorClass {
+	/// Hint for the operator about the error of this class
+	pub fn recovery_hint(&self) -> &'static str {
+		match self {
+			ErrorClass::Transient => {
+				"Temporary failure, the node retries automatically. Check the disk space and the IO errors if it persists."
+			}
+			ErrorClass::BadData => {
+				"Peer sent invalid data, the peer is banned. No action is needed."
+			}
+			ErrorClass::Corruption => {
+				"Local chain data is inconsistent, the node repairs the chain heads. Restart the node to run the full startup checks if it persists, resync is needed only if they fail."
+			}
+			ErrorClass::Rejected => {
+				"Data doesn't fit our chain at the moment, it is expected during the sync and the forks. No action is needed."
+			}
+			ErrorClass::Internal => {
+				"Unexpected internal error, please check the logs and report it if it persists."
+			}
+		}
+	}
+}
+
+impl ErrorKind {
+	/// Class of the error
+	pub fn class(&self) -> ErrorClass {
+		match self {
+			ErrorKind::Unfit(_) | ErrorKind::Orphan(_) | ErrorKind::DeepReorg { .. } => {
+				ErrorClass::Rejected
+			}
+			ErrorKind::Stopped | ErrorKind::SyncError(_) | ErrorKind::TxHashSetErr(_) => {
+				ErrorClass::Transient
+			}
+			ErrorKind::StoreErr(e, _) => match e {
+				store::Error::NotFoundErr(_) => ErrorClass::Internal,
+				store::Error::SerErr(_) => ErrorClass::Corruption,
+				_ => ErrorClass::Transient,
+			},
+			ErrorKind::SerErr(e) => match e {
+				ser::Error::IOErr(_, _) => ErrorClass::Transient,
+				_ => ErrorClass::Corruption,
+			},
+			ErrorKind::FileReadErr(_) | ErrorKind::Bitmap => ErrorClass::Corruption,
+			ErrorKind::GenesisBlockRequired | ErrorKind::Keychain(_) | ErrorKind::Other(_) => {
+				ErrorClass::Internal
+			}
+			_ => ErrorClass::BadData,
+		}
+	}
+}
+
 impl Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let cause = match self.cause() {
@@ -189,19 +271,14 @@ impl Error {
 		self.inner.backtrace()
 	}
 
+	/// Class of the error
+	pub fn class(&self) -> ErrorClass {
+		self.inner.get_context().class()
+	}
+
 	/// Whether the error is due to a block that was intrinsically wrong
 	pub fn is_bad_data(&self) -> bool {
-		// shorter to match on all the "not the block's fault" errors
-		match self.kind() {
-			ErrorKind::Unfit(_)
-			| ErrorKind::Orphan(_)
-			| ErrorKind::StoreErr(_, _)
-			| ErrorKind::SerErr(_)
-			| ErrorKind::TxHashSetErr(_)
-			| ErrorKind::GenesisBlockRequired
-			| ErrorKind::Other(_) => false,
-			_ => true,
-		}
+		self.class() == ErrorClass::BadData
 	}
 }
 
@@ -38,20 +38,29 @@ use grin_core as core;
 use grin_keychain as keychain;
 use grin_util as util;
 
+pub mod block_profile;
 mod chain;
+pub mod difficulty_cache;
 mod error;
 pub mod linked_list;
 pub mod pipe;
+pub mod pow_batch;
+pub mod rproof_spot_check;
 pub mod store;
 pub mod txhashset;
 pub mod types;
+pub mod utxo_digest;
 
 // Re-export the base interface
 
-pub use crate::chain::{Chain, BLOCK_TO_BAN, MAX_ORPHAN_SIZE};
-pub use crate::error::{Error, ErrorKind};
-pub use crate::store::ChainStore;
+pub use crate::block_profile::BlockTimings;
+pub use crate::chain::{Chain, ReadSnapshot, BLOCK_TO_BAN, MAX_ORPHAN_SIZE};
+pub use crate::error::{Error, ErrorClass, ErrorKind};
+pub use crate::store::{ChainSnapshot, ChainStore};
 pub use crate::types::{
-	BlockStatus, ChainAdapter, Options, SyncState, SyncStatus, Tip, TxHashsetDownloadStats,
-	TxHashsetWriteStatus,
+	BlockKernelFees, BlockStatus, ChainAdapter, ChainErrorInfo, CompactionStats, DeepReorgAlert,
+	DeferredRangeproofs, KernelFee, KernelFeeIndexHead, MetricsSnapshot, Options,
+	RangeproofValidation, SyncProgress, SyncSourceCandidate, SyncSourceDecision, SyncStageRecord,
+	SyncState, SyncStatus, Tip, TxHashsetDownloadStats, TxHashsetWriteStatus, UptimeRecord,
 };
+pub use crate::utxo_digest::UtxoSetDigest;
@@ -14,24 +14,30 @@
 
 //! Implementation of the chain block acceptance (or refusal) pipeline.
 
+use crate::block_profile::{to_micros, BlockTimings};
 use crate::core::consensus;
 use crate::core::core::hash::Hashed;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::Committed;
 use crate::core::core::{
 	block, Block, BlockHeader, BlockSums, HeaderVersion, OutputIdentifier, TransactionBody,
+	Weighting,
 };
 use crate::core::global;
 use crate::core::pow;
+use crate::difficulty_cache::DifficultyCache;
 use crate::error::{Error, ErrorKind};
+use crate::pow_batch;
 use crate::store;
 use crate::txhashset;
 use crate::types::{CommitPos, Options, Tip};
 use crate::util::RwLock;
+use chrono::prelude::{DateTime, Utc};
 use grin_core::core::hash::Hash;
 use std::collections::HashSet;
 use std::iter::FromIterator;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Contextual information required to process a new block and either reject or
 /// accept it.
@@ -48,10 +54,117 @@ pub struct BlockContext<'a> {
 	pub batch: store::Batch<'a>,
 	/// The verifier cache (caching verifier for rangeproofs and kernel signatures)
 	pub verifier_cache: Arc<RwLock<dyn VerifierCache>>,
+	/// Timing breakdown of the block processing
+	pub timings: BlockTimings,
+	/// Cached difficulty adjustment windows
+	pub difficulty_cache: Arc<DifficultyCache>,
 }
 
+/// Deep reorg alerts are not repeated more often than that
+const DEEP_REORG_ALERT_INTERVAL: Duration = Duration::from_secs(600);
+
 lazy_static! {
 	static ref INVALID_BLOCK_HASHES: RwLock<HashSet<Hash>> = RwLock::new(HashSet::new());
+	static ref REORG_GUARD: RwLock<ReorgGuard> = RwLock::new(ReorgGuard::default());
+}
+
+/// Anti deep reorg rule. Protects against the long range attacks, the reorgs deeper
+/// than max_depth are rejected unless the operator allows them for a while.
+#[derive(Default)]
+struct ReorgGuard {
+	// None - no limit
+	max_depth: Option<u64>,
+	// Deep reorgs are accepted until that time
+	allowed_until: Option<DateTime<Utc>>,
+	// Last time the rejected deep reorg was reported
+	last_alert: Option<Instant>,
+}
+
+/// Setup the max reorg depth defined at the config. 0 or None - no limit.
+pub fn init_max_reorg_depth(max_depth: Option<u64>) {
+	REORG_GUARD.write().max_depth = max_depth.filter(|d| *d > 0);
+}
+
+/// Max reorg depth, None if reorgs are not limited.
+pub fn max_reorg_depth() -> Option<u64> {
+	REORG_GUARD.read().max_depth
+}
+
+/// Operator override, accept the reorgs of any depth until the provided time.
+/// None cancels the override.
+pub fn allow_deep_reorgs(until: Option<DateTime<Utc>>) {
+	REORG_GUARD.write().allowed_until = until;
+}
+
+/// Time until the reorgs of any depth are accepted, None if there is no override.
+pub fn deep_reorgs_allowed_until() -> Option<DateTime<Utc>> {
+	REORG_GUARD
+		.read()
+		.allowed_until
+		.filter(|until| *until > Utc::now())
+}
+
+/// Check if the rejected deep reorg needs to be reported. Rejections repeat every time
+/// peers send us the fork, the alerts are throttled.
+pub fn deep_reorg_alert_due() -> bool {
+	let mut guard = REORG_GUARD.write();
+	let due = guard
+		.last_alert
+		.map(|t| t.elapsed() > DEEP_REORG_ALERT_INTERVAL)
+		.unwrap_or(true);
+	if due {
+		guard.last_alert = Some(Instant::now());
+	}
+	due
+}
+
+/// Reject the switch from the head to the fork ending at fork_tip (it has more work)
+/// if more than max_depth blocks would be disconnected.
+fn check_reorg_depth(
+	fork_tip: &BlockHeader,
+	head: &Tip,
+	batch: &store::Batch<'_>,
+) -> Result<(), Error> {
+	let max_depth = match max_reorg_depth() {
+		Some(max_depth) => max_depth,
+		None => return Ok(()),
+	};
+	if fork_tip.hash() == head.last_block_h {
+		return Ok(());
+	}
+
+	// Walk back both chains to the fork point, no further than max_depth
+	let mut ours = batch.get_block_header(&head.last_block_h)?;
+	let mut theirs = fork_tip.clone();
+	while theirs.height > ours.height {
+		theirs = batch.get_previous_header(&theirs)?;
+	}
+	while ours.hash() != theirs.hash() {
+		if head.height.saturating_sub(ours.height) >= max_depth {
+			if let Some(until) = deep_reorgs_allowed_until() {
+				warn!(
+					"Accepting reorg deeper than {} blocks to {} at {}, allowed by the operator until {}",
+					max_depth,
+					fork_tip.hash(),
+					fork_tip.height,
+					until
+				);
+				return Ok(());
+			}
+			return Err(ErrorKind::DeepReorg {
+				max_depth,
+				fork_tip: fork_tip.hash(),
+				fork_tip_height: fork_tip.height,
+				head_height: head.height,
+			}
+			.into());
+		}
+		if ours.height == theirs.height {
+			theirs = batch.get_previous_header(&theirs)?;
+		}
+		ours = batch.get_previous_header(&ours)?;
+	}
+	Ok(())
 }
 
 /// Setup the banned header hashes defined at the config.
@@ -137,6 +250,16 @@ pub fn check_against_spent_output(
 // Validate only the proof of work in a block header.
 // Used to cheaply validate pow before checking if orphan or continuing block validation.
 fn validate_pow_only(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(), Error> {
+	validate_pow_params(header, ctx, true)
+}
+
+// Validate the PoW related parameters, the cuckoo verification is optional because
+// the header sync verifies it for the whole batch.
+fn validate_pow_params(
+	header: &BlockHeader,
+	ctx: &mut BlockContext<'_>,
+	verify_cuckoo: bool,
+) -> Result<(), Error> {
 	let hash = header.hash();
 	if INVALID_BLOCK_HASHES.read().contains(&hash) {
 		error!("Invalid header found: {}. Rejecting it!", hash);
@@ -150,7 +273,7 @@ fn validate_pow_only(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result
 	if !header.pow.is_primary() && !header.pow.is_secondary() {
 		return Err(ErrorKind::LowEdgebits.into());
 	}
-	if (ctx.pow_verifier)(header).is_err() {
+	if verify_cuckoo && (ctx.pow_verifier)(header).is_err() {
 		error!(
 			"pipe: error validating header with cuckoo edge_bits {}",
 			header.pow.edge_bits(),
@@ -177,6 +300,8 @@ pub fn process_block(
 		b.inputs().version_str(),
 	);
 
+	let start = Instant::now();
+
 	// Read current chain head from db via the batch.
 	// We use this for various operations later.
 	let head = ctx.batch.head()?;
@@ -192,10 +317,16 @@ pub fn process_block(
 	// Get previous header from the db.
 	let prev = prev_header_store(&b.header, &mut ctx.batch)?;
 
+	// Refuse to switch to the fork if it is too deep.
+	if has_more_work(&b.header, &head) {
+		check_reorg_depth(&prev, &head, &ctx.batch)?;
+	}
+
 	// Process the header for the block.
 	// Note: We still want to process the full block if we have seen this header before
 	// as we may have processed it "header first" and not yet processed the full block.
 	process_block_header(&b.header, ctx)?;
+	ctx.timings.contextual_checks += to_micros(start.elapsed());
 
 	// Validate the block itself, make sure it is internally consistent.
 	// Use the verifier_cache for verifying rangeproofs and kernel signatures.
@@ -203,6 +334,8 @@ pub fn process_block(
 
 	// Start a chain extension unit of work dependent on the success of the
 	// internal validation and saving operations
+	let extending_start = Instant::now();
+	let mut extension_checks = 0;
 	let header_pmmr = &mut ctx.header_pmmr;
 	let txhashset = &mut ctx.txhashset;
 	let batch = &mut ctx.batch;
@@ -211,6 +344,7 @@ pub fn process_block(
 		let fork_point = fork_point_local_blocks.0;
 		let local_branch_blocks = fork_point_local_blocks.1;
 
+		let checks_start = Instant::now();
 		replay_attack_check(b, fork_point.height, local_branch_blocks, ext, batch)?;
 
 		// Check any coinbase being spent have matured sufficiently.
@@ -227,6 +361,7 @@ pub fn process_block(
 		// accounting for inputs/outputs/kernels in this new block.
 		// We know there are no double-spends etc. if this verifies successfully.
 		verify_block_sums(b, batch)?;
+		extension_checks = to_micros(checks_start.elapsed());
 
 		// Apply the block to the txhashset state.
 		// Validate the txhashset roots and sizes against the block header.
@@ -244,6 +379,9 @@ pub fn process_block(
 
 		Ok(fork_point)
 	})?;
+	// Everything in the extension except the contextual checks is the MMR work
+	ctx.timings.contextual_checks += extension_checks;
+	ctx.timings.mmr_apply += to_micros(extending_start.elapsed()).saturating_sub(extension_checks);
 
 	// Add the validated block to the db.
 	// Note we do this in the outer batch, not the child batch from the extension
@@ -311,10 +449,26 @@ pub fn sync_block_headers(
 		}
 	}
 
+	// Cheap checks first, so the invalid chunk is rejected before the cuckoo verification
+	check_headers_chunk(headers, ctx)?;
+
+	// Cuckoo verification is the most expensive part, verify the whole chunk in parallel
+	if !ctx.opts.contains(Options::SKIP_POW) {
+		if let Err(idx) = pow_batch::verify_headers_pow(headers, ctx.pow_verifier) {
+			error!(
+				"pipe: error validating header {} at {} with cuckoo edge_bits {}",
+				headers[idx].hash(),
+				headers[idx].height,
+				headers[idx].pow.edge_bits(),
+			);
+			return Err(ErrorKind::InvalidPow.into());
+		}
+	}
+
 	// Validate each header in the chunk and add to our db.
 	// Note: This batch may be rolled back later if the MMR does not validate successfully.
 	for header in headers {
-		validate_header(header, ctx)?;
+		validate_header_params(header, ctx, false)?;
 		add_block_header(header, &ctx.batch)?;
 	}
 
@@ -325,6 +479,22 @@ pub fn sync_block_headers(
 	})
 }
 
+// Checks of the sync chunk headers that don't need the cuckoo verification: known bad
+// headers, edge bits and the previous header, that is either the previous one in the chunk
+// or a known one. The full validation follows the batch cuckoo verification.
+fn check_headers_chunk(headers: &[BlockHeader], ctx: &mut BlockContext<'_>) -> Result<(), Error> {
+	let mut prev_hash = None;
+	for header in headers {
+		check_bad_header(header)?;
+		validate_pow_params(header, ctx, false)?;
+		if prev_hash != Some(header.prev_hash) {
+			ctx.batch.get_previous_header(header)?;
+		}
+		prev_hash = Some(header.hash());
+	}
+	Ok(())
+}
+
 /// Process a block header. Update the header MMR and corresponding header_head if this header
 /// increases the total work relative to header_head.
 /// Note: In contrast to processing a full block we treat "already known" as success
@@ -356,6 +526,11 @@ pub fn process_block_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) ->
 	// We want to validate this individual header before applying it to our header PMMR.
 	validate_header(header, ctx)?;
 
+	// Refuse to switch the header chain to the fork if it is too deep.
+	if has_more_work(header, &header_head) {
+		check_reorg_depth(&prev_header, &header_head, &ctx.batch)?;
+	}
+
 	// Apply the header to the header PMMR, making sure we put the extension in the correct state
 	// based on previous header first.
 	txhashset::header_extending(&mut ctx.header_pmmr, &mut ctx.batch, |ext, batch| {
@@ -438,6 +613,14 @@ fn check_bad_header(header: &BlockHeader) -> Result<(), Error> {
 /// to make it as cheap as possible. The different validations are also
 /// arranged by order of cost to have as little DoS surface as possible.
 fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(), Error> {
+	validate_header_params(header, ctx, true)
+}
+
+fn validate_header_params(
+	header: &BlockHeader,
+	ctx: &mut BlockContext<'_>,
+	verify_cuckoo: bool,
+) -> Result<(), Error> {
 	// First I/O cost, delayed as late as possible.
 	let prev = prev_header_store(header, &mut ctx.batch)?;
 
@@ -489,7 +672,7 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 	if !ctx.opts.contains(Options::SKIP_POW) {
 		// Quick check of this header in isolation. No point proceeding if this fails.
 		// We can do this without needing to iterate over previous headers.
-		validate_pow_only(header, ctx)?;
+		validate_pow_params(header, ctx, verify_cuckoo)?;
 
 		if header.total_difficulty() <= prev.total_difficulty() {
 			return Err(ErrorKind::DifficultyTooLow.into());
@@ -504,9 +687,11 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 		// explicit check to ensure total_difficulty has increased by exactly
 		// the _network_ difficulty of the previous block
 		// (during testnet1 we use _block_ difficulty here)
-		let child_batch = ctx.batch.child()?;
-		let diff_iter = store::DifficultyIter::from_batch(prev.hash(), child_batch);
-		let next_header_info = consensus::next_difficulty(header.height, diff_iter);
+		let batch = &ctx.batch;
+		let diff_window = ctx
+			.difficulty_cache
+			.window(&prev.hash(), |h| batch.get_block_header(h).ok());
+		let next_header_info = consensus::next_difficulty(header.height, diff_window);
 		if target_difficulty != next_header_info.difficulty {
 			info!(
 				"validate_header: header target difficulty {} != {}",
@@ -528,11 +713,35 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 	Ok(())
 }
 
+// Equivalent of the Block::validate, split into the steps so we can profile them.
 fn validate_block(block: &Block, ctx: &mut BlockContext<'_>) -> Result<(), Error> {
+	let start = Instant::now();
 	let prev = ctx.batch.get_previous_header(&block.header)?;
 	block
-		.validate(&prev.total_kernel_offset, ctx.verifier_cache.clone())
+		.body
+		.validate_read(Weighting::AsBlock)
+		.map_err(|e| ErrorKind::InvalidBlockProof(block::Error::from(e)))?;
+	ctx.timings.contextual_checks += to_micros(start.elapsed());
+
+	let start = Instant::now();
+	block
+		.body
+		.verify_rangeproofs()
+		.map_err(|e| ErrorKind::InvalidBlockProof(block::Error::from(e)))?;
+	ctx.timings.rangeproof_batch += to_micros(start.elapsed());
+
+	let start = Instant::now();
+	block
+		.body
+		.verify_kernel_signatures()
+		.map_err(|e| ErrorKind::InvalidBlockProof(block::Error::from(e)))?;
+	ctx.timings.kernel_sig_batch += to_micros(start.elapsed());
+
+	let start = Instant::now();
+	block
+		.validate_consensus_rules(&prev.total_kernel_offset)
 		.map_err(ErrorKind::InvalidBlockProof)?;
+	ctx.timings.contextual_checks += to_micros(start.elapsed());
 	Ok(())
 }
 
@@ -0,0 +1,215 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Batched PoW verification of the headers. Cuckoo verification dominates the header
+//! sync on the low power devices, so the headers of the sync batch are verified in
+//! parallel by a bounded pool of the verifier threads. The threads are started once
+//! and reused by the following batches. The CPU share used by the verification is
+//! limited by the config.
+
+use crate::core::core::BlockHeader;
+use crate::core::pow;
+use crate::util::Mutex;
+use std::cmp;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+/// Max number of the verifier threads, no matter how many cores we have
+pub const MAX_VERIFIER_THREADS: usize = 8;
+
+lazy_static! {
+	// Max CPU usage by the verification, percents of all cores
+	static ref CPU_LIMIT_PERCENT: AtomicUsize = AtomicUsize::new(100);
+
+	// Verifier threads, shared by all the batches
+	static ref VERIFIER_POOL: VerifierPool = VerifierPool::new();
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Pool of the verifier threads. Threads are started on demand, up to the requested
+/// number, and wait for the jobs between the batches.
+struct VerifierPool {
+	jobs: Mutex<mpsc::Sender<Job>>,
+	receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+	workers: Arc<AtomicUsize>,
+}
+
+// Keeps the number of the live workers, decremented when the worker panics
+struct WorkerGuard(Arc<AtomicUsize>);
+
+impl Drop for WorkerGuard {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+impl VerifierPool {
+	fn new() -> VerifierPool {
+		let (sender, receiver) = mpsc::channel();
+		VerifierPool {
+			jobs: Mutex::new(sender),
+			receiver: Arc::new(Mutex::new(receiver)),
+			workers: Arc::new(AtomicUsize::new(0)),
+		}
+	}
+
+	/// Make sure at least `threads` workers are running, returns the number of the workers
+	fn ensure_workers(&self, threads: usize) -> usize {
+		while self.workers.load(Ordering::SeqCst) < threads {
+			let idx = self.workers.fetch_add(1, Ordering::SeqCst);
+			let guard = WorkerGuard(self.workers.clone());
+			let receiver = self.receiver.clone();
+			let res = thread::Builder::new()
+				.name(format!("pow_verifier_{}", idx))
+				.spawn(move || {
+					let _guard = guard;
+					loop {
+						let job = receiver.lock().recv();
+						match job {
+							Ok(job) => job(),
+							Err(_) => break,
+						}
+					}
+				});
+			if let Err(e) = res {
+				// Not critical, the running threads will do the job
+				warn!("Unable to start PoW verifier thread, {}", e);
+				break;
+			}
+		}
+		self.workers.load(Ordering::SeqCst)
+	}
+
+	fn execute(&self, job: Job) -> bool {
+		self.jobs.lock().send(job).is_ok()
+	}
+}
+
+/// Setup the CPU usage limit of the headers PoW verification, percents of all cores.
+/// None - no limit.
+pub fn init_cpu_limit(percent: Option<u8>) {
+	let percent = percent.map(|p| p as usize).unwrap_or(100);
+	CPU_LIMIT_PERCENT.store(cmp::max(1, cmp::min(100, percent)), Ordering::Relaxed);
+}
+
+/// CPU usage limit of the headers PoW verification, percents of all cores
+pub fn cpu_limit() -> usize {
+	CPU_LIMIT_PERCENT.load(Ordering::Relaxed)
+}
+
+/// Number of the verifier threads and the share of the time every thread is allowed
+/// to be busy, in percents.
+fn verifier_pool_size(cores: usize, cpu_limit: usize) -> (usize, usize) {
+	// Cores worth of the CPU time, in percents
+	let budget = cores * cpu_limit;
+	let threads = cmp::max(1, cmp::min(MAX_VERIFIER_THREADS, budget / 100));
+	let duty = cmp::min(100, budget / threads);
+	(threads, cmp::max(1, duty))
+}
+
+/// Verify the PoW of the headers in parallel. The verification is aborted on the first
+/// failure, index of the failed header is returned as the error. If several headers
+/// fail, the lowest index of the checked ones is reported.
+pub fn verify_headers_pow(
+	headers: &[BlockHeader],
+	pow_verifier: fn(&BlockHeader) -> Result<(), pow::Error>,
+) -> Result<(), usize> {
+	if headers.is_empty() {
+		return Ok(());
+	}
+	let (threads, duty) = verifier_pool_size(num_cpus::get(), cpu_limit());
+	let threads = cmp::min(threads, headers.len());
+
+	if threads == 1 && duty >= 100 {
+		// Nothing to parallelize, no need to copy the headers
+		return verify_sequentially(headers, pow_verifier);
+	}
+
+	let headers = Arc::new(headers.to_vec());
+	let next = Arc::new(AtomicUsize::new(0));
+	let abort = Arc::new(AtomicBool::new(false));
+	let failed: Arc<Mutex<Option<usize>>> = Arc::new(Mutex::new(None));
+	// Every job reports back when it is done. Sender of the panicked job is dropped
+	// without the report.
+	let (done_tx, done_rx) = mpsc::channel();
+
+	let threads = cmp::min(threads, VERIFIER_POOL.ensure_workers(threads));
+	let mut jobs = 0;
+	for _ in 0..threads {
+		let headers = headers.clone();
+		let next = next.clone();
+		let abort = abort.clone();
+		let failed = failed.clone();
+		let done_tx = done_tx.clone();
+		let job: Job = Box::new(move || {
+			while !abort.load(Ordering::Relaxed) {
+				let idx = next.fetch_add(1, Ordering::Relaxed);
+				if idx >= headers.len() {
+					break;
+				}
+				let start = Instant::now();
+				if pow_verifier(&headers[idx]).is_err() {
+					abort.store(true, Ordering::Relaxed);
+					let mut failed = failed.lock();
+					*failed = Some(failed.map_or(idx, |f| cmp::min(f, idx)));
+					break;
+				}
+				if duty < 100 {
+					// Keep this thread idle for the rest of its time share
+					let busy = start.elapsed();
+					thread::sleep(busy * (100 - duty) as u32 / duty as u32);
+				}
+			}
+			let _ = done_tx.send(());
+		});
+		if VERIFIER_POOL.execute(job) {
+			jobs += 1;
+		}
+	}
+	drop(done_tx);
+
+	let mut panicked = jobs == 0;
+	for _ in 0..jobs {
+		if done_rx.recv().is_err() {
+			abort.store(true, Ordering::Relaxed);
+			panicked = true;
+			break;
+		}
+	}
+
+	if let Some(idx) = *failed.lock() {
+		return Err(idx);
+	}
+	if panicked {
+		// Not all the headers are verified, let's do it here
+		return verify_sequentially(&headers, pow_verifier);
+	}
+	Ok(())
+}
+
+fn verify_sequentially(
+	headers: &[BlockHeader],
+	pow_verifier: fn(&BlockHeader) -> Result<(), pow::Error>,
+) -> Result<(), usize> {
+	for (i, header) in headers.iter().enumerate() {
+		if pow_verifier(header).is_err() {
+			return Err(i);
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,71 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rangeproof spot-check mode. THIS IS A REDUCED VALIDATION MODE, it is off by default.
+//! Verification of the UTXO rangeproofs dominates the state validation on the Raspberry Pi
+//! class hardware. With the spot-check enabled only a random sample of the rangeproofs is
+//! verified during the state sync, the node goes online and the rest of the rangeproofs
+//! is verified lazily in the background. Until the background verification is finished
+//! the node trusts the most work chain for the skipped rangeproofs.
+
+use rand::{thread_rng, Rng};
+use std::cmp;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+lazy_static! {
+	// Percents of the rangeproofs verified during the state validation
+	static ref SAMPLE_PERCENT: AtomicUsize = AtomicUsize::new(100);
+	// Set when the background verification found an invalid rangeproof
+	static ref VERIFICATION_FAILED: AtomicBool = AtomicBool::new(false);
+}
+
+/// Setup the share of the rangeproofs that are verified during the state validation,
+/// in percents. None or 100 - all rangeproofs are verified (the default).
+pub fn init_sample_percent(percent: Option<u8>) {
+	let percent = percent.map(|p| p as usize).unwrap_or(100);
+	let percent = cmp::max(1, cmp::min(100, percent));
+	if percent < 100 {
+		warn!(
+			"REDUCED VALIDATION: only {}% of the rangeproofs are verified during the state sync, the rest are verified in background",
+			percent
+		);
+	}
+	SAMPLE_PERCENT.store(percent, Ordering::Relaxed);
+}
+
+/// Share of the rangeproofs verified during the state validation, in percents
+pub fn sample_percent() -> usize {
+	SAMPLE_PERCENT.load(Ordering::Relaxed)
+}
+
+/// Is the spot-check mode enabled
+pub fn is_enabled() -> bool {
+	sample_percent() < 100
+}
+
+/// Random choice whether the rangeproof goes into the sample
+pub fn is_sampled(percent: usize) -> bool {
+	percent >= 100 || thread_rng().gen_range(0, 100) < percent
+}
+
+/// Report the failure of the background verification
+pub fn set_verification_failed() {
+	VERIFICATION_FAILED.store(true, Ordering::Relaxed);
+}
+
+/// True if the background verification found an invalid rangeproof. The chain state
+/// can't be trusted in this case, it must be synced again with the full validation.
+pub fn is_verification_failed() -> bool {
+	VERIFICATION_FAILED.load(Ordering::Relaxed)
+}
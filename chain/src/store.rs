@@ -20,12 +20,15 @@ use crate::core::core::{Block, BlockHeader, BlockSums, Inputs};
 use crate::core::pow::Difficulty;
 use crate::core::ser::ProtocolVersion;
 use crate::linked_list::MultiIndex;
-use crate::types::{CommitPos, HashHeight, Tip};
+use crate::types::{
+	BlockKernelFees, CommitPos, DeferredRangeproofs, HashHeight, KernelFeeIndexHead,
+	MetricsSnapshot, SyncProgress, Tip, UptimeRecord,
+};
 use crate::util::secp::pedersen::Commitment;
 
 use croaring::Bitmap;
 use grin_store as store;
-use grin_store::{option_to_not_found, to_key, Error, SerIterator};
+use grin_store::{option_to_not_found, to_key, to_key_u64, u64_to_key, Error, SerIterator};
 use std::convert::TryInto;
 use std::sync::Arc;
 
@@ -47,6 +50,14 @@ const BLOCK_INPUT_BITMAP_PREFIX: u8 = b'B';
 const BLOCK_SUMS_PREFIX: u8 = b'M';
 const BLOCK_SPENT_PREFIX: u8 = b'S';
 const BLOCK_SPENT_COMMITMENT_PREFIX: u8 = b'C';
+const UPTIME_PREFIX: u8 = b'U';
+const KERNEL_FEE_PREFIX: u8 = b'f';
+const KERNEL_FEE_HEAD_PREFIX: u8 = b'F';
+const SYNC_PROGRESS_PREFIX: u8 = b'Y';
+const DEFERRED_RANGEPROOFS_PREFIX: u8 = b'R';
+const METRICS_SNAPSHOT_PREFIX: u8 = b'Q';
+const HEADER_HEIGHT_PREFIX: u8 = b'j';
+const HEADER_HEIGHT_INIT_PREFIX: u8 = b'J';
 
 /// All chain-related database operations
 pub struct ChainStore {
@@ -60,6 +71,18 @@ impl ChainStore {
 		Ok(ChainStore { db })
 	}
 
+	/// Open the existing chain store in read-only mode. Can be used by a separate
+	/// process while the node is running, all writes are rejected.
+	pub fn open_read_only(db_root: &str) -> Result<ChainStore, Error> {
+		let db = store::Store::open_read_only(db_root, None, Some(STORE_SUBPATH), None)?;
+		Ok(ChainStore { db })
+	}
+
+	/// Release the DB reader slots left by the terminated processes.
+	pub fn check_stale_readers(&self) -> Result<i32, Error> {
+		self.db.check_stale_readers()
+	}
+
 	/// Create a new instance of the chain store based on this instance
 	/// but with the provided protocol version. This is used when migrating
 	/// data in the db to a different protocol version, reading using one version and
@@ -105,6 +128,13 @@ impl ChainStore {
 		self.db.exists(&to_key(BLOCK_PREFIX, h))
 	}
 
+	/// Get the "spent index" of the block, the outputs spent by the block inputs.
+	pub fn get_spent_index(&self, bh: &Hash) -> Result<Vec<CommitPos>, Error> {
+		option_to_not_found(self.db.get_ser(&to_key(BLOCK_SPENT_PREFIX, bh)), || {
+			format!("spent index: {}", bh)
+		})
+	}
+
 	/// Get block_sums for the block hash.
 	pub fn get_block_sums(&self, h: &Hash) -> Result<BlockSums, Error> {
 		option_to_not_found(self.db.get_ser(&to_key(BLOCK_SUMS_PREFIX, h)), || {
@@ -140,6 +170,66 @@ impl ChainStore {
 		self.db.get_ser(&to_key(OUTPUT_POS_PREFIX, commit))
 	}
 
+	/// Node uptime history, ordered by the node start time.
+	pub fn uptime_history(&self) -> Result<Vec<UptimeRecord>, Error> {
+		let key = to_key(UPTIME_PREFIX, "");
+		Ok(self
+			.db
+			.iter::<UptimeRecord>(&key)?
+			.map(|(_, v)| v)
+			.collect::<Vec<_>>())
+	}
+
+	/// Metrics snapshots taken between from and to (unix timestamps, inclusive),
+	/// ordered by the snapshot time.
+	pub fn metrics_history(
+		&self,
+		from: Option<i64>,
+		to: Option<i64>,
+	) -> Result<Vec<MetricsSnapshot>, Error> {
+		let key = to_key(METRICS_SNAPSHOT_PREFIX, "");
+		Ok(self
+			.db
+			.iter::<MetricsSnapshot>(&key)?
+			.map(|(_, v)| v)
+			.filter(|s| from.map_or(true, |from| s.timestamp >= from))
+			.filter(|s| to.map_or(true, |to| s.timestamp <= to))
+			.collect::<Vec<_>>())
+	}
+
+	/// Persisted sync progress, None if the node is not syncing.
+	pub fn sync_progress(&self) -> Result<Option<SyncProgress>, Error> {
+		self.db.get_ser(&[SYNC_PROGRESS_PREFIX])
+	}
+
+	/// Rangeproofs skipped by the spot-check state validation, None if all rangeproofs
+	/// are verified.
+	pub fn deferred_rangeproofs(&self) -> Result<Option<DeferredRangeproofs>, Error> {
+		self.db.get_ser(&[DEFERRED_RANGEPROOFS_PREFIX])
+	}
+
+	/// Kernel fee index entry for the block at the height.
+	pub fn get_block_kernel_fees(&self, height: u64) -> Result<BlockKernelFees, Error> {
+		option_to_not_found(
+			self.db.get_ser(&u64_to_key(KERNEL_FEE_PREFIX, height)),
+			|| format!("kernel fees at height {}", height),
+		)
+	}
+
+	/// Kernel fee index state, not found if index was never built.
+	pub fn kernel_fee_index_head(&self) -> Result<KernelFeeIndexHead, Error> {
+		option_to_not_found(self.db.get_ser(&[KERNEL_FEE_HEAD_PREFIX]), || {
+			"KERNEL_FEE_HEAD".to_owned()
+		})
+	}
+
+	/// Consistent read-only view of the chain store, see `ChainSnapshot`.
+	pub fn snapshot(&self) -> Result<ChainSnapshot, Error> {
+		Ok(ChainSnapshot {
+			db: self.db.snapshot()?,
+		})
+	}
+
 	/// Builds a new batch to be used with this store.
 	pub fn batch(&self) -> Result<Batch<'_>, Error> {
 		Ok(Batch {
@@ -148,6 +238,63 @@ impl ChainStore {
 	}
 }
 
+/// Read-only view of the chain store at the moment it was taken. The block is applied
+/// with a single batch, so the snapshot sees either the state before the block or after
+/// it: head, headers, blocks and the output index are consistent.
+pub struct ChainSnapshot {
+	db: store::Snapshot,
+}
+
+impl ChainSnapshot {
+	/// The chain head of the snapshot.
+	pub fn head(&self) -> Result<Tip, Error> {
+		option_to_not_found(self.db.get_ser(&[HEAD_PREFIX]), || "HEAD".to_owned())
+	}
+
+	/// The header head of the snapshot (may differ from chain head).
+	pub fn header_head(&self) -> Result<Tip, Error> {
+		option_to_not_found(self.db.get_ser(&[HEADER_HEAD_PREFIX]), || {
+			"HEADER_HEAD".to_owned()
+		})
+	}
+
+	/// The chain "tail" of the snapshot.
+	pub fn tail(&self) -> Result<Tip, Error> {
+		option_to_not_found(self.db.get_ser(&[TAIL_PREFIX]), || "TAIL".to_owned())
+	}
+
+	/// Get full block.
+	pub fn get_block(&self, h: &Hash) -> Result<Block, Error> {
+		option_to_not_found(self.db.get_ser(&to_key(BLOCK_PREFIX, h)), || {
+			format!("BLOCK: {}", h)
+		})
+	}
+
+	/// Does this full block exist?
+	pub fn block_exists(&self, h: &Hash) -> Result<bool, Error> {
+		self.db.exists(&to_key(BLOCK_PREFIX, h))
+	}
+
+	/// Get block header.
+	pub fn get_block_header(&self, h: &Hash) -> Result<BlockHeader, Error> {
+		option_to_not_found(self.db.get_ser(&to_key(BLOCK_HEADER_PREFIX, h)), || {
+			format!("BLOCK HEADER: {}", h)
+		})
+	}
+
+	/// Get block_sums for the block hash.
+	pub fn get_block_sums(&self, h: &Hash) -> Result<BlockSums, Error> {
+		option_to_not_found(self.db.get_ser(&to_key(BLOCK_SUMS_PREFIX, h)), || {
+			format!("Block sums for block: {}", h)
+		})
+	}
+
+	/// Get output_pos and block height from index.
+	pub fn get_output_pos_height(&self, commit: &Commitment) -> Result<Option<CommitPos>, Error> {
+		self.db.get_ser(&to_key(OUTPUT_POS_PREFIX, commit))
+	}
+}
+
 /// An atomic batch in which all changes can be committed all at once or
 /// discarded on error.
 pub struct Batch<'a> {
@@ -317,6 +464,49 @@ impl<'a> Batch<'a> {
 		self.db
 			.put_ser(&to_key(BLOCK_HEADER_PREFIX, hash)[..], header)?;
 
+		// And by height, the compaction finds the fork headers with it.
+		self.db
+			.put_ser(&header_height_key(header.height, &hash)[..], &hash)?;
+
+		Ok(())
+	}
+
+	/// Does the block header exist?
+	pub fn block_header_exists(&self, h: &Hash) -> Result<bool, Error> {
+		self.db.exists(&to_key(BLOCK_HEADER_PREFIX, h))
+	}
+
+	/// Headers by height index, from the lowest height. Every saved header is in the
+	/// index until the compaction checks it.
+	pub fn header_heights_iter(&self) -> Result<impl Iterator<Item = (u64, Hash)>, Error> {
+		let key = to_key(HEADER_HEIGHT_PREFIX, "");
+		Ok(self.db.iter::<Hash>(&key)?.filter_map(|(k, hash)| {
+			k.get(2..10)
+				.and_then(|h| h.try_into().ok())
+				.map(|h| (u64::from_be_bytes(h), hash))
+		}))
+	}
+
+	/// Remove the header from the headers by height index.
+	pub fn delete_header_height(&self, height: u64, h: &Hash) -> Result<(), Error> {
+		self.db.delete(&header_height_key(height, h)[..])
+	}
+
+	/// Index the headers that were saved before the headers by height index existed.
+	/// Done once, the later headers are indexed when they are saved.
+	pub fn init_header_heights(&self) -> Result<(), Error> {
+		if self.db.exists(&[HEADER_HEIGHT_INIT_PREFIX])? {
+			return Ok(());
+		}
+		let mut count: u64 = 0;
+		for (_, header) in self.headers_iter()? {
+			let hash = header.hash();
+			self.db
+				.put_ser(&header_height_key(header.height, &hash)[..], &hash)?;
+			count += 1;
+		}
+		self.db.put_ser(&[HEADER_HEIGHT_INIT_PREFIX], &count)?;
+		debug!("init_header_heights: indexed {} headers", count);
 		Ok(())
 	}
 
@@ -464,11 +654,86 @@ impl<'a> Batch<'a> {
 		})
 	}
 
+	/// Save the node uptime record. Records are indexed by the node start time and
+	/// the sequence of the run within that second.
+	pub fn save_uptime_record(&self, record: &UptimeRecord) -> Result<(), Error> {
+		self.db
+			.put_ser(&uptime_key(record.start_time, record.seq)[..], record)
+	}
+
+	/// Delete the node uptime record.
+	pub fn delete_uptime_record(&self, start_time: i64, seq: u32) -> Result<(), Error> {
+		self.db.delete(&uptime_key(start_time, seq)[..])
+	}
+
+	/// Save the metrics snapshot. Snapshots are indexed by the snapshot time.
+	pub fn save_metrics_snapshot(&self, snapshot: &MetricsSnapshot) -> Result<(), Error> {
+		self.db.put_ser(
+			&u64_to_key(METRICS_SNAPSHOT_PREFIX, snapshot.timestamp as u64)[..],
+			snapshot,
+		)
+	}
+
+	/// Delete the metrics snapshot.
+	pub fn delete_metrics_snapshot(&self, timestamp: i64) -> Result<(), Error> {
+		self.db
+			.delete(&u64_to_key(METRICS_SNAPSHOT_PREFIX, timestamp as u64)[..])
+	}
+
+	/// Save the sync progress.
+	pub fn save_sync_progress(&self, progress: &SyncProgress) -> Result<(), Error> {
+		self.db.put_ser(&[SYNC_PROGRESS_PREFIX], progress)
+	}
+
+	/// Delete the sync progress once the sync is done.
+	pub fn delete_sync_progress(&self) -> Result<(), Error> {
+		self.db.delete(&[SYNC_PROGRESS_PREFIX])
+	}
+
+	/// Save the progress of the background rangeproofs verification.
+	pub fn save_deferred_rangeproofs(&self, deferred: &DeferredRangeproofs) -> Result<(), Error> {
+		self.db.put_ser(&[DEFERRED_RANGEPROOFS_PREFIX], deferred)
+	}
+
+	/// Delete the deferred rangeproofs record once all of them are verified.
+	pub fn delete_deferred_rangeproofs(&self) -> Result<(), Error> {
+		self.db.delete(&[DEFERRED_RANGEPROOFS_PREFIX])
+	}
+
+	/// Save the kernel fee index entry for the block.
+	pub fn save_block_kernel_fees(&self, fees: &BlockKernelFees) -> Result<(), Error> {
+		self.db
+			.put_ser(&u64_to_key(KERNEL_FEE_PREFIX, fees.height)[..], fees)
+	}
+
+	/// Delete the kernel fee index entry at the height.
+	pub fn delete_block_kernel_fees(&self, height: u64) -> Result<(), Error> {
+		self.db.delete(&u64_to_key(KERNEL_FEE_PREFIX, height)[..])
+	}
+
+	/// Save the kernel fee index state.
+	pub fn save_kernel_fee_index_head(&self, head: &KernelFeeIndexHead) -> Result<(), Error> {
+		self.db.put_ser(&[KERNEL_FEE_HEAD_PREFIX], head)
+	}
+
 	/// An iterator to all block in db
 	pub fn blocks_iter(&self) -> Result<SerIterator<Block>, Error> {
 		let key = to_key(BLOCK_PREFIX, "");
 		self.db.iter(&key)
 	}
+
+	/// An iterator to all block headers in db
+	pub fn headers_iter(&self) -> Result<SerIterator<BlockHeader>, Error> {
+		let key = to_key(BLOCK_HEADER_PREFIX, "");
+		self.db.iter(&key)
+	}
+}
+
+// Headers by height index key, the height is big endian so the headers are ordered by height
+fn header_height_key(height: u64, h: &Hash) -> Vec<u8> {
+	let mut key = u64_to_key(HEADER_HEIGHT_PREFIX, height);
+	key.extend_from_slice(h.as_bytes());
+	key
 }
 
 /// An iterator on blocks, from latest to earliest, specialized to return
@@ -563,6 +828,16 @@ impl<'a> Iterator for DifficultyIter<'a> {
 	}
 }
 
+// The first run of the second keeps the plain start time key, the records saved before
+// the sequence was added are found by it. Longer keys of the same second sort after it.
+fn uptime_key(start_time: i64, seq: u32) -> Vec<u8> {
+	if seq == 0 {
+		u64_to_key(UPTIME_PREFIX, start_time as u64)
+	} else {
+		to_key_u64(UPTIME_PREFIX, (start_time as u64).to_be_bytes(), seq as u64)
+	}
+}
+
 /// Init the NRD "recent history" kernel index backed by the underlying db.
 /// List index supports multiple entries per key, maintaining insertion order.
 /// Allows for fast lookup of the most recent entry per excess commitment.
@@ -25,6 +25,7 @@ use crate::core::global;
 use crate::core::ser::{PMMRable, ProtocolVersion};
 use crate::error::{Error, ErrorKind};
 use crate::linked_list::{ListIndex, PruneableListIndex, RewindableListIndex};
+use crate::rproof_spot_check;
 use crate::store::{self, Batch, ChainStore};
 use crate::txhashset::bitmap_accumulator::BitmapAccumulator;
 use crate::txhashset::{RewindableKernelView, UTXOView};
@@ -34,6 +35,7 @@ use crate::util::{file, secp_static, zip};
 use croaring::Bitmap;
 use grin_store;
 use grin_store::pmmr::{clean_files_by_prefix, PMMRBackend};
+use std::cmp;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -301,6 +303,15 @@ impl TxHashSet {
 			.get_last_n_insertions(distance)
 	}
 
+	/// Kernels in the kernel MMR positions range, both ends are included
+	pub fn kernels_by_pmmr_range(&self, from_pos: u64, to_pos: u64) -> Vec<TxKernel> {
+		let pmmr = ReadonlyPMMR::at(&self.kernel_pmmr_h.backend, self.kernel_pmmr_h.last_pos);
+		(from_pos..=to_pos)
+			.filter(|pos| pmmr::is_leaf(*pos))
+			.filter_map(|pos| pmmr.get_data(pos))
+			.collect()
+	}
+
 	/// Convenience function to query the db for a header by its hash.
 	pub fn get_block_header(&self, hash: &Hash) -> Result<BlockHeader, Error> {
 		Ok(self.commit_index.get_block_header(&hash)?)
@@ -335,6 +346,44 @@ impl TxHashSet {
 			.elements_from_pmmr_index(start_index, max_count, max_index)
 	}
 
+	/// Verify the rangeproofs of the outputs at the output PMMR positions from `from_pos`
+	/// up to `last_pos`, not more than `max_count` of them. Outputs spent after the state
+	/// sync are verified as well, their data stays on disk because the txhashset is not
+	/// compacted until the deferred rangeproofs are verified. Returns the next position
+	/// to verify and the number of verified rangeproofs.
+	pub fn verify_rangeproofs_range(
+		&self,
+		from_pos: u64,
+		last_pos: u64,
+		max_count: usize,
+	) -> Result<(u64, usize), Error> {
+		let output_backend = &self.output_pmmr_h.backend;
+		let rproof_backend = &self.rproof_pmmr_h.backend;
+
+		let mut commits: Vec<Commitment> = Vec::with_capacity(max_count);
+		let mut proofs: Vec<RangeProof> = Vec::with_capacity(max_count);
+
+		let mut pos = cmp::max(from_pos, 1);
+		while pos <= last_pos && commits.len() < max_count {
+			// Outputs compacted before the state sync have no data
+			if pmmr::is_leaf(pos) {
+				if let (Some(output), Some(proof)) = (
+					output_backend.get_data_from_file(pos),
+					rproof_backend.get_data_from_file(pos),
+				) {
+					commits.push(output.commit);
+					proofs.push(proof);
+				}
+			}
+			pos += 1;
+		}
+
+		if !commits.is_empty() {
+			Output::batch_verify_proofs(&commits, &proofs)?;
+		}
+		Ok((pos, commits.len()))
+	}
+
 	/// Find a kernel with a given excess. Work backwards from `max_index` to `min_index`
 	pub fn find_kernel(
 		&self,
@@ -377,6 +426,26 @@ impl TxHashSet {
 		}
 	}
 
+	/// Validate the MMR sizes and roots as they are on disk against the block header,
+	/// nothing is rewound.
+	pub fn validate_head(&self, header: &BlockHeader) -> Result<(), Error> {
+		if header.height == 0 {
+			return Ok(());
+		}
+		if (
+			header.output_mmr_size,
+			header.output_mmr_size,
+			header.kernel_mmr_size,
+		) != (
+			self.output_pmmr_h.last_pos,
+			self.rproof_pmmr_h.last_pos,
+			self.kernel_pmmr_h.last_pos,
+		) {
+			return Err(ErrorKind::InvalidMMRSize.into());
+		}
+		self.roots().validate(header)
+	}
+
 	/// Return Commit's MMR position
 	pub fn get_output_pos(&self, commit: &Commitment) -> Result<u64, Error> {
 		Ok(self.commit_index.get_output_pos(&commit)?)
@@ -1529,10 +1598,13 @@ impl<'a> Extension<'a> {
 
 	/// Validate the txhashset state against the provided block header.
 	/// A "fast validation" will skip rangeproof verification and kernel signature verification.
+	/// With `rproof_sample_percent` below 100 only a random sample of the rangeproofs is
+	/// verified (spot-check mode), the caller is responsible for verifying the rest later.
 	pub fn validate(
 		&self,
 		genesis: &BlockHeader,
 		fast_validation: bool,
+		rproof_sample_percent: usize,
 		status: &dyn TxHashsetWriteStatus,
 		header: &BlockHeader,
 	) -> Result<(Commitment, Commitment), Error> {
@@ -1552,7 +1624,7 @@ impl<'a> Extension<'a> {
 		// These are expensive verification step (skipped for "fast validation").
 		if !fast_validation {
 			// Verify the rangeproof associated with each unspent output.
-			self.verify_rangeproofs(status)?;
+			self.verify_rangeproofs(status, rproof_sample_percent)?;
 
 			// Verify all the kernel signatures.
 			self.verify_kernel_signatures(status)?;
@@ -1636,16 +1708,26 @@ impl<'a> Extension<'a> {
 		Ok(())
 	}
 
-	fn verify_rangeproofs(&self, status: &dyn TxHashsetWriteStatus) -> Result<(), Error> {
+	fn verify_rangeproofs(
+		&self,
+		status: &dyn TxHashsetWriteStatus,
+		sample_percent: usize,
+	) -> Result<(), Error> {
 		let now = Instant::now();
 
 		let mut commits: Vec<Commitment> = Vec::with_capacity(1_000);
 		let mut proofs: Vec<RangeProof> = Vec::with_capacity(1_000);
 
 		let mut proof_count = 0;
+		let mut skipped_count = 0;
 		let total_rproofs = self.output_pmmr.n_unpruned_leaves();
 
 		for pos in self.output_pmmr.leaf_pos_iter() {
+			if !rproof_spot_check::is_sampled(sample_percent) {
+				skipped_count += 1;
+				continue;
+			}
+
 			let output = self.output_pmmr.get_data(pos);
 			let proof = self.rproof_pmmr.get_data(pos);
 
@@ -1683,7 +1765,7 @@ impl<'a> Extension<'a> {
 					proof_count,
 				);
 				if proof_count % 1_000 == 0 {
-					status.on_validation_rproofs(proof_count, total_rproofs);
+					status.on_validation_rproofs(proof_count + skipped_count, total_rproofs);
 				}
 			}
 		}
@@ -1705,6 +1787,12 @@ impl<'a> Extension<'a> {
 			self.rproof_pmmr.unpruned_size(),
 			now.elapsed().as_secs(),
 		);
+		if skipped_count > 0 {
+			warn!(
+				"txhashset: spot-check mode, {} of {} rangeproofs are left for the background verification",
+				skipped_count, total_rproofs,
+			);
+		}
 		Ok(())
 	}
 }
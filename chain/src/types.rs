@@ -15,12 +15,14 @@
 //! Base types that the block chain pipeline requires.
 
 use chrono::prelude::{DateTime, Utc};
+use std::cmp;
 
 use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
-use crate::core::core::{Block, BlockHeader, HeaderVersion};
+use crate::core::core::{Block, BlockHeader, HeaderVersion, KernelFeatures};
 use crate::core::pow::Difficulty;
 use crate::core::ser::{self, PMMRIndexHashable, Readable, Reader, Writeable, Writer};
-use crate::error::{Error, ErrorKind};
+use crate::error::{Error, ErrorClass, ErrorKind};
+use crate::util::secp::pedersen::Commitment;
 use crate::util::{RwLock, RwLockWriteGuard};
 
 bitflags! {
@@ -117,10 +119,66 @@ impl Default for TxHashsetDownloadStats {
 	}
 }
 
+/// Last chain error with the recovery hint, reported in the node status
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainErrorInfo {
+	/// Class of the error
+	pub class: ErrorClass,
+	/// Error message
+	pub error: String,
+	/// What the node does about it and what the operator can do
+	pub hint: String,
+	/// When the error happened
+	pub time: DateTime<Utc>,
+}
+
+impl ChainErrorInfo {
+	/// Error info for the chain error
+	pub fn from_error(error: &Error) -> ChainErrorInfo {
+		let class = error.class();
+		ChainErrorInfo {
+			class,
+			error: error.kind().to_string(),
+			hint: class.recovery_hint().to_string(),
+			time: Utc::now(),
+		}
+	}
+}
+
+/// Peer considered as the initial state sync source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncSourceCandidate {
+	/// Peer address
+	pub addr: String,
+	/// Peer advertises the archive capability
+	pub archive: bool,
+	/// Measured uptime of the peer connections, in seconds
+	pub uptime_secs: u64,
+	/// Number of the corrupt txhashsets the peer served before
+	pub bad_txhashsets: u32,
+	/// Selection score, higher is better
+	pub score: u64,
+	/// Why the peer is not considered, None if it is
+	pub excluded: Option<String>,
+}
+
+/// Decision trail of the initial state sync source selection, for debugging
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncSourceDecision {
+	/// Selected peer, None if there was no acceptable candidate
+	pub selected: Option<String>,
+	/// Candidates in the order of preference, the excluded ones are last
+	pub candidates: Vec<SyncSourceCandidate>,
+	/// When the decision was made
+	pub time: DateTime<Utc>,
+}
+
 /// Current sync state. Encapsulates the current SyncStatus.
 pub struct SyncState {
 	current: RwLock<SyncStatus>,
 	sync_error: RwLock<Option<Error>>,
+	last_chain_error: RwLock<Option<ChainErrorInfo>>,
+	sync_source: RwLock<Option<SyncSourceDecision>>,
 }
 
 impl SyncState {
@@ -129,6 +187,8 @@ impl SyncState {
 		SyncState {
 			current: RwLock::new(SyncStatus::Initial),
 			sync_error: RwLock::new(None),
+			last_chain_error: RwLock::new(None),
+			sync_source: RwLock::new(None),
 		}
 	}
 
@@ -195,6 +255,26 @@ impl SyncState {
 	pub fn clear_sync_error(&self) {
 		*self.sync_error.write() = None;
 	}
+
+	/// Record the chain error that needs the operator attention
+	pub fn set_chain_error(&self, error: &Error) {
+		*self.last_chain_error.write() = Some(ChainErrorInfo::from_error(error));
+	}
+
+	/// Last recorded chain error
+	pub fn last_chain_error(&self) -> Option<ChainErrorInfo> {
+		self.last_chain_error.read().clone()
+	}
+
+	/// Record how the state sync source was selected
+	pub fn set_sync_source_decision(&self, decision: SyncSourceDecision) {
+		*self.sync_source.write() = Some(decision);
+	}
+
+	/// Last state sync source selection
+	pub fn sync_source_decision(&self) -> Option<SyncSourceDecision> {
+		self.sync_source.read().clone()
+	}
 }
 
 impl TxHashsetWriteStatus for SyncState {
@@ -432,6 +512,23 @@ pub trait ChainAdapter {
 	/// The blockchain pipeline has accepted this block as valid and added
 	/// it to our chain.
 	fn block_accepted(&self, block: &Block, status: BlockStatus, opts: Options);
+
+	/// The blockchain pipeline has rejected the reorg deeper than the max reorg depth.
+	/// Rejections are reported not more often than every 10 minutes.
+	fn deep_reorg_rejected(&self, _alert: &DeepReorgAlert) {}
+}
+
+/// Reorg rejected by the anti deep reorg rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeepReorgAlert {
+	/// Max reorg depth allowed
+	pub max_depth: u64,
+	/// Tip of the fork we refused to switch to, hex
+	pub fork_tip: String,
+	/// Height of the fork tip
+	pub fork_tip_height: u64,
+	/// Height of our head
+	pub head_height: u64,
 }
 
 /// Inform the caller of the current status of a txhashset write operation,
@@ -516,3 +613,426 @@ impl BlockStatus {
 		}
 	}
 }
+
+/// Time spent by the node in a single sync stage during a node run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncStageRecord {
+	/// Sync stage name: header_sync, txhashset or body_sync
+	pub stage: String,
+	/// Unix timestamp when the node entered this stage first time
+	pub start_time: i64,
+	/// Total time in seconds the node spent in this stage
+	pub duration: i64,
+}
+
+impl Readable for SyncStageRecord {
+	fn read<R: Reader>(reader: &mut R) -> Result<SyncStageRecord, ser::Error> {
+		let stage = String::from_utf8(reader.read_bytes_len_prefix()?).map_err(|e| {
+			ser::Error::CorruptedData(format!("Fail to read sync stage name, {}", e))
+		})?;
+		let start_time = reader.read_i64()?;
+		let duration = reader.read_i64()?;
+		Ok(SyncStageRecord {
+			stage,
+			start_time,
+			duration,
+		})
+	}
+}
+
+impl Writeable for SyncStageRecord {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.stage)?;
+		writer.write_i64(self.start_time)?;
+		writer.write_i64(self.duration)?;
+		Ok(())
+	}
+}
+
+/// Single run of the node, from the start to the stop, with the sync stages
+/// that node passed. Used for availability (SLA) reporting.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UptimeRecord {
+	/// Unix timestamp when the node was started
+	pub start_time: i64,
+	/// Unix timestamp when the node was stopped. 0 if the node is still running
+	/// or it was terminated without clean shutdown.
+	pub stop_time: i64,
+	/// Unix timestamp when the node finished the sync. 0 if sync is not done yet
+	pub sync_done_time: i64,
+	/// Sync stages that node passed during this run
+	pub sync_stages: Vec<SyncStageRecord>,
+	/// Number of the earlier runs started at the same second, keeps their records apart
+	#[serde(default)]
+	pub seq: u32,
+}
+
+impl UptimeRecord {
+	/// New record for the node that was just started
+	pub fn new(start_time: i64) -> UptimeRecord {
+		UptimeRecord {
+			start_time,
+			stop_time: 0,
+			sync_done_time: 0,
+			sync_stages: vec![],
+			seq: 0,
+		}
+	}
+}
+
+impl Readable for UptimeRecord {
+	fn read<R: Reader>(reader: &mut R) -> Result<UptimeRecord, ser::Error> {
+		let start_time = reader.read_i64()?;
+		let stop_time = reader.read_i64()?;
+		let sync_done_time = reader.read_i64()?;
+		let stages_num = reader.read_u32()?;
+		let mut sync_stages = Vec::with_capacity(stages_num as usize);
+		for _ in 0..stages_num {
+			sync_stages.push(SyncStageRecord::read(reader)?);
+		}
+		// Records written before the sequence was added end here
+		let seq = reader.read_u32().unwrap_or(0);
+		Ok(UptimeRecord {
+			start_time,
+			stop_time,
+			sync_done_time,
+			sync_stages,
+			seq,
+		})
+	}
+}
+
+impl Writeable for UptimeRecord {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_i64(self.start_time)?;
+		writer.write_i64(self.stop_time)?;
+		writer.write_i64(self.sync_done_time)?;
+		writer.write_u32(self.sync_stages.len() as u32)?;
+		for stage in &self.sync_stages {
+			stage.write(writer)?;
+		}
+		writer.write_u32(self.seq)?;
+		Ok(())
+	}
+}
+
+/// Snapshot of the key node metrics. Snapshots are persisted periodically, so the
+/// incidents can be investigated on the nodes without external monitoring.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct MetricsSnapshot {
+	/// Unix timestamp of the snapshot
+	pub timestamp: i64,
+	/// Height of the chain tip
+	pub height: u64,
+	/// Height of the header chain tip
+	pub header_height: u64,
+	/// Number of the connected peers
+	pub peers: u32,
+	/// Number of the inbound peers
+	pub inbound_peers: u32,
+	/// Number of the outbound peers
+	pub outbound_peers: u32,
+	/// Number of the transactions in the tx pool
+	pub txpool_size: u32,
+	/// Number of the transactions in the stem pool
+	pub stempool_size: u32,
+	/// Number of the blocks validated since the previous snapshot
+	pub blocks_validated: u32,
+	/// Average block validation time since the previous snapshot, in microseconds
+	pub avg_block_validation_us: u64,
+	/// Max block validation time since the previous snapshot, in microseconds
+	pub max_block_validation_us: u64,
+}
+
+impl Readable for MetricsSnapshot {
+	fn read<R: Reader>(reader: &mut R) -> Result<MetricsSnapshot, ser::Error> {
+		Ok(MetricsSnapshot {
+			timestamp: reader.read_i64()?,
+			height: reader.read_u64()?,
+			header_height: reader.read_u64()?,
+			peers: reader.read_u32()?,
+			inbound_peers: reader.read_u32()?,
+			outbound_peers: reader.read_u32()?,
+			txpool_size: reader.read_u32()?,
+			stempool_size: reader.read_u32()?,
+			blocks_validated: reader.read_u32()?,
+			avg_block_validation_us: reader.read_u64()?,
+			max_block_validation_us: reader.read_u64()?,
+		})
+	}
+}
+
+impl Writeable for MetricsSnapshot {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_i64(self.timestamp)?;
+		writer.write_u64(self.height)?;
+		writer.write_u64(self.header_height)?;
+		writer.write_u32(self.peers)?;
+		writer.write_u32(self.inbound_peers)?;
+		writer.write_u32(self.outbound_peers)?;
+		writer.write_u32(self.txpool_size)?;
+		writer.write_u32(self.stempool_size)?;
+		writer.write_u32(self.blocks_validated)?;
+		writer.write_u64(self.avg_block_validation_us)?;
+		writer.write_u64(self.max_block_validation_us)
+	}
+}
+
+/// Sync cursors that are persisted while the node is syncing, so after the restart the
+/// sync continues from where it was stopped instead of starting every stage from scratch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SyncProgress {
+	/// Unix timestamp of the last update
+	pub update_time: i64,
+	/// Locator of the last headers request, the first hash is the sync head
+	pub header_locator: Vec<Hash>,
+	/// Height and hash of the txhashset archive that was requested. None if state sync
+	/// was not started.
+	pub txhashset_request: Option<(u64, Hash)>,
+	/// Downloaded size of the txhashset archive
+	pub txhashset_downloaded_size: u64,
+	/// Total size of the txhashset archive
+	pub txhashset_total_size: u64,
+	/// Blocks requested by the last body sync round
+	pub body_sync_window: Vec<Hash>,
+}
+
+fn read_hashes<R: Reader>(reader: &mut R) -> Result<Vec<Hash>, ser::Error> {
+	let len = reader.read_u32()?;
+	let mut hashes = Vec::with_capacity(cmp::min(len, 1000) as usize);
+	for _ in 0..len {
+		hashes.push(Hash::read(reader)?);
+	}
+	Ok(hashes)
+}
+
+fn write_hashes<W: Writer>(writer: &mut W, hashes: &[Hash]) -> Result<(), ser::Error> {
+	writer.write_u32(hashes.len() as u32)?;
+	for h in hashes {
+		h.write(writer)?;
+	}
+	Ok(())
+}
+
+impl Readable for SyncProgress {
+	fn read<R: Reader>(reader: &mut R) -> Result<SyncProgress, ser::Error> {
+		let update_time = reader.read_i64()?;
+		let header_locator = read_hashes(reader)?;
+		let txhashset_request = match reader.read_u8()? {
+			0 => None,
+			_ => Some((reader.read_u64()?, Hash::read(reader)?)),
+		};
+		let txhashset_downloaded_size = reader.read_u64()?;
+		let txhashset_total_size = reader.read_u64()?;
+		let body_sync_window = read_hashes(reader)?;
+		Ok(SyncProgress {
+			update_time,
+			header_locator,
+			txhashset_request,
+			txhashset_downloaded_size,
+			txhashset_total_size,
+			body_sync_window,
+		})
+	}
+}
+
+impl Writeable for SyncProgress {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_i64(self.update_time)?;
+		write_hashes(writer, &self.header_locator)?;
+		match &self.txhashset_request {
+			None => writer.write_u8(0)?,
+			Some((height, hash)) => {
+				writer.write_u8(1)?;
+				writer.write_u64(*height)?;
+				hash.write(writer)?;
+			}
+		}
+		writer.write_u64(self.txhashset_downloaded_size)?;
+		writer.write_u64(self.txhashset_total_size)?;
+		write_hashes(writer, &self.body_sync_window)?;
+		Ok(())
+	}
+}
+
+/// Fee paid by a single kernel. Coinbase kernels are not included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KernelFee {
+	/// Kernel excess commitment
+	pub excess: Commitment,
+	/// Kernel fee
+	pub fee: u64,
+}
+
+impl Readable for KernelFee {
+	fn read<R: Reader>(reader: &mut R) -> Result<KernelFee, ser::Error> {
+		let excess = Commitment::read(reader)?;
+		let fee = reader.read_u64()?;
+		Ok(KernelFee { excess, fee })
+	}
+}
+
+impl Writeable for KernelFee {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.excess.write(writer)?;
+		writer.write_u64(self.fee)?;
+		Ok(())
+	}
+}
+
+/// Kernel fee index entry for a single block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockKernelFees {
+	/// Block hash, needed to detect entries that belong to the orphaned blocks
+	pub hash: Hash,
+	/// Block height
+	pub height: u64,
+	/// Fees of the non coinbase kernels of the block
+	pub kernels: Vec<KernelFee>,
+}
+
+impl BlockKernelFees {
+	/// Build the index entry from the block
+	pub fn from_block(block: &Block) -> BlockKernelFees {
+		let kernels = block
+			.kernels()
+			.iter()
+			.filter_map(|k| {
+				let fee = match k.features {
+					KernelFeatures::Coinbase => return None,
+					KernelFeatures::Plain { fee } => fee,
+					KernelFeatures::HeightLocked { fee, .. } => fee,
+					KernelFeatures::NoRecentDuplicate { fee, .. } => fee,
+				};
+				Some(KernelFee {
+					excess: k.excess,
+					fee,
+				})
+			})
+			.collect();
+		BlockKernelFees {
+			hash: block.hash(),
+			height: block.header.height,
+			kernels,
+		}
+	}
+}
+
+impl Readable for BlockKernelFees {
+	fn read<R: Reader>(reader: &mut R) -> Result<BlockKernelFees, ser::Error> {
+		let hash = Hash::read(reader)?;
+		let height = reader.read_u64()?;
+		let kernels_num = reader.read_u32()?;
+		let mut kernels = Vec::with_capacity(kernels_num as usize);
+		for _ in 0..kernels_num {
+			kernels.push(KernelFee::read(reader)?);
+		}
+		Ok(BlockKernelFees {
+			hash,
+			height,
+			kernels,
+		})
+	}
+}
+
+impl Writeable for BlockKernelFees {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.hash.write(writer)?;
+		writer.write_u64(self.height)?;
+		writer.write_u32(self.kernels.len() as u32)?;
+		for k in &self.kernels {
+			k.write(writer)?;
+		}
+		Ok(())
+	}
+}
+
+/// Kernel fee index state. Index covers the blocks from `start_height` to `height`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KernelFeeIndexHead {
+	/// First indexed height. Non archive nodes don't have the old blocks.
+	pub start_height: u64,
+	/// Last indexed height
+	pub height: u64,
+	/// Hash of the last indexed block
+	pub hash: Hash,
+}
+
+impl Readable for KernelFeeIndexHead {
+	fn read<R: Reader>(reader: &mut R) -> Result<KernelFeeIndexHead, ser::Error> {
+		let start_height = reader.read_u64()?;
+		let height = reader.read_u64()?;
+		let hash = Hash::read(reader)?;
+		Ok(KernelFeeIndexHead {
+			start_height,
+			height,
+			hash,
+		})
+	}
+}
+
+impl Writeable for KernelFeeIndexHead {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u64(self.start_height)?;
+		writer.write_u64(self.height)?;
+		self.hash.write(writer)?;
+		Ok(())
+	}
+}
+
+/// Rangeproofs that were skipped by the spot-check state validation and are waiting
+/// for the background verification. Output PMMR positions from `next_pos` to `last_pos`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DeferredRangeproofs {
+	/// Next output PMMR position to verify
+	pub next_pos: u64,
+	/// Last output PMMR position of the validated state
+	pub last_pos: u64,
+	/// Invalid rangeproof was found at `next_pos`, the chain state can't be trusted
+	pub failed: bool,
+}
+
+impl Readable for DeferredRangeproofs {
+	fn read<R: Reader>(reader: &mut R) -> Result<DeferredRangeproofs, ser::Error> {
+		let next_pos = reader.read_u64()?;
+		let last_pos = reader.read_u64()?;
+		let failed = reader.read_u8()? != 0;
+		Ok(DeferredRangeproofs {
+			next_pos,
+			last_pos,
+			failed,
+		})
+	}
+}
+
+impl Writeable for DeferredRangeproofs {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u64(self.next_pos)?;
+		writer.write_u64(self.last_pos)?;
+		writer.write_u8(self.failed as u8)?;
+		Ok(())
+	}
+}
+
+/// Rangeproofs validation of the chain state, reported while it is reduced
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RangeproofValidation {
+	/// Some rangeproofs of the chain state are not verified (yet)
+	pub reduced_validation: bool,
+	/// Share of the rangeproofs verified during the state sync, in percents
+	pub sample_percent: usize,
+	/// Rangeproofs waiting for the background verification
+	pub deferred_rangeproofs: Option<DeferredRangeproofs>,
+	/// Background verification found an invalid rangeproof
+	pub verification_failed: bool,
+}
+
+/// Result of the chain compaction: data that belongs to the abandoned forks
+/// older than the cut through horizon and was removed from the db.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct CompactionStats {
+	/// Number of the removed fork blocks
+	pub fork_blocks: u64,
+	/// Number of the removed fork headers
+	pub fork_headers: u64,
+}
@@ -0,0 +1,141 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Canonical digest of the UTXO set. Two nodes with the same digest have the same unspent
+//! outputs, whatever the history that led them there (full sync, state sync, reorgs).
+//!
+//! The digest is a multiset hash: the sum modulo 2^256 of the hashes of the unspent output
+//! commitments, hashed together with the number of the outputs. The order doesn't matter,
+//! so the block adds its outputs and subtracts its inputs, and the reorg undoes the blocks
+//! that left the chain the opposite way. The full UTXO set is scanned only once, when the
+//! digest is enabled, or if the blocks to undo are not available anymore.
+
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::{Block, Inputs};
+use crate::util::secp::pedersen::Commitment;
+use crate::util::ToHex;
+
+/// Max number of the blocks applied and undone to catch up with the head. The digest is
+/// recomputed from the UTXO set if the head moved further.
+pub const MAX_UTXO_DIGEST_CATCH_UP: u64 = 1000;
+
+/// Digest of the UTXO set at the block
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UtxoSetDigest {
+	/// Height of the block
+	pub height: u64,
+	/// Hash of the block, hex
+	pub block_hash: String,
+	/// Digest of the UTXO set after this block, hex
+	pub digest: String,
+	/// Number of the unspent outputs
+	pub utxo_count: u64,
+}
+
+/// Order independent accumulator of the unspent output commitments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UtxoAccumulator {
+	// Sum of the commitment hashes modulo 2^256, big endian
+	sum: [u8; 32],
+	count: u64,
+}
+
+impl UtxoAccumulator {
+	/// Accumulator of the empty set
+	pub fn new() -> UtxoAccumulator {
+		UtxoAccumulator {
+			sum: [0u8; 32],
+			count: 0,
+		}
+	}
+
+	/// Add the unspent output
+	pub fn add(&mut self, commit: &Commitment) {
+		let h = commit_hash(commit);
+		let mut carry = 0u16;
+		for i in (0..32).rev() {
+			let v = self.sum[i] as u16 + h[i] as u16 + carry;
+			self.sum[i] = v as u8;
+			carry = v >> 8;
+		}
+		self.count += 1;
+	}
+
+	/// Remove the spent output
+	pub fn remove(&mut self, commit: &Commitment) {
+		let h = commit_hash(commit);
+		let mut borrow = 0i16;
+		for i in (0..32).rev() {
+			let mut v = self.sum[i] as i16 - h[i] as i16 - borrow;
+			borrow = 0;
+			if v < 0 {
+				v += 256;
+				borrow = 1;
+			}
+			self.sum[i] = v as u8;
+		}
+		self.count = self.count.saturating_sub(1);
+	}
+
+	/// Apply the block, its inputs are spent and its outputs are added
+	pub fn apply_block(&mut self, block: &Block) {
+		for commit in input_commits(block) {
+			self.remove(&commit);
+		}
+		for output in block.outputs() {
+			self.add(&output.commitment());
+		}
+	}
+
+	/// Undo the block that left the chain, its inputs are unspent again
+	pub fn undo_block(&mut self, block: &Block) {
+		for output in block.outputs() {
+			self.remove(&output.commitment());
+		}
+		for commit in input_commits(block) {
+			self.add(&commit);
+		}
+	}
+
+	/// Number of the unspent outputs
+	pub fn count(&self) -> u64 {
+		self.count
+	}
+
+	/// Digest of the set
+	pub fn digest(&self) -> Hash {
+		(Hash::from_vec(&self.sum), self.count).hash()
+	}
+
+	/// Digest of the set at the block
+	pub fn to_digest(&self, height: u64, block_hash: &Hash) -> UtxoSetDigest {
+		UtxoSetDigest {
+			height,
+			block_hash: block_hash.to_hex(),
+			digest: self.digest().to_hex(),
+			utxo_count: self.count,
+		}
+	}
+}
+
+fn commit_hash(commit: &Commitment) -> Hash {
+	commit.0.to_vec().hash()
+}
+
+fn input_commits(block: &Block) -> Vec<Commitment> {
+	match block.inputs() {
+		Inputs::CommitOnly(inputs) => inputs.iter().map(|i| i.commitment()).collect(),
+		Inputs::FeaturesAndCommit(inputs) => inputs.iter().map(|i| i.commitment()).collect(),
+	}
+}
@@ -0,0 +1,108 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use self::chain::{Chain, ErrorKind, Options};
+use self::core::core::hash::Hashed;
+use self::core::core::{Block, BlockHeader};
+use self::core::global::{self, ChainTypes};
+use self::core::libtx;
+use self::core::pow::{self, Difficulty};
+use self::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use chrono::{Duration, Utc};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+
+mod chain_test_helper;
+
+use self::chain_test_helper::{clean_output_dir, init_chain};
+
+// Use diff as both diff *and* key_idx for convenience (deterministic private key for test blocks)
+fn prepare_block<K>(kc: &K, prev: &BlockHeader, chain: &Chain, diff: u64) -> Block
+where
+	K: Keychain,
+{
+	let key_id = ExtKeychainPath::new(1, diff as u32, 0, 0, 0).to_identifier();
+	let reward = libtx::reward::output(
+		kc,
+		&libtx::ProofBuilder::new(kc),
+		&key_id,
+		0,
+		false,
+		prev.height + 1,
+	)
+	.unwrap();
+	let mut b = Block::new(prev, &[], Difficulty::from_num(diff), reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.total_difficulty = prev.total_difficulty() + Difficulty::from_num(diff);
+	b.header.pow.proof = pow::Proof::random(global::proofsize());
+	chain.set_txhashset_roots(&mut b).unwrap();
+	b
+}
+
+#[test]
+fn reject_deep_reorg() {
+	const NUM_BLOCKS_MAIN: u64 = 6;
+	const MAX_REORG_DEPTH: u64 = 2;
+
+	let chain_dir = ".mwc_deep_reorg";
+	clean_output_dir(chain_dir);
+	global::set_local_chain_type(ChainTypes::AutomatedTesting);
+	chain::pipe::init_max_reorg_depth(Some(MAX_REORG_DEPTH));
+
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	{
+		let chain = init_chain(chain_dir, genesis);
+
+		let mut prev = chain.head_header().unwrap();
+		for n in 1..=NUM_BLOCKS_MAIN {
+			let b = prepare_block(&kc, &prev, &chain, n);
+			prev = b.header.clone();
+			chain.process_block(b, Options::SKIP_POW).unwrap();
+		}
+		let head = chain.head().unwrap();
+		assert_eq!(head.height, NUM_BLOCKS_MAIN);
+
+		// Reorg within the limit is accepted
+		let fork_head = chain.get_header_by_height(NUM_BLOCKS_MAIN - 1).unwrap();
+		let b = prepare_block(&kc, &fork_head, &chain, 100);
+		let shallow_fork = b.header.clone();
+		chain.process_block(b, Options::SKIP_POW).unwrap();
+		assert_eq!(chain.head().unwrap().hash(), shallow_fork.hash());
+
+		// Deeper reorg is rejected, the head is not changed
+		let fork_head = chain.get_header_by_height(1).unwrap();
+		let b = prepare_block(&kc, &fork_head, &chain, 1000);
+		let deep_fork = b.header.clone();
+		match chain.process_block(b.clone(), Options::SKIP_POW) {
+			Err(e) => match e.kind() {
+				ErrorKind::DeepReorg { max_depth, .. } => assert_eq!(max_depth, MAX_REORG_DEPTH),
+				kind => panic!("unexpected error {:?}", kind),
+			},
+			Ok(_) => panic!("deep reorg is expected to be rejected"),
+		}
+		assert_eq!(chain.head().unwrap().hash(), shallow_fork.hash());
+
+		// Operator override allows the deep reorg
+		chain.allow_deep_reorgs(Some(Utc::now() + Duration::hours(1)));
+		assert!(chain.deep_reorgs_allowed_until().is_some());
+		chain.process_block(b, Options::SKIP_POW).unwrap();
+		assert_eq!(chain.head().unwrap().hash(), deep_fork.hash());
+
+		chain.allow_deep_reorgs(None);
+		assert!(chain.deep_reorgs_allowed_until().is_none());
+	}
+	clean_output_dir(chain_dir);
+}
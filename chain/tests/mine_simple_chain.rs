@@ -12,9 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use self::chain::difficulty_cache::DifficultyCache;
 use self::chain::types::{NoopAdapter, Tip};
 use self::chain::Chain;
-use self::core::core::hash::Hashed;
+use self::core::core::hash::{Hash, Hashed};
 use self::core::core::verifier_cache::LruVerifierCache;
 use self::core::core::{Block, BlockHeader, KernelFeatures, Transaction};
 use self::core::global::ChainTypes;
@@ -22,7 +23,7 @@ use self::core::libtx::{self, build, ProofBuilder};
 use self::core::pow::Difficulty;
 use self::core::{consensus, global, pow};
 use self::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
-use self::util::RwLock;
+use self::util::{RwLock, ToHex};
 use chrono::Duration;
 use grin_chain as chain;
 use grin_chain::{BlockStatus, ChainAdapter, Options};
@@ -607,6 +608,131 @@ fn spend_rewind_spend() {
 	clean_output_dir(chain_dir);
 }
 
+#[test]
+fn utxo_digest_follows_spends_and_reorgs() {
+	global::set_local_chain_type(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+	let chain_dir = ".mwc_utxo_digest";
+	clean_output_dir(chain_dir);
+
+	{
+		let chain = init_chain(chain_dir, pow::mine_genesis_block().unwrap());
+		assert_eq!(chain.utxo_set_digest(), None);
+		let genesis_digest = chain.enable_utxo_digest().unwrap();
+		assert_eq!(chain.utxo_set_digest(), Some(genesis_digest.clone()));
+
+		let kc = ExtKeychain::from_random_seed(false).unwrap();
+		let pb = ProofBuilder::new(&kc);
+		let mut head = chain.head_header().unwrap();
+		for n in 2..6 {
+			let b = prepare_block_key_idx(&kc, &head, &chain, n, n as u32 - 1);
+			head = b.header.clone();
+			chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		}
+		let rewind_to = head.clone();
+		let digest = chain.utxo_set_digest().unwrap();
+		assert_eq!(digest.height, 4);
+		assert_eq!(digest.utxo_count, genesis_digest.utxo_count + 4);
+		assert_ne!(digest.digest, genesis_digest.digest);
+
+		// Spend the first coinbase
+		let tx = build::transaction(
+			KernelFeatures::Plain { fee: 20000 },
+			&[
+				build::coinbase_input(
+					consensus::MWC_FIRST_GROUP_REWARD,
+					ExtKeychainPath::new(1, 1, 0, 0, 0).to_identifier(),
+				),
+				build::output(
+					consensus::MWC_FIRST_GROUP_REWARD - 20000,
+					ExtKeychainPath::new(1, 30, 0, 0, 0).to_identifier(),
+				),
+			],
+			&kc,
+			&pb,
+		)
+		.unwrap();
+		let b = prepare_block_tx(&kc, &head, &chain, 6, &[tx]);
+		chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		let spent_digest = chain.utxo_set_digest().unwrap();
+		assert_eq!(spent_digest.utxo_count, digest.utxo_count + 1);
+		// Incremental digest matches the UTXO set scan
+		assert_eq!(chain.enable_utxo_digest().unwrap(), spent_digest);
+
+		// Reorg drops the spend, the coinbase is unspent again
+		let mut fork_head = rewind_to;
+		for n in 10..12 {
+			let b = prepare_block_key_idx(&kc, &fork_head, &chain, n, n as u32);
+			fork_head = b.header.clone();
+			chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		}
+		assert_eq!(chain.head().unwrap().last_block_h, fork_head.hash());
+		let reorg_digest = chain.utxo_set_digest().unwrap();
+		assert_eq!(reorg_digest.block_hash, fork_head.hash().to_hex());
+		assert_eq!(reorg_digest.utxo_count, digest.utxo_count + 2);
+		assert_eq!(chain.enable_utxo_digest().unwrap(), reorg_digest);
+	}
+
+	clean_output_dir(chain_dir);
+}
+
+#[test]
+fn difficulty_window_cache_matches_iter() {
+	global::set_local_chain_type(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+	let chain_dir = ".mwc_difficulty_cache";
+	clean_output_dir(chain_dir);
+
+	{
+		let chain = init_chain(chain_dir, pow::mine_genesis_block().unwrap());
+		let kc = ExtKeychain::from_random_seed(false).unwrap();
+		let needed = global::difficulty_adjust_window() as usize + 1;
+		let iter_window = |chain: &Chain| -> Vec<consensus::HeaderInfo> {
+			chain.difficulty_iter().unwrap().take(needed).collect()
+		};
+
+		let mut head = chain.head_header().unwrap();
+		for n in 1..8 {
+			let b = prepare_block(&kc, &head, &chain, n);
+			head = b.header.clone();
+			chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+			assert_eq!(chain.difficulty_window().unwrap(), iter_window(&chain));
+			assert_eq!(
+				chain.next_difficulty().unwrap(),
+				consensus::next_difficulty(head.height + 1, chain.difficulty_iter().unwrap())
+			);
+		}
+
+		// Reorg to a fork from height 3
+		let mut fork_head = chain.get_header_by_height(3).unwrap();
+		for n in 20..26 {
+			let b = prepare_block_key_idx(&kc, &fork_head, &chain, n, n as u32);
+			fork_head = b.header.clone();
+			chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		}
+		assert_eq!(chain.head().unwrap().last_block_h, fork_head.hash());
+		assert_eq!(chain.difficulty_window().unwrap(), iter_window(&chain));
+
+		// Window of the child is built from the cached window of the parent
+		let cache = DifficultyCache::new();
+		let get_header = |h: &Hash| chain.get_block_header(h).ok();
+		let parent = chain.get_previous_header(&fork_head).unwrap();
+		cache.window(&parent.hash(), get_header);
+		assert_eq!(cache.misses(), 1);
+		assert_eq!(
+			cache.window(&fork_head.hash(), get_header),
+			iter_window(&chain)
+		);
+		assert_eq!(
+			cache.window(&fork_head.hash(), get_header),
+			iter_window(&chain)
+		);
+		assert_eq!((cache.hits(), cache.misses()), (1, 2));
+	}
+
+	clean_output_dir(chain_dir);
+}
+
 #[test]
 fn spend_in_fork_and_compact() {
 	clean_output_dir(".mwc6");
@@ -692,6 +818,12 @@ fn spend_in_fork_and_compact() {
 			.unwrap()
 			.is_none());
 
+		// fork header without a block
+		let fork_header = prepare_block(&kc, &fork_head, &chain, 5).header;
+		chain
+			.process_block_header(&fork_header, chain::Options::SKIP_POW)
+			.unwrap();
+
 		// mine 2 forked blocks from the first
 		let fork = prepare_block_tx(&kc, &fork_head, &chain, 6, &[tx1.clone()]);
 		let prev_fork = fork.header.clone();
@@ -748,12 +880,21 @@ fn spend_in_fork_and_compact() {
 		}
 
 		chain.validate(false).unwrap();
-		if let Err(e) = chain.compact() {
-			panic!("Error compacting chain: {:?}", e);
-		}
+		assert!(chain.block_exists(prev_main.hash()).unwrap());
+		let stats = match chain.compact() {
+			Ok(stats) => stats,
+			Err(e) => panic!("Error compacting chain: {:?}", e),
+		};
 		if let Err(e) = chain.validate(false) {
 			panic!("Validation error after compacting chain: {:?}", e);
 		}
+
+		// abandoned blocks at heights 4 and 5 and the fork header are below the horizon now
+		assert_eq!(stats.fork_blocks, 2);
+		assert_eq!(stats.fork_headers, 3);
+		assert!(!chain.block_exists(prev_main.hash()).unwrap());
+		assert!(chain.get_block_header(&prev_main.hash()).is_err());
+		assert!(chain.get_block_header(&fork_header.hash()).is_err());
 	}
 	// Cleanup chain directory
 	clean_output_dir(".mwc6");
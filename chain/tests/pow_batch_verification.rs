@@ -0,0 +1,62 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_chain as chain;
+use grin_core as core;
+
+use crate::chain::pow_batch;
+use crate::core::core::BlockHeader;
+use crate::core::pow;
+
+fn headers(num: u64) -> Vec<BlockHeader> {
+	(1..=num)
+		.map(|height| BlockHeader {
+			height,
+			..BlockHeader::default()
+		})
+		.collect()
+}
+
+fn pass_all(_header: &BlockHeader) -> Result<(), pow::Error> {
+	Ok(())
+}
+
+fn fail_odd(header: &BlockHeader) -> Result<(), pow::Error> {
+	if header.height % 2 == 1 && header.height > 20 {
+		Err(pow::Error::Verification("odd height".to_string()))
+	} else {
+		Ok(())
+	}
+}
+
+#[test]
+fn test_verify_headers_pow() {
+	for cpu_limit in &[1, 30, 100] {
+		pow_batch::init_cpu_limit(Some(*cpu_limit));
+		assert!(pow_batch::verify_headers_pow(&[], fail_odd).is_ok());
+		assert!(pow_batch::verify_headers_pow(&headers(20), fail_odd).is_ok());
+		assert!(pow_batch::verify_headers_pow(&headers(100), pass_all).is_ok());
+
+		// Batch is aborted on the failure, the failed header is reported
+		let res = pow_batch::verify_headers_pow(&headers(100), fail_odd);
+		let idx = res.unwrap_err();
+		assert!(idx >= 20);
+		assert_eq!(idx % 2, 0);
+	}
+
+	pow_batch::init_cpu_limit(None);
+	assert_eq!(pow_batch::cpu_limit(), 100);
+	pow_batch::init_cpu_limit(Some(0));
+	assert_eq!(pow_batch::cpu_limit(), 1);
+}
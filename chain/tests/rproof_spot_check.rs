@@ -0,0 +1,58 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::chain::DeferredRangeproofs;
+use grin_chain as chain;
+use grin_util as util;
+mod chain_test_helper;
+use self::chain_test_helper::{clean_output_dir, mine_chain};
+
+// Rangeproofs left by the spot-check validation are verified chunk by chunk
+#[test]
+fn test_verify_deferred_rangeproofs() {
+	util::init_test_logger();
+	let chain_dir = ".grin_rproof_spot_check";
+	clean_output_dir(chain_dir);
+	{
+		let chain = mine_chain(chain_dir, 10);
+		assert_eq!(chain.deferred_rangeproofs().unwrap(), None);
+		assert_eq!(chain.verify_deferred_rangeproofs(2).unwrap(), None);
+
+		let last_pos = chain.head_header().unwrap().output_mmr_size;
+		let batch = chain.store().batch().unwrap();
+		batch
+			.save_deferred_rangeproofs(&DeferredRangeproofs {
+				next_pos: 1,
+				last_pos,
+				failed: false,
+			})
+			.unwrap();
+		batch.commit().unwrap();
+
+		// Coinbase outputs of the mined blocks, 2 rangeproofs per chunk
+		let mut chunks = 0;
+		let mut prev_pos = 1;
+		while let Some(deferred) = chain.verify_deferred_rangeproofs(2).unwrap() {
+			assert!(deferred.next_pos > prev_pos);
+			assert_eq!(deferred.last_pos, last_pos);
+			assert_eq!(chain.deferred_rangeproofs().unwrap(), Some(deferred));
+			prev_pos = deferred.next_pos;
+			chunks += 1;
+		}
+		assert!(chunks >= 4);
+		assert_eq!(chain.deferred_rangeproofs().unwrap(), None);
+		assert!(!chain::rproof_spot_check::is_verification_failed());
+	}
+	clean_output_dir(chain_dir);
+}
@@ -0,0 +1,71 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::chain::types::NoopAdapter;
+use crate::chain::{Chain, DeferredRangeproofs};
+use crate::core::core::hash::Hashed;
+use crate::core::core::verifier_cache::LruVerifierCache;
+use crate::core::pow;
+use crate::util::RwLock;
+use grin_chain as chain;
+use grin_core as core;
+use grin_util as util;
+use std::sync::Arc;
+mod chain_test_helper;
+use self::chain_test_helper::{clean_output_dir, mine_chain};
+
+// Failed background verification is saved, reported and the chain is not opened again.
+// The failure flag is process wide, so this test has its own binary.
+#[test]
+fn test_rproof_verification_failed() {
+	util::init_test_logger();
+	let chain_dir = ".grin_rproof_verification_failed";
+	clean_output_dir(chain_dir);
+	let genesis = {
+		let chain = mine_chain(chain_dir, 5);
+		assert_eq!(chain.rangeproof_validation().unwrap(), None);
+
+		let last_pos = chain.head_header().unwrap().output_mmr_size;
+		let batch = chain.store().batch().unwrap();
+		batch
+			.save_deferred_rangeproofs(&DeferredRangeproofs {
+				next_pos: 3,
+				last_pos,
+				failed: true,
+			})
+			.unwrap();
+		batch.commit().unwrap();
+
+		assert!(chain.verify_deferred_rangeproofs(2).is_err());
+		assert!(chain::rproof_spot_check::is_verification_failed());
+		let validation = chain.rangeproof_validation().unwrap().unwrap();
+		assert!(validation.reduced_validation);
+		assert!(validation.verification_failed);
+		assert_eq!(validation.deferred_rangeproofs.unwrap().next_pos, 3);
+
+		let genesis_hash = chain.get_header_by_height(0).unwrap().hash();
+		chain.get_block(&genesis_hash).unwrap()
+	};
+
+	let res = Chain::init(
+		chain_dir.to_string(),
+		Arc::new(NoopAdapter {}),
+		genesis,
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		false,
+	);
+	assert!(res.is_err());
+	clean_output_dir(chain_dir);
+}
@@ -0,0 +1,60 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::chain::store::ChainStore;
+use crate::chain::SyncProgress;
+use crate::core::core::hash::Hash;
+use crate::core::global;
+use grin_chain as chain;
+use grin_core as core;
+use grin_util as util;
+mod chain_test_helper;
+use self::chain_test_helper::clean_output_dir;
+
+#[test]
+fn test_store_sync_progress() {
+	util::init_test_logger();
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_sync_progress";
+	clean_output_dir(chain_dir);
+	{
+		let store = ChainStore::new(chain_dir).unwrap();
+		assert_eq!(store.sync_progress().unwrap(), None);
+
+		let progress = SyncProgress {
+			update_time: 1_600_000_000,
+			header_locator: vec![Hash::from_vec(&[1]), Hash::from_vec(&[2])],
+			txhashset_request: Some((720, Hash::from_vec(&[3]))),
+			txhashset_downloaded_size: 1000,
+			txhashset_total_size: 5000,
+			body_sync_window: vec![Hash::from_vec(&[4])],
+		};
+		let batch = store.batch().unwrap();
+		batch.save_sync_progress(&progress).unwrap();
+		batch.commit().unwrap();
+		assert_eq!(store.sync_progress().unwrap(), Some(progress));
+
+		let progress = SyncProgress::default();
+		let batch = store.batch().unwrap();
+		batch.save_sync_progress(&progress).unwrap();
+		batch.commit().unwrap();
+		assert_eq!(store.sync_progress().unwrap(), Some(progress));
+
+		let batch = store.batch().unwrap();
+		batch.delete_sync_progress().unwrap();
+		batch.commit().unwrap();
+		assert_eq!(store.sync_progress().unwrap(), None);
+	}
+	clean_output_dir(chain_dir);
+}
@@ -0,0 +1,57 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::chain::store::ChainStore;
+use crate::chain::UptimeRecord;
+use crate::core::global;
+use grin_chain as chain;
+use grin_core as core;
+use grin_util as util;
+mod chain_test_helper;
+use self::chain_test_helper::clean_output_dir;
+
+// Runs started at the same second are kept apart and ordered
+#[test]
+fn test_store_uptime_same_second() {
+	util::init_test_logger();
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_uptime_history";
+	clean_output_dir(chain_dir);
+	{
+		let store = ChainStore::new(chain_dir).unwrap();
+		let first = UptimeRecord::new(1_600_000_000);
+		let mut second = UptimeRecord::new(1_600_000_000);
+		second.seq = 1;
+		let mut later = UptimeRecord::new(1_600_000_001);
+		later.stop_time = 1_600_000_100;
+
+		let batch = store.batch().unwrap();
+		batch.save_uptime_record(&later).unwrap();
+		batch.save_uptime_record(&second).unwrap();
+		batch.save_uptime_record(&first).unwrap();
+		batch.commit().unwrap();
+		assert_eq!(
+			store.uptime_history().unwrap(),
+			vec![first.clone(), second.clone(), later.clone()]
+		);
+
+		let batch = store.batch().unwrap();
+		batch
+			.delete_uptime_record(first.start_time, first.seq)
+			.unwrap();
+		batch.commit().unwrap();
+		assert_eq!(store.uptime_history().unwrap(), vec![second, later]);
+	}
+	clean_output_dir(chain_dir);
+}
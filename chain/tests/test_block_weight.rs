@@ -0,0 +1,40 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod chain_test_helper;
+use self::chain_test_helper::{clean_output_dir, mine_chain};
+use grin_chain as chain;
+use grin_core::core::hash::Hashed;
+use grin_util as util;
+
+#[test]
+fn block_weight_from_header() {
+	let chain_dir = ".grin.block_weight";
+	util::init_test_logger();
+	clean_output_dir(chain_dir);
+
+	{
+		let chain: chain::Chain = mine_chain(chain_dir, 4);
+		let head = chain.head().unwrap();
+		for height in 0..=head.height {
+			let header = chain.get_header_by_height(height).unwrap();
+			let block = chain.get_block(&header.hash()).unwrap();
+			assert_eq!(
+				chain.get_block_weight(&header).unwrap(),
+				block.body.body_weight_as_block()
+			);
+		}
+	}
+	clean_output_dir(chain_dir);
+}
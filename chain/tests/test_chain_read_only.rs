@@ -0,0 +1,61 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod chain_test_helper;
+use self::chain_test_helper::{clean_output_dir, mine_chain};
+use chain::types::NoopAdapter;
+use chain::Chain;
+use grin_chain as chain;
+use grin_core::core::hash::Hashed;
+use grin_core::core::verifier_cache::LruVerifierCache;
+use grin_core::pow;
+use grin_util as util;
+use std::sync::Arc;
+use util::RwLock;
+
+#[test]
+fn init_read_only_chain() {
+	let chain_dir = ".grin.read_only";
+	util::init_test_logger();
+	clean_output_dir(chain_dir);
+
+	let (head, header_head, genesis) = {
+		let chain = mine_chain(chain_dir, 4);
+		let genesis = chain.get_header_by_height(0).unwrap();
+		let genesis = chain.get_block(&genesis.hash()).unwrap();
+		(chain.head().unwrap(), chain.header_head().unwrap(), genesis)
+	};
+
+	{
+		let chain = Chain::init_read_only(
+			chain_dir.to_string(),
+			Arc::new(NoopAdapter {}),
+			genesis,
+			pow::verify_size,
+			Arc::new(RwLock::new(LruVerifierCache::new())),
+			false,
+		)
+		.unwrap();
+		assert_eq!(chain.head().unwrap(), head);
+		assert_eq!(chain.header_head().unwrap(), header_head);
+		assert_eq!(
+			chain.get_header_by_height(head.height).unwrap().hash(),
+			head.last_block_h
+		);
+		// Nothing can be written to the chain
+		assert!(chain.store().batch().is_err());
+	}
+
+	clean_output_dir(chain_dir);
+}
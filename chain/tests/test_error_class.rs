@@ -0,0 +1,80 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod chain_test_helper;
+use self::chain_test_helper::{clean_output_dir, mine_chain};
+use chain::{Error, ErrorClass, ErrorKind};
+use grin_chain as chain;
+use grin_core::ser;
+use grin_store as store;
+use grin_util as util;
+
+#[test]
+fn error_classes() {
+	let class = |kind: ErrorKind| Error::from(kind).class();
+
+	assert_eq!(
+		class(ErrorKind::Orphan("".to_string())),
+		ErrorClass::Rejected
+	);
+	assert_eq!(
+		class(ErrorKind::Unfit("".to_string())),
+		ErrorClass::Rejected
+	);
+	assert_eq!(class(ErrorKind::InvalidPow), ErrorClass::BadData);
+	assert_eq!(class(ErrorKind::OldBlock), ErrorClass::BadData);
+	assert_eq!(class(ErrorKind::Stopped), ErrorClass::Transient);
+	assert_eq!(
+		class(ErrorKind::SyncError("timeout".to_string())),
+		ErrorClass::Transient
+	);
+	assert_eq!(
+		class(ErrorKind::FileReadErr("pmmr".to_string())),
+		ErrorClass::Corruption
+	);
+	assert_eq!(
+		class(ErrorKind::SerErr(ser::Error::CorruptedData("".to_string()))),
+		ErrorClass::Corruption
+	);
+	assert_eq!(
+		class(ErrorKind::StoreErr(
+			store::Error::NotFoundErr("".to_string()),
+			"".to_string()
+		)),
+		ErrorClass::Internal
+	);
+	assert_eq!(
+		class(ErrorKind::Other("".to_string())),
+		ErrorClass::Internal
+	);
+
+	// Only the bad data bans the peer
+	assert!(Error::from(ErrorKind::InvalidPow).is_bad_data());
+	assert!(!Error::from(ErrorKind::Stopped).is_bad_data());
+	assert!(!Error::from(ErrorKind::Bitmap).is_bad_data());
+}
+
+#[test]
+fn repair_consistent_heads() {
+	let chain_dir = ".grin.repair_heads";
+	util::init_test_logger();
+	clean_output_dir(chain_dir);
+	{
+		let chain = mine_chain(chain_dir, 4);
+		let header_head = chain.header_head().unwrap();
+		assert!(!chain.repair_heads().unwrap());
+		assert_eq!(chain.header_head().unwrap(), header_head);
+	}
+	clean_output_dir(chain_dir);
+}
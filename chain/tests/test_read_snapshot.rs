@@ -0,0 +1,72 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod chain_test_helper;
+use self::chain_test_helper::{clean_output_dir, mine_chain};
+use chain::Tip;
+use grin_chain as chain;
+use grin_core::core::hash::Hashed;
+use grin_util as util;
+
+#[test]
+fn read_snapshot_isolation() {
+	let chain_dir = ".grin.read_snapshot";
+	util::init_test_logger();
+	clean_output_dir(chain_dir);
+
+	{
+		let chain = mine_chain(chain_dir, 4);
+		let head = chain.head().unwrap();
+		let head_header = chain.head_header().unwrap();
+
+		let snapshot = chain.snapshot().unwrap();
+		assert_eq!(snapshot.head(), head);
+		assert_eq!(
+			snapshot.get_header_by_height(head.height).unwrap().hash(),
+			head.last_block_h
+		);
+		assert!(snapshot.get_header_by_height(head.height + 1).is_err());
+
+		// Body head moves back while the snapshot is open
+		{
+			let store = chain.store();
+			let batch = store.batch().unwrap();
+			let prev = batch.get_previous_header(&head_header).unwrap();
+			batch.save_body_head(&Tip::from_header(&prev)).unwrap();
+			batch.commit().unwrap();
+		}
+
+		// Old snapshot still sees the chain as it was
+		assert_eq!(snapshot.head(), head);
+		let header = snapshot.get_header_by_height(head.height).unwrap();
+		assert_eq!(header.hash(), head.last_block_h);
+		assert!(snapshot.get_block(&header.hash()).is_ok());
+		assert!(snapshot.is_on_current_chain(&header).is_ok());
+
+		// New snapshot doesn't resolve the height to the header without the body
+		let snapshot = chain.snapshot().unwrap();
+		assert_eq!(snapshot.head().height, head.height - 1);
+		assert!(snapshot.get_header_by_height(head.height).is_err());
+		assert!(snapshot.is_on_current_chain(&header).is_err());
+		assert_eq!(
+			snapshot
+				.get_header_by_height(head.height - 1)
+				.unwrap()
+				.hash(),
+			head_header.prev_hash
+		);
+	}
+
+	clean_output_dir(chain_dir);
+}
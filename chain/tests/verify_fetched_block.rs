@@ -0,0 +1,48 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use self::core::core::hash::Hashed;
+use chrono::Duration;
+use grin_core as core;
+use grin_util as util;
+mod chain_test_helper;
+use self::chain_test_helper::{clean_output_dir, mine_chain};
+
+// Block fetched from a peer must match our header and our kernel MMR
+#[test]
+fn test_verify_fetched_block() {
+	util::init_test_logger();
+	let chain_dir = ".grin_verify_fetched_block";
+	clean_output_dir(chain_dir);
+	{
+		let chain = mine_chain(chain_dir, 5);
+		let header3 = chain.get_header_by_height(3).unwrap();
+		let header4 = chain.get_header_by_height(4).unwrap();
+		let block3 = chain.get_block(&header3.hash()).unwrap();
+		let block4 = chain.get_block(&header4.hash()).unwrap();
+		assert!(chain.verify_fetched_block(&block3).is_ok());
+		assert!(chain.verify_fetched_block(&block4).is_ok());
+
+		// Body of another block
+		let mut bad_block = block3.clone();
+		bad_block.body = block4.body.clone();
+		assert!(chain.verify_fetched_block(&bad_block).is_err());
+
+		// Block that is not on our header chain
+		let mut bad_block = block3.clone();
+		bad_block.header.timestamp = bad_block.header.timestamp + Duration::seconds(1);
+		assert!(chain.verify_fetched_block(&bad_block).is_err());
+	}
+	clean_output_dir(chain_dir);
+}
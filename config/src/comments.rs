@@ -112,116 +112,1082 @@ fn comments() -> HashMap<String, String> {
 		.to_string(),
 	);
 
+	retval.insert(
+		"kernel_fee_index".to_string(),
+		"
+#build the index of kernels by fee in background. Needed for the fee analysis API
+#(get_kernels_by_fee, get_fee_histogram). Non archive nodes index only the blocks they have.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"utxo_set_digest".to_string(),
+		"
+#maintain the digest of the UTXO set and report it in the node status (utxo_digest). Wallets
+#and auditors compare it between the nodes to check that they agree on the unspent outputs.
+#The UTXO set is scanned once at the start, then the digest is updated with every block.
+"
+		.to_string(),
+	);
+
 	retval.insert(
 		"skip_sync_wait".to_string(),
 		"
-#skip waiting for sync on startup, (optional param, mostly for testing)
+#skip waiting for sync on startup, (optional param, mostly for testing)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"header_cache_size".to_string(),
+		"
+#size of the header cache for syncing. (default is 0)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"duration_sync_short".to_string(),
+		"
+#length in milliseconds to between requesting headers when short wait is desired (default is 100 ms)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"duration_sync_long".to_string(),
+		"
+#length in milliseconds to between requesting headers when long wait is desired (default is 150 ms)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+                "invalid_block_hashes".to_string(),
+                "
+#A list of block hashes to ignore for this node. (mostly used for testing).
+#invalid_block_hashes = [\"735cf2a4492b437e292a295549c31df5f1e8e6d09e58ed20abdd808c2261d1f1\", \"9686b69cab945146fd431ec4459a0eef6efbcc5553480b7454edd32f9c3b4d52\"]
+"
+                .to_string(),
+	);
+
+	retval.insert(
+		"max_reorg_depth".to_string(),
+		"
+#Anti deep reorg rule. Reorgs deeper than max_reorg_depth blocks are rejected and reported
+#to the log and the deep_reorg_rejected webhook. The operator can allow the deep reorgs for
+#a while with the owner API allow_deep_reorgs. 0 - no limit (default).
+#max_reorg_depth = 500
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"sync_pow_cpu_limit".to_string(),
+		"
+#Headers PoW is verified in parallel during the sync. Max CPU usage by the verification,
+#percents of all cores. Lower it on the low power devices that are doing something else.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"rangeproof_spot_check_percent".to_string(),
+		"
+#REDUCED VALIDATION, NOT RECOMMENDED. Percents of the UTXO rangeproofs that are verified
+#during the state sync. The rest are verified in background after the node is online, until
+#then the node trusts the most work chain. For the Raspberry Pi class hardware only.
+#The node stops if an invalid rangeproof is found, the chain data must be synced again.
+#100 - all rangeproofs are verified during the state sync.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"stealth_mode".to_string(),
+		"
+#Outbound-only stealth mode for the privacy focused personal nodes. The node doesn't accept
+#any inbound connections: the p2p listener, the onion service and libp2p are not started,
+#Tor (if enabled) is used for the outbound connections only. The API must listen on
+#a loopback address, the stratum server is started only if it listens on a loopback address.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"upgrade_handoff".to_string(),
+		"
+#Zero-downtime binary upgrade (unix only). The node binds its listeners with SO_REUSEPORT
+#and serves its state on the handoff socket in the db_root. The new node binary, started
+#with 'mwc server --takeover run', binds the same ports, receives the known peers and the
+#txpool transactions, and this node exits. The new node must run with the same config.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"outbound_http_proxy".to_string(),
+		"
+#Proxy for the outbound HTTP requests of the node: webhooks and the test miner wallet
+#calls. Supported are 'socks5://host:port' (Tor) and 'http://host:port'.
+#outbound_http_proxy = \"socks5://127.0.0.1:51234\"
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"outbound_onion_only".to_string(),
+		"
+#Never send the outbound HTTP requests directly. Without outbound_http_proxy the requests
+#go through the Tor socks port if Tor is enabled, otherwise they are refused. Requests
+#to the loopback addresses are always direct.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"api_ready_max_blocks_behind".to_string(),
+		"
+#The /readyz endpoint reports the node as ready only if its chain is at most that many
+#blocks behind the connected peers. Load balancers use it to route the API requests to
+#the synced nodes only, /healthz just checks that the node and its stores are running.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_enabled".to_string(),
+		"
+#Start gossipsub libp2p node. Node will be started only if Tor is enabled. Default value: true
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_port".to_string(),
+		"
+#libp2p node local port. libp2p node is run only with TOR. Note, this setting doesn't affect libp2p address at the network.
+"
+			.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_allow_list".to_string(),
+		"
+#Onion addresses that libp2p node is allowed to connect to. If the list is not empty,
+#only these peers are dialed and accepted. Rules are stored in the peers db and can be
+#updated with the owner API.
+#libp2p_allow_list = [\"2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid\"]
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_deny_list".to_string(),
+		"
+#Onion addresses that libp2p node will never dial or accept (known spammers).
+#libp2p_deny_list = [\"xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad\"]
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_audit_topics".to_string(),
+		"
+#Audit log of the accepted gossip messages for the dispute resolution. The topic, message
+#id, sender, integrity fee, time and payload hash of every message accepted on these
+#topics are recorded. The log is queried with the owner API get_gossip_audit.
+#libp2p_audit_topics = [\"SwapMarketplace\"]
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_audit_retention_days".to_string(),
+		"
+#Gossip audit records older than that are pruned. Default: 30
+#libp2p_audit_retention_days = 30
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_audit_max_entries".to_string(),
+		"
+#Max number of the gossip audit records, the oldest records are pruned. Default: 100000
+#libp2p_audit_max_entries = 100000
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_probe_interval_secs".to_string(),
+		"
+#Gossip mesh latency probes. The node publishes a tiny probe on the diagnostic topic with
+#this interval, the nodes with the probes enabled answer it. The round trip time of the
+#answers is reported by the owner API get_gossip_probe_stats. Disabled by default.
+#libp2p_probe_interval_secs = 300
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"libp2p_quarantine_secs".to_string(),
+		"
+#Quarantine of the libp2p peers that originated the invalid gossip messages, in seconds.
+#Quarantined peer is not dialed, its connections are banned, its messages are not relayed
+#and it is dropped from the peer exchange lists. Default is 3600, 0 disables the quarantine.
+#libp2p_quarantine_secs = 3600
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"run_tui".to_string(),
+		"
+#whether to run the ncurses TUI. Ncurses must be installed and this
+#will also disable logging to stdout
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"run_test_miner".to_string(),
+		"
+#Whether to run a test miner. This is only for developer testing (chaintype
+#usertesting) at cuckoo 16, and will only mine into the default wallet port.
+#real mining should use the standalone grin-miner
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.webhook_config]".to_string(),
+		"
+#########################################
+### WEBHOOK CONFIGURATION             ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"nthreads".to_string(),
+		"
+#The url where a POST request will be sent when a new block is accepted by our node.
+#block_accepted_url = \"http://127.0.0.1:8080/acceptedblock\"
+
+#The url where a POST request will be sent when a new transaction is received by a peer.
+#tx_received_url = \"http://127.0.0.1:8080/tx\"
+
+#The url where a POST request will be sent when a new header is received by a peer.
+#header_received_url = \"http://127.0.0.1:8080/header\"
+
+#The url where a POST request will be sent when a new block is received by a peer.
+#block_received_url = \"http://127.0.0.1:8080/block\"
+
+#The url where a POST request will be sent when the chain tip lag state is changed.
+#tip_lag_alert_url = \"http://127.0.0.1:8080/tiplag\"
+
+#Additional destinations. Every destination receives the listed events (all events if
+#the list is empty): tx_received, header_received, block_received, block_accepted, tip_lag_alert,
+#deep_reorg_rejected, kernel_watch, consensus_canary.
+#If the secret is set, the payload HMAC-SHA256 signature (hex) is sent in the X-Mwc-Signature
+#header and the event name in the X-Mwc-Event header.
+#Set full_fidelity to receive every block_accepted event as is when the events are batched
+#(see batch_window_ms).
+#[[server.webhook_config.destinations]]
+#url = \"https://127.0.0.1:8443/events\"
+#events = [\"block_accepted\", \"tip_lag_alert\"]
+#secret = \"shared_secret\"
+#full_fidelity = false
+
+#The number of worker threads that will be assigned to making the http requests.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"timeout".to_string(),
+		"
+#The timeout of the http request in seconds.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"batch_window_ms".to_string(),
+		"
+#Big reorgs fire hundreds of block_accepted events in a second. With a non zero window (ms)
+#the events that fire within the window are coalesced: a single block_batch event with the
+#list of the blocks is posted, plus a reorg_summary event (fork point, old and new head, depth)
+#if there were reorgs. A lone block is still posted as block_accepted. 0 disables batching.
+#block_accepted_url receives the batch events too.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"kernel_watch_depth".to_string(),
+		"
+#Kernels registered with the watch_kernels foreign API are reported on every block until they
+#have that many confirmations, unless the watch has its own depth. The events are posted to
+#the destinations that accept kernel_watch and sent to the /v2/kernels/events websocket.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"event_journal_max_entries".to_string(),
+		"
+#Max number of the tx pool and kernel watch events kept in the event journal. The event
+#sequence numbers continue across the node restarts and the websocket subscribers that lost
+#the connection reconnect with ?resume_from=<seq> to get the missed events. 0 disables the journal.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.foreign_api_load_shedding]".to_string(),
+		"
+#########################################
+### FOREIGN API LOAD SHEDDING         ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_load_shedding".to_string(),
+		"
+#reject the heavy foreign API calls with 503 and Retry-After while the node
+#validates the chain state. Public explorers should keep it enabled, a private
+#wallet backend might disable it.
+#The rejected methods are listed in load_shedding_heavy_methods, the rejected
+#v1 REST routes are listed in load_shedding_heavy_uris.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"shed_during_block_sync".to_string(),
+		"
+#reject the heavy calls during the headers, txhashset and blocks download as well
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"load_shedding_heavy_uris".to_string(),
+		"
+#path prefixes of the v1 REST routes that are rejected, same as the heavy methods
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"load_shedding_retry_after_secs".to_string(),
+		"
+#Retry-After value of the rejected responses, in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.api_http_config]".to_string(),
+		"
+#########################################
+### API SERVER HTTP CONFIGURATION     ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_http2".to_string(),
+		"
+#accept HTTP/2 connections, explorers can issue many queries over one connection.
+#With TLS HTTP/2 is negotiated with ALPN, without TLS clients must use h2c prior knowledge.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"http1_keep_alive".to_string(),
+		"
+#keep the HTTP/1 connections open between the requests
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"tcp_keep_alive_secs".to_string(),
+		"
+#TCP keep-alive of the API connections in seconds, 0 to disable
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"http2_keep_alive_interval_secs".to_string(),
+		"
+#interval of the HTTP/2 keep-alive pings in seconds, 0 to disable.
+#The connection is closed if the ping is not acknowledged in http2_keep_alive_timeout_secs.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"http2_keep_alive_timeout_secs".to_string(),
+		"
+#timeout of the HTTP/2 keep-alive ping acknowledgement in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"http2_max_concurrent_streams".to_string(),
+		"
+#max number of the concurrent requests over a single HTTP/2 connection
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"max_request_body_size".to_string(),
+		"
+#max size of the request body in bytes, larger requests are rejected with 413. 0 - no limit.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_compression".to_string(),
+		"
+#compress the responses with brotli or gzip, as negotiated with the Accept-Encoding header.
+#Saves the bandwidth of the blocks and the outputs queries, especially over Tor.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"compression_min_size".to_string(),
+		"
+#responses smaller than this size in bytes are sent uncompressed
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.owner_api_sessions]".to_string(),
+		"
+#########################################
+### OWNER API SESSIONS                ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_owner_sessions".to_string(),
+		"
+#owner API and v1 API require the session tokens instead of the api secret. The secret is
+#accepted by POST /v2/owner/login only, it returns the token for the Authorization: Bearer header.
+#The login body can have a label of the session and a shorter lifetime:
+#{\"label\": \"alice\", \"ttl_secs\": 600}
+#POST /v2/owner/logout revokes the token, GET /v2/owner/sessions lists the active sessions,
+#DELETE /v2/owner/sessions?id=<session id> revokes a session. GET /v2/owner/audit returns
+#the owner methods and v1 calls made with every session. Requires api secret.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"owner_session_ttl_secs".to_string(),
+		"
+#lifetime of the session token in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"owner_max_sessions".to_string(),
+		"
+#max number of the active sessions
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"owner_audit_log_size".to_string(),
+		"
+#number of the owner actions kept in the in-memory audit log
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.grpc_config]".to_string(),
+		"
+#########################################
+### GRPC API CONFIGURATION            ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_grpc".to_string(),
+		"
+#run the gRPC server next to the REST and JSON-RPC APIs. The services are defined in
+#api/proto/mwc_node.proto: Foreign (tip, blocks, outputs, push transaction and the
+#streams of the tip, the pool events and the kernel events) and Owner (status).
+#Foreign service requires the foreign api secret, Owner service requires the api secret,
+#both in the same Authorization: Basic header as the HTTP API.
+#Owner service is not started when the owner API sessions are enabled.
+#The node must be built with the grpc feature.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"grpc_addr".to_string(),
+		"
+#listening address of the gRPC server
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.tip_lag_alert_config]".to_string(),
+		"
+#########################################
+### TIP LAG ALERT CONFIGURATION       ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_tip_lag_alerts".to_string(),
+		"
+#compare our chain tip with the tips advertised by the peers. Alerts are logged,
+#reported in the node status and posted to the tip_lag_alert_url webhook.
+#States: Ok, NoPeers, NetworkQuiet (our tip is old, but peers don't have newer blocks),
+#Lagging (peers are ahead, our tip is moving), Stuck (peers are ahead, our tip is not moving)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"max_height_lag".to_string(),
+		"
+#alert if the peers are ahead of us by more than this number of blocks
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"max_tip_age_secs".to_string(),
+		"
+#alert if our tip block is older than this number of seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"tip_lag_check_interval_secs".to_string(),
+		"
+#how often the tip lag is checked, in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.consensus_canary_config]".to_string(),
+		"
+#########################################
+### CONSENSUS CANARY CONFIGURATION    ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_consensus_canary".to_string(),
+		"
+#compare our tip, kernel root and recent block hashes with the trusted remote nodes.
+#The chains that stay different longer than canary_tolerance_secs raise the critical
+#alert: it is logged, reported in the node status and sent to the webhook destinations
+#that accept the consensus_canary event. Early warning of the consensus bugs after releases.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"canary_nodes".to_string(),
+		"
+#foreign API urls of the remote nodes to compare with, e.g. [\"http://10.0.0.5:3413\"]
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"canary_api_secret".to_string(),
+		"
+#foreign API secret of the remote nodes, if they require one
+#canary_api_secret = \"\"
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"canary_check_interval_secs".to_string(),
+		"
+#how often the chains are compared, in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"canary_block_window".to_string(),
+		"
+#number of the recent blocks searched for the fork point
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"canary_max_height_gap".to_string(),
+		"
+#report the remote node as lagging if the tips differ by more than this number of blocks
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"canary_tolerance_secs".to_string(),
+		"
+#divergence that lasts longer than this number of seconds is critical.
+#Short forks are normal and get resolved by the network.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.standby_config]".to_string(),
+		"
+#########################################
+### STANDBY NODE CONFIGURATION        ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"standby_mode".to_string(),
+		"
+#start the node as the hot spare of the primary node. The standby node syncs and
+#validates the chain, but the stratum server, the transaction pushes and the webhooks
+#wait until the node is promoted with the owner API promote_to_primary call, or by
+#the failover if auto_promote is set. Promotion takes about a second.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"primary_url".to_string(),
+		"
+#foreign API url of the primary node, e.g. \"http://10.0.0.2:3413\"
+#primary_url = \"\"
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"primary_api_secret".to_string(),
+		"
+#foreign API secret of the primary node, if it requires one
+#primary_api_secret = \"\"
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"auto_promote".to_string(),
+		"
+#promote the node when primary_failure_threshold checks of the primary failed in a row.
+#Make sure the standby can tell the primary is down, not just unreachable from here,
+#otherwise both nodes will mine.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"primary_check_interval_secs".to_string(),
+		"
+#how often the primary is checked, in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"primary_failure_threshold".to_string(),
+		"
+#number of the failed checks in a row that means the primary is down
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.metrics_history_config]".to_string(),
+		"
+#########################################
+### METRICS HISTORY CONFIGURATION     ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_metrics_history".to_string(),
+		"
+#periodically store the snapshots of the key metrics (peer counts, tip height,
+#mempool size, block validation timings) into the chain db. Snapshots can be read
+#with the owner API get_metrics_history call.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"metrics_snapshot_interval_secs".to_string(),
+		"
+#how often the snapshot is taken, in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"metrics_history_limit".to_string(),
+		"
+#max number of the snapshots that are kept, the oldest ones are pruned
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.cold_storage_config]".to_string(),
+		"
+#########################################
+### COLD STORAGE CONFIGURATION        ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"cold_storage_mode".to_string(),
+		"
+#serve the chain snapshot read-only without connecting to the network, for the audit
+#environments that verify the historical data offline. Headers, blocks, kernels, outputs
+#and merkle proofs are available through the APIs. P2P, sync, Tor, libp2p and stratum
+#are not started, the transaction pushes and the chain compaction are rejected.
+#Can be enabled from the command line with 'mwc server --cold-storage <dir> run'
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"snapshot_dir".to_string(),
+		"
+#snapshot directory, a copy of the chain_data directory (lmdb, header and txhashset)
+#taken from a stopped node of the same network. It is opened read-only and never
+#repaired, the node doesn't start if the snapshot heads and MMRs are inconsistent.
+#snapshot_dir = \"\"
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.libp2p_config]".to_string(),
+		"
+#########################################
+### LIBP2P GOSSIPSUB CONFIGURATION    ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"heartbeat_interval_ms".to_string(),
+		"
+#gossipsub heartbeat interval in milliseconds. The mesh maintenance and the gossip
+#emission happen on the heartbeat. Default: 3000
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"mesh_n".to_string(),
+		"
+#target number of the peers in the topic mesh. The mesh and history parameters that are
+#not set use the gossipsub defaults. mesh_n_low <= mesh_n <= mesh_n_high must hold.
+#mesh_n = 6
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"mesh_n_low".to_string(),
+		"
+#the mesh is extended when it has less peers than that
+#mesh_n_low = 5
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"header_cache_size".to_string(),
+		"mesh_n_high".to_string(),
 		"
-#size of the header cache for syncing. (default is 0)
+#the mesh is pruned when it has more peers than that, the node tries to keep that
+#many libp2p connections
+#mesh_n_high = 12
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"duration_sync_short".to_string(),
+		"validation_mode".to_string(),
 		"
-#length in milliseconds to between requesting headers when short wait is desired (default is 100 ms)
+#validation of the gossip message signatures: Strict (every message must be signed by
+#its author) or Permissive (unsigned messages are accepted). Default: Strict
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"duration_sync_long".to_string(),
+		"history_length".to_string(),
 		"
-#length in milliseconds to between requesting headers when long wait is desired (default is 150 ms)
+#number of the heartbeats the message ids are kept in the message cache
+#history_length = 5
 "
 		.to_string(),
 	);
 
 	retval.insert(
-                "invalid_block_hashes".to_string(),
-                "
-#A list of block hashes to ignore for this node. (mostly used for testing).
-#invalid_block_hashes = [\"735cf2a4492b437e292a295549c31df5f1e8e6d09e58ed20abdd808c2261d1f1\", \"9686b69cab945146fd431ec4459a0eef6efbcc5553480b7454edd32f9c3b4d52\"]
+		"history_gossip".to_string(),
+		"
+#number of the recent heartbeats whose message ids are gossiped, history_gossip <= history_length
+#history_gossip = 3
 "
-                .to_string(),
+		.to_string(),
 	);
 
 	retval.insert(
-		"libp2p_enabled".to_string(),
+		"reconnect_interval_secs".to_string(),
 		"
-#Start gossipsub libp2p node. Node will be started only if Tor is enabled. Default value: true
+#how often the libp2p node checks its connections and dials the new peers, in seconds
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"libp2p_port".to_string(),
+		"transport".to_string(),
 		"
-#libp2p node local port. libp2p node is run only with TOR. Note, this setting doesn't affect libp2p address at the network.
+#transport of the libp2p node:
+#\"tor\" - onion service only, the peers are dialed through Tor (default)
+#\"tcp\" - clearnet TCP only, for the nodes without Tor. Only tcp_peers are dialed.
+#\"tor_and_tcp\" - both, the onion peers are preferred, tcp_peers are dialed if no onion
+#peer is available
+#QUIC is not available until the libp2p fork is upgraded
 "
-			.to_string(),
+		.to_string(),
 	);
 
 	retval.insert(
-		"run_tui".to_string(),
+		"tcp_port".to_string(),
 		"
-#whether to run the ncurses TUI. Ncurses must be installed and this
-#will also disable logging to stdout
+#port of the libp2p clearnet TCP listener
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"run_test_miner".to_string(),
+		"tcp_peers".to_string(),
 		"
-#Whether to run a test miner. This is only for developer testing (chaintype
-#usertesting) at cuckoo 16, and will only mine into the default wallet port.
-#real mining should use the standalone grin-miner
+#clearnet libp2p peers, multiaddresses with the peer id, the peer id is the onion address
+#of the peer key: [\"/ip4/1.2.3.4/tcp/3418/p2p/<peer id>\"]
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"[server.webhook_config]".to_string(),
+		"dial_timeout_secs".to_string(),
 		"
-#########################################
-### WEBHOOK CONFIGURATION             ###
-#########################################
+#hard timeout of the libp2p dial through the Tor SOCKS proxy, in seconds. Unreachable peers
+#are backed off, the Tor dials are paused while the proxy is down
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"nthreads".to_string(),
+		"dial_workers".to_string(),
 		"
-#The url where a POST request will be sent when a new block is accepted by our node.
-#block_accepted_url = \"http://127.0.0.1:8080/acceptedblock\"
+#number of the libp2p dials through the Tor SOCKS proxy that can run at the same time
+"
+		.to_string(),
+	);
 
-#The url where a POST request will be sent when a new transaction is received by a peer.
-#tx_received_url = \"http://127.0.0.1:8080/tx\"
+	retval.insert(
+		"identity".to_string(),
+		"
+#libp2p identity key, the PeerId of the node:
+#\"tor\" - the onion service key, the PeerId matches the onion address (default)
+#\"standalone\" - own key generated on the first run and kept in the data dir as
+#libp2p_identity. The PeerId survives the onion service rotation, but the peers can't
+#derive the onion address of the node from it. The clearnet only node always uses it.
+"
+		.to_string(),
+	);
 
-#The url where a POST request will be sent when a new header is received by a peer.
-#header_received_url = \"http://127.0.0.1:8080/header\"
+	retval.insert(
+		"kad_discovery".to_string(),
+		"
+#Kademlia DHT peer discovery. The node announces the topics it handles in the DHT and
+#looks up the peers when its mesh is thin or the seeds are offline
+"
+		.to_string(),
+	);
 
-#The url where a POST request will be sent when a new block is received by a peer.
-#block_received_url = \"http://127.0.0.1:8080/block\"
+	retval.insert(
+		"kad_query_interval_secs".to_string(),
+		"
+#interval between the DHT lookups, in seconds
+"
+		.to_string(),
+	);
 
-#The number of worker threads that will be assigned to making the http requests.
+	retval.insert(
+		"topic_allowlist".to_string(),
+		"
+#gossipsub topics the node subscribes to and relays, all topics if empty.
+#Trailing '*' matches any topic with that prefix, e.g. [\"swap_*\"]
 "
 		.to_string(),
 	);
 
 	retval.insert(
-		"timeout".to_string(),
+		"topic_denylist".to_string(),
 		"
-#The timeout of the http request in seconds.
+#gossipsub topics the node never subscribes to. Denylist wins over the allowlist.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"seen_cache_size".to_string(),
+		"
+#max number of the gossip messages in the cache of the messages that were already
+#passed to the topic handlers. The same message is not handled twice.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"seen_cache_ttl_secs".to_string(),
+		"
+#time the message stays in the seen messages cache, in seconds
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"persist_seen_cache".to_string(),
+		"
+#save the seen messages cache to the disk, so after the restart the node doesn't
+#pass the messages it already handled to the topic handlers again
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"relay_hop".to_string(),
+		"
+#relay the circuits of the peers that can't publish an onion service, dialing the
+#destination of the circuit if it is not connected. Without it the circuits are relayed
+#to the connected peers only, if the relay_nodes are set.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"relay_nodes".to_string(),
+		"
+#relay nodes, multiaddresses with the peer id: [\"/onion3/<address>:<port>/p2p/<peer id>\"].
+#The node keeps them connected and listens through them, so the peers can reach it without
+#the onion service. Peers that can't be dialed directly are dialed through them.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"relay_max_connections".to_string(),
+		"
+#max number of the relayed connections, the relayed connections above it are refused
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"relay_connection_bytes_per_sec".to_string(),
+		"
+#bandwidth limit of the relayed connection, in bytes per second. 0 is unlimited.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"relay_total_bytes_per_sec".to_string(),
+		"
+#bandwidth limit of all the relayed connections together, in bytes per second. 0 is unlimited.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.libp2p_policy]".to_string(),
+		"
+#########################################
+### LIBP2P RATE LIMITS CONFIGURATION  ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"integrity_call_history_len".to_string(),
+		"
+#number of the recent messages of the integrity kernel the message rate is checked on
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"integrity_call_min_period_secs".to_string(),
+		"
+#min average interval between the messages paid with the same integrity kernel,
+#in seconds. Messages of the kernel that is used more often are rejected.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.libp2p_policy.topic_rate_multipliers]".to_string(),
+		"
+#multipliers of the allowed message rate by the topic. High volume topics can
+#accept the messages of the same kernel more often, e.g.
+#SwapMarketplace = 4.0
 "
 		.to_string(),
 	);
@@ -276,6 +1242,122 @@ fn comments() -> HashMap<String, String> {
 		.to_string(),
 	);
 
+	retval.insert(
+		"own_tx_privacy".to_string(),
+		"
+#privacy mode for our (pushed via api) txs, useful if the wallet is running on the same host.
+#First relay of our txs is delayed by a random interval, our txs are always stemmed (even with
+#fluff), stem peer is selected from the Tor peers only and our txs are not served to the peers
+#for the first own_tx_private_mins minutes.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"own_tx_relay_delay_secs".to_string(),
+		"
+#max random delay in secs before the first relay of our txs (privacy mode)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"own_tx_private_mins".to_string(),
+		"
+#number of minutes our txs are not served to the peers (privacy mode)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"stem_aggregation_secs".to_string(),
+		"
+#stem txs received from the peers in the stem epoch are aggregated together for this number
+#of seconds and relayed as a single tx in random order, so it is harder to link the txs to
+#their origin. 0 - relay every stem tx right away
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"stem_aggregation_max_txs".to_string(),
+		"
+#aggregated stem txs are relayed as soon as there are that many of them
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.consensus_overlay]".to_string(),
+		"
+#########################################
+### CONSENSUS OVERLAY (USERNET ONLY)  ###
+#########################################
+
+#Private networks and integration tests can run with the custom consensus parameters.
+#The overlay is applied to the usernet only, floonet and mainnet ignore it. Parameters
+#that are not set keep the usernet values.
+#block_time_sec = 10
+#difficulty_adjust_window = 10
+#coinbase_maturity = 3
+#max_block_weight = 40000
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.tor_config]".to_string(),
+		"
+#########################################
+### TOR CONFIGURATION                 ###
+#########################################
+
+#Wallet listeners can be reachable with the node onion address, so there is no need
+#to run a second Tor instance for the wallet. Applicable only with internal Tor.
+#Key is the onion service port, value is the local wallet listener address.
+#[server.tor_config.wallet_listener_ports]
+#3420 = \"127.0.0.1:3415\"
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"pow_defenses_enabled".to_string(),
+		"
+#Tor onion service PoW defense against the introduction floods (Tor 0.4.8+, internal Tor
+#only). Clients solve a puzzle, the requests with higher effort are served first.
+#When the node detects a flood of the inbound onion connections (pow_flood_threshold
+#connections per minute) it lowers the queue rate step by step, so Tor raises the
+#suggested effort. The rate is restored when the flood is over. Current defense level
+#is reported in the node status.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"pow_queue_rate".to_string(),
+		"
+#introduction requests per second Tor serves from the PoW queue, without the flood
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"pow_queue_burst".to_string(),
+		"
+#introduction requests burst Tor serves from the PoW queue, without the flood
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"pow_flood_threshold".to_string(),
+		"
+#inbound onion connections per minute that are considered a flood
+"
+		.to_string(),
+	);
+
 	retval.insert(
 		"[server.p2p_config]".to_string(),
 		"#test miner wallet URL (burns if this doesn't exist)
@@ -330,7 +1412,7 @@ fn comments() -> HashMap<String, String> {
 #a list of preferred peers to connect to
 #peers_preferred = [\"192.168.0.1:3414\",\"192.168.0.2:3414\"]
 
-#how long a banned peer should stay banned
+#how long a banned peer should stay banned, libp2p gossip peers included
 #ban_window = 10800
 
 #maximum number of inbound peer connections
@@ -346,6 +1428,57 @@ fn comments() -> HashMap<String, String> {
 #amount of incoming connections temporarily allowed to exceed peer_max_inbound_count
 #peer_listener_buffer_count = 8
 
+#max number of the concurrent outbound dials, p2p and libp2p together. Dials over tor
+#are slow, too many of them at once overwhelm the SOCKS proxy
+#dial_max_concurrent = 8
+
+#number of the concurrent outbound dials right after the start. Preferred and recently
+#connected peers are dialed first, the limit grows by one every ramp up interval
+#dial_initial_concurrent = 2
+#dial_ramp_up_interval_secs = 10
+
+#user agent advertised to the peers in the handshake. It must start with \"MW/\", be no longer
+#than 64 characters and have printable ascii characters only
+#user_agent = \"MW/MWC\"
+
+#don't advertise the exact node version and the optional capabilities in the handshake,
+#so the node is harder to enumerate by the crawlers
+#minimize_fingerprint = false
+
+#debug only, record all the p2p messages of every peer connection into the capture files
+#in this directory. The node must be built with the msg_capture feature
+#capture_dir = \"/tmp/mwc_p2p_capture\"
+
+#pruned node only, fetch the block bodies requested by the API that the node doesn't store
+#any more from the peers. Fetched blocks are verified against our headers and kernels
+#fetch_pruned_blocks = false
+
+#address advertised to the peers instead of the bind address, for the nodes behind a NAT
+#with the port mapping. Peers take our IP from the connection, the port is what matters
+#advertised_address = \"203.0.113.10:3414\"
+
+#set to false if the node doesn't accept the connections, the peers will not share and
+#dial its address
+#advertise_address = true
+
+#detect the external address from the addresses that the connecting peers dialed. The
+#address reported by the most peers is advertised if advertised_address is not set
+#detect_external_address = true
+
+#data, in MB, a single peer can feed to the header, block and TxHashSet sync stages before
+#the other peers are preferred as the sync sources. Limits the damage of a malicious data
+#source and rotates the sources. 0 is no cap
+#sync_headers_cap_mb = 100
+#sync_blocks_cap_mb = 1000
+#sync_txhashset_cap_mb = 4000
+
+#peers that trickle the messages (slow-loris) are dropped. A message read longer than
+#slow_read_secs with the average socket read below slow_read_min_avg_bytes, or without
+#any data for max_stalled_read_secs in the middle of the message drops the peer. 0 disables
+#slow_read_secs = 30
+#slow_read_min_avg_bytes = 64
+#max_stalled_read_secs = 60
+
 # 15 = Bit flags for FULL_NODE
 #This structure needs to be changed internally, to make it more configurable
 
@@ -406,6 +1539,51 @@ fn comments() -> HashMap<String, String> {
 		.to_string(),
 	);
 
+	retval.insert(
+		"mineable_selection".to_string(),
+		"
+#order of the transactions selected to build a block. Can be:
+#\"Greedy\" - highest fee rate first, equal fee rates in the pool insertion order
+#\"Deterministic\" - highest fee rate first, equal fee rates ordered by the kernel excess,
+#nodes with the same txpool build identical block templates
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"recent_kernel_window".to_string(),
+		"
+#number of the recent blocks whose kernels are indexed. Transactions re-announcing a kernel
+#from these blocks, or with all the kernels already in the pool, are rejected before the
+#validation. 0 - disabled
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.pool_config.relay_policy]".to_string(),
+		"
+#local rules for the txs the node keeps in its pool and relays. They are not consensus,
+#the rejected txs are valid for the other nodes. Rules that are not set are not applied.
+#min fee per unit of the tx weight, accept_fee_base applies to the same weight
+#min_fee_rate = 2000000
+#max block weight of the tx
+#max_tx_weight = 2000
+#max number of the outputs of the tx
+#max_outputs = 50
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"reject_kernel_features".to_string(),
+		"
+#txs with a kernel of these features are rejected. Can be:
+#\"Plain\", \"HeightLocked\", \"NoRecentDuplicate\"
+"
+		.to_string(),
+	);
+
 	retval.insert(
 		"[server.stratum_mining_config]".to_string(),
 		"
@@ -537,6 +1715,31 @@ fn comments() -> HashMap<String, String> {
 			.to_string(),
 	);
 
+	retval.insert(
+		"share_audit".to_string(),
+		"
+#Record the jobs sent to the workers and the submitted shares with their validation results.
+#The log is queried with the owner API get_stratum_audit by the height, job id and worker login.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"share_audit_retention_days".to_string(),
+		"
+#Stratum log records older than that are pruned
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"share_audit_max_entries".to_string(),
+		"
+#Max number of the stratum log records, jobs and shares together. The oldest are pruned.
+"
+		.to_string(),
+	);
+
 	retval.insert(
 		"[logging]".to_string(),
 		"
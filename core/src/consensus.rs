@@ -199,6 +199,40 @@ pub fn valid_header_version(height: u64, version: HeaderVersion) -> bool {
 	}
 }
 
+/// Hard fork schedule entry. Header version is expected starting from the height
+/// until the next entry of the schedule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HardFork {
+	/// Activation height
+	pub height: u64,
+	/// Expected header version
+	pub version: HeaderVersion,
+}
+
+/// Hard fork schedule for the current chain type, ordered by height.
+/// Must match header_version and valid_header_version.
+pub fn hard_fork_schedule() -> Vec<HardFork> {
+	let chain_type = global::get_chain_type();
+	let heights = match chain_type {
+		global::ChainTypes::Mainnet | global::ChainTypes::Floonet => {
+			vec![0, get_c31_hard_fork_block_height()]
+		}
+		global::ChainTypes::AutomatedTesting | global::ChainTypes::UserTesting => vec![
+			0,
+			TESTING_FIRST_HARD_FORK,
+			TESTING_SECOND_HARD_FORK,
+			TESTING_THIRD_HARD_FORK,
+		],
+	};
+	heights
+		.into_iter()
+		.map(|height| HardFork {
+			height,
+			version: header_version(height),
+		})
+		.collect()
+}
+
 /// Number of blocks used to calculate difficulty adjustments
 pub const DIFFICULTY_ADJUST_WINDOW: u64 = HOUR_HEIGHT;
 
@@ -343,7 +377,7 @@ where
 
 	// Get the timestamp delta across the window
 	let ts_delta: u64 =
-		diff_data[DIFFICULTY_ADJUST_WINDOW as usize].timestamp - diff_data[0].timestamp;
+		diff_data[global::difficulty_adjust_window() as usize].timestamp - diff_data[0].timestamp;
 
 	// Get the difficulty sum of the last DIFFICULTY_ADJUST_WINDOW elements
 	let diff_sum: u64 = diff_data
@@ -353,13 +387,14 @@ where
 		.sum();
 
 	// adjust time delta toward goal subject to dampening and clamping
+	let block_time_window = global::block_time_window();
 	let adj_ts = clamp(
-		damp(ts_delta, BLOCK_TIME_WINDOW, DIFFICULTY_DAMP_FACTOR),
-		BLOCK_TIME_WINDOW,
+		damp(ts_delta, block_time_window, DIFFICULTY_DAMP_FACTOR),
+		block_time_window,
 		CLAMP_FACTOR,
 	);
 	// minimum difficulty avoids getting stuck due to dampening
-	let difficulty = max(MIN_DIFFICULTY, diff_sum * BLOCK_TIME_SEC / adj_ts);
+	let difficulty = max(MIN_DIFFICULTY, diff_sum * global::block_time_sec() / adj_ts);
 
 	HeaderInfo::from_diff_scaling(Difficulty::from_num(difficulty), sec_pow_scaling)
 }
@@ -376,7 +411,7 @@ pub fn secondary_pow_scaling(height: u64, diff_data: &[HeaderInfo]) -> u32 {
 
 	// compute ideal 2nd_pow_fraction in pct and across window
 	let target_pct = secondary_pow_ratio(height);
-	let target_count = DIFFICULTY_ADJUST_WINDOW * target_pct;
+	let target_count = global::difficulty_adjust_window() * target_pct;
 
 	// Get the secondary count across the window, adjusting count toward goal
 	// subject to dampening and clamping.
@@ -685,6 +720,30 @@ mod test {
 		assert_eq!(graph_weight(4 * YEAR_HEIGHT, 33), 1);
 	}
 
+	#[test]
+	fn test_hard_fork_schedule() {
+		for chain_type in &[
+			global::ChainTypes::Mainnet,
+			global::ChainTypes::Floonet,
+			global::ChainTypes::AutomatedTesting,
+		] {
+			global::set_local_chain_type(*chain_type);
+			let schedule = hard_fork_schedule();
+			assert_eq!(schedule[0].height, 0);
+			for (i, fork) in schedule.iter().enumerate() {
+				assert!(valid_header_version(fork.height, fork.version));
+				if i > 0 {
+					let prev = &schedule[i - 1];
+					assert!(fork.height > prev.height);
+					assert!(fork.version > prev.version);
+					assert_eq!(header_version(fork.height - 1), prev.version);
+				}
+			}
+		}
+		global::set_local_chain_type(global::ChainTypes::Mainnet);
+		assert_eq!(hard_fork_schedule()[1].height, 202_500);
+	}
+
 	// MWC test the epoch dates
 	#[test]
 	fn test_epoch_dates() {
@@ -460,8 +460,7 @@ pub struct UntrustedBlockHeader(BlockHeader);
 impl Readable for UntrustedBlockHeader {
 	fn read<R: Reader>(reader: &mut R) -> Result<UntrustedBlockHeader, ser::Error> {
 		let header = read_block_header(reader)?;
-		if header.timestamp
-			> Utc::now() + Duration::seconds(12 * (consensus::BLOCK_TIME_SEC as i64))
+		if header.timestamp > Utc::now() + Duration::seconds(12 * (global::block_time_sec() as i64))
 		{
 			// refuse blocks more than 12 blocks intervals in future (as in bitcoin)
 			// TODO add warning in p2p code if local time is too different from peers
@@ -777,7 +776,15 @@ impl Block {
 		verifier: Arc<RwLock<dyn VerifierCache>>,
 	) -> Result<Commitment, Error> {
 		self.body.validate(Weighting::AsBlock, verifier)?;
+		self.validate_consensus_rules(prev_kernel_offset)
+	}
 
+	/// Validate the block consensus rules (kernel lock heights, coinbase, kernel sums).
+	/// Rangeproofs and kernel signatures are expected to be verified separately.
+	pub fn validate_consensus_rules(
+		&self,
+		prev_kernel_offset: &BlindingFactor,
+	) -> Result<Commitment, Error> {
 		self.verify_kernel_lock_heights()?;
 		self.verify_nrd_kernels_for_header_version()?;
 		self.verify_coinbase()?;
@@ -1119,8 +1119,13 @@ impl TransactionBody {
 		_verifier: Arc<RwLock<dyn VerifierCache>>,
 	) -> Result<(), Error> {
 		self.validate_read(weighting)?;
+		self.verify_rangeproofs()?;
+		self.verify_kernel_signatures()?;
+		Ok(())
+	}
 
-		// Now batch verify all those unverified rangeproofs
+	/// Batch verify the rangeproofs of all outputs.
+	pub fn verify_rangeproofs(&self) -> Result<(), Error> {
 		if !self.outputs.is_empty() {
 			let mut commits = vec![];
 			let mut proofs = vec![];
@@ -1130,8 +1135,11 @@ impl TransactionBody {
 			}
 			Output::batch_verify_proofs(&commits, &proofs)?;
 		}
+		Ok(())
+	}
 
-		// Verify the unverified tx kernels.
+	/// Batch verify the signatures of all kernels.
+	pub fn verify_kernel_signatures(&self) -> Result<(), Error> {
 		TxKernel::batch_sig_verify(&self.kernels)?;
 		Ok(())
 	}
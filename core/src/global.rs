@@ -171,6 +171,91 @@ lazy_static! {
 	/// Running flag for MWC node.
 	pub static ref SERVER_RUNNING: Arc<AtomicBool> =
 			Arc::new(AtomicBool::new(true));
+
+	/// Global consensus parameters overlay for the testing networks.
+	/// Must be initialized once on node startup, ignored on floonet and mainnet.
+	pub static ref GLOBAL_CONSENSUS_OVERLAY: OneTime<ConsensusOverlay> = OneTime::new();
+}
+
+/// Consensus parameters that the usernet and the automated tests can override, so the
+/// private networks and the integration tests can run with fast blocks. Parameters that
+/// are not set keep the values of the chain type. The overlay is ignored on floonet
+/// and mainnet.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConsensusOverlay {
+	/// Target block time in seconds
+	#[serde(default)]
+	pub block_time_sec: Option<u64>,
+	/// Number of the blocks in the difficulty adjustment window
+	#[serde(default)]
+	pub difficulty_adjust_window: Option<u64>,
+	/// Number of the blocks before the coinbase can be spent
+	#[serde(default)]
+	pub coinbase_maturity: Option<u64>,
+	/// Max block weight
+	#[serde(default)]
+	pub max_block_weight: Option<u64>,
+}
+
+impl ConsensusOverlay {
+	/// Overlay that doesn't override anything
+	pub fn new() -> ConsensusOverlay {
+		ConsensusOverlay::default()
+	}
+
+	/// Override the target block time
+	pub fn block_time_sec(mut self, block_time_sec: u64) -> ConsensusOverlay {
+		self.block_time_sec = Some(block_time_sec);
+		self
+	}
+
+	/// Override the difficulty adjustment window
+	pub fn difficulty_adjust_window(mut self, window: u64) -> ConsensusOverlay {
+		self.difficulty_adjust_window = Some(window);
+		self
+	}
+
+	/// Override the coinbase maturity
+	pub fn coinbase_maturity(mut self, maturity: u64) -> ConsensusOverlay {
+		self.coinbase_maturity = Some(maturity);
+		self
+	}
+
+	/// Override the max block weight
+	pub fn max_block_weight(mut self, weight: u64) -> ConsensusOverlay {
+		self.max_block_weight = Some(weight);
+		self
+	}
+
+	/// True if nothing is overridden
+	pub fn is_empty(&self) -> bool {
+		*self == ConsensusOverlay::default()
+	}
+
+	/// Check that the overridden values are usable
+	pub fn validate(&self) -> Result<(), String> {
+		if self.block_time_sec == Some(0) {
+			return Err("block_time_sec must be positive".to_string());
+		}
+		if let Some(window) = self.difficulty_adjust_window {
+			if window < 2 {
+				return Err(format!(
+					"difficulty_adjust_window must be at least 2, get {}",
+					window
+				));
+			}
+		}
+		if let Some(weight) = self.max_block_weight {
+			let coinbase_weight = BLOCK_OUTPUT_WEIGHT + BLOCK_KERNEL_WEIGHT;
+			if weight <= coinbase_weight {
+				return Err(format!(
+					"max_block_weight must be greater than the coinbase weight {}, get {}",
+					coinbase_weight, weight
+				));
+			}
+		}
+		Ok(())
+	}
 }
 
 thread_local! {
@@ -179,6 +264,9 @@ thread_local! {
 
 	/// Local feature flag for NRD kernel support.
 	pub static NRD_FEATURE_ENABLED: Cell<Option<bool>> = Cell::new(None);
+
+	/// Local consensus parameters overlay, for testing.
+	pub static CONSENSUS_OVERLAY: Cell<Option<ConsensusOverlay>> = Cell::new(None);
 }
 
 /// Set the chain type on a per-thread basis via thread_local storage.
@@ -214,6 +302,38 @@ pub fn init_global_nrd_enabled(enabled: bool) {
 	GLOBAL_NRD_FEATURE_ENABLED.init(enabled)
 }
 
+/// One time initialization of the global consensus parameters overlay.
+/// Will panic if we attempt to re-initialize this (via OneTime).
+pub fn init_global_consensus_overlay(overlay: ConsensusOverlay) {
+	GLOBAL_CONSENSUS_OVERLAY.init(overlay)
+}
+
+/// Set the consensus parameters overlay on a per-thread basis, for testing.
+pub fn set_local_consensus_overlay(overlay: ConsensusOverlay) {
+	CONSENSUS_OVERLAY.with(|o| o.set(Some(overlay)))
+}
+
+/// Consensus parameters overlay of the current chain type. Look at thread local config
+/// first. If not set fallback to global config. Empty for floonet and mainnet.
+pub fn get_consensus_overlay() -> ConsensusOverlay {
+	match get_chain_type() {
+		ChainTypes::Floonet | ChainTypes::Mainnet => return ConsensusOverlay::default(),
+		_ => {}
+	}
+	CONSENSUS_OVERLAY.with(|o| match o.get() {
+		None => {
+			if GLOBAL_CONSENSUS_OVERLAY.is_init() {
+				let overlay = GLOBAL_CONSENSUS_OVERLAY.borrow();
+				o.set(Some(overlay));
+				overlay
+			} else {
+				ConsensusOverlay::default()
+			}
+		}
+		Some(overlay) => overlay,
+	})
+}
+
 /// Explicitly enable the NRD global feature flag.
 pub fn set_local_nrd_enabled(enabled: bool) {
 	NRD_FEATURE_ENABLED.with(|flag| flag.set(Some(enabled)))
@@ -293,6 +413,9 @@ pub fn proofsize() -> usize {
 
 /// Coinbase maturity for coinbases to be spent
 pub fn coinbase_maturity() -> u64 {
+	if let Some(maturity) = get_consensus_overlay().coinbase_maturity {
+		return maturity;
+	}
 	match get_chain_type() {
 		ChainTypes::AutomatedTesting => AUTOMATED_TESTING_COINBASE_MATURITY,
 		ChainTypes::UserTesting => USER_TESTING_COINBASE_MATURITY,
@@ -321,6 +444,9 @@ pub fn initial_graph_weight() -> u32 {
 
 /// Maximum allowed block weight.
 pub fn max_block_weight() -> u64 {
+	if let Some(weight) = get_consensus_overlay().max_block_weight {
+		return weight;
+	}
 	match get_chain_type() {
 		ChainTypes::AutomatedTesting => TESTING_MAX_BLOCK_WEIGHT,
 		ChainTypes::UserTesting => TESTING_MAX_BLOCK_WEIGHT,
@@ -335,6 +461,25 @@ pub fn max_tx_weight() -> u64 {
 	max_block_weight().saturating_sub(coinbase_weight) as u64
 }
 
+/// Target block time in seconds
+pub fn block_time_sec() -> u64 {
+	get_consensus_overlay()
+		.block_time_sec
+		.unwrap_or(BLOCK_TIME_SEC)
+}
+
+/// Number of the blocks in the difficulty adjustment window
+pub fn difficulty_adjust_window() -> u64 {
+	get_consensus_overlay()
+		.difficulty_adjust_window
+		.unwrap_or(DIFFICULTY_ADJUST_WINDOW)
+}
+
+/// Timespan of the difficulty adjustment window in seconds
+pub fn block_time_window() -> u64 {
+	difficulty_adjust_window() * block_time_sec()
+}
+
 /// Horizon at which we can cut-through and do full local pruning
 pub fn cut_through_horizon() -> u32 {
 	match get_chain_type() {
@@ -420,7 +565,7 @@ where
 	T: IntoIterator<Item = HeaderInfo>,
 {
 	// Convert iterator to vector, so we can append to it if necessary
-	let needed_block_count = DIFFICULTY_ADJUST_WINDOW as usize + 1;
+	let needed_block_count = difficulty_adjust_window() as usize + 1;
 	let mut last_n: Vec<HeaderInfo> = cursor.into_iter().take(needed_block_count).collect();
 
 	// Only needed just after blockchain launch... basically ensures there's
@@ -431,7 +576,7 @@ where
 		let last_ts_delta = if n > 1 {
 			last_n[0].timestamp - last_n[1].timestamp
 		} else {
-			BLOCK_TIME_SEC
+			block_time_sec()
 		};
 		let last_diff = last_n[0].difficulty;
 
@@ -0,0 +1,114 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Output ownership proofs. The owner of the output `commit = r*G + v*H` proves the
+//! knowledge of the blinding factor `r` by signing a challenge with it. The amount `v`
+//! is revealed, so the proofs can be used for the proof of reserves.
+
+use crate::libtx::aggsig;
+use crate::libtx::error::{Error, ErrorKind};
+use blake2::blake2b::blake2b;
+use keychain::{Identifier, Keychain, SwitchCommitmentType};
+use util::secp::key::PublicKey;
+use util::secp::pedersen::Commitment;
+use util::secp::{Message, Secp256k1, Signature};
+
+/// Domain separator, so the ownership proof signature can't be reused anywhere else
+const OWNERSHIP_PROOF_DOMAIN: &[u8] = b"MWC_OUTPUT_OWNERSHIP_PROOF";
+
+/// Message that is signed by the ownership proof. It commits to the challenge and
+/// to the output, so the signature can't be replayed for another challenge or output.
+pub fn ownership_proof_msg(challenge: &str, commit: &Commitment) -> Result<Message, Error> {
+	let mut data = OWNERSHIP_PROOF_DOMAIN.to_vec();
+	data.extend_from_slice(&commit.0);
+	data.extend_from_slice(challenge.as_bytes());
+	let hash = blake2b(32, &[], &data);
+	Ok(Message::from_slice(hash.as_bytes())?)
+}
+
+/// Public key of the output blinding factor: `commit - v*H = r*G`
+fn ownership_pubkey(
+	secp: &Secp256k1,
+	commit: &Commitment,
+	amount: u64,
+) -> Result<PublicKey, Error> {
+	let value_commit = secp.commit_value(amount)?;
+	let excess = Secp256k1::commit_sum(vec![commit.clone()], vec![value_commit])?;
+	Ok(excess.to_pubkey()?)
+}
+
+/// Create the ownership proof for the output owned by the keychain. Used by the wallets.
+pub fn create_ownership_proof<K>(
+	keychain: &K,
+	amount: u64,
+	key_id: &Identifier,
+	challenge: &str,
+) -> Result<(Commitment, Signature), Error>
+where
+	K: Keychain,
+{
+	let switch = SwitchCommitmentType::Regular;
+	let commit = keychain.commit(amount, key_id, switch)?;
+	let skey = keychain.derive_key(amount, key_id, switch)?;
+	let secp = keychain.secp();
+	let pubkey = PublicKey::from_secret_key(secp, &skey)?;
+	let msg = ownership_proof_msg(challenge, &commit)?;
+	let sig = aggsig::sign_single(secp, &msg, &skey, None, Some(&pubkey))?;
+	Ok((commit, sig))
+}
+
+/// Verify the ownership proof of the output with the revealed amount.
+/// Returns `Ok(())` if the signature is valid, or a Signature
+/// [ErrorKind](../enum.ErrorKind.html) otherwise
+pub fn verify_ownership_proof(
+	secp: &Secp256k1,
+	commit: &Commitment,
+	amount: u64,
+	challenge: &str,
+	sig: &Signature,
+) -> Result<(), Error> {
+	let pubkey = ownership_pubkey(secp, commit, amount)?;
+	let msg = ownership_proof_msg(challenge, commit)?;
+	if !aggsig::verify_single(secp, sig, &msg, None, &pubkey, Some(&pubkey), false) {
+		return Err(ErrorKind::Signature("Ownership proof validation error".to_string()).into());
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use keychain::ExtKeychain;
+
+	#[test]
+	fn ownership_proof() {
+		let keychain = ExtKeychain::from_random_seed(false).unwrap();
+		let secp = keychain.secp();
+		let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+		let amount = 1_234_000_000;
+		let challenge = "exchange reserves 2020-10-01";
+
+		let (commit, sig) = create_ownership_proof(&keychain, amount, &key_id, challenge).unwrap();
+		assert!(verify_ownership_proof(secp, &commit, amount, challenge, &sig).is_ok());
+
+		// Wrong amount, challenge or output must fail
+		assert!(verify_ownership_proof(secp, &commit, amount + 1, challenge, &sig).is_err());
+		assert!(verify_ownership_proof(secp, &commit, amount, "other challenge", &sig).is_err());
+		let other_id = ExtKeychain::derive_key_id(1, 2, 0, 0, 0);
+		let other_commit = keychain
+			.commit(amount, &other_id, SwitchCommitmentType::Regular)
+			.unwrap();
+		assert!(verify_ownership_proof(secp, &other_commit, amount, challenge, &sig).is_err());
+	}
+}
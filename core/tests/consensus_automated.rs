@@ -123,6 +123,47 @@ fn next_target_adjustment() {
 	);
 }
 
+/// Checks the difficulty adjustment with the overridden block time and window
+#[test]
+fn consensus_overlay_adjustment() {
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let overlay = global::ConsensusOverlay::new()
+		.block_time_sec(10)
+		.difficulty_adjust_window(10)
+		.coinbase_maturity(2)
+		.max_block_weight(1000);
+	assert!(overlay.validate().is_ok());
+	assert!(global::ConsensusOverlay::new()
+		.difficulty_adjust_window(1)
+		.validate()
+		.is_err());
+	global::set_local_consensus_overlay(overlay);
+
+	assert_eq!(global::block_time_sec(), 10);
+	assert_eq!(global::difficulty_adjust_window(), 10);
+	assert_eq!(global::coinbase_maturity(), 2);
+	assert_eq!(global::max_block_weight(), 1000);
+
+	// 10 seconds blocks over 10 blocks window keep the difficulty
+	let mut hi =
+		HeaderInfo::from_diff_scaling(Difficulty::from_num(10000), AR_SCALE_DAMP_FACTOR as u32);
+	hi.is_secondary = false;
+	assert_eq!(
+		next_difficulty(1, repeat(10, hi.clone(), 11, None)).difficulty,
+		Difficulty::from_num(10000)
+	);
+	// Default 60 seconds blocks are too slow now
+	assert!(
+		next_difficulty(1, repeat(BLOCK_TIME_SEC, hi.clone(), 11, None)).difficulty
+			< Difficulty::from_num(10000)
+	);
+
+	// Overlay is ignored on mainnet
+	global::set_local_chain_type(global::ChainTypes::Mainnet);
+	assert_eq!(global::block_time_sec(), BLOCK_TIME_SEC);
+	assert_eq!(global::difficulty_adjust_window(), DIFFICULTY_ADJUST_WINDOW);
+}
+
 // Builds an iterator for next difficulty calculation with the provided
 // constant time interval, difficulty and total length.
 fn repeat(interval: u64, diff: HeaderInfo, len: u64, cur_time: Option<u64>) -> Vec<HeaderInfo> {
@@ -0,0 +1,149 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! On demand retrieval of the block bodies the pruned node doesn't store any more.
+//! The API asks the peers for the block, the received block is handed over to the
+//! waiting request instead of the chain. Fetched blocks are cached for a short time,
+//! explorers tend to query the same block a few times in a row.
+
+use crate::chain;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::Block;
+use crate::peer::Peer;
+use crate::types::{Error, PeerAddr};
+use crate::util::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Number of the fetched blocks in the cache
+const FETCH_CACHE_SIZE: usize = 32;
+/// Fetched block is cached for that long
+const FETCH_CACHE_TTL: Duration = Duration::from_secs(600);
+/// Time to wait for the block from a single peer. Peers that pruned the block
+/// don't respond at all.
+const FETCH_PEER_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of the peers that are asked for the block, one by one
+pub const FETCH_MAX_PEERS: usize = 3;
+
+struct PendingFetch {
+	id: u64,
+	sender: mpsc::SyncSender<(Block, PeerAddr)>,
+}
+
+/// Result of the fetch attempt from a single peer
+pub enum FetchAttempt {
+	/// Peer sent the block that passed the verification
+	Verified(Block),
+	/// Peer sent the block that failed the verification
+	Invalid(PeerAddr, chain::Error),
+	/// Peer didn't send the block in time
+	NoResponse,
+}
+
+/// Tracks the pending block fetches and caches the fetched blocks
+pub struct BlockFetcher {
+	next_id: Mutex<u64>,
+	pending: Mutex<HashMap<Hash, Vec<PendingFetch>>>,
+	cache: Mutex<VecDeque<(Instant, Block)>>,
+}
+
+impl BlockFetcher {
+	/// New fetcher with the empty cache
+	pub fn new() -> BlockFetcher {
+		BlockFetcher {
+			next_id: Mutex::new(0),
+			pending: Mutex::new(HashMap::new()),
+			cache: Mutex::new(VecDeque::new()),
+		}
+	}
+
+	/// Fetched block from the cache
+	pub fn cached(&self, h: &Hash) -> Option<Block> {
+		let mut cache = self.cache.lock();
+		cache.retain(|(time, _)| time.elapsed() < FETCH_CACHE_TTL);
+		cache
+			.iter()
+			.find(|(_, b)| b.hash() == *h)
+			.map(|(_, b)| b.clone())
+	}
+
+	/// Hand over the received block to the waiting fetches. Returns true if the block
+	/// was requested by a fetch, such block must not be processed by the chain.
+	pub fn block_received(&self, b: &Block, peer_addr: &PeerAddr) -> bool {
+		let fetches = match self.pending.lock().remove(&b.hash()) {
+			Some(fetches) => fetches,
+			None => return false,
+		};
+		for fetch in fetches {
+			let _ = fetch.sender.try_send((b.clone(), peer_addr.clone()));
+		}
+		true
+	}
+
+	/// Request the block from a single peer and wait for it
+	pub fn fetch_from_peer<F>(&self, h: Hash, peer: &Peer, verify: &F) -> FetchAttempt
+	where
+		F: Fn(&Block) -> Result<(), chain::Error>,
+	{
+		let id = {
+			let mut next_id = self.next_id.lock();
+			*next_id += 1;
+			*next_id
+		};
+		let (sender, receiver) = mpsc::sync_channel(1);
+		self.pending
+			.lock()
+			.entry(h)
+			.or_insert_with(Vec::new)
+			.push(PendingFetch { id, sender });
+
+		let res = match peer.send_block_request(h, chain::Options::NONE) {
+			Ok(_) => match receiver.recv_timeout(FETCH_PEER_TIMEOUT) {
+				Ok((b, addr)) => match verify(&b) {
+					Ok(_) => FetchAttempt::Verified(b),
+					Err(e) => FetchAttempt::Invalid(addr, e),
+				},
+				Err(_) => FetchAttempt::NoResponse,
+			},
+			Err(e) => {
+				debug!(
+					"Unable to request block {} from {}, {}",
+					h, peer.info.addr, e
+				);
+				FetchAttempt::NoResponse
+			}
+		};
+
+		let mut pending = self.pending.lock();
+		if let Some(fetches) = pending.get_mut(&h) {
+			fetches.retain(|f| f.id != id);
+			if fetches.is_empty() {
+				pending.remove(&h);
+			}
+		}
+		res
+	}
+
+	/// Cache the fetched and verified block
+	pub fn add_to_cache(&self, b: Block) {
+		let mut cache = self.cache.lock();
+		let h = b.hash();
+		cache.retain(|(_, c)| c.hash() != h);
+		cache.push_back((Instant::now(), b));
+		while cache.len() > FETCH_CACHE_SIZE {
+			cache.pop_front();
+		}
+	}
+}
@@ -0,0 +1,286 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! p2p message capture and replay, a debug facility built with the `msg_capture` feature.
+//! Raw inbound and outbound messages of every peer connection are recorded into a capture
+//! file per connection. The replay harness feeds the inbound messages of a capture back
+//! through the protocol handler, so the hard to reproduce sync bugs become the tests.
+//!
+//! Capture file format: 8 bytes magic, protocol version (u32), then the records:
+//! direction (u8), unix time in millis (i64), message header, body length (u64), body.
+//! Attachments (txhashset archive) are not captured.
+
+use crate::conn::{Message, MessageHandler, Tracker};
+use crate::core::ser::{self, ProtocolVersion};
+use crate::msg::{MsgHeader, MsgHeaderWrapper};
+use crate::protocol::Protocol;
+use crate::serv::Server;
+use crate::types::{Error, NetAdapter, PeerAddr, PeerInfo};
+use crate::util::{Mutex, RwLock};
+use chrono::Utc;
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Capture file magic
+const CAPTURE_MAGIC: &[u8; 8] = b"MWCCAP01";
+
+lazy_static! {
+	// Directory for the capture files, None - capture is disabled
+	static ref CAPTURE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Setup the directory for the capture files. None - capture is disabled.
+pub fn init_capture_dir(dir: Option<String>) {
+	let dir = dir.map(PathBuf::from);
+	if let Some(dir) = &dir {
+		if let Err(e) = fs::create_dir_all(dir) {
+			error!("Unable to create the p2p capture dir {:?}, {}", dir, e);
+			return;
+		}
+		warn!(
+			"p2p message capture is enabled, all the peer messages are recorded into {:?}",
+			dir
+		);
+	}
+	*CAPTURE_DIR.write() = dir;
+}
+
+/// Direction of the captured message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureDirection {
+	/// Message received from the peer
+	Inbound = 0,
+	/// Message sent to the peer
+	Outbound = 1,
+}
+
+/// Message read from the capture file
+#[derive(Debug, Clone)]
+pub struct CapturedMsg {
+	/// Direction of the message
+	pub direction: CaptureDirection,
+	/// Unix time of the capture, in millis
+	pub timestamp_ms: i64,
+	/// Serialized message header
+	pub header: Vec<u8>,
+	/// Message body
+	pub body: Vec<u8>,
+}
+
+/// Capture file of a single peer connection, shared by the reader and the writer threads
+pub struct PeerCapture {
+	file: Mutex<BufWriter<File>>,
+	version: ProtocolVersion,
+}
+
+impl PeerCapture {
+	/// Open the capture file for the peer connection in the capture dir.
+	/// None if the capture is disabled or the file can't be created.
+	pub fn open(peer_addr: &PeerAddr, version: ProtocolVersion) -> Option<Arc<PeerCapture>> {
+		let dir = CAPTURE_DIR.read().clone()?;
+		let name: String = peer_addr
+			.as_key()
+			.chars()
+			.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+			.collect();
+		let path = dir.join(format!("{}-{}.cap", name, Utc::now().timestamp_millis()));
+		match PeerCapture::create(&path, version) {
+			Ok(capture) => Some(Arc::new(capture)),
+			Err(e) => {
+				error!("Unable to create the p2p capture file {:?}, {}", path, e);
+				None
+			}
+		}
+	}
+
+	/// Create the capture file at the path
+	pub fn create(path: &Path, version: ProtocolVersion) -> Result<PeerCapture, Error> {
+		let mut file = BufWriter::new(File::create(path)?);
+		file.write_all(CAPTURE_MAGIC)?;
+		file.write_all(&version.value().to_be_bytes())?;
+		Ok(PeerCapture {
+			file: Mutex::new(file),
+			version,
+		})
+	}
+
+	/// Append the message to the capture. Failures are logged, the connection
+	/// must not be affected by the capture.
+	pub fn record(&self, direction: CaptureDirection, header: &MsgHeader, body: &[u8]) {
+		if let Err(e) = self.write_record(direction, header, body) {
+			warn!("Unable to write the p2p capture record, {}", e);
+		}
+	}
+
+	fn write_record(
+		&self,
+		direction: CaptureDirection,
+		header: &MsgHeader,
+		body: &[u8],
+	) -> Result<(), Error> {
+		let header = ser::ser_vec(header, self.version)?;
+		let mut file = self.file.lock();
+		file.write_all(&[direction as u8])?;
+		file.write_all(&Utc::now().timestamp_millis().to_be_bytes())?;
+		file.write_all(&header)?;
+		file.write_all(&(body.len() as u64).to_be_bytes())?;
+		file.write_all(body)?;
+		file.flush()?;
+		Ok(())
+	}
+}
+
+/// Reader that keeps a copy of the message body read through it, so the inbound
+/// messages can be captured while the protocol handler consumes them.
+pub struct TeeReader<'a, R: Read> {
+	inner: &'a mut R,
+	captured: Option<Vec<u8>>,
+	limit: usize,
+}
+
+impl<'a, R: Read> TeeReader<'a, R> {
+	/// Wrap the stream, up to `limit` bytes are copied if `capture` is true
+	pub fn new(inner: &'a mut R, capture: bool, limit: usize) -> TeeReader<'a, R> {
+		TeeReader {
+			inner,
+			captured: if capture { Some(vec![]) } else { None },
+			limit,
+		}
+	}
+
+	/// Bytes that were read through the reader
+	pub fn captured(&self) -> &[u8] {
+		self.captured.as_ref().map(|c| &c[..]).unwrap_or(&[])
+	}
+}
+
+impl<'a, R: Read> Read for TeeReader<'a, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		if let Some(captured) = self.captured.as_mut() {
+			let len = cmp::min(n, self.limit.saturating_sub(captured.len()));
+			captured.extend_from_slice(&buf[..len]);
+		}
+		Ok(n)
+	}
+}
+
+/// Read the capture file. Returns the protocol version of the connection and the messages.
+pub fn read_capture(path: &Path) -> Result<(ProtocolVersion, Vec<CapturedMsg>), Error> {
+	let mut file = BufReader::new(File::open(path)?);
+	let mut magic = [0u8; 8];
+	file.read_exact(&mut magic)?;
+	if &magic != CAPTURE_MAGIC {
+		return Err(Error::Internal(format!("{:?} is not a p2p capture", path)));
+	}
+	let mut buf4 = [0u8; 4];
+	file.read_exact(&mut buf4)?;
+	let version = ProtocolVersion(u32::from_be_bytes(buf4));
+
+	let mut msgs = vec![];
+	loop {
+		let mut direction = [0u8; 1];
+		match file.read_exact(&mut direction) {
+			Ok(()) => {}
+			Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+			Err(e) => return Err(e.into()),
+		}
+		let direction = match direction[0] {
+			0 => CaptureDirection::Inbound,
+			1 => CaptureDirection::Outbound,
+			d => {
+				return Err(Error::Internal(format!(
+					"Invalid capture record direction {}",
+					d
+				)))
+			}
+		};
+		let mut buf8 = [0u8; 8];
+		file.read_exact(&mut buf8)?;
+		let timestamp_ms = i64::from_be_bytes(buf8);
+		let mut header = vec![0u8; MsgHeader::LEN];
+		file.read_exact(&mut header)?;
+		file.read_exact(&mut buf8)?;
+		let mut body = vec![0u8; u64::from_be_bytes(buf8) as usize];
+		file.read_exact(&mut body)?;
+		msgs.push(CapturedMsg {
+			direction,
+			timestamp_ms,
+			header,
+			body,
+		});
+	}
+	Ok((version, msgs))
+}
+
+/// Result of the capture replay
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayStats {
+	/// Number of the inbound messages fed to the protocol handler
+	pub replayed: usize,
+	/// Number of the responses produced by the handler
+	pub responses: usize,
+	/// Inbound messages of the unknown type, skipped
+	pub unknown: usize,
+	/// Handler errors, by the index of the message in the capture
+	pub errors: Vec<(usize, String)>,
+}
+
+/// Feed the inbound messages of the capture through the protocol handler, as if they
+/// were received from the peer. The adapter gets all the consequences of the messages.
+pub fn replay_capture(
+	path: &Path,
+	server: &Server,
+	adapter: Arc<dyn NetAdapter>,
+	peer_info: PeerInfo,
+) -> Result<ReplayStats, Error> {
+	let (version, msgs) = read_capture(path)?;
+	let mut handler = Protocol::new(
+		adapter,
+		peer_info,
+		Arc::new(AtomicBool::new(false)),
+		0,
+		server.clone(),
+	);
+	let stopped = Arc::new(AtomicBool::new(false));
+	let tracker = Arc::new(Tracker::new());
+
+	let mut stats = ReplayStats::default();
+	for (i, captured) in msgs.iter().enumerate() {
+		if captured.direction != CaptureDirection::Inbound {
+			continue;
+		}
+		let header: MsgHeaderWrapper = ser::deserialize(&mut &captured.header[..], version)?;
+		let header = match header {
+			MsgHeaderWrapper::Known(header) => header,
+			MsgHeaderWrapper::Unknown(_, _) => {
+				stats.unknown += 1;
+				continue;
+			}
+		};
+		let mut body = &captured.body[..];
+		let msg = Message::from_header(header, &mut body, version);
+		stats.replayed += 1;
+		match handler.consume(msg, stopped.clone(), tracker.clone()) {
+			Ok(Some(_)) => stats.responses += 1,
+			Ok(None) => {}
+			Err(e) => stats.errors.push((i, format!("{:?}", e))),
+		}
+	}
+	Ok(stats)
+}
@@ -0,0 +1,574 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Peer protocol conformance checks. The suite connects to a target node as a
+//! regular peer and exercises the handshake, the header/block/tx request
+//! matrix, the malformed message handling and the ban behavior. Operators and
+//! alternative implementations can use it to validate the compatibility with
+//! the reference node before deploying.
+
+use crate::conn::Tracker;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::{Block, CompactBlock, UntrustedBlock, UntrustedCompactBlock};
+use crate::core::pow::Difficulty;
+use crate::core::ser::{self, ProtocolVersion, Readable, Writeable};
+use crate::msg::{
+	self, GetPeerAddrs, Hand, Locator, MsgHeader, MsgHeaderWrapper, PeerAddrs, Ping, Pong, Shake,
+	Type,
+};
+use crate::types::{Capabilities, Error, PeerAddr, MAX_BLOCK_HEADERS, MAX_LOCATORS};
+use rand::{thread_rng, Rng};
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// User agent the conformance suite connects with
+pub const CONFORMANCE_USER_AGENT: &str = concat!("MW/MWC conformance ", env!("CARGO_PKG_VERSION"));
+
+/// Message type byte that is not assigned to any known message
+const UNKNOWN_MSG_TYPE: u8 = 0xfe;
+
+/// How long we wait for a reply that the target is allowed to omit
+/// (block or transaction it doesn't have).
+const OPTIONAL_REPLY_WAIT: Duration = Duration::from_millis(2_000);
+
+/// Result of a single conformance check
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckStatus {
+	/// The target behaved as expected
+	Pass,
+	/// The target didn't behave as expected, with the reason
+	Fail(String),
+	/// The check could not be run against this target, with the reason
+	Skipped(String),
+}
+
+/// Named conformance check result
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+	/// Check name
+	pub name: String,
+	/// Check outcome
+	pub status: CheckStatus,
+}
+
+impl fmt::Display for CheckResult {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.status {
+			CheckStatus::Pass => write!(f, "PASS  {}", self.name),
+			CheckStatus::Fail(reason) => write!(f, "FAIL  {}: {}", self.name, reason),
+			CheckStatus::Skipped(reason) => write!(f, "SKIP  {}: {}", self.name, reason),
+		}
+	}
+}
+
+/// Results of the whole suite run
+#[derive(Debug, Clone)]
+pub struct ConformanceReport {
+	/// Target node address
+	pub target: SocketAddr,
+	/// Results in the order the checks were run
+	pub results: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+	/// True if none of the checks failed
+	pub fn passed(&self) -> bool {
+		self.failures().is_empty()
+	}
+
+	/// Failed checks
+	pub fn failures(&self) -> Vec<&CheckResult> {
+		self.results
+			.iter()
+			.filter(|r| match r.status {
+				CheckStatus::Fail(_) => true,
+				_ => false,
+			})
+			.collect()
+	}
+
+	/// Result of the check with this name
+	pub fn get(&self, name: &str) -> Option<&CheckResult> {
+		self.results.iter().find(|r| r.name == name)
+	}
+}
+
+/// Peer protocol conformance suite against a single target node
+#[derive(Debug, Clone)]
+pub struct ConformanceSuite {
+	/// Address of the node under test
+	pub target: SocketAddr,
+	/// Genesis hash of the chain the target is expected to run
+	pub genesis: Hash,
+	/// Timeout for connecting and for every expected reply
+	pub timeout: Duration,
+	/// Run the ban checks. They get the host we are running from banned by
+	/// the target, so they are opt-in and always run last.
+	pub ban_checks: bool,
+}
+
+type CheckFn = fn(&ConformanceSuite) -> Result<CheckStatus, String>;
+
+impl ConformanceSuite {
+	/// New suite with the default timeout and without the ban checks
+	pub fn new(target: SocketAddr, genesis: Hash) -> ConformanceSuite {
+		ConformanceSuite {
+			target,
+			genesis,
+			timeout: Duration::from_secs(10),
+			ban_checks: false,
+		}
+	}
+
+	/// Run all checks, every check uses its own connection to the target
+	pub fn run(&self) -> ConformanceReport {
+		let mut checks: Vec<(&str, CheckFn)> = vec![
+			("handshake", check_handshake),
+			("ping_pong", check_ping_pong),
+			("get_peer_addrs", check_get_peer_addrs),
+			("get_headers", check_get_headers),
+			("get_block", check_get_block),
+			("get_compact_block", check_get_compact_block),
+			("get_unknown_transaction", check_get_unknown_transaction),
+			("unknown_message_type", check_unknown_message_type),
+			("bad_magic", check_bad_magic),
+			("oversized_message", check_oversized_message),
+			("too_many_locators", check_too_many_locators),
+			("truncated_body", check_truncated_body),
+		];
+		if self.ban_checks {
+			checks.push(("ban_on_bad_handshake", check_ban_on_bad_handshake));
+		}
+
+		let results = checks
+			.into_iter()
+			.map(|(name, check)| {
+				let status = match check(self) {
+					Ok(status) => status,
+					Err(reason) => CheckStatus::Fail(reason),
+				};
+				debug!(
+					"conformance check {} on {}: {:?}",
+					name, self.target, status
+				);
+				CheckResult {
+					name: name.to_string(),
+					status,
+				}
+			})
+			.collect();
+
+		ConformanceReport {
+			target: self.target,
+			results,
+		}
+	}
+
+	fn connect(&self) -> Result<Probe, String> {
+		let stream = TcpStream::connect_timeout(&self.target, self.timeout)
+			.map_err(|e| format!("unable to connect to {}, {}", self.target, e))?;
+		let _ = stream.set_write_timeout(Some(self.timeout));
+		Ok(Probe {
+			stream,
+			version: ProtocolVersion::local(),
+			tracker: Arc::new(Tracker::new()),
+			timeout: self.timeout,
+		})
+	}
+
+	fn hand(&self, probe: &Probe, genesis: Hash) -> Result<Hand, String> {
+		let sender_addr = probe
+			.stream
+			.local_addr()
+			.map_err(|e| format!("unable to get local address, {}", e))?;
+		Ok(Hand {
+			version: ProtocolVersion::local(),
+			capabilities: Capabilities::UNKNOWN,
+			nonce: thread_rng().gen(),
+			genesis,
+			total_difficulty: Difficulty::min(),
+			sender_addr: PeerAddr::Ip(sender_addr),
+			receiver_addr: PeerAddr::Ip(self.target),
+			user_agent: CONFORMANCE_USER_AGENT.to_string(),
+		})
+	}
+
+	/// Connect and complete the handshake, returning the live connection
+	fn connect_peer(&self) -> Result<(Probe, Shake), String> {
+		let mut probe = self.connect()?;
+		let hand = self.hand(&probe, self.genesis)?;
+		probe.send(Type::Hand, hand)?;
+		let shake: Shake = probe.expect(Type::Shake)?;
+		if shake.genesis != self.genesis {
+			return Err(format!(
+				"genesis mismatch, expected {}, got {}",
+				self.genesis, shake.genesis
+			));
+		}
+		if !shake.user_agent.starts_with(msg::USER_AGENT_PREFIX) {
+			return Err(format!(
+				"user agent '{}' doesn't start with '{}'",
+				shake.user_agent,
+				msg::USER_AGENT_PREFIX
+			));
+		}
+		probe.version = std::cmp::min(ProtocolVersion::local(), shake.version);
+		Ok((probe, shake))
+	}
+}
+
+/// Raw connection to the target
+struct Probe {
+	stream: TcpStream,
+	version: ProtocolVersion,
+	tracker: Arc<Tracker>,
+	timeout: Duration,
+}
+
+impl Probe {
+	fn send<T: Writeable>(&mut self, msg_type: Type, body: T) -> Result<(), String> {
+		let msg = msg::Msg::new(msg_type, body, self.version)
+			.map_err(|e| format!("unable to build {:?}, {:?}", msg_type, e))?;
+		msg::write_message(&mut self.stream, &msg, self.tracker.clone())
+			.map_err(|e| format!("unable to send {:?}, {:?}", msg_type, e))
+	}
+
+	/// Write the bytes as they are, used for the malformed messages
+	fn send_raw(&mut self, data: &[u8]) -> Result<(), String> {
+		self.stream
+			.write_all(data)
+			.map_err(|e| format!("unable to send the raw message, {}", e))
+	}
+
+	/// Serialized message header, for the hand crafted messages
+	fn raw_header(&self, msg_type: Type, msg_len: u64) -> Result<Vec<u8>, String> {
+		ser::ser_vec(&MsgHeader::new(msg_type, msg_len), self.version)
+			.map_err(|e| format!("unable to serialize the header, {:?}", e))
+	}
+
+	/// Wait for a message of the expected type. Other messages the target is
+	/// free to send at any time (ping, peer requests) are skipped.
+	fn wait_for(&mut self, msg_type: Type, wait: Duration) -> Result<Option<Vec<u8>>, String> {
+		let deadline = Instant::now() + wait;
+		loop {
+			let now = Instant::now();
+			if now >= deadline {
+				return Ok(None);
+			}
+			let _ = self.stream.set_read_timeout(Some(deadline - now));
+			let header = match msg::read_header(&mut self.stream, self.version) {
+				Ok(header) => header,
+				Err(Error::Connection(ref e))
+					if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock =>
+				{
+					return Ok(None);
+				}
+				Err(e) => {
+					return Err(format!("failed waiting for {:?}, {:?}", msg_type, e));
+				}
+			};
+			let _ = self.stream.set_read_timeout(Some(self.timeout));
+			match header {
+				MsgHeaderWrapper::Known(header) => {
+					let mut body = vec![0u8; header.msg_len as usize];
+					self.stream
+						.read_exact(&mut body)
+						.map_err(|e| format!("unable to read {:?} body, {}", header.msg_type, e))?;
+					if header.msg_type == msg_type {
+						return Ok(Some(body));
+					}
+					if header.msg_type == Type::Error || header.msg_type == Type::BanReason {
+						return Err(format!(
+							"got {:?} while waiting for {:?}",
+							header.msg_type, msg_type
+						));
+					}
+				}
+				MsgHeaderWrapper::Unknown(msg_len, _) => {
+					msg::read_discard(msg_len, &mut self.stream)
+						.map_err(|e| format!("unable to skip unknown message, {:?}", e))?;
+				}
+			}
+		}
+	}
+
+	fn expect<T: Readable>(&mut self, msg_type: Type) -> Result<T, String> {
+		let timeout = self.timeout;
+		let body = self
+			.wait_for(msg_type, timeout)?
+			.ok_or_else(|| format!("no {:?} received in {:?}", msg_type, timeout))?;
+		self.decode(msg_type, &body)
+	}
+
+	/// Reply that the target is allowed to omit
+	fn expect_optional<T: Readable>(&mut self, msg_type: Type) -> Result<Option<T>, String> {
+		match self.wait_for(msg_type, OPTIONAL_REPLY_WAIT)? {
+			Some(body) => Ok(Some(self.decode(msg_type, &body)?)),
+			None => Ok(None),
+		}
+	}
+
+	fn decode<T: Readable>(&self, msg_type: Type, body: &[u8]) -> Result<T, String> {
+		ser::deserialize(&mut &body[..], self.version)
+			.map_err(|e| format!("unable to decode {:?}, {:?}", msg_type, e))
+	}
+
+	/// Check that the connection is still usable with a ping/pong round trip
+	fn expect_alive(&mut self) -> Result<Pong, String> {
+		self.send(
+			Type::Ping,
+			Ping {
+				total_difficulty: Difficulty::min(),
+				height: 0,
+			},
+		)?;
+		self.expect(Type::Pong)
+	}
+
+	/// Check that the target drops the connection. Everything the target
+	/// still sends before closing is ignored.
+	fn expect_closed(&mut self) -> Result<(), String> {
+		let deadline = Instant::now() + self.timeout;
+		let mut buf = [0u8; 1024];
+		loop {
+			let now = Instant::now();
+			if now >= deadline {
+				return Err(format!("connection is still open after {:?}", self.timeout));
+			}
+			let _ = self.stream.set_read_timeout(Some(deadline - now));
+			match self.stream.read(&mut buf) {
+				Ok(0) => return Ok(()),
+				Ok(_) => continue,
+				Err(ref e)
+					if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock =>
+				{
+					continue
+				}
+				Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+				Err(_) => return Ok(()),
+			}
+		}
+	}
+}
+
+impl Drop for Probe {
+	fn drop(&mut self) {
+		let _ = self.stream.shutdown(Shutdown::Both);
+	}
+}
+
+fn check_handshake(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (_probe, shake) = suite.connect_peer()?;
+	if shake.version.value() == 0 {
+		return Err("target advertised protocol version 0".to_string());
+	}
+	Ok(CheckStatus::Pass)
+}
+
+fn check_ping_pong(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	probe.expect_alive()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_get_peer_addrs(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	probe.send(
+		Type::GetPeerAddrs,
+		GetPeerAddrs {
+			capabilities: Capabilities::PEER_LIST,
+		},
+	)?;
+	let peers: PeerAddrs = probe.expect(Type::PeerAddrs)?;
+	if peers.peers.iter().any(|p| {
+		if let PeerAddr::Onion(_) = p {
+			true
+		} else {
+			false
+		}
+	}) {
+		return Err("onion addresses sent to a peer without TOR_ADDRESS capability".to_string());
+	}
+	Ok(CheckStatus::Pass)
+}
+
+fn check_get_headers(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	probe.send(
+		Type::GetHeaders,
+		Locator {
+			hashes: vec![suite.genesis],
+		},
+	)?;
+	let timeout = probe.timeout;
+	let body = probe
+		.wait_for(Type::Headers, timeout)?
+		.ok_or_else(|| format!("no Headers received in {:?}", timeout))?;
+	// Headers are prefixed with the u16 count
+	if body.len() < 2 {
+		return Err(format!("Headers body is too short, {} bytes", body.len()));
+	}
+	let count = u16::from_be_bytes([body[0], body[1]]);
+	if count as u32 > MAX_BLOCK_HEADERS {
+		return Err(format!(
+			"got {} headers, max is {}",
+			count, MAX_BLOCK_HEADERS
+		));
+	}
+	probe.expect_alive()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_get_block(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	probe.send(Type::GetBlock, suite.genesis)?;
+	if let Some(block) = probe.expect_optional::<UntrustedBlock>(Type::Block)? {
+		let block: Block = block.into();
+		if block.hash() != suite.genesis {
+			return Err(format!(
+				"requested block {}, got {}",
+				suite.genesis,
+				block.hash()
+			));
+		}
+	}
+	probe.expect_alive()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_get_compact_block(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	probe.send(Type::GetCompactBlock, suite.genesis)?;
+	if let Some(cb) = probe.expect_optional::<UntrustedCompactBlock>(Type::CompactBlock)? {
+		let cb: CompactBlock = cb.into();
+		if cb.header.hash() != suite.genesis {
+			return Err(format!(
+				"requested compact block {}, got {}",
+				suite.genesis,
+				cb.header.hash()
+			));
+		}
+	}
+	probe.expect_alive()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_get_unknown_transaction(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	let unknown: [u8; 32] = thread_rng().gen();
+	probe.send(Type::GetTransaction, Hash::from_vec(&unknown))?;
+	if probe
+		.wait_for(Type::Transaction, OPTIONAL_REPLY_WAIT)?
+		.is_some()
+	{
+		return Err("got a transaction for a random kernel hash".to_string());
+	}
+	probe.expect_alive()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_unknown_message_type(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	let body = [0u8; 16];
+	let mut data = probe.raw_header(Type::Ping, body.len() as u64)?;
+	data[2] = UNKNOWN_MSG_TYPE;
+	data.extend_from_slice(&body);
+	probe.send_raw(&data)?;
+	// Unknown messages must be skipped, the connection stays usable
+	probe.expect_alive()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_bad_magic(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	let mut data = probe.raw_header(Type::Ping, 16)?;
+	data[0] = !data[0];
+	data[1] = !data[1];
+	data.extend_from_slice(&[0u8; 16]);
+	probe.send_raw(&data)?;
+	probe.expect_closed()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_oversized_message(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	// Ping max size is way below that, the target must not try to read the body
+	let data = probe.raw_header(Type::Ping, 1 << 40)?;
+	probe.send_raw(&data)?;
+	probe.expect_closed()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_too_many_locators(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	let count = MAX_LOCATORS as usize + 1;
+	let mut body = vec![count as u8];
+	for _ in 0..count {
+		body.extend_from_slice(suite.genesis.as_bytes());
+	}
+	let mut data = probe.raw_header(Type::GetHeaders, body.len() as u64)?;
+	data.extend_from_slice(&body);
+	probe.send_raw(&data)?;
+	probe.expect_closed()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_truncated_body(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let (mut probe, _) = suite.connect_peer()?;
+	// Ping body is 16 bytes, the header declares only 4
+	let mut data = probe.raw_header(Type::Ping, 4)?;
+	data.extend_from_slice(&[0u8; 4]);
+	probe.send_raw(&data)?;
+	probe.expect_closed()?;
+	Ok(CheckStatus::Pass)
+}
+
+fn check_ban_on_bad_handshake(suite: &ConformanceSuite) -> Result<CheckStatus, String> {
+	let mut probe = suite.connect()?;
+	let bad_genesis = Hash::from_vec(&thread_rng().gen::<[u8; 32]>());
+	let hand = suite.hand(&probe, bad_genesis)?;
+	probe.send(Type::Hand, hand)?;
+	if probe.wait_for(Type::Shake, suite.timeout)?.is_some() {
+		return Err("got Shake for a handshake with a foreign genesis".to_string());
+	}
+	probe.expect_closed()?;
+	drop(probe);
+
+	// Loopback peers are identified by the ip and the port, a new connection
+	// comes from another port and is not covered by the ban.
+	if suite.target.ip().is_loopback() {
+		return Ok(CheckStatus::Skipped(
+			"the ban is per ip:port for loopback targets, refused reconnect is not checked"
+				.to_string(),
+		));
+	}
+
+	// Banned host must be refused even with a valid handshake
+	let mut probe = suite.connect()?;
+	let hand = suite.hand(&probe, suite.genesis)?;
+	// Target may have closed the connection already, the write error is fine
+	let _ = probe.send(Type::Hand, hand);
+	if probe
+		.wait_for(Type::Shake, suite.timeout)
+		.unwrap_or(None)
+		.is_some()
+	{
+		return Err("banned host completed the handshake".to_string());
+	}
+	probe.expect_closed()?;
+	Ok(CheckStatus::Pass)
+}
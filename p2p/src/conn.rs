@@ -20,13 +20,16 @@
 //! forces us to go through some additional gymnastic to loop over the async
 //! stream and make sure we get the right number of bytes out.
 
+#[cfg(feature = "msg_capture")]
+use crate::capture::{CaptureDirection, PeerCapture, TeeReader};
+use crate::conn_stats::{ConnStats, ConnStatsReader, SlowPeerThresholds};
 use crate::core::ser;
 use crate::core::ser::ProtocolVersion;
 use crate::msg::{
 	read_body, read_discard, read_header, read_item, write_message, Msg, MsgHeader,
 	MsgHeaderWrapper,
 };
-use crate::types::Error;
+use crate::types::{Error, PeerAddr};
 use crate::util::{RateCounter, RwLock};
 use std::io::{self, Read, Write};
 use std::net::{Shutdown, TcpStream};
@@ -95,7 +98,11 @@ pub struct Message<'a, R: Read> {
 }
 
 impl<'a, R: Read> Message<'a, R> {
-	fn from_header(header: MsgHeader, stream: &'a mut R, version: ProtocolVersion) -> Self {
+	pub(crate) fn from_header(
+		header: MsgHeader,
+		stream: &'a mut R,
+		version: ProtocolVersion,
+	) -> Self {
 		Message {
 			header,
 			stream,
@@ -199,15 +206,23 @@ pub struct Tracker {
 	pub sent_bytes: Arc<RwLock<RateCounter>>,
 	/// Bytes we've received.
 	pub received_bytes: Arc<RwLock<RateCounter>>,
+	/// Ping round trip, read sizes and stalls
+	pub conn_stats: Arc<ConnStats>,
 }
 
 impl Tracker {
 	pub fn new() -> Tracker {
+		Tracker::with_thresholds(SlowPeerThresholds::disabled())
+	}
+
+	/// Tracker that finds the slow peers with the thresholds
+	pub fn with_thresholds(thresholds: SlowPeerThresholds) -> Tracker {
 		let received_bytes = Arc::new(RwLock::new(RateCounter::new()));
 		let sent_bytes = Arc::new(RwLock::new(RateCounter::new()));
 		Tracker {
 			received_bytes,
 			sent_bytes,
+			conn_stats: Arc::new(ConnStats::new(thresholds)),
 		}
 	}
 
@@ -233,6 +248,7 @@ impl Tracker {
 /// itself.
 pub fn listen<H>(
 	stream: TcpStream,
+	peer_addr: &PeerAddr,
 	version: ProtocolVersion,
 	tracker: Arc<Tracker>,
 	handler: H,
@@ -241,6 +257,10 @@ where
 	H: MessageHandler,
 {
 	let (send_tx, send_rx) = mpsc::sync_channel(SEND_CHANNEL_CAP);
+	#[cfg(feature = "msg_capture")]
+	let capture = PeerCapture::open(peer_addr, version);
+	#[cfg(not(feature = "msg_capture"))]
+	let _ = peer_addr;
 
 	let stopped = Arc::new(AtomicBool::new(false));
 
@@ -256,6 +276,8 @@ where
 		send_rx,
 		stopped.clone(),
 		tracker,
+		#[cfg(feature = "msg_capture")]
+		capture,
 	)?;
 
 	Ok((
@@ -276,6 +298,7 @@ fn poll<H>(
 	send_rx: mpsc::Receiver<Msg>,
 	stopped: Arc<AtomicBool>,
 	tracker: Arc<Tracker>,
+	#[cfg(feature = "msg_capture")] capture: Option<Arc<PeerCapture>>,
 ) -> io::Result<(JoinHandle<()>, JoinHandle<()>)>
 where
 	H: MessageHandler,
@@ -283,20 +306,31 @@ where
 	// Split out tcp stream out into separate reader/writer halves.
 	let mut reader = conn.try_clone().expect("clone conn for reader failed");
 	let mut writer = conn.try_clone().expect("clone conn for writer failed");
+	// Messages are read through the stats, the reader is for the timeouts and the shutdown
+	let mut stream = ConnStatsReader::new(
+		conn.try_clone().expect("clone conn for stream failed"),
+		tracker.conn_stats.clone(),
+	);
 	let reader_stopped = stopped.clone();
 
 	let reader_tracker = tracker.clone();
 	let writer_tracker = tracker;
+	#[cfg(feature = "msg_capture")]
+	let writer_capture = capture.clone();
 
 	let reader_thread = thread::Builder::new()
 		.name("peer_read".to_string())
 		.spawn(move || {
 			loop {
 				// check the read end
-				match try_header!(read_header(&mut reader, version), &reader) {
+				match try_header!(read_header(&mut stream, version), &reader) {
 					Some(MsgHeaderWrapper::Known(header)) => {
 						let _ = reader.set_read_timeout(Some(BODY_IO_TIMEOUT));
-						let msg = Message::from_header(header, &mut reader, version);
+						#[cfg(feature = "msg_capture")]
+						let captured_header = header.clone();
+						#[cfg(feature = "msg_capture")]
+						let mut stream = TeeReader::new(&mut stream, capture.is_some(), header.msg_len as usize);
+						let msg = Message::from_header(header, &mut stream, version);
 
 						trace!(
 							"Received message header, type {:?}, len {}.",
@@ -307,11 +341,20 @@ where
 						// Increase received bytes counter
 						reader_tracker.inc_received(MsgHeader::LEN as u64 + msg.header.msg_len);
 
-						let resp_msg = try_break!(handler.consume(
-							msg,
-							reader_stopped.clone(),
-							reader_tracker.clone()
-						));
+						let resp_msg =
+							handler.consume(msg, reader_stopped.clone(), reader_tracker.clone());
+						#[cfg(feature = "msg_capture")]
+						{
+							if let Some(capture) = &capture {
+								capture.record(
+									CaptureDirection::Inbound,
+									&captured_header,
+									stream.captured(),
+								);
+							}
+						}
+						reader_tracker.conn_stats.message_finished();
+						let resp_msg = try_break!(resp_msg);
 						if let Some(Some(resp_msg)) = resp_msg {
 							try_break!(conn_handle.send(resp_msg));
 						}
@@ -324,7 +367,8 @@ where
 						// Increase received bytes counter
 						reader_tracker.inc_received(MsgHeader::LEN as u64 + msg_len);
 
-						try_break!(read_discard(msg_len, &mut reader));
+						try_break!(read_discard(msg_len, &mut stream));
+						reader_tracker.conn_stats.message_finished();
 					}
 					None => {}
 				}
@@ -359,6 +403,17 @@ where
 							try_break!(write_message(&mut writer, &data, writer_tracker.clone()));
 						if written.is_none() {
 							retry_send = Ok(data);
+						} else {
+							#[cfg(feature = "msg_capture")]
+							{
+								if let Some(capture) = &writer_capture {
+									capture.record(
+										CaptureDirection::Outbound,
+										data.header(),
+										data.body(),
+									);
+								}
+							}
 						}
 					}
 					Err(RecvTimeoutError::Disconnected) => {
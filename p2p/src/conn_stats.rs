@@ -0,0 +1,284 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Protocol statistics of the peer connection: ping round trip, read sizes and read
+//! stalls. A peer that keeps the message half sent (slow-loris) holds the reader thread
+//! for the whole body timeout and keeps doing it again with the next message. Such
+//! peers are found by the stats and dropped. The message read starts with the first
+//! byte of the header, the idle time between the messages is not a stall.
+
+use crate::util::Mutex;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Max number of the pings waiting for the pong. Older pings are forgotten.
+const MAX_PENDING_PINGS: usize = 10;
+
+/// Thresholds of the slow peer detection. 0 disables the check.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlowPeerThresholds {
+	/// Message read that takes longer than that is checked for the read size
+	pub slow_read_secs: u64,
+	/// Min average read size of the long message read, in bytes
+	pub min_avg_read_bytes: u64,
+	/// Max time without any byte in the middle of the message
+	pub max_stalled_read_secs: u64,
+}
+
+impl SlowPeerThresholds {
+	/// Thresholds with all checks disabled
+	pub fn disabled() -> SlowPeerThresholds {
+		SlowPeerThresholds {
+			slow_read_secs: 0,
+			min_avg_read_bytes: 0,
+			max_stalled_read_secs: 0,
+		}
+	}
+}
+
+/// Why the peer is considered slow
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SlowPeerReason {
+	/// Message is trickled with the tiny reads
+	SlowRead,
+	/// No data in the middle of the message
+	StalledRead,
+}
+
+/// Protocol statistics of the connection
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerConnStats {
+	/// Last ping round trip, in ms. None until the first pong
+	pub last_rtt_ms: Option<u64>,
+	/// Average ping round trip, in ms. None until the first pong
+	pub avg_rtt_ms: Option<u64>,
+	/// Messages read from the connection
+	pub messages_read: u64,
+	/// Average size of the single socket read, in bytes
+	pub avg_read_bytes: u64,
+	/// Longest time without any byte in the middle of the message, in ms
+	pub max_stalled_read_ms: u64,
+	/// Duration of the message read in progress, in ms. None between the messages
+	pub current_read_ms: Option<u64>,
+}
+
+/// Number of the peers dropped by the slow peer detection
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SlowPeerCounters {
+	/// Peers that trickled the messages with the tiny reads
+	pub slow_read_dropped: u64,
+	/// Peers that stalled in the middle of the message
+	pub stalled_read_dropped: u64,
+}
+
+static SLOW_READ_DROPPED: AtomicU64 = AtomicU64::new(0);
+static STALLED_READ_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Number of the peers dropped by the slow peer detection since the start
+pub fn slow_peer_counters() -> SlowPeerCounters {
+	SlowPeerCounters {
+		slow_read_dropped: SLOW_READ_DROPPED.load(Ordering::Relaxed),
+		stalled_read_dropped: STALLED_READ_DROPPED.load(Ordering::Relaxed),
+	}
+}
+
+// Message that is being read
+struct MessageRead {
+	started: Instant,
+	last_read: Instant,
+	reads: u64,
+	bytes: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+	pending_pings: VecDeque<Instant>,
+	last_rtt: Option<Duration>,
+	rtt_total: Duration,
+	pongs: u32,
+	messages_read: u64,
+	reads: u64,
+	read_bytes: u64,
+	max_stalled_read: Duration,
+	current: Option<MessageRead>,
+}
+
+/// Protocol statistics of the single connection
+pub struct ConnStats {
+	thresholds: SlowPeerThresholds,
+	counters: Mutex<Counters>,
+	// Set once the peer is found slow, so it is counted once
+	slow: AtomicBool,
+}
+
+impl ConnStats {
+	/// Stats of the new connection
+	pub fn new(thresholds: SlowPeerThresholds) -> ConnStats {
+		ConnStats {
+			thresholds,
+			counters: Mutex::new(Counters::default()),
+			slow: AtomicBool::new(false),
+		}
+	}
+
+	/// Ping is sent to the peer
+	pub fn ping_sent(&self, now: Instant) {
+		let mut counters = self.counters.lock();
+		if counters.pending_pings.len() >= MAX_PENDING_PINGS {
+			counters.pending_pings.pop_front();
+		}
+		counters.pending_pings.push_back(now);
+	}
+
+	/// Pong is received, it answers the oldest pending ping
+	pub fn pong_received(&self, now: Instant) {
+		let mut counters = self.counters.lock();
+		if let Some(sent) = counters.pending_pings.pop_front() {
+			let rtt = now.saturating_duration_since(sent);
+			counters.last_rtt = Some(rtt);
+			counters.rtt_total += rtt;
+			counters.pongs += 1;
+		}
+	}
+
+	/// Socket read returned the data. The first read starts the message.
+	pub fn record_read(&self, bytes: usize, now: Instant) {
+		if bytes == 0 {
+			return;
+		}
+		let mut guard = self.counters.lock();
+		let counters = &mut *guard;
+		counters.reads += 1;
+		counters.read_bytes += bytes as u64;
+		let stalled = match &mut counters.current {
+			Some(current) => {
+				let stalled = now.saturating_duration_since(current.last_read);
+				current.last_read = now;
+				current.reads += 1;
+				current.bytes += bytes as u64;
+				stalled
+			}
+			None => {
+				counters.current = Some(MessageRead {
+					started: now,
+					last_read: now,
+					reads: 1,
+					bytes: bytes as u64,
+				});
+				Duration::from_secs(0)
+			}
+		};
+		if stalled > counters.max_stalled_read {
+			counters.max_stalled_read = stalled;
+		}
+	}
+
+	/// Message is read completely
+	pub fn message_finished(&self) {
+		let mut counters = self.counters.lock();
+		if counters.current.take().is_some() {
+			counters.messages_read += 1;
+		}
+	}
+
+	/// Reason to drop the peer if the message in progress is trickled or stalled.
+	/// The drop counters are increased once per connection.
+	pub fn check(&self, now: Instant) -> Option<SlowPeerReason> {
+		let reason = {
+			let counters = self.counters.lock();
+			let current = counters.current.as_ref()?;
+			let t = &self.thresholds;
+			let reading = now.saturating_duration_since(current.started);
+			let stalled = now.saturating_duration_since(current.last_read);
+			if t.max_stalled_read_secs > 0 && stalled > Duration::from_secs(t.max_stalled_read_secs)
+			{
+				SlowPeerReason::StalledRead
+			} else if t.slow_read_secs > 0
+				&& reading > Duration::from_secs(t.slow_read_secs)
+				&& current.bytes / current.reads < t.min_avg_read_bytes
+			{
+				SlowPeerReason::SlowRead
+			} else {
+				return None;
+			}
+		};
+		if !self.slow.swap(true, Ordering::Relaxed) {
+			match reason {
+				SlowPeerReason::SlowRead => SLOW_READ_DROPPED.fetch_add(1, Ordering::Relaxed),
+				SlowPeerReason::StalledRead => STALLED_READ_DROPPED.fetch_add(1, Ordering::Relaxed),
+			};
+		}
+		Some(reason)
+	}
+
+	/// Current stats of the connection
+	pub fn stats(&self, now: Instant) -> PeerConnStats {
+		let counters = self.counters.lock();
+		let current_stall = counters
+			.current
+			.as_ref()
+			.map(|c| now.saturating_duration_since(c.last_read))
+			.unwrap_or_default();
+		PeerConnStats {
+			last_rtt_ms: counters.last_rtt.map(|rtt| rtt.as_millis() as u64),
+			avg_rtt_ms: match counters.pongs {
+				0 => None,
+				pongs => Some((counters.rtt_total / pongs).as_millis() as u64),
+			},
+			messages_read: counters.messages_read,
+			avg_read_bytes: match counters.reads {
+				0 => 0,
+				reads => counters.read_bytes / reads,
+			},
+			max_stalled_read_ms: counters.max_stalled_read.max(current_stall).as_millis() as u64,
+			current_read_ms: counters
+				.current
+				.as_ref()
+				.map(|c| now.saturating_duration_since(c.started).as_millis() as u64),
+		}
+	}
+}
+
+/// Reader that feeds the connection stats. Read fails once the peer is found slow,
+/// so the connection is closed.
+pub struct ConnStatsReader<R: Read> {
+	inner: R,
+	stats: Arc<ConnStats>,
+}
+
+impl<R: Read> ConnStatsReader<R> {
+	/// Wrap the connection reader
+	pub fn new(inner: R, stats: Arc<ConnStats>) -> ConnStatsReader<R> {
+		ConnStatsReader { inner, stats }
+	}
+}
+
+impl<R: Read> Read for ConnStatsReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let size = self.inner.read(buf)?;
+		// Checked before the read is recorded, the wait for these bytes might be the stall
+		let now = Instant::now();
+		if let Some(reason) = self.stats.check(now) {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("slow peer, {:?}", reason),
+			));
+		}
+		self.stats.record_read(size, now);
+		Ok(size)
+	}
+}
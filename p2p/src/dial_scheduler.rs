@@ -0,0 +1,222 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Outbound dial scheduling, shared by the p2p seeder and the libp2p reconnection task.
+//! After the restart the node knows many peers and used to dial dozens of them at once,
+//! over Tor that overwhelms the SOCKS proxy. The scheduler limits the number of the
+//! concurrent dials, dials the most promising peers first and ramps up the concurrency
+//! progressively.
+
+use crate::types::{P2PConfig, PeerAddr};
+use crate::util::{Mutex, MutexGuard};
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Max number of the addresses waiting for the dial
+const MAX_DIAL_QUEUE_SIZE: usize = 1000;
+
+/// Dial priority, anchors are dialed first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DialPriority {
+	/// Preferred peers and the peers we were connected to before the restart
+	Anchor,
+	/// Peers we had a successful handshake with, and the seeds
+	Reputable,
+	/// Peers we never connected to
+	New,
+}
+
+/// Dial concurrency limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialLimits {
+	/// Max number of the concurrent dials once the ramp up is finished
+	pub max_concurrent: usize,
+	/// Number of the concurrent dials allowed right after the start
+	pub initial_concurrent: usize,
+	/// One more concurrent dial is allowed every ramp up interval
+	pub ramp_up_interval: Duration,
+	/// Dial slot is released automatically if the dial didn't release it in time
+	pub slot_timeout: Duration,
+}
+
+impl DialLimits {
+	/// Dial limits from the p2p config
+	pub fn from_config(config: &P2PConfig) -> DialLimits {
+		let max_concurrent = cmp::max(1, config.dial_max_concurrent() as usize);
+		DialLimits {
+			max_concurrent,
+			initial_concurrent: cmp::min(
+				max_concurrent,
+				cmp::max(1, config.dial_initial_concurrent() as usize),
+			),
+			ramp_up_interval: Duration::from_secs(config.dial_ramp_up_interval_secs()),
+			slot_timeout: Duration::from_secs(60),
+		}
+	}
+}
+
+impl Default for DialLimits {
+	fn default() -> DialLimits {
+		DialLimits::from_config(&P2PConfig::default())
+	}
+}
+
+/// Queue of the addresses to dial with the limit on the concurrent dials
+pub struct DialScheduler {
+	limits: DialLimits,
+	started: Instant,
+	seq: u64,
+	queue: BTreeMap<(DialPriority, u64), PeerAddr>,
+	queued: HashMap<PeerAddr, (DialPriority, u64)>,
+	// Dials in progress by the dial key, with the time the slot was taken
+	in_flight: HashMap<String, Instant>,
+}
+
+impl DialScheduler {
+	/// New scheduler, the ramp up starts now
+	pub fn new(limits: DialLimits) -> DialScheduler {
+		DialScheduler {
+			limits,
+			started: Instant::now(),
+			seq: 0,
+			queue: BTreeMap::new(),
+			queued: HashMap::new(),
+			in_flight: HashMap::new(),
+		}
+	}
+
+	/// Update the limits, the ramp up progress is kept
+	pub fn set_limits(&mut self, limits: DialLimits) {
+		self.limits = limits;
+	}
+
+	/// Number of the concurrent dials allowed at the moment
+	pub fn concurrency_limit(&self) -> usize {
+		let interval = cmp::max(1, self.limits.ramp_up_interval.as_secs());
+		let steps = (self.started.elapsed().as_secs() / interval) as usize;
+		cmp::min(
+			self.limits.max_concurrent,
+			self.limits.initial_concurrent.saturating_add(steps),
+		)
+	}
+
+	/// Queue the address for the dial. Address that is already queued keeps the best priority.
+	pub fn schedule(&mut self, addr: PeerAddr, priority: DialPriority) {
+		if self.in_flight.contains_key(&addr.as_key()) {
+			return;
+		}
+		if let Some(key) = self.queued.get(&addr).cloned() {
+			if key.0 <= priority {
+				return;
+			}
+			self.queue.remove(&key);
+			self.queued.remove(&addr);
+		}
+		if self.queue.len() >= MAX_DIAL_QUEUE_SIZE {
+			// Evict the least promising address if the new one is better
+			let worst = match self.queue.keys().next_back() {
+				Some(worst) if worst.0 > priority => *worst,
+				_ => return,
+			};
+			if let Some(evicted) = self.queue.remove(&worst) {
+				self.queued.remove(&evicted);
+			}
+		}
+		let key = (priority, self.seq);
+		self.seq += 1;
+		self.queue.insert(key, addr.clone());
+		self.queued.insert(addr, key);
+	}
+
+	/// Take the addresses to dial, as many as the free dial slots allow. Slots are taken
+	/// for the returned addresses, the caller must release them with `release`.
+	pub fn next_dials(&mut self) -> Vec<PeerAddr> {
+		self.expire_slots();
+		let mut res = vec![];
+		while self.in_flight.len() < self.concurrency_limit() {
+			let key = match self.queue.keys().next() {
+				Some(key) => *key,
+				None => break,
+			};
+			if let Some(addr) = self.queue.remove(&key) {
+				self.queued.remove(&addr);
+				self.in_flight.insert(addr.as_key(), Instant::now());
+				res.push(addr);
+			}
+		}
+		res
+	}
+
+	/// Take a dial slot for the dial that doesn't go through the queue. The slot is not
+	/// granted if there are no free slots or if the queued dials have a better priority.
+	pub fn try_acquire(&mut self, dial_key: &str, priority: DialPriority) -> bool {
+		self.expire_slots();
+		if self.in_flight.contains_key(dial_key) {
+			return false;
+		}
+		if self.in_flight.len() >= self.concurrency_limit() {
+			return false;
+		}
+		if let Some(best) = self.queue.keys().next() {
+			if best.0 < priority {
+				return false;
+			}
+		}
+		self.in_flight.insert(dial_key.to_string(), Instant::now());
+		true
+	}
+
+	/// Release the dial slot when the dial is finished, successfully or not
+	pub fn release(&mut self, dial_key: &str) {
+		self.in_flight.remove(dial_key);
+	}
+
+	/// Drop all the queued addresses, for example when we have enough peers
+	pub fn clear_queue(&mut self) {
+		self.queue.clear();
+		self.queued.clear();
+	}
+
+	/// Number of the addresses waiting for the dial
+	pub fn queue_len(&self) -> usize {
+		self.queue.len()
+	}
+
+	/// Number of the dials in progress
+	pub fn in_flight_len(&self) -> usize {
+		self.in_flight.len()
+	}
+
+	fn expire_slots(&mut self) {
+		let timeout = self.limits.slot_timeout;
+		self.in_flight.retain(|key, started| {
+			let alive = started.elapsed() < timeout;
+			if !alive {
+				debug!("Dial slot for {} is expired", key);
+			}
+			alive
+		});
+	}
+}
+
+lazy_static! {
+	static ref DIAL_SCHEDULER: Mutex<DialScheduler> =
+		Mutex::new(DialScheduler::new(DialLimits::default()));
+}
+
+/// Dial scheduler shared by the p2p and libp2p outbound connections
+pub fn dial_scheduler() -> MutexGuard<'static, DialScheduler> {
+	DIAL_SCHEDULER.lock()
+}
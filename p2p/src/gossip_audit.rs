@@ -0,0 +1,270 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Audit log of the accepted gossip messages. The operator selects the topics, every
+//! message accepted on them is recorded with its sender, integrity fee and payload hash.
+//! Marketplace operators use the log to investigate the disputes about what was
+//! broadcast and when. The log is persistent, the records older than the retention
+//! period and the oldest records above the size limit are pruned.
+
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::util::RwLock;
+use grin_store::{self, to_key, to_key_u64, Error};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const DB_NAME: &str = "gossip_audit";
+const STORE_SUBPATH: &str = "audit";
+
+const AUDIT_PREFIX: u8 = b'A';
+
+/// Default retention period of the audit records
+pub const DEFAULT_AUDIT_RETENTION_DAYS: u32 = 30;
+/// Default max number of the audit records
+pub const DEFAULT_AUDIT_MAX_ENTRIES: u64 = 100_000;
+/// Max number of the records returned by the query
+pub const AUDIT_QUERY_LIMIT: usize = 1000;
+// Pruning runs after that many records
+const PRUNE_INTERVAL: u64 = 1000;
+
+/// Accepted gossip message record
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GossipAuditEntry {
+	/// Unix time when the message was received
+	pub timestamp: i64,
+	/// Topic of the message
+	pub topic: String,
+	/// Gossipsub message id
+	pub message_id: String,
+	/// Onion address of the integrity output owner, the message sender
+	pub sender_address: String,
+	/// libp2p peer that published the message
+	pub source_peer_id: Option<String>,
+	/// libp2p peer that forwarded the message to us
+	pub propagation_peer_id: String,
+	/// Integrity fee that was paid
+	pub fee: u64,
+	/// Blake2b hash of the message payload, hex
+	pub payload_hash: String,
+}
+
+fn write_str<W: Writer>(writer: &mut W, s: &str) -> Result<(), ser::Error> {
+	if s.len() > 10_000 {
+		return Err(ser::Error::TooLargeWriteErr(format!(
+			"Unreasonable long audit field, length is {}",
+			s.len()
+		)));
+	}
+	writer.write_bytes(&s)
+}
+
+fn read_str<R: Reader>(reader: &mut R) -> Result<String, ser::Error> {
+	String::from_utf8(reader.read_bytes_len_prefix()?)
+		.map_err(|e| ser::Error::CorruptedData(format!("Fail to read audit field, {}", e)))
+}
+
+impl Writeable for GossipAuditEntry {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_i64(self.timestamp)?;
+		write_str(writer, &self.topic)?;
+		write_str(writer, &self.message_id)?;
+		write_str(writer, &self.sender_address)?;
+		match &self.source_peer_id {
+			Some(peer_id) => {
+				writer.write_u8(1)?;
+				write_str(writer, peer_id)?;
+			}
+			None => writer.write_u8(0)?,
+		}
+		write_str(writer, &self.propagation_peer_id)?;
+		writer.write_u64(self.fee)?;
+		write_str(writer, &self.payload_hash)
+	}
+}
+
+impl Readable for GossipAuditEntry {
+	fn read<R: Reader>(reader: &mut R) -> Result<GossipAuditEntry, ser::Error> {
+		let timestamp = reader.read_i64()?;
+		let topic = read_str(reader)?;
+		let message_id = read_str(reader)?;
+		let sender_address = read_str(reader)?;
+		let source_peer_id = match reader.read_u8()? {
+			0 => None,
+			_ => Some(read_str(reader)?),
+		};
+		let propagation_peer_id = read_str(reader)?;
+		let fee = reader.read_u64()?;
+		let payload_hash = read_str(reader)?;
+		Ok(GossipAuditEntry {
+			timestamp,
+			topic,
+			message_id,
+			sender_address,
+			source_peer_id,
+			propagation_peer_id,
+			fee,
+			payload_hash,
+		})
+	}
+}
+
+/// Audit log query, all conditions are optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GossipAuditQuery {
+	/// Messages of this topic only
+	pub topic: Option<String>,
+	/// Messages of this sender onion address only
+	pub sender_address: Option<String>,
+	/// Messages with this payload hash only
+	pub payload_hash: Option<String>,
+	/// Messages received at this unix time or later
+	pub from_time: Option<i64>,
+	/// Messages received at this unix time or earlier
+	pub to_time: Option<i64>,
+	/// Max number of the records, the most recent are returned. Default and max is 1000.
+	pub limit: Option<usize>,
+}
+
+impl GossipAuditQuery {
+	fn matches(&self, entry: &GossipAuditEntry) -> bool {
+		self.topic.as_ref().map_or(true, |t| *t == entry.topic)
+			&& self
+				.sender_address
+				.as_ref()
+				.map_or(true, |s| *s == entry.sender_address)
+			&& self
+				.payload_hash
+				.as_ref()
+				.map_or(true, |h| *h == entry.payload_hash)
+			&& self.from_time.map_or(true, |t| entry.timestamp >= t)
+			&& self.to_time.map_or(true, |t| entry.timestamp <= t)
+	}
+}
+
+/// Persistent audit log of the accepted gossip messages
+pub struct GossipAuditLog {
+	db: grin_store::Store,
+	topics: HashSet<String>,
+	retention_secs: i64,
+	max_entries: u64,
+	count: AtomicU64,
+	seq: AtomicU64,
+}
+
+impl GossipAuditLog {
+	/// Open the audit log under the provided root path
+	pub fn new(
+		db_root: &str,
+		topics: Vec<String>,
+		retention_days: u32,
+		max_entries: u64,
+	) -> Result<GossipAuditLog, Error> {
+		let db = grin_store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		let count = db
+			.iter::<GossipAuditEntry>(&to_key(AUDIT_PREFIX, ""))?
+			.count() as u64;
+		Ok(GossipAuditLog {
+			db,
+			topics: topics.into_iter().collect(),
+			retention_secs: retention_days as i64 * 24 * 3600,
+			max_entries,
+			count: AtomicU64::new(count),
+			seq: AtomicU64::new(0),
+		})
+	}
+
+	/// True if the messages of the topic are audited
+	pub fn is_audited(&self, topic: &str) -> bool {
+		self.topics.contains(topic)
+	}
+
+	/// Record the accepted message
+	pub fn record(&self, entry: &GossipAuditEntry) -> Result<(), Error> {
+		// Sequence number keeps the records of the same second apart and in order
+		let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+		let key = to_key_u64(AUDIT_PREFIX, &entry.timestamp.to_be_bytes(), seq);
+		let batch = self.db.batch()?;
+		batch.put_ser(&key, entry)?;
+		batch.commit()?;
+		let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+		if seq % PRUNE_INTERVAL == 0 || count > self.max_entries + PRUNE_INTERVAL {
+			self.prune(entry.timestamp)?;
+		}
+		Ok(())
+	}
+
+	/// Delete the records older than the retention period and the oldest records
+	/// above the size limit. Returns the number of the deleted records.
+	pub fn prune(&self, now: i64) -> Result<u64, Error> {
+		let min_time = now - self.retention_secs;
+		let entries: Vec<(Vec<u8>, i64)> = self
+			.db
+			.iter::<GossipAuditEntry>(&to_key(AUDIT_PREFIX, ""))?
+			.map(|(k, v)| (k, v.timestamp))
+			.collect();
+		let over_limit = (entries.len() as u64).saturating_sub(self.max_entries);
+		let batch = self.db.batch()?;
+		let mut deleted = 0;
+		// Keys are ordered by the time, the oldest first
+		for (i, (key, timestamp)) in entries.iter().enumerate() {
+			if (i as u64) < over_limit || *timestamp < min_time {
+				batch.delete(key)?;
+				deleted += 1;
+			} else {
+				break;
+			}
+		}
+		batch.commit()?;
+		self.count
+			.store(entries.len() as u64 - deleted, Ordering::Relaxed);
+		if deleted > 0 {
+			debug!("Gossip audit log pruned, {} records deleted", deleted);
+		}
+		Ok(deleted)
+	}
+
+	/// Records that match the query, the oldest first
+	pub fn query(&self, query: &GossipAuditQuery) -> Result<Vec<GossipAuditEntry>, Error> {
+		let limit = query
+			.limit
+			.unwrap_or(AUDIT_QUERY_LIMIT)
+			.min(AUDIT_QUERY_LIMIT);
+		let mut res: Vec<GossipAuditEntry> = self
+			.db
+			.iter::<GossipAuditEntry>(&to_key(AUDIT_PREFIX, ""))?
+			.map(|(_, v)| v)
+			.filter(|e| query.matches(e))
+			.collect();
+		if res.len() > limit {
+			res.drain(..res.len() - limit);
+		}
+		Ok(res)
+	}
+}
+
+lazy_static! {
+	// Audit log of the node, None if the audit is not enabled
+	static ref GOSSIP_AUDIT: RwLock<Option<Arc<GossipAuditLog>>> = RwLock::new(None);
+}
+
+/// Set the audit log of the node
+pub fn init_gossip_audit(log: GossipAuditLog) {
+	*GOSSIP_AUDIT.write() = Some(Arc::new(log));
+}
+
+/// Audit log of the node, None if the audit is not enabled
+pub fn gossip_audit() -> Option<Arc<GossipAuditLog>> {
+	GOSSIP_AUDIT.read().clone()
+}
@@ -0,0 +1,341 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Gossip mesh latency probes. The nodes that enabled the probes subscribe to the
+//! diagnostic topic and periodically publish a tiny probe there. Gossipsub signs the
+//! messages, so the probe origin is the message source. Every cooperating node answers
+//! the probe with an echo on the same topic, the origin measures the round trip time
+//! of the echo. Clocks of the nodes are not involved. The probe or the echo that was
+//! forwarded by some other node is counted as relayed, the direct neighbours answer
+//! with a single hop each way. Aggregated latencies help to tune gossipsub for Tor.
+
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::util::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Diagnostic topic of the probes and the echoes
+pub const PROBE_TOPIC: &str = "mwc/diag/probe";
+/// Probes and echoes are tiny, larger messages are rejected
+pub const MAX_PROBE_MESSAGE_SIZE: usize = 256;
+/// Number of the latency samples the stats are calculated from
+pub const PROBE_SAMPLES_WINDOW: usize = 1000;
+/// Echoes that come later than that are not counted
+pub const PROBE_ECHO_TIMEOUT_MS: i64 = 120_000;
+/// Max number of the messages per source per minute that are relayed on the topic
+pub const PROBE_MAX_MESSAGES_PER_MIN: usize = 12;
+// Number of our recent probes that wait for the echoes
+const PENDING_PROBES: usize = 16;
+// We answer the probes of the same origin not more often than that
+const MIN_ECHO_INTERVAL_MS: i64 = 10_000;
+// Onion v3 address is 56 chars, some room for the normalization differences
+const MAX_ADDRESS_LEN: usize = 100;
+
+/// Message of the probe topic
+#[derive(Debug, Clone, PartialEq)]
+pub enum GossipProbeMessage {
+	/// Probe, the origin is the message source
+	Probe {
+		/// Sequence number of the probe at the origin
+		seq: u64,
+	},
+	/// Answer to the probe
+	Echo {
+		/// Onion address of the probe origin
+		origin: String,
+		/// Sequence number of the probe
+		seq: u64,
+		/// The probe came to the responder through some other node
+		relayed: bool,
+	},
+}
+
+impl Writeable for GossipProbeMessage {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		match self {
+			GossipProbeMessage::Probe { seq } => {
+				writer.write_u8(0)?;
+				writer.write_u64(*seq)
+			}
+			GossipProbeMessage::Echo {
+				origin,
+				seq,
+				relayed,
+			} => {
+				writer.write_u8(1)?;
+				writer.write_bytes(origin)?;
+				writer.write_u64(*seq)?;
+				writer.write_u8(*relayed as u8)
+			}
+		}
+	}
+}
+
+impl Readable for GossipProbeMessage {
+	fn read<R: Reader>(reader: &mut R) -> Result<GossipProbeMessage, ser::Error> {
+		match reader.read_u8()? {
+			0 => Ok(GossipProbeMessage::Probe {
+				seq: reader.read_u64()?,
+			}),
+			1 => {
+				let origin = reader.read_bytes_len_prefix()?;
+				if origin.len() > MAX_ADDRESS_LEN {
+					return Err(ser::Error::TooLargeReadErr(format!(
+						"Probe origin is too long, {}",
+						origin.len()
+					)));
+				}
+				let origin = String::from_utf8(origin).map_err(|e| {
+					ser::Error::CorruptedData(format!("Invalid probe origin, {}", e))
+				})?;
+				Ok(GossipProbeMessage::Echo {
+					origin,
+					seq: reader.read_u64()?,
+					relayed: reader.read_u8()? != 0,
+				})
+			}
+			_ => Err(ser::Error::CorruptedData(
+				"Unknown probe message type".to_string(),
+			)),
+		}
+	}
+}
+
+/// Round trip time statistics, in milliseconds
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProbeLatencyStats {
+	/// Number of the samples
+	pub samples: usize,
+	/// Min round trip time
+	pub min_ms: u64,
+	/// Median round trip time
+	pub median_ms: u64,
+	/// 90th percentile of the round trip time
+	pub p90_ms: u64,
+	/// Max round trip time
+	pub max_ms: u64,
+	/// Mean round trip time
+	pub mean_ms: u64,
+}
+
+impl ProbeLatencyStats {
+	/// Stats of the samples, None if there are no samples
+	pub fn from_samples(mut samples: Vec<u64>) -> Option<ProbeLatencyStats> {
+		if samples.is_empty() {
+			return None;
+		}
+		samples.sort_unstable();
+		let n = samples.len();
+		Some(ProbeLatencyStats {
+			samples: n,
+			min_ms: samples[0],
+			median_ms: samples[n / 2],
+			p90_ms: samples[(n * 9 / 10).min(n - 1)],
+			max_ms: samples[n - 1],
+			mean_ms: samples.iter().sum::<u64>() / n as u64,
+		})
+	}
+}
+
+/// Aggregated gossip mesh latency
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GossipProbeStats {
+	/// Probes are enabled on this node
+	pub enabled: bool,
+	/// Interval between our probes, in seconds
+	pub interval_secs: u64,
+	/// Number of the probes we published
+	pub probes_sent: u64,
+	/// Number of the echoes received for our probes
+	pub echoes_received: u64,
+	/// Number of the distinct nodes that answered our recent probes
+	pub responders: usize,
+	/// Round trip time of all recent echoes
+	pub all: Option<ProbeLatencyStats>,
+	/// Round trip time of the direct neighbours, one hop each way
+	pub direct: Option<ProbeLatencyStats>,
+	/// Round trip time of the echoes that were relayed on the way
+	pub relayed: Option<ProbeLatencyStats>,
+}
+
+struct PendingProbe {
+	seq: u64,
+	sent_ms: i64,
+	responders: HashSet<String>,
+}
+
+/// Probes of this node and the echo state
+pub struct GossipProbes {
+	interval_secs: u64,
+	seq: u64,
+	probes_sent: u64,
+	echoes_received: u64,
+	pending: VecDeque<PendingProbe>,
+	// Round trip time, relayed flag and the responder
+	samples: VecDeque<(u64, bool, String)>,
+	// Last echo time per probe origin
+	last_echo: HashMap<String, i64>,
+	// Recent message times per source, for the rate limit
+	source_messages: HashMap<String, VecDeque<i64>>,
+}
+
+impl GossipProbes {
+	/// Probes that are published with the interval
+	pub fn new(interval_secs: u64) -> GossipProbes {
+		GossipProbes {
+			interval_secs,
+			seq: 0,
+			probes_sent: 0,
+			echoes_received: 0,
+			pending: VecDeque::new(),
+			samples: VecDeque::new(),
+			last_echo: HashMap::new(),
+			source_messages: HashMap::new(),
+		}
+	}
+
+	/// Interval between the probes, in seconds
+	pub fn interval_secs(&self) -> u64 {
+		self.interval_secs
+	}
+
+	/// New probe to publish
+	pub fn next_probe(&mut self, now_ms: i64) -> GossipProbeMessage {
+		self.seq += 1;
+		self.probes_sent += 1;
+		self.pending.push_back(PendingProbe {
+			seq: self.seq,
+			sent_ms: now_ms,
+			responders: HashSet::new(),
+		});
+		while self.pending.len() > PENDING_PROBES {
+			self.pending.pop_front();
+		}
+		GossipProbeMessage::Probe { seq: self.seq }
+	}
+
+	/// Check the rate limit of the source. Messages above the limit are not relayed.
+	pub fn allow_message(&mut self, source: &str, now_ms: i64) -> bool {
+		let times = self
+			.source_messages
+			.entry(source.to_string())
+			.or_insert_with(VecDeque::new);
+		while times.front().map(|t| *t <= now_ms - 60_000) == Some(true) {
+			times.pop_front();
+		}
+		if times.len() >= PROBE_MAX_MESSAGES_PER_MIN {
+			return false;
+		}
+		times.push_back(now_ms);
+		true
+	}
+
+	/// Probe of the other node is received. Returns the echo to publish, None if we
+	/// answered that origin recently.
+	pub fn on_probe(
+		&mut self,
+		origin: &str,
+		seq: u64,
+		relayed: bool,
+		now_ms: i64,
+	) -> Option<GossipProbeMessage> {
+		if let Some(last) = self.last_echo.get(origin) {
+			if now_ms - *last < MIN_ECHO_INTERVAL_MS {
+				return None;
+			}
+		}
+		self.last_echo.insert(origin.to_string(), now_ms);
+		Some(GossipProbeMessage::Echo {
+			origin: origin.to_string(),
+			seq,
+			relayed,
+		})
+	}
+
+	/// Echo to our probe is received. Returns false if the echo is not expected: unknown
+	/// or expired probe, or the responder already answered it.
+	pub fn on_echo(&mut self, responder: &str, seq: u64, relayed: bool, now_ms: i64) -> bool {
+		let probe = match self.pending.iter_mut().find(|p| p.seq == seq) {
+			Some(probe) => probe,
+			None => return false,
+		};
+		let rtt = now_ms - probe.sent_ms;
+		if rtt < 0 || rtt > PROBE_ECHO_TIMEOUT_MS || !probe.responders.insert(responder.to_string())
+		{
+			return false;
+		}
+		self.echoes_received += 1;
+		self.samples
+			.push_back((rtt as u64, relayed, responder.to_string()));
+		while self.samples.len() > PROBE_SAMPLES_WINDOW {
+			self.samples.pop_front();
+		}
+		true
+	}
+
+	/// Drop the state of the sources that were silent since the time
+	pub fn prune(&mut self, min_time_ms: i64) {
+		self.last_echo.retain(|_, t| *t > min_time_ms);
+		self.source_messages
+			.retain(|_, times| times.back().map(|t| *t > min_time_ms) == Some(true));
+	}
+
+	/// Aggregated latency of the recent echoes
+	pub fn stats(&self) -> GossipProbeStats {
+		let select = |relayed: Option<bool>| {
+			self.samples
+				.iter()
+				.filter(|(_, r, _)| relayed.map_or(true, |relayed| *r == relayed))
+				.map(|(rtt, _, _)| *rtt)
+				.collect::<Vec<u64>>()
+		};
+		let responders: HashSet<&String> = self.samples.iter().map(|(_, _, r)| r).collect();
+		GossipProbeStats {
+			enabled: true,
+			interval_secs: self.interval_secs,
+			probes_sent: self.probes_sent,
+			echoes_received: self.echoes_received,
+			responders: responders.len(),
+			all: ProbeLatencyStats::from_samples(select(None)),
+			direct: ProbeLatencyStats::from_samples(select(Some(false))),
+			relayed: ProbeLatencyStats::from_samples(select(Some(true))),
+		}
+	}
+}
+
+lazy_static! {
+	// Probes of the node, None if the probes are not enabled
+	pub(crate) static ref GOSSIP_PROBES: Mutex<Option<GossipProbes>> = Mutex::new(None);
+}
+
+/// Enable the probes with the interval in seconds. 0 disables them.
+pub fn init_gossip_probes(interval_secs: u64) {
+	*GOSSIP_PROBES.lock() = if interval_secs > 0 {
+		Some(GossipProbes::new(interval_secs))
+	} else {
+		None
+	};
+}
+
+/// True if the probes are enabled on this node
+pub fn is_gossip_probes_enabled() -> bool {
+	GOSSIP_PROBES.lock().is_some()
+}
+
+/// Aggregated gossip mesh latency measured by the probes
+pub fn gossip_probe_stats() -> GossipProbeStats {
+	match &*GOSSIP_PROBES.lock() {
+		Some(probes) => probes.stats(),
+		None => GossipProbeStats::default(),
+	}
+}
@@ -0,0 +1,277 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Zero-downtime upgrade handoff. The running node binds its listeners with SO_REUSEPORT,
+//! so the new node can bind the same ports while the old one is still running. The new
+//! node binds its listeners first, then requests the state of the old node (known peers
+//! and txpool transactions) on the local handoff socket. The old node sends the state,
+//! stops accepting the connections and exits. Connections that arrive in between wait in
+//! the listen backlog of the new node instead of being refused.
+//! Handoff socket is a unix domain socket, the handoff is not supported on other platforms.
+
+use crate::core::core::Transaction;
+use crate::store::PeerData;
+use crate::types::Error;
+use crate::util::Mutex;
+use futures::channel::oneshot;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Current handoff format version
+pub const HANDOFF_VERSION: u16 = 1;
+/// Name of the handoff socket file in the node db root
+pub const HANDOFF_SOCKET_FILE: &str = "mwc_handoff.sock";
+
+const HANDOFF_REQUEST: &str = "mwc-handoff-request";
+const HANDOFF_ACK: &str = "mwc-handoff-done";
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// State of the old node passed to the new one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoffState {
+	/// Handoff format version
+	pub version: u16,
+	/// Peers known by the old node
+	pub peers: Vec<PeerData>,
+	/// Transactions of the old node txpool. Stempool is not passed, these transactions
+	/// are still in the Dandelion stem phase.
+	pub txs: Vec<Transaction>,
+}
+
+// Listeners are bound with SO_REUSEPORT
+static REUSE_PORT: AtomicBool = AtomicBool::new(false);
+// The state is handed off, the listeners should stop accepting
+static HANDED_OFF: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+	// Listeners bound by the new node before the handoff
+	static ref PREBOUND: Mutex<HashMap<SocketAddr, TcpListener>> = Mutex::new(HashMap::new());
+	// State received from the old node, applied once the new node is started
+	static ref PENDING_STATE: Mutex<Option<HandoffState>> = Mutex::new(None);
+	// Listeners waiting for the handoff to stop accepting
+	static ref HANDOFF_WAITERS: Mutex<Vec<oneshot::Sender<()>>> = Mutex::new(vec![]);
+}
+
+/// Bind the listeners with SO_REUSEPORT, so the next node version can take them over
+pub fn set_reuse_port(enabled: bool) {
+	REUSE_PORT.store(enabled, Ordering::Relaxed);
+}
+
+/// True if the node state is handed off to the new node and this node is exiting
+pub fn is_handed_off() -> bool {
+	HANDED_OFF.load(Ordering::Relaxed)
+}
+
+/// Signal that fires when the node state is handed off. The async listeners stop accepting
+/// on it, so the new connections go to the new node only.
+pub fn handoff_signal() -> oneshot::Receiver<()> {
+	let (sender, receiver) = oneshot::channel();
+	let mut waiters = HANDOFF_WAITERS.lock();
+	if is_handed_off() {
+		let _ = sender.send(());
+	} else {
+		waiters.push(sender);
+	}
+	receiver
+}
+
+// Mark the node as handed off and notify the listeners
+fn set_handed_off() {
+	let mut waiters = HANDOFF_WAITERS.lock();
+	HANDED_OFF.store(true, Ordering::Relaxed);
+	for sender in waiters.drain(..) {
+		let _ = sender.send(());
+	}
+}
+
+/// Path of the handoff socket for the node db root
+pub fn handoff_socket_path(db_root: &str) -> PathBuf {
+	Path::new(db_root).join(HANDOFF_SOCKET_FILE)
+}
+
+fn bind_reuse_port(addr: &SocketAddr) -> io::Result<TcpListener> {
+	let builder = match addr {
+		SocketAddr::V4(_) => net2::TcpBuilder::new_v4()?,
+		SocketAddr::V6(_) => net2::TcpBuilder::new_v6()?,
+	};
+	#[cfg(unix)]
+	{
+		use net2::unix::UnixTcpBuilderExt;
+		builder.reuse_address(true)?;
+		builder.reuse_port(true)?;
+	}
+	builder.bind(addr)?;
+	builder.listen(LISTEN_BACKLOG)
+}
+
+/// Bind the listener before the handoff. The listener is used by `bind_listener`
+/// once the new node starts.
+pub fn prebind(addr: SocketAddr) -> io::Result<()> {
+	let listener = bind_reuse_port(&addr)?;
+	PREBOUND.lock().insert(addr, listener);
+	Ok(())
+}
+
+/// Listener for the node. It is the one bound before the handoff if there is such,
+/// otherwise a new one, with SO_REUSEPORT if enabled.
+pub fn bind_listener(addr: SocketAddr) -> io::Result<TcpListener> {
+	if let Some(listener) = PREBOUND.lock().remove(&addr) {
+		return Ok(listener);
+	}
+	if REUSE_PORT.load(Ordering::Relaxed) {
+		bind_reuse_port(&addr)
+	} else {
+		TcpListener::bind(addr)
+	}
+}
+
+/// Keep the state received from the old node until the new node is started
+pub fn set_pending_state(state: HandoffState) {
+	*PENDING_STATE.lock() = Some(state);
+}
+
+/// True if the node is taking over from the old node
+pub fn has_pending_state() -> bool {
+	PENDING_STATE.lock().is_some()
+}
+
+/// State received from the old node, if any
+pub fn take_pending_state() -> Option<HandoffState> {
+	PENDING_STATE.lock().take()
+}
+
+/// Serve the node state on the handoff socket. `state` is called when the new node
+/// requests the handoff, `done` once the new node confirmed it got the state.
+#[cfg(unix)]
+pub fn serve_handoff<S, D>(path: PathBuf, state: S, done: D) -> Result<(), Error>
+where
+	S: Fn() -> HandoffState + Send + 'static,
+	D: Fn() + Send + 'static,
+{
+	use std::os::unix::net::UnixListener;
+	use std::time::Duration;
+
+	// Socket of the previous run
+	let _ = std::fs::remove_file(&path);
+	let listener = UnixListener::bind(&path)?;
+	std::thread::Builder::new()
+		.name("handoff".to_string())
+		.spawn(move || {
+			for stream in listener.incoming() {
+				let mut stream = match stream {
+					Ok(stream) => stream,
+					Err(e) => {
+						error!("Handoff socket accept error, {}", e);
+						continue;
+					}
+				};
+				let _ = stream.set_read_timeout(Some(Duration::from_secs(30)));
+				let res = (|| -> Result<(), Error> {
+					let mut reader = BufReader::new(stream.try_clone()?);
+					let mut line = String::new();
+					reader.read_line(&mut line)?;
+					if line.trim() != HANDOFF_REQUEST {
+						return Err(Error::Internal(format!(
+							"Unexpected handoff request {}",
+							line.trim()
+						)));
+					}
+					let state = state();
+					info!(
+						"Handing off {} peers and {} transactions to the new node",
+						state.peers.len(),
+						state.txs.len()
+					);
+					let data = serde_json::to_string(&state)
+						.map_err(|e| Error::Internal(format!("Unable to encode state, {}", e)))?;
+					stream.write_all(data.as_bytes())?;
+					stream.write_all(b"\n")?;
+					line.clear();
+					reader.read_line(&mut line)?;
+					if line.trim() != HANDOFF_ACK {
+						return Err(Error::Internal(
+							"New node didn't confirm the handoff".to_string(),
+						));
+					}
+					Ok(())
+				})();
+				match res {
+					Ok(_) => {
+						warn!("Node state is handed off to the new node, exiting");
+						// Listeners stop accepting before the node is stopped
+						set_handed_off();
+						let _ = std::fs::remove_file(&path);
+						done();
+						break;
+					}
+					Err(e) => error!("Handoff failed, {}", e),
+				}
+			}
+		})?;
+	Ok(())
+}
+
+/// Serve the node state on the handoff socket. Not supported on this platform.
+#[cfg(not(unix))]
+pub fn serve_handoff<S, D>(_path: PathBuf, _state: S, _done: D) -> Result<(), Error>
+where
+	S: Fn() -> HandoffState + Send + 'static,
+	D: Fn() + Send + 'static,
+{
+	Err(Error::Internal(
+		"Upgrade handoff is supported on unix only".to_string(),
+	))
+}
+
+/// Request the state from the old node on the handoff socket. The old node exits
+/// once the state is received.
+#[cfg(unix)]
+pub fn request_handoff(path: &Path) -> Result<HandoffState, Error> {
+	use std::os::unix::net::UnixStream;
+	use std::time::Duration;
+
+	let mut stream = UnixStream::connect(path).map_err(|e| {
+		Error::Internal(format!(
+			"Unable to connect to the running node at {}, {}",
+			path.display(),
+			e
+		))
+	})?;
+	let _ = stream.set_read_timeout(Some(Duration::from_secs(120)));
+	stream.write_all(format!("{}\n", HANDOFF_REQUEST).as_bytes())?;
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let mut line = String::new();
+	reader.read_line(&mut line)?;
+	let state: HandoffState = serde_json::from_str(line.trim())
+		.map_err(|e| Error::Internal(format!("Unable to decode the handoff state, {}", e)))?;
+	if state.version != HANDOFF_VERSION {
+		return Err(Error::Internal(format!(
+			"Unsupported handoff version {}",
+			state.version
+		)));
+	}
+	stream.write_all(format!("{}\n", HANDOFF_ACK).as_bytes())?;
+	Ok(state)
+}
+
+/// Request the state from the old node. Not supported on this platform.
+#[cfg(not(unix))]
+pub fn request_handoff(_path: &Path) -> Result<HandoffState, Error> {
+	Err(Error::Internal(
+		"Upgrade handoff is supported on unix only".to_string(),
+	))
+}
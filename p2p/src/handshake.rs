@@ -16,13 +16,15 @@ use crate::conn::Tracker;
 use crate::core::core::hash::Hash;
 use crate::core::pow::Difficulty;
 use crate::core::ser::ProtocolVersion;
-use crate::msg::{read_message, write_message, Hand, Msg, Shake, TorAddress, Type, USER_AGENT};
+use crate::local_address;
+use crate::msg::{read_message, write_message, Hand, Msg, Shake, TorAddress, Type};
 use crate::peer::Peer;
 use crate::types::{
 	Capabilities, Direction, Error, P2PConfig, PeerAddr, PeerAddr::Ip, PeerAddr::Onion, PeerInfo,
 	PeerLiveInfo,
 };
 use crate::util::RwLock;
+use crate::version_census;
 use rand::{thread_rng, Rng};
 use std::collections::VecDeque;
 use std::net::{SocketAddr, TcpStream};
@@ -131,7 +133,7 @@ impl Handshake {
 			total_difficulty,
 			sender_addr: self_addr.clone(),
 			receiver_addr: peer_addr.clone(),
-			user_agent: USER_AGENT.to_string(),
+			user_agent: self.config.user_agent(),
 		};
 
 		// write and read the handshake response
@@ -162,6 +164,7 @@ impl Handshake {
 		}
 
 		let negotiated_version = self.negotiate_protocol_version(shake.version)?;
+		let advertised_version = shake.version.value();
 
 		let peer_info = PeerInfo {
 			capabilities: shake.capabilities,
@@ -185,6 +188,12 @@ impl Handshake {
 			return Err(Error::ConnectionClose);
 		}
 
+		version_census::record_peer_version(
+			peer_info.addr.clone(),
+			&peer_info.user_agent,
+			advertised_version,
+		);
+
 		debug!(
 			"Connected! Cumulative {} offered from {:?}, {:?}, {:?}, {:?}",
 			shake.total_difficulty.to_num(),
@@ -233,6 +242,12 @@ impl Handshake {
 		}
 
 		let negotiated_version = self.negotiate_protocol_version(hand.version)?;
+		let advertised_version = hand.version.value();
+
+		// The peer tells the address it dialed, it is a vote for our external address
+		if let (Ip(observed), Ok(peer_addr)) = (&hand.receiver_addr, conn.peer_addr()) {
+			local_address::record_observed_address(peer_addr.ip(), *observed);
+		}
 
 		// all good, keep peer info
 		let peer_info = PeerInfo {
@@ -259,13 +274,19 @@ impl Handshake {
 			return Err(Error::ConnectionClose);
 		}
 
+		version_census::record_peer_version(
+			peer_info.addr.clone(),
+			&peer_info.user_agent,
+			advertised_version,
+		);
+
 		// send our reply with our info
 		let shake = Shake {
 			version: self.protocol_version,
 			capabilities: capab,
 			genesis: self.genesis,
 			total_difficulty: total_difficulty,
-			user_agent: USER_AGENT.to_string(),
+			user_agent: self.config.user_agent(),
 		};
 
 		let msg = Msg::new(Type::Shake, shake, negotiated_version)?;
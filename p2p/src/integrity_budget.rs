@@ -0,0 +1,140 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Integrity kernels budget of the node that publishes its own gossip messages. Every
+//! message is paid with an integrity kernel. The kernel is accepted by the peers while it
+//! is in the last INTEGRITY_FEE_VALID_BLOCKS blocks, and the peers reject the messages
+//! of the kernel that is used more often than the rate limit allows. The operator registers
+//! the available kernels, the node tracks their usage, so the wallet knows when to top up.
+
+use crate::util::secp::pedersen::Commitment;
+use crate::util::{clock, RwLock};
+use std::collections::{HashMap, VecDeque};
+
+// Message with same integrity output consensus. Default limits of Libp2pPolicy, the budget
+// is calculated with them because we don't know the policy of the peers.
+// History of the calls. 10 calls should be enough to compensate some glitches
+pub const INTEGRITY_CALL_HISTORY_LEN_LIMIT: usize = 10;
+// call interval limit, in second.
+pub const INTEGRITY_CALL_MAX_PERIOD: i64 = 15;
+
+/// Number of top block when integrity fee is valid.  24 hours + 3 blocks. 3 blocks it is a minimum confirmations numbers that is required
+pub const INTEGRITY_FEE_VALID_BLOCKS: u64 = 1443;
+
+/// Kernel is reported as expiring when less blocks than that are left in its validity window
+pub const INTEGRITY_EXPIRY_ALERT_BLOCKS: u64 = 60;
+
+/// Usage of the registered integrity kernel
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntegrityKernelUsage {
+	/// Kernel excess
+	pub excess: Commitment,
+	/// Unix time when the kernel was registered
+	pub registered: i64,
+	/// Number of the messages published with this kernel
+	pub messages_published: u64,
+	/// Publish times of the recent messages, the peers check the rate limit on them
+	pub recent_publishes: Vec<i64>,
+}
+
+struct KernelRecord {
+	registered: i64,
+	messages_published: u64,
+	recent_publishes: VecDeque<i64>,
+}
+
+lazy_static! {
+	// Integrity kernels registered by the operator
+	static ref INTEGRITY_KERNELS: RwLock<HashMap<Commitment, KernelRecord>> =
+		RwLock::new(HashMap::new());
+}
+
+/// Register the integrity kernel for the budget tracking. Returns false if it is
+/// already registered.
+pub fn register_integrity_kernel(excess: Commitment) -> bool {
+	let mut kernels = INTEGRITY_KERNELS.write();
+	if kernels.contains_key(&excess) {
+		return false;
+	}
+	kernels.insert(
+		excess,
+		KernelRecord {
+			registered: clock::now().timestamp(),
+			messages_published: 0,
+			recent_publishes: VecDeque::new(),
+		},
+	);
+	true
+}
+
+/// Stop tracking the integrity kernel. Returns false if it is not registered.
+pub fn remove_integrity_kernel(excess: &Commitment) -> bool {
+	INTEGRITY_KERNELS.write().remove(excess).is_some()
+}
+
+/// Register the message published with the integrity kernel. Messages of the
+/// kernels that are not registered are not tracked.
+pub fn record_integrity_publish(excess: &Commitment, time: i64) {
+	if let Some(record) = INTEGRITY_KERNELS.write().get_mut(excess) {
+		record.messages_published += 1;
+		record.recent_publishes.push_back(time);
+		while record.recent_publishes.len() > INTEGRITY_CALL_HISTORY_LEN_LIMIT {
+			record.recent_publishes.pop_front();
+		}
+	}
+}
+
+/// Usage of all registered integrity kernels
+pub fn integrity_kernels() -> Vec<IntegrityKernelUsage> {
+	let mut res: Vec<IntegrityKernelUsage> = INTEGRITY_KERNELS
+		.read()
+		.iter()
+		.map(|(excess, record)| IntegrityKernelUsage {
+			excess: excess.clone(),
+			registered: record.registered,
+			messages_published: record.messages_published,
+			recent_publishes: record.recent_publishes.iter().cloned().collect(),
+		})
+		.collect();
+	res.sort_by_key(|k| k.registered);
+	res
+}
+
+// Same check as the peers do for the received messages
+fn is_rate_limited(history: &VecDeque<i64>) -> bool {
+	if history.len() < INTEGRITY_CALL_HISTORY_LEN_LIMIT {
+		return false;
+	}
+	let first = *history.front().unwrap_or(&0);
+	let last = *history.back().unwrap_or(&0);
+	(last - first) / ((history.len() - 1) as i64) < INTEGRITY_CALL_MAX_PERIOD
+}
+
+/// Number of the messages that can be published with the kernel right now without
+/// hitting the peers rate limit.
+pub fn publish_rate_budget(recent_publishes: &[i64], now: i64) -> usize {
+	let mut history: VecDeque<i64> = recent_publishes.iter().cloned().collect();
+	let mut budget = 0;
+	while budget < INTEGRITY_CALL_HISTORY_LEN_LIMIT {
+		history.push_back(now);
+		while history.len() > INTEGRITY_CALL_HISTORY_LEN_LIMIT {
+			history.pop_front();
+		}
+		if is_rate_limited(&history) {
+			break;
+		}
+		budget += 1;
+	}
+	budget
+}
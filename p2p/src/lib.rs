@@ -39,30 +39,85 @@ extern crate log;
 #[macro_use]
 extern crate lazy_static;
 
+mod block_fetch;
+#[cfg(feature = "msg_capture")]
+pub mod capture;
+pub mod conformance;
 mod conn;
+pub mod conn_stats;
+pub mod dial_scheduler;
+pub mod gossip_audit;
+pub mod gossip_probe;
+pub mod handoff;
 pub mod handshake;
+pub mod integrity_budget;
 #[cfg(feature = "marketplace")]
 pub mod libp2p_connection;
+pub mod libp2p_dial;
+pub mod libp2p_kad;
+pub mod libp2p_peer_store;
+#[cfg(feature = "marketplace")]
+pub mod libp2p_query;
+pub mod libp2p_relay;
+#[cfg(feature = "marketplace")]
+pub mod libp2p_sandbox;
+#[cfg(feature = "marketplace")]
+pub mod libp2p_schema;
+pub mod libp2p_seen_cache;
+pub mod libp2p_stats;
+pub mod local_address;
 pub mod msg;
 mod peer;
+pub mod peer_exchange;
+pub mod peer_quarantine;
+pub mod peer_snapshot;
 mod peers;
 mod protocol;
 mod serv;
 mod store;
+pub mod sync_data;
+#[cfg(feature = "test_vectors")]
+pub mod test_vectors;
 pub mod types;
+pub mod version_census;
 
 pub use crate::conn::SEND_CHANNEL_CAP;
+pub use crate::conn_stats::{PeerConnStats, SlowPeerCounters, SlowPeerReason, SlowPeerThresholds};
+pub use crate::dial_scheduler::{dial_scheduler, DialLimits, DialPriority, DialScheduler};
+pub use crate::gossip_audit::{GossipAuditEntry, GossipAuditQuery};
+pub use crate::gossip_probe::{GossipProbeStats, ProbeLatencyStats};
+pub use crate::libp2p_dial::{DialFailure, DialStats};
+pub use crate::libp2p_kad::KadStats;
+pub use crate::libp2p_relay::RelayStats;
+pub use crate::libp2p_stats::{
+	get_libp2p_connections_info, get_libp2p_stats, Libp2pConnection, Libp2pStats, PeerTraffic,
+	TopicTraffic,
+};
 pub use crate::peer::Peer;
+pub use crate::peer_quarantine::QuarantinedPeer;
+pub use crate::peer_snapshot::{PeerSnapshot, PeerSnapshotImport};
 pub use crate::peers::Peers;
-pub use crate::serv::{DummyAdapter, Server};
-pub use crate::store::{PeerData, State};
+pub use crate::serv::{loopback_inbound_count, DummyAdapter, Server};
+pub use crate::store::{Libp2pPeerAccess, Libp2pPeerBan, PeerData, PeerSyncRecord, State};
+pub use crate::sync_data::{PeerSyncData, SyncStage};
 pub use crate::types::{
-	Capabilities, ChainAdapter, Direction, Error, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	Seeding, TxHashSetRead, MAX_BLOCK_HEADERS, MAX_LOCATORS, MAX_PEER_ADDRS,
+	Capabilities, ChainAdapter, Direction, Error, GossipValidationMode, HeaderFirstStats,
+	Libp2pConfig, Libp2pIdentity, Libp2pPolicy, Libp2pTransport, P2PConfig, PeerAddr, PeerInfo,
+	ReasonForBan, Seeding, TxHashSetRead, MAX_BLOCK_HEADERS, MAX_LOCATORS, MAX_PEER_ADDRS,
 };
+pub use crate::version_census::{VersionCensusReport, VersionCount};
 
 #[cfg(feature = "marketplace")]
 pub use crate::libp2p_connection::{
-	add_new_peer, build_integrity_message, get_libp2p_connections, read_message_data,
-	run_libp2p_node, set_seed_list,
+	add_new_peer, add_query_handler, build_integrity_message, get_libp2p_connections,
+	is_peer_allowed, libp2p_node, query_peer, read_message_data, remove_query_handler,
+	run_libp2p_node, set_seed_list, sync_topic_handler, topic_handler, HandlerId, Libp2pNode,
+	Libp2pNodeHandle, MessageContext, TopicHandler,
+};
+#[cfg(feature = "marketplace")]
+pub use crate::libp2p_query::{query_handler, QueryHandler, QueryRequest, QueryResponse};
+#[cfg(feature = "marketplace")]
+pub use crate::libp2p_schema::{
+	register_payload_schema, unregister_payload_schema, IntegrityPayload, PayloadDecoding,
+	PayloadSchema,
 };
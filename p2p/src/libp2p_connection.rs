@@ -20,43 +20,84 @@ use libp2p::core::Multiaddr;
 use libp2p::{
 	core::{
 		muxing::StreamMuxerBox,
+		transport::{OptionalTransport, OrTransport},
 		upgrade::{SelectUpgrade, Version},
 		SimplePopSerializer, SimplePushSerializer,
 	},
 	dns::DnsConfig,
 	identity::Keypair,
+	kad::{record::store::MemoryStore, Kademlia, KademliaEvent},
 	mplex::MplexConfig,
 	noise::{self, NoiseConfig, X25519Spec},
-	swarm::SwarmBuilder,
+	relay::Relay,
+	request_response::{RequestId, RequestResponse, RequestResponseEvent, RequestResponseMessage},
+	swarm::{toggle::Toggle, SwarmBuilder},
+	tcp::TokioTcpConfig,
 	yamux::YamuxConfig,
-	PeerId, Swarm, Transport,
+	NetworkBehaviour, PeerId, Swarm, Transport,
 };
 use libp2p_tokio_socks5::Socks5TokioTcpConfig;
 
 use libp2p::gossipsub::{
-	self, GossipsubEvent, IdentTopic as Topic, MessageAuthenticity, MessageId, ValidationMode,
+	self, GossipsubConfig, GossipsubEvent, IdentTopic as Topic, MessageAuthenticity, MessageId,
+	ValidationMode,
 };
 use libp2p::gossipsub::{Gossipsub, MessageAcceptance, TopicHash};
 
 use crate::core::global;
-use crate::types::Error;
+use crate::dial_scheduler::{dial_scheduler, DialPriority};
+use crate::gossip_audit::{self, GossipAuditEntry};
+use crate::gossip_probe::{self, GossipProbeMessage, MAX_PROBE_MESSAGE_SIZE, PROBE_TOPIC};
+use crate::integrity_budget;
+use crate::libp2p_dial::{
+	backed_off_peers, dial_retries, forget_dial_failures, is_dial_allowed, is_proxy_down, DialPool,
+};
+use crate::libp2p_kad::{build_kademlia, KadDiscovery, KAD_SEED_PEERS};
+use crate::libp2p_peer_store;
+use crate::libp2p_query::{
+	build_query_behaviour, QueryCodec, QueryHandler, QueryRequest, QueryResponse, HAS_KERNEL_METHOD,
+};
+use crate::libp2p_relay::{
+	build_relay, record_connected_relays, record_relayed_dial, relayed_dial_addr, throttle_relayed,
+};
+use crate::libp2p_sandbox::{handler_sandbox, HandlerOutcome};
+use crate::libp2p_schema::{
+	decode_payload, default_payload_schema, integrity_message_version, IntegrityPayload,
+	PayloadDecoding,
+};
+use crate::libp2p_seen_cache;
+use crate::libp2p_stats::{self, ValidationResult};
+use crate::peer_exchange::{
+	PeerExchangeMessage, PeerExchangeOutcome, PeerExchangeReceiver, PeerExchangeSender,
+	PEER_EXCHANGE_DIFF_VERSION,
+};
+use crate::peer_quarantine::{self, is_peer_quarantined};
+use crate::store::{Libp2pPeerAccess, Libp2pPeerBan};
+use crate::types::{Error, GossipValidationMode, Libp2pConfig, Libp2pPolicy, ReasonForBan};
 use crate::PeerAddr;
 use async_std::task;
 use chrono::Utc;
 use ed25519_dalek::PublicKey as DalekPublicKey;
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
 use futures::{future, prelude::*};
-use grin_core::core::hash::Hash;
+use grin_core::core::hash::{Hash, HashWriter};
 use grin_core::core::TxKernel;
 use grin_core::libtx::aggsig;
+use grin_core::ser::{self, Writer};
 use grin_util::secp::pedersen::Commitment;
 use grin_util::secp::rand::Rng;
 use grin_util::secp::{ContextFlag, Message, Secp256k1, Signature};
+use grin_util::subsystem::{self, SUBSYSTEM_LIBP2P};
 use grin_util::RwLock;
-use grin_util::{Mutex, OnionV3Address, OnionV3AddressError, ToHex};
+use grin_util::{clock, Mutex, OnionV3Address, OnionV3AddressError, ToHex};
 use libp2p::core::network::NetworkInfo;
 use rand::seq::SliceRandom;
-use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet, VecDeque};
 use std::convert::TryInto;
+use std::hash::Hasher;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 use std::{
@@ -69,7 +110,11 @@ use std::{
 struct TokioExecutor;
 impl libp2p::core::Executor for TokioExecutor {
 	fn exec(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
-		tokio::spawn(future);
+		let usage = subsystem::track_task(SUBSYSTEM_LIBP2P);
+		tokio::spawn(async move {
+			let _usage = usage;
+			future.await
+		});
 	}
 }
 
@@ -86,426 +131,2106 @@ pub struct ReceivedMessage {
 	pub fee: u64,
 	/// The message.
 	pub message: String,
+	/// Gossipsub message id
+	#[serde(default)]
+	pub message_id: String,
+	/// Blake2b hash of the message data, the same data published by different peers has the same hash
+	#[serde(default)]
+	pub content_hash: String,
+	/// libp2p peer that published the message
+	#[serde(default)]
+	pub source_peer_id: Option<String>,
+	/// libp2p peer that forwarded the message to us
+	#[serde(default)]
+	pub propagation_peer_id: String,
+}
+
+/// Metadata of the received gossip message that is passed to the topic handlers, so
+/// the handlers can dedupe the messages, trace their origin and route the replies.
+#[derive(Clone, Debug)]
+pub struct MessageContext {
+	/// Gossipsub message id
+	pub message_id: MessageId,
+	/// Blake2b hash of the message data (no integrity header)
+	pub content_hash: Hash,
+	/// Peer that published the message. None if the message doesn't carry the author.
+	pub source: Option<PeerId>,
+	/// Peer that forwarded the message to us
+	pub propagation_source: PeerId,
+	/// Topic that received the message
+	pub topic: TopicHash,
+	/// Onion address of the integrity output owner
+	pub sender_address: String,
+	/// Integrity fee that was paid
+	pub fee: u64,
+	/// Unix timestamp when this message was received
+	pub received: i64,
+}
+
+/// Topic message handler, arguments: message metadata and the message data (no header).
+/// Handler resolves to false if the message is incorrect, so the peer must be banned.
+/// Handlers are closures, they can capture the chain or pool references, and they run
+/// on the handler executor, so they can await without blocking the swarm polling loop.
+pub type TopicHandler =
+	Arc<dyn Fn(MessageContext, Vec<u8>) -> BoxFuture<'static, bool> + Send + Sync>;
+
+/// Topic handler from the async closure
+pub fn topic_handler<F, Fut>(handler: F) -> TopicHandler
+where
+	F: Fn(MessageContext, Vec<u8>) -> Fut + Send + Sync + 'static,
+	Fut: Future<Output = bool> + Send + 'static,
+{
+	Arc::new(move |context, data| handler(context, data).boxed())
+}
+
+/// Topic handler from the plain function. It runs on the executor thread, so it should
+/// not do long blocking calls.
+pub fn sync_topic_handler<F>(handler: F) -> TopicHandler
+where
+	F: Fn(&MessageContext, Vec<u8>) -> bool + Send + Sync + 'static,
+{
+	let handler = Arc::new(handler);
+	Arc::new(move |context, data| {
+		let handler = handler.clone();
+		async move { handler(&context, data) }.boxed()
+	})
+}
+
+/// Id of the registered topic handler, it is needed to remove the handler
+pub type HandlerId = u64;
+
+// Handlers registered for the topic
+struct TopicHandlers {
+	topic: Topic,
+	handlers: Vec<(HandlerId, TopicHandler)>,
+}
+
+/// Gossipsub diagnostics for a single topic. Grafts and prunes are observed as the mesh
+/// membership changes between the heartbeats. IHAVE/IWANT and the gossipsub level duplicates
+/// are not reported: the gossipsub of the libp2p fork handles the control messages and drops
+/// the duplicated message ids inside the behaviour, they never reach the events. The repeat
+/// counters are application level, they count the delivered messages with the data that the
+/// topic already received, for example the same payload published again or by another author.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TopicDiagnostics {
+	/// Topic name
+	pub topic: String,
+	/// Peers in our mesh for this topic
+	pub mesh_peers: Vec<String>,
+	/// Number of the connected peers that are subscribed to this topic
+	pub subscribed_peers: usize,
+	/// Number of the peers that joined our mesh
+	pub grafts: u64,
+	/// Number of the peers that left our mesh
+	pub prunes: u64,
+	/// Number of the received messages
+	pub messages_received: u64,
+	/// Number of the received messages with the data that was already received on the topic
+	#[serde(alias = "duplicates")]
+	pub repeated: u64,
+	/// Application level repeat rate, share of the repeated messages in the received ones,
+	/// from 0 to 1. It is not the gossipsub duplicates rate.
+	#[serde(default, alias = "duplicate_rate")]
+	pub repeat_rate: f64,
+	/// Number of the accepted messages
+	pub accepted: u64,
+	/// Number of the rejected messages, sender peer is penalized for them
+	pub rejected: u64,
+	/// Number of the ignored messages
+	pub ignored: u64,
+	/// Number of the messages published by this node
+	pub published: u64,
+	/// Number of the topic handler panics
+	#[serde(default)]
+	pub handler_panics: u64,
+	/// Number of the topic handler calls that didn't finish in time
+	#[serde(default)]
+	pub handler_timeouts: u64,
+	/// Handler is disabled after repeated failures, messages of the topic are ignored
+	/// until the handler is registered again
+	#[serde(default)]
+	pub handler_disabled: bool,
+	/// Last handler panic or timeout
+	#[serde(default)]
+	pub handler_last_incident: Option<String>,
+	/// Number of the messages with the payload version the topic doesn't declare, they are ignored
+	#[serde(default)]
+	pub unknown_version: u64,
+	/// Last payload version the topic didn't know
+	#[serde(default)]
+	pub last_unknown_version: Option<u16>,
+	/// Number of the messages that were already passed to the handlers, they are ignored
+	#[serde(default)]
+	pub seen_before: u64,
+}
+
+// Gossipsub diagnostics data for the topic
+#[derive(Default)]
+struct TopicStats {
+	diagnostics: TopicDiagnostics,
+	mesh: HashSet<PeerId>,
+	// Handler panics and timeouts in a row
+	handler_failures: u32,
+	// Hashes of the recent messages data of the topic, used to calculate the repeat rate
+	recent_messages: VecDeque<u64>,
+	recent_messages_set: HashSet<u64>,
 }
 
 const MESSAGING_RECEIVED_LIMIT: usize = 1000;
+// Number of the recent messages of the topic that we are checking for the repeated data
+const GOSSIP_REPEATS_WINDOW: usize = 1000;
+// Mesh membership is sampled with gossipsub heartbeat interval
+const GOSSIP_MESH_SAMPLE_INTERVAL: Duration = Duration::from_secs(3);
+// Connected peers are checked against the access rules with this interval
+const PEER_ACCESS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// Topic handler is disabled after that many panics or timeouts in a row
+const HANDLER_MAX_FAILURES: u32 = 5;
+// Peer set of this node is checked for the changes with this interval
+const PEER_EXCHANGE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// Digest is announced with this interval even if the set is not changed, for the new neighbours
+const PEER_EXCHANGE_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(600);
+// Known sets of the neighbours that are silent for that long are dropped
+const PEER_EXCHANGE_EXPIRE_SECS: i64 = 3600;
+// Probe state of the sources that are silent for that long is dropped
+const PROBE_STATE_EXPIRE_MS: i64 = 3_600_000;
+// Discovered peers are saved to the peer store with that interval
+const PEER_STORE_SAVE_INTERVAL: Duration = Duration::from_secs(300);
+// Discovered peers key of the peers loaded from the peer store
+const STORED_PEERS_KEY: &str = "STORED";
+// Discovered peers key of the peers found in the DHT
+const KAD_PEERS_KEY: &str = "KADEMLIA";
+// On shutdown the connections are kept that long after unsubscribing, so PRUNE
+// reaches the mesh peers
+const SHUTDOWN_PRUNE_FLUSH: Duration = Duration::from_secs(2);
+// Shutdown is finished after that time even if some connections are not closed yet
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 lazy_static! {
-	static ref LIBP2P_SWARM: Mutex<Option<Swarm<Gossipsub>>> = Mutex::new(None);
-	/// Discovered Peer Onion addresses
-	static ref LIBP2P_PEERS: RwLock<HashMap<String, (Vec<String>, u64)>> =
-		RwLock::new(HashMap::new());
+	// Node that is used by the free functions of this module
+	static ref LIBP2P_NODE: RwLock<Libp2pNodeHandle> = RwLock::new(Arc::new(Libp2pNode::new()));
+}
 
-	static ref THIS_PEER_ID: RwLock<Option<PeerId>> = RwLock::new(None);
-	// Message handlers arguments: topic hash, message (no header), paid integrity fee
-	//   Handler must return false if the message is incorrect, so the peer must be banned.
-	static ref LIBP2P_MESSAGE_HANDLERS: RwLock<HashMap<TopicHash, (fn(sender_address: &String, topic: &TopicHash, Vec<u8>, u64) -> bool, Topic)>> = RwLock::new(HashMap::new());
+pub use crate::integrity_budget::{
+	INTEGRITY_CALL_HISTORY_LEN_LIMIT, INTEGRITY_CALL_MAX_PERIOD, INTEGRITY_FEE_VALID_BLOCKS,
+};
+/// Minimum integrity fee value in term of Base fees
+pub const INTEGRITY_FEE_MIN_X: u64 = 10;
+
+/// Discovered peer onion addresses, by the address of the peer that reported them,
+/// with the time of the last report
+type DiscoveredPeers = HashMap<String, (Vec<String>, u64)>;
+
+/// Network behaviour of the swarm: the gossipsub, the Kademlia DHT discovery, the direct
+/// queries and the circuit relay
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "Libp2pEvent", event_process = false)]
+pub struct Libp2pBehaviour {
+	/// Gossip messaging
+	pub gossipsub: Gossipsub,
+	/// Peer discovery, disabled by the config
+	pub kademlia: Toggle<Kademlia<MemoryStore>>,
+	/// Request-response queries to the single peer
+	pub query: RequestResponse<QueryCodec>,
+	/// Circuit relay, disabled if the node is neither the hop nor the relay client
+	pub relay: Toggle<Relay>,
+}
+
+/// Events of the swarm behaviours
+#[derive(Debug)]
+pub enum Libp2pEvent {
+	/// Gossipsub event
+	Gossipsub(GossipsubEvent),
+	/// Kademlia event
+	Kademlia(KademliaEvent),
+	/// Query event
+	Query(RequestResponseEvent<QueryRequest, QueryResponse>),
+	/// Relay doesn't report any event
+	Relay,
+}
+
+impl From<GossipsubEvent> for Libp2pEvent {
+	fn from(event: GossipsubEvent) -> Libp2pEvent {
+		Libp2pEvent::Gossipsub(event)
+	}
+}
+
+impl From<KademliaEvent> for Libp2pEvent {
+	fn from(event: KademliaEvent) -> Libp2pEvent {
+		Libp2pEvent::Kademlia(event)
+	}
+}
+
+impl From<RequestResponseEvent<QueryRequest, QueryResponse>> for Libp2pEvent {
+	fn from(event: RequestResponseEvent<QueryRequest, QueryResponse>) -> Libp2pEvent {
+		Libp2pEvent::Query(event)
+	}
+}
+
+impl From<()> for Libp2pEvent {
+	fn from(_: ()) -> Libp2pEvent {
+		Libp2pEvent::Relay
+	}
+}
+
+/// Gossipsub configuration from the node configuration. Returns an error if the
+/// parameters are inconsistent, for example the mesh sizes.
+pub fn gossipsub_config(config: &Libp2pConfig) -> Result<GossipsubConfig, Error> {
+	if config.heartbeat_interval_ms == 0 {
+		return Err(Error::Libp2pError(
+			"Invalid gossipsub config, heartbeat_interval_ms must be positive".to_string(),
+		));
+	}
+	let mut builder = gossipsub::GossipsubConfigBuilder::default();
+	builder
+		.heartbeat_interval(Duration::from_millis(config.heartbeat_interval_ms))
+		.validation_mode(match config.validation_mode {
+			GossipValidationMode::Strict => ValidationMode::Strict,
+			GossipValidationMode::Permissive => ValidationMode::Permissive,
+		})
+		.validate_messages() // !!!!! Now we are responsible for validation of all incoming traffic!!!!
+		.accept_dalek_pk_peers_only();
+	if let Some(mesh_n) = config.mesh_n {
+		builder.mesh_n(mesh_n);
+	}
+	if let Some(mesh_n_low) = config.mesh_n_low {
+		builder.mesh_n_low(mesh_n_low);
+	}
+	if let Some(mesh_n_high) = config.mesh_n_high {
+		builder.mesh_n_high(mesh_n_high);
+	}
+	if let Some(history_length) = config.history_length {
+		builder.history_length(history_length);
+	}
+	if let Some(history_gossip) = config.history_gossip {
+		builder.history_gossip(history_gossip);
+	}
+	builder
+		.build()
+		.map_err(|e| Error::Libp2pError(format!("Invalid gossipsub config, {}", e)))
+}
 
+/// libp2p node state: the running swarm, the discovered peers and the topic handlers.
+/// Several nodes can live in the same process, the free functions of this module work
+/// with the node that is returned by `libp2p_node`.
+pub struct Libp2pNode {
+	swarm: Mutex<Option<Swarm<Libp2pBehaviour>>>,
+	/// Discovered Peer Onion addresses
+	peers: RwLock<DiscoveredPeers>,
+	// Message handlers by topic
+	handlers: RwLock<HashMap<TopicHash, TopicHandlers>>,
+	next_handler_id: AtomicU64,
+	// Query handlers by method
+	query_handlers: RwLock<HashMap<String, QueryHandler>>,
+	// Sent queries that wait for the response
+	pending_queries: Mutex<HashMap<RequestId, oneshot::Sender<QueryResponse>>>,
+	this_peer_id: RwLock<Option<PeerId>>,
 	/// Seeds peer list. Will use it if not connections are available.
-	static ref SEED_LIST: RwLock<Vec<PeerAddr>> = RwLock::new(vec![]);
+	seed_list: RwLock<Vec<PeerAddr>>,
+	// Gossipsub parameters, applied when the node is started
+	config: RwLock<Libp2pConfig>,
+	// Set when this node identity is changed, the new identity needs to be announced
+	identity_changed: AtomicBool,
+	// Banned onion addresses (normalized) with the ban expiration time
+	ban_list: RwLock<HashMap<String, Option<i64>>>,
+	// Peers banned by the node for the misbehaviour, waiting to be saved into the ban store
+	pending_bans: Mutex<Vec<(String, ReasonForBan)>>,
+	// Listened topics and the received messages, shared with the listener handlers
+	messaging: Arc<Messaging>,
+	// Ids of the listener handlers of the topics, other handlers of the topic are kept
+	messaging_handlers: RwLock<HashMap<TopicHash, HandlerId>>,
+	// Gossipsub diagnostics per topic
+	gossip_stats: RwLock<HashMap<TopicHash, TopicStats>>,
+	// Explicitly allowed and denied onion addresses (normalized)
+	peer_allow_list: RwLock<HashSet<String>>,
+	peer_deny_list: RwLock<HashSet<String>>,
+	// Set when the access rules are changed, so the swarm bans need to be updated
+	peer_access_changed: AtomicBool,
+}
 
-	// Topics that we are listening now
-	static ref MESSAGING_TOPICS: RwLock<HashMap<TopicHash, (String, Topic, u64)>> = RwLock::new(HashMap::new());
+// Topics that the node is listening, the messages of these topics are kept for the API
+#[derive(Default)]
+struct Messaging {
+	// Listened topics with the min integrity fee
+	topics: RwLock<HashMap<TopicHash, (String, Topic, u64)>>,
+	// Received messages
+	received: RwLock<VecDeque<ReceivedMessage>>,
+}
 
-	/// Received messages
-	static ref MESSAGING_RECEIVED: RwLock<VecDeque<ReceivedMessage>> = RwLock::new(VecDeque::new());
+impl Default for Libp2pNode {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
-// Message with same integrity output consensus
-// History of the calls. 10 calls should be enough to compensate some glitches
-pub const INTEGRITY_CALL_HISTORY_LEN_LIMIT: usize = 10;
-// call interval limit, in second.
-pub const INTEGRITY_CALL_MAX_PERIOD: i64 = 15;
+/// Shared handle of the libp2p node, the server and the API clone it
+pub type Libp2pNodeHandle = Arc<Libp2pNode>;
+
+impl Libp2pNode {
+	/// Node without the swarm, it is created by `run`
+	pub fn new() -> Libp2pNode {
+		Libp2pNode {
+			swarm: Mutex::new(None),
+			peers: RwLock::new(HashMap::new()),
+			handlers: RwLock::new(HashMap::new()),
+			next_handler_id: AtomicU64::new(1),
+			query_handlers: RwLock::new(HashMap::new()),
+			pending_queries: Mutex::new(HashMap::new()),
+			this_peer_id: RwLock::new(None),
+			seed_list: RwLock::new(vec![]),
+			config: RwLock::new(Libp2pConfig::default()),
+			identity_changed: AtomicBool::new(false),
+			ban_list: RwLock::new(HashMap::new()),
+			pending_bans: Mutex::new(vec![]),
+			messaging: Arc::new(Messaging::default()),
+			messaging_handlers: RwLock::new(HashMap::new()),
+			gossip_stats: RwLock::new(HashMap::new()),
+			peer_allow_list: RwLock::new(HashSet::new()),
+			peer_deny_list: RwLock::new(HashSet::new()),
+			peer_access_changed: AtomicBool::new(false),
+		}
+	}
+
+	/// Gossipsub configuration of the node
+	pub fn config(&self) -> Libp2pConfig {
+		self.config.read().clone()
+	}
 
-/// Number of top block when integrity fee is valid.  24 hours + 3 blocks. 3 blocks it is a minimum confirmations numbers that is required
-pub const INTEGRITY_FEE_VALID_BLOCKS: u64 = 1443;
-/// Minimum integrity fee value in term of Base fees
-pub const INTEGRITY_FEE_MIN_X: u64 = 10;
+	/// Update the gossipsub configuration, it is applied on the next node start.
+	/// Invalid configuration is rejected.
+	pub fn set_config(&self, config: Libp2pConfig) -> Result<(), Error> {
+		gossipsub_config(&config)?;
+		tcp_peers(&config)?;
+		relay_nodes(&config)?;
+		*self.config.write() = config;
+		Ok(())
+	}
+
+	/// Peer Id of this node, known once the node is started
+	pub fn this_peer_id(&self) -> Option<PeerId> {
+		self.this_peer_id.read().clone()
+	}
+
+	/// Set the peer Id of this node. The previous identity is forgotten.
+	pub fn set_this_peer_id(&self, peer_id: &PeerId) {
+		let prev_peer_id = self.this_peer_id.write().replace(peer_id.clone());
+		if let Some(prev_peer_id) = prev_peer_id {
+			if prev_peer_id != *peer_id {
+				if let Ok(addr) = prev_peer_id.as_onion_address() {
+					self.forget_identity(&addr);
+				}
+			}
+		}
+	}
+
+	/// Drop the previous identity of this node after the onion key rotation or restore.
+	/// The old address is removed from the discovered peers, so we never dial it, and the
+	/// running swarm dials the peers and the seeds right away to announce the new identity.
+	pub fn forget_identity(&self, prev_onion_address: &str) {
+		let prev_address = Libp2pPeerAccess::normalize_address(prev_onion_address);
+		{
+			let mut libp2p_peers = self.peers.write();
+			libp2p_peers.remove(&prev_address);
+			for (peers, _) in libp2p_peers.values_mut() {
+				peers.retain(|p| Libp2pPeerAccess::normalize_address(p) != prev_address);
+			}
+		}
+		if let Err(e) = libp2p_peer_store::forget_peer(&prev_address) {
+			warn!(
+				"Unable to delete {} from the libp2p peer store, {}",
+				prev_address, e
+			);
+		}
+		info!(
+			"libp2p identity {} is dropped, the new identity will be announced",
+			prev_address
+		);
+		self.identity_changed.store(true, Ordering::Relaxed);
+	}
+
+	/// Init Swarm instance of this node
+	pub fn init_swarm(&self, swarm: Swarm<Libp2pBehaviour>) {
+		self.swarm.lock().replace(swarm);
+	}
+
+	/// Report that libp2p connection is done
+	pub fn reset_swarm(&self) {
+		self.swarm.lock().take();
+		// Nobody will answer the pending queries
+		self.pending_queries.lock().clear();
+	}
+
+	/// True if the swarm is running
+	pub fn is_running(&self) -> bool {
+		self.swarm.lock().is_some()
+	}
+
+	/// Report the seed list. We will add them as a found peers. That should be enough for bootstraping
+	pub fn set_seed_list(&self, seed_list: &Vec<PeerAddr>, update_seed_list: bool) {
+		if update_seed_list {
+			*self.seed_list.write() = seed_list.clone();
+		}
+
+		for s in seed_list {
+			match s {
+				PeerAddr::Onion(_) => {
+					if let Err(e) = self.add_new_peer(s) {
+						error!("Unable to add libp2p peer, {}", e);
+					}
+				}
+				_ => {}
+			}
+		}
+	}
+
+	/// Reporting new discovered mwc-wallet peer. That might be libp2p node as well
+	pub fn add_new_peer(&self, peer: &PeerAddr) -> Result<(), Error> {
+		info!("libp2p adding a new peer {}", peer);
+		let addr = peer.tor_address().map_err(|e| {
+			Error::Libp2pError(format!(
+				"Unable to retrieve TOR pk from the peer address, {}",
+				e
+			))
+		})?;
+
+		if !self.is_peer_allowed(&addr) {
+			debug!("libp2p peer {} is skipped by the access rules", addr);
+			return Ok(());
+		}
+		if is_peer_quarantined(&addr) {
+			debug!("libp2p peer {} is skipped, it is quarantined", addr);
+			return Ok(());
+		}
+
+		let cur_time = Utc::now().timestamp() as u64;
+		libp2p_peer_store::peers_seen(&[addr.clone()], cur_time as i64);
+		let mut peer_list = self.peers.write();
+		if let Some((peers, time)) = peer_list.get_mut("SELF") {
+			if !peers.contains(&addr) {
+				peers.push(addr);
+			}
+			*time = cur_time;
+		} else {
+			peer_list.insert("SELF".to_string(), (vec![addr], cur_time));
+		}
+
+		Ok(())
+	}
+
+	/// Discovered peers that are not dialed yet
+	pub fn discovered_peers(&self) -> Vec<String> {
+		let mut peers: Vec<String> = self
+			.peers
+			.read()
+			.values()
+			.flat_map(|(peers, _)| peers.iter().cloned())
+			.collect();
+		peers.sort();
+		peers.dedup();
+		peers
+	}
+
+	/// Add the peers from the peer store to the discovered peers, so after the restart
+	/// the node doesn't need to bootstrap from the seeds
+	fn load_stored_peers(&self, this_address: &str) {
+		let this_address = Libp2pPeerAccess::normalize_address(this_address);
+		let records = match libp2p_peer_store::stored_peers(Utc::now().timestamp()) {
+			Ok(records) => records,
+			Err(e) => {
+				warn!("Unable to load the stored libp2p peers, {}", e);
+				return;
+			}
+		};
+		let peers: Vec<String> = records
+			.into_iter()
+			.map(|r| r.address)
+			.filter(|a| *a != this_address && self.is_peer_allowed(a) && !is_peer_quarantined(a))
+			.collect();
+		if !peers.is_empty() {
+			info!("Loaded {} stored libp2p peers", peers.len());
+			self.peers.write().insert(
+				STORED_PEERS_KEY.to_string(),
+				(peers, Utc::now().timestamp() as u64),
+			);
+		}
+	}
+
+	// Onion peers to add to the DHT routing table: the connected peers first, then
+	// the discovered ones
+	fn kad_seed_peers(&self, connected: &[PeerId]) -> Vec<(PeerId, Multiaddr)> {
+		let mut rng = rand::thread_rng();
+		let mut discovered = self.discovered_peers();
+		discovered.shuffle(&mut rng);
+		connected
+			.iter()
+			.filter_map(|p| p.as_onion_address().ok())
+			.chain(discovered)
+			.filter_map(|addr| onion_dial_target(&addr).ok())
+			.take(KAD_SEED_PEERS)
+			.collect()
+	}
+
+	// Add the peers found in the DHT to the discovered peers, they are dialed when the node
+	// needs more connections
+	fn add_kad_peers(&self, peers: Vec<PeerId>, this_address: &str) {
+		let new_peers: Vec<String> = peers
+			.iter()
+			.filter_map(|p| p.as_onion_address().ok())
+			.filter(|a| {
+				Libp2pPeerAccess::normalize_address(a) != this_address
+					&& self.is_peer_allowed(a)
+					&& !is_peer_quarantined(a)
+					&& is_dial_allowed(a)
+			})
+			.collect();
+		if new_peers.is_empty() {
+			return;
+		}
+		let mut peer_list = self.peers.write();
+		let entry = peer_list
+			.entry(KAD_PEERS_KEY.to_string())
+			.or_insert((vec![], 0));
+		for peer in new_peers {
+			if !entry.0.contains(&peer) {
+				debug!("Found libp2p peer {} in the DHT", peer);
+				entry.0.push(peer);
+			}
+		}
+		entry.1 = Utc::now().timestamp() as u64;
+	}
+
+	/// Topics that have the handler
+	pub fn handled_topics(&self) -> Vec<TopicHash> {
+		self.handlers.read().keys().cloned().collect()
+	}
+
+	/// Ids of the handlers registered for the topic
+	pub fn topic_handlers(&self, topic: &str) -> Vec<HandlerId> {
+		self.handlers
+			.read()
+			.get(&Topic::new(topic).hash())
+			.map(|h| h.handlers.iter().map(|(id, _)| *id).collect())
+			.unwrap_or(vec![])
+	}
+
+	/// Stop listening on the topic, all its handlers are removed
+	pub fn remove_topic(&self, topic: &str) {
+		// remove topic and handlers
+		let topic = Topic::new(topic);
+		let mut handlers = self.handlers.write();
+		if handlers.remove(&topic.hash()).is_some() {
+			self.unsubscribe(&topic);
+		}
+	}
+
+	/// Remove the topic handler. Node stops listening on the topic when its last handler
+	/// is removed. Returns false if there is no such handler.
+	pub fn remove_handler(&self, topic: &str, handler_id: HandlerId) -> bool {
+		let topic = Topic::new(topic);
+		let mut handlers = self.handlers.write();
+		let topic_handlers = match handlers.get_mut(&topic.hash()) {
+			Some(h) => h,
+			None => return false,
+		};
+		let count = topic_handlers.handlers.len();
+		topic_handlers.handlers.retain(|(id, _)| *id != handler_id);
+		if topic_handlers.handlers.len() == count {
+			return false;
+		}
+		if topic_handlers.handlers.is_empty() {
+			handlers.remove(&topic.hash());
+			self.unsubscribe(&topic);
+		}
+		true
+	}
+
+	// Let's Unregister in the swarm
+	fn unsubscribe(&self, topic: &Topic) {
+		match &mut *self.swarm.lock() {
+			Some(swarm) => match swarm.gossipsub.unsubscribe(topic) {
+				Ok(res) => {
+					if !res {
+						warn!("Not found expected subscribed topic {}", topic);
+					}
+				}
+				Err(e) => warn!("Unable to unsubscribe from the topic {}", e),
+			},
+			None => (),
+		}
+	}
+
+	/// Start listen on topic, the handler is added to the handlers of the topic. Every
+	/// message is dispatched to all handlers of the topic. Topics that are not allowed by
+	/// the topic allowlist/denylist of the config are rejected.
+	/// Handler gets the message metadata and the message data (no header).
+	//   Handler resolves to false if the message is incorrect, so the peer must be banned.
+	pub fn add_topic(&self, topic: &str, handler: TopicHandler) -> Result<HandlerId, Error> {
+		if !self.config.read().is_topic_allowed(topic) {
+			return Err(Error::Libp2pError(format!(
+				"Topic {} is not allowed by the node config",
+				topic
+			)));
+		}
+		let handler_id = self.next_handler_id.fetch_add(1, Ordering::Relaxed);
+		let mut handlers = self.handlers.write();
+		let topic = Topic::new(topic);
+		let topic_handlers = handlers
+			.entry(topic.hash())
+			.or_insert_with(|| TopicHandlers {
+				topic: topic.clone(),
+				handlers: vec![],
+			});
+		topic_handlers.handlers.push((handler_id, handler));
+		let first_handler = topic_handlers.handlers.len() == 1;
+		// New handler gets a clean record
+		if let Some(stats) = self.gossip_stats.write().get_mut(&topic.hash()) {
+			stats.handler_failures = 0;
+			stats.diagnostics.handler_disabled = false;
+		}
+
+		// Let's Register in the swarm
+		if first_handler {
+			match &mut *self.swarm.lock() {
+				Some(swarm) => match swarm.gossipsub.subscribe(&topic) {
+					Ok(_res) => (),
+					Err(e) => warn!("Unable to subscribe to the topic {:?}", e),
+				},
+				None => (),
+			}
+		}
+		Ok(handler_id)
+	}
+
+	/// Publish the message with the integrity header to the topic
+	pub fn publish_message(&self, topic: &Topic, integrity_message: Vec<u8>) -> Option<MessageId> {
+		// The integrity kernel is the first field of the message header
+		let integrity_kernel = {
+			let mut ser = SimplePopSerializer::new(&integrity_message);
+			if ser.version == integrity_message_version() {
+				Some(Commitment::from_vec(ser.pop_vec()))
+			} else {
+				None
+			}
+		};
+		match &mut *self.swarm.lock() {
+			Some(swarm) => {
+				// Published messages are flooded to all subscribers of the topic
+				let topic_hash = topic.hash();
+				let subscribers: Vec<String> = swarm
+					.all_peers()
+					.filter(|(_, topics)| topics.contains(&&topic_hash))
+					.map(|(peer, _)| peer.to_string())
+					.collect();
+				let message_size = integrity_message.len();
+				match swarm.gossipsub.publish(topic.clone(), integrity_message) {
+					Ok(msg_id) => {
+						libp2p_stats::record_published(
+							topic_hash.as_str(),
+							&subscribers,
+							message_size,
+						);
+						if let Some(excess) = &integrity_kernel {
+							integrity_budget::record_integrity_publish(
+								excess,
+								clock::now().timestamp(),
+							);
+						}
+						self.gossip_stats
+							.write()
+							.entry(topic.hash())
+							.or_default()
+							.diagnostics
+							.published += 1;
+						Some(msg_id)
+					}
+					Err(e) => {
+						warn!("Unable to publish libp2p message, {}", e);
+						None
+					}
+				}
+			}
+			None => None,
+		}
+	}
+
+	/// Request number of established connections to libp2p
+	pub fn connections(&self) -> Vec<PeerId> {
+		match &*self.swarm.lock() {
+			Some(swarm) => Swarm::network_info(swarm).into_peers(),
+			None => vec![],
+		}
+	}
+
+	/// Register the handler of the query method. The handler that was registered for
+	/// the method is replaced. Built-in methods can't be overridden.
+	pub fn add_query_handler(&self, method: &str, handler: QueryHandler) -> Result<(), Error> {
+		if method == HAS_KERNEL_METHOD {
+			return Err(Error::Libp2pError(format!(
+				"Query method {} is built-in",
+				method
+			)));
+		}
+		self.query_handlers
+			.write()
+			.insert(method.to_string(), handler);
+		Ok(())
+	}
+
+	/// Remove the handler of the query method. Returns false if there is no such handler.
+	pub fn remove_query_handler(&self, method: &str) -> bool {
+		self.query_handlers.write().remove(method).is_some()
+	}
+
+	/// Query methods that have the handler
+	pub fn query_methods(&self) -> Vec<String> {
+		self.query_handlers.read().keys().cloned().collect()
+	}
+
+	/// Answer the query of the peer with the registered handler. Built-in methods are
+	/// answered by the swarm loop.
+	pub fn handle_query(&self, peer_id: &PeerId, request: QueryRequest) -> QueryResponse {
+		if !self.is_peer_id_allowed(peer_id) {
+			return Err("peer is not allowed".to_string());
+		}
+		let handler = self.query_handlers.read().get(&request.method).cloned();
+		match handler {
+			Some(handler) => handler(peer_id, request.params),
+			None => Err(format!("unknown query method {}", request.method)),
+		}
+	}
+
+	/// Send the query to the onion peer and wait for the response. The peer is dialed
+	/// if it is not connected.
+	pub async fn query_peer(
+		&self,
+		onion_address: &str,
+		method: &str,
+		params: serde_json::Value,
+	) -> Result<serde_json::Value, Error> {
+		if !self.is_peer_allowed(onion_address) {
+			return Err(Error::Libp2pError(format!(
+				"Peer {} is not allowed",
+				onion_address
+			)));
+		}
+		let (peer_id, addr) = onion_dial_target(onion_address).map_err(Error::Libp2pError)?;
+		let (sender, receiver) = oneshot::channel();
+		{
+			let mut swarm = self.swarm.lock();
+			let swarm = swarm
+				.as_mut()
+				.ok_or_else(|| Error::Libp2pError("libp2p node is not running".to_string()))?;
+			swarm.query.add_address(&peer_id, addr);
+			let request_id = swarm.query.send_request(
+				&peer_id,
+				QueryRequest {
+					method: method.to_string(),
+					params,
+				},
+			);
+			self.pending_queries.lock().insert(request_id, sender);
+		}
+		receiver
+			.await
+			.map_err(|_| Error::Libp2pError("libp2p node is stopped".to_string()))?
+			.map_err(|e| Error::Libp2pError(format!("Query {} failed, {}", method, e)))
+	}
+
+	// Answer the inbound queries and route the responses to the waiting requesters
+	fn process_query_event(
+		&self,
+		swarm: &mut Swarm<Libp2pBehaviour>,
+		event: RequestResponseEvent<QueryRequest, QueryResponse>,
+		kernel_validation_fn: &impl Fn(&Commitment) -> Result<Option<TxKernel>, Error>,
+	) {
+		match event {
+			RequestResponseEvent::Message { peer, message } => match message {
+				RequestResponseMessage::Request {
+					request, channel, ..
+				} => {
+					debug!("Get libp2p query {} from {}", request.method, peer);
+					let response = if request.method == HAS_KERNEL_METHOD {
+						has_kernel(&request.params, kernel_validation_fn)
+					} else {
+						self.handle_query(&peer, request)
+					};
+					if swarm.query.send_response(channel, response).is_err() {
+						debug!("Unable to respond to the query of {}", peer);
+					}
+				}
+				RequestResponseMessage::Response {
+					request_id,
+					response,
+				} => {
+					if let Some(sender) = self.pending_queries.lock().remove(&request_id) {
+						let _ = sender.send(response);
+					}
+				}
+			},
+			RequestResponseEvent::OutboundFailure {
+				peer,
+				request_id,
+				error,
+			} => {
+				debug!("libp2p query to {} failed, {:?}", peer, error);
+				if let Some(sender) = self.pending_queries.lock().remove(&request_id) {
+					let _ = sender.send(Err(format!("{:?}", error)));
+				}
+			}
+			RequestResponseEvent::InboundFailure { peer, error, .. } => {
+				debug!("libp2p query from {} failed, {:?}", peer, error);
+			}
+			RequestResponseEvent::ResponseSent { .. } => (),
+		}
+	}
+}
+
+// Built-in has_kernel query, params is the kernel excess hex
+fn has_kernel(
+	params: &serde_json::Value,
+	kernel_validation_fn: &impl Fn(&Commitment) -> Result<Option<TxKernel>, Error>,
+) -> QueryResponse {
+	let excess = params
+		.as_str()
+		.ok_or_else(|| "kernel excess hex is expected".to_string())?;
+	let excess = grin_util::from_hex(excess)
+		.map_err(|e| format!("Invalid kernel excess {}, {}", excess, e))?;
+	let kernel = kernel_validation_fn(&Commitment::from_vec(excess))
+		.map_err(|e| format!("Unable to lookup the kernel, {}", e))?;
+	Ok(serde_json::Value::Bool(kernel.is_some()))
+}
+
+/// Node that is used by the free functions of this module
+pub fn libp2p_node() -> Libp2pNodeHandle {
+	LIBP2P_NODE.read().clone()
+}
+
+/// Replace the node that is used by the free functions of this module
+pub fn set_libp2p_node(node: Libp2pNodeHandle) {
+	*LIBP2P_NODE.write() = node;
+}
 
 pub fn get_this_peer_id() -> Option<PeerId> {
-	THIS_PEER_ID.read().clone()
+	libp2p_node().this_peer_id()
 }
 pub fn set_this_peer_id(peer_id: &PeerId) {
-	THIS_PEER_ID.write().replace(peer_id.clone());
+	libp2p_node().set_this_peer_id(peer_id)
+}
+
+/// Drop the previous identity of this node, see `Libp2pNode::forget_identity`
+pub fn forget_libp2p_identity(prev_onion_address: &str) {
+	libp2p_node().forget_identity(prev_onion_address)
 }
 
 /// Init Swarm instance. App expecting to have only single instance for everybody.
-pub fn init_libp2p_swarm(swarm: Swarm<Gossipsub>) {
-	LIBP2P_SWARM.lock().replace(swarm);
+pub fn init_libp2p_swarm(swarm: Swarm<Libp2pBehaviour>) {
+	libp2p_node().init_swarm(swarm)
 }
 /// Report that libp2p connection is done
 pub fn reset_libp2p_swarm() {
-	LIBP2P_SWARM.lock().take();
+	libp2p_node().reset_swarm()
+}
+
+pub fn get_libp2p_running() -> bool {
+	libp2p_node().is_running()
 }
 
 /// Report the seed list. We will add them as a found peers. That should be enough for bootstraping
 pub fn set_seed_list(seed_list: &Vec<PeerAddr>, update_seed_list: bool) {
-	if update_seed_list {
-		*SEED_LIST.write() = seed_list.clone();
+	libp2p_node().set_seed_list(seed_list, update_seed_list)
+}
+
+/// Reporting new discovered mwc-wallet peer. That might be libp2p node as well
+pub fn add_new_peer(peer: &PeerAddr) -> Result<(), Error> {
+	libp2p_node().add_new_peer(peer)
+}
+
+/// Stop listening on the topic
+pub fn remove_topic_from_libp2p(topic: &str) {
+	libp2p_node().remove_topic(topic)
+}
+
+/// Start listen on topic, see `Libp2pNode::add_topic`
+pub fn add_topic_to_libp2p(topic: &str, handler: TopicHandler) -> Result<HandlerId, Error> {
+	libp2p_node().add_topic(topic, handler)
+}
+
+/// Remove the topic handler, see `Libp2pNode::remove_handler`
+pub fn remove_handler(topic: &str, handler_id: HandlerId) -> bool {
+	libp2p_node().remove_handler(topic, handler_id)
+}
+
+pub fn publish_message(topic: &Topic, integrity_message: Vec<u8>) -> Option<MessageId> {
+	libp2p_node().publish_message(topic, integrity_message)
+}
+
+/// Request number of established connections to libp2p
+pub fn get_libp2p_connections() -> Vec<PeerId> {
+	libp2p_node().connections()
+}
+
+/// Register the query handler, see `Libp2pNode::add_query_handler`
+pub fn add_query_handler(method: &str, handler: QueryHandler) -> Result<(), Error> {
+	libp2p_node().add_query_handler(method, handler)
+}
+
+/// Remove the query handler, see `Libp2pNode::remove_query_handler`
+pub fn remove_query_handler(method: &str) -> bool {
+	libp2p_node().remove_query_handler(method)
+}
+
+/// Query the onion peer, see `Libp2pNode::query_peer`
+pub async fn query_peer(
+	onion_address: &str,
+	method: &str,
+	params: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+	libp2p_node()
+		.query_peer(onion_address, method, params)
+		.await
+}
+
+/// Update the onion peers access rules, see `Libp2pNode::set_peer_access`
+pub fn set_peer_access(rules: &Vec<Libp2pPeerAccess>) {
+	libp2p_node().set_peer_access(rules)
+}
+
+/// Update the banned onion peers, see `Libp2pNode::set_peer_bans`
+pub fn set_peer_bans(bans: &Vec<Libp2pPeerBan>) {
+	libp2p_node().set_peer_bans(bans)
+}
+
+/// Peers banned for the misbehaviour, see `Libp2pNode::take_pending_bans`
+pub fn take_pending_bans() -> Vec<(String, ReasonForBan)> {
+	libp2p_node().take_pending_bans()
+}
+
+/// Check the onion address against the access rules and bans, see `Libp2pNode::is_peer_allowed`
+pub fn is_peer_allowed(onion_address: &str) -> bool {
+	libp2p_node().is_peer_allowed(onion_address)
+}
+
+impl Libp2pNode {
+	/// Update the onion peers access rules. Denied peers are never dialed or accepted. If there is
+	/// any allowed peer, only allowed peers are dialed and accepted.
+	pub fn set_peer_access(&self, rules: &Vec<Libp2pPeerAccess>) {
+		let mut allow_list = self.peer_allow_list.write();
+		let mut deny_list = self.peer_deny_list.write();
+		allow_list.clear();
+		deny_list.clear();
+		for r in rules {
+			if r.allowed {
+				allow_list.insert(r.address.clone());
+			} else {
+				deny_list.insert(r.address.clone());
+			}
+		}
+		self.peer_access_changed.store(true, Ordering::Relaxed);
+	}
+
+	/// Update the banned onion peers. Banned peers are never dialed or accepted until the ban expires.
+	pub fn set_peer_bans(&self, bans: &Vec<Libp2pPeerBan>) {
+		let mut ban_list = self.ban_list.write();
+		ban_list.clear();
+		for b in bans {
+			ban_list.insert(b.address.clone(), b.expires_at);
+		}
+		self.peer_access_changed.store(true, Ordering::Relaxed);
 	}
 
-	for s in seed_list {
-		match s {
-			PeerAddr::Onion(_) => {
-				if let Err(e) = add_new_peer(s) {
-					error!("Unable to add libp2p peer, {}", e);
+	/// Peers banned by the node for the misbehaviour since the last call. The caller is
+	/// responsible to save them into the ban store.
+	pub fn take_pending_bans(&self) -> Vec<(String, ReasonForBan)> {
+		std::mem::replace(&mut *self.pending_bans.lock(), vec![])
+	}
+
+	// Address is normalized
+	fn is_peer_banned(&self, address: &str, now: i64) -> bool {
+		match self.ban_list.read().get(address) {
+			Some(Some(expires_at)) => *expires_at > now,
+			Some(None) => true,
+			None => false,
+		}
+	}
+
+	// Disconnect and ban the misbehaving peer. The gossipsub ban lives in memory only, so the
+	// peer is queued to be saved into the ban store as well.
+	fn ban_misbehaving_peer(&self, gossip: &Gossipsub, peer_id: PeerId) {
+		if let Ok(addr) = peer_id.as_onion_address() {
+			let address = Libp2pPeerAccess::normalize_address(&addr);
+			self.ban_list.write().insert(address.clone(), None);
+			self.pending_bans
+				.lock()
+				.push((address, ReasonForBan::BadGossip));
+		}
+		gossip.disconnect_peer(peer_id, true);
+	}
+
+	/// Check if libp2p connections with the onion address are allowed by the access rules and bans
+	pub fn is_peer_allowed(&self, onion_address: &str) -> bool {
+		let address = Libp2pPeerAccess::normalize_address(onion_address);
+		if self.peer_deny_list.read().contains(&address) {
+			return false;
+		}
+		if self.is_peer_banned(&address, clock::now().timestamp()) {
+			return false;
+		}
+		let allow_list = self.peer_allow_list.read();
+		allow_list.is_empty() || allow_list.contains(&address)
+	}
+
+	// Peers without onion address can't pass the gossip validation, the access rules are not applicable for them
+	fn is_peer_id_allowed(&self, peer_id: &PeerId) -> bool {
+		match peer_id.as_onion_address() {
+			Ok(addr) => self.is_peer_allowed(&addr),
+			Err(_) => true,
+		}
+	}
+
+	// Ban the denied, not allowed and quarantined peers at the swarm level, so the connections with
+	// them are closed and new inbound connections are rejected. Peers that are allowed again and
+	// the peers with the expired quarantine are unbanned.
+	fn update_peer_access_bans(
+		&self,
+		swarm: &mut Swarm<Libp2pBehaviour>,
+		banned: &mut HashSet<PeerId>,
+	) {
+		let mut to_ban: HashSet<PeerId> = HashSet::new();
+		for addr in self.peer_deny_list.read().iter() {
+			match onion_to_peer_id(addr) {
+				Ok(peer_id) => {
+					to_ban.insert(peer_id);
 				}
+				Err(e) => warn!(
+					"Unable to build PeerId for denied onion address {}, {}",
+					addr, e
+				),
 			}
-			_ => {}
 		}
+		let now = clock::now().timestamp();
+		let ban_list: Vec<String> = self.ban_list.read().keys().cloned().collect();
+		for addr in ban_list.iter().filter(|a| self.is_peer_banned(a, now)) {
+			match onion_to_peer_id(addr) {
+				Ok(peer_id) => {
+					to_ban.insert(peer_id);
+				}
+				Err(e) => warn!(
+					"Unable to build PeerId for banned onion address {}, {}",
+					addr, e
+				),
+			}
+		}
+		for peer in peer_quarantine::quarantined_peers() {
+			match onion_to_peer_id(&peer.address) {
+				Ok(peer_id) => {
+					to_ban.insert(peer_id);
+				}
+				Err(e) => warn!(
+					"Unable to build PeerId for quarantined onion address {}, {}",
+					peer.address, e
+				),
+			}
+		}
+		for peer_id in Swarm::network_info(swarm).into_peers() {
+			if !self.is_peer_id_allowed(&peer_id) {
+				to_ban.insert(peer_id);
+			}
+		}
+
+		for peer_id in banned.difference(&to_ban) {
+			info!("libp2p peer {} is allowed again", peer_id);
+			Swarm::unban_peer_id(swarm, peer_id.clone());
+		}
+		for peer_id in to_ban.difference(banned) {
+			info!("libp2p peer {} is banned by the access rules", peer_id);
+			Swarm::ban_peer_id(swarm, peer_id.clone());
+		}
+		*banned = to_ban;
 	}
 }
 
-pub fn get_libp2p_running() -> bool {
-	LIBP2P_SWARM.lock().is_some()
+/// Clearnet peers of the config, the address to dial and the expected peer id
+pub fn tcp_peers(config: &Libp2pConfig) -> Result<Vec<(Multiaddr, PeerId)>, Error> {
+	parse_peer_addrs(&config.tcp_peers, "tcp peer")
 }
 
-/// Get topics that we are listening
-pub fn get_topics() -> Vec<(String, Topic, u64)> {
-	MESSAGING_TOPICS
-		.read()
+/// Relay nodes of the config, the address to dial and the expected peer id
+pub fn relay_nodes(config: &Libp2pConfig) -> Result<Vec<(Multiaddr, PeerId)>, Error> {
+	parse_peer_addrs(&config.relay_nodes, "relay node")
+}
+
+// Multiaddresses with the peer id: <multiaddress>/p2p/<peer id>
+fn parse_peer_addrs(peers: &[String], kind: &str) -> Result<Vec<(Multiaddr, PeerId)>, Error> {
+	peers
 		.iter()
-		.map(|(_k, v)| v.clone())
+		.map(|peer| {
+			let mut parts = peer.rsplitn(2, "/p2p/");
+			let (peer_id, addr) = match (parts.next(), parts.next()) {
+				(Some(peer_id), Some(addr)) => (peer_id, addr),
+				_ => {
+					return Err(Error::Libp2pError(format!(
+						"Invalid {} {}, expected <multiaddress>/p2p/<peer id>",
+						kind, peer
+					)))
+				}
+			};
+			let addr = addr.parse::<Multiaddr>().map_err(|e| {
+				Error::Libp2pError(format!("Invalid {} address {}, {}", kind, peer, e))
+			})?;
+			let peer_id = onion_to_peer_id(peer_id)
+				.map_err(|e| Error::Libp2pError(format!("Invalid {} id {}, {}", kind, peer, e)))?;
+			Ok((addr, peer_id))
+		})
 		.collect()
 }
 
-fn get_message_version() -> u16 {
-	if global::is_mainnet() {
-		1
-	} else {
-		256
-	}
+// PeerId and the dial address of the onion peer
+fn onion_dial_target(onion_address: &str) -> Result<(PeerId, Multiaddr), String> {
+	let peer_id = onion_to_peer_id(onion_address)?;
+	let address = peer_id
+		.get_address()
+		.map_err(|e| format!("Unable to get peer address, {}", e))?;
+	let multiaddress = format!("/onion3/{}:{}", address, global::get_tor_libp2p_port());
+	let addr = multiaddress
+		.parse::<Multiaddr>()
+		.map_err(|e| format!("Unable to construct multiaddress {}, {}", multiaddress, e))?;
+	Ok((peer_id, addr))
 }
 
-fn listener_handler(sender_address: &String, topic: &TopicHash, data: Vec<u8>, fee: u64) -> bool {
-	if let Some((topic_str, _topic, min_fee)) = MESSAGING_TOPICS.read().get(topic) {
-		if fee >= *min_fee {
-			// Parse message. It should be Json string
-			let message_str = match String::from_utf8(data) {
-				Ok(s) => s,
-				Err(_) => return false,
-			};
-			if serde_json::from_str::<serde_json::Value>(&message_str).is_err() {
-				return false;
-			}
+fn onion_to_peer_id(onion_address: &str) -> Result<PeerId, String> {
+	let onion_addr: OnionV3Address = onion_address
+		.try_into()
+		.map_err(|e: OnionV3AddressError| e.to_string())?;
+	let pk = onion_addr.to_ed25519().map_err(|e| e.to_string())?;
+	Ok(PeerId::from_public_key(
+		libp2p::identity::PublicKey::Ed25519(libp2p::identity::ed25519::PublicKey(pk)),
+	))
+}
 
-			debug!(
-				"Get a message from {}, on topic {},  data {}, fee {}",
-				sender_address, topic_str, message_str, fee
-			);
+impl Messaging {
+	// Listener handler of the topics, the valid Json messages with enough fee are kept
+	fn on_message(&self, context: &MessageContext, data: Vec<u8>) -> bool {
+		let sender_address = &context.sender_address;
+		let fee = context.fee;
+		if let Some((topic_str, _topic, min_fee)) = self.topics.read().get(&context.topic) {
+			if fee >= *min_fee {
+				// Parse message. It should be Json string
+				let message_str = match String::from_utf8(data) {
+					Ok(s) => s,
+					Err(_) => return false,
+				};
+				if serde_json::from_str::<serde_json::Value>(&message_str).is_err() {
+					return false;
+				}
 
-			// Everything looks good so far. We can keep the data
-			{
-				let mut messages = MESSAGING_RECEIVED.write();
-				messages.retain(|m| m.message != message_str || m.peer_id != *sender_address);
-				messages.push_back(ReceivedMessage {
-					timestamp: Utc::now().timestamp(),
+				debug!(
+					"Get a message from {}, on topic {},  data {}, fee {}",
+					sender_address, topic_str, message_str, fee
+				);
+
+				// Everything looks good so far. We can keep the data
+				self.keep_messages(vec![ReceivedMessage {
+					timestamp: context.received,
 					peer_id: sender_address.clone(),
 					topic: topic_str.clone(),
 					fee,
 					message: message_str,
-				});
-				while messages.len() > MESSAGING_RECEIVED_LIMIT {
-					messages.pop_front();
-				}
+					message_id: context.message_id.to_string(),
+					content_hash: context.content_hash.to_hex(),
+					source_peer_id: context.source.as_ref().map(|p| p.to_string()),
+					propagation_peer_id: context.propagation_source.to_string(),
+				}]);
 			}
 		}
+		true
+	}
+
+	// The same message from the same peer is kept once, the latest copy wins
+	fn keep_messages(&self, new_msgs: Vec<ReceivedMessage>) {
+		let mut messages = self.received.write();
+		for new_msg in new_msgs {
+			messages.retain(|m| m.message != new_msg.message || m.peer_id != new_msg.peer_id);
+			messages.push_back(new_msg);
+		}
+		while messages.len() > MESSAGING_RECEIVED_LIMIT {
+			messages.pop_front();
+		}
 	}
-	true
 }
 
-/// Start listening on the topic
-pub fn add_topic(topic_str: &String, min_fee: u64) -> bool {
-	let topic = Topic::new(topic_str.clone());
+impl Libp2pNode {
+	/// Get topics that we are listening
+	pub fn listened_topics(&self) -> Vec<(String, Topic, u64)> {
+		self.messaging
+			.topics
+			.read()
+			.iter()
+			.map(|(_k, v)| v.clone())
+			.collect()
+	}
 
-	match MESSAGING_TOPICS
-		.write()
-		.insert(topic.hash(), (topic_str.clone(), topic, min_fee))
-	{
-		Some(_) => (), // Data updated, already subscribed
-		None => {
-			add_topic_to_libp2p(&topic_str, listener_handler);
-			return true;
+	/// Start listening on the topic. Received messages with at least min_fee are kept
+	/// until they are read with `received_messages`.
+	pub fn listen_topic(&self, topic_str: &String, min_fee: u64) -> bool {
+		let topic = Topic::new(topic_str.clone());
+		let topic_hash = topic.hash();
+
+		match self
+			.messaging
+			.topics
+			.write()
+			.insert(topic_hash.clone(), (topic_str.clone(), topic, min_fee))
+		{
+			Some(_) => return false, // Data updated, already subscribed
+			None => (),
+		}
+		let messaging = self.messaging.clone();
+		let handler = sync_topic_handler(move |context, data| messaging.on_message(context, data));
+		match self.add_topic(&topic_str, handler) {
+			Ok(handler_id) => {
+				self.messaging_handlers
+					.write()
+					.insert(topic_hash, handler_id);
+				true
+			}
+			Err(e) => {
+				warn!("Unable to listen on the topic {}, {}", topic_str, e);
+				self.messaging.topics.write().remove(&topic_hash);
+				false
+			}
 		}
 	}
-	return false;
-}
 
-/// Remove topic from listening
-pub fn remove_topic(topic_str: &String) -> bool {
-	let topic = Topic::new(topic_str.clone());
+	/// Stop listening on the topic, other handlers of the topic are kept
+	pub fn stop_listening_topic(&self, topic_str: &String) -> bool {
+		let topic = Topic::new(topic_str.clone());
 
-	match MESSAGING_TOPICS.write().remove(&topic.hash()) {
-		Some(_) => {
-			remove_topic_from_libp2p(&topic_str);
-			return true;
+		match self.messaging.topics.write().remove(&topic.hash()) {
+			Some(_) => {
+				if let Some(handler_id) = self.messaging_handlers.write().remove(&topic.hash()) {
+					self.remove_handler(&topic_str, handler_id);
+				}
+				true
+			}
+			None => false,
 		}
-		None => (),
 	}
-	return false;
-}
 
-pub fn inject_received_messaged(inject_msgs: Vec<ReceivedMessage>) {
-	let mut messages = MESSAGING_RECEIVED.write();
+	/// Add the messages to the received ones
+	pub fn inject_received_messages(&self, inject_msgs: Vec<ReceivedMessage>) {
+		self.messaging.keep_messages(inject_msgs);
+	}
 
-	for inj_msg in inject_msgs {
-		messages.retain(|m| m.message != inj_msg.message || m.peer_id != inj_msg.peer_id);
-		messages.push_back(inj_msg);
+	/// Read received messages
+	pub fn received_messages(&self, delete: bool) -> VecDeque<ReceivedMessage> {
+		let mut messages = self.messaging.received.write();
+		if delete {
+			let mut res: VecDeque<ReceivedMessage> = VecDeque::new();
+			res.append(&mut *messages);
+			res
+		} else {
+			let time_limit = Utc::now().timestamp() - 600; // 10 minutes it is really more than enough for our needs.
+			messages.retain(|m| m.timestamp > time_limit);
+			messages.clone()
+		}
 	}
 
-	while messages.len() > MESSAGING_RECEIVED_LIMIT {
-		messages.pop_front();
+	/// Get number of received messages
+	pub fn received_messages_num(&self) -> usize {
+		self.messaging.received.read().len()
 	}
-}
 
-/// Read received messages
-pub fn get_received_messages(delete: bool) -> VecDeque<ReceivedMessage> {
-	if delete {
-		let mut res: VecDeque<ReceivedMessage> = VecDeque::new();
-		res.append(&mut *MESSAGING_RECEIVED.write());
+	/// Gossipsub diagnostics for all known topics
+	pub fn gossip_diagnostics(&self) -> Vec<TopicDiagnostics> {
+		let mut res: Vec<TopicDiagnostics> = self
+			.gossip_stats
+			.read()
+			.iter()
+			.map(|(topic, stats)| {
+				let mut diagnostics = stats.diagnostics.clone();
+				diagnostics.topic = topic.to_string();
+				diagnostics.mesh_peers = stats.mesh.iter().map(|p| p.to_string()).collect();
+				if diagnostics.messages_received > 0 {
+					diagnostics.repeat_rate =
+						diagnostics.repeated as f64 / diagnostics.messages_received as f64;
+				}
+				diagnostics
+			})
+			.collect();
+		res.sort_by(|a, b| a.topic.cmp(&b.topic));
 		res
-	} else {
-		let mut messages = MESSAGING_RECEIVED.write();
-		let time_limit = Utc::now().timestamp() - 600; // 10 minutes it is really more than enough for our needs.
-		messages.retain(|m| m.timestamp > time_limit);
-		messages.clone()
 	}
 }
 
-/// Get number of received messages
+/// Get topics that we are listening, see `Libp2pNode::listened_topics`
+pub fn get_topics() -> Vec<(String, Topic, u64)> {
+	libp2p_node().listened_topics()
+}
+
+/// Start listening on the topic, see `Libp2pNode::listen_topic`
+pub fn add_topic(topic_str: &String, min_fee: u64) -> bool {
+	libp2p_node().listen_topic(topic_str, min_fee)
+}
+
+/// Remove topic from listening, see `Libp2pNode::stop_listening_topic`
+pub fn remove_topic(topic_str: &String) -> bool {
+	libp2p_node().stop_listening_topic(topic_str)
+}
+
+/// Add the messages to the received ones, see `Libp2pNode::inject_received_messages`
+pub fn inject_received_messaged(inject_msgs: Vec<ReceivedMessage>) {
+	libp2p_node().inject_received_messages(inject_msgs)
+}
+
+/// Read received messages, see `Libp2pNode::received_messages`
+pub fn get_received_messages(delete: bool) -> VecDeque<ReceivedMessage> {
+	libp2p_node().received_messages(delete)
+}
+
+/// Get number of received messages, see `Libp2pNode::received_messages_num`
 pub fn get_received_messages_num() -> usize {
-	MESSAGING_RECEIVED.read().len()
+	libp2p_node().received_messages_num()
 }
 
-/// Stop listening on the topic
-pub fn remove_topic_from_libp2p(topic: &str) {
-	// remove topic and handler
-	let topic = Topic::new(topic);
-	let mut handlers = LIBP2P_MESSAGE_HANDLERS.write();
-	if handlers.remove(&topic.hash()).is_some() {
-		// Let's Unregister in the swarm
-		match &mut *LIBP2P_SWARM.lock() {
-			Some(swarm) => match swarm.unsubscribe(&topic) {
-				Ok(res) => {
-					if !res {
-						warn!("Not found expected subscribed topic {}", topic);
+/// Gossipsub diagnostics for all known topics, see `Libp2pNode::gossip_diagnostics`
+pub fn get_gossip_diagnostics() -> Vec<TopicDiagnostics> {
+	libp2p_node().gossip_diagnostics()
+}
+
+// Shutdown sequence of the swarm. Topics are unsubscribed first, so the mesh peers
+// get PRUNE and drop this node from their mesh instead of waiting for the timeout.
+// Then the connections are closed. The swarm is polled all the time, otherwise
+// nothing is sent.
+#[derive(Default)]
+struct SwarmShutdown {
+	started: Option<Instant>,
+	connections_closed: bool,
+}
+
+impl SwarmShutdown {
+	fn poll(&mut self, swarm: &mut Swarm<Libp2pBehaviour>, cx: &mut Context<'_>) -> Poll<()> {
+		let started = match self.started {
+			Some(started) => started,
+			None => {
+				let topics: Vec<TopicHash> = swarm.gossipsub.topics().cloned().collect();
+				info!(
+					"Stopping libp2p node, leaving {} topics and {} peers",
+					topics.len(),
+					Swarm::network_info(swarm).into_peers().len()
+				);
+				for t in &topics {
+					if let Err(e) = swarm.gossipsub.unsubscribe(&Topic::new(t.to_string())) {
+						warn!("Unable to unsubscribe from the topic {}, {:?}", t, e);
 					}
 				}
-				Err(e) => warn!("Unable to unsubscribe from the topic {}", e),
-			},
-			None => (),
+				let now = Instant::now();
+				self.started = Some(now);
+				now
+			}
+		};
+
+		// Messages that are still coming are dropped, nobody will handle them
+		loop {
+			match swarm.poll_next_unpin(cx) {
+				Poll::Ready(Some(_)) => continue,
+				Poll::Ready(None) => return Poll::Ready(()),
+				Poll::Pending => break,
+			}
+		}
+
+		if !self.connections_closed && started.elapsed() > SHUTDOWN_PRUNE_FLUSH {
+			// Ban closes the connections right away, the ban list is gone with the swarm
+			for peer in Swarm::network_info(swarm).into_peers() {
+				Swarm::ban_peer_id(swarm, peer);
+			}
+			self.connections_closed = true;
+		}
+
+		let connections = Swarm::network_info(swarm)
+			.connection_counters()
+			.num_connections();
+		if self.connections_closed && connections == 0 {
+			info!("libp2p node is stopped, all connections are closed");
+			Poll::Ready(())
+		} else if started.elapsed() > SHUTDOWN_TIMEOUT {
+			warn!(
+				"libp2p node is stopped by timeout, {} connections are not closed",
+				connections
+			);
+			Poll::Ready(())
+		} else {
+			Poll::Pending
 		}
 	}
 }
 
-/// Start listen on topic
-/// Message handlers arguments: topic hash, message (no header), paid integrity fee
-//   Handler must return false if the message is incorrect, so the peer must be banned.
-pub fn add_topic_to_libp2p(
-	topic: &str,
-	handler: fn(sender_address: &String, topic: &TopicHash, Vec<u8>, u64) -> bool,
+// Sample the connected peers with their onion addresses and subscriptions
+fn update_connection_stats(swarm: &Swarm<Libp2pBehaviour>) {
+	let mut topics: HashMap<PeerId, Vec<String>> = HashMap::new();
+	for (peer, peer_topics) in swarm.get_behaviour().gossipsub.all_peers() {
+		topics.insert(
+			peer.clone(),
+			peer_topics.iter().map(|t| t.to_string()).collect(),
+		);
+	}
+	let peers = Swarm::network_info(swarm)
+		.into_peers()
+		.into_iter()
+		.map(|peer| libp2p_stats::ConnectedPeer {
+			peer_id: peer.to_string(),
+			onion_address: peer.as_onion_address().ok(),
+			topics: topics.remove(&peer).unwrap_or_default(),
+		})
+		.collect();
+	libp2p_stats::record_connections(peers, Utc::now().timestamp());
+}
+
+// Version 2 peer topic message
+fn build_peer_exchange_message(message: &PeerExchangeMessage) -> Option<Vec<u8>> {
+	match ser::ser_vec(message, ser::ProtocolVersion(1)) {
+		Ok(data) => {
+			let mut serializer = SimplePushSerializer::new(PEER_EXCHANGE_DIFF_VERSION);
+			serializer.push_vec(&data);
+			Some(serializer.to_vec())
+		}
+		Err(e) => {
+			error!("Unable to serialize peer exchange message, {}", e);
+			None
+		}
+	}
+}
+
+fn publish_peer_exchange(
+	swarm: &mut Swarm<Libp2pBehaviour>,
+	topic: &Topic,
+	message: &PeerExchangeMessage,
 ) {
-	let mut handlers = LIBP2P_MESSAGE_HANDLERS.write();
-	let topic = Topic::new(topic);
-	let _ = handlers.insert(topic.hash(), (handler, topic.clone()));
+	if let Some(data) = build_peer_exchange_message(message) {
+		if let Err(e) = swarm.gossipsub.publish(topic.clone(), data) {
+			debug!("Unable to publish peer exchange message, {}", e);
+		}
+	}
+}
 
-	// Let's Unregister in the swarm
-	match &mut *LIBP2P_SWARM.lock() {
-		Some(swarm) => match swarm.subscribe(&topic) {
-			Ok(_res) => (),
-			Err(e) => warn!("Unable to subscribe to the topic {:?}", e),
-		},
-		None => (),
+fn publish_probe_message(
+	swarm: &mut Swarm<Libp2pBehaviour>,
+	topic: &Topic,
+	message: &GossipProbeMessage,
+) {
+	match ser::ser_vec(message, ser::ProtocolVersion(1)) {
+		Ok(data) => {
+			if let Err(e) = swarm.gossipsub.publish(topic.clone(), data) {
+				debug!("Unable to publish gossip probe, {}", e);
+			}
+		}
+		Err(e) => error!("Unable to serialize gossip probe, {}", e),
 	}
 }
 
-pub fn publish_message(topic: &Topic, integrity_message: Vec<u8>) -> Option<MessageId> {
-	match &mut *LIBP2P_SWARM.lock() {
-		Some(swarm) => match swarm.publish(topic.clone(), integrity_message) {
-			Ok(msg_id) => Some(msg_id),
+// Process the message of the probe topic. Probes are answered with the echo, the echoes
+// to our probes are measured. Returns the validation result, accepted messages are relayed.
+fn process_probe_message(
+	swarm: &mut Swarm<Libp2pBehaviour>,
+	this_peer_id: &PeerId,
+	topic: &Topic,
+	propagation_source: &PeerId,
+	source: &Option<PeerId>,
+	data: &[u8],
+) -> MessageAcceptance {
+	// Messages are signed, the source is the node that published it
+	let source_address = match source.as_ref().map(|s| s.as_onion_address()) {
+		Some(Ok(addr)) => addr,
+		_ => return MessageAcceptance::Reject,
+	};
+	if data.len() > MAX_PROBE_MESSAGE_SIZE {
+		return MessageAcceptance::Reject;
+	}
+	let message: GossipProbeMessage =
+		match ser::deserialize(&mut &data[..], ser::ProtocolVersion(1)) {
+			Ok(m) => m,
 			Err(e) => {
-				warn!("Unable to publish libp2p message, {}", e);
-				None
+				debug!(
+					"Get invalid probe message from {}, {}",
+					propagation_source, e
+				);
+				return MessageAcceptance::Reject;
 			}
-		},
-		None => None,
+		};
+	let relayed = source.as_ref() != Some(propagation_source);
+	let now_ms = Utc::now().timestamp_millis();
+
+	let (acceptance, echo) = {
+		let mut probes = gossip_probe::GOSSIP_PROBES.lock();
+		let probes = match &mut *probes {
+			Some(probes) => probes,
+			None => return MessageAcceptance::Ignore,
+		};
+		if !probes.allow_message(&source_address, now_ms) {
+			debug!(
+				"Probe messages of {} are over the rate limit",
+				source_address
+			);
+			return MessageAcceptance::Ignore;
+		}
+		match message {
+			GossipProbeMessage::Probe { seq } => (
+				MessageAcceptance::Accept,
+				probes.on_probe(&source_address, seq, relayed, now_ms),
+			),
+			GossipProbeMessage::Echo {
+				origin,
+				seq,
+				relayed: probe_relayed,
+			} => {
+				let this_address = this_peer_id.as_onion_address().ok();
+				if this_address.as_ref() == Some(&origin) {
+					// The echo reached its destination, no need to relay it further
+					probes.on_echo(&source_address, seq, probe_relayed || relayed, now_ms);
+					(MessageAcceptance::Ignore, None)
+				} else {
+					(MessageAcceptance::Accept, None)
+				}
+			}
+		}
+	};
+	if let Some(echo) = echo {
+		publish_probe_message(swarm, topic, &echo);
 	}
+	acceptance
 }
 
-/// Request number of established connections to libp2p
-pub fn get_libp2p_connections() -> Vec<PeerId> {
-	match &*LIBP2P_SWARM.lock() {
-		Some(swarm) => Swarm::network_info(swarm).into_peers(),
-		None => vec![],
+// Content address of the message data
+fn content_hash(data: &[u8]) -> Hash {
+	let mut hasher = HashWriter::default();
+	hasher
+		.write_fixed_bytes(data)
+		.expect("hash writer never fails");
+	hasher.into_hash()
+}
+
+// Register the accepted message. Gossipsub forwards it to our mesh peers of the topic,
+// except the peer that we got it from and the author.
+fn record_gossip_forward(
+	gossip: &Gossipsub,
+	topic: &TopicHash,
+	propagation_source: &PeerId,
+	source: &Option<PeerId>,
+	size: usize,
+) {
+	let peers: Vec<String> = gossip
+		.mesh_peers(topic)
+		.filter(|p| *p != propagation_source && Some(*p) != source.as_ref())
+		.map(|p| p.to_string())
+		.collect();
+	libp2p_stats::record_sent(topic.as_str(), &peers, size);
+}
+
+// The accepted message with the paid integrity fee
+fn message_accepted(gossip: &Gossipsub, context: &MessageContext, size: usize) {
+	audit_accepted_message(context);
+	libp2p_stats::record_integrity_fee(context.topic.as_str(), context.fee);
+	record_gossip_forward(
+		gossip,
+		&context.topic,
+		&context.propagation_source,
+		&context.source,
+		size,
+	);
+}
+
+// Record the accepted message in the audit log if its topic is audited
+fn audit_accepted_message(context: &MessageContext) {
+	let audit = match gossip_audit::gossip_audit() {
+		Some(audit) => audit,
+		None => return,
+	};
+	if !audit.is_audited(context.topic.as_str()) {
+		return;
+	}
+	let entry = GossipAuditEntry {
+		timestamp: context.received,
+		topic: context.topic.to_string(),
+		message_id: context.message_id.to_string(),
+		sender_address: context.sender_address.clone(),
+		source_peer_id: context.source.as_ref().map(|p| p.to_string()),
+		propagation_peer_id: context.propagation_source.to_string(),
+		fee: context.fee,
+		payload_hash: context.content_hash.to_hex(),
+	};
+	if let Err(e) = audit.record(&entry) {
+		error!("Unable to record the gossip audit entry, {}", e);
+	}
+}
+
+// Messages of the quarantined authors are not relayed
+fn is_source_quarantined(source: &Option<PeerId>) -> bool {
+	match source.as_ref().map(|s| s.as_onion_address()) {
+		Some(Ok(address)) => is_peer_quarantined(&address),
+		_ => false,
+	}
+}
+
+impl Libp2pNode {
+	// Update the mesh members and the subscribers for all topics. Grafts and prunes
+	// are calculated from the changes since the previous sample.
+	fn update_gossip_mesh_stats(&self, gossip: &Gossipsub) {
+		let mut subscribers: HashMap<TopicHash, usize> = HashMap::new();
+		for (_peer, topics) in gossip.all_peers() {
+			for topic in topics {
+				*subscribers.entry(topic.clone()).or_insert(0) += 1;
+			}
+		}
+
+		let mut stats = self.gossip_stats.write();
+		let topics: Vec<TopicHash> = gossip.topics().cloned().collect();
+		for topic in &topics {
+			let mesh: HashSet<PeerId> = gossip.mesh_peers(topic).cloned().collect();
+			let topic_stats = stats.entry(topic.clone()).or_default();
+			topic_stats.diagnostics.grafts += mesh.difference(&topic_stats.mesh).count() as u64;
+			topic_stats.diagnostics.prunes += topic_stats.mesh.difference(&mesh).count() as u64;
+			topic_stats.diagnostics.subscribed_peers = subscribers.get(topic).cloned().unwrap_or(0);
+			topic_stats.mesh = mesh;
+		}
+		// We are not in the mesh for unsubscribed topics
+		for (topic, topic_stats) in stats.iter_mut() {
+			if !topics.contains(topic) {
+				topic_stats.diagnostics.prunes += topic_stats.mesh.len() as u64;
+				topic_stats.mesh.clear();
+				topic_stats.diagnostics.subscribed_peers = 0;
+			}
+		}
+	}
+
+	// Register received message, check if the topic already received the same data
+	fn record_gossip_message(&self, topic: &TopicHash, peer_id: &PeerId, data: &[u8]) {
+		libp2p_stats::record_received(topic.as_str(), &peer_id.to_string(), data.len());
+
+		let mut hasher = DefaultHasher::new();
+		hasher.write(data);
+		let data_hash = hasher.finish();
+
+		let mut stats = self.gossip_stats.write();
+		let stats = stats.entry(topic.clone()).or_default();
+		stats.diagnostics.messages_received += 1;
+		if stats.recent_messages_set.contains(&data_hash) {
+			stats.diagnostics.repeated += 1;
+		} else {
+			stats.recent_messages_set.insert(data_hash);
+			stats.recent_messages.push_back(data_hash);
+			while stats.recent_messages.len() > GOSSIP_REPEATS_WINDOW {
+				if let Some(h) = stats.recent_messages.pop_front() {
+					stats.recent_messages_set.remove(&h);
+				}
+			}
+		}
+	}
+
+	// Register the message that was already passed to the handlers
+	fn record_seen_before(&self, topic: &TopicHash) {
+		let mut stats = self.gossip_stats.write();
+		stats
+			.entry(topic.clone())
+			.or_default()
+			.diagnostics
+			.seen_before += 1;
+	}
+
+	// Register the message with the payload version that the topic doesn't declare
+	fn record_unknown_version(&self, topic: &TopicHash, version: u16) {
+		let mut stats = self.gossip_stats.write();
+		let diagnostics = &mut stats.entry(topic.clone()).or_default().diagnostics;
+		diagnostics.unknown_version += 1;
+		diagnostics.last_unknown_version = Some(version);
+	}
+
+	// Register validation result for the message that the peer forwarded to us
+	fn record_gossip_acceptance(
+		&self,
+		topic: &TopicHash,
+		peer_id: &PeerId,
+		acceptance: &MessageAcceptance,
+	) {
+		let result = {
+			let mut stats = self.gossip_stats.write();
+			let diagnostics = &mut stats.entry(topic.clone()).or_default().diagnostics;
+			match acceptance {
+				MessageAcceptance::Accept => {
+					diagnostics.accepted += 1;
+					ValidationResult::Accepted
+				}
+				MessageAcceptance::Reject => {
+					diagnostics.rejected += 1;
+					ValidationResult::Rejected
+				}
+				MessageAcceptance::Ignore => {
+					diagnostics.ignored += 1;
+					ValidationResult::Ignored
+				}
+			}
+		};
+		libp2p_stats::record_validation(topic.as_str(), &peer_id.to_string(), result);
+	}
+
+	// Check if the topic handler is disabled because of the repeated failures
+	fn is_handler_disabled(&self, topic: &TopicHash) -> bool {
+		self.gossip_stats
+			.read()
+			.get(topic)
+			.map(|stats| stats.diagnostics.handler_disabled)
+			.unwrap_or(false)
+	}
+
+	// Register the handler result, return the validation result for the message. Panics
+	// and timeouts are not the sender fault, such messages are ignored.
+	fn record_handler_outcome(
+		&self,
+		topic: &TopicHash,
+		outcome: &HandlerOutcome,
+	) -> MessageAcceptance {
+		let mut stats = self.gossip_stats.write();
+		let stats = stats.entry(topic.clone()).or_default();
+		let incident = match outcome {
+			HandlerOutcome::Done(valid) => {
+				stats.handler_failures = 0;
+				return if *valid {
+					MessageAcceptance::Accept
+				} else {
+					// false mean that message was invalid, so we can ban the peer
+					MessageAcceptance::Reject
+				};
+			}
+			HandlerOutcome::Panic(msg) => {
+				stats.diagnostics.handler_panics += 1;
+				format!("handler panicked: {}", msg)
+			}
+			HandlerOutcome::Timeout => {
+				stats.diagnostics.handler_timeouts += 1;
+				"handler timed out".to_string()
+			}
+		};
+		error!("libp2p topic {} {}", topic, incident);
+		stats.handler_failures += 1;
+		stats.diagnostics.handler_last_incident =
+			Some(format!("{} {}", Utc::now().to_rfc3339(), incident));
+		if stats.handler_failures >= HANDLER_MAX_FAILURES && !stats.diagnostics.handler_disabled {
+			error!(
+				"libp2p topic {} handler failed {} times in a row, it is disabled",
+				topic, stats.handler_failures
+			);
+			stats.diagnostics.handler_disabled = true;
+		}
+		MessageAcceptance::Ignore
 	}
-}
-
-/// Reporting new discovered mwc-wallet peer. That might be libp2p node as well
-pub fn add_new_peer(peer: &PeerAddr) -> Result<(), Error> {
-	info!("libp2p adding a new peer {}", peer);
-	let addr = peer.tor_address().map_err(|e| {
-		Error::Libp2pError(format!(
-			"Unable to retrieve TOR pk from the peer address, {}",
-			e
-		))
-	})?;
 
-	let cur_time = Utc::now().timestamp() as u64;
-	let mut peer_list = LIBP2P_PEERS.write();
-	if let Some((peers, time)) = peer_list.get_mut("SELF") {
-		if !peers.contains(&addr) {
-			peers.push(addr);
+	// Quarantine the author of the message that the topic handler found invalid. The author
+	// is known from the gossipsub signature, the forwarding peer is penalized by gossipsub.
+	fn quarantine_message_source(&self, this_peer_id: &PeerId, context: &MessageContext) {
+		let source = match &context.source {
+			Some(source) => source,
+			None => return,
+		};
+		if this_peer_id == source {
+			return;
+		}
+		match source.as_onion_address() {
+			Ok(address) => {
+				let reason = format!("invalid message on topic {}", context.topic);
+				if peer_quarantine::quarantine_peer(&address, &reason) {
+					warn!("libp2p peer {} is quarantined, {}", address, reason);
+					self.peer_access_changed.store(true, Ordering::Relaxed);
+				}
+			}
+			Err(e) => debug!(
+				"Unable to quarantine the message author {} without onion address, {}",
+				source, e
+			),
 		}
-		*time = cur_time;
-	} else {
-		peer_list.insert("SELF".to_string(), (vec![addr], cur_time));
 	}
-
-	Ok(())
 }
 
-/// Created libp2p listener for Socks5 tor address.
-/// tor_socks_port - listener port, param from  SocksPort 127.0.0.1:51234
-/// output_validation_fn - kernel excess validation method. Return height RangeProof if that output was seen during last 24 hours (last 1440 blocks)
-pub async fn run_libp2p_node(
-	tor_socks_port: u16,
-	tor_secret: &[u8; 32],
-	libp2p_port: u16,
-	fee_base: u64,
-	kernel_validation_fn: Arc<impl Fn(&Commitment) -> Result<Option<TxKernel>, Error>>,
-	stop_mutex: std::sync::Arc<std::sync::Mutex<u32>>,
-) -> Result<(), Error> {
-	// Generate Onion address.
-	let onion_address = OnionV3Address::from_private(tor_secret)
-		.map_err(|e| Error::Libp2pError(format!("Unable to build onion address, {}", e)))?;
-
-	// Init Tor address configs..
-	// 80 comes from: /tor/listener/torrc   HiddenServicePort 80 0.0.0.0:13425
-	let addr_str = format!(
-		"/onion3/{}:{}",
-		onion_address.to_string(),
-		global::get_tor_libp2p_port()
-	);
-	let addr = addr_str
-		.parse::<Multiaddr>()
-		.map_err(|e| Error::Internal(format!("Unable to construct onion multiaddress, {}", e)))?;
-
-	let mut map = HashMap::new();
-	map.insert(addr.clone(), libp2p_port);
-
-	// Build swarm (libp2p stuff)
-	// Each time will join with a new p2p node ID. I think it is fine, let's keep p2p network dynamic
-	let id_keys = Keypair::ed25519_from_secret(&mut tor_secret.clone())
-		.map_err(|e| Error::Libp2pError(format!("Unable to build ed25519 key pairs, {}", e)))?;
-	let this_peer_id = PeerId::from_public_key(id_keys.public());
-	set_this_peer_id(&this_peer_id);
-
-	warn!("Starting libp2p, this peer: {}", this_peer_id);
-	debug_assert_eq!(this_peer_id.to_string(), onion_address.to_string());
-
-	// Building transport
-	let dh_keys = noise::Keypair::<X25519Spec>::new()
-		.into_authentic(&id_keys)
-		.map_err(|e| Error::Libp2pError(format!("Unable to build p2p keys, {}", e)))?;
-	let noise = NoiseConfig::xx(dh_keys).into_authenticated();
-	let tcp = Socks5TokioTcpConfig::new(tor_socks_port)
-		.nodelay(true)
-		.onion_map(map);
-	let transport = DnsConfig::new(tcp)
-		.map_err(|e| Error::Libp2pError(format!("Unable to build a transport, {}", e)))?;
-
-	let transport = transport
-		.upgrade(Version::V1)
-		.authenticate(noise)
-		.multiplex(SelectUpgrade::new(
-			YamuxConfig::default(),
-			MplexConfig::new(),
-		))
-		.map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
-		.boxed();
-
-	//Ping pond already works. But it is not we needed
-	// mwc-node does nothing, just forming a node with aping.
-	/*    let config = PingConfig::new()
-			.with_keep_alive(true)
-			.with_interval(Duration::from_secs(600))
-			.with_timeout(Duration::from_secs(60))
-			.with_max_failures( NonZeroU32::new(2).unwrap() );
-		let behaviour = Ping::new(config);
-	*/
-
-	// Set a custom gossipsub
-	let gossipsub_config = gossipsub::GossipsubConfigBuilder::default()
-		.heartbeat_interval(Duration::from_secs(3)) // Default is 1, but we don't want overload network much. Instead we prefer slow adaptation network
-		.validation_mode(ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
-		.validate_messages() // !!!!! Now we are responsible for validation of all incoming traffic!!!!
-		.accept_dalek_pk_peers_only()
-		.build()
-		.expect("Valid gossip config");
-
-	// Here are how many connection we will try to keep...
-	let connections_number_low = gossipsub_config.mesh_n_high();
-
-	// build a gossipsub network behaviour
-	let gossipsub: gossipsub::Gossipsub =
-		gossipsub::Gossipsub::new(MessageAuthenticity::Signed(id_keys), gossipsub_config)
-			.expect("Correct configuration");
+impl Libp2pNode {
+	/// Created libp2p listener for Socks5 tor address.
+	/// tor_socks_port - listener port, param from  SocksPort 127.0.0.1:51234
+	/// tor_secret - onion service key, identity_secret - libp2p identity key. They are
+	/// the same unless the standalone identity is used.
+	/// output_validation_fn - kernel excess validation method. Return height RangeProof if that output was seen during last 24 hours (last 1440 blocks)
+	/// policy - rate limits of the integrity kernels
+	pub async fn run(
+		&self,
+		tor_socks_port: u16,
+		tor_secret: &[u8; 32],
+		identity_secret: &[u8; 32],
+		libp2p_port: u16,
+		fee_base: u64,
+		kernel_validation_fn: Arc<impl Fn(&Commitment) -> Result<Option<TxKernel>, Error>>,
+		policy: Libp2pPolicy,
+		stop_mutex: std::sync::Arc<std::sync::Mutex<u32>>,
+	) -> Result<(), Error> {
+		policy.validate()?;
+		// Generate Onion address.
+		let onion_address = OnionV3Address::from_private(tor_secret)
+			.map_err(|e| Error::Libp2pError(format!("Unable to build onion address, {}", e)))?;
+
+		// Init Tor address configs..
+		// 80 comes from: /tor/listener/torrc   HiddenServicePort 80 0.0.0.0:13425
+		let addr_str = format!(
+			"/onion3/{}:{}",
+			onion_address.to_string(),
+			global::get_tor_libp2p_port()
+		);
+		let addr = addr_str.parse::<Multiaddr>().map_err(|e| {
+			Error::Internal(format!("Unable to construct onion multiaddress, {}", e))
+		})?;
+
+		let mut map = HashMap::new();
+		map.insert(addr.clone(), libp2p_port);
+
+		let libp2p_config = self.config();
+		let transport_kind = libp2p_config.transport;
+		let tcp_peers = tcp_peers(&libp2p_config)?;
+		let relay_nodes = relay_nodes(&libp2p_config)?;
+
+		// Build swarm (libp2p stuff)
+		// Each time will join with a new p2p node ID. I think it is fine, let's keep p2p network dynamic
+		let id_keys = Keypair::ed25519_from_secret(&mut identity_secret.clone())
+			.map_err(|e| Error::Libp2pError(format!("Unable to build ed25519 key pairs, {}", e)))?;
+		let this_peer_id = PeerId::from_public_key(id_keys.public());
+		self.set_this_peer_id(&this_peer_id);
+
+		warn!("Starting libp2p, this peer: {}", this_peer_id);
+		if tor_secret == identity_secret {
+			debug_assert_eq!(this_peer_id.to_string(), onion_address.to_string());
+		} else if transport_kind.has_tor() {
+			info!(
+				"libp2p standalone identity is used, onion service {}",
+				onion_address
+			);
+		}
 
-	// subscribes to our topic
+		self.load_stored_peers(&onion_address.to_string());
+
+		// Building transport
+		let dh_keys = noise::Keypair::<X25519Spec>::new()
+			.into_authentic(&id_keys)
+			.map_err(|e| Error::Libp2pError(format!("Unable to build p2p keys, {}", e)))?;
+		let noise = NoiseConfig::xx(dh_keys).into_authenticated();
+		// Tor transport goes first, so the onion dialing is preferred. Addresses that
+		// it doesn't support fall through to the clearnet TCP.
+		let tor =
+			if transport_kind.has_tor() {
+				let socks = Socks5TokioTcpConfig::new(tor_socks_port)
+					.nodelay(true)
+					.onion_map(map);
+				let socks = DialPool::new(
+					socks,
+					libp2p_config.dial_workers,
+					Duration::from_secs(libp2p_config.dial_timeout_secs),
+				);
+				OptionalTransport::some(DnsConfig::new(socks).map_err(|e| {
+					Error::Libp2pError(format!("Unable to build a transport, {}", e))
+				})?)
+			} else {
+				OptionalTransport::none()
+			};
+		let tcp = if transport_kind.has_tcp() {
+			OptionalTransport::some(DnsConfig::new(TokioTcpConfig::new().nodelay(true)).map_err(
+				|e| Error::Libp2pError(format!("Unable to build a tcp transport, {}", e)),
+			)?)
+		} else {
+			OptionalTransport::none()
+		};
 
-	let mut swarm = SwarmBuilder::new(transport, gossipsub, this_peer_id.clone())
-		.executor(Box::new(TokioExecutor))
-		.build();
+		// Relayed connections are throttled before they are upgraded
+		let (relay_transport, relay, relay_bandwidth) = build_relay(
+			OrTransport::new(tor, tcp),
+			libp2p_config.relay_hop,
+			relay_nodes.len(),
+			libp2p_config.relay_limits(),
+		);
+		let transport = relay_transport
+			.and_then(move |output, _| future::ready(throttle_relayed(output, &relay_bandwidth)))
+			.upgrade(Version::V1)
+			.authenticate(noise)
+			.multiplex(SelectUpgrade::new(
+				YamuxConfig::default(),
+				MplexConfig::new(),
+			))
+			.map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+			.boxed();
+
+		//Ping pond already works. But it is not we needed
+		// mwc-node does nothing, just forming a node with aping.
+		/*    let config = PingConfig::new()
+				.with_keep_alive(true)
+				.with_interval(Duration::from_secs(600))
+				.with_timeout(Duration::from_secs(60))
+				.with_max_failures( NonZeroU32::new(2).unwrap() );
+			let behaviour = Ping::new(config);
+		*/
+
+		// Set a custom gossipsub
+		let gossipsub_config = gossipsub_config(&libp2p_config)?;
+		let reconnect_interval = Duration::from_secs(libp2p_config.reconnect_interval_secs.max(1));
+
+		// Here are how many connection we will try to keep...
+		let connections_number_low = gossipsub_config.mesh_n_high();
+		// Topics with a smaller mesh are looked up in the DHT
+		let mesh_n_low = gossipsub_config.mesh_n_low();
+
+		// build a gossipsub network behaviour
+		let gossipsub: gossipsub::Gossipsub =
+			gossipsub::Gossipsub::new(MessageAuthenticity::Signed(id_keys), gossipsub_config)
+				.map_err(|e| Error::Libp2pError(format!("Unable to create gossipsub, {}", e)))?;
+
+		let behaviour = Libp2pBehaviour {
+			gossipsub,
+			kademlia: build_kademlia(&this_peer_id, libp2p_config.kad_discovery),
+			query: build_query_behaviour(),
+			relay,
+		};
+		let kad_query_interval = Duration::from_secs(libp2p_config.kad_query_interval_secs.max(1));
 
-	Swarm::listen_on(&mut swarm, addr.clone())
-		.map_err(|e| Error::Libp2pError(format!("Unable to start listening, {}", e)))?;
+		let mut swarm = SwarmBuilder::new(transport, behaviour, this_peer_id.clone())
+			.executor(Box::new(TokioExecutor))
+			.build();
 
-	/*   // It is ping pong handler
-	 future::poll_fn(move |cx: &mut Context<'_>| loop {
-		match swarm.poll_next_unpin(cx) {
-			Poll::Ready(Some(event)) => info!("{:?}", event),
-			Poll::Ready(None) => return Poll::Ready(()),
-			Poll::Pending => return Poll::Pending,
+		if transport_kind.has_tor() {
+			Swarm::listen_on(&mut swarm, addr.clone())
+				.map_err(|e| Error::Libp2pError(format!("Unable to start listening, {}", e)))?;
+		}
+		if transport_kind.has_tcp() {
+			let tcp_addr = format!("/ip4/0.0.0.0/tcp/{}", libp2p_config.tcp_port)
+				.parse::<Multiaddr>()
+				.map_err(|e| Error::Internal(format!("Unable to build tcp address, {}", e)))?;
+			Swarm::listen_on(&mut swarm, tcp_addr)
+				.map_err(|e| Error::Libp2pError(format!("Unable to start tcp listening, {}", e)))?;
+			info!(
+				"libp2p is listening on the clearnet tcp port {}, {} tcp peers",
+				libp2p_config.tcp_port,
+				tcp_peers.len()
+			);
+		}
+		// Peers reach this node through any connected relay
+		if !relay_nodes.is_empty() {
+			let circuit_addr = "/p2p-circuit"
+				.parse::<Multiaddr>()
+				.map_err(|e| Error::Internal(format!("Unable to build relay address, {}", e)))?;
+			Swarm::listen_on(&mut swarm, circuit_addr).map_err(|e| {
+				Error::Libp2pError(format!("Unable to start listening through relays, {}", e))
+			})?;
+			info!(
+				"libp2p is listening through {} relay nodes",
+				relay_nodes.len()
+			);
 		}
-	})
-	.await;*/
-
-	// Special topic for peer reporting. We don't need to listen on it and we
-	// don't want the node forward that message as well
-	let peer_topic = Topic::new(libp2p::gossipsub::PEER_TOPIC).hash();
 
-	// Subscribe to the topics that we are ready to listen
-	LIBP2P_MESSAGE_HANDLERS
-		.read()
-		.iter()
-		.for_each(|(_topic_hash, (_fn, topic))| {
-			if let Err(e) = swarm.subscribe(&topic) {
-				error!("Unable initial subscribe to the topic, {:?}", e);
+		/*   // It is ping pong handler
+		 future::poll_fn(move |cx: &mut Context<'_>| loop {
+			match swarm.poll_next_unpin(cx) {
+				Poll::Ready(Some(event)) => info!("{:?}", event),
+				Poll::Ready(None) => return Poll::Ready(()),
+				Poll::Pending => return Poll::Pending,
 			}
-		});
-
-	init_libp2p_swarm(swarm);
+		})
+		.await;*/
+
+		// Special topic for peer reporting. We don't need to listen on it and we
+		// don't want the node forward that message as well
+		let peer_topic_ident = Topic::new(libp2p::gossipsub::PEER_TOPIC);
+		let peer_topic = peer_topic_ident.hash();
+		// Diagnostic topic of the latency probes, only the nodes with the probes enabled
+		// are subscribed and relay them
+		let probe_topic_ident = Topic::new(PROBE_TOPIC);
+		let probe_topic = probe_topic_ident.hash();
+
+		// Subscribe to the topics that we are ready to listen. The topic rules could be
+		// changed after the handlers were added, the topics that are not allowed are skipped.
+		self.handlers
+			.read()
+			.iter()
+			.for_each(|(_topic_hash, handlers)| {
+				if !libp2p_config.is_topic_allowed(handlers.topic.hash().as_str()) {
+					warn!(
+						"Not subscribing to the topic {}, it is not allowed by the node config",
+						handlers.topic
+					);
+				} else if let Err(e) = swarm.gossipsub.subscribe(&handlers.topic) {
+					error!("Unable initial subscribe to the topic, {:?}", e);
+				}
+			});
+		if gossip_probe::is_gossip_probes_enabled() {
+			if let Err(e) = swarm.gossipsub.subscribe(&probe_topic_ident) {
+				error!("Unable to subscribe to the probe topic, {:?}", e);
+			}
+		}
 
-	let mut requests_cash: HashMap<Commitment, VecDeque<i64>> = HashMap::new();
-	let mut last_cash_clean = Instant::now();
-	let mut last_reconnect = Instant::now();
-	// Kick it off
-	// Event processing future...
-	task::block_on(future::poll_fn(move |cx: &mut Context<'_>| {
-		let mut swarm = LIBP2P_SWARM.lock();
-		match &mut *swarm {
-			Some(swarm) => {
-				loop {
-					let event = swarm.poll_next_unpin(cx);
-					//debug!("swarm.poll_next_unpin event: {:?}", event);
-					match event {
-						Poll::Ready(Some(gossip_event)) => match gossip_event {
-							GossipsubEvent::Message {
-								propagation_source: peer_id,
-								message_id: id,
-								message,
-							} => {
-								debug!(
+		self.init_swarm(swarm);
+
+		let mut requests_cash: HashMap<Commitment, VecDeque<i64>> = HashMap::new();
+		let mut last_cash_clean = Instant::now();
+		let mut last_reconnect = Instant::now();
+		// Dial in progress, it holds the slot of the shared dial scheduler
+		let mut libp2p_dial: Option<(String, PeerId)> = None;
+		// With the standalone identity our onion address doesn't match this_peer_id
+		let this_onion_address = onion_address.to_string();
+		let mut last_mesh_sample = Instant::now();
+		let mut last_access_check = Instant::now();
+		let mut access_banned: HashSet<PeerId> = HashSet::new();
+		self.peer_access_changed.store(true, Ordering::Relaxed);
+		let mut exchange_sender = PeerExchangeSender::new();
+		let mut exchange_receiver = PeerExchangeReceiver::new();
+		let mut last_exchange_check = Instant::now();
+		let mut last_exchange_announce = Instant::now();
+		let mut last_probe = Instant::now();
+		let mut last_peer_store_save = Instant::now();
+		// Size of the messages that wait for the topic handlers, for the traffic stats
+		let mut handler_pending_sizes: HashMap<MessageId, usize> = HashMap::new();
+		let mut kad_discovery = KadDiscovery::new(this_peer_id.clone());
+		// The first lookup runs once the first peers are connected
+		let mut last_kad_query: Option<Instant> = None;
+		let mut shutdown = SwarmShutdown::default();
+		// Kick it off
+		// Event processing future...
+		task::block_on(future::poll_fn(move |cx: &mut Context<'_>| {
+			let mut swarm = self.swarm.lock();
+			if *stop_mutex.lock().unwrap() == 0 {
+				return match &mut *swarm {
+					Some(swarm) => shutdown.poll(swarm, cx),
+					None => Poll::Ready(()),
+				};
+			}
+			match &mut *swarm {
+				Some(swarm) => {
+					loop {
+						let event = match swarm.poll_next_unpin(cx) {
+							Poll::Ready(Some(Libp2pEvent::Kademlia(kad_event))) => {
+								let found = kad_discovery.process_event(kad_event);
+								self.add_kad_peers(found, &this_onion_address);
+								continue;
+							}
+							Poll::Ready(Some(Libp2pEvent::Query(query_event))) => {
+								self.process_query_event(
+									swarm,
+									query_event,
+									&*kernel_validation_fn,
+								);
+								continue;
+							}
+							Poll::Ready(Some(Libp2pEvent::Gossipsub(gossip_event))) => {
+								Poll::Ready(Some(gossip_event))
+							}
+							Poll::Ready(Some(Libp2pEvent::Relay)) => continue,
+							Poll::Ready(None) => Poll::Ready(None),
+							Poll::Pending => Poll::Pending,
+						};
+						//debug!("swarm.poll_next_unpin event: {:?}", event);
+						match event {
+							Poll::Ready(Some(gossip_event)) => match gossip_event {
+								GossipsubEvent::Message {
+									propagation_source: peer_id,
+									message_id: id,
+									message,
+								} => {
+									debug!(
 									"Get libp2p message from {}, with ID {}, topic {}, data: {}",
 									peer_id,
 									id,
@@ -513,282 +2238,819 @@ pub async fn run_libp2p_node(
 									String::from_utf8_lossy(&read_message_data(&message.data))
 										.to_string(),
 								);
+									self.record_gossip_message(
+										&message.topic,
+										&peer_id,
+										&message.data,
+									);
 
-								if message.topic == peer_topic {
-									// We get new peers to connect. Let's update that
-									if !Swarm::is_connected(&swarm, &peer_id) {
-										error!(
-											"Get topic from nodes that we are not connected to."
-										);
-										let gossip = swarm.get_behaviour();
-										let _ = gossip.report_message_validation_result(
-											&id,
-											&peer_id,
-											MessageAcceptance::Reject,
+									if !self.is_peer_id_allowed(&peer_id) {
+										debug!(
+											"Ignoring message from not allowed peer {}",
+											peer_id
 										);
-										gossip.disconnect_peer(peer_id, true);
-										continue;
-									} else {
-										// report validation for this message
-										let gossip = swarm.get_behaviour();
-										if let Err(e) = gossip.report_message_validation_result(
-											&id,
-											&peer_id,
-											MessageAcceptance::Ignore,
-										) {
-											error!("report_message_validation_result failed for error {}", e);
+										let _ = swarm
+											.get_behaviour()
+											.gossipsub
+											.report_message_validation_result(
+												&id,
+												&peer_id,
+												MessageAcceptance::Ignore,
+											);
+										if access_banned.insert(peer_id.clone()) {
+											Swarm::ban_peer_id(swarm, peer_id);
 										}
+										continue;
 									}
 
-									let mut serializer = SimplePopSerializer::new(&message.data);
-									if serializer.version != 1 {
-										warn!("Get peer info data of unexpected version. Probably your client need to be upgraded");
+									if is_source_quarantined(&message.source) {
+										debug!(
+											"Ignoring message of the quarantined author, {}",
+											id
+										);
+										let _ = swarm
+											.get_behaviour()
+											.gossipsub
+											.report_message_validation_result(
+												&id,
+												&peer_id,
+												MessageAcceptance::Ignore,
+											);
+										self.record_gossip_acceptance(
+											&message.topic,
+											&peer_id,
+											&MessageAcceptance::Ignore,
+										);
 										continue;
 									}
 
-									let sz = serializer.pop_u16() as usize;
-									if sz > gossipsub::PEER_EXCHANGE_NUMBER_LIMIT {
-										warn!("Get too many peers from {}", peer_id);
-										// let's ban it, probably it is an attacker...
-										let gossip = swarm.get_behaviour();
-										gossip.disconnect_peer(peer_id, true);
+									if message.topic == probe_topic {
+										let acceptance = process_probe_message(
+											swarm,
+											&this_peer_id,
+											&probe_topic_ident,
+											&peer_id,
+											&message.source,
+											&message.data,
+										);
+										self.record_gossip_acceptance(
+											&message.topic,
+											&peer_id,
+											&acceptance,
+										);
+										if let MessageAcceptance::Accept = acceptance {
+											record_gossip_forward(
+												&swarm.get_behaviour().gossipsub,
+												&message.topic,
+												&peer_id,
+												&message.source,
+												message.data.len(),
+											);
+										}
+										let _ = swarm
+											.get_behaviour()
+											.gossipsub
+											.report_message_validation_result(
+												&id, &peer_id, acceptance,
+											);
 										continue;
 									}
 
-									let mut peer_arr = vec![];
-									for _i in 0..sz {
-										let peer_data = serializer.pop_vec();
-										match PeerId::from_bytes(&peer_data) {
-											Ok(peer) => match peer.as_onion_address() {
-												Ok(addr) => peer_arr.push(addr),
+									if message.topic == peer_topic {
+										// We get new peers to connect. Let's update that
+										if !Swarm::is_connected(&swarm, &peer_id) {
+											error!(
+											"Get topic from nodes that we are not connected to."
+										);
+											let gossip = &swarm.get_behaviour().gossipsub;
+											let _ = gossip.report_message_validation_result(
+												&id,
+												&peer_id,
+												MessageAcceptance::Reject,
+											);
+											self.ban_misbehaving_peer(gossip, peer_id);
+											continue;
+										} else {
+											// report validation for this message
+											let gossip = &swarm.get_behaviour().gossipsub;
+											if let Err(e) = gossip.report_message_validation_result(
+												&id,
+												&peer_id,
+												MessageAcceptance::Ignore,
+											) {
+												error!("report_message_validation_result failed for error {}", e);
+											}
+										}
+
+										let mut serializer =
+											SimplePopSerializer::new(&message.data);
+										if serializer.version == PEER_EXCHANGE_DIFF_VERSION {
+											let sender_address = match peer_id.as_onion_address() {
+												Ok(addr) => addr,
 												Err(e) => {
-													error!("Get from libp2p peer without Dalek PK {}, {}", peer, e);
+													error!("Get peer exchange from libp2p peer without Dalek PK {}, {}", peer_id, e);
 													continue;
 												}
-											},
-											Err(e) => {
-												warn!("Unable to decode the libp2p peer form the peer update message, {}", e);
+											};
+											let exchange_message: PeerExchangeMessage =
+												match ser::deserialize(
+													&mut &serializer.pop_vec()[..],
+													ser::ProtocolVersion(1),
+												) {
+													Ok(m) => m,
+													Err(e) => {
+														warn!("Get invalid peer exchange message from {}, {}", peer_id, e);
+														// Too large or broken lists, probably it is an attacker...
+														let gossip =
+															&swarm.get_behaviour().gossipsub;
+														self.ban_misbehaving_peer(gossip, peer_id);
+														continue;
+													}
+												};
+											if let PeerExchangeMessage::Request { target, base } =
+												&exchange_message
+											{
+												let this_address =
+													this_peer_id.as_onion_address().ok();
+												if this_address.as_ref() == Some(target) {
+													exchange_sender.on_request(base.clone());
+												}
 												continue;
 											}
+											match exchange_receiver.on_message(
+												&sender_address,
+												exchange_message,
+												Utc::now().timestamp(),
+											) {
+												PeerExchangeOutcome::None => (),
+												PeerExchangeOutcome::Publish(reply) => {
+													publish_peer_exchange(
+														swarm,
+														&peer_topic_ident,
+														&reply,
+													);
+												}
+												PeerExchangeOutcome::NewPeers(new_peers) => {
+													let new_peers: Vec<String> = new_peers
+														.into_iter()
+														.filter(|p| !is_peer_quarantined(p))
+														.collect();
+													if !new_peers.is_empty() {
+														info!("Get {} new peers from {}. Will process them later when we will need to increase connection number", new_peers.len(), peer_id);
+														libp2p_peer_store::peers_seen(
+															&new_peers,
+															Utc::now().timestamp(),
+														);
+														let mut new_peers_list = self.peers.write();
+														let entry = new_peers_list
+															.entry(sender_address)
+															.or_insert((vec![], 0));
+														entry.0.extend(new_peers);
+														entry.1 = Utc::now().timestamp() as u64;
+													}
+												}
+											}
+											continue;
+										}
+										if serializer.version != 1 {
+											warn!("Get peer info data of unexpected version. Probably your client need to be upgraded");
+											continue;
 										}
-									}
-									info!("Get {} peers from {}. Will process them later when we will need to increase connection number", peer_arr.len(), peer_id);
 
-									if let Ok(addr) = peer_id.as_onion_address() {
-										let mut new_peers_list = LIBP2P_PEERS.write();
+										let sz = serializer.pop_u16() as usize;
+										if sz > gossipsub::PEER_EXCHANGE_NUMBER_LIMIT {
+											warn!("Get too many peers from {}", peer_id);
+											// let's ban it, probably it is an attacker...
+											let gossip = &swarm.get_behaviour().gossipsub;
+											self.ban_misbehaving_peer(gossip, peer_id);
+											continue;
+										}
 
-										(*new_peers_list).insert(
-											addr,
-											(peer_arr, Utc::now().timestamp() as u64),
-										);
-									} else {
-										error!(
+										let mut peer_arr = vec![];
+										for _i in 0..sz {
+											let peer_data = serializer.pop_vec();
+											match PeerId::from_bytes(&peer_data) {
+												Ok(peer) => match peer.as_onion_address() {
+													Ok(addr) if is_peer_quarantined(&addr) => {
+														debug!("Skipping quarantined peer {} from the peer list", addr);
+													}
+													Ok(addr) => peer_arr.push(addr),
+													Err(e) => {
+														error!("Get from libp2p peer without Dalek PK {}, {}", peer, e);
+														continue;
+													}
+												},
+												Err(e) => {
+													warn!("Unable to decode the libp2p peer form the peer update message, {}", e);
+													continue;
+												}
+											}
+										}
+										info!("Get {} peers from {}. Will process them later when we will need to increase connection number", peer_arr.len(), peer_id);
+
+										if let Ok(addr) = peer_id.as_onion_address() {
+											libp2p_peer_store::peers_seen(
+												&peer_arr,
+												Utc::now().timestamp(),
+											);
+											let mut new_peers_list = self.peers.write();
+
+											(*new_peers_list).insert(
+												addr,
+												(peer_arr, Utc::now().timestamp() as u64),
+											);
+										} else {
+											error!(
 											"Internal Error. Getting peer without onion address {}",
 											peer_id
 										);
-									}
-								} else {
-									// We get the regular message and we need to validate it now.
-
-									let gossip = swarm.get_behaviour();
-
-									let acceptance = match validate_integrity_message(
-										&peer_id,
-										&message.data,
-										kernel_validation_fn.clone(),
-										&mut requests_cash,
-										fee_base,
-									) {
-										Ok((integrity_fee, sender_address)) => {
-											if integrity_fee > 0 {
-												let mut acceptance = MessageAcceptance::Accept;
-
-												if let Some((handler, _topic)) =
-													LIBP2P_MESSAGE_HANDLERS
+										}
+									} else {
+										// We get the regular message and we need to validate it now.
+
+										let gossip = &swarm.get_behaviour().gossipsub;
+
+										// Messages are decoded with the payload schema of the topic. Unknown
+										// version is probably sent by the newer node, it is not penalized.
+										let payload =
+											match decode_payload(&message.topic, &message.data) {
+												PayloadDecoding::Decoded(payload) => Ok(payload),
+												PayloadDecoding::UnknownVersion(version) => {
+													debug!(
+													"Ignoring message of unknown version {} on topic {} from {}",
+													version, message.topic, peer_id
+												);
+													self.record_unknown_version(
+														&message.topic,
+														version,
+													);
+													Err(MessageAcceptance::Ignore)
+												}
+												PayloadDecoding::Invalid(e) => {
+													debug!(
+													"Get invalid message on topic {} from {}, {}",
+													message.topic, peer_id, e
+												);
+													Err(MessageAcceptance::Reject)
+												}
+											};
+
+										// None if the validation is done by the topic handler, the result
+										// is reported when the handler is finished
+										let acceptance = match payload.map(|payload| {
+											let res = validate_integrity_payload(
+												&peer_id,
+												&payload,
+												kernel_validation_fn.clone(),
+												&mut requests_cash,
+												fee_base,
+												&policy,
+												&message.topic,
+											);
+											(payload, res)
+										}) {
+											Err(acceptance) => Some(acceptance),
+											Ok((payload, Ok((integrity_fee, sender_address)))) => {
+												if integrity_fee > 0 {
+													let handlers: Vec<TopicHandler> = self
+														.handlers
 														.read()
 														.get(&message.topic)
-												{
-													if !(handler)(
-														&sender_address,
-														&message.topic,
-														read_message_data(&message.data),
-														integrity_fee,
+														.map(|h| {
+															h.handlers
+																.iter()
+																.map(|(_, f)| f.clone())
+																.collect()
+														})
+														.unwrap_or(vec![]);
+													let data = payload.data;
+													let context = MessageContext {
+														message_id: id.clone(),
+														content_hash: content_hash(&data),
+														source: message.source.clone(),
+														propagation_source: peer_id.clone(),
+														topic: message.topic.clone(),
+														sender_address,
+														fee: integrity_fee,
+														received: Utc::now().timestamp(),
+													};
+													if handlers.is_empty() {
+														message_accepted(
+															gossip,
+															&context,
+															message.data.len(),
+														);
+														Some(MessageAcceptance::Accept)
+													} else if self
+														.is_handler_disabled(&message.topic)
+													{
+														Some(MessageAcceptance::Ignore)
+													} else if libp2p_seen_cache::is_message_seen(
+														&content_hash(&message.data),
+														context.received,
 													) {
-														// false mean that message was invalid, so we can ban the peer
-														acceptance = MessageAcceptance::Reject;
+														// Handlers already processed it, probably before the restart
+														self.record_seen_before(&message.topic);
+														Some(MessageAcceptance::Ignore)
+													} else {
+														// The message is rejected if any handler rejects it
+														handler_pending_sizes
+															.insert(id.clone(), message.data.len());
+														handler_sandbox()
+															.submit_all(handlers, context, data);
+														None
 													}
+												} else {
+													// Invalid message
+													Some(MessageAcceptance::Reject)
 												}
-												acceptance
-											} else {
-												// Invalid message
-												MessageAcceptance::Reject
 											}
-										}
-										Err(e) => {
-											warn!("Message is skipped, Unable to verify the message because of some error. {:?}", e);
-											MessageAcceptance::Ignore
-										}
-									};
+											Ok((_, Err(e))) => {
+												warn!("Message is skipped, Unable to verify the message because of some error. {:?}", e);
+												Some(MessageAcceptance::Ignore)
+											}
+										};
 
-									debug!("report_message_validation_result as {:?}", acceptance);
-									let _ = gossip.report_message_validation_result(
-										&id, &peer_id, acceptance,
-									);
+										let acceptance = match acceptance {
+											Some(acceptance) => acceptance,
+											None => continue,
+										};
+										self.record_gossip_acceptance(
+											&message.topic,
+											&peer_id,
+											&acceptance,
+										);
+										debug!(
+											"report_message_validation_result as {:?}",
+											acceptance
+										);
+										let _ = gossip.report_message_validation_result(
+											&id, &peer_id, acceptance,
+										);
+									}
 								}
+								_ => {}
+							},
+							Poll::Ready(None) | Poll::Pending => {
+								break;
 							}
-							_ => {}
-						},
-						Poll::Ready(None) | Poll::Pending => {
-							break;
 						}
 					}
-				}
 
-				// cleanup expired requests_cash values
-				let history_time_limit = Utc::now().timestamp()
-					- INTEGRITY_CALL_HISTORY_LEN_LIMIT as i64 * INTEGRITY_CALL_MAX_PERIOD;
-				let now = Instant::now();
-				if last_cash_clean + Duration::from_secs(600) < now {
-					last_cash_clean = now;
-					// Let's do clean up...
-					requests_cash.retain(|_commit, history| {
-						*history.back().unwrap_or(&0) > history_time_limit
-					});
-				}
+					// Report the validation results of the finished topic handlers
+					let sandbox = handler_sandbox();
+					sandbox.set_waker(cx.waker());
+					for (context, outcome) in sandbox.collect() {
+						let acceptance = self.record_handler_outcome(&context.topic, &outcome);
+						self.record_gossip_acceptance(
+							&context.topic,
+							&context.propagation_source,
+							&acceptance,
+						);
+						let size = handler_pending_sizes
+							.remove(&context.message_id)
+							.unwrap_or(0);
+						match acceptance {
+							MessageAcceptance::Accept => {
+								message_accepted(&swarm.get_behaviour().gossipsub, &context, size)
+							}
+							MessageAcceptance::Reject => {
+								self.quarantine_message_source(&this_peer_id, &context)
+							}
+							MessageAcceptance::Ignore => (),
+						}
+						debug!(
+							"report_message_validation_result for {} as {:?}",
+							context.message_id, acceptance
+						);
+						let _ = swarm
+							.get_behaviour()
+							.gossipsub
+							.report_message_validation_result(
+								&context.message_id,
+								&context.propagation_source,
+								acceptance,
+							);
+					}
 
-				// Will try to reconnect if needed every 15 seconds.
-				if last_reconnect + Duration::from_secs(14) < now {
-					last_reconnect = now;
-					// let's try to make a new connection if needed
-					let nw_info: NetworkInfo = Swarm::network_info(&swarm);
-					let mut rng = rand::thread_rng();
-
-					debug!(
-						"Processing libp2p reconnection task. Has connections: {},  {:?}",
-						nw_info.connection_counters().num_connections(),
-						nw_info.connection_counters()
-					);
+					if last_mesh_sample.elapsed() > GOSSIP_MESH_SAMPLE_INTERVAL {
+						last_mesh_sample = Instant::now();
+						self.update_gossip_mesh_stats(&swarm.get_behaviour().gossipsub);
+						update_connection_stats(swarm);
+					}
+
+					// Changes of our peer set are announced with the digest, the neighbours
+					// request the diffs
+					if last_exchange_check.elapsed() > PEER_EXCHANGE_CHECK_INTERVAL {
+						last_exchange_check = Instant::now();
+						let peers: BTreeSet<String> = Swarm::network_info(&swarm)
+							.into_peers()
+							.iter()
+							.filter_map(|p| p.as_onion_address().ok())
+							.collect();
+						libp2p_peer_store::peers_seen(&peers, Utc::now().timestamp());
+						let announce = match exchange_sender.update(peers) {
+							Some(digest) => Some(digest),
+							None if last_exchange_announce.elapsed()
+								> PEER_EXCHANGE_ANNOUNCE_INTERVAL =>
+							{
+								Some(exchange_sender.digest_message())
+							}
+							None => None,
+						};
+						if let Some(digest) = announce {
+							last_exchange_announce = Instant::now();
+							publish_peer_exchange(swarm, &peer_topic_ident, &digest);
+						}
+						exchange_receiver.prune(Utc::now().timestamp() - PEER_EXCHANGE_EXPIRE_SECS);
+					}
+					for response in exchange_sender.responses() {
+						publish_peer_exchange(swarm, &peer_topic_ident, &response);
+					}
+
+					let probe = match &mut *gossip_probe::GOSSIP_PROBES.lock() {
+						Some(probes)
+							if last_probe.elapsed()
+								> Duration::from_secs(probes.interval_secs()) =>
+						{
+							let now_ms = Utc::now().timestamp_millis();
+							probes.prune(now_ms - PROBE_STATE_EXPIRE_MS);
+							Some(probes.next_probe(now_ms))
+						}
+						_ => None,
+					};
+					if let Some(probe) = probe {
+						last_probe = Instant::now();
+						publish_probe_message(swarm, &probe_topic_ident, &probe);
+					}
+
+					if last_peer_store_save.elapsed() > PEER_STORE_SAVE_INTERVAL {
+						last_peer_store_save = Instant::now();
+						if let Err(e) = libp2p_peer_store::save_seen_peers() {
+							warn!("Unable to save the discovered libp2p peers, {}", e);
+						}
+						if let Err(e) = libp2p_seen_cache::save_seen_messages() {
+							warn!("Unable to save the seen libp2p messages, {}", e);
+						}
+					}
 
-					// We are leaking on oputgoing connection. The leak is slow, but we really don't want to go through all libp2p code.
-					// In case of leak, we will restart the swarm.
-					// Note, the leak is minor, it takes about 4-5 days to build 400 leaked connections. In this case node trying to establish
-					// connections constantly.
-					if nw_info.connection_counters().num_pending_outgoing() > 100 {
-						info!("Restarting libp2p engine...");
-						return Poll::Ready(()); // Exiting
+					if swarm.kademlia.is_enabled() {
+						let connected = Swarm::network_info(&swarm).into_peers();
+						let kad_due = match last_kad_query {
+							Some(last) => last.elapsed() > kad_query_interval,
+							None => !connected.is_empty(),
+						};
+						if kad_due {
+							last_kad_query = Some(Instant::now());
+							let known_peers = self.kad_seed_peers(&connected);
+							let topics = self.handled_topics();
+							let thin_topics: Vec<TopicHash> = topics
+								.iter()
+								.filter(|t| swarm.gossipsub.mesh_peers(t).count() < mesh_n_low)
+								.cloned()
+								.collect();
+							if let Some(kademlia) = swarm.kademlia.as_mut() {
+								kad_discovery.lookup(kademlia, known_peers, &topics, &thin_topics);
+							}
+						}
 					}
 
-					if nw_info.connection_counters().num_connections()
-						< connections_number_low as u32
+					if self.peer_access_changed.swap(false, Ordering::Relaxed)
+						|| last_access_check.elapsed() > PEER_ACCESS_CHECK_INTERVAL
 					{
-						// Let's try to connect to somebody if we can...
-						let mut address_to_connect: Option<Multiaddr> = None;
-						loop {
-							// cloned to unblock the mutex
-							let mut libp2p_peers = LIBP2P_PEERS.write();
-							let peers: Vec<String> = libp2p_peers.keys().cloned().collect();
-							if let Some(peer_id) = peers.choose(&mut rng) {
-								if let Some(peers) = libp2p_peers.get_mut(peer_id) {
-									if !peers.0.is_empty() {
-										let tor_address =
-											peers.0.remove(rng.gen::<usize>() % peers.0.len());
-
-										let res: Result<OnionV3Address, OnionV3AddressError> =
-											tor_address.as_str().try_into();
-										let p = match res {
-											Ok(onion_addr) => match onion_addr.to_ed25519() {
-												Ok(pk) => PeerId::from_public_key(
-													libp2p::identity::PublicKey::Ed25519(
-														libp2p::identity::ed25519::PublicKey(pk),
-													),
-												),
+						last_access_check = Instant::now();
+						self.update_peer_access_bans(swarm, &mut access_banned);
+					}
+
+					// cleanup expired requests_cash values
+					let history_time_limit =
+						clock::now().timestamp() - policy.call_history_window_secs();
+					let now = Instant::now();
+					if last_cash_clean + Duration::from_secs(600) < now {
+						last_cash_clean = now;
+						// Let's do clean up...
+						requests_cash.retain(|_commit, history| {
+							*history.back().unwrap_or(&0) > history_time_limit
+						});
+					}
+
+					// New identity is announced by connecting to the peers, so don't wait for
+					// the reconnection interval
+					let identity_changed = self.identity_changed.swap(false, Ordering::Relaxed);
+					if identity_changed {
+						let seed_list = self.seed_list.read().clone();
+						self.set_seed_list(&seed_list, false);
+					}
+
+					// Will try to reconnect if needed every reconnect interval
+					if identity_changed || last_reconnect + reconnect_interval < now {
+						last_reconnect = now;
+
+						// Release the dial slot once the previous dial is finished
+						let dial_finished = match &libp2p_dial {
+							Some((_, p)) => !Swarm::is_dialing(&swarm, p),
+							None => false,
+						};
+						if dial_finished {
+							if let Some((dial_key, _)) = libp2p_dial.take() {
+								dial_scheduler().release(&dial_key);
+							}
+						}
+
+						// let's try to make a new connection if needed
+						let nw_info: NetworkInfo = Swarm::network_info(&swarm);
+						let mut rng = rand::thread_rng();
+
+						debug!(
+							"Processing libp2p reconnection task. Has connections: {},  {:?}",
+							nw_info.connection_counters().num_connections(),
+							nw_info.connection_counters()
+						);
+
+						// We are leaking on oputgoing connection. The leak is slow, but we really don't want to go through all libp2p code.
+						// In case of leak, we will restart the swarm.
+						// Note, the leak is minor, it takes about 4-5 days to build 400 leaked connections. In this case node trying to establish
+						// connections constantly.
+						if nw_info.connection_counters().num_pending_outgoing() > 100 {
+							info!("Restarting libp2p engine...");
+							return Poll::Ready(()); // Exiting
+						}
+
+						// Relay nodes are kept connected, the peers reach this node through them
+						let connected_relays: Vec<(Multiaddr, PeerId)> = relay_nodes
+							.iter()
+							.filter(|(_, p)| Swarm::is_connected(&swarm, p))
+							.cloned()
+							.collect();
+						record_connected_relays(connected_relays.len());
+						for (addr, p) in &relay_nodes {
+							if *p == this_peer_id
+								|| Swarm::is_connected(&swarm, p)
+								|| Swarm::is_dialing(&swarm, p)
+							{
+								continue;
+							}
+							match Swarm::dial_addr(swarm, addr.clone()) {
+								Ok(_) => debug!("Dialling to the relay node {}", addr),
+								Err(con_limit) => warn!(
+									"Unable to dial the relay node {}. Connected to {} peers, connection limit {}",
+									addr, con_limit.current, con_limit.limit
+								),
+							}
+						}
+
+						if nw_info.connection_counters().num_connections()
+							< connections_number_low as u32
+							&& libp2p_dial.is_none()
+						{
+							// Let's try to connect to somebody if we can...
+							let mut address_to_connect: Option<Multiaddr> = None;
+							// Discovered peers are onion addresses, they need the Tor transport.
+							// No point to dial them while the proxy is down.
+							let proxy_down = transport_kind.has_tor() && is_proxy_down();
+							if proxy_down {
+								debug!("Tor SOCKS proxy is down, libp2p onion dials are paused");
+							}
+							// Failed peers are retried once their backoff expires, before the
+							// new ones are tried
+							if transport_kind.has_tor() && !proxy_down {
+								for tor_address in dial_retries() {
+									if !self.is_peer_allowed(&tor_address)
+										|| is_peer_quarantined(&tor_address)
+									{
+										forget_dial_failures(&tor_address);
+										continue;
+									}
+									let (p, addr) = match onion_dial_target(&tor_address) {
+										Ok(target) => target,
+										Err(e) => {
+											warn!(
+												"Unable to retry libp2p peer {}, {}",
+												tor_address, e
+											);
+											forget_dial_failures(&tor_address);
+											continue;
+										}
+									};
+									if Swarm::is_connected(&swarm, &p) {
+										// It connected to us
+										forget_dial_failures(&tor_address);
+										continue;
+									}
+									if Swarm::is_dialing(&swarm, &p) || p == this_peer_id {
+										continue;
+									}
+									if !dial_scheduler()
+										.try_acquire(&tor_address, DialPriority::New)
+									{
+										debug!("No free dial slots, libp2p retry is postponed");
+										break;
+									}
+									libp2p_dial = Some((tor_address, p));
+									address_to_connect = Some(addr);
+									break;
+								}
+							}
+
+							while address_to_connect.is_none()
+								&& transport_kind.has_tor()
+								&& !proxy_down
+							{
+								// cloned to unblock the mutex
+								let mut libp2p_peers = self.peers.write();
+								let peers: Vec<String> = libp2p_peers.keys().cloned().collect();
+								if let Some(peer_id) = peers.choose(&mut rng) {
+									if let Some(peers) = libp2p_peers.get_mut(peer_id) {
+										if !peers.0.is_empty() {
+											let tor_address =
+												peers.0.remove(rng.gen::<usize>() % peers.0.len());
+
+											// Backed off peers are tracked by the dial backoff, they
+											// are retried from there
+											if !self.is_peer_allowed(&tor_address)
+												|| is_peer_quarantined(&tor_address)
+												|| !is_dial_allowed(&tor_address)
+												|| Libp2pPeerAccess::normalize_address(&tor_address)
+													== this_onion_address
+											{
+												continue;
+											}
+
+											let (p, addr) = match onion_dial_target(&tor_address) {
+												Ok(target) => target,
 												Err(e) => {
-													error!("Unable to build PeerId form onion address {}, {}", tor_address, e);
+													warn!("Unable to dial libp2p peer {}. Will skip it, {}", tor_address, e);
 													continue;
 												}
-											},
-											Err(e) => {
-												error!("Unable to build PeerId form onion address {}, {}", tor_address, e);
+											};
+
+											if Swarm::is_connected(&swarm, &p)
+												|| Swarm::is_dialing(&swarm, &p) || p == this_peer_id
+											{
 												continue;
 											}
-										};
 
-										if Swarm::is_connected(&swarm, &p)
-											|| Swarm::is_dialing(&swarm, &p) || p == this_peer_id
-										{
+											// Peers reported by our own node passed the p2p handshake,
+											// the stored peers were seen before the restart
+											let priority = if peer_id == "SELF"
+												|| peer_id == STORED_PEERS_KEY
+											{
+												DialPriority::Reputable
+											} else {
+												DialPriority::New
+											};
+											if !dial_scheduler().try_acquire(&tor_address, priority)
+											{
+												debug!(
+													"No free dial slots, libp2p dial is postponed"
+												);
+												peers.0.push(tor_address);
+												break;
+											}
+											libp2p_dial = Some((tor_address, p));
+											address_to_connect = Some(addr);
+											break;
+										} else {
+											libp2p_peers.remove(peer_id);
 											continue;
 										}
+									}
+									continue;
+								} else {
+									break; // no data is found...
+								}
+							}
 
-										let address = match p.get_address() {
-											Ok(addr) => addr,
-											Err(e) => {
-												warn!("Unable to get peer address to connect . Will skip it, {}", e);
-												continue;
-											}
-										};
+							// Clearnet peers when no onion peer is available
+							if address_to_connect.is_none() && transport_kind.has_tcp() {
+								let candidates: Vec<&(Multiaddr, PeerId)> = tcp_peers
+									.iter()
+									.filter(|(_, p)| {
+										*p != this_peer_id
+											&& !Swarm::is_connected(&swarm, p)
+											&& !Swarm::is_dialing(&swarm, p) && self
+											.is_peer_allowed(&p.to_string()) && !is_peer_quarantined(
+											&p.to_string(),
+										)
+									})
+									.collect();
+								if let Some((addr, p)) = candidates.choose(&mut rng) {
+									let dial_key = addr.to_string();
+									if dial_scheduler()
+										.try_acquire(&dial_key, DialPriority::Reputable)
+									{
+										libp2p_dial = Some((dial_key, p.clone()));
+										address_to_connect = Some(addr.clone());
+									} else {
+										debug!("No free dial slots, libp2p tcp dial is postponed");
+									}
+								}
+							}
 
-										let multiaddress = format!(
-											"/onion3/{}:{}",
-											address,
-											global::get_tor_libp2p_port()
-										);
-										match multiaddress.parse::<Multiaddr>() {
-											Ok(addr) => {
+							// Peers that can't be dialed directly are dialed through a relay
+							if address_to_connect.is_none() && !connected_relays.is_empty() {
+								let candidates: Vec<(String, PeerId)> = backed_off_peers()
+									.into_iter()
+									.filter(|a| self.is_peer_allowed(a) && !is_peer_quarantined(a))
+									.filter_map(|a| onion_to_peer_id(&a).ok().map(|p| (a, p)))
+									.filter(|(_, p)| {
+										*p != this_peer_id
+											&& !Swarm::is_connected(&swarm, p)
+											&& !Swarm::is_dialing(&swarm, p)
+									})
+									.collect();
+								if let (Some((tor_address, p)), Some((relay_addr, relay_id))) = (
+									candidates.choose(&mut rng),
+									connected_relays.choose(&mut rng),
+								) {
+									match relayed_dial_addr(relay_addr, relay_id, p) {
+										Ok(addr) => {
+											let dial_key = format!("relay:{}", tor_address);
+											if dial_scheduler()
+												.try_acquire(&dial_key, DialPriority::New)
+											{
+												record_relayed_dial();
+												libp2p_dial = Some((dial_key, p.clone()));
 												address_to_connect = Some(addr);
-												break;
-											}
-											Err(e) => {
-												warn!("Unable to construct onion multiaddress from {} the peer address. Will skip it, {}", multiaddress, e);
-												continue;
+											} else {
+												debug!("No free dial slots, libp2p relayed dial is postponed");
 											}
 										}
-									} else {
-										libp2p_peers.remove(peer_id);
-										continue;
+										Err(e) => warn!(
+											"Unable to dial libp2p peer {} through the relay, {}",
+											tor_address, e
+										),
 									}
 								}
-								continue;
-							} else {
-								break; // no data is found...
 							}
-						}
 
-						if address_to_connect.is_none()
-							&& nw_info.connection_counters().num_connections() == 0
-						{
-							info!("Retry connect to libp2p seeds peers...");
-							let seed_list = SEED_LIST.read().clone();
-							set_seed_list(&seed_list, false);
-						}
+							if address_to_connect.is_none()
+								&& transport_kind.has_tor()
+								&& !proxy_down && nw_info.connection_counters().num_connections()
+								== 0
+							{
+								info!("Retry connect to libp2p seeds peers...");
+								let seed_list = self.seed_list.read().clone();
+								self.set_seed_list(&seed_list, false);
+							}
 
-						// The address of a new peer is selected, we can deal to it.
-						if let Some(addr) = address_to_connect {
-							match Swarm::dial_addr(swarm, addr.clone()) {
-								Ok(_) => {
-									info!("Dialling to a new peer {}", addr);
-								}
-								Err(con_limit) => {
-									error!("Unable deal to a new peer. Connected to {} peers, connection limit {}", con_limit.current, con_limit.limit);
+							// The address of a new peer is selected, we can deal to it.
+							if let Some(addr) = address_to_connect {
+								match Swarm::dial_addr(swarm, addr.clone()) {
+									Ok(_) => {
+										info!("Dialling to a new peer {}", addr);
+									}
+									Err(con_limit) => {
+										error!("Unable deal to a new peer. Connected to {} peers, connection limit {}", con_limit.current, con_limit.limit);
+										if let Some((dial_key, _)) = libp2p_dial.take() {
+											dial_scheduler().release(&dial_key);
+										}
+									}
 								}
 							}
 						}
 					}
 				}
-			}
-			None => (),
-		};
+				None => (),
+			};
 
-		if *stop_mutex.lock().unwrap() == 0 {
-			info!("Exiting libp2p polling task");
-			Poll::Ready(()) // Exiting
-		} else {
+			if *stop_mutex.lock().unwrap() == 0 {
+				info!("Exiting libp2p polling task");
+				// Next poll runs the shutdown sequence
+				cx.waker().wake_by_ref();
+			}
 			Poll::Pending as Poll<()>
-		}
-	}));
+		}));
 
-	reset_libp2p_swarm();
+		self.reset_swarm();
 
-	Ok(())
+		Ok(())
+	}
+}
+
+/// Run the libp2p node of this module, see `Libp2pNode::run`
+pub async fn run_libp2p_node(
+	tor_socks_port: u16,
+	tor_secret: &[u8; 32],
+	identity_secret: &[u8; 32],
+	libp2p_port: u16,
+	fee_base: u64,
+	kernel_validation_fn: Arc<impl Fn(&Commitment) -> Result<Option<TxKernel>, Error>>,
+	policy: Libp2pPolicy,
+	stop_mutex: std::sync::Arc<std::sync::Mutex<u32>>,
+) -> Result<(), Error> {
+	let node = libp2p_node();
+	node.run(
+		tor_socks_port,
+		tor_secret,
+		identity_secret,
+		libp2p_port,
+		fee_base,
+		kernel_validation_fn,
+		policy,
+		stop_mutex,
+	)
+	.await
 }
 
 // return paid fee if this message is valid. It is caller responsibility to make sure that valid_outputs cache is well maintained
@@ -800,19 +3062,45 @@ pub fn validate_integrity_message(
 	output_validation_fn: Arc<impl Fn(&Commitment) -> Result<Option<TxKernel>, Error>>,
 	requests_cash: &mut HashMap<Commitment, VecDeque<i64>>,
 	fee_base: u64,
+	policy: &Libp2pPolicy,
+	topic: &TopicHash,
 ) -> Result<(u64, String), Error> {
-	let mut ser = SimplePopSerializer::new(message);
-	if ser.version != get_message_version() {
-		debug!(
-			"Get message with invalid version {} from peer {}",
-			ser.version, peer_id
-		);
-		// Will be rejected and peer will be banned
-		return Ok((0, String::new()));
+	match default_payload_schema().decode(message) {
+		PayloadDecoding::Decoded(payload) => validate_integrity_payload(
+			peer_id,
+			&payload,
+			output_validation_fn,
+			requests_cash,
+			fee_base,
+			policy,
+			topic,
+		),
+		PayloadDecoding::UnknownVersion(version) => {
+			debug!(
+				"Get message with unknown version {} from peer {}",
+				version, peer_id
+			);
+			Ok((0, String::new()))
+		}
+		PayloadDecoding::Invalid(e) => {
+			debug!("Get invalid message from peer {}, {}", peer_id, e);
+			Ok((0, String::new()))
+		}
 	}
+}
 
+// Same as validate_integrity_message, for the payload that is already decoded with the topic schema
+fn validate_integrity_payload(
+	peer_id: &PeerId,
+	payload: &IntegrityPayload,
+	output_validation_fn: Arc<impl Fn(&Commitment) -> Result<Option<TxKernel>, Error>>,
+	requests_cash: &mut HashMap<Commitment, VecDeque<i64>>,
+	fee_base: u64,
+	policy: &Libp2pPolicy,
+	topic: &TopicHash,
+) -> Result<(u64, String), Error> {
 	// Let's check signature first. The kernel search might take time. Signature checking should be faster.
-	let integrity_kernel_excess = Commitment::from_vec(ser.pop_vec());
+	let integrity_kernel_excess = Commitment::from_vec(payload.kernel_excess.clone());
 	let integrity_pk = match integrity_kernel_excess.to_pubkey() {
 		Ok(pk) => pk,
 		Err(e) => {
@@ -827,7 +3115,7 @@ pub fn validate_integrity_message(
 	let secp = Secp256k1::with_caps(ContextFlag::VerifyOnly);
 
 	// Checking if public key match the signature.
-	let sender_address_pk = match DalekPublicKey::from_bytes(&ser.pop_vec()) {
+	let sender_address_pk = match DalekPublicKey::from_bytes(&payload.sender_pk) {
 		Ok(pk) => pk,
 		Err(e) => {
 			debug!(
@@ -851,7 +3139,7 @@ pub fn validate_integrity_message(
 
 	let sender_address = PeerId::onion_v3_from_pubkey(&sender_address_pk);
 
-	let signature = match Signature::from_compact(&ser.pop_vec()) {
+	let signature = match Signature::from_compact(&payload.signature) {
 		Ok(s) => s,
 		Err(e) => {
 			debug!(
@@ -901,11 +3189,11 @@ pub fn validate_integrity_message(
 	}
 
 	// Updating calls history cash.
-	let now = Utc::now().timestamp();
+	let now = clock::now().timestamp();
 	match requests_cash.get_mut(&integrity_kernel_excess) {
 		Some(calls) => {
 			calls.push_back(now);
-			while calls.len() > INTEGRITY_CALL_HISTORY_LEN_LIMIT {
+			while calls.len() > policy.integrity_call_history_len {
 				calls.pop_front();
 			}
 		}
@@ -915,15 +3203,17 @@ pub fn validate_integrity_message(
 			requests_cash.insert(integrity_kernel_excess.clone(), calls);
 		}
 	}
-	// Checking if ths peer sent too many messages
+	// Checking if ths peer sent too many messages. The history is shared by the topics,
+	// the limit is the one of the topic of this message.
 	let call_history = requests_cash.get(&integrity_kernel_excess).unwrap();
-	if call_history.len() >= INTEGRITY_CALL_HISTORY_LEN_LIMIT {
+	if call_history.len() >= policy.integrity_call_history_len {
 		let call_period = (call_history.back().unwrap() - call_history.front().unwrap())
 			/ (call_history.len() - 1) as i64;
-		if call_period < INTEGRITY_CALL_MAX_PERIOD {
+		let min_call_period = policy.min_call_period(topic.as_str());
+		if (call_period as f64) < min_call_period {
 			debug!(
-				"Get invalid message from peer {}. Message sending period is {}, limit {}",
-				peer_id, call_period, INTEGRITY_CALL_MAX_PERIOD
+				"Get invalid message from peer {}. Message sending period is {}, limit {} for topic {}",
+				peer_id, call_period, min_call_period, topic
 			);
 			return Ok((0, String::new()));
 		}
@@ -936,21 +3226,14 @@ pub fn validate_integrity_message(
 	return Ok((integrity_fee, sender_address));
 }
 
-/// Skip the header and return the message data
+/// Skip the header and return the message data. Empty for the messages that can't be
+/// decoded with the default payload schema.
 pub fn read_message_data(message: &Vec<u8>) -> Vec<u8> {
-	let mut ser = SimplePopSerializer::new(message);
-	if ser.version != get_message_version() {
+	match default_payload_schema().decode(message) {
+		PayloadDecoding::Decoded(payload) => payload.data,
 		// Probably wrong network. But may be wrong version as well. We don't want to read it
-		return vec![];
+		_ => vec![],
 	}
-
-	// Skipping header data. The header size if not known because bulletproof size can vary.
-	ser.skip_vec();
-	ser.skip_vec();
-	ser.skip_vec();
-
-	// Here is the data
-	ser.pop_vec()
 }
 
 /// Helper method for the wallet that allow to build a message with integrity_output
@@ -963,7 +3246,7 @@ pub fn build_integrity_message(
 	signature: &Signature,
 	message_data: &[u8],
 ) -> Result<Vec<u8>, Error> {
-	let mut ser = SimplePushSerializer::new(get_message_version());
+	let mut ser = SimplePushSerializer::new(integrity_message_version());
 
 	ser.push_vec(&kernel_excess.0);
 	ser.push_vec(tor_pk.as_bytes());
@@ -1004,6 +3287,8 @@ fn test_integrity() -> Result<(), Error> {
 	let empty_output_validation_fn = Arc::new(empty_output_validation_fn);
 
 	let fee_base: u64 = 1_000_000;
+	let policy = Libp2pPolicy::default();
+	let topic = Topic::new("SwapMarketplace").hash();
 
 	let mut valid_kernels = HashMap::<Commitment, TxKernel>::new();
 	let paid_integrity_fee = fee_base * 10;
@@ -1025,7 +3310,9 @@ fn test_integrity() -> Result<(), Error> {
 			&encoded_message,
 			empty_output_validation_fn.clone(),
 			&mut requests_cache,
-			fee_base
+			fee_base,
+			&policy,
+			&topic
 		)
 		.unwrap()
 		.0,
@@ -1039,6 +3326,8 @@ fn test_integrity() -> Result<(), Error> {
 		output_validation_fn.clone(),
 		&mut requests_cache,
 		fee_base,
+		&policy,
+		&topic,
 	)
 	.unwrap();
 
@@ -1055,7 +3344,9 @@ fn test_integrity() -> Result<(), Error> {
 			&encoded_message,
 			output_validation_fn.clone(),
 			&mut requests_cache,
-			fee_base
+			fee_base,
+			&policy,
+			&topic
 		)
 		.unwrap()
 		.0,
@@ -1071,7 +3362,9 @@ fn test_integrity() -> Result<(), Error> {
 				&encoded_message,
 				output_validation_fn.clone(),
 				&mut requests_cache,
-				fee_base
+				fee_base,
+				&policy,
+				&topic
 			)
 			.unwrap()
 			.0,
@@ -1087,7 +3380,9 @@ fn test_integrity() -> Result<(), Error> {
 			&encoded_message,
 			output_validation_fn.clone(),
 			&mut requests_cache,
-			fee_base
+			fee_base,
+			&policy,
+			&topic
 		)
 		.unwrap()
 		.0,
@@ -1102,7 +3397,9 @@ fn test_integrity() -> Result<(), Error> {
 			&encoded_message,
 			output_validation_fn.clone(),
 			&mut requests_cache,
-			fee_base
+			fee_base,
+			&policy,
+			&topic
 		)
 		.unwrap()
 		.0,
@@ -1117,7 +3414,9 @@ fn test_integrity() -> Result<(), Error> {
 			&encoded_message,
 			output_validation_fn.clone(),
 			&mut requests_cache,
-			fee_base
+			fee_base,
+			&policy,
+			&topic
 		)
 		.unwrap()
 		.0,
@@ -0,0 +1,473 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Establishment of the libp2p connections through the Tor SOCKS proxy. A SOCKS handshake
+//! to an onion address can hang for minutes, so every dial runs on its own worker task
+//! with a hard timeout, and the number of the running dials is limited. Dial that is
+//! dropped by the swarm or runs out of time is aborted.
+//!
+//! Failed dials are classified. If the local proxy doesn't accept the connections, the
+//! peer is not to blame, all the Tor dials are paused instead. Unreachable or timed out
+//! peer is backed off exponentially and retried when its backoff expires, a single
+//! failure doesn't drop it. After MAX_DIAL_RETRIES failures in a row the peer goes to
+//! the cooldown list, it is not retried and not dialed even if it is discovered again
+//! until the cooldown expires. Then it gets a single attempt per cooldown, until it is
+//! dialed successfully.
+
+use crate::store::Libp2pPeerAccess;
+use crate::util::{clock, Mutex};
+use std::collections::HashMap;
+use std::io;
+
+/// Default hard timeout of the SOCKS dial, in seconds
+pub const DEFAULT_DIAL_TIMEOUT_SECS: u64 = 45;
+/// Default number of the dials that can run at the same time
+pub const DEFAULT_DIAL_WORKERS: usize = 8;
+/// Peer backoff after the first failure, in seconds. It doubles with every failure.
+pub const PEER_BACKOFF_BASE_SECS: i64 = 30;
+/// Max peer backoff, in seconds
+pub const PEER_BACKOFF_MAX_SECS: i64 = 3600;
+/// Number of the failed dials in a row before the peer goes to the cooldown list
+pub const MAX_DIAL_RETRIES: u32 = 8;
+/// Cooldown of the peer that failed MAX_DIAL_RETRIES dials, in seconds
+pub const DIAL_COOLDOWN_SECS: i64 = 6 * 3600;
+/// Pause of the Tor dials after the first proxy failure, in seconds. It doubles with
+/// every failure.
+pub const PROXY_BACKOFF_BASE_SECS: i64 = 5;
+/// Max pause of the Tor dials, in seconds
+pub const PROXY_BACKOFF_MAX_SECS: i64 = 300;
+/// Max number of the tracked peers. The expired backoffs are dropped above the limit.
+pub const MAX_BACKOFF_PEERS: usize = 10_000;
+
+/// Cause of the failed dial
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DialFailure {
+	/// Local SOCKS proxy is down or refuses the connections
+	ProxyDown,
+	/// Proxy is up, but it wasn't able to reach the peer
+	TargetUnreachable,
+	/// Dial didn't finish in time
+	Timeout,
+}
+
+/// Classify the dial error. The connection to the local proxy fails with the socket
+/// errors, the unreachable target is reported by the proxy with the SOCKS reply that
+/// comes as a generic error. Returns None for the cancelled dials and the dials that
+/// were not started because all the workers were busy.
+pub fn classify_dial_error(err: &io::Error) -> Option<DialFailure> {
+	match err.kind() {
+		io::ErrorKind::TimedOut => Some(DialFailure::Timeout),
+		io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => None,
+		io::ErrorKind::ConnectionRefused
+		| io::ErrorKind::ConnectionReset
+		| io::ErrorKind::ConnectionAborted
+		| io::ErrorKind::NotConnected
+		| io::ErrorKind::AddrNotAvailable
+		| io::ErrorKind::BrokenPipe
+		| io::ErrorKind::UnexpectedEof => Some(DialFailure::ProxyDown),
+		_ => Some(DialFailure::TargetUnreachable),
+	}
+}
+
+/// Dial outcome counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DialStats {
+	/// Number of the finished dials
+	pub attempts: u64,
+	/// Dials that established the connection
+	pub succeeded: u64,
+	/// Dials that failed because the proxy was down
+	pub proxy_down: u64,
+	/// Dials that failed because the peer was unreachable
+	pub target_unreachable: u64,
+	/// Dials that ran out of time
+	pub timeouts: u64,
+	/// Dials dropped by the swarm before they finished
+	pub cancelled: u64,
+	/// Dials rejected because all the workers were busy
+	pub busy: u64,
+	/// Number of the peers that are backed off now, including the cooldown list
+	pub peers_in_backoff: usize,
+	/// Number of the peers in the cooldown list
+	pub peers_in_cooldown: usize,
+	/// Unix time until the Tor dials are paused, if the proxy is down
+	pub proxy_down_until: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+struct PeerBackoff {
+	failures: u32,
+	retry_at: i64,
+	cooldown: bool,
+}
+
+/// Backoff of the peers and of the proxy, driven by the dial outcomes
+pub struct DialBackoff {
+	peers: HashMap<String, PeerBackoff>,
+	proxy_failures: u32,
+	proxy_down_until: i64,
+	stats: DialStats,
+}
+
+impl DialBackoff {
+	/// Backoff with no failures
+	pub fn new() -> DialBackoff {
+		DialBackoff {
+			peers: HashMap::new(),
+			proxy_failures: 0,
+			proxy_down_until: 0,
+			stats: DialStats::default(),
+		}
+	}
+
+	/// Check if the peer can be dialed at the time
+	pub fn is_dial_allowed(&self, address: &str, now: i64) -> bool {
+		self.peers
+			.get(&Libp2pPeerAccess::normalize_address(address))
+			.map(|p| p.retry_at <= now)
+			.unwrap_or(true)
+	}
+
+	/// Check if the Tor dials are paused at the time
+	pub fn is_proxy_down(&self, now: i64) -> bool {
+		self.proxy_down_until > now
+	}
+
+	/// Peer was dialed successfully, its backoff is reset
+	pub fn record_success(&mut self, address: &str) {
+		self.stats.attempts += 1;
+		self.stats.succeeded += 1;
+		self.proxy_failures = 0;
+		self.peers
+			.remove(&Libp2pPeerAccess::normalize_address(address));
+	}
+
+	/// Dial of the peer failed. Returns the unix time of the next allowed dial, of the
+	/// peer or of any Tor peer if the proxy is down. The proxy failure doesn't count
+	/// against the peer.
+	pub fn record_failure(&mut self, address: &str, failure: DialFailure, now: i64) -> i64 {
+		self.stats.attempts += 1;
+		match failure {
+			DialFailure::ProxyDown => {
+				self.stats.proxy_down += 1;
+				self.proxy_failures = self.proxy_failures.saturating_add(1);
+				self.proxy_down_until = now
+					+ backoff_secs(
+						PROXY_BACKOFF_BASE_SECS,
+						PROXY_BACKOFF_MAX_SECS,
+						self.proxy_failures,
+					);
+				return self.proxy_down_until;
+			}
+			DialFailure::TargetUnreachable => {
+				// The proxy did answer
+				self.stats.target_unreachable += 1;
+				self.proxy_failures = 0;
+			}
+			DialFailure::Timeout => self.stats.timeouts += 1,
+		}
+
+		if self.peers.len() >= MAX_BACKOFF_PEERS {
+			self.prune(now);
+		}
+		let address = Libp2pPeerAccess::normalize_address(address);
+		let peer = self.peers.entry(address).or_insert(PeerBackoff {
+			failures: 0,
+			retry_at: now,
+			cooldown: false,
+		});
+		peer.failures = peer.failures.saturating_add(1);
+		if peer.failures > MAX_DIAL_RETRIES {
+			peer.cooldown = true;
+			peer.retry_at = now + DIAL_COOLDOWN_SECS;
+		} else {
+			peer.retry_at =
+				now + backoff_secs(PEER_BACKOFF_BASE_SECS, PEER_BACKOFF_MAX_SECS, peer.failures);
+		}
+		peer.retry_at
+	}
+
+	/// Failed peers whose backoff expired, to be dialed again. The longest waiting first,
+	/// the peers in the cooldown list are not retried.
+	pub fn retries(&self, now: i64) -> Vec<String> {
+		let mut retries: Vec<(&String, i64)> = self
+			.peers
+			.iter()
+			.filter(|(_, p)| !p.cooldown && p.retry_at <= now)
+			.map(|(a, p)| (a, p.retry_at))
+			.collect();
+		retries.sort_by_key(|(_, retry_at)| *retry_at);
+		retries.into_iter().map(|(a, _)| a.clone()).collect()
+	}
+
+	/// Peers that are backed off at the time, including the cooldown list. They can't
+	/// be dialed directly now.
+	pub fn backed_off(&self, now: i64) -> Vec<String> {
+		self.peers
+			.iter()
+			.filter(|(_, p)| p.retry_at > now)
+			.map(|(a, _)| a.clone())
+			.collect()
+	}
+
+	/// Stop tracking the peer, if it is not to be dialed any more or it is connected
+	pub fn forget(&mut self, address: &str) {
+		self.peers
+			.remove(&Libp2pPeerAccess::normalize_address(address));
+	}
+
+	/// Dial was dropped before it finished
+	pub fn record_cancelled(&mut self) {
+		self.stats.cancelled += 1;
+	}
+
+	/// Dial was rejected because all the workers were busy
+	pub fn record_busy(&mut self) {
+		self.stats.busy += 1;
+	}
+
+	/// Drop the expired cooldowns and the retries that are overdue for longer than the
+	/// max backoff. Above the limit the backoffs that expire first are dropped as well.
+	pub fn prune(&mut self, now: i64) {
+		self.peers.retain(|_, p| {
+			p.retry_at > now || (!p.cooldown && p.retry_at + PEER_BACKOFF_MAX_SECS > now)
+		});
+		if self.peers.len() >= MAX_BACKOFF_PEERS {
+			let mut retry_at: Vec<i64> = self.peers.values().map(|p| p.retry_at).collect();
+			retry_at.sort();
+			let threshold = retry_at[self.peers.len() - MAX_BACKOFF_PEERS / 2];
+			self.peers.retain(|_, p| p.retry_at > threshold);
+		}
+	}
+
+	/// Dial counters at the time
+	pub fn stats(&self, now: i64) -> DialStats {
+		let mut stats = self.stats.clone();
+		stats.peers_in_backoff = self.peers.values().filter(|p| p.retry_at > now).count();
+		stats.peers_in_cooldown = self
+			.peers
+			.values()
+			.filter(|p| p.cooldown && p.retry_at > now)
+			.count();
+		stats.proxy_down_until = if self.is_proxy_down(now) {
+			Some(self.proxy_down_until)
+		} else {
+			None
+		};
+		stats
+	}
+}
+
+fn backoff_secs(base: i64, max: i64, failures: u32) -> i64 {
+	let shift = failures.saturating_sub(1).min(20);
+	(base << shift).min(max)
+}
+
+lazy_static! {
+	static ref DIAL_BACKOFF: Mutex<DialBackoff> = Mutex::new(DialBackoff::new());
+}
+
+/// Check if the peer is not backed off
+pub fn is_dial_allowed(address: &str) -> bool {
+	DIAL_BACKOFF
+		.lock()
+		.is_dial_allowed(address, clock::now().timestamp())
+}
+
+/// Check if the Tor dials are paused because the proxy is down
+pub fn is_proxy_down() -> bool {
+	DIAL_BACKOFF.lock().is_proxy_down(clock::now().timestamp())
+}
+
+/// Failed peers that are due for the retry, the longest waiting first
+pub fn dial_retries() -> Vec<String> {
+	DIAL_BACKOFF.lock().retries(clock::now().timestamp())
+}
+
+/// Peers that can't be dialed directly now because they are backed off
+pub fn backed_off_peers() -> Vec<String> {
+	DIAL_BACKOFF.lock().backed_off(clock::now().timestamp())
+}
+
+/// Stop tracking the dial failures of the peer
+pub fn forget_dial_failures(address: &str) {
+	DIAL_BACKOFF.lock().forget(address)
+}
+
+/// Record the outcome of the dial
+pub fn record_dial_result(address: &str, result: Result<(), &io::Error>) {
+	let mut backoff = DIAL_BACKOFF.lock();
+	match result {
+		Ok(()) => backoff.record_success(address),
+		Err(e) => match classify_dial_error(e) {
+			Some(failure) => {
+				let retry_at = backoff.record_failure(address, failure, clock::now().timestamp());
+				debug!(
+					"libp2p dial to {} failed, {:?}, {}. Retry after {}",
+					address, failure, e, retry_at
+				);
+			}
+			None if e.kind() == io::ErrorKind::WouldBlock => backoff.record_busy(),
+			None => backoff.record_cancelled(),
+		},
+	}
+}
+
+/// Dial counters
+pub fn dial_stats() -> DialStats {
+	DIAL_BACKOFF.lock().stats(clock::now().timestamp())
+}
+
+#[cfg(feature = "marketplace")]
+pub use self::pool::DialPool;
+
+#[cfg(feature = "marketplace")]
+mod pool {
+	use super::{record_dial_result, DIAL_BACKOFF};
+	use futures::future::{self, AbortHandle, Aborted, BoxFuture, FutureExt};
+	use libp2p::core::transport::{Transport, TransportError};
+	use libp2p::multiaddr::{Multiaddr, Protocol};
+	use std::io;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+	use std::time::Duration;
+
+	/// Transport wrapper that runs the dials of the inner transport on the worker tasks,
+	/// with the hard timeout. Dials above the workers limit fail without being started.
+	#[derive(Clone)]
+	pub struct DialPool<T> {
+		inner: T,
+		workers: Arc<AtomicUsize>,
+		max_workers: usize,
+		timeout: Duration,
+	}
+
+	impl<T> DialPool<T> {
+		/// Wrap the transport. max_workers is the number of the dials that can run at the
+		/// same time.
+		pub fn new(inner: T, max_workers: usize, timeout: Duration) -> DialPool<T> {
+			DialPool {
+				inner,
+				workers: Arc::new(AtomicUsize::new(0)),
+				max_workers: max_workers.max(1),
+				timeout,
+			}
+		}
+	}
+
+	// Worker slot of the running dial. Aborts the dial and frees the slot when dropped.
+	struct DialWorker {
+		workers: Arc<AtomicUsize>,
+		abort: Option<AbortHandle>,
+		finished: bool,
+	}
+
+	impl Drop for DialWorker {
+		fn drop(&mut self) {
+			if let Some(abort) = self.abort.take() {
+				abort.abort();
+			}
+			if !self.finished {
+				DIAL_BACKOFF.lock().record_cancelled();
+			}
+			self.workers.fetch_sub(1, Ordering::SeqCst);
+		}
+	}
+
+	// Backoff key of the dialed address, the onion address for the onion peers
+	fn dial_backoff_key(addr: &Multiaddr) -> String {
+		for protocol in addr.iter() {
+			if let Protocol::Onion3(_) = protocol {
+				let addr = protocol.to_string();
+				let addr = addr.trim_start_matches("/onion3/");
+				return addr.split(':').next().unwrap_or(addr).to_string();
+			}
+		}
+		addr.to_string()
+	}
+
+	impl<T> Transport for DialPool<T>
+	where
+		T: Transport<Error = io::Error>,
+		T::Dial: Send + 'static,
+		T::Output: Send + 'static,
+	{
+		type Output = T::Output;
+		type Error = io::Error;
+		type Listener = T::Listener;
+		type ListenerUpgrade = T::ListenerUpgrade;
+		type Dial = BoxFuture<'static, Result<T::Output, io::Error>>;
+
+		fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<io::Error>> {
+			self.inner.listen_on(addr)
+		}
+
+		fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<io::Error>> {
+			let key = dial_backoff_key(&addr);
+
+			let running = self.workers.fetch_add(1, Ordering::SeqCst);
+			if running >= self.max_workers {
+				self.workers.fetch_sub(1, Ordering::SeqCst);
+				let err = io::Error::new(
+					io::ErrorKind::WouldBlock,
+					format!("All {} dial workers are busy", self.max_workers),
+				);
+				record_dial_result(&key, Err(&err));
+				return Ok(future::ready(Err(err)).boxed());
+			}
+			let mut worker = DialWorker {
+				workers: self.workers.clone(),
+				abort: None,
+				finished: false,
+			};
+
+			let dial = self.inner.dial(addr)?;
+			let (dial, abort) = future::abortable(dial);
+			worker.abort = Some(abort);
+			let task = tokio::spawn(dial);
+			let timeout = self.timeout;
+
+			Ok(async move {
+				let res = match tokio::time::timeout(timeout, task).await {
+					Ok(Ok(Ok(res))) => res,
+					Ok(Ok(Err(Aborted))) => Err(io::Error::new(
+						io::ErrorKind::Interrupted,
+						"Dial is cancelled",
+					)),
+					Ok(Err(e)) => Err(io::Error::new(
+						io::ErrorKind::Other,
+						format!("Dial worker failed, {}", e),
+					)),
+					Err(_) => Err(io::Error::new(
+						io::ErrorKind::TimedOut,
+						format!("Dial is timed out after {} seconds", timeout.as_secs()),
+					)),
+				};
+				worker.finished = true;
+				drop(worker);
+				record_dial_result(&key, res.as_ref().map(|_| ()));
+				res
+			}
+			.boxed())
+		}
+
+		fn address_translation(
+			&self,
+			server: &Multiaddr,
+			observed: &Multiaddr,
+		) -> Option<Multiaddr> {
+			self.inner.address_translation(server, observed)
+		}
+	}
+}
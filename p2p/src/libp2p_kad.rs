@@ -0,0 +1,209 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kademlia DHT peer discovery for the gossip network. It runs next to the gossipsub,
+//! so the node can find peers when the mesh is thin and the seeds are offline.
+//!
+//! PeerIds of the network are derived from the onion addresses, so the DHT is keyed
+//! on them and any PeerId the DHT returns is dialable: its onion address is derived
+//! from it, the addresses the peers report are not needed. Every node announces itself
+//! as a provider of the topics it handles, the providers of the topics with the thin
+//! mesh are looked up, the rest of the peers come from the random walks over the DHT.
+//! Nodes with the standalone identity are in the DHT, but their derived onion
+//! address is not reachable, the dial backoff drops them.
+
+use crate::util::Mutex;
+
+/// Protocol name of the DHT, it separates our DHT from the other libp2p networks
+pub const KAD_PROTOCOL_NAME: &[u8] = b"/mwc/kad/1.0.0";
+/// Default interval between the DHT lookups, in seconds
+pub const DEFAULT_KAD_QUERY_INTERVAL_SECS: u64 = 300;
+/// Timeout of the DHT query. Every hop is a Tor dial, so it is long.
+pub const KAD_QUERY_TIMEOUT_SECS: u64 = 180;
+/// Max number of the known peers added to the routing table per lookup
+pub const KAD_SEED_PEERS: usize = 20;
+
+/// Kademlia discovery counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct KadStats {
+	/// True if the DHT is running
+	pub enabled: bool,
+	/// Number of the peers in the routing table
+	pub routing_table_peers: usize,
+	/// Number of the topics this node is a provider of
+	pub provided_topics: usize,
+	/// Number of the finished lookups
+	pub queries: u64,
+	/// Lookups that failed or timed out
+	pub failed_queries: u64,
+	/// Number of the peers the lookups returned
+	pub discovered_peers: u64,
+}
+
+lazy_static! {
+	static ref KAD_STATS: Mutex<KadStats> = Mutex::new(KadStats::default());
+}
+
+/// Kademlia discovery counters
+pub fn kad_stats() -> KadStats {
+	KAD_STATS.lock().clone()
+}
+
+fn record_query(failed: bool, discovered: usize) {
+	let mut stats = KAD_STATS.lock();
+	stats.queries += 1;
+	if failed {
+		stats.failed_queries += 1;
+	}
+	stats.discovered_peers += discovered as u64;
+}
+
+#[cfg(feature = "marketplace")]
+pub use self::discovery::{build_kademlia, topic_key, KadDiscovery};
+
+#[cfg(feature = "marketplace")]
+mod discovery {
+	use super::{record_query, KAD_PROTOCOL_NAME, KAD_QUERY_TIMEOUT_SECS, KAD_STATS};
+	use libp2p::gossipsub::TopicHash;
+	use libp2p::kad::record::store::MemoryStore;
+	use libp2p::kad::record::Key;
+	use libp2p::kad::{
+		GetClosestPeersOk, GetProvidersOk, Kademlia, KademliaConfig, KademliaEvent, QueryResult,
+	};
+	use libp2p::swarm::toggle::Toggle;
+	use libp2p::{Multiaddr, PeerId};
+	use std::collections::HashSet;
+	use std::time::Duration;
+
+	/// Kademlia behaviour of the node, disabled if the DHT is off
+	pub fn build_kademlia(this_peer_id: &PeerId, enabled: bool) -> Toggle<Kademlia<MemoryStore>> {
+		KAD_STATS.lock().enabled = enabled;
+		if !enabled {
+			return Toggle::from(None);
+		}
+		let mut config = KademliaConfig::default();
+		config.set_protocol_name(KAD_PROTOCOL_NAME);
+		config.set_query_timeout(Duration::from_secs(KAD_QUERY_TIMEOUT_SECS));
+		let store = MemoryStore::new(this_peer_id.clone());
+		Toggle::from(Some(Kademlia::with_config(
+			this_peer_id.clone(),
+			store,
+			config,
+		)))
+	}
+
+	/// DHT key of the topic providers
+	pub fn topic_key(topic: &TopicHash) -> Key {
+		Key::new(&topic.as_str())
+	}
+
+	/// Provided topics and the lookups of the node
+	pub struct KadDiscovery {
+		this_peer_id: PeerId,
+		provided: HashSet<TopicHash>,
+	}
+
+	impl KadDiscovery {
+		/// Discovery of the node that doesn't provide any topic yet
+		pub fn new(this_peer_id: PeerId) -> KadDiscovery {
+			KadDiscovery {
+				this_peer_id,
+				provided: HashSet::new(),
+			}
+		}
+
+		/// Topics this node is a provider of
+		pub fn provided_topics(&self) -> Vec<TopicHash> {
+			self.provided.iter().cloned().collect()
+		}
+
+		/// Start the lookups. Known peers are added to the routing table first, the
+		/// provider records follow the handled topics, the providers of the thin topics
+		/// are looked up and the random walk finds the rest.
+		pub fn lookup(
+			&mut self,
+			kademlia: &mut Kademlia<MemoryStore>,
+			known_peers: Vec<(PeerId, Multiaddr)>,
+			topics: &[TopicHash],
+			thin_topics: &[TopicHash],
+		) {
+			for (peer_id, addr) in known_peers {
+				if peer_id != self.this_peer_id {
+					kademlia.add_address(&peer_id, addr);
+				}
+			}
+
+			let topics: HashSet<TopicHash> = topics.iter().cloned().collect();
+			for topic in self.provided.difference(&topics) {
+				kademlia.stop_providing(&topic_key(topic));
+			}
+			for topic in topics.difference(&self.provided) {
+				if let Err(e) = kademlia.start_providing(topic_key(topic)) {
+					warn!("Unable to provide the topic {} in the DHT, {:?}", topic, e);
+				}
+			}
+			self.provided = topics;
+
+			for topic in thin_topics {
+				kademlia.get_providers(topic_key(topic));
+			}
+			// Random walk, the key is random so the lookup visits a random part of the DHT
+			kademlia.get_closest_peers(PeerId::random());
+
+			let mut stats = KAD_STATS.lock();
+			stats.routing_table_peers = kademlia.kbuckets().map(|b| b.num_entries()).sum();
+			stats.provided_topics = self.provided.len();
+		}
+
+		/// Peers found by the finished lookups, this node excluded
+		pub fn process_event(&mut self, event: KademliaEvent) -> Vec<PeerId> {
+			let peers: Vec<PeerId> = match event {
+				KademliaEvent::QueryResult { result, .. } => match result {
+					QueryResult::GetClosestPeers(Ok(GetClosestPeersOk { peers, .. })) => {
+						record_query(false, peers.len());
+						peers
+					}
+					QueryResult::GetProviders(Ok(GetProvidersOk {
+						providers,
+						closest_peers,
+						..
+					})) => {
+						record_query(false, providers.len());
+						// Nodes close to the key are the peers of the network too
+						providers.into_iter().chain(closest_peers).collect()
+					}
+					QueryResult::GetClosestPeers(Err(e)) => {
+						debug!("DHT random walk failed, {:?}", e);
+						record_query(true, 0);
+						vec![]
+					}
+					QueryResult::GetProviders(Err(e)) => {
+						debug!("DHT topic providers lookup failed, {:?}", e);
+						record_query(true, 0);
+						vec![]
+					}
+					_ => vec![],
+				},
+				KademliaEvent::RoutingUpdated { peer, .. } => vec![peer],
+				_ => vec![],
+			};
+			let mut peers: Vec<PeerId> = peers
+				.into_iter()
+				.filter(|p| *p != self.this_peer_id)
+				.collect();
+			peers.dedup();
+			peers
+		}
+	}
+}
@@ -0,0 +1,196 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistent list of the discovered libp2p onion peers. The libp2p node records the
+//! peers it was connected to and the peers it learned from the peer exchange with the
+//! last seen time. The records are saved periodically and loaded when the libp2p node
+//! is started, so after the restart the node doesn't need to bootstrap from the seeds.
+
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::store::Libp2pPeerAccess;
+use crate::util::{Mutex, RwLock};
+use grin_store::{self, to_key, Error};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const DB_NAME: &str = "libp2p_peers";
+const STORE_SUBPATH: &str = "libp2p";
+
+const LIBP2P_PEER_PREFIX: u8 = b'P';
+
+/// Peers that were not seen for that long are dropped
+pub const STORED_PEER_EXPIRE_SECS: i64 = 14 * 24 * 3600;
+/// Max number of the stored peers. The peers seen long ago are dropped above the limit.
+pub const MAX_STORED_PEERS: usize = 5_000;
+
+/// Discovered libp2p peer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Libp2pPeerRecord {
+	/// Onion address of the peer, without '.onion' suffix
+	pub address: String,
+	/// Unix time when the peer was seen the last time
+	pub last_seen: i64,
+}
+
+impl Writeable for Libp2pPeerRecord {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.address)?;
+		writer.write_i64(self.last_seen)
+	}
+}
+
+impl Readable for Libp2pPeerRecord {
+	fn read<R: Reader>(reader: &mut R) -> Result<Libp2pPeerRecord, ser::Error> {
+		let address = String::from_utf8(reader.read_bytes_len_prefix()?).map_err(|e| {
+			ser::Error::CorruptedData(format!("Fail to read libp2p peer address, {}", e))
+		})?;
+		let last_seen = reader.read_i64()?;
+		Ok(Libp2pPeerRecord { address, last_seen })
+	}
+}
+
+/// Storage of the discovered libp2p peers
+pub struct Libp2pPeerStore {
+	db: grin_store::Store,
+}
+
+impl Libp2pPeerStore {
+	/// Open the store under the provided root path
+	pub fn new(db_root: &str) -> Result<Libp2pPeerStore, Error> {
+		let db = grin_store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		Ok(Libp2pPeerStore { db })
+	}
+
+	/// Save the records, the last seen time of the known peers is updated
+	pub fn save(&self, records: &[Libp2pPeerRecord]) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		for record in records {
+			let key = to_key(LIBP2P_PEER_PREFIX, &record.address);
+			let last_seen = batch
+				.get_ser::<Libp2pPeerRecord>(&key)?
+				.map(|r| r.last_seen.max(record.last_seen))
+				.unwrap_or(record.last_seen);
+			batch.put_ser(
+				&key,
+				&Libp2pPeerRecord {
+					address: record.address.clone(),
+					last_seen,
+				},
+			)?;
+		}
+		batch.commit()
+	}
+
+	/// Delete the peer
+	pub fn delete(&self, address: &str) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.delete(&to_key(
+			LIBP2P_PEER_PREFIX,
+			&Libp2pPeerAccess::normalize_address(address),
+		))?;
+		batch.commit()
+	}
+
+	/// Peers seen within the expiration period, the recent first. The expired peers and
+	/// the peers above the limit are deleted.
+	pub fn load(&self, now: i64) -> Result<Vec<Libp2pPeerRecord>, Error> {
+		let mut records: Vec<Libp2pPeerRecord> = self
+			.db
+			.iter::<Libp2pPeerRecord>(&to_key(LIBP2P_PEER_PREFIX, ""))?
+			.map(|(_, r)| r)
+			.collect();
+		records.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+		let min_time = now - STORED_PEER_EXPIRE_SECS;
+		let keep = records
+			.iter()
+			.take(MAX_STORED_PEERS)
+			.take_while(|r| r.last_seen >= min_time)
+			.count();
+		let expired = records.split_off(keep);
+		if !expired.is_empty() {
+			let batch = self.db.batch()?;
+			for record in &expired {
+				batch.delete(&to_key(LIBP2P_PEER_PREFIX, &record.address))?;
+			}
+			batch.commit()?;
+			debug!("{} expired libp2p peers are deleted", expired.len());
+		}
+		Ok(records)
+	}
+}
+
+lazy_static! {
+	// Peer store of the node, None if the peers are not persisted
+	static ref LIBP2P_PEER_STORE: RwLock<Option<Arc<Libp2pPeerStore>>> = RwLock::new(None);
+	// Peers seen since the last save, by the normalized address
+	static ref SEEN_PEERS: Mutex<HashMap<String, i64>> = Mutex::new(HashMap::new());
+}
+
+/// Set the peer store of the node
+pub fn init_libp2p_peer_store(store: Libp2pPeerStore) {
+	*LIBP2P_PEER_STORE.write() = Some(Arc::new(store));
+}
+
+/// Record the peers that were seen at the time. They are saved by `save_seen_peers`.
+pub fn peers_seen<'a, I>(addresses: I, now: i64)
+where
+	I: IntoIterator<Item = &'a String>,
+{
+	if LIBP2P_PEER_STORE.read().is_none() {
+		return;
+	}
+	let mut seen = SEEN_PEERS.lock();
+	for address in addresses {
+		if seen.len() >= MAX_STORED_PEERS {
+			break;
+		}
+		seen.insert(Libp2pPeerAccess::normalize_address(address), now);
+	}
+}
+
+/// Save the peers seen since the last save
+pub fn save_seen_peers() -> Result<(), Error> {
+	let store = match LIBP2P_PEER_STORE.read().clone() {
+		Some(store) => store,
+		None => return Ok(()),
+	};
+	let records: Vec<Libp2pPeerRecord> = SEEN_PEERS
+		.lock()
+		.drain()
+		.map(|(address, last_seen)| Libp2pPeerRecord { address, last_seen })
+		.collect();
+	if records.is_empty() {
+		return Ok(());
+	}
+	store.save(&records)
+}
+
+/// Stored peers seen within the expiration period, the recent first
+pub fn stored_peers(now: i64) -> Result<Vec<Libp2pPeerRecord>, Error> {
+	match LIBP2P_PEER_STORE.read().clone() {
+		Some(store) => store.load(now),
+		None => Ok(vec![]),
+	}
+}
+
+/// Forget the stored peer
+pub fn forget_peer(address: &str) -> Result<(), Error> {
+	SEEN_PEERS
+		.lock()
+		.remove(&Libp2pPeerAccess::normalize_address(address));
+	match LIBP2P_PEER_STORE.read().clone() {
+		Some(store) => store.delete(address),
+		None => Ok(()),
+	}
+}
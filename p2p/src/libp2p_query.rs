@@ -0,0 +1,154 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Direct queries between the libp2p peers. The gossip reaches everybody, the query
+//! goes to the single peer and gets the answer back, so the wallets can ask the peer
+//! "do you have kernel X" or fetch the swap offer details without flooding the mesh.
+//!
+//! The request names the method, the peer dispatches it to the query handler that is
+//! registered for the method. Requests and responses are length prefixed json.
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::core::upgrade::{read_one, write_one};
+use libp2p::request_response::{
+	ProtocolName, ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+};
+use libp2p::PeerId;
+use std::io;
+use std::iter;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Protocol name of the queries
+pub const QUERY_PROTOCOL_NAME: &[u8] = b"/mwc/query/1";
+/// Max size of the request or response, in bytes
+pub const MAX_QUERY_SIZE: usize = 64 * 1024;
+/// Time to get the response. Peer might need to be dialed over Tor first.
+pub const QUERY_TIMEOUT_SECS: u64 = 90;
+/// Built-in method, params: kernel excess hex. Responds with true if the node knows the kernel.
+pub const HAS_KERNEL_METHOD: &str = "has_kernel";
+
+/// Query to the peer
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct QueryRequest {
+	/// Method of the registered query handler
+	pub method: String,
+	/// Method parameters
+	#[serde(default)]
+	pub params: serde_json::Value,
+}
+
+/// Result of the query, the error is the message for the requester
+pub type QueryResponse = Result<serde_json::Value, String>;
+
+/// Query handler, arguments: peer that sent the query and the query params.
+/// Handler runs in the swarm polling loop, it must answer right away.
+pub type QueryHandler = Arc<dyn Fn(&PeerId, serde_json::Value) -> QueryResponse + Send + Sync>;
+
+/// Query handler from the plain function
+pub fn query_handler<F>(handler: F) -> QueryHandler
+where
+	F: Fn(&PeerId, serde_json::Value) -> QueryResponse + Send + Sync + 'static,
+{
+	Arc::new(handler)
+}
+
+/// Query protocol
+#[derive(Clone, Debug)]
+pub struct QueryProtocol;
+
+impl ProtocolName for QueryProtocol {
+	fn protocol_name(&self) -> &[u8] {
+		QUERY_PROTOCOL_NAME
+	}
+}
+
+/// Json codec of the queries
+#[derive(Clone, Debug, Default)]
+pub struct QueryCodec;
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+	E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+	io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[async_trait]
+impl RequestResponseCodec for QueryCodec {
+	type Protocol = QueryProtocol;
+	type Request = QueryRequest;
+	type Response = QueryResponse;
+
+	async fn read_request<T>(&mut self, _: &QueryProtocol, io: &mut T) -> io::Result<QueryRequest>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		let data = read_one(io, MAX_QUERY_SIZE).await.map_err(invalid_data)?;
+		serde_json::from_slice(&data).map_err(invalid_data)
+	}
+
+	async fn read_response<T>(&mut self, _: &QueryProtocol, io: &mut T) -> io::Result<QueryResponse>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		let data = read_one(io, MAX_QUERY_SIZE).await.map_err(invalid_data)?;
+		serde_json::from_slice(&data).map_err(invalid_data)
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		_: &QueryProtocol,
+		io: &mut T,
+		request: QueryRequest,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		let data = serde_json::to_vec(&request).map_err(invalid_data)?;
+		if data.len() > MAX_QUERY_SIZE {
+			return Err(invalid_data("query request is too large"));
+		}
+		write_one(io, data).await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		_: &QueryProtocol,
+		io: &mut T,
+		response: QueryResponse,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		let mut data = serde_json::to_vec(&response).map_err(invalid_data)?;
+		if data.len() > MAX_QUERY_SIZE {
+			let response: QueryResponse = Err("query response is too large".to_string());
+			data = serde_json::to_vec(&response).map_err(invalid_data)?;
+		}
+		write_one(io, data).await
+	}
+}
+
+/// Request-response behaviour of the queries
+pub fn build_query_behaviour() -> RequestResponse<QueryCodec> {
+	let mut config = RequestResponseConfig::default();
+	config.set_request_timeout(Duration::from_secs(QUERY_TIMEOUT_SECS));
+	RequestResponse::new(
+		QueryCodec,
+		iter::once((QueryProtocol, ProtocolSupport::Full)),
+		config,
+	)
+}
@@ -0,0 +1,368 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Circuit relay for the libp2p peers that can't publish an onion service. Such a peer
+//! keeps the connections with the relay nodes of its config and listens through them,
+//! the other peers reach it with the relayed dial
+//! `<relay address>/p2p/<relay id>/p2p-circuit/p2p/<peer id>`. Peers that can't be
+//! dialed directly are dialed through the connected relays by the reconnection task.
+//! Node with the relay hop enabled forwards the circuits of the peers and dials the
+//! destination if it is not connected, otherwise the circuits are forwarded to the
+//! connected peers only.
+//!
+//! Relayed connections are throttled at the transport. Every relayed connection has its
+//! own bandwidth limit, all of them share the total limit, and the number of the relayed
+//! connections is capped, the connections above the cap are refused. The hop forwards
+//! the circuit data at the pace the throttled ends of the circuit read and write it.
+
+use crate::util::Mutex;
+use std::cmp;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default max number of the relayed connections of the node
+pub const DEFAULT_RELAY_MAX_CONNECTIONS: usize = 16;
+/// Default bandwidth limit of the relayed connection, in bytes per second
+pub const DEFAULT_RELAY_CONNECTION_BYTES_PER_SEC: u64 = 64 * 1024;
+/// Default bandwidth limit of all the relayed connections, in bytes per second
+pub const DEFAULT_RELAY_TOTAL_BYTES_PER_SEC: u64 = 512 * 1024;
+/// Idle connections of the relay are closed after that time, in seconds
+pub const RELAY_IDLE_TIMEOUT_SECS: u64 = 120;
+
+/// Circuit relay counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RelayStats {
+	/// True if the relay is running, as the hop or as the client
+	pub enabled: bool,
+	/// True if the node dials the destinations of the circuits it relays
+	pub hop: bool,
+	/// Number of the relay nodes of the config
+	pub relay_nodes: usize,
+	/// Number of the relay nodes that are connected now
+	pub connected_relays: usize,
+	/// Number of the relayed connections now
+	pub relayed_connections: usize,
+	/// Relayed connections refused because of the connections limit
+	pub refused_connections: u64,
+	/// Number of the dials through the relays
+	pub relayed_dials: u64,
+	/// Data of the relayed connections, both directions, in bytes
+	pub bytes: u64,
+	/// Number of the times the relayed connections were paused by the bandwidth limits
+	pub throttled: u64,
+}
+
+lazy_static! {
+	static ref RELAY_STATS: Mutex<RelayStats> = Mutex::new(RelayStats::default());
+}
+
+/// Circuit relay counters
+pub fn relay_stats() -> RelayStats {
+	RELAY_STATS.lock().clone()
+}
+
+/// Limits of the relayed connections
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelayLimits {
+	/// Max number of the relayed connections
+	pub max_connections: usize,
+	/// Bandwidth limit of the relayed connection, in bytes per second. Zero is unlimited.
+	pub connection_bytes_per_sec: u64,
+	/// Bandwidth limit of all the relayed connections, in bytes per second. Zero is unlimited.
+	pub total_bytes_per_sec: u64,
+}
+
+/// Token bucket that lets a second of data through at once. The transfer that goes
+/// above it is not cut, it is paid by the pause of the next transfers.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimit {
+	bytes_per_sec: u64,
+	available: i64,
+	updated: Instant,
+}
+
+impl BandwidthLimit {
+	/// Full bucket, zero rate is unlimited
+	pub fn new(bytes_per_sec: u64, now: Instant) -> BandwidthLimit {
+		BandwidthLimit {
+			bytes_per_sec,
+			available: bytes_per_sec as i64,
+			updated: now,
+		}
+	}
+
+	/// Account the transferred bytes. Returns how long the transfers must pause, so the
+	/// rate stays within the limit.
+	pub fn consume(&mut self, bytes: usize, now: Instant) -> Duration {
+		if self.bytes_per_sec == 0 {
+			return Duration::from_secs(0);
+		}
+		let elapsed = now.saturating_duration_since(self.updated);
+		self.updated = cmp::max(self.updated, now);
+		let refill = elapsed.as_nanos() * self.bytes_per_sec as u128 / 1_000_000_000;
+		self.available = cmp::min(
+			self.bytes_per_sec as i128,
+			self.available as i128 + refill as i128,
+		) as i64;
+		self.available = self.available.saturating_sub(bytes as i64);
+		if self.available >= 0 {
+			Duration::from_secs(0)
+		} else {
+			Duration::from_nanos(
+				((-self.available) as u128 * 1_000_000_000 / self.bytes_per_sec as u128) as u64,
+			)
+		}
+	}
+}
+
+/// Shared limits of the relayed connections of the node
+pub struct RelayBandwidth {
+	limits: RelayLimits,
+	total: Mutex<BandwidthLimit>,
+	connections: AtomicUsize,
+}
+
+impl RelayBandwidth {
+	/// Limits without any relayed connection
+	pub fn new(limits: RelayLimits) -> Arc<RelayBandwidth> {
+		Arc::new(RelayBandwidth {
+			limits,
+			total: Mutex::new(BandwidthLimit::new(
+				limits.total_bytes_per_sec,
+				Instant::now(),
+			)),
+			connections: AtomicUsize::new(0),
+		})
+	}
+
+	/// Number of the relayed connections now
+	pub fn connections(&self) -> usize {
+		self.connections.load(Ordering::SeqCst)
+	}
+
+	/// Budget of the new relayed connection, None if the connections limit is reached
+	pub fn open_connection(self: &Arc<Self>) -> Option<RelayBudget> {
+		let opened = self.connections.fetch_add(1, Ordering::SeqCst);
+		if opened >= self.limits.max_connections {
+			self.connections.fetch_sub(1, Ordering::SeqCst);
+			RELAY_STATS.lock().refused_connections += 1;
+			return None;
+		}
+		RELAY_STATS.lock().relayed_connections = opened + 1;
+		Some(RelayBudget {
+			bandwidth: self.clone(),
+			own: BandwidthLimit::new(self.limits.connection_bytes_per_sec, Instant::now()),
+		})
+	}
+}
+
+/// Bandwidth budget of the relayed connection. Its slot is released when it is dropped.
+pub struct RelayBudget {
+	bandwidth: Arc<RelayBandwidth>,
+	own: BandwidthLimit,
+}
+
+impl RelayBudget {
+	/// Account the bytes of the connection. Returns how long the connection must pause,
+	/// the longer pause of the connection and of the total limits.
+	pub fn consume(&mut self, bytes: usize, now: Instant) -> Duration {
+		let own = self.own.consume(bytes, now);
+		let total = self.bandwidth.total.lock().consume(bytes, now);
+		let pause = cmp::max(own, total);
+		let mut stats = RELAY_STATS.lock();
+		stats.bytes += bytes as u64;
+		if pause > Duration::from_secs(0) {
+			stats.throttled += 1;
+		}
+		pause
+	}
+}
+
+impl Drop for RelayBudget {
+	fn drop(&mut self) {
+		let opened = self.bandwidth.connections.fetch_sub(1, Ordering::SeqCst);
+		RELAY_STATS.lock().relayed_connections = opened.saturating_sub(1);
+	}
+}
+
+#[cfg(feature = "marketplace")]
+fn record_config(enabled: bool, hop: bool, relay_nodes: usize) {
+	let mut stats = RELAY_STATS.lock();
+	stats.enabled = enabled;
+	stats.hop = hop;
+	stats.relay_nodes = relay_nodes;
+}
+
+/// Number of the relay nodes that are connected now
+pub fn record_connected_relays(connected: usize) {
+	RELAY_STATS.lock().connected_relays = connected;
+}
+
+/// Dial through the relay is started
+pub fn record_relayed_dial() {
+	RELAY_STATS.lock().relayed_dials += 1;
+}
+
+#[cfg(feature = "marketplace")]
+pub use self::transport::{build_relay, relayed_dial_addr, throttle_relayed, ThrottledConnection};
+
+#[cfg(feature = "marketplace")]
+mod transport {
+	use super::{record_config, RelayBandwidth, RelayBudget, RelayLimits, RELAY_IDLE_TIMEOUT_SECS};
+	use futures::future::BoxFuture;
+	use futures::io::{AsyncRead, AsyncWrite};
+	use futures::{ready, FutureExt};
+	use libp2p::core::either::EitherOutput;
+	use libp2p::relay::{new_transport_and_behaviour, Relay, RelayConfig, RelayTransport};
+	use libp2p::swarm::toggle::Toggle;
+	use libp2p::{Multiaddr, PeerId, Transport};
+	use std::io;
+	use std::pin::Pin;
+	use std::sync::Arc;
+	use std::task::{Context, Poll};
+	use std::time::{Duration, Instant};
+
+	/// Relay transport over the base transport and the relay behaviour. The behaviour is
+	/// disabled if the node is neither the hop nor the client, the relayed dials and
+	/// listeners fail then.
+	pub fn build_relay<T: Transport + Clone>(
+		transport: T,
+		hop: bool,
+		relay_nodes: usize,
+		limits: RelayLimits,
+	) -> (RelayTransport<T>, Toggle<Relay>, Arc<RelayBandwidth>) {
+		let enabled = hop || relay_nodes > 0;
+		record_config(enabled, hop, relay_nodes);
+		let config = RelayConfig {
+			connection_idle_timeout: Duration::from_secs(RELAY_IDLE_TIMEOUT_SECS),
+			actively_connect_to_dst_nodes: hop,
+		};
+		let (transport, behaviour) = new_transport_and_behaviour(config, transport);
+		let behaviour = if enabled { Some(behaviour) } else { None };
+		(
+			transport,
+			Toggle::from(behaviour),
+			RelayBandwidth::new(limits),
+		)
+	}
+
+	/// Throttle the relayed connection of the relay transport, the direct connection is
+	/// passed as it is. Relayed connection above the connections limit is refused.
+	pub fn throttle_relayed<A, B>(
+		output: EitherOutput<A, B>,
+		bandwidth: &Arc<RelayBandwidth>,
+	) -> Result<EitherOutput<A, ThrottledConnection<B>>, io::Error> {
+		match output {
+			EitherOutput::First(direct) => Ok(EitherOutput::First(direct)),
+			EitherOutput::Second(relayed) => match bandwidth.open_connection() {
+				Some(budget) => Ok(EitherOutput::Second(ThrottledConnection::new(
+					relayed, budget,
+				))),
+				None => Err(io::Error::new(
+					io::ErrorKind::ConnectionRefused,
+					"Relayed connections limit is reached",
+				)),
+			},
+		}
+	}
+
+	/// Address of the peer behind the relay
+	pub fn relayed_dial_addr(
+		relay_addr: &Multiaddr,
+		relay_id: &PeerId,
+		peer_id: &PeerId,
+	) -> Result<Multiaddr, String> {
+		let addr = format!(
+			"{}/p2p/{}/p2p-circuit/p2p/{}",
+			relay_addr, relay_id, peer_id
+		);
+		addr.parse::<Multiaddr>()
+			.map_err(|e| format!("Unable to build relayed address {}, {}", addr, e))
+	}
+
+	/// Relayed connection that pauses the reads and the writes when its budget is spent
+	pub struct ThrottledConnection<C> {
+		inner: C,
+		budget: RelayBudget,
+		read_pause: Option<BoxFuture<'static, ()>>,
+		write_pause: Option<BoxFuture<'static, ()>>,
+	}
+
+	impl<C> ThrottledConnection<C> {
+		/// Throttle the connection with the budget
+		pub fn new(inner: C, budget: RelayBudget) -> ThrottledConnection<C> {
+			ThrottledConnection {
+				inner,
+				budget,
+				read_pause: None,
+				write_pause: None,
+			}
+		}
+	}
+
+	// Wait for the pause to end, if any
+	fn poll_pause(pause: &mut Option<BoxFuture<'static, ()>>, cx: &mut Context<'_>) -> Poll<()> {
+		if let Some(p) = pause.as_mut() {
+			ready!(p.poll_unpin(cx));
+			*pause = None;
+		}
+		Poll::Ready(())
+	}
+
+	fn pause_after(budget: &mut RelayBudget, bytes: usize) -> Option<BoxFuture<'static, ()>> {
+		let pause = budget.consume(bytes, Instant::now());
+		if pause > Duration::from_secs(0) {
+			Some(async_std::task::sleep(pause).boxed())
+		} else {
+			None
+		}
+	}
+
+	impl<C: AsyncRead + Unpin> AsyncRead for ThrottledConnection<C> {
+		fn poll_read(
+			mut self: Pin<&mut Self>,
+			cx: &mut Context<'_>,
+			buf: &mut [u8],
+		) -> Poll<io::Result<usize>> {
+			let this = &mut *self;
+			ready!(poll_pause(&mut this.read_pause, cx));
+			let n = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+			this.read_pause = pause_after(&mut this.budget, n);
+			Poll::Ready(Ok(n))
+		}
+	}
+
+	impl<C: AsyncWrite + Unpin> AsyncWrite for ThrottledConnection<C> {
+		fn poll_write(
+			mut self: Pin<&mut Self>,
+			cx: &mut Context<'_>,
+			buf: &[u8],
+		) -> Poll<io::Result<usize>> {
+			let this = &mut *self;
+			ready!(poll_pause(&mut this.write_pause, cx));
+			let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+			this.write_pause = pause_after(&mut this.budget, n);
+			Poll::Ready(Ok(n))
+		}
+
+		fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+			Pin::new(&mut self.inner).poll_flush(cx)
+		}
+
+		fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+			Pin::new(&mut self.inner).poll_close(cx)
+		}
+	}
+}
@@ -0,0 +1,219 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Topic handlers sandbox. The handlers run on the sandbox tokio executor instead of the
+//! swarm polling loop, so a panicking or slow handler can't take down or stall the swarm,
+//! and the handlers are free to await the chain or DB queries.
+//! The polling loop collects the handler results and reports the message validation
+//! once the handler is done. Handlers that don't finish in time are reported as timed out
+//! and cancelled, the message is ignored.
+
+use crate::libp2p_connection::{MessageContext, TopicHandler};
+use futures::future::{self, FutureExt};
+use grin_util::Mutex;
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::task::Waker;
+use std::time::{Duration, Instant};
+use tokio::runtime::{self, Runtime};
+
+/// Number of the handler executor threads
+const HANDLER_WORKERS: usize = 4;
+/// Handler that runs longer is reported as timed out
+pub const HANDLER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Result of the topic handler call
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandlerOutcome {
+	/// Handler finished, false if the message is invalid
+	Done(bool),
+	/// Handler panicked, with the panic message
+	Panic(String),
+	/// Handler didn't finish in time
+	Timeout,
+}
+
+/// Executor that runs the topic handlers
+pub struct HandlerSandbox {
+	// Keeps the executor running, None if it failed to start
+	_runtime: Mutex<Option<Runtime>>,
+	executor: Option<runtime::Handle>,
+	results_tx: Mutex<mpsc::Sender<(u64, HandlerOutcome)>>,
+	results: Mutex<mpsc::Receiver<(u64, HandlerOutcome)>>,
+	// Submitted jobs that are not finished yet
+	pending: Mutex<HashMap<u64, (MessageContext, Instant)>>,
+	// Polling loop is woken up when a handler is done
+	waker: Arc<Mutex<Option<Waker>>>,
+	next_id: AtomicU64,
+	timeout: Duration,
+}
+
+impl HandlerSandbox {
+	/// Start the executor with the number of the worker threads
+	pub fn new(workers: usize, timeout: Duration) -> HandlerSandbox {
+		let (results_tx, results_rx) = mpsc::channel();
+		let runtime = match runtime::Builder::new()
+			.threaded_scheduler()
+			.core_threads(workers.max(1))
+			.thread_name("libp2p_handler")
+			.enable_all()
+			.build()
+		{
+			Ok(runtime) => Some(runtime),
+			Err(e) => {
+				error!("Unable to start libp2p handler executor, {}", e);
+				None
+			}
+		};
+		let executor = runtime.as_ref().map(|r| r.handle().clone());
+
+		HandlerSandbox {
+			_runtime: Mutex::new(runtime),
+			executor,
+			results_tx: Mutex::new(results_tx),
+			results: Mutex::new(results_rx),
+			pending: Mutex::new(HashMap::new()),
+			waker: Arc::new(Mutex::new(None)),
+			next_id: AtomicU64::new(0),
+			timeout,
+		}
+	}
+
+	/// Run the handler for the message on the executor. The result is
+	/// returned by `collect`.
+	pub fn submit(&self, handler: TopicHandler, context: MessageContext, data: Vec<u8>) {
+		self.submit_all(vec![handler], context, data)
+	}
+
+	/// Run all handlers of the topic for the message on the executor, the handlers
+	/// run concurrently. The message is invalid if any handler rejects it.
+	/// The result is returned by `collect`.
+	pub fn submit_all(&self, handlers: Vec<TopicHandler>, context: MessageContext, data: Vec<u8>) {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		self.pending
+			.lock()
+			.insert(id, (context.clone(), Instant::now()));
+		let executor = match &self.executor {
+			Some(executor) => executor,
+			None => {
+				// Nobody will run it, it is reported as timed out
+				error!("libp2p handler executor is not running");
+				return;
+			}
+		};
+		let results_tx = self.results_tx.lock().clone();
+		let waker = self.waker.clone();
+		let timeout = self.timeout;
+		executor.spawn(async move {
+			// Stuck handlers are cancelled, the timeout is already reported by `collect`
+			let outcome = tokio::time::timeout(timeout, run_handlers(handlers, context, data))
+				.await
+				.unwrap_or(HandlerOutcome::Timeout);
+			if results_tx.send((id, outcome)).is_ok() {
+				if let Some(waker) = waker.lock().take() {
+					waker.wake();
+				}
+			}
+		});
+	}
+
+	/// Waker of the polling loop, it is woken up when a handler is done
+	pub fn set_waker(&self, waker: &Waker) {
+		*self.waker.lock() = Some(waker.clone());
+	}
+
+	/// Number of the handlers that are not finished yet
+	pub fn pending_count(&self) -> usize {
+		self.pending.lock().len()
+	}
+
+	/// Results of the finished handlers and the handlers that timed out
+	pub fn collect(&self) -> Vec<(MessageContext, HandlerOutcome)> {
+		let mut res = vec![];
+		let mut pending = self.pending.lock();
+		{
+			let results = self.results.lock();
+			while let Ok((id, outcome)) = results.try_recv() {
+				// Results of the timed out handlers are dropped, they are already reported
+				if let Some((context, _)) = pending.remove(&id) {
+					res.push((context, outcome));
+				}
+			}
+		}
+		let timeout = self.timeout;
+		let expired: Vec<u64> = pending
+			.iter()
+			.filter(|(_, (_, started))| started.elapsed() >= timeout)
+			.map(|(id, _)| *id)
+			.collect();
+		for id in expired {
+			if let Some((context, _)) = pending.remove(&id) {
+				res.push((context, HandlerOutcome::Timeout));
+			}
+		}
+		res
+	}
+}
+
+// Every handler gets the message. Rejection by any handler wins, so the sender is
+// banned, otherwise a panic of any handler is reported.
+async fn run_handlers(
+	handlers: Vec<TopicHandler>,
+	context: MessageContext,
+	data: Vec<u8>,
+) -> HandlerOutcome {
+	let calls = handlers.into_iter().map(|handler| {
+		let context = context.clone();
+		let data = data.clone();
+		// The handler is called inside the future, so a panic before the first await
+		// is caught as well
+		AssertUnwindSafe(async move { handler(context, data).await }).catch_unwind()
+	});
+	let mut panic_msg = None;
+	let mut valid = true;
+	for res in future::join_all(calls).await {
+		match res {
+			Ok(res) => valid &= res,
+			Err(e) => panic_msg = Some(panic_message(&*e)),
+		}
+	}
+	match panic_msg {
+		Some(msg) if valid => HandlerOutcome::Panic(msg),
+		_ => HandlerOutcome::Done(valid),
+	}
+}
+
+fn panic_message(e: &(dyn Any + Send)) -> String {
+	if let Some(s) = e.downcast_ref::<&str>() {
+		s.to_string()
+	} else if let Some(s) = e.downcast_ref::<String>() {
+		s.clone()
+	} else {
+		"unknown panic".to_string()
+	}
+}
+
+lazy_static! {
+	static ref HANDLER_SANDBOX: HandlerSandbox =
+		HandlerSandbox::new(HANDLER_WORKERS, HANDLER_TIMEOUT);
+}
+
+/// Sandbox shared by all topic handlers
+pub fn handler_sandbox() -> &'static HandlerSandbox {
+	&HANDLER_SANDBOX
+}
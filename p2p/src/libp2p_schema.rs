@@ -0,0 +1,166 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Payload schemas of the gossip topics. Every topic declares the payload versions it
+//! understands with the decoding function for each of them. Topics without the declared
+//! schema use the integrity message layout of the current network.
+//!
+//! A message with the version that the topic doesn't know is not an attack, it is most
+//! likely sent by the newer node, so it is ignored and counted, the sender is not penalized.
+
+use crate::core::global;
+use crate::util::RwLock;
+use libp2p::core::SimplePopSerializer;
+use libp2p::gossipsub::{IdentTopic as Topic, TopicHash};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Integrity message version of the mainnet
+pub const MAINNET_MESSAGE_VERSION: u16 = 1;
+/// Integrity message version of the floonet and the test networks
+pub const TESTNET_MESSAGE_VERSION: u16 = 256;
+
+/// Decoded integrity message. Fields are raw, the validation is up to the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityPayload {
+	/// Excess of the integrity kernel that paid for the message
+	pub kernel_excess: Vec<u8>,
+	/// Dalek public key of the sender address
+	pub sender_pk: Vec<u8>,
+	/// Signature of the sender public key by the integrity kernel
+	pub signature: Vec<u8>,
+	/// Message data
+	pub data: Vec<u8>,
+}
+
+/// Decoding function of the single payload version. Serializer is positioned right
+/// after the version.
+pub type PayloadDecoder =
+	Arc<dyn Fn(&mut SimplePopSerializer) -> Result<IntegrityPayload, String> + Send + Sync>;
+
+/// Result of the payload decoding
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayloadDecoding {
+	/// Payload of the known version
+	Decoded(IntegrityPayload),
+	/// Topic doesn't declare this version, the message must be ignored
+	UnknownVersion(u16),
+	/// Known version, but the data is broken
+	Invalid(String),
+}
+
+/// Payload versions of the topic with their decoders
+#[derive(Clone, Default)]
+pub struct PayloadSchema {
+	decoders: HashMap<u16, PayloadDecoder>,
+}
+
+impl PayloadSchema {
+	/// Schema without versions, every message is unknown
+	pub fn new() -> PayloadSchema {
+		PayloadSchema {
+			decoders: HashMap::new(),
+		}
+	}
+
+	/// Declare the payload version
+	pub fn with_version<F>(mut self, version: u16, decoder: F) -> PayloadSchema
+	where
+		F: Fn(&mut SimplePopSerializer) -> Result<IntegrityPayload, String> + Send + Sync + 'static,
+	{
+		self.decoders.insert(version, Arc::new(decoder));
+		self
+	}
+
+	/// Declared payload versions, sorted
+	pub fn versions(&self) -> Vec<u16> {
+		let mut versions: Vec<u16> = self.decoders.keys().cloned().collect();
+		versions.sort();
+		versions
+	}
+
+	/// Decode the message
+	pub fn decode(&self, message: &Vec<u8>) -> PayloadDecoding {
+		let mut ser = SimplePopSerializer::new(message);
+		match self.decoders.get(&ser.version) {
+			Some(decoder) => match (decoder)(&mut ser) {
+				Ok(payload) => PayloadDecoding::Decoded(payload),
+				Err(e) => PayloadDecoding::Invalid(e),
+			},
+			None => PayloadDecoding::UnknownVersion(ser.version),
+		}
+	}
+}
+
+lazy_static! {
+	// Payload schemas declared by the topics
+	static ref PAYLOAD_SCHEMAS: RwLock<HashMap<TopicHash, PayloadSchema>> = RwLock::new(HashMap::new());
+}
+
+/// Integrity message version of the current network. Messages of the other networks
+/// have the different version, so they are never decoded.
+pub fn integrity_message_version() -> u16 {
+	if global::is_mainnet() {
+		MAINNET_MESSAGE_VERSION
+	} else {
+		TESTNET_MESSAGE_VERSION
+	}
+}
+
+/// Decoder of the integrity message: kernel excess, sender public key, signature and data
+pub fn decode_integrity_payload(ser: &mut SimplePopSerializer) -> Result<IntegrityPayload, String> {
+	let payload = IntegrityPayload {
+		kernel_excess: ser.pop_vec(),
+		sender_pk: ser.pop_vec(),
+		signature: ser.pop_vec(),
+		data: ser.pop_vec(),
+	};
+	if payload.kernel_excess.is_empty() || payload.sender_pk.is_empty() {
+		return Err("integrity header is missing".to_string());
+	}
+	Ok(payload)
+}
+
+/// Schema of the topics that don't declare their own
+pub fn default_payload_schema() -> PayloadSchema {
+	PayloadSchema::new().with_version(integrity_message_version(), decode_integrity_payload)
+}
+
+/// Declare the payload schema of the topic, the previous schema is replaced
+pub fn register_payload_schema(topic: &str, schema: PayloadSchema) {
+	PAYLOAD_SCHEMAS
+		.write()
+		.insert(Topic::new(topic).hash(), schema);
+}
+
+/// Drop the payload schema of the topic, the topic returns to the default schema
+pub fn unregister_payload_schema(topic: &str) {
+	PAYLOAD_SCHEMAS.write().remove(&Topic::new(topic).hash());
+}
+
+/// Payload versions that the topic understands
+pub fn payload_versions(topic: &TopicHash) -> Vec<u16> {
+	match PAYLOAD_SCHEMAS.read().get(topic) {
+		Some(schema) => schema.versions(),
+		None => default_payload_schema().versions(),
+	}
+}
+
+/// Decode the message of the topic with the topic schema
+pub fn decode_payload(topic: &TopicHash, message: &Vec<u8>) -> PayloadDecoding {
+	match PAYLOAD_SCHEMAS.read().get(topic) {
+		Some(schema) => schema.decode(message),
+		None => default_payload_schema().decode(message),
+	}
+}
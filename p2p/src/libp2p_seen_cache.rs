@@ -0,0 +1,261 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache of the gossip messages that were already passed to the topic handlers. Gossipsub
+//! remembers the message ids for a few heartbeats only and forgets them on restart, so
+//! the same message could reach the handlers again. The cache is bounded, the records
+//! expire after the TTL. Optionally it is saved to the disk, so it survives the restart.
+
+use crate::core::core::hash::Hash;
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::util::{Mutex, RwLock};
+use grin_store::{self, to_key, Error};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+const DB_NAME: &str = "libp2p_seen";
+const STORE_SUBPATH: &str = "libp2p";
+
+const SEEN_MESSAGE_PREFIX: u8 = b'M';
+
+/// Default max number of the messages in the cache
+pub const DEFAULT_SEEN_CACHE_SIZE: usize = 50_000;
+/// Default time the message is remembered, in seconds
+pub const DEFAULT_SEEN_CACHE_TTL_SECS: u64 = 3600;
+
+/// Message that was passed to the topic handlers
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeenMessageRecord {
+	/// Hash of the message data
+	pub hash: Hash,
+	/// Unix time when the message was seen
+	pub seen_at: i64,
+}
+
+impl Writeable for SeenMessageRecord {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.hash.write(writer)?;
+		writer.write_i64(self.seen_at)
+	}
+}
+
+impl Readable for SeenMessageRecord {
+	fn read<R: Reader>(reader: &mut R) -> Result<SeenMessageRecord, ser::Error> {
+		let hash = Hash::read(reader)?;
+		let seen_at = reader.read_i64()?;
+		Ok(SeenMessageRecord { hash, seen_at })
+	}
+}
+
+/// Bounded cache of the seen messages with the expiration
+pub struct SeenMessageCache {
+	capacity: usize,
+	ttl_secs: i64,
+	seen: HashMap<Hash, i64>,
+	// Messages in the order they were seen
+	order: VecDeque<(Hash, i64)>,
+	// Changes since the last save, tracked only for the persisted cache
+	persist: bool,
+	unsaved: Vec<SeenMessageRecord>,
+	dropped: Vec<Hash>,
+}
+
+impl SeenMessageCache {
+	/// Empty cache. The persisted cache keeps the changes for `take_changes`.
+	pub fn new(capacity: usize, ttl_secs: u64, persist: bool) -> SeenMessageCache {
+		SeenMessageCache {
+			capacity: capacity.max(1),
+			ttl_secs: ttl_secs as i64,
+			seen: HashMap::new(),
+			order: VecDeque::new(),
+			persist,
+			unsaved: vec![],
+			dropped: vec![],
+		}
+	}
+
+	/// Number of the messages in the cache
+	pub fn len(&self) -> usize {
+		self.seen.len()
+	}
+
+	/// True if the message was seen within the TTL. Otherwise the message is
+	/// registered as seen now.
+	pub fn check_and_insert(&mut self, hash: &Hash, now: i64) -> bool {
+		self.expire(now);
+		if self.seen.contains_key(hash) {
+			return true;
+		}
+		self.insert(hash.clone(), now);
+		if self.persist {
+			self.unsaved.push(SeenMessageRecord {
+				hash: hash.clone(),
+				seen_at: now,
+			});
+		}
+		false
+	}
+
+	// Register the message, the oldest messages are dropped above the capacity
+	fn insert(&mut self, hash: Hash, seen_at: i64) {
+		self.seen.insert(hash.clone(), seen_at);
+		self.order.push_back((hash, seen_at));
+		while self.seen.len() > self.capacity {
+			self.pop_oldest();
+		}
+	}
+
+	fn expire(&mut self, now: i64) {
+		while let Some((_, seen_at)) = self.order.front() {
+			if *seen_at + self.ttl_secs > now {
+				break;
+			}
+			self.pop_oldest();
+		}
+	}
+
+	fn pop_oldest(&mut self) {
+		if let Some((hash, seen_at)) = self.order.pop_front() {
+			if self.seen.get(&hash) == Some(&seen_at) {
+				self.seen.remove(&hash);
+				if self.persist {
+					self.dropped.push(hash);
+				}
+			}
+		}
+	}
+
+	/// Load the records, the expired ones are skipped
+	pub fn load(&mut self, mut records: Vec<SeenMessageRecord>, now: i64) {
+		records.sort_by_key(|r| r.seen_at);
+		for record in records {
+			if record.seen_at + self.ttl_secs > now && !self.seen.contains_key(&record.hash) {
+				self.insert(record.hash, record.seen_at);
+			}
+		}
+		self.dropped.clear();
+	}
+
+	/// Changes since the last call: the new records and the dropped messages
+	pub fn take_changes(&mut self) -> (Vec<SeenMessageRecord>, Vec<Hash>) {
+		let seen = &self.seen;
+		let dropped: Vec<Hash> = self
+			.dropped
+			.drain(..)
+			.filter(|h| !seen.contains_key(h))
+			.collect();
+		let unsaved = self
+			.unsaved
+			.drain(..)
+			.filter(|r| seen.get(&r.hash) == Some(&r.seen_at))
+			.collect();
+		(unsaved, dropped)
+	}
+}
+
+/// Storage of the seen messages
+pub struct SeenMessageStore {
+	db: grin_store::Store,
+}
+
+impl SeenMessageStore {
+	/// Open the store under the provided root path
+	pub fn new(db_root: &str) -> Result<SeenMessageStore, Error> {
+		let db = grin_store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		Ok(SeenMessageStore { db })
+	}
+
+	/// Save the new records and delete the dropped ones
+	pub fn save(&self, records: &[SeenMessageRecord], dropped: &[Hash]) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		for hash in dropped {
+			batch.delete(&to_key(SEEN_MESSAGE_PREFIX, hash))?;
+		}
+		for record in records {
+			batch.put_ser(&to_key(SEEN_MESSAGE_PREFIX, &record.hash), record)?;
+		}
+		batch.commit()
+	}
+
+	/// Records seen within the TTL. The expired records are deleted.
+	pub fn load(&self, now: i64, ttl_secs: u64) -> Result<Vec<SeenMessageRecord>, Error> {
+		let (records, expired): (Vec<SeenMessageRecord>, Vec<SeenMessageRecord>) = self
+			.db
+			.iter::<SeenMessageRecord>(&to_key(SEEN_MESSAGE_PREFIX, ""))?
+			.map(|(_, r)| r)
+			.partition(|r| r.seen_at + ttl_secs as i64 > now);
+		if !expired.is_empty() {
+			let batch = self.db.batch()?;
+			for record in &expired {
+				batch.delete(&to_key(SEEN_MESSAGE_PREFIX, &record.hash))?;
+			}
+			batch.commit()?;
+			debug!("{} expired seen messages are deleted", expired.len());
+		}
+		Ok(records)
+	}
+}
+
+lazy_static! {
+	// Seen messages of the node
+	static ref SEEN_MESSAGES: Mutex<SeenMessageCache> = Mutex::new(SeenMessageCache::new(
+		DEFAULT_SEEN_CACHE_SIZE,
+		DEFAULT_SEEN_CACHE_TTL_SECS,
+		false
+	));
+	// Store of the seen messages, None if the cache is not persisted
+	static ref SEEN_MESSAGE_STORE: RwLock<Option<Arc<SeenMessageStore>>> = RwLock::new(None);
+}
+
+/// Set up the seen messages cache of the node. The messages saved before the restart
+/// are loaded from the store.
+pub fn init_seen_message_cache(
+	capacity: usize,
+	ttl_secs: u64,
+	store: Option<SeenMessageStore>,
+	now: i64,
+) -> Result<(), Error> {
+	let mut cache = SeenMessageCache::new(capacity, ttl_secs, store.is_some());
+	if let Some(store) = &store {
+		cache.load(store.load(now, ttl_secs)?, now);
+		debug!("{} seen messages are loaded", cache.len());
+	}
+	*SEEN_MESSAGES.lock() = cache;
+	*SEEN_MESSAGE_STORE.write() = store.map(Arc::new);
+	Ok(())
+}
+
+/// True if the message was already passed to the handlers. Otherwise the message is
+/// registered as seen.
+pub fn is_message_seen(hash: &Hash, now: i64) -> bool {
+	SEEN_MESSAGES.lock().check_and_insert(hash, now)
+}
+
+/// Number of the messages in the seen messages cache
+pub fn seen_messages_count() -> usize {
+	SEEN_MESSAGES.lock().len()
+}
+
+/// Save the changes of the seen messages cache
+pub fn save_seen_messages() -> Result<(), Error> {
+	let store = match SEEN_MESSAGE_STORE.read().clone() {
+		Some(store) => store,
+		None => return Ok(()),
+	};
+	let (records, dropped) = SEEN_MESSAGES.lock().take_changes();
+	if records.is_empty() && dropped.is_empty() {
+		return Ok(());
+	}
+	store.save(&records, &dropped)
+}
@@ -0,0 +1,332 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Libp2p gossip traffic counters: the received messages and their validation results
+//! per topic, the bytes per peer and the integrity fees of the accepted messages.
+//! Bytes are the gossip message data. The sent bytes are counted for the peers the
+//! message is published or forwarded to, gossipsub control traffic and the transport
+//! overhead are not included.
+//!
+//! Connected peers are sampled by the libp2p node, the connection age is accurate to
+//! the sampling interval.
+
+use crate::libp2p_dial::{dial_stats, DialStats};
+use crate::libp2p_kad::{kad_stats, KadStats};
+use crate::libp2p_relay::{relay_stats, RelayStats};
+use crate::util::Mutex;
+use chrono::Utc;
+use std::collections::HashMap;
+
+/// Max number of the peers the traffic is tracked for, the least active are dropped
+pub const MAX_TRACKED_PEERS: usize = 1000;
+
+lazy_static! {
+	static ref LIBP2P_TRAFFIC: Mutex<TrafficCounters> = Mutex::new(TrafficCounters::new());
+	// Connected peers by the peer id
+	static ref LIBP2P_CONNECTIONS: Mutex<HashMap<String, Libp2pConnection>> = Mutex::new(HashMap::new());
+}
+
+/// Validation result of the received message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationResult {
+	/// Message is valid, it is forwarded to the mesh
+	Accepted,
+	/// Message is invalid, the sender is penalized
+	Rejected,
+	/// Message is dropped without penalty
+	Ignored,
+}
+
+/// Gossip traffic of the topic
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct TopicTraffic {
+	/// Topic name
+	pub topic: String,
+	/// Number of the received messages
+	pub messages_received: u64,
+	/// Number of the accepted messages
+	pub accepted: u64,
+	/// Number of the rejected messages
+	pub rejected: u64,
+	/// Number of the ignored messages
+	pub ignored: u64,
+	/// Data of the received messages, in bytes
+	pub bytes_received: u64,
+	/// Number of the messages published by this node
+	pub messages_published: u64,
+	/// Data of the published and forwarded messages, in bytes
+	pub bytes_sent: u64,
+	/// Integrity fees of the accepted messages
+	pub integrity_fees: u64,
+}
+
+/// Gossip traffic of the peer
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PeerTraffic {
+	/// Libp2p peer id
+	pub peer_id: String,
+	/// Number of the messages received from the peer
+	pub messages_received: u64,
+	/// Data of the received messages, in bytes
+	pub bytes_received: u64,
+	/// Number of the messages from the peer that were rejected
+	pub rejected: u64,
+	/// Number of the messages sent to the peer
+	pub messages_sent: u64,
+	/// Data of the sent messages, in bytes
+	pub bytes_sent: u64,
+	/// Unix timestamp of the last message from or to the peer
+	pub last_activity: i64,
+}
+
+/// Libp2p gossip traffic since the node start
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Libp2pStats {
+	/// Unix timestamp when the counting started
+	pub since: i64,
+	/// Number of the received messages on all topics
+	pub messages_received: u64,
+	/// Received message data on all topics, in bytes
+	pub bytes_received: u64,
+	/// Sent message data on all topics, in bytes
+	pub bytes_sent: u64,
+	/// Number of the accepted messages that paid the integrity fee
+	pub integrity_messages: u64,
+	/// Integrity fees of the accepted messages on all topics
+	pub integrity_fees_total: u64,
+	/// Traffic per topic, sorted by the topic name
+	pub topics: Vec<TopicTraffic>,
+	/// Traffic per peer, the most received bytes first
+	pub peers: Vec<PeerTraffic>,
+	/// Outcomes of the dials through the Tor SOCKS proxy
+	pub dials: DialStats,
+	/// Kademlia DHT discovery
+	pub kademlia: KadStats,
+	/// Circuit relay
+	pub relay: RelayStats,
+}
+
+/// Connected peer as seen by the libp2p node sampling
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConnectedPeer {
+	/// Libp2p peer id
+	pub peer_id: String,
+	/// Onion address, None for the peers with the non-onion identity
+	pub onion_address: Option<String>,
+	/// Gossip topics the peer is subscribed to
+	pub topics: Vec<String>,
+}
+
+/// Established libp2p connection with its gossip traffic
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Libp2pConnection {
+	/// Libp2p peer id
+	pub peer_id: String,
+	/// Onion address, None for the peers with the non-onion identity
+	pub onion_address: Option<String>,
+	/// Unix timestamp when the connection was first seen
+	pub connected_since: i64,
+	/// Connection age, in seconds
+	pub age_secs: i64,
+	/// Gossip topics the peer is subscribed to, sorted
+	pub topics: Vec<String>,
+	/// Number of the messages received from the peer
+	pub messages_received: u64,
+	/// Data of the received messages, in bytes
+	pub bytes_received: u64,
+	/// Number of the messages from the peer that were rejected
+	pub rejected: u64,
+	/// Number of the messages sent to the peer
+	pub messages_sent: u64,
+	/// Data of the sent messages, in bytes
+	pub bytes_sent: u64,
+}
+
+struct TrafficCounters {
+	since: i64,
+	integrity_messages: u64,
+	topics: HashMap<String, TopicTraffic>,
+	peers: HashMap<String, PeerTraffic>,
+}
+
+impl TrafficCounters {
+	fn new() -> TrafficCounters {
+		TrafficCounters {
+			since: Utc::now().timestamp(),
+			integrity_messages: 0,
+			topics: HashMap::new(),
+			peers: HashMap::new(),
+		}
+	}
+
+	fn topic(&mut self, topic: &str) -> &mut TopicTraffic {
+		self.topics
+			.entry(topic.to_string())
+			.or_insert_with(|| TopicTraffic {
+				topic: topic.to_string(),
+				..TopicTraffic::default()
+			})
+	}
+
+	fn peer(&mut self, peer_id: &str, now: i64) -> &mut PeerTraffic {
+		if !self.peers.contains_key(peer_id) && self.peers.len() >= MAX_TRACKED_PEERS {
+			let least_active = self
+				.peers
+				.values()
+				.min_by_key(|p| p.last_activity)
+				.map(|p| p.peer_id.clone());
+			if let Some(least_active) = least_active {
+				self.peers.remove(&least_active);
+			}
+		}
+		let peer = self
+			.peers
+			.entry(peer_id.to_string())
+			.or_insert_with(|| PeerTraffic {
+				peer_id: peer_id.to_string(),
+				..PeerTraffic::default()
+			});
+		peer.last_activity = now;
+		peer
+	}
+}
+
+/// Count the message received from the peer
+pub fn record_received(topic: &str, peer_id: &str, bytes: usize) {
+	let now = Utc::now().timestamp();
+	let mut counters = LIBP2P_TRAFFIC.lock();
+	let topic = counters.topic(topic);
+	topic.messages_received += 1;
+	topic.bytes_received += bytes as u64;
+	let peer = counters.peer(peer_id, now);
+	peer.messages_received += 1;
+	peer.bytes_received += bytes as u64;
+}
+
+/// Count the validation result of the message received from the peer
+pub fn record_validation(topic: &str, peer_id: &str, result: ValidationResult) {
+	let now = Utc::now().timestamp();
+	let mut counters = LIBP2P_TRAFFIC.lock();
+	let topic = counters.topic(topic);
+	match result {
+		ValidationResult::Accepted => topic.accepted += 1,
+		ValidationResult::Rejected => topic.rejected += 1,
+		ValidationResult::Ignored => topic.ignored += 1,
+	}
+	if result == ValidationResult::Rejected {
+		counters.peer(peer_id, now).rejected += 1;
+	}
+}
+
+/// Count the integrity fee of the accepted message
+pub fn record_integrity_fee(topic: &str, fee: u64) {
+	let mut counters = LIBP2P_TRAFFIC.lock();
+	counters.integrity_messages += 1;
+	let topic = counters.topic(topic);
+	topic.integrity_fees = topic.integrity_fees.saturating_add(fee);
+}
+
+/// Count the message this node published to the peers
+pub fn record_published(topic: &str, peer_ids: &[String], bytes: usize) {
+	LIBP2P_TRAFFIC.lock().topic(topic).messages_published += 1;
+	record_sent(topic, peer_ids, bytes);
+}
+
+/// Count the message sent to the peers, published or forwarded
+pub fn record_sent(topic: &str, peer_ids: &[String], bytes: usize) {
+	let now = Utc::now().timestamp();
+	let mut counters = LIBP2P_TRAFFIC.lock();
+	counters.topic(topic).bytes_sent += (bytes * peer_ids.len()) as u64;
+	for peer_id in peer_ids {
+		let peer = counters.peer(peer_id, now);
+		peer.messages_sent += 1;
+		peer.bytes_sent += bytes as u64;
+	}
+}
+
+/// Gossip traffic counters of the libp2p node
+pub fn get_libp2p_stats() -> Libp2pStats {
+	let counters = LIBP2P_TRAFFIC.lock();
+	let mut topics: Vec<TopicTraffic> = counters.topics.values().cloned().collect();
+	topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+	let mut peers: Vec<PeerTraffic> = counters.peers.values().cloned().collect();
+	peers.sort_by(|a, b| b.bytes_received.cmp(&a.bytes_received));
+	Libp2pStats {
+		since: counters.since,
+		messages_received: topics.iter().map(|t| t.messages_received).sum(),
+		bytes_received: topics.iter().map(|t| t.bytes_received).sum(),
+		bytes_sent: topics.iter().map(|t| t.bytes_sent).sum(),
+		integrity_messages: counters.integrity_messages,
+		integrity_fees_total: topics.iter().map(|t| t.integrity_fees).sum(),
+		topics,
+		peers,
+		dials: dial_stats(),
+		kademlia: kad_stats(),
+		relay: relay_stats(),
+	}
+}
+
+/// Reset all counters
+pub fn reset_libp2p_stats() {
+	*LIBP2P_TRAFFIC.lock() = TrafficCounters::new();
+}
+
+/// Update the connected peers with the current sample. New peers are connected since
+/// now, the peers that are not in the sample are dropped.
+pub fn record_connections(peers: Vec<ConnectedPeer>, now: i64) {
+	let mut connections = LIBP2P_CONNECTIONS.lock();
+	let mut updated: HashMap<String, Libp2pConnection> = HashMap::with_capacity(peers.len());
+	for peer in peers {
+		let connected_since = connections
+			.get(&peer.peer_id)
+			.map(|c| c.connected_since)
+			.unwrap_or(now);
+		let mut topics = peer.topics;
+		topics.sort();
+		updated.insert(
+			peer.peer_id.clone(),
+			Libp2pConnection {
+				peer_id: peer.peer_id,
+				onion_address: peer.onion_address,
+				connected_since,
+				topics,
+				..Libp2pConnection::default()
+			},
+		);
+	}
+	*connections = updated;
+}
+
+/// Established libp2p connections with the traffic counters, the oldest first
+pub fn get_libp2p_connections_info(now: i64) -> Vec<Libp2pConnection> {
+	let mut connections: Vec<Libp2pConnection> =
+		LIBP2P_CONNECTIONS.lock().values().cloned().collect();
+	let counters = LIBP2P_TRAFFIC.lock();
+	for c in &mut connections {
+		c.age_secs = (now - c.connected_since).max(0);
+		if let Some(traffic) = counters.peers.get(&c.peer_id) {
+			c.messages_received = traffic.messages_received;
+			c.bytes_received = traffic.bytes_received;
+			c.rejected = traffic.rejected;
+			c.messages_sent = traffic.messages_sent;
+			c.bytes_sent = traffic.bytes_sent;
+		}
+	}
+	connections.sort_by(|a, b| {
+		a.connected_since
+			.cmp(&b.connected_since)
+			.then_with(|| a.peer_id.cmp(&b.peer_id))
+	});
+	connections
+}
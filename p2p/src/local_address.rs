@@ -0,0 +1,156 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local address detection. The peer that connects to us puts the address it dialed into
+//! the handshake. Every peer votes with that observed address, the address with the most
+//! recent votes is our external address. Behind a NAT it is the address the peers can
+//! reach, not the bind address. The advertised address can also be set explicitly, or
+//! the advertisement disabled for the nodes that don't accept the connections.
+
+use crate::types::{P2PConfig, PeerAddr};
+use crate::util::RwLock;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Min number of the votes for the detected address
+pub const MIN_ADDRESS_VOTES: usize = 3;
+/// Votes older than that are not counted
+pub const ADDRESS_VOTE_EXPIRE_SECS: i64 = 24 * 3600;
+/// Max number of the tracked voters, the oldest votes are dropped
+pub const MAX_ADDRESS_VOTERS: usize = 1000;
+
+/// True if the address can be reached from the internet
+pub fn is_routable(ip: &IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => {
+			!(ip.is_unspecified()
+				|| ip.is_loopback()
+				|| ip.is_private()
+				|| ip.is_link_local()
+				|| ip.is_broadcast()
+				|| ip.is_documentation())
+		}
+		IpAddr::V6(ip) => {
+			let segment = ip.segments()[0];
+			!(ip.is_unspecified()
+				|| ip.is_loopback()
+				// unique local fc00::/7 and link local fe80::/10
+				|| (segment & 0xfe00) == 0xfc00
+				|| (segment & 0xffc0) == 0xfe80)
+		}
+	}
+}
+
+/// Votes of the peers for our external address
+#[derive(Default)]
+pub struct ObservedAddresses {
+	// Voter IP -> observed address and the vote time. One vote per peer IP, so a single
+	// host can't outvote the others with many connections.
+	votes: HashMap<IpAddr, (SocketAddr, i64)>,
+}
+
+impl ObservedAddresses {
+	/// No votes
+	pub fn new() -> ObservedAddresses {
+		ObservedAddresses {
+			votes: HashMap::new(),
+		}
+	}
+
+	/// Record the address the peer dialed. Not routable addresses and the votes of the
+	/// local peers are ignored.
+	pub fn record(&mut self, voter: IpAddr, observed: SocketAddr, now: i64) {
+		if !is_routable(&voter) || !is_routable(&observed.ip()) || observed.port() == 0 {
+			return;
+		}
+		self.votes.insert(voter, (observed, now));
+		if self.votes.len() > MAX_ADDRESS_VOTERS {
+			let oldest = self
+				.votes
+				.iter()
+				.min_by_key(|(_, (_, time))| *time)
+				.map(|(voter, _)| *voter);
+			if let Some(oldest) = oldest {
+				self.votes.remove(&oldest);
+			}
+		}
+	}
+
+	/// Address with the most recent votes, if it has enough of them
+	pub fn detected(&self, now: i64) -> Option<SocketAddr> {
+		let mut counts: HashMap<SocketAddr, usize> = HashMap::new();
+		for (addr, time) in self.votes.values() {
+			if *time > now - ADDRESS_VOTE_EXPIRE_SECS {
+				*counts.entry(*addr).or_insert(0) += 1;
+			}
+		}
+		counts
+			.into_iter()
+			.filter(|(_, count)| *count >= MIN_ADDRESS_VOTES)
+			// Ties are resolved by the address, so the result is stable
+			.max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+			.map(|(addr, _)| addr)
+	}
+}
+
+lazy_static! {
+	// Votes of the peers that connected to this node
+	static ref OBSERVED_ADDRESSES: RwLock<ObservedAddresses> = RwLock::new(ObservedAddresses::new());
+}
+
+/// Record our address as it is observed by the connected peer
+pub fn record_observed_address(voter: IpAddr, observed: SocketAddr) {
+	let now = Utc::now().timestamp();
+	let prev = OBSERVED_ADDRESSES.read().detected(now);
+	let mut observed_addresses = OBSERVED_ADDRESSES.write();
+	observed_addresses.record(voter, observed, now);
+	let detected = observed_addresses.detected(now);
+	if detected.is_some() && detected != prev {
+		info!("External address is detected as {:?}", detected);
+	}
+}
+
+/// Our external address detected from the peer votes
+pub fn detected_external_address() -> Option<SocketAddr> {
+	OBSERVED_ADDRESSES.read().detected(Utc::now().timestamp())
+}
+
+/// Address that we advertise in the handshake. Port 0 means that the address is not
+/// advertised, the peers don't share it and don't dial it.
+pub fn advertised_address(config: &P2PConfig) -> PeerAddr {
+	if !config.advertise_address() {
+		return PeerAddr::Ip(SocketAddr::new(config.host, 0));
+	}
+	if let Some(addr) = &config.advertised_address {
+		match addr.parse::<SocketAddr>() {
+			Ok(addr) => return PeerAddr::Ip(addr),
+			Err(e) => warn!("Invalid advertised_address {}, {}", addr, e),
+		}
+	}
+	if config.detect_external_address() {
+		if let Some(addr) = detected_external_address() {
+			return PeerAddr::Ip(addr);
+		}
+	}
+	PeerAddr::Ip(SocketAddr::new(config.host, config.port))
+}
+
+/// False if the peer doesn't advertise its address, we can't dial it
+pub fn is_advertised(addr: &PeerAddr) -> bool {
+	match addr {
+		PeerAddr::Ip(addr) => addr.port() != 0,
+		PeerAddr::Onion(_) => true,
+	}
+}
@@ -32,6 +32,42 @@ use std::sync::Arc;
 
 /// Grin's user agent with current version
 pub const USER_AGENT: &str = concat!("MW/MWC ", env!("CARGO_PKG_VERSION"));
+/// Prefix every user agent must start with, so peers can recognize the protocol
+pub const USER_AGENT_PREFIX: &str = "MW/";
+/// Max length of the custom user agent
+pub const USER_AGENT_MAX_LEN: usize = 64;
+
+/// User agent without the exact version, only the major and minor numbers are advertised
+pub fn minimal_user_agent() -> String {
+	let version: Vec<&str> = env!("CARGO_PKG_VERSION").splitn(3, '.').collect();
+	format!(
+		"MW/MWC {}",
+		version[..std::cmp::min(2, version.len())].join(".")
+	)
+}
+
+/// Check the custom user agent against the policy: it starts with the protocol prefix,
+/// is not too long and has printable ascii characters only.
+pub fn validate_user_agent(user_agent: &str) -> Result<(), Error> {
+	if !user_agent.starts_with(USER_AGENT_PREFIX) {
+		return Err(Error::Internal(format!(
+			"user agent must start with '{}'",
+			USER_AGENT_PREFIX
+		)));
+	}
+	if user_agent.len() > USER_AGENT_MAX_LEN {
+		return Err(Error::Internal(format!(
+			"user agent is longer than {} characters",
+			USER_AGENT_MAX_LEN
+		)));
+	}
+	if !user_agent.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+		return Err(Error::Internal(
+			"user agent must have printable ascii characters only".to_string(),
+		));
+	}
+	Ok(())
+}
 
 // MWC - Magic number are updated to be different from grin.
 /// Magic numbers expected in the header of every message
@@ -141,6 +177,16 @@ impl Msg {
 	pub fn add_attachment(&mut self, attachment: File) {
 		self.attachment = Some(attachment)
 	}
+
+	/// Header of the message
+	pub fn header(&self) -> &MsgHeader {
+		&self.header
+	}
+
+	/// Serialized body of the message, without the attachment
+	pub fn body(&self) -> &[u8] {
+		&self.body
+	}
 }
 
 /// Read a header from the provided stream without blocking if the
@@ -608,6 +654,22 @@ impl Writeable for Headers {
 	}
 }
 
+/// Headers are read from the stream one by one by the protocol, the whole message
+/// is read only by the tools.
+impl Readable for Headers {
+	fn read<R: Reader>(reader: &mut R) -> Result<Headers, ser::Error> {
+		let len = reader.read_u16()?;
+		if len as u32 > MAX_BLOCK_HEADERS {
+			return Err(ser::Error::TooLargeReadErr(format!(
+				"Get too many headers: {}",
+				len
+			)));
+		}
+		let headers = ser::read_multi(reader, len as u64)?;
+		Ok(Headers { headers })
+	}
+}
+
 pub struct Ping {
 	/// total difficulty accumulated by the sender, used to check whether sync
 	/// may be needed
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use crate::serv::Server;
-use crate::util::{Mutex, RwLock};
+use crate::util::{clock, Mutex, RwLock};
 use std::fmt;
 use std::fs::File;
 use std::net::{Shutdown, TcpStream};
@@ -25,6 +25,7 @@ use lru_cache::LruCache;
 
 use crate::chain;
 use crate::conn;
+use crate::conn_stats::{PeerConnStats, SlowPeerReason};
 use crate::core::core::hash::{Hash, Hashed};
 use crate::core::pow::Difficulty;
 use crate::core::ser::Writeable;
@@ -84,6 +85,9 @@ impl Peer {
 		let state = Arc::new(RwLock::new(State::Connected));
 		let state_sync_requested = Arc::new(AtomicBool::new(false));
 		let tracking_adapter = TrackingAdapter::new(adapter);
+		let tracker = Arc::new(conn::Tracker::with_thresholds(
+			server.config.slow_peer_thresholds(),
+		));
 		let handler = Protocol::new(
 			Arc::new(tracking_adapter.clone()),
 			info.clone(),
@@ -91,8 +95,8 @@ impl Peer {
 			header_cache_size,
 			server,
 		);
-		let tracker = Arc::new(conn::Tracker::new());
-		let (sendh, stoph) = conn::listen(conn, info.version, tracker.clone(), handler)?;
+		let (sendh, stoph) =
+			conn::listen(conn, &info.addr, info.version, tracker.clone(), handler)?;
 		let send_handle = Mutex::new(sendh);
 		let stop_handle = Mutex::new(stoph);
 		Ok(Peer {
@@ -225,7 +229,7 @@ impl Peer {
 	/// Whether this peer is stuck on sync.
 	pub fn is_stuck(&self) -> (bool, Difficulty) {
 		let peer_live_info = self.info.live_info.read();
-		let now = Utc::now().timestamp_millis();
+		let now = clock::now().timestamp_millis();
 		// if last updated difficulty is 2 hours ago, we're sure this peer is a stuck node.
 		if now > peer_live_info.stuck_detector.timestamp_millis() + global::STUCK_PEER_KICK_TIME {
 			(true, peer_live_info.total_difficulty)
@@ -259,6 +263,16 @@ impl Peer {
 		Some((sent_bytes.count_per_min(), received_bytes.count_per_min()))
 	}
 
+	/// Ping round trip, read sizes and stalls of the connection
+	pub fn conn_stats(&self) -> PeerConnStats {
+		self.tracker.conn_stats.stats(Instant::now())
+	}
+
+	/// Reason to drop the peer if it trickles or stalls the message it is sending
+	pub fn slow_peer_reason(&self) -> Option<SlowPeerReason> {
+		self.tracker.conn_stats.check(Instant::now())
+	}
+
 	/// Set this peer status to banned
 	pub fn set_banned(&self) {
 		*self.state.write() = State::Banned;
@@ -277,7 +291,9 @@ impl Peer {
 			total_difficulty,
 			height,
 		};
-		self.send(ping_msg, msg::Type::Ping)
+		self.send(ping_msg, msg::Type::Ping)?;
+		self.tracker.conn_stats.ping_sent(Instant::now());
+		Ok(())
 	}
 
 	/// Send the ban reason before banning
@@ -301,6 +317,25 @@ impl Peer {
 		}
 	}
 
+	/// Announce the block mined by us. Peers with the header first capability get
+	/// the header only and request the body if they don't have the block yet,
+	/// headers only relay peers get the header only too, other peers get the compact
+	/// block pushed.
+	pub fn announce_block(&self, b: &core::CompactBlock) -> Result<bool, Error> {
+		if self.announces_header() {
+			self.send_header(&b.header)
+		} else {
+			self.send_compact_block(b)
+		}
+	}
+
+	/// True if the blocks are announced to the peer with the header
+	pub fn announces_header(&self) -> bool {
+		self.info
+			.capabilities
+			.intersects(Capabilities::HEADER_FIRST | Capabilities::HEADERS_ONLY)
+	}
+
 	pub fn send_header(&self, bh: &core::BlockHeader) -> Result<bool, Error> {
 		if !self.tracking_adapter.has_recv(bh.hash()) {
 			debug!("Send header {} to {}", bh.hash(), self.info.addr);
@@ -337,6 +372,11 @@ impl Peer {
 	pub fn send_transaction(&self, tx: &core::Transaction) -> Result<bool, Error> {
 		let kernel = &tx.kernels()[0];
 
+		// Headers only relay peers don't take part in the transaction relay
+		if !self.info.serves_bodies() {
+			return Ok(false);
+		}
+
 		if self
 			.info
 			.capabilities
@@ -0,0 +1,414 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Differential peer list exchange over the libp2p peer topic. Version 1 messages carry
+//! the full peer list of the sender. With version 2 the sender announces the digest of its
+//! peer set only. A neighbour that has a different digest for the sender requests the
+//! changes since the set it knows, and the sender answers with the added and removed
+//! peers, or with the full set if it doesn't remember that base set any more. Stable
+//! meshes exchange the digests only.
+
+use crate::core::core::hash::{Hash, HashWriter};
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+/// Version of the differential peer exchange messages
+pub const PEER_EXCHANGE_DIFF_VERSION: u16 = 2;
+/// Max number of the peers in the single message
+pub const PEER_EXCHANGE_MAX_ENTRIES: usize = 100;
+/// Number of the previous announced sets that the sender can build the diff from
+pub const PEER_EXCHANGE_HISTORY: usize = 8;
+// Onion v3 address is 56 chars, some room for the normalization differences
+const MAX_ADDRESS_LEN: usize = 100;
+
+/// Digest of the peer set. The set is ordered, so the same set has the same digest.
+pub fn peer_set_digest(peers: &BTreeSet<String>) -> Hash {
+	let mut hasher = HashWriter::default();
+	for peer in peers {
+		hasher.write_bytes(peer).expect("hash writer never fails");
+	}
+	hasher.into_hash()
+}
+
+/// Differential peer exchange message
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerExchangeMessage {
+	/// Digest of the sender peer set
+	Digest {
+		/// Digest of the set
+		digest: Hash,
+		/// Number of the peers in the set
+		count: u32,
+	},
+	/// Request to the target to send the changes since the base set
+	Request {
+		/// Onion address of the node that should answer
+		target: String,
+		/// Digest of the target set known by the requester
+		base: Hash,
+	},
+	/// Changes of the sender set since the base set
+	Diff {
+		/// Digest of the set the changes are applied to
+		base: Hash,
+		/// Digest of the resulting set
+		digest: Hash,
+		/// Peers added since the base set
+		added: Vec<String>,
+		/// Peers removed since the base set
+		removed: Vec<String>,
+	},
+	/// Full sender set, when the base set is not known by the sender
+	Full {
+		/// Digest of the set
+		digest: Hash,
+		/// Peers of the set
+		peers: Vec<String>,
+	},
+}
+
+fn write_peers<W: Writer>(writer: &mut W, peers: &Vec<String>) -> Result<(), ser::Error> {
+	if peers.len() > PEER_EXCHANGE_MAX_ENTRIES {
+		return Err(ser::Error::TooLargeWriteErr(format!(
+			"Too many peers in the exchange message, {}",
+			peers.len()
+		)));
+	}
+	writer.write_u16(peers.len() as u16)?;
+	for peer in peers {
+		writer.write_bytes(peer)?;
+	}
+	Ok(())
+}
+
+fn read_peers<R: Reader>(reader: &mut R) -> Result<Vec<String>, ser::Error> {
+	let len = reader.read_u16()? as usize;
+	if len > PEER_EXCHANGE_MAX_ENTRIES {
+		return Err(ser::Error::TooLargeReadErr(format!(
+			"Too many peers in the exchange message, {}",
+			len
+		)));
+	}
+	let mut peers = Vec::with_capacity(len);
+	for _ in 0..len {
+		peers.push(read_address(reader)?);
+	}
+	Ok(peers)
+}
+
+fn read_address<R: Reader>(reader: &mut R) -> Result<String, ser::Error> {
+	let data = reader.read_bytes_len_prefix()?;
+	if data.len() > MAX_ADDRESS_LEN {
+		return Err(ser::Error::TooLargeReadErr(format!(
+			"Peer address is too long, {}",
+			data.len()
+		)));
+	}
+	String::from_utf8(data)
+		.map_err(|e| ser::Error::CorruptedData(format!("Invalid peer address, {}", e)))
+}
+
+impl Writeable for PeerExchangeMessage {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		match self {
+			PeerExchangeMessage::Digest { digest, count } => {
+				writer.write_u8(0)?;
+				digest.write(writer)?;
+				writer.write_u32(*count)
+			}
+			PeerExchangeMessage::Request { target, base } => {
+				writer.write_u8(1)?;
+				writer.write_bytes(target)?;
+				base.write(writer)
+			}
+			PeerExchangeMessage::Diff {
+				base,
+				digest,
+				added,
+				removed,
+			} => {
+				writer.write_u8(2)?;
+				base.write(writer)?;
+				digest.write(writer)?;
+				write_peers(writer, added)?;
+				write_peers(writer, removed)
+			}
+			PeerExchangeMessage::Full { digest, peers } => {
+				writer.write_u8(3)?;
+				digest.write(writer)?;
+				write_peers(writer, peers)
+			}
+		}
+	}
+}
+
+impl Readable for PeerExchangeMessage {
+	fn read<R: Reader>(reader: &mut R) -> Result<PeerExchangeMessage, ser::Error> {
+		match reader.read_u8()? {
+			0 => Ok(PeerExchangeMessage::Digest {
+				digest: Hash::read(reader)?,
+				count: reader.read_u32()?,
+			}),
+			1 => Ok(PeerExchangeMessage::Request {
+				target: read_address(reader)?,
+				base: Hash::read(reader)?,
+			}),
+			2 => Ok(PeerExchangeMessage::Diff {
+				base: Hash::read(reader)?,
+				digest: Hash::read(reader)?,
+				added: read_peers(reader)?,
+				removed: read_peers(reader)?,
+			}),
+			3 => Ok(PeerExchangeMessage::Full {
+				digest: Hash::read(reader)?,
+				peers: read_peers(reader)?,
+			}),
+			_ => Err(ser::Error::CorruptedData(
+				"Unknown peer exchange message type".to_string(),
+			)),
+		}
+	}
+}
+
+/// Sender side of the exchange, the set of this node and the previously announced sets
+pub struct PeerExchangeSender {
+	peers: BTreeSet<String>,
+	digest: Hash,
+	history: VecDeque<(Hash, BTreeSet<String>)>,
+	requested: HashSet<Hash>,
+}
+
+impl Default for PeerExchangeSender {
+	fn default() -> PeerExchangeSender {
+		PeerExchangeSender::new()
+	}
+}
+
+impl PeerExchangeSender {
+	/// Sender with the empty set
+	pub fn new() -> PeerExchangeSender {
+		let peers = BTreeSet::new();
+		PeerExchangeSender {
+			digest: peer_set_digest(&peers),
+			peers,
+			history: VecDeque::new(),
+			requested: HashSet::new(),
+		}
+	}
+
+	/// Digest of the current set
+	pub fn digest(&self) -> Hash {
+		self.digest
+	}
+
+	/// Digest announcement of the current set
+	pub fn digest_message(&self) -> PeerExchangeMessage {
+		PeerExchangeMessage::Digest {
+			digest: self.digest,
+			count: self.peers.len() as u32,
+		}
+	}
+
+	/// Update the set of this node. Returns the digest announcement if the set is changed.
+	pub fn update(&mut self, peers: BTreeSet<String>) -> Option<PeerExchangeMessage> {
+		// The set is truncated, so every diff and the full set fit into a message
+		let peers: BTreeSet<String> = peers.into_iter().take(PEER_EXCHANGE_MAX_ENTRIES).collect();
+		if peers == self.peers {
+			return None;
+		}
+		let prev_peers = std::mem::replace(&mut self.peers, peers);
+		self.history.push_back((self.digest, prev_peers));
+		while self.history.len() > PEER_EXCHANGE_HISTORY {
+			self.history.pop_front();
+		}
+		self.digest = peer_set_digest(&self.peers);
+		Some(self.digest_message())
+	}
+
+	/// Neighbour requested the changes since the base set. Requests are answered in batch,
+	/// the neighbours with the same base get the same answer.
+	pub fn on_request(&mut self, base: Hash) {
+		if base != self.digest {
+			self.requested.insert(base);
+		}
+	}
+
+	/// Answers to the pending requests
+	pub fn responses(&mut self) -> Vec<PeerExchangeMessage> {
+		let requested: Vec<Hash> = self.requested.drain().collect();
+		requested
+			.into_iter()
+			.filter(|base| *base != self.digest)
+			.map(
+				|base| match self.history.iter().find(|(digest, _)| *digest == base) {
+					Some((_, base_peers)) => PeerExchangeMessage::Diff {
+						base,
+						digest: self.digest,
+						added: self.peers.difference(base_peers).cloned().collect(),
+						removed: base_peers.difference(&self.peers).cloned().collect(),
+					},
+					None => PeerExchangeMessage::Full {
+						digest: self.digest,
+						peers: self.peers.iter().cloned().collect(),
+					},
+				},
+			)
+			.collect()
+	}
+}
+
+/// Result of the received message processing
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerExchangeOutcome {
+	/// Nothing to do
+	None,
+	/// The message should be published
+	Publish(PeerExchangeMessage),
+	/// Set of the sender is updated, these peers are new
+	NewPeers(Vec<String>),
+}
+
+struct KnownSet {
+	peers: BTreeSet<String>,
+	digest: Hash,
+	updated: i64,
+}
+
+/// Receiver side of the exchange, the sets of the neighbours
+pub struct PeerExchangeReceiver {
+	known: HashMap<String, KnownSet>,
+}
+
+impl Default for PeerExchangeReceiver {
+	fn default() -> PeerExchangeReceiver {
+		PeerExchangeReceiver::new()
+	}
+}
+
+impl PeerExchangeReceiver {
+	/// No known sets
+	pub fn new() -> PeerExchangeReceiver {
+		PeerExchangeReceiver {
+			known: HashMap::new(),
+		}
+	}
+
+	/// Known set of the neighbour
+	pub fn peers(&self, sender: &str) -> Option<Vec<String>> {
+		self.known
+			.get(sender)
+			.map(|k| k.peers.iter().cloned().collect())
+	}
+
+	fn base(&self, sender: &str) -> Hash {
+		match self.known.get(sender) {
+			Some(known) => known.digest,
+			None => peer_set_digest(&BTreeSet::new()),
+		}
+	}
+
+	// Requests the changes, or the full set if the known set is broken
+	fn request(&self, sender: &str) -> PeerExchangeOutcome {
+		PeerExchangeOutcome::Publish(PeerExchangeMessage::Request {
+			target: sender.to_string(),
+			base: self.base(sender),
+		})
+	}
+
+	/// Process the message of the neighbour `sender`. Requests are not processed here,
+	/// the ones targeted to this node go to the sender side.
+	pub fn on_message(
+		&mut self,
+		sender: &str,
+		message: PeerExchangeMessage,
+		now: i64,
+	) -> PeerExchangeOutcome {
+		match message {
+			PeerExchangeMessage::Digest { digest, .. } => {
+				if self.base(sender) == digest {
+					if let Some(known) = self.known.get_mut(sender) {
+						known.updated = now;
+					}
+					PeerExchangeOutcome::None
+				} else {
+					self.request(sender)
+				}
+			}
+			PeerExchangeMessage::Request { .. } => PeerExchangeOutcome::None,
+			PeerExchangeMessage::Diff {
+				base,
+				digest,
+				added,
+				removed,
+			} => {
+				if base != self.base(sender) {
+					// The answer to the neighbour that knows a different set
+					return PeerExchangeOutcome::None;
+				}
+				let mut peers = self
+					.known
+					.remove(sender)
+					.map(|k| k.peers)
+					.unwrap_or_default();
+				for peer in &removed {
+					peers.remove(peer);
+				}
+				let new_peers: Vec<String> = added
+					.into_iter()
+					.filter(|p| peers.insert(p.clone()))
+					.collect();
+				if peer_set_digest(&peers) != digest {
+					// Inconsistent diff, the set is dropped and requested in full
+					return self.request(sender);
+				}
+				self.known.insert(
+					sender.to_string(),
+					KnownSet {
+						peers,
+						digest,
+						updated: now,
+					},
+				);
+				PeerExchangeOutcome::NewPeers(new_peers)
+			}
+			PeerExchangeMessage::Full { digest, peers } => {
+				if self.base(sender) == digest {
+					return PeerExchangeOutcome::None;
+				}
+				let peers: BTreeSet<String> = peers.into_iter().collect();
+				if peer_set_digest(&peers) != digest {
+					return PeerExchangeOutcome::None;
+				}
+				let new_peers = match self.known.get(sender) {
+					Some(known) => peers.difference(&known.peers).cloned().collect(),
+					None => peers.iter().cloned().collect(),
+				};
+				self.known.insert(
+					sender.to_string(),
+					KnownSet {
+						peers,
+						digest,
+						updated: now,
+					},
+				);
+				PeerExchangeOutcome::NewPeers(new_peers)
+			}
+		}
+	}
+
+	/// Drop the sets of the neighbours that were not heard from since `min_time`
+	pub fn prune(&mut self, min_time: i64) {
+		self.known.retain(|_, k| k.updated >= min_time);
+	}
+}
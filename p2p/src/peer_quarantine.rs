@@ -0,0 +1,169 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local quarantine of the libp2p peers that originated the invalid gossip messages.
+//! Banning the peer that forwarded the message doesn't stop the author, gossipsub signs
+//! the messages, so the originator is known. Quarantined peer is not dialed, its
+//! connections are banned, its messages are not relayed and its address is dropped from
+//! the peer exchange lists until the quarantine expires.
+
+use crate::store::Libp2pPeerAccess;
+use crate::util::{clock, RwLock};
+use std::collections::HashMap;
+
+/// Default quarantine period, in seconds
+pub const DEFAULT_QUARANTINE_SECS: u64 = 3600;
+/// Max number of the quarantined peers. The oldest quarantines are dropped above the limit.
+pub const MAX_QUARANTINED_PEERS: usize = 10_000;
+
+/// Quarantined peer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QuarantinedPeer {
+	/// Onion address of the peer
+	pub address: String,
+	/// Reason of the last quarantine
+	pub reason: String,
+	/// Number of the invalid messages originated by the peer
+	pub offenses: u32,
+	/// Unix time when the peer was quarantined the first time
+	pub since: i64,
+	/// Unix time when the quarantine expires
+	pub until: i64,
+}
+
+/// Quarantined peers by the normalized onion address
+pub struct PeerQuarantine {
+	quarantine_secs: u64,
+	peers: HashMap<String, QuarantinedPeer>,
+}
+
+impl PeerQuarantine {
+	/// Quarantine with the period in seconds. 0 disables it.
+	pub fn new(quarantine_secs: u64) -> PeerQuarantine {
+		PeerQuarantine {
+			quarantine_secs,
+			peers: HashMap::new(),
+		}
+	}
+
+	/// Quarantine period in seconds, 0 if the quarantine is disabled
+	pub fn quarantine_secs(&self) -> u64 {
+		self.quarantine_secs
+	}
+
+	/// Quarantine the peer or extend its quarantine. Returns true if the peer was not
+	/// quarantined before.
+	pub fn quarantine(&mut self, address: &str, reason: &str, now: i64) -> bool {
+		if self.quarantine_secs == 0 {
+			return false;
+		}
+		self.prune(now);
+		let address = Libp2pPeerAccess::normalize_address(address);
+		let until = now + self.quarantine_secs as i64;
+		if let Some(peer) = self.peers.get_mut(&address) {
+			peer.reason = reason.to_string();
+			peer.offenses += 1;
+			peer.until = until;
+			return false;
+		}
+		if self.peers.len() >= MAX_QUARANTINED_PEERS {
+			if let Some(oldest) = self
+				.peers
+				.values()
+				.min_by_key(|p| p.until)
+				.map(|p| p.address.clone())
+			{
+				self.peers.remove(&oldest);
+			}
+		}
+		self.peers.insert(
+			address.clone(),
+			QuarantinedPeer {
+				address,
+				reason: reason.to_string(),
+				offenses: 1,
+				since: now,
+				until,
+			},
+		);
+		true
+	}
+
+	/// Check if the peer is quarantined at the time
+	pub fn is_quarantined(&self, address: &str, now: i64) -> bool {
+		self.peers
+			.get(&Libp2pPeerAccess::normalize_address(address))
+			.map(|p| p.until > now)
+			.unwrap_or(false)
+	}
+
+	/// Release the peer, returns false if it was not quarantined
+	pub fn release(&mut self, address: &str) -> bool {
+		self.peers
+			.remove(&Libp2pPeerAccess::normalize_address(address))
+			.is_some()
+	}
+
+	/// Drop the expired quarantines
+	pub fn prune(&mut self, now: i64) {
+		self.peers.retain(|_, p| p.until > now);
+	}
+
+	/// Peers that are quarantined at the time, the recent first
+	pub fn peers(&self, now: i64) -> Vec<QuarantinedPeer> {
+		let mut peers: Vec<QuarantinedPeer> = self
+			.peers
+			.values()
+			.filter(|p| p.until > now)
+			.cloned()
+			.collect();
+		peers.sort_by(|a, b| b.until.cmp(&a.until));
+		peers
+	}
+}
+
+lazy_static! {
+	static ref PEER_QUARANTINE: RwLock<PeerQuarantine> =
+		RwLock::new(PeerQuarantine::new(DEFAULT_QUARANTINE_SECS));
+}
+
+/// Set the quarantine period in seconds, 0 disables the quarantine. Current quarantines
+/// are dropped.
+pub fn init_peer_quarantine(quarantine_secs: u64) {
+	*PEER_QUARANTINE.write() = PeerQuarantine::new(quarantine_secs);
+}
+
+/// Quarantine the peer, returns true if the peer was not quarantined before
+pub fn quarantine_peer(address: &str, reason: &str) -> bool {
+	PEER_QUARANTINE
+		.write()
+		.quarantine(address, reason, clock::now().timestamp())
+}
+
+/// Check if the peer is quarantined
+pub fn is_peer_quarantined(address: &str) -> bool {
+	PEER_QUARANTINE
+		.read()
+		.is_quarantined(address, clock::now().timestamp())
+}
+
+/// Release the quarantined peer, returns false if it was not quarantined
+pub fn release_peer(address: &str) -> bool {
+	PEER_QUARANTINE.write().release(address)
+}
+
+/// Currently quarantined peers
+pub fn quarantined_peers() -> Vec<QuarantinedPeer> {
+	PEER_QUARANTINE.read().peers(clock::now().timestamp())
+}
@@ -0,0 +1,153 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Peer snapshots. The operator exports the peers the node knows (address, capabilities,
+//! user agent, last connection time and ban state) and imports them into a fresh node,
+//! so the fresh node doesn't depend on the seeds only. The snapshot is signed with the
+//! snapshot key of the exporting node. The importing node verifies the signature and,
+//! if the operator provided it, the signer public key.
+
+use crate::core::core::hash::Hashed;
+use crate::core::libtx::aggsig;
+use crate::core::ser::{self, ProtocolVersion};
+use crate::store::{PeerData, State};
+use crate::types::Error;
+use crate::util::secp::key::{PublicKey, SecretKey};
+use crate::util::secp::{ContextFlag, Message, Secp256k1, Signature};
+use crate::util::{from_hex, ToHex};
+use chrono::Utc;
+
+/// Current snapshot format version
+pub const PEER_SNAPSHOT_VERSION: u16 = 1;
+/// Max number of the peers in the snapshot
+pub const PEER_SNAPSHOT_MAX_PEERS: usize = 10_000;
+
+/// Domain separator, so the snapshot signature can't be reused anywhere else
+const PEER_SNAPSHOT_DOMAIN: &[u8] = b"MWC_PEER_SNAPSHOT";
+
+/// Signed list of the peers known by the node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerSnapshot {
+	/// Snapshot format version
+	pub version: u16,
+	/// Unix time when the snapshot was created
+	pub created: i64,
+	/// Known peers, the recently connected first
+	pub peers: Vec<PeerData>,
+	/// Public key of the snapshot signer, hex encoded
+	pub public_key: String,
+	/// Signature of the snapshot, hex encoded
+	pub signature: String,
+}
+
+/// Result of the snapshot import
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeerSnapshotImport {
+	/// Peers that were added to the peer store
+	pub imported: usize,
+	/// Peers that were skipped: already known or defunct
+	pub skipped: usize,
+}
+
+// Blake2b hash of the signed snapshot data
+fn snapshot_msg(version: u16, created: i64, peers: &[PeerData]) -> Result<Message, Error> {
+	let mut data = PEER_SNAPSHOT_DOMAIN.to_vec();
+	data.extend_from_slice(&version.to_be_bytes());
+	data.extend_from_slice(&created.to_be_bytes());
+	for peer in peers {
+		data.extend_from_slice(&ser::ser_vec(peer, ProtocolVersion(1))?);
+	}
+	Message::from_slice(data.hash().as_bytes())
+		.map_err(|e| Error::Internal(format!("Unable to build snapshot message, {}", e)))
+}
+
+impl PeerSnapshot {
+	/// Build the snapshot of the peers and sign it. Defunct peers are not included.
+	pub fn create(mut peers: Vec<PeerData>, key: &SecretKey) -> Result<PeerSnapshot, Error> {
+		peers.retain(|p| p.flags != State::Defunct);
+		peers.sort_by(|a, b| b.last_connected.cmp(&a.last_connected));
+		peers.truncate(PEER_SNAPSHOT_MAX_PEERS);
+
+		let created = Utc::now().timestamp();
+		let secp = Secp256k1::with_caps(ContextFlag::Full);
+		let public_key = PublicKey::from_secret_key(&secp, key)
+			.map_err(|e| Error::Internal(format!("Invalid snapshot key, {}", e)))?;
+		let msg = snapshot_msg(PEER_SNAPSHOT_VERSION, created, &peers)?;
+		let signature = aggsig::sign_single(&secp, &msg, key, None, Some(&public_key))
+			.map_err(|e| Error::Internal(format!("Unable to sign the snapshot, {}", e)))?;
+
+		Ok(PeerSnapshot {
+			version: PEER_SNAPSHOT_VERSION,
+			created,
+			peers,
+			public_key: public_key.serialize_vec(true).to_hex(),
+			signature: signature.serialize_compact().to_hex(),
+		})
+	}
+
+	/// Verify the snapshot signature. If `trusted_key` is provided, the snapshot must be
+	/// signed with this key.
+	pub fn verify(&self, trusted_key: Option<&str>) -> Result<(), Error> {
+		if self.version != PEER_SNAPSHOT_VERSION {
+			return Err(Error::Internal(format!(
+				"Unsupported peer snapshot version {}",
+				self.version
+			)));
+		}
+		if self.peers.len() > PEER_SNAPSHOT_MAX_PEERS {
+			return Err(Error::Internal(format!(
+				"Peer snapshot has {} peers, the limit is {}",
+				self.peers.len(),
+				PEER_SNAPSHOT_MAX_PEERS
+			)));
+		}
+		if let Some(trusted_key) = trusted_key {
+			if trusted_key != self.public_key {
+				return Err(Error::Internal(format!(
+					"Peer snapshot is signed with {}, expected {}",
+					self.public_key, trusted_key
+				)));
+			}
+		}
+
+		let secp = Secp256k1::with_caps(ContextFlag::VerifyOnly);
+		let public_key = from_hex(&self.public_key)
+			.ok()
+			.and_then(|k| PublicKey::from_slice(&k).ok())
+			.ok_or_else(|| {
+				Error::Internal(format!("Invalid snapshot public key {}", self.public_key))
+			})?;
+		let signature = from_hex(&self.signature)
+			.ok()
+			.and_then(|s| Signature::from_compact(&s).ok())
+			.ok_or_else(|| {
+				Error::Internal(format!("Invalid snapshot signature {}", self.signature))
+			})?;
+		let msg = snapshot_msg(self.version, self.created, &self.peers)?;
+		if !aggsig::verify_single(
+			&secp,
+			&signature,
+			&msg,
+			None,
+			&public_key,
+			Some(&public_key),
+			false,
+		) {
+			return Err(Error::Internal(
+				"Peer snapshot signature is not valid".to_string(),
+			));
+		}
+		Ok(())
+	}
+}
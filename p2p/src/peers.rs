@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::util::RwLock;
-use std::collections::HashMap;
+use crate::util::{clock, Mutex, RwLock};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -21,20 +22,26 @@ use std::sync::Arc;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
+use crate::block_fetch::{BlockFetcher, FetchAttempt, FETCH_MAX_PEERS};
 use crate::chain;
 use crate::core::core;
 use crate::core::core::hash::{Hash, Hashed};
 use crate::core::global;
 use crate::core::pow::Difficulty;
+use crate::local_address;
 use crate::peer::Peer;
-use crate::store::{PeerData, PeerStore, State};
+use crate::peer_snapshot::{PeerSnapshot, PeerSnapshotImport};
+use crate::store::{Libp2pPeerAccess, Libp2pPeerBan, PeerData, PeerStore, PeerSyncRecord, State};
+use crate::sync_data::{PeerSyncData, SyncDataAccounting, SyncStage};
 use crate::types::{
-	Capabilities, ChainAdapter, Error, NetAdapter, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	TxHashSetRead, MAX_PEER_ADDRS,
+	Capabilities, ChainAdapter, Error, HeaderFirstStats, NetAdapter, P2PConfig, PeerAddr, PeerInfo,
+	ReasonForBan, TxHashSetRead, MAX_PEER_ADDRS,
 };
 use chrono::prelude::*;
 use chrono::Duration;
-use grin_util::StopState;
+use grin_util::secp::key::SecretKey;
+use grin_util::{OnionV3Address, StopState};
+use std::convert::TryFrom;
 
 const LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
@@ -44,8 +51,25 @@ pub struct Peers {
 	peers: RwLock<HashMap<PeerAddr, Arc<Peer>>>,
 	config: P2PConfig,
 	stop_state: Arc<StopState>,
+	// Peer requested by the user as the download source for the next sync cycle
+	sync_peer: RwLock<Option<PeerAddr>>,
+	// Recently mined blocks that were announced header first
+	announced_blocks: Mutex<VecDeque<Hash>>,
+	header_first_stats: RwLock<HeaderFirstStats>,
+	// On demand fetches of the pruned blocks
+	block_fetcher: BlockFetcher,
+	// Data accepted by the sync stages from every peer
+	sync_data: SyncDataAccounting,
 }
 
+/// Number of the recently announced blocks we track the body requests for
+const ANNOUNCED_BLOCKS_SIZE: usize = 10;
+
+// Uptime counts for the state sync source score up to a week. The archive bonus
+// is above it, so any archive peer is preferred over a non archive one.
+const SYNC_SOURCE_UPTIME_CAP_SECS: u64 = 7 * 24 * 3600;
+const SYNC_SOURCE_ARCHIVE_SCORE: u64 = SYNC_SOURCE_UPTIME_CAP_SECS + 1;
+
 impl Peers {
 	pub fn new(
 		store: PeerStore,
@@ -53,12 +77,156 @@ impl Peers {
 		config: P2PConfig,
 		stop_state: Arc<StopState>,
 	) -> Peers {
+		let sync_data = SyncDataAccounting::new(config.sync_data_caps());
 		Peers {
 			adapter,
 			store,
 			config,
 			peers: RwLock::new(HashMap::new()),
 			stop_state,
+			sync_peer: RwLock::new(None),
+			announced_blocks: Mutex::new(VecDeque::new()),
+			header_first_stats: RwLock::new(HeaderFirstStats::default()),
+			block_fetcher: BlockFetcher::new(),
+			sync_data,
+		}
+	}
+
+	/// Use the connected peer as the download source for the next header
+	/// and body sync cycle.
+	pub fn set_sync_peer(&self, peer_addr: PeerAddr) -> Result<(), Error> {
+		if self.get_connected_peer(peer_addr.clone()).is_none() {
+			return Err(Error::PeerNotFound);
+		}
+		info!("Peer {} is requested as the next sync source", peer_addr);
+		*self.sync_peer.write() = Some(peer_addr);
+		Ok(())
+	}
+
+	/// Peer requested by `set_sync_peer`, None if it is not set or it is
+	/// not connected any more.
+	pub fn sync_peer(&self) -> Option<Arc<Peer>> {
+		let peer_addr = self.sync_peer.read().clone()?;
+		let peer = self.get_connected_peer(peer_addr);
+		if peer.is_none() {
+			self.clear_sync_peer();
+		}
+		peer
+	}
+
+	/// Done with the requested sync peer, sync will use the most work peers again.
+	pub fn clear_sync_peer(&self) {
+		self.sync_peer.write().take();
+	}
+
+	/// Sync stage accepted the data from the peer
+	pub fn record_sync_data(&self, peer_addr: &PeerAddr, stage: SyncStage, bytes: u64) {
+		self.sync_data.record(peer_addr, stage, bytes);
+	}
+
+	/// Sync sources for the stage. Peers that fed more than the stage cap are dropped
+	/// while there are other sources, so a single peer can't feed the whole sync.
+	pub fn preferred_sync_peers(&self, stage: SyncStage, peers: Vec<Arc<Peer>>) -> Vec<Arc<Peer>> {
+		self.sync_data
+			.preferred(stage, peers, |p| p.info.addr.clone())
+	}
+
+	/// Data accepted by the sync stages from every peer
+	pub fn sync_data(&self) -> Vec<PeerSyncData> {
+		self.sync_data.peers()
+	}
+
+	/// Sync source track record of the peer from the peer store
+	pub fn sync_record(&self, peer_addr: PeerAddr) -> PeerSyncRecord {
+		self.store.get_sync_record(peer_addr).unwrap_or_default()
+	}
+
+	/// Order the initial state sync source candidates. Archive peers are preferred,
+	/// then the peers with the longer measured uptime. Peers that served a corrupt
+	/// txhashset before are excluded. Returns the acceptable peers, the best first,
+	/// and the decision trail.
+	pub fn rank_state_sync_sources(
+		&self,
+		candidates: Vec<Arc<Peer>>,
+	) -> (Vec<Arc<Peer>>, chain::SyncSourceDecision) {
+		let now = clock::now();
+		let mut ranked: Vec<(Arc<Peer>, chain::SyncSourceCandidate)> = candidates
+			.into_iter()
+			.map(|peer| {
+				let record = self.sync_record(peer.info.addr.clone());
+				let session_secs = (now - peer.info.first_seen()).num_seconds().max(0) as u64;
+				let uptime_secs = record.uptime_secs.saturating_add(session_secs);
+				let archive = peer.info.capabilities.contains(Capabilities::ARCHIVE);
+				let excluded = if record.bad_txhashsets > 0 {
+					Some(format!(
+						"served {} corrupt txhashset(s), last at {}",
+						record.bad_txhashsets,
+						Utc.timestamp(record.last_bad_txhashset, 0)
+					))
+				} else {
+					None
+				};
+				let mut score = uptime_secs.min(SYNC_SOURCE_UPTIME_CAP_SECS);
+				if archive {
+					score += SYNC_SOURCE_ARCHIVE_SCORE;
+				}
+				let candidate = chain::SyncSourceCandidate {
+					addr: peer.info.addr.to_string(),
+					archive,
+					uptime_secs,
+					bad_txhashsets: record.bad_txhashsets,
+					score,
+					excluded,
+				};
+				(peer, candidate)
+			})
+			.collect();
+
+		// stable sort, the peers with the same score stay in the candidates order
+		ranked.sort_by(|(_, a), (_, b)| {
+			a.excluded
+				.is_some()
+				.cmp(&b.excluded.is_some())
+				.then(b.score.cmp(&a.score))
+		});
+
+		let peers: Vec<Arc<Peer>> = ranked
+			.iter()
+			.filter(|(_, c)| c.excluded.is_none())
+			.map(|(p, _)| p.clone())
+			.collect();
+		let decision = chain::SyncSourceDecision {
+			selected: peers.first().map(|p| p.info.addr.to_string()),
+			candidates: ranked.into_iter().map(|(_, c)| c).collect(),
+			time: now,
+		};
+		(peers, decision)
+	}
+
+	/// Remember that the peer served a corrupt txhashset, it won't be selected
+	/// as the state sync source again.
+	pub fn record_bad_txhashset(&self, peer_addr: PeerAddr) -> Result<(), Error> {
+		let mut record = self.sync_record(peer_addr.clone());
+		record.bad_txhashsets = record.bad_txhashsets.saturating_add(1);
+		record.last_bad_txhashset = clock::now().timestamp();
+		self.store
+			.save_sync_record(peer_addr, &record)
+			.map_err(From::from)
+	}
+
+	// Add the time of the finished connection to the peer uptime
+	fn record_session_uptime(&self, peer: &Peer) {
+		let session_secs = (clock::now() - peer.info.first_seen()).num_seconds();
+		if session_secs <= 0 {
+			return;
+		}
+		let mut record = self.sync_record(peer.info.addr.clone());
+		record.uptime_secs = record.uptime_secs.saturating_add(session_secs as u64);
+		if let Err(e) = self.store.save_sync_record(peer.info.addr.clone(), &record) {
+			warn!(
+				"Unable to save the uptime of peer {}, {}",
+				peer.info.addr, e
+			);
 		}
 	}
 
@@ -80,7 +248,7 @@ impl Peers {
 			flags: State::Healthy,
 			last_banned: 0,
 			ban_reason: ReasonForBan::None,
-			last_connected: Utc::now().timestamp(),
+			last_connected: clock::now().timestamp(),
 		};
 		debug!("Saving newly connected peer {}.", peer_data.addr);
 		self.save_peer(&peer_data)?;
@@ -97,9 +265,9 @@ impl Peers {
 			capabilities: Capabilities::UNKNOWN,
 			user_agent: "".to_string(),
 			flags: State::Banned,
-			last_banned: Utc::now().timestamp(),
+			last_banned: clock::now().timestamp(),
 			ban_reason,
-			last_connected: Utc::now().timestamp(),
+			last_connected: clock::now().timestamp(),
 		};
 		debug!("Banning peer {}, ban_reason={:?}", addr, ban_reason);
 		self.save_peer(&peer_data)
@@ -262,6 +430,87 @@ impl Peers {
 		self.most_work_peers().pop()
 	}
 
+	/// Peers with more work than us that serve the block bodies, headers only
+	/// relay peers are skipped. Used as the body sync sources.
+	pub fn more_work_body_peers(&self) -> Result<Vec<Arc<Peer>>, chain::Error> {
+		Ok(self
+			.more_work_peers()?
+			.into_iter()
+			.filter(|p| p.info.serves_bodies())
+			.collect())
+	}
+
+	/// Peers with the most work among the peers that serve the block bodies and the
+	/// TxHashSet, in random order. Used as the state sync sources.
+	pub fn most_work_body_peers(&self) -> Vec<Arc<Peer>> {
+		let peers = self
+			.connected_peers()
+			.into_iter()
+			.filter(|p| p.info.serves_bodies())
+			.collect::<Vec<_>>();
+		let max_total_difficulty = match peers.iter().map(|p| p.info.total_difficulty()).max() {
+			Some(v) => v,
+			None => return vec![],
+		};
+		let mut max_peers = peers
+			.into_iter()
+			.filter(|p| p.info.total_difficulty() == max_total_difficulty)
+			.collect::<Vec<_>>();
+		max_peers.shuffle(&mut thread_rng());
+		max_peers
+	}
+
+	/// Single random peer with the most work among the peers that serve the block
+	/// bodies and the TxHashSet.
+	pub fn most_work_body_peer(&self) -> Option<Arc<Peer>> {
+		self.most_work_body_peers().pop()
+	}
+
+	/// Fetch the block that we don't store any more (pruned) from the peers. The peers
+	/// at the block height or above are asked one by one, the received block must pass
+	/// the verification against our header chain. Peers that send an invalid block are banned.
+	pub fn fetch_block<F>(&self, h: Hash, height: u64, verify: F) -> Result<core::Block, Error>
+	where
+		F: Fn(&core::Block) -> Result<(), chain::Error>,
+	{
+		if let Some(b) = self.block_fetcher.cached(&h) {
+			return Ok(b);
+		}
+		if !self.config.fetch_pruned_blocks() {
+			return Err(Error::Internal(
+				"fetch of the pruned blocks is disabled".to_string(),
+			));
+		}
+		let peers = self
+			.connected_peers()
+			.into_iter()
+			.filter(|p| p.info.height() >= height && p.info.serves_bodies())
+			.take(FETCH_MAX_PEERS)
+			.collect::<Vec<_>>();
+		if peers.is_empty() {
+			return Err(Error::PeerNotFound);
+		}
+		for peer in peers {
+			match self.block_fetcher.fetch_from_peer(h, &peer, &verify) {
+				FetchAttempt::Verified(b) => {
+					debug!("Fetched pruned block {} from {}", h, peer.info.addr);
+					self.block_fetcher.add_to_cache(b.clone());
+					return Ok(b);
+				}
+				FetchAttempt::Invalid(addr, e) => {
+					warn!("Fetched block {} from {} is invalid, {}", h, addr, e);
+					if let Err(e) = self.ban_peer(addr.clone(), ReasonForBan::BadBlock) {
+						debug!("Unable to ban peer {}, {}", addr, e);
+					}
+				}
+				FetchAttempt::NoResponse => {
+					debug!("Peer {} didn't send the block {}", peer.info.addr, h);
+				}
+			}
+		}
+		Err(Error::Timeout)
+	}
+
 	pub fn is_banned(&self, peer_addr: PeerAddr) -> bool {
 		if let Ok(peer) = self.store.get_peer(peer_addr) {
 			return peer.flags == State::Banned;
@@ -279,11 +528,16 @@ impl Peers {
 				peer.send_ban_reason(ban_reason)?;
 				peer.set_banned();
 				peer.stop();
-				let mut peers = self.peers.try_write_for(LOCK_TIMEOUT).ok_or_else(|| {
-					error!("ban_peer: failed to get peers lock");
-					Error::PeerException("ban_peer: failed to get peers lock".to_string())
-				})?;
-				peers.remove(&peer.info.addr);
+				let removed = {
+					let mut peers = self.peers.try_write_for(LOCK_TIMEOUT).ok_or_else(|| {
+						error!("ban_peer: failed to get peers lock");
+						Error::PeerException("ban_peer: failed to get peers lock".to_string())
+					})?;
+					peers.remove(&peer.info.addr)
+				};
+				if let Some(removed) = removed {
+					self.record_session_uptime(&removed);
+				}
 				Ok(())
 			}
 			None => Err(Error::PeerNotFound),
@@ -335,17 +589,44 @@ impl Peers {
 
 	/// Broadcast a compact block to all our connected peers.
 	/// This is only used when initially broadcasting a newly mined block.
+	/// Peers with the header first capability get the header only, the others
+	/// get the compact block.
 	pub fn broadcast_compact_block(&self, b: &core::CompactBlock) {
-		let count = self.broadcast("compact block", |p| p.send_compact_block(b));
+		{
+			let mut announced = self.announced_blocks.lock();
+			announced.push_front(b.hash());
+			announced.truncate(ANNOUNCED_BLOCKS_SIZE);
+		}
+		let headers = Cell::new(0);
+		let count = self.broadcast("compact block", |p| {
+			let res = p.announce_block(b);
+			if let Ok(true) = res {
+				if p.announces_header() {
+					headers.set(headers.get() + 1);
+				}
+			}
+			res
+		});
+		{
+			let mut stats = self.header_first_stats.write();
+			stats.headers_announced += headers.get() as u64;
+			stats.compact_blocks_pushed += (count - headers.get()) as u64;
+		}
 		debug!(
-			"broadcast_compact_block: {}, {} at {}, to {} peers, done.",
+			"broadcast_compact_block: {}, {} at {}, to {} peers ({} header first), done.",
 			b.hash(),
 			b.header.pow.total_difficulty,
 			b.header.height,
 			count,
+			headers.get(),
 		);
 	}
 
+	/// Statistics of the header first announcements of the mined blocks
+	pub fn header_first_stats(&self) -> HeaderFirstStats {
+		self.header_first_stats.read().clone()
+	}
+
 	/// Broadcast a block header to all our connected peers.
 	/// A peer implementation may drop the broadcast request
 	/// if it knows the remote peer already has the header.
@@ -405,7 +686,11 @@ impl Peers {
 	/// Find peers in store (not necessarily connected) and return their data
 	pub fn find_peers(&self, state: State, cap: Capabilities, count: usize) -> Vec<PeerData> {
 		match self.store.find_peers(state, cap, count) {
-			Ok(peers) => peers,
+			// Peers that don't advertise the address can't be dialed or shared
+			Ok(peers) => peers
+				.into_iter()
+				.filter(|p| local_address::is_advertised(&p.addr))
+				.collect(),
 			Err(e) => {
 				error!("failed to find peers: {:?}", e);
 				vec![]
@@ -435,6 +720,211 @@ impl Peers {
 			.map_err(From::from)
 	}
 
+	/// Export the known peers as a signed snapshot. The snapshot key is generated
+	/// on the first export and kept in the peer store.
+	pub fn export_snapshot(&self) -> Result<PeerSnapshot, Error> {
+		let key = match self.store.get_snapshot_key()? {
+			Some(key) => SecretKey::from_slice(&key)
+				.map_err(|e| Error::Internal(format!("Invalid stored snapshot key, {}", e)))?,
+			None => {
+				let key = SecretKey::new(&mut thread_rng());
+				self.store.save_snapshot_key(&key.as_ref())?;
+				key
+			}
+		};
+		PeerSnapshot::create(self.store.all_peers()?, &key)
+	}
+
+	/// Import the peers from the verified snapshot. Peers we already know are not
+	/// updated, our own experience with them is preferred.
+	pub fn import_snapshot(
+		&self,
+		snapshot: &PeerSnapshot,
+		trusted_key: Option<&str>,
+	) -> Result<PeerSnapshotImport, Error> {
+		snapshot.verify(trusted_key)?;
+		let res = self.import_peers(&snapshot.peers)?;
+		info!(
+			"Imported {} peers from the snapshot signed by {}, {} skipped",
+			res.imported, snapshot.public_key, res.skipped
+		);
+		Ok(res)
+	}
+
+	/// Add the peers to the peer store. Peers that are already known and the defunct
+	/// peers are skipped.
+	pub fn import_peers(&self, peers: &[PeerData]) -> Result<PeerSnapshotImport, Error> {
+		let mut res = PeerSnapshotImport {
+			imported: 0,
+			skipped: 0,
+		};
+		for peer in peers {
+			if peer.flags == State::Defunct || self.store.exists_peer(peer.addr.clone())? {
+				res.skipped += 1;
+				continue;
+			}
+			self.store.save_peer(peer)?;
+			res.imported += 1;
+		}
+		Ok(res)
+	}
+
+	/// Explicit libp2p access rules for the onion peers
+	pub fn libp2p_peer_access(&self) -> Result<Vec<Libp2pPeerAccess>, Error> {
+		self.store.all_libp2p_access().map_err(From::from)
+	}
+
+	/// Allow or deny libp2p dialing and inbound connections for the onion address.
+	/// The rule is persistent and applied to the running libp2p node immediately.
+	pub fn set_libp2p_peer_access(&self, address: &str, allowed: bool) -> Result<(), Error> {
+		self.save_libp2p_peer_access(address, allowed)?;
+		self.apply_libp2p_peer_access()
+	}
+
+	/// Remove the libp2p access rule for the onion address
+	pub fn remove_libp2p_peer_access(&self, address: &str) -> Result<(), Error> {
+		self.store
+			.delete_libp2p_access(&Libp2pPeerAccess::normalize_address(address))?;
+		self.apply_libp2p_peer_access()
+	}
+
+	/// Add the access rules from the config to the stored ones and apply them to the libp2p node
+	pub fn init_libp2p_peer_access(
+		&self,
+		allow_list: &Vec<String>,
+		deny_list: &Vec<String>,
+	) -> Result<(), Error> {
+		for addr in allow_list {
+			self.save_libp2p_peer_access(addr, true)?;
+		}
+		for addr in deny_list {
+			self.save_libp2p_peer_access(addr, false)?;
+		}
+		self.apply_libp2p_peer_access()
+	}
+
+	fn save_libp2p_peer_access(&self, address: &str, allowed: bool) -> Result<(), Error> {
+		let access = Libp2pPeerAccess::new(address, allowed);
+		OnionV3Address::try_from(access.address.as_str())
+			.map_err(|e| Error::Libp2pError(format!("Invalid onion address {}, {}", address, e)))?;
+		self.store.save_libp2p_access(&access).map_err(From::from)
+	}
+
+	/// Check the libp2p identity against the one used by the previous run. If the onion
+	/// key was rotated or restored, the old identity is forgotten and the new one is
+	/// announced to the network. Returns true if the identity is changed.
+	pub fn check_libp2p_identity(&self, onion_address: &str) -> Result<bool, Error> {
+		let onion_address = Libp2pPeerAccess::normalize_address(onion_address);
+		let prev_address = self.store.get_libp2p_identity()?;
+		if prev_address.as_ref() == Some(&onion_address) {
+			return Ok(false);
+		}
+		self.store.save_libp2p_identity(&onion_address)?;
+		let prev_address = match prev_address {
+			Some(prev_address) => prev_address,
+			None => return Ok(false),
+		};
+		warn!(
+			"libp2p identity is changed from {} to {}, the old identity is dropped",
+			prev_address, onion_address
+		);
+		// Our old address might be learned from the peers, we don't want to dial it
+		for addr in &[prev_address.clone(), format!("{}.onion", prev_address)] {
+			let _ = self.store.delete_peer(PeerAddr::Onion(addr.clone()));
+		}
+		#[cfg(feature = "marketplace")]
+		crate::libp2p_connection::forget_libp2p_identity(&prev_address);
+		Ok(true)
+	}
+
+	fn apply_libp2p_peer_access(&self) -> Result<(), Error> {
+		#[cfg(feature = "marketplace")]
+		crate::libp2p_connection::set_peer_access(&self.store.all_libp2p_access()?);
+		Ok(())
+	}
+
+	/// Active bans of the libp2p peers
+	pub fn libp2p_bans(&self) -> Result<Vec<Libp2pPeerBan>, Error> {
+		let now = clock::now().timestamp();
+		Ok(self
+			.store
+			.all_libp2p_bans()?
+			.into_iter()
+			.filter(|b| !b.is_expired(now))
+			.collect())
+	}
+
+	/// Ban the libp2p peer by the onion address, disconnecting it if it is connected.
+	/// The ban is persistent. Without the duration the ban lasts for the ban window of
+	/// the p2p peers, zero or negative duration bans the peer permanently.
+	pub fn ban_libp2p_peer(
+		&self,
+		address: &str,
+		reason: ReasonForBan,
+		duration_secs: Option<i64>,
+	) -> Result<Libp2pPeerBan, Error> {
+		let ban = self.save_libp2p_ban(address, reason, duration_secs)?;
+		info!(
+			"Banning libp2p peer {}, ban_reason {:?}, expires at {:?}",
+			ban.address, ban.reason, ban.expires_at
+		);
+		self.apply_libp2p_bans()?;
+		Ok(ban)
+	}
+
+	/// Unban the libp2p peer by the onion address
+	pub fn unban_libp2p_peer(&self, address: &str) -> Result<(), Error> {
+		let address = Libp2pPeerAccess::normalize_address(address);
+		if !self.libp2p_bans()?.iter().any(|b| b.address == address) {
+			return Err(Error::PeerNotBanned);
+		}
+		info!("Unbanning libp2p peer {}", address);
+		self.store.delete_libp2p_ban(&address)?;
+		self.apply_libp2p_bans()
+	}
+
+	/// Save the bans that the libp2p node made for the misbehaviour, drop the expired
+	/// bans and apply the result to the libp2p node. Called on start and by the peers
+	/// monitoring, the same way the p2p peers are unbanned after the ban window.
+	pub fn update_libp2p_bans(&self) -> Result<(), Error> {
+		#[cfg(feature = "marketplace")]
+		for (address, reason) in crate::libp2p_connection::take_pending_bans() {
+			self.save_libp2p_ban(&address, reason, None)?;
+		}
+		let now = clock::now().timestamp();
+		for ban in self.store.all_libp2p_bans()? {
+			if ban.is_expired(now) {
+				debug!("libp2p peer {} ban is expired", ban.address);
+				self.store.delete_libp2p_ban(&ban.address)?;
+			}
+		}
+		self.apply_libp2p_bans()
+	}
+
+	fn save_libp2p_ban(
+		&self,
+		address: &str,
+		reason: ReasonForBan,
+		duration_secs: Option<i64>,
+	) -> Result<Libp2pPeerBan, Error> {
+		let now = clock::now().timestamp();
+		let expires_at = match duration_secs.unwrap_or(self.config.ban_window()) {
+			d if d > 0 => Some(now + d),
+			_ => None,
+		};
+		let ban = Libp2pPeerBan::new(address, reason, now, expires_at);
+		OnionV3Address::try_from(ban.address.as_str())
+			.map_err(|e| Error::Libp2pError(format!("Invalid onion address {}, {}", address, e)))?;
+		self.store.save_libp2p_ban(&ban)?;
+		Ok(ban)
+	}
+
+	fn apply_libp2p_bans(&self) -> Result<(), Error> {
+		#[cfg(feature = "marketplace")]
+		crate::libp2p_connection::set_peer_bans(&self.libp2p_bans()?);
+		Ok(())
+	}
+
 	/// Iterate over the peer list and prune all peers we have
 	/// lost connection to or have been deemed problematic.
 	/// Also avoid connected peer count getting too high.
@@ -471,6 +961,9 @@ impl Peers {
 					}
 					let _ = self.update_state(peer.info.addr.clone(), State::Banned);
 					rm.push(peer.info.addr.clone());
+				} else if let Some(reason) = peer.slow_peer_reason() {
+					debug!("clean_peers {:?}, slow peer, {:?}", peer.info.addr, reason);
+					rm.push(peer.info.addr.clone());
 				} else {
 					let (stuck, diff) = peer.is_stuck();
 					match self.adapter.total_difficulty() {
@@ -516,6 +1009,7 @@ impl Peers {
 		}
 
 		// now clean up peer map based on the list to remove
+		let mut removed = vec![];
 		{
 			let mut peers = match self.peers.try_write_for(LOCK_TIMEOUT) {
 				Some(peers) => peers,
@@ -526,9 +1020,14 @@ impl Peers {
 			};
 			for addr in rm {
 				let _ = peers.get(&addr).map(|peer| peer.stop());
-				peers.remove(&addr);
+				if let Some(peer) = peers.remove(&addr) {
+					removed.push(peer);
+				}
 			}
 		}
+		for peer in removed {
+			self.record_session_uptime(&peer);
+		}
 	}
 
 	pub fn stop(&self) {
@@ -538,6 +1037,7 @@ impl Peers {
 		}
 		for (_, peer) in peers.drain() {
 			peer.wait();
+			self.record_session_uptime(&peer);
 		}
 	}
 
@@ -548,7 +1048,7 @@ impl Peers {
 
 	/// Removes those peers that seem to have expired
 	pub fn remove_expired(&self) {
-		let now = Utc::now();
+		let now = clock::now();
 
 		// Delete defunct peers from storage
 		let _ = self.store.delete_peers(|peer| {
@@ -607,6 +1107,10 @@ impl ChainAdapter for Peers {
 		peer_info: &PeerInfo,
 		opts: chain::Options,
 	) -> Result<bool, chain::Error> {
+		// Pruned block requested by the API, the chain doesn't need it
+		if self.block_fetcher.block_received(&b, &peer_info.addr) {
+			return Ok(true);
+		}
 		let hash = b.hash();
 		if !self.adapter.block_received(b, peer_info, opts)? {
 			// if the peer sent us a block that's intrinsically bad
@@ -696,6 +1200,9 @@ impl ChainAdapter for Peers {
 	}
 
 	fn get_block(&self, h: Hash, peer_info: &PeerInfo) -> Option<core::Block> {
+		if self.announced_blocks.lock().contains(&h) {
+			self.header_first_stats.write().bodies_requested += 1;
+		}
 		self.adapter.get_block(h, peer_info)
 	}
 
@@ -722,6 +1229,12 @@ impl ChainAdapter for Peers {
 				"Received a bad txhashset data from {}, the peer will be banned",
 				peer_info.addr.clone()
 			);
+			if let Err(e) = self.record_bad_txhashset(peer_info.addr.clone()) {
+				warn!(
+					"Unable to record the bad txhashset of {}, {}",
+					peer_info.addr, e
+				);
+			}
 			self.ban_peer(peer_info.addr.clone(), ReasonForBan::BadTxHashSet)
 				.map_err(|e| chain::ErrorKind::Other(format!("ban peer error {}", e)))?;
 			Ok(true)
@@ -774,7 +1287,7 @@ impl NetAdapter for Peers {
 				flags: State::Healthy,
 				last_banned: 0,
 				ban_reason: ReasonForBan::None,
-				last_connected: Utc::now().timestamp(),
+				last_connected: clock::now().timestamp(),
 			};
 			if let Err(e) = self.save_peer(&peer) {
 				error!("Could not save received peer address: {:?}", e);
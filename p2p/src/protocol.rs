@@ -16,6 +16,7 @@ use crate::chain;
 use crate::conn::{Message, MessageHandler, Tracker};
 use crate::core::core::{self, hash::Hash, hash::Hashed, CompactBlock};
 use crate::serv::Server;
+use crate::sync_data::SyncStage;
 use crate::types::PeerAddr::Onion;
 
 use crate::msg::{
@@ -103,6 +104,7 @@ impl MessageHandler for Protocol {
 
 			Type::Pong => {
 				let pong: Pong = msg.body()?;
+				tracker.conn_stats.pong_received(Instant::now());
 				adapter.peer_difficulty(
 					self.peer_info.addr.clone(),
 					pong.total_difficulty,
@@ -185,13 +187,21 @@ impl MessageHandler for Protocol {
 					"handle_payload: received block: msg_len: {}",
 					msg.header.msg_len
 				);
+				let deserialize_start = Instant::now();
 				let b: core::UntrustedBlock = msg.body()?;
+				let b: core::Block = b.into();
+				chain::block_profile::record_deserialize(b.hash(), deserialize_start.elapsed());
 
 				// We default to NONE opts here as we do not know know yet why this block was
 				// received.
 				// If we requested this block from a peer due to our node syncing then
 				// the peer adapter will override opts to reflect this.
-				adapter.block_received(b.into(), &self.peer_info, chain::Options::NONE)?;
+				adapter.block_received(b, &self.peer_info, chain::Options::NONE)?;
+				self.server.peers.record_sync_data(
+					&self.peer_info.addr,
+					SyncStage::Blocks,
+					msg.header.msg_len,
+				);
 				Ok(None)
 			}
 
@@ -217,6 +227,11 @@ impl MessageHandler for Protocol {
 				let b: core::UntrustedCompactBlock = msg.body()?;
 
 				adapter.compact_block_received(b.into(), &self.peer_info)?;
+				self.server.peers.record_sync_data(
+					&self.peer_info.addr,
+					SyncStage::Blocks,
+					msg.header.msg_len,
+				);
 				Ok(None)
 			}
 			Type::TorAddress => {
@@ -288,6 +303,11 @@ impl MessageHandler for Protocol {
 				if total_bytes_read != msg.header.msg_len {
 					return Err(Error::MsgLen);
 				}
+				self.server.peers.record_sync_data(
+					&self.peer_info.addr,
+					SyncStage::Headers,
+					total_bytes_read,
+				);
 
 				Ok(None)
 			}
@@ -467,6 +487,11 @@ impl MessageHandler for Protocol {
 				let res = self
 					.adapter
 					.txhashset_write(sm_arch.hash, tmp_zip, &self.peer_info)?;
+				self.server.peers.record_sync_data(
+					&self.peer_info.addr,
+					SyncStage::TxHashSet,
+					sm_arch.bytes,
+				);
 
 				info!(
 					"handle_payload: txhashset archive for {} at {}, DONE. Data Ok: {}",
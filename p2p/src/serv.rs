@@ -15,8 +15,9 @@
 use crate::types::PeerAddr::Onion;
 use std::fs::File;
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpStream};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
@@ -26,7 +27,9 @@ use crate::core::core;
 use crate::core::core::hash::Hash;
 use crate::core::global;
 use crate::core::pow::Difficulty;
+use crate::handoff;
 use crate::handshake::Handshake;
+use crate::local_address;
 use crate::peer::Peer;
 use crate::peers::Peers;
 use crate::store::PeerStore;
@@ -37,6 +40,15 @@ use crate::types::{
 use crate::util::StopState;
 use chrono::prelude::{DateTime, Utc};
 
+// Number of the inbound connections accepted from the loopback interface
+static LOOPBACK_INBOUND: AtomicU64 = AtomicU64::new(0);
+
+/// Number of the inbound connections accepted from the loopback interface since the
+/// start. With Tor listener those are the onion service connections.
+pub fn loopback_inbound_count() -> u64 {
+	LOOPBACK_INBOUND.load(Ordering::Relaxed)
+}
+
 /// P2P server implementation, handling bootstrapping to find and connect to
 /// peers, receiving connections from other peers and keep track of all of them.
 #[derive(Clone)]
@@ -63,6 +75,14 @@ impl Server {
 		socks_port: u16,
 		onion_address: Option<String>,
 	) -> Result<Server, Error> {
+		#[cfg(feature = "msg_capture")]
+		crate::capture::init_capture_dir(config.capture_dir.clone());
+		#[cfg(not(feature = "msg_capture"))]
+		{
+			if config.capture_dir.is_some() {
+				warn!("capture_dir is ignored, the node is built without the msg_capture feature");
+			}
+		}
 		Ok(Server {
 			config: config.clone(),
 			capabilities: capab,
@@ -88,11 +108,17 @@ impl Server {
 	pub fn listen(&self, header_cache_size: u64) -> Result<(), Error> {
 		// start TCP listener and handle incoming connections
 		let addr = SocketAddr::new(self.config.host, self.config.port);
-		let listener = TcpListener::bind(addr)?;
+		let listener = handoff::bind_listener(addr)?;
 		listener.set_nonblocking(true)?;
 
 		let sleep_time = Duration::from_millis(5);
 		loop {
+			// New node took over the listener
+			if handoff::is_handed_off() {
+				info!("P2P listener is handed off to the new node");
+				break;
+			}
+
 			// Pause peer ingress connection request. Only for tests.
 			if self.stop_state.is_paused() {
 				thread::sleep(Duration::from_secs(1));
@@ -109,6 +135,11 @@ impl Server {
 					// we do not want.
 					stream.set_nonblocking(false)?;
 
+					// Tor forwards the onion service connections from the loopback
+					if peer_addr.ip().is_loopback() {
+						LOOPBACK_INBOUND.fetch_add(1, Ordering::Relaxed);
+					}
+
 					let mut peer_addr = PeerAddr::Ip(peer_addr);
 
 					// attempt to see if it an ipv4-mapped ipv6
@@ -213,8 +244,8 @@ impl Server {
 
 		let stream = match addr.clone() {
 			PeerAddr::Ip(address) => {
-				// we do this, not a good solution, but for now, we'll use it. Other side usually detects with ip.
-				self_addr = PeerAddr::Ip(SocketAddr::new(self.config.host, self.config.port));
+				// Other side usually detects the ip from the connection, the port is what matters
+				self_addr = local_address::advertised_address(&self.config);
 				if self.socks_port != 0 {
 					peer_addr = Some(PeerAddr::Ip(address));
 					let proxy_addr =
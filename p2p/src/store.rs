@@ -14,19 +14,24 @@
 
 //! Storage implementation for peer data.
 
-use chrono::Utc;
 use num::FromPrimitive;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
 use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
 use crate::types::{Capabilities, PeerAddr, ReasonForBan};
+use crate::util::clock;
 use grin_store::{self, option_to_not_found, to_key, Error};
 
 const DB_NAME: &str = "peerV2";
 const STORE_SUBPATH: &str = "peers";
 
 const PEER_PREFIX: u8 = b'P';
+const LIBP2P_ACCESS_PREFIX: u8 = b'L';
+const LIBP2P_IDENTITY_PREFIX: u8 = b'I';
+const SNAPSHOT_KEY_PREFIX: u8 = b'K';
+const SYNC_RECORD_PREFIX: u8 = b'S';
+const LIBP2P_BAN_PREFIX: u8 = b'B';
 
 // Types of messages
 enum_from_primitive! {
@@ -91,7 +96,7 @@ impl Readable for PeerData {
 		// this only works because each PeerData is read in its own vector and this
 		// is the last data element
 		let last_connected = match lc {
-			Err(_) => Utc::now().timestamp(),
+			Err(_) => clock::now().timestamp(),
 			Ok(lc) => lc,
 		};
 
@@ -119,6 +124,160 @@ impl Readable for PeerData {
 	}
 }
 
+/// Explicit libp2p access rule for the onion address.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Libp2pPeerAccess {
+	/// Onion address of the peer, without '.onion' suffix
+	pub address: String,
+	/// true if the peer is allowed, false if it is denied
+	pub allowed: bool,
+}
+
+impl Libp2pPeerAccess {
+	/// Create the access rule, the address is normalized
+	pub fn new(address: &str, allowed: bool) -> Libp2pPeerAccess {
+		Libp2pPeerAccess {
+			address: Libp2pPeerAccess::normalize_address(address),
+			allowed,
+		}
+	}
+
+	/// Onion address in the form that is used for the access rules: lower case, no '.onion' suffix
+	pub fn normalize_address(address: &str) -> String {
+		let address = address.trim().to_lowercase();
+		address
+			.strip_suffix(".onion")
+			.unwrap_or(&address)
+			.to_string()
+	}
+}
+
+impl Writeable for Libp2pPeerAccess {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.address)?;
+		writer.write_u8(self.allowed as u8)
+	}
+}
+
+impl Readable for Libp2pPeerAccess {
+	fn read<R: Reader>(reader: &mut R) -> Result<Libp2pPeerAccess, ser::Error> {
+		let address = String::from_utf8(reader.read_bytes_len_prefix()?).map_err(|e| {
+			ser::Error::CorruptedData(format!("Fail to read libp2p peer address, {}", e))
+		})?;
+		let allowed = reader.read_u8()? != 0;
+		Ok(Libp2pPeerAccess { address, allowed })
+	}
+}
+
+/// Ban of the libp2p peer by the onion address. Gossip peers are not in the peers
+/// table, so their bans are kept apart from PeerData.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Libp2pPeerBan {
+	/// Onion address of the peer, without '.onion' suffix
+	pub address: String,
+	/// Why the peer is banned
+	pub reason: ReasonForBan,
+	/// When the peer was banned, timestamp
+	pub banned_at: i64,
+	/// When the ban expires, timestamp. None if the ban is permanent
+	pub expires_at: Option<i64>,
+}
+
+impl Libp2pPeerBan {
+	/// Create the ban, the address is normalized
+	pub fn new(
+		address: &str,
+		reason: ReasonForBan,
+		banned_at: i64,
+		expires_at: Option<i64>,
+	) -> Libp2pPeerBan {
+		Libp2pPeerBan {
+			address: Libp2pPeerAccess::normalize_address(address),
+			reason,
+			banned_at,
+			expires_at,
+		}
+	}
+
+	/// Check if the ban is expired at the time
+	pub fn is_expired(&self, now: i64) -> bool {
+		match self.expires_at {
+			Some(expires_at) => expires_at <= now,
+			None => false,
+		}
+	}
+}
+
+impl Writeable for Libp2pPeerBan {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.address)?;
+		writer.write_i32(self.reason as i32)?;
+		writer.write_i64(self.banned_at)?;
+		// 0 is the permanent ban
+		writer.write_i64(self.expires_at.unwrap_or(0))
+	}
+}
+
+impl Readable for Libp2pPeerBan {
+	fn read<R: Reader>(reader: &mut R) -> Result<Libp2pPeerBan, ser::Error> {
+		let address = String::from_utf8(reader.read_bytes_len_prefix()?).map_err(|e| {
+			ser::Error::CorruptedData(format!("Fail to read libp2p peer address, {}", e))
+		})?;
+		let br = reader.read_i32()?;
+		let reason = ReasonForBan::from_i32(br).ok_or(ser::Error::CorruptedData(format!(
+			"Unable to read libp2p ban reason {}",
+			br
+		)))?;
+		let banned_at = reader.read_i64()?;
+		let expires_at = match reader.read_i64()? {
+			0 => None,
+			t => Some(t),
+		};
+		Ok(Libp2pPeerBan {
+			address,
+			reason,
+			banned_at,
+			expires_at,
+		})
+	}
+}
+
+/// Track record of the peer as a sync source. Kept apart from PeerData, so it
+/// survives the peer data resets on every new connection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PeerSyncRecord {
+	/// Total time we were connected to the peer, in seconds
+	pub uptime_secs: u64,
+	/// Number of the corrupt txhashsets the peer served
+	pub bad_txhashsets: u32,
+	/// Time the peer served the last corrupt txhashset
+	pub last_bad_txhashset: i64,
+}
+
+impl Writeable for PeerSyncRecord {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		ser_multiwrite!(
+			writer,
+			[write_u64, self.uptime_secs],
+			[write_u32, self.bad_txhashsets],
+			[write_i64, self.last_bad_txhashset]
+		);
+		Ok(())
+	}
+}
+
+impl Readable for PeerSyncRecord {
+	fn read<R: Reader>(reader: &mut R) -> Result<PeerSyncRecord, ser::Error> {
+		let (uptime_secs, bad_txhashsets, last_bad_txhashset) =
+			ser_multiread!(reader, read_u64, read_u32, read_i64);
+		Ok(PeerSyncRecord {
+			uptime_secs,
+			bad_txhashsets,
+			last_bad_txhashset,
+		})
+	}
+}
+
 /// Storage facility for peer data.
 pub struct PeerStore {
 	db: grin_store::Store,
@@ -195,7 +354,7 @@ impl PeerStore {
 		)?;
 		peer.flags = new_state;
 		if new_state == State::Banned {
-			peer.last_banned = Utc::now().timestamp();
+			peer.last_banned = clock::now().timestamp();
 		}
 
 		batch.put_ser(&peer_key(peer_addr)[..], &peer)?;
@@ -228,9 +387,106 @@ impl PeerStore {
 
 		Ok(())
 	}
+
+	/// Save the libp2p access rule for the onion address
+	pub fn save_libp2p_access(&self, access: &Libp2pPeerAccess) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.put_ser(&to_key(LIBP2P_ACCESS_PREFIX, &access.address)[..], access)?;
+		batch.commit()
+	}
+
+	/// Delete the libp2p access rule for the onion address
+	pub fn delete_libp2p_access(&self, address: &str) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.delete(&to_key(LIBP2P_ACCESS_PREFIX, address)[..])?;
+		batch.commit()
+	}
+
+	/// Save the libp2p peer ban, the previous ban of the address is replaced
+	pub fn save_libp2p_ban(&self, ban: &Libp2pPeerBan) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.put_ser(&to_key(LIBP2P_BAN_PREFIX, &ban.address)[..], ban)?;
+		batch.commit()
+	}
+
+	/// Delete the libp2p peer ban for the onion address
+	pub fn delete_libp2p_ban(&self, address: &str) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.delete(&to_key(LIBP2P_BAN_PREFIX, address)[..])?;
+		batch.commit()
+	}
+
+	/// Onion address of this node libp2p identity that was used by the last run
+	pub fn get_libp2p_identity(&self) -> Result<Option<String>, Error> {
+		self.db.get_with(&[LIBP2P_IDENTITY_PREFIX], |v| {
+			String::from_utf8_lossy(v).to_string()
+		})
+	}
+
+	/// Save the onion address of this node libp2p identity
+	pub fn save_libp2p_identity(&self, onion_address: &str) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.put(&[LIBP2P_IDENTITY_PREFIX], onion_address.as_bytes())?;
+		batch.commit()
+	}
+
+	/// Secret key the peer snapshots of this node are signed with
+	pub fn get_snapshot_key(&self) -> Result<Option<Vec<u8>>, Error> {
+		self.db.get_with(&[SNAPSHOT_KEY_PREFIX], |v| v.to_vec())
+	}
+
+	/// Save the peer snapshots signing key
+	pub fn save_snapshot_key(&self, key: &[u8]) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.put(&[SNAPSHOT_KEY_PREFIX], key)?;
+		batch.commit()
+	}
+
+	/// Sync source track record of the peer, default if there is none yet
+	pub fn get_sync_record(&self, peer_addr: PeerAddr) -> Result<PeerSyncRecord, Error> {
+		Ok(self
+			.db
+			.get_ser(&sync_record_key(peer_addr)[..])?
+			.unwrap_or_default())
+	}
+
+	/// Save the sync source track record of the peer
+	pub fn save_sync_record(
+		&self,
+		peer_addr: PeerAddr,
+		record: &PeerSyncRecord,
+	) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.put_ser(&sync_record_key(peer_addr)[..], record)?;
+		batch.commit()
+	}
+
+	/// List all libp2p access rules
+	pub fn all_libp2p_access(&self) -> Result<Vec<Libp2pPeerAccess>, Error> {
+		let key = to_key(LIBP2P_ACCESS_PREFIX, "");
+		Ok(self
+			.db
+			.iter::<Libp2pPeerAccess>(&key)?
+			.map(|(_, v)| v)
+			.collect::<Vec<_>>())
+	}
+
+	/// List all libp2p peer bans, the expired ones included
+	pub fn all_libp2p_bans(&self) -> Result<Vec<Libp2pPeerBan>, Error> {
+		let key = to_key(LIBP2P_BAN_PREFIX, "");
+		Ok(self
+			.db
+			.iter::<Libp2pPeerBan>(&key)?
+			.map(|(_, v)| v)
+			.collect::<Vec<_>>())
+	}
 }
 
 // Ignore the port unless ip is loopback address.
 fn peer_key(peer_addr: PeerAddr) -> Vec<u8> {
 	to_key(PEER_PREFIX, &peer_addr.as_key())
 }
+
+fn sync_record_key(peer_addr: PeerAddr) -> Vec<u8> {
+	to_key(SYNC_RECORD_PREFIX, &peer_addr.as_key())
+}
@@ -0,0 +1,190 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Accounting of the data the sync stages accepted from every peer. A peer that fed
+//! more than the stage cap in the current round is not used as the source of that
+//! stage while the other peers are below the cap. When every candidate is over the cap
+//! a new round starts, so the sources rotate and a single malicious peer can't be the
+//! only data source for the whole sync.
+
+use crate::types::PeerAddr;
+use crate::util::RwLock;
+use std::collections::HashMap;
+
+/// Max number of the peers we keep the accounting for. Peers with the least data are
+/// dropped above the limit.
+const MAX_ACCOUNTED_PEERS: usize = 1_000;
+
+/// Sync stage that accepts the data from the peers
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum SyncStage {
+	/// Block headers
+	Headers,
+	/// Full and compact blocks
+	Blocks,
+	/// TxHashSet archive
+	TxHashSet,
+}
+
+impl SyncStage {
+	fn index(&self) -> usize {
+		match self {
+			SyncStage::Headers => 0,
+			SyncStage::Blocks => 1,
+			SyncStage::TxHashSet => 2,
+		}
+	}
+}
+
+/// Per stage caps, in bytes. 0 is no cap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncDataCaps {
+	/// Headers accepted from a single peer in a round
+	pub headers: u64,
+	/// Blocks accepted from a single peer in a round
+	pub blocks: u64,
+	/// TxHashSet archives accepted from a single peer in a round
+	pub txhashset: u64,
+}
+
+impl SyncDataCaps {
+	fn cap(&self, stage: SyncStage) -> u64 {
+		match stage {
+			SyncStage::Headers => self.headers,
+			SyncStage::Blocks => self.blocks,
+			SyncStage::TxHashSet => self.txhashset,
+		}
+	}
+}
+
+/// Data accepted from the peer, in bytes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeerSyncData {
+	/// Address of the peer
+	pub addr: PeerAddr,
+	/// Headers accepted since the start
+	pub headers_bytes: u64,
+	/// Blocks accepted since the start
+	pub blocks_bytes: u64,
+	/// TxHashSet archives accepted since the start
+	pub txhashset_bytes: u64,
+	/// Stages the peer is over the cap in the current round
+	pub capped_stages: Vec<SyncStage>,
+}
+
+#[derive(Default, Clone)]
+struct PeerCounters {
+	total: [u64; 3],
+	round: [u64; 3],
+}
+
+/// Accepted sync data by peer
+pub struct SyncDataAccounting {
+	caps: SyncDataCaps,
+	peers: RwLock<HashMap<PeerAddr, PeerCounters>>,
+}
+
+impl SyncDataAccounting {
+	/// Accounting with the caps
+	pub fn new(caps: SyncDataCaps) -> SyncDataAccounting {
+		SyncDataAccounting {
+			caps,
+			peers: RwLock::new(HashMap::new()),
+		}
+	}
+
+	/// Stage accepted the data from the peer
+	pub fn record(&self, addr: &PeerAddr, stage: SyncStage, bytes: u64) {
+		let mut peers = self.peers.write();
+		if !peers.contains_key(addr) && peers.len() >= MAX_ACCOUNTED_PEERS {
+			if let Some(smallest) = peers
+				.iter()
+				.min_by_key(|(_, c)| c.total.iter().sum::<u64>())
+				.map(|(a, _)| a.clone())
+			{
+				peers.remove(&smallest);
+			}
+		}
+		let counters = peers.entry(addr.clone()).or_default();
+		counters.total[stage.index()] = counters.total[stage.index()].saturating_add(bytes);
+		counters.round[stage.index()] = counters.round[stage.index()].saturating_add(bytes);
+	}
+
+	/// Check if the peer fed more than the stage cap in the current round
+	pub fn is_over_cap(&self, addr: &PeerAddr, stage: SyncStage) -> bool {
+		let cap = self.caps.cap(stage);
+		cap > 0
+			&& self
+				.peers
+				.read()
+				.get(addr)
+				.map(|c| c.round[stage.index()] >= cap)
+				.unwrap_or(false)
+	}
+
+	/// Candidates for the stage, the order is preserved. Peers over the cap are dropped
+	/// while there are other candidates. If every candidate is over the cap, the new
+	/// round starts and all of them are returned.
+	pub fn preferred<T, F>(&self, stage: SyncStage, candidates: Vec<T>, addr: F) -> Vec<T>
+	where
+		F: Fn(&T) -> PeerAddr,
+	{
+		if candidates.is_empty() || self.caps.cap(stage) == 0 {
+			return candidates;
+		}
+		let (under_cap, over_cap): (Vec<T>, Vec<T>) = candidates
+			.into_iter()
+			.partition(|c| !self.is_over_cap(&addr(c), stage));
+		if !under_cap.is_empty() {
+			return under_cap;
+		}
+		debug!(
+			"sync_data: all {} {:?} sources are over the cap, starting new round",
+			over_cap.len(),
+			stage
+		);
+		for counters in self.peers.write().values_mut() {
+			counters.round[stage.index()] = 0;
+		}
+		over_cap
+	}
+
+	/// Data accepted from every peer, the largest sources first
+	pub fn peers(&self) -> Vec<PeerSyncData> {
+		let stages = [SyncStage::Headers, SyncStage::Blocks, SyncStage::TxHashSet];
+		let mut res: Vec<PeerSyncData> = self
+			.peers
+			.read()
+			.iter()
+			.map(|(addr, c)| PeerSyncData {
+				addr: addr.clone(),
+				headers_bytes: c.total[SyncStage::Headers.index()],
+				blocks_bytes: c.total[SyncStage::Blocks.index()],
+				txhashset_bytes: c.total[SyncStage::TxHashSet.index()],
+				capped_stages: stages
+					.iter()
+					.filter(|s| {
+						let cap = self.caps.cap(**s);
+						cap > 0 && c.round[s.index()] >= cap
+					})
+					.cloned()
+					.collect(),
+			})
+			.collect();
+		res.sort_by_key(|p| {
+			std::cmp::Reverse(p.headers_bytes + p.blocks_bytes + p.txhashset_bytes)
+		});
+		res
+	}
+}
@@ -0,0 +1,505 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Canonical serialization test vectors for the wire messages and the stored
+//! structures. Alternative implementations and wallets can use them to validate
+//! the compatibility with the node serialization for every protocol version.
+
+use crate::chain::types::{
+	CommitPos, DeferredRangeproofs, HashHeight, KernelFeeIndexHead, SyncStageRecord, Tip,
+	UptimeRecord,
+};
+use crate::core::core::hash::Hash;
+use crate::core::core::{
+	Block, BlockHeader, BlockSums, CompactBlock, Input, Inputs, KernelFeatures, Output,
+	OutputFeatures, OutputIdentifier, Transaction, TxKernel,
+};
+use crate::core::pow::Difficulty;
+use crate::core::ser::{self, ProtocolVersion, Readable, Writeable};
+use crate::core::{genesis, global};
+use crate::msg::{
+	BanReason, GetPeerAddrs, Hand, Headers, Locator, PeerAddrs, PeerError, Ping, Pong, Shake,
+	TorAddress, TxHashSetArchive, TxHashSetRequest, Type,
+};
+use crate::store::{PeerData, State};
+use crate::types::{Capabilities, Error, PeerAddr, ReasonForBan};
+use crate::util;
+use std::fs::File;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Protocol versions that test vectors are generated for
+pub const TEST_VECTOR_PROTOCOL_VERSIONS: [u32; 3] = [1, 2, 3];
+
+/// Single serialized structure
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TestVector {
+	/// Structure name
+	pub name: String,
+	/// Message type for the wire messages, None for the stored structures
+	pub msg_type: Option<u8>,
+	/// Protocol version used for serialization
+	pub protocol_version: u32,
+	/// Serialized data, hex encoded
+	pub data: String,
+}
+
+/// Test vectors for all structures and protocol versions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TestVectors {
+	/// Chain type the vectors were generated for
+	pub chain_type: String,
+	/// Node user agent
+	pub user_agent: String,
+	/// Test vectors
+	pub vectors: Vec<TestVector>,
+}
+
+fn sample_hash(seed: u8) -> Hash {
+	Hash::from_vec(&[seed; 32])
+}
+
+fn sample_addrs() -> Vec<PeerAddr> {
+	vec![
+		PeerAddr::Ip(SocketAddr::new(
+			IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+			3414,
+		)),
+		PeerAddr::Ip(SocketAddr::new(
+			IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+			13414,
+		)),
+		PeerAddr::Onion("2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid".to_string()),
+	]
+}
+
+// Body of the mainnet genesis, a real coinbase output and kernel. The header is the
+// default one, its proof size matches the chain type the vectors are generated for.
+fn sample_block() -> Block {
+	Block {
+		header: BlockHeader {
+			height: 1,
+			..BlockHeader::default()
+		},
+		body: genesis::genesis_main().body,
+	}
+}
+
+// Transaction built from the genesis output and kernel, so the commitments, the proof
+// and the signature have the real sizes
+fn sample_tx() -> Transaction {
+	let block = sample_block();
+	let output = &block.outputs()[0];
+	let kernel = &block.kernels()[0];
+	let input = Input::new(OutputFeatures::Plain, kernel.excess.clone());
+	Transaction::new(
+		Inputs::from(&[input][..]),
+		&[Output::new(
+			OutputFeatures::Plain,
+			output.commitment(),
+			output.proof(),
+		)],
+		&[TxKernel {
+			features: KernelFeatures::Plain { fee: 7_000_000 },
+			excess: kernel.excess.clone(),
+			excess_sig: kernel.excess_sig.clone(),
+		}],
+	)
+}
+
+// Compact block nonce is random, the genesis has no kernels that depend on it
+fn sample_compact_block() -> CompactBlock {
+	let mut cb = CompactBlock::from(sample_block());
+	cb.nonce = 0x0102_0304_0506_0708;
+	cb
+}
+
+// Header as the chain store keeps it, with the non default fields
+fn sample_db_header() -> BlockHeader {
+	BlockHeader {
+		height: 123_456,
+		prev_hash: sample_hash(9),
+		output_mmr_size: 1_000,
+		kernel_mmr_size: 500,
+		..BlockHeader::default()
+	}
+}
+
+fn vector<T: Writeable>(
+	name: &str,
+	msg_type: Option<Type>,
+	thing: &T,
+	version: ProtocolVersion,
+) -> Result<TestVector, Error> {
+	Ok(TestVector {
+		name: name.to_string(),
+		msg_type: msg_type.map(|t| t as u8),
+		protocol_version: version.0,
+		data: util::to_hex(&ser::ser_vec(thing, version)?),
+	})
+}
+
+/// Generate the test vectors for every known structure and protocol version
+pub fn generate_test_vectors() -> Result<TestVectors, Error> {
+	let mut vectors = vec![];
+	for v in TEST_VECTOR_PROTOCOL_VERSIONS.iter() {
+		let version = ProtocolVersion(*v);
+		let addrs = sample_addrs();
+
+		vectors.push(vector(
+			"Hand",
+			Some(Type::Hand),
+			&Hand {
+				version,
+				capabilities: Capabilities::FULL_NODE,
+				nonce: 0x0102_0304_0506_0708,
+				genesis: sample_hash(1),
+				total_difficulty: Difficulty::from_num(1_000_000),
+				sender_addr: addrs[0].clone(),
+				receiver_addr: addrs[2].clone(),
+				user_agent: "MW/MWC test".to_string(),
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"Shake",
+			Some(Type::Shake),
+			&Shake {
+				version,
+				capabilities: Capabilities::FULL_NODE,
+				genesis: sample_hash(1),
+				total_difficulty: Difficulty::from_num(1_000_000),
+				user_agent: "MW/MWC test".to_string(),
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"Ping",
+			Some(Type::Ping),
+			&Ping {
+				total_difficulty: Difficulty::from_num(1_000_000),
+				height: 123_456,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"Pong",
+			Some(Type::Pong),
+			&Pong {
+				total_difficulty: Difficulty::from_num(1_000_000),
+				height: 123_456,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"GetPeerAddrs",
+			Some(Type::GetPeerAddrs),
+			&GetPeerAddrs {
+				capabilities: Capabilities::PEER_LIST,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"PeerAddrs",
+			Some(Type::PeerAddrs),
+			&PeerAddrs {
+				peers: addrs.clone(),
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"PeerError",
+			Some(Type::Error),
+			&PeerError {
+				code: 7,
+				message: "test error".to_string(),
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"Locator",
+			Some(Type::GetHeaders),
+			&Locator {
+				hashes: vec![sample_hash(2), sample_hash(3)],
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"BlockHeader",
+			Some(Type::Header),
+			&BlockHeader::default(),
+			version,
+		)?);
+		vectors.push(vector(
+			"Headers",
+			Some(Type::Headers),
+			&Headers {
+				headers: vec![sample_block().header, BlockHeader::default()],
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"GetBlock",
+			Some(Type::GetBlock),
+			&sample_hash(5),
+			version,
+		)?);
+		vectors.push(vector(
+			"Block",
+			Some(Type::Block),
+			&sample_block(),
+			version,
+		)?);
+		vectors.push(vector(
+			"GetCompactBlock",
+			Some(Type::GetCompactBlock),
+			&sample_hash(6),
+			version,
+		)?);
+		vectors.push(vector(
+			"CompactBlock",
+			Some(Type::CompactBlock),
+			&sample_compact_block(),
+			version,
+		)?);
+		vectors.push(vector(
+			"Transaction",
+			Some(Type::Transaction),
+			&sample_tx(),
+			version,
+		)?);
+		vectors.push(vector(
+			"StemTransaction",
+			Some(Type::StemTransaction),
+			&sample_tx(),
+			version,
+		)?);
+		vectors.push(vector(
+			"GetTransaction",
+			Some(Type::GetTransaction),
+			&sample_hash(7),
+			version,
+		)?);
+		vectors.push(vector(
+			"TransactionKernel",
+			Some(Type::TransactionKernel),
+			&sample_hash(8),
+			version,
+		)?);
+		vectors.push(vector(
+			"BanReason",
+			Some(Type::BanReason),
+			&BanReason {
+				ban_reason: ReasonForBan::BadBlock,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"TxHashSetRequest",
+			Some(Type::TxHashSetRequest),
+			&TxHashSetRequest {
+				hash: sample_hash(4),
+				height: 123_456,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"TxHashSetArchive",
+			Some(Type::TxHashSetArchive),
+			&TxHashSetArchive {
+				hash: sample_hash(4),
+				height: 123_456,
+				bytes: 7_654_321,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"TorAddress",
+			Some(Type::TorAddress),
+			&TorAddress::new(
+				"2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid".to_string(),
+			),
+			version,
+		)?);
+		vectors.push(vector(
+			"PeerData",
+			None,
+			&PeerData {
+				addr: addrs[2].clone(),
+				capabilities: Capabilities::FULL_NODE,
+				user_agent: "MW/MWC test".to_string(),
+				flags: State::Banned,
+				last_banned: 1_600_000_000,
+				ban_reason: ReasonForBan::ManualBan,
+				last_connected: 1_600_000_100,
+			},
+			version,
+		)?);
+
+		// Chain store structures
+		let block = sample_block();
+		vectors.push(vector(
+			"Tip",
+			None,
+			&Tip::from_header(&sample_db_header()),
+			version,
+		)?);
+		vectors.push(vector(
+			"BlockSums",
+			None,
+			&BlockSums {
+				utxo_sum: block.outputs()[0].commitment(),
+				kernel_sum: block.kernels()[0].excess.clone(),
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"CommitPos",
+			None,
+			&CommitPos {
+				pos: 7_654_321,
+				height: 123_456,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"HashHeight",
+			None,
+			&HashHeight {
+				hash: sample_hash(10),
+				height: 123_456,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"OutputIdentifier",
+			None,
+			&block.outputs()[0].identifier(),
+			version,
+		)?);
+		vectors.push(vector("DbBlockHeader", None, &sample_db_header(), version)?);
+		vectors.push(vector(
+			"UptimeRecord",
+			None,
+			&UptimeRecord {
+				start_time: 1_600_000_000,
+				stop_time: 1_600_086_400,
+				sync_done_time: 1_600_003_600,
+				sync_stages: vec![SyncStageRecord {
+					stage: "header_sync".to_string(),
+					start_time: 1_600_000_010,
+					duration: 1_200,
+				}],
+				seq: 1,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"DeferredRangeproofs",
+			None,
+			&DeferredRangeproofs {
+				next_pos: 1_001,
+				last_pos: 7_654_321,
+				failed: true,
+			},
+			version,
+		)?);
+		vectors.push(vector(
+			"KernelFeeIndexHead",
+			None,
+			&KernelFeeIndexHead {
+				start_height: 100_000,
+				height: 123_456,
+				hash: sample_hash(11),
+			},
+			version,
+		)?);
+	}
+
+	Ok(TestVectors {
+		chain_type: format!("{:?}", global::get_chain_type()),
+		user_agent: crate::msg::USER_AGENT.to_string(),
+		vectors,
+	})
+}
+
+/// Write the test vectors as a json file
+pub fn write_test_vectors(file_path: &str) -> Result<(), Error> {
+	let vectors = generate_test_vectors()?;
+	let json = serde_json::to_string_pretty(&vectors)
+		.map_err(|e| Error::Internal(format!("Unable to serialize test vectors, {}", e)))?;
+	let mut file = File::create(file_path)?;
+	file.write_all(json.as_bytes())?;
+	Ok(())
+}
+
+fn round_trip<T: Readable + Writeable>(
+	data: &[u8],
+	version: ProtocolVersion,
+) -> Result<Vec<u8>, Error> {
+	let thing: T = ser::deserialize(&mut &data[..], version)?;
+	Ok(ser::ser_vec(&thing, version)?)
+}
+
+/// Deserialize the test vector data and serialize it back. Data must be the same.
+pub fn verify_test_vector(vector: &TestVector) -> Result<(), Error> {
+	let data = util::from_hex(&vector.data)
+		.map_err(|e| Error::Internal(format!("Invalid hex data for {}, {}", vector.name, e)))?;
+	let version = ProtocolVersion(vector.protocol_version);
+	let res = match vector.name.as_str() {
+		"Hand" => round_trip::<Hand>(&data, version)?,
+		"Shake" => round_trip::<Shake>(&data, version)?,
+		"Ping" => round_trip::<Ping>(&data, version)?,
+		"Pong" => round_trip::<Pong>(&data, version)?,
+		"GetPeerAddrs" => round_trip::<GetPeerAddrs>(&data, version)?,
+		"PeerAddrs" => round_trip::<PeerAddrs>(&data, version)?,
+		"PeerError" => round_trip::<PeerError>(&data, version)?,
+		"Locator" => round_trip::<Locator>(&data, version)?,
+		"BlockHeader" => round_trip::<BlockHeader>(&data, version)?,
+		"Headers" => round_trip::<Headers>(&data, version)?,
+		"Block" => round_trip::<Block>(&data, version)?,
+		"CompactBlock" => round_trip::<CompactBlock>(&data, version)?,
+		"Transaction" | "StemTransaction" => round_trip::<Transaction>(&data, version)?,
+		"GetBlock" | "GetCompactBlock" | "GetTransaction" | "TransactionKernel" => {
+			round_trip::<Hash>(&data, version)?
+		}
+		"BanReason" => round_trip::<BanReason>(&data, version)?,
+		"TxHashSetRequest" => round_trip::<TxHashSetRequest>(&data, version)?,
+		"TxHashSetArchive" => round_trip::<TxHashSetArchive>(&data, version)?,
+		"TorAddress" => round_trip::<TorAddress>(&data, version)?,
+		"PeerData" => round_trip::<PeerData>(&data, version)?,
+		"Tip" => round_trip::<Tip>(&data, version)?,
+		"BlockSums" => round_trip::<BlockSums>(&data, version)?,
+		"CommitPos" => round_trip::<CommitPos>(&data, version)?,
+		"HashHeight" => round_trip::<HashHeight>(&data, version)?,
+		"OutputIdentifier" => round_trip::<OutputIdentifier>(&data, version)?,
+		"DbBlockHeader" => round_trip::<BlockHeader>(&data, version)?,
+		"UptimeRecord" => round_trip::<UptimeRecord>(&data, version)?,
+		"DeferredRangeproofs" => round_trip::<DeferredRangeproofs>(&data, version)?,
+		"KernelFeeIndexHead" => round_trip::<KernelFeeIndexHead>(&data, version)?,
+		_ => {
+			return Err(Error::Internal(format!(
+				"Unknown test vector {}",
+				vector.name
+			)));
+		}
+	};
+	if res != data {
+		return Err(Error::Internal(format!(
+			"Test vector {} v{} doesn't match after the round trip",
+			vector.name, vector.protocol_version
+		)));
+	}
+	Ok(())
+}
@@ -15,6 +15,7 @@
 use crate::types::PeerAddr::Ip;
 use crate::types::PeerAddr::Onion;
 use failure::Fail;
+use std::collections::HashMap;
 use std::convert::From;
 use std::fmt;
 use std::fs::File;
@@ -33,13 +34,15 @@ use std::sync::atomic::AtomicUsize;
 use grin_store;
 
 use crate::chain;
+use crate::conn_stats::SlowPeerThresholds;
 use crate::core::core;
 use crate::core::core::hash::Hash;
 use crate::core::global;
 use crate::core::pow::Difficulty;
 use crate::core::ser::{self, ProtocolVersion, Readable, Reader, Writeable, Writer};
-use crate::msg::PeerAddrs;
-use crate::util::RwLock;
+use crate::msg::{self, PeerAddrs};
+use crate::sync_data::SyncDataCaps;
+use crate::util::{clock, RwLock};
 use std::time::Instant;
 
 /// Maximum number of block headers a peer should ever send
@@ -71,6 +74,35 @@ const PEER_MIN_PREFERRED_OUTBOUND_COUNT: u32 = 8;
 /// than allowed by PEER_MAX_INBOUND_COUNT to encourage network bootstrapping.
 const PEER_LISTENER_BUFFER_COUNT: u32 = 8;
 
+/// Max number of the concurrent outbound dials (p2p and libp2p together)
+const DIAL_MAX_CONCURRENT: u32 = 8;
+
+/// Number of the concurrent outbound dials right after the start
+const DIAL_INITIAL_CONCURRENT: u32 = 2;
+
+/// Concurrent outbound dials limit is increased by one every interval
+const DIAL_RAMP_UP_INTERVAL_SECS: u64 = 10;
+
+/// Headers a single peer can feed in a sync round before the other peers are preferred
+const SYNC_HEADERS_CAP_MB: u64 = 100;
+
+/// Blocks a single peer can feed in a sync round before the other peers are preferred
+const SYNC_BLOCKS_CAP_MB: u64 = 1_000;
+
+/// TxHashSet archives a single peer can feed in a sync round before the other peers
+/// are preferred
+const SYNC_TXHASHSET_CAP_MB: u64 = 4_000;
+
+/// Message read that takes longer than that is checked for the trickling
+const SLOW_READ_SECS: u64 = 30;
+
+/// Min average socket read size of the long message read. Tor delivers the data by
+/// the cells of about 500 bytes, so the honest peers are above it even over slow circuits.
+const SLOW_READ_MIN_AVG_BYTES: u64 = 64;
+
+/// Max time without data in the middle of the message
+const MAX_STALLED_READ_SECS: u64 = 60;
+
 #[derive(Debug, Fail)]
 pub enum Error {
 	#[fail(display = "p2p Serialization error, {}", _0)]
@@ -382,6 +414,64 @@ pub struct P2PConfig {
 	pub peer_listener_buffer_count: Option<u32>,
 
 	pub dandelion_peer: Option<PeerAddr>,
+
+	/// Max number of the concurrent outbound dials
+	pub dial_max_concurrent: Option<u32>,
+
+	/// Number of the concurrent outbound dials right after the start
+	pub dial_initial_concurrent: Option<u32>,
+
+	/// Concurrent outbound dials limit ramp up interval
+	pub dial_ramp_up_interval_secs: Option<u64>,
+
+	/// User agent advertised in the handshake instead of the default one
+	pub user_agent: Option<String>,
+
+	/// Don't advertise the exact version and the optional capabilities, so the node
+	/// is harder to enumerate by the crawlers
+	pub minimize_fingerprint: Option<bool>,
+
+	/// Directory for the p2p message captures, debug only. The node must be built
+	/// with the msg_capture feature.
+	pub capture_dir: Option<String>,
+
+	/// Fetch the block bodies the node doesn't store any more from the peers
+	/// when they are requested by the API
+	pub fetch_pruned_blocks: Option<bool>,
+
+	/// Address advertised to the peers as "ip:port" instead of the bind address.
+	/// Peers take our IP from the connection, so behind a NAT the port matters most.
+	pub advertised_address: Option<String>,
+
+	/// Advertise our address to the peers. Nodes that don't accept the connections
+	/// disable it, so the peers don't share and dial the unreachable address.
+	pub advertise_address: Option<bool>,
+
+	/// Detect the external address from the addresses the peers dialed
+	pub detect_external_address: Option<bool>,
+
+	/// Headers, in MB, a single peer can feed before the other peers are preferred.
+	/// 0 is no cap.
+	pub sync_headers_cap_mb: Option<u64>,
+
+	/// Blocks, in MB, a single peer can feed before the other peers are preferred.
+	/// 0 is no cap.
+	pub sync_blocks_cap_mb: Option<u64>,
+
+	/// TxHashSet archives, in MB, a single peer can feed before the other peers are
+	/// preferred. 0 is no cap.
+	pub sync_txhashset_cap_mb: Option<u64>,
+
+	/// Message read that takes longer than that, in seconds, is checked for the average
+	/// read size. 0 disables the check.
+	pub slow_read_secs: Option<u64>,
+
+	/// Min average read size, in bytes, of the long message read. Slower peers are dropped.
+	pub slow_read_min_avg_bytes: Option<u64>,
+
+	/// Max time, in seconds, without any data in the middle of the message. 0 disables
+	/// the check.
+	pub max_stalled_read_secs: Option<u64>,
 }
 
 /// Default address for peer-to-peer connections.
@@ -403,6 +493,22 @@ impl Default for P2PConfig {
 			peer_min_preferred_outbound_count: None,
 			peer_listener_buffer_count: None,
 			dandelion_peer: None,
+			dial_max_concurrent: None,
+			dial_initial_concurrent: None,
+			dial_ramp_up_interval_secs: None,
+			user_agent: None,
+			minimize_fingerprint: None,
+			capture_dir: None,
+			fetch_pruned_blocks: None,
+			advertised_address: None,
+			advertise_address: None,
+			detect_external_address: None,
+			sync_headers_cap_mb: None,
+			sync_blocks_cap_mb: None,
+			sync_txhashset_cap_mb: None,
+			slow_read_secs: None,
+			slow_read_min_avg_bytes: None,
+			max_stalled_read_secs: None,
 		}
 	}
 }
@@ -449,6 +555,438 @@ impl P2PConfig {
 			None => PEER_LISTENER_BUFFER_COUNT,
 		}
 	}
+
+	/// return max number of the concurrent outbound dials
+	pub fn dial_max_concurrent(&self) -> u32 {
+		match self.dial_max_concurrent {
+			Some(n) => n,
+			None => DIAL_MAX_CONCURRENT,
+		}
+	}
+
+	/// return number of the concurrent outbound dials right after the start
+	pub fn dial_initial_concurrent(&self) -> u32 {
+		match self.dial_initial_concurrent {
+			Some(n) => n,
+			None => DIAL_INITIAL_CONCURRENT,
+		}
+	}
+
+	/// return whether the fingerprintable handshake fields are minimized
+	pub fn minimize_fingerprint(&self) -> bool {
+		self.minimize_fingerprint.unwrap_or(false)
+	}
+
+	/// return true if the pruned blocks requested by the API are fetched from the peers
+	pub fn fetch_pruned_blocks(&self) -> bool {
+		self.fetch_pruned_blocks.unwrap_or(false)
+	}
+
+	/// return true if our address is advertised to the peers
+	pub fn advertise_address(&self) -> bool {
+		self.advertise_address.unwrap_or(true)
+	}
+
+	/// return true if the external address is detected from the peer votes
+	pub fn detect_external_address(&self) -> bool {
+		self.detect_external_address.unwrap_or(true)
+	}
+
+	/// return the per peer caps of the data accepted by the sync stages
+	pub fn sync_data_caps(&self) -> SyncDataCaps {
+		let mb = 1024 * 1024;
+		SyncDataCaps {
+			headers: self.sync_headers_cap_mb.unwrap_or(SYNC_HEADERS_CAP_MB) * mb,
+			blocks: self.sync_blocks_cap_mb.unwrap_or(SYNC_BLOCKS_CAP_MB) * mb,
+			txhashset: self.sync_txhashset_cap_mb.unwrap_or(SYNC_TXHASHSET_CAP_MB) * mb,
+		}
+	}
+
+	/// return the thresholds of the slow peer detection
+	pub fn slow_peer_thresholds(&self) -> SlowPeerThresholds {
+		SlowPeerThresholds {
+			slow_read_secs: self.slow_read_secs.unwrap_or(SLOW_READ_SECS),
+			min_avg_read_bytes: self
+				.slow_read_min_avg_bytes
+				.unwrap_or(SLOW_READ_MIN_AVG_BYTES),
+			max_stalled_read_secs: self.max_stalled_read_secs.unwrap_or(MAX_STALLED_READ_SECS),
+		}
+	}
+
+	/// return the user agent advertised in the handshake
+	pub fn user_agent(&self) -> String {
+		match &self.user_agent {
+			Some(user_agent) => user_agent.clone(),
+			None if self.minimize_fingerprint() => msg::minimal_user_agent(),
+			None => msg::USER_AGENT.to_string(),
+		}
+	}
+
+	/// return the capabilities advertised in the handshake. Optional capabilities
+	/// are dropped if the fingerprint is minimized.
+	pub fn advertised_capabilities(&self, capabilities: Capabilities) -> Capabilities {
+		if self.minimize_fingerprint() {
+			capabilities & Capabilities::FULL_NODE
+		} else {
+			capabilities
+		}
+	}
+
+	/// return the concurrent outbound dials ramp up interval
+	pub fn dial_ramp_up_interval_secs(&self) -> u64 {
+		match self.dial_ramp_up_interval_secs {
+			Some(n) => n,
+			None => DIAL_RAMP_UP_INTERVAL_SECS,
+		}
+	}
+}
+
+/// Validation of the gossipsub message signatures
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum GossipValidationMode {
+	/// Every message must be signed by its author and carry the sequence number
+	Strict,
+	/// Signatures are verified if present, unsigned messages are accepted
+	Permissive,
+}
+
+impl Default for GossipValidationMode {
+	fn default() -> GossipValidationMode {
+		GossipValidationMode::Strict
+	}
+}
+
+/// Transport of the libp2p swarm
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Libp2pTransport {
+	/// Onion service only, the peers are dialed through the Tor socks proxy
+	Tor,
+	/// Plain TCP only, for the nodes without Tor. Only the clearnet peers are dialed.
+	Tcp,
+	/// Onion service and the TCP listener. Onion peers are preferred, the clearnet peers
+	/// are dialed when no onion peer is available.
+	TorAndTcp,
+}
+
+impl Libp2pTransport {
+	/// True if the onion service and the Tor dialing are used
+	pub fn has_tor(&self) -> bool {
+		*self != Libp2pTransport::Tcp
+	}
+
+	/// True if the TCP listener and the clearnet dialing are used
+	pub fn has_tcp(&self) -> bool {
+		*self != Libp2pTransport::Tor
+	}
+}
+
+impl Default for Libp2pTransport {
+	fn default() -> Libp2pTransport {
+		Libp2pTransport::Tor
+	}
+}
+
+/// Key the libp2p PeerId of the node is derived from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Libp2pIdentity {
+	/// Key of the onion service, the PeerId matches the onion address
+	Tor,
+	/// Own key kept in the data dir. PeerId stays the same when the onion service is
+	/// rotated, but the peers can't derive the onion address of the node from it.
+	Standalone,
+}
+
+impl Default for Libp2pIdentity {
+	fn default() -> Libp2pIdentity {
+		Libp2pIdentity::Tor
+	}
+}
+
+/// Gossipsub libp2p node configuration. Unset mesh and history parameters use
+/// the gossipsub defaults.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Libp2pConfig {
+	/// Gossipsub heartbeat interval in milliseconds. Mesh maintenance and the gossip
+	/// emission happen on the heartbeat.
+	#[serde(default = "default_gossip_heartbeat_interval_ms")]
+	pub heartbeat_interval_ms: u64,
+	/// Target number of the peers in the topic mesh
+	#[serde(default)]
+	pub mesh_n: Option<usize>,
+	/// Mesh is extended when it has less peers than that
+	#[serde(default)]
+	pub mesh_n_low: Option<usize>,
+	/// Mesh is pruned when it has more peers than that. The node also tries to keep
+	/// that many connections.
+	#[serde(default)]
+	pub mesh_n_high: Option<usize>,
+	/// Validation of the message signatures
+	#[serde(default)]
+	pub validation_mode: GossipValidationMode,
+	/// Number of the heartbeats the message ids are kept in the message cache
+	#[serde(default)]
+	pub history_length: Option<usize>,
+	/// Number of the recent heartbeats whose message ids are gossiped
+	#[serde(default)]
+	pub history_gossip: Option<usize>,
+	/// How often the node checks its connections and dials the new peers, in seconds
+	#[serde(default = "default_libp2p_reconnect_interval_secs")]
+	pub reconnect_interval_secs: u64,
+	/// Transport of the swarm: tor, tcp or tor_and_tcp
+	#[serde(default)]
+	pub transport: Libp2pTransport,
+	/// Port of the clearnet TCP listener
+	#[serde(default = "default_libp2p_tcp_port")]
+	pub tcp_port: u16,
+	/// Clearnet peers, multiaddresses with the peer id: /ip4/1.2.3.4/tcp/3418/p2p/<peer id>
+	#[serde(default)]
+	pub tcp_peers: Vec<String>,
+	/// Hard timeout of the dial through the Tor SOCKS proxy, in seconds
+	#[serde(default = "default_libp2p_dial_timeout_secs")]
+	pub dial_timeout_secs: u64,
+	/// Number of the dials through the Tor SOCKS proxy that can run at the same time
+	#[serde(default = "default_libp2p_dial_workers")]
+	pub dial_workers: usize,
+	/// Identity key of the node: tor or standalone. The clearnet only node always uses
+	/// the standalone key.
+	#[serde(default)]
+	pub identity: Libp2pIdentity,
+	/// Kademlia DHT peer discovery next to the gossipsub
+	#[serde(default = "default_libp2p_kad_discovery")]
+	pub kad_discovery: bool,
+	/// Interval between the DHT lookups, in seconds
+	#[serde(default = "default_libp2p_kad_query_interval_secs")]
+	pub kad_query_interval_secs: u64,
+	/// Topics the node subscribes to and relays, all topics if empty. Trailing '*'
+	/// matches any topic with that prefix.
+	#[serde(default)]
+	pub topic_allowlist: Vec<String>,
+	/// Topics the node never subscribes to, the denylist wins over the allowlist.
+	/// Trailing '*' matches any topic with that prefix.
+	#[serde(default)]
+	pub topic_denylist: Vec<String>,
+	/// Max number of the messages in the cache of the messages that were passed to the handlers
+	#[serde(default = "default_libp2p_seen_cache_size")]
+	pub seen_cache_size: usize,
+	/// Time the message stays in the seen messages cache, in seconds
+	#[serde(default = "default_libp2p_seen_cache_ttl_secs")]
+	pub seen_cache_ttl_secs: u64,
+	/// Whether the seen messages cache is saved to the disk, so it survives the restart
+	#[serde(default = "default_libp2p_persist_seen_cache")]
+	pub persist_seen_cache: bool,
+	/// Relay the circuits of the peers, dialing the destination if it is not connected
+	#[serde(default)]
+	pub relay_hop: bool,
+	/// Relay nodes the node listens through and dials the unreachable peers through,
+	/// multiaddresses with the peer id: /onion3/<address>:<port>/p2p/<peer id>
+	#[serde(default)]
+	pub relay_nodes: Vec<String>,
+	/// Max number of the relayed connections
+	#[serde(default = "default_libp2p_relay_max_connections")]
+	pub relay_max_connections: usize,
+	/// Bandwidth limit of the relayed connection, in bytes per second. Zero is unlimited.
+	#[serde(default = "default_libp2p_relay_connection_bytes_per_sec")]
+	pub relay_connection_bytes_per_sec: u64,
+	/// Bandwidth limit of all the relayed connections, in bytes per second. Zero is unlimited.
+	#[serde(default = "default_libp2p_relay_total_bytes_per_sec")]
+	pub relay_total_bytes_per_sec: u64,
+}
+
+impl Libp2pConfig {
+	/// Whether the node can subscribe to the topic and relay its messages
+	pub fn is_topic_allowed(&self, topic: &str) -> bool {
+		let matches = |pattern: &String| {
+			if pattern.ends_with('*') {
+				topic.starts_with(&pattern[..pattern.len() - 1])
+			} else {
+				pattern == topic
+			}
+		};
+		if self.topic_denylist.iter().any(matches) {
+			return false;
+		}
+		self.topic_allowlist.is_empty() || self.topic_allowlist.iter().any(matches)
+	}
+
+	/// Limits of the relayed connections
+	pub fn relay_limits(&self) -> crate::libp2p_relay::RelayLimits {
+		crate::libp2p_relay::RelayLimits {
+			max_connections: self.relay_max_connections,
+			connection_bytes_per_sec: self.relay_connection_bytes_per_sec,
+			total_bytes_per_sec: self.relay_total_bytes_per_sec,
+		}
+	}
+}
+
+fn default_gossip_heartbeat_interval_ms() -> u64 {
+	// Gossipsub default is 1 second, but we don't want to overload the network.
+	// Slow adaptation of the network is preferred.
+	3_000
+}
+
+fn default_libp2p_reconnect_interval_secs() -> u64 {
+	14
+}
+
+fn default_libp2p_tcp_port() -> u16 {
+	3418
+}
+
+fn default_libp2p_dial_timeout_secs() -> u64 {
+	crate::libp2p_dial::DEFAULT_DIAL_TIMEOUT_SECS
+}
+
+fn default_libp2p_dial_workers() -> usize {
+	crate::libp2p_dial::DEFAULT_DIAL_WORKERS
+}
+
+fn default_libp2p_kad_discovery() -> bool {
+	true
+}
+
+fn default_libp2p_kad_query_interval_secs() -> u64 {
+	crate::libp2p_kad::DEFAULT_KAD_QUERY_INTERVAL_SECS
+}
+
+fn default_libp2p_seen_cache_size() -> usize {
+	crate::libp2p_seen_cache::DEFAULT_SEEN_CACHE_SIZE
+}
+
+fn default_libp2p_seen_cache_ttl_secs() -> u64 {
+	crate::libp2p_seen_cache::DEFAULT_SEEN_CACHE_TTL_SECS
+}
+
+fn default_libp2p_persist_seen_cache() -> bool {
+	true
+}
+
+fn default_libp2p_relay_max_connections() -> usize {
+	crate::libp2p_relay::DEFAULT_RELAY_MAX_CONNECTIONS
+}
+
+fn default_libp2p_relay_connection_bytes_per_sec() -> u64 {
+	crate::libp2p_relay::DEFAULT_RELAY_CONNECTION_BYTES_PER_SEC
+}
+
+fn default_libp2p_relay_total_bytes_per_sec() -> u64 {
+	crate::libp2p_relay::DEFAULT_RELAY_TOTAL_BYTES_PER_SEC
+}
+
+impl Default for Libp2pConfig {
+	fn default() -> Libp2pConfig {
+		Libp2pConfig {
+			heartbeat_interval_ms: default_gossip_heartbeat_interval_ms(),
+			mesh_n: None,
+			mesh_n_low: None,
+			mesh_n_high: None,
+			validation_mode: GossipValidationMode::default(),
+			history_length: None,
+			history_gossip: None,
+			reconnect_interval_secs: default_libp2p_reconnect_interval_secs(),
+			transport: Libp2pTransport::default(),
+			tcp_port: default_libp2p_tcp_port(),
+			tcp_peers: vec![],
+			dial_timeout_secs: default_libp2p_dial_timeout_secs(),
+			dial_workers: default_libp2p_dial_workers(),
+			identity: Libp2pIdentity::default(),
+			kad_discovery: default_libp2p_kad_discovery(),
+			kad_query_interval_secs: default_libp2p_kad_query_interval_secs(),
+			topic_allowlist: vec![],
+			topic_denylist: vec![],
+			seen_cache_size: default_libp2p_seen_cache_size(),
+			seen_cache_ttl_secs: default_libp2p_seen_cache_ttl_secs(),
+			persist_seen_cache: default_libp2p_persist_seen_cache(),
+			relay_hop: false,
+			relay_nodes: vec![],
+			relay_max_connections: default_libp2p_relay_max_connections(),
+			relay_connection_bytes_per_sec: default_libp2p_relay_connection_bytes_per_sec(),
+			relay_total_bytes_per_sec: default_libp2p_relay_total_bytes_per_sec(),
+		}
+	}
+}
+
+/// Flood protection of the gossip messages. Every message is paid with an integrity kernel,
+/// the messages of the kernel that is used more often than the limits allow are rejected.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Libp2pPolicy {
+	/// Number of the recent messages of the integrity kernel the rate is checked on
+	#[serde(default = "default_integrity_call_history_len")]
+	pub integrity_call_history_len: usize,
+	/// Min average interval between the messages of the same integrity kernel, in seconds
+	#[serde(default = "default_integrity_call_min_period_secs")]
+	pub integrity_call_min_period_secs: i64,
+	/// Multipliers of the allowed message rate by the topic name. The topic with multiplier
+	/// 4.0 accepts the messages of the kernel 4 times more often. Other topics use 1.0.
+	#[serde(default)]
+	pub topic_rate_multipliers: HashMap<String, f64>,
+}
+
+fn default_integrity_call_history_len() -> usize {
+	crate::integrity_budget::INTEGRITY_CALL_HISTORY_LEN_LIMIT
+}
+
+fn default_integrity_call_min_period_secs() -> i64 {
+	crate::integrity_budget::INTEGRITY_CALL_MAX_PERIOD
+}
+
+impl Default for Libp2pPolicy {
+	fn default() -> Libp2pPolicy {
+		Libp2pPolicy {
+			integrity_call_history_len: default_integrity_call_history_len(),
+			integrity_call_min_period_secs: default_integrity_call_min_period_secs(),
+			topic_rate_multipliers: HashMap::new(),
+		}
+	}
+}
+
+impl Libp2pPolicy {
+	/// Check that the limits are consistent
+	pub fn validate(&self) -> Result<(), Error> {
+		if self.integrity_call_history_len < 2 {
+			return Err(Error::Libp2pError(
+				"Invalid libp2p policy, integrity_call_history_len must be at least 2".to_string(),
+			));
+		}
+		if self.integrity_call_min_period_secs < 0 {
+			return Err(Error::Libp2pError(
+				"Invalid libp2p policy, integrity_call_min_period_secs can't be negative"
+					.to_string(),
+			));
+		}
+		if let Some((topic, _)) = self
+			.topic_rate_multipliers
+			.iter()
+			.find(|(_, m)| !(**m > 0.0))
+		{
+			return Err(Error::Libp2pError(format!(
+				"Invalid libp2p policy, rate multiplier of the topic {} must be positive",
+				topic
+			)));
+		}
+		Ok(())
+	}
+
+	/// Min average interval between the messages of the integrity kernel on the topic, in seconds
+	pub fn min_call_period(&self, topic: &str) -> f64 {
+		let multiplier = self
+			.topic_rate_multipliers
+			.get(topic)
+			.cloned()
+			.unwrap_or(1.0);
+		self.integrity_call_min_period_secs as f64 / multiplier
+	}
+
+	/// Call history that is older than that doesn't affect the rate checks, in seconds
+	pub fn call_history_window_secs(&self) -> i64 {
+		let max_period = self
+			.topic_rate_multipliers
+			.keys()
+			.map(|topic| self.min_call_period(topic))
+			.fold(self.integrity_call_min_period_secs as f64, f64::max);
+		(self.integrity_call_history_len as f64 * max_period).ceil() as i64
+	}
 }
 
 /// Type of seeding the server will use to find other peers on the network.
@@ -488,10 +1026,23 @@ bitflags! {
 		const TX_KERNEL_HASH = 0b0000_1000;
 		/// Can send/receive tor addresses
 		const TOR_ADDRESS = 0b0001_0000;
+		/// Accepts the header first announcements of the freshly mined blocks
+		/// and requests the block body only if the block is new to it.
+		/// Not a part of FULL_NODE, the node advertises it on top of the configured
+		/// capabilities.
+		const HEADER_FIRST = 0b1_0000_0000;
+		/// Light relay peer that serves and relays the block headers only. It is never
+		/// asked for the block bodies or the TxHashSet, gets the headers instead of
+		/// the blocks and doesn't take part in the transaction relay.
+		const HEADERS_ONLY = 0b10_0000_0000;
+		/// Archive node that keeps the full block history. Not a part of FULL_NODE,
+		/// the node advertises it on top of the configured capabilities when it
+		/// runs in the archive mode. Preferred as the initial state sync source.
+		const ARCHIVE = 0b100_0000_0000;
 
 		/// All nodes right now are "full nodes".
-		/// Some nodes internally may maintain longer block histories (archival_mode)
-		/// but we do not advertise this to other nodes.
+		/// Some nodes internally may maintain longer block histories (archival_mode),
+		/// they advertise it with ARCHIVE.
 		/// All nodes by default will accept lightweight "kernel first" tx broadcast.
 		const FULL_NODE = Capabilities::HEADER_HIST.bits
 			| Capabilities::TXHASHSET_HIST.bits
@@ -524,9 +1075,21 @@ enum_from_primitive! {
 		ManualBan = 5,
 		FraudHeight = 6,
 		BadHandshake = 7,
+		BadGossip = 8,
 	}
 }
 
+/// Statistics of the header first announcements of the blocks mined by this node
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct HeaderFirstStats {
+	/// Number of the headers announced instead of the compact blocks
+	pub headers_announced: u64,
+	/// Number of the compact blocks pushed to the peers without the header first capability
+	pub compact_blocks_pushed: u64,
+	/// Number of the announced block bodies that were requested by the peers
+	pub bodies_requested: u64,
+}
+
 #[derive(Clone, Debug)]
 pub struct PeerLiveInfo {
 	pub total_difficulty: Difficulty,
@@ -555,9 +1118,9 @@ impl PeerLiveInfo {
 		PeerLiveInfo {
 			total_difficulty: difficulty,
 			height: 0,
-			first_seen: Utc::now(),
-			last_seen: Utc::now(),
-			stuck_detector: Utc::now(),
+			first_seen: clock::now(),
+			last_seen: clock::now(),
+			stuck_detector: clock::now(),
 		}
 	}
 }
@@ -576,6 +1139,12 @@ impl PeerInfo {
 		self.direction == Direction::Inbound || self.direction == Direction::InboundTor
 	}
 
+	/// True if the peer can be asked for the block bodies and the TxHashSet.
+	/// Headers only relay peers serve the headers only.
+	pub fn serves_bodies(&self) -> bool {
+		!self.capabilities.contains(Capabilities::HEADERS_ONLY)
+	}
+
 	/// The current height of the peer.
 	pub fn height(&self) -> u64 {
 		self.live_info.read().height
@@ -596,11 +1165,11 @@ impl PeerInfo {
 	pub fn update(&self, height: u64, total_difficulty: Difficulty) {
 		let mut live_info = self.live_info.write();
 		if total_difficulty != live_info.total_difficulty {
-			live_info.stuck_detector = Utc::now();
+			live_info.stuck_detector = clock::now();
 		}
 		live_info.height = height;
 		live_info.total_difficulty = total_difficulty;
-		live_info.last_seen = Utc::now()
+		live_info.last_seen = clock::now()
 	}
 }
 
@@ -0,0 +1,259 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Peer version census. Every peer that completes the handshake is recorded with its
+//! user agent and advertised protocol version. The census counts the peers seen in the
+//! last window by version, and keeps the hourly samples of these counts, so before a
+//! hard fork the operator can see what part of the reachable network has upgraded.
+//! The census is not persistent, it starts empty on every node run.
+
+use crate::types::PeerAddr;
+use crate::util::RwLock;
+use chrono::Utc;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Peers seen in the last 24 hours are counted by the census
+pub const CENSUS_WINDOW_SECS: i64 = 24 * 3600;
+/// Interval between the trend samples
+pub const CENSUS_SAMPLE_SECS: i64 = 3600;
+/// Number of the trend samples to keep, 30 days
+pub const CENSUS_MAX_SAMPLES: usize = 30 * 24;
+/// Max number of the tracked peers, the ones not seen for the longest time are dropped
+pub const CENSUS_MAX_PEERS: usize = 50_000;
+
+/// Number of the peers that run the version
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VersionCount {
+	/// User agent or protocol version
+	pub version: String,
+	/// Number of the peers
+	pub peers: usize,
+	/// Part of all peers in the window, 0.0 - 1.0
+	pub fraction: f64,
+}
+
+/// Hourly census sample
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CensusSample {
+	/// Unix time of the sample
+	pub time: i64,
+	/// Number of the peers seen in the window before the sample
+	pub total_peers: usize,
+	/// Peers by the user agent
+	pub user_agents: BTreeMap<String, usize>,
+}
+
+/// Census of the peers seen in the window
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VersionCensusReport {
+	/// Window length, seconds
+	pub window_secs: i64,
+	/// Number of the peers seen in the window
+	pub total_peers: usize,
+	/// Peers by the user agent, the most popular first
+	pub user_agents: Vec<VersionCount>,
+	/// Peers by the advertised protocol version, the most popular first
+	pub protocol_versions: Vec<VersionCount>,
+	/// Target node version of the upgrade readiness, if requested
+	pub upgrade_target: Option<String>,
+	/// Number of the peers that run the target node version or newer
+	pub upgraded_peers: Option<usize>,
+	/// Part of the peers that run the target node version or newer, 0.0 - 1.0
+	pub upgraded_fraction: Option<f64>,
+	/// Hourly samples, the oldest first
+	pub trend: Vec<CensusSample>,
+}
+
+struct SeenPeer {
+	user_agent: String,
+	protocol_version: u32,
+	last_seen: i64,
+}
+
+/// Tracker of the peer versions
+#[derive(Default)]
+pub struct VersionCensus {
+	peers: HashMap<PeerAddr, SeenPeer>,
+	samples: VecDeque<CensusSample>,
+}
+
+/// Node version from the user agent, "MW/MWC 4.4.2" is (4,4,2). Missing parts are zeros.
+pub fn parse_node_version(user_agent: &str) -> Option<(u32, u32, u32)> {
+	let version = user_agent.rsplit(' ').next()?;
+	let mut parts = version.trim_start_matches('v').splitn(3, '.').map(|p| {
+		// Suffixes like "-beta" are ignored
+		p.chars()
+			.take_while(|c| c.is_ascii_digit())
+			.collect::<String>()
+			.parse::<u32>()
+	});
+	let major = parts.next()?.ok()?;
+	let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+	let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+	Some((major, minor, patch))
+}
+
+fn to_counts(counts: HashMap<String, usize>, total: usize) -> Vec<VersionCount> {
+	let mut res: Vec<VersionCount> = counts
+		.into_iter()
+		.map(|(version, peers)| VersionCount {
+			version,
+			peers,
+			fraction: if total > 0 {
+				peers as f64 / total as f64
+			} else {
+				0.0
+			},
+		})
+		.collect();
+	res.sort_by(|a, b| b.peers.cmp(&a.peers).then(a.version.cmp(&b.version)));
+	res
+}
+
+impl VersionCensus {
+	/// Empty census
+	pub fn new() -> VersionCensus {
+		VersionCensus {
+			peers: HashMap::new(),
+			samples: VecDeque::new(),
+		}
+	}
+
+	/// Record the peer that completed the handshake
+	pub fn record(&mut self, addr: PeerAddr, user_agent: &str, protocol_version: u32, now: i64) {
+		self.update_samples(now);
+		self.peers.insert(
+			addr,
+			SeenPeer {
+				user_agent: user_agent.to_string(),
+				protocol_version,
+				last_seen: now,
+			},
+		);
+		if self.peers.len() > CENSUS_MAX_PEERS {
+			let oldest = self
+				.peers
+				.iter()
+				.min_by_key(|(_, p)| p.last_seen)
+				.map(|(addr, _)| addr.clone());
+			if let Some(oldest) = oldest {
+				self.peers.remove(&oldest);
+			}
+		}
+	}
+
+	fn in_window(&self, now: i64) -> impl Iterator<Item = &SeenPeer> {
+		self.peers
+			.values()
+			.filter(move |p| p.last_seen > now - CENSUS_WINDOW_SECS)
+	}
+
+	fn sample(&self, now: i64) -> CensusSample {
+		let mut user_agents = BTreeMap::new();
+		let mut total_peers = 0;
+		for peer in self.in_window(now) {
+			*user_agents.entry(peer.user_agent.clone()).or_insert(0) += 1;
+			total_peers += 1;
+		}
+		CensusSample {
+			time: now,
+			total_peers,
+			user_agents,
+		}
+	}
+
+	// Sample is taken when the sample interval is passed since the previous one
+	fn update_samples(&mut self, now: i64) {
+		let due = match self.samples.back() {
+			Some(last) => now - last.time >= CENSUS_SAMPLE_SECS,
+			None => !self.peers.is_empty(),
+		};
+		if due {
+			let sample = self.sample(now);
+			self.samples.push_back(sample);
+			while self.samples.len() > CENSUS_MAX_SAMPLES {
+				self.samples.pop_front();
+			}
+			// Peers that are out of the window are not needed any more
+			self.peers
+				.retain(|_, p| p.last_seen > now - CENSUS_WINDOW_SECS);
+		}
+	}
+
+	/// Census of the peers seen in the window. `upgrade_target` is the node version
+	/// like "5.0.0", the peers that run this version or newer are counted as upgraded.
+	pub fn report(&mut self, upgrade_target: Option<&str>, now: i64) -> VersionCensusReport {
+		self.update_samples(now);
+
+		let mut user_agents = HashMap::new();
+		let mut protocol_versions = HashMap::new();
+		let mut total_peers = 0;
+		let target = upgrade_target.and_then(parse_node_version);
+		let mut upgraded = 0;
+		for peer in self.in_window(now) {
+			total_peers += 1;
+			*user_agents.entry(peer.user_agent.clone()).or_insert(0) += 1;
+			*protocol_versions
+				.entry(peer.protocol_version.to_string())
+				.or_insert(0) += 1;
+			if let Some(target) = target {
+				if parse_node_version(&peer.user_agent).map_or(false, |v| v >= target) {
+					upgraded += 1;
+				}
+			}
+		}
+
+		let (upgraded_peers, upgraded_fraction) = match target {
+			Some(_) => (
+				Some(upgraded),
+				Some(if total_peers > 0 {
+					upgraded as f64 / total_peers as f64
+				} else {
+					0.0
+				}),
+			),
+			None => (None, None),
+		};
+
+		VersionCensusReport {
+			window_secs: CENSUS_WINDOW_SECS,
+			total_peers,
+			user_agents: to_counts(user_agents, total_peers),
+			protocol_versions: to_counts(protocol_versions, total_peers),
+			upgrade_target: upgrade_target.map(|t| t.to_string()),
+			upgraded_peers,
+			upgraded_fraction,
+			trend: self.samples.iter().cloned().collect(),
+		}
+	}
+}
+
+lazy_static! {
+	// Census of the peers seen by this node
+	static ref VERSION_CENSUS: RwLock<VersionCensus> = RwLock::new(VersionCensus::new());
+}
+
+/// Record the peer that completed the handshake in the node census
+pub fn record_peer_version(addr: PeerAddr, user_agent: &str, protocol_version: u32) {
+	VERSION_CENSUS
+		.write()
+		.record(addr, user_agent, protocol_version, Utc::now().timestamp());
+}
+
+/// Census of the peers seen by the node
+pub fn version_census(upgrade_target: Option<&str>) -> VersionCensusReport {
+	VERSION_CENSUS
+		.write()
+		.report(upgrade_target, Utc::now().timestamp())
+}
@@ -0,0 +1,107 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+
+use grin_util as util;
+use grin_util::StopState;
+
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::{thread, time};
+
+use crate::core::core::hash::Hash;
+use crate::core::global;
+use crate::p2p::conformance::{CheckStatus, ConformanceSuite};
+
+fn open_port() -> u16 {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	listener.local_addr().unwrap().port()
+}
+
+// Runs the conformance suite against an in-process node backed by the dummy adapter
+#[test]
+fn conformance_against_local_node() {
+	global::init_global_chain_type(global::ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+
+	let p2p_config = p2p::P2PConfig {
+		host: "127.0.0.1".parse().unwrap(),
+		port: open_port(),
+		peers_allow: None,
+		peers_deny: None,
+		peer_max_inbound_count: Some(32),
+		..p2p::P2PConfig::default()
+	};
+	let genesis = Hash::from_vec(&vec![]);
+	let server = Arc::new(
+		p2p::Server::new(
+			".grin_conformance",
+			p2p::Capabilities::UNKNOWN,
+			p2p_config.clone(),
+			Arc::new(p2p::DummyAdapter {}),
+			genesis,
+			Arc::new(StopState::new()),
+			0,
+			None,
+		)
+		.unwrap(),
+	);
+	let p2p_inner = server.clone();
+	let _ = thread::spawn(move || p2p_inner.listen(100_000));
+	thread::sleep(time::Duration::from_secs(1));
+
+	let target = SocketAddr::new(p2p_config.host, p2p_config.port);
+	let mut suite = ConformanceSuite::new(target, genesis);
+	suite.timeout = time::Duration::from_secs(5);
+	suite.ban_checks = true;
+	let report = suite.run();
+
+	for result in &report.results {
+		println!("{}", result);
+	}
+	assert!(report.passed());
+	for name in &[
+		"handshake",
+		"ping_pong",
+		"get_peer_addrs",
+		"get_headers",
+		"get_block",
+		"unknown_message_type",
+		"bad_magic",
+		"oversized_message",
+		"too_many_locators",
+		"truncated_body",
+	] {
+		assert_eq!(report.get(name).unwrap().status, CheckStatus::Pass);
+	}
+	// Loopback peers are banned by ip:port, the reconnect can't be checked
+	match report.get("ban_on_bad_handshake").unwrap().status {
+		CheckStatus::Skipped(_) => {}
+		ref status => panic!("unexpected ban check status {:?}", status),
+	}
+
+	// Wrong genesis never completes the handshake
+	let mut suite = ConformanceSuite::new(target, Hash::from_vec(&[1u8; 32]));
+	suite.timeout = time::Duration::from_secs(2);
+	let report = suite.run();
+	assert!(!report.passed());
+	match report.get("handshake").unwrap().status {
+		CheckStatus::Fail(_) => {}
+		ref status => panic!("unexpected handshake status {:?}", status),
+	}
+
+	server.stop();
+}
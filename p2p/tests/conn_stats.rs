@@ -0,0 +1,118 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::conn_stats::{slow_peer_counters, ConnStats, ConnStatsReader};
+use crate::p2p::{SlowPeerReason, SlowPeerThresholds};
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn thresholds() -> SlowPeerThresholds {
+	SlowPeerThresholds {
+		slow_read_secs: 30,
+		min_avg_read_bytes: 64,
+		max_stalled_read_secs: 60,
+	}
+}
+
+fn secs(s: u64) -> Duration {
+	Duration::from_secs(s)
+}
+
+#[test]
+fn test_conn_stats_rtt_and_reads() {
+	let stats = ConnStats::new(thresholds());
+	let start = Instant::now();
+
+	assert_eq!(stats.stats(start).last_rtt_ms, None);
+	stats.ping_sent(start);
+	stats.ping_sent(start + secs(1));
+	stats.pong_received(start + Duration::from_millis(200));
+	stats.pong_received(start + Duration::from_millis(1600));
+	// Pong without the ping is ignored
+	stats.pong_received(start + secs(2));
+
+	stats.record_read(1000, start);
+	stats.record_read(3000, start + secs(5));
+	stats.message_finished();
+	// Idle time between the messages is not a stall
+	stats.record_read(2000, start + secs(100));
+
+	let s = stats.stats(start + secs(101));
+	assert_eq!(s.last_rtt_ms, Some(600));
+	assert_eq!(s.avg_rtt_ms, Some(400));
+	assert_eq!(s.messages_read, 1);
+	assert_eq!(s.avg_read_bytes, 2000);
+	assert_eq!(s.max_stalled_read_ms, 5000);
+	assert_eq!(s.current_read_ms, Some(1000));
+	assert_eq!(stats.check(start + secs(101)), None);
+}
+
+#[test]
+fn test_conn_stats_slow_peers() {
+	let before = slow_peer_counters();
+
+	// Trickled message: tiny reads for longer than the slow read time
+	let slow = ConnStats::new(thresholds());
+	let start = Instant::now();
+	for i in 0..40 {
+		slow.record_read(2, start + secs(i));
+	}
+	assert_eq!(slow.check(start + secs(20)), None);
+	assert_eq!(slow.check(start + secs(40)), Some(SlowPeerReason::SlowRead));
+	assert_eq!(slow.check(start + secs(41)), Some(SlowPeerReason::SlowRead));
+
+	// Long message with the normal reads is fine
+	let big = ConnStats::new(thresholds());
+	for i in 0..40 {
+		big.record_read(500, start + secs(i));
+	}
+	assert_eq!(big.check(start + secs(40)), None);
+
+	// Stalled in the middle of the message
+	let stalled = ConnStats::new(thresholds());
+	stalled.record_read(500, start);
+	assert_eq!(stalled.check(start + secs(59)), None);
+	assert_eq!(
+		stalled.check(start + secs(61)),
+		Some(SlowPeerReason::StalledRead)
+	);
+
+	// Disabled checks never drop
+	let disabled = ConnStats::new(SlowPeerThresholds::disabled());
+	disabled.record_read(1, start);
+	assert_eq!(disabled.check(start + secs(1000)), None);
+
+	// Every slow connection is counted once
+	let after = slow_peer_counters();
+	assert!(after.slow_read_dropped >= before.slow_read_dropped + 1);
+	assert!(after.stalled_read_dropped >= before.stalled_read_dropped + 1);
+}
+
+#[test]
+fn test_conn_stats_reader() {
+	let stats = Arc::new(ConnStats::new(thresholds()));
+	let data = vec![7u8; 100];
+	let mut reader = ConnStatsReader::new(&data[..], stats.clone());
+	let mut buf = vec![0u8; 100];
+	reader.read_exact(&mut buf).unwrap();
+	stats.message_finished();
+
+	let s = stats.stats(Instant::now());
+	assert_eq!(s.messages_read, 1);
+	assert_eq!(s.avg_read_bytes, 100);
+	assert_eq!(s.current_read_ms, None);
+}
@@ -0,0 +1,84 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use grin_p2p as p2p;
+
+use crate::p2p::types::PeerAddr;
+use crate::p2p::{DialLimits, DialPriority, DialScheduler};
+
+fn addr(i: u8) -> PeerAddr {
+	PeerAddr::Ip(SocketAddr::new(
+		IpAddr::V4(Ipv4Addr::new(10, 0, 0, i)),
+		3414,
+	))
+}
+
+// Dials are limited by the concurrency limit and ordered by priority
+#[test]
+fn test_dial_scheduler_priority() {
+	let mut scheduler = DialScheduler::new(DialLimits {
+		max_concurrent: 8,
+		initial_concurrent: 2,
+		ramp_up_interval: Duration::from_secs(3600),
+		slot_timeout: Duration::from_secs(60),
+	});
+	assert_eq!(scheduler.concurrency_limit(), 2);
+
+	scheduler.schedule(addr(1), DialPriority::New);
+	scheduler.schedule(addr(2), DialPriority::Reputable);
+	scheduler.schedule(addr(3), DialPriority::Anchor);
+	// duplicate with a better priority is moved up, with a worse one is ignored
+	scheduler.schedule(addr(1), DialPriority::Anchor);
+	scheduler.schedule(addr(3), DialPriority::New);
+	assert_eq!(scheduler.queue_len(), 3);
+
+	assert_eq!(scheduler.next_dials(), vec![addr(3), addr(1)]);
+	assert_eq!(scheduler.in_flight_len(), 2);
+	// no free slots
+	assert!(scheduler.next_dials().is_empty());
+	assert!(!scheduler.try_acquire("libp2p", DialPriority::Reputable));
+
+	// address that is dialed now is not queued again
+	scheduler.schedule(addr(3), DialPriority::Anchor);
+	assert_eq!(scheduler.queue_len(), 1);
+
+	scheduler.release(&addr(3).as_key());
+	// queued Reputable dial goes before the New one
+	assert!(!scheduler.try_acquire("libp2p", DialPriority::New));
+	assert!(scheduler.try_acquire("libp2p", DialPriority::Reputable));
+	scheduler.release("libp2p");
+
+	assert_eq!(scheduler.next_dials(), vec![addr(2)]);
+	scheduler.clear_queue();
+	assert_eq!(scheduler.queue_len(), 0);
+}
+
+// Slots that were never released are expired
+#[test]
+fn test_dial_scheduler_slot_timeout() {
+	let mut scheduler = DialScheduler::new(DialLimits {
+		max_concurrent: 1,
+		initial_concurrent: 1,
+		ramp_up_interval: Duration::from_secs(10),
+		slot_timeout: Duration::from_secs(0),
+	});
+	scheduler.schedule(addr(1), DialPriority::New);
+	scheduler.schedule(addr(2), DialPriority::New);
+	assert_eq!(scheduler.next_dials(), vec![addr(1)]);
+	std::thread::sleep(Duration::from_millis(10));
+	assert_eq!(scheduler.next_dials(), vec![addr(2)]);
+}
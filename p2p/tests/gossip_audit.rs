@@ -0,0 +1,96 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::gossip_audit::GossipAuditLog;
+use crate::p2p::{GossipAuditEntry, GossipAuditQuery};
+
+fn entry(timestamp: i64, topic: &str, sender: &str) -> GossipAuditEntry {
+	GossipAuditEntry {
+		timestamp,
+		topic: topic.to_string(),
+		message_id: format!("id{}", timestamp),
+		sender_address: sender.to_string(),
+		source_peer_id: None,
+		propagation_peer_id: "12D3KooWPeer".to_string(),
+		fee: 1_000_000,
+		payload_hash: format!("{:064x}", timestamp),
+	}
+}
+
+#[test]
+fn test_gossip_audit_log() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_root = dir.path().to_str().unwrap();
+	let now = 1_600_000_000;
+	let day = 24 * 3600;
+
+	{
+		let audit =
+			GossipAuditLog::new(db_root, vec!["SwapMarketplace".to_string()], 30, 4).unwrap();
+		assert!(audit.is_audited("SwapMarketplace"));
+		assert!(!audit.is_audited("Other"));
+
+		audit
+			.record(&entry(now - 40 * day, "SwapMarketplace", "alice"))
+			.unwrap();
+		audit
+			.record(&entry(now - 2, "SwapMarketplace", "bob"))
+			.unwrap();
+		audit.record(&entry(now - 1, "Other", "alice")).unwrap();
+		audit
+			.record(&entry(now, "SwapMarketplace", "alice"))
+			.unwrap();
+
+		let all = audit.query(&GossipAuditQuery::default()).unwrap();
+		assert_eq!(all.len(), 4);
+		// Oldest first
+		assert_eq!(all[0].timestamp, now - 40 * day);
+		assert_eq!(all[3], entry(now, "SwapMarketplace", "alice"));
+
+		let query = GossipAuditQuery {
+			topic: Some("SwapMarketplace".to_string()),
+			sender_address: Some("alice".to_string()),
+			from_time: Some(now - day),
+			..Default::default()
+		};
+		assert_eq!(
+			audit.query(&query).unwrap(),
+			vec![entry(now, "SwapMarketplace", "alice")]
+		);
+
+		let query = GossipAuditQuery {
+			limit: Some(2),
+			..Default::default()
+		};
+		let res = audit.query(&query).unwrap();
+		assert_eq!(res.len(), 2);
+		assert_eq!(res[1].timestamp, now);
+
+		// Expired record is pruned
+		assert_eq!(audit.prune(now).unwrap(), 1);
+		assert_eq!(audit.query(&GossipAuditQuery::default()).unwrap().len(), 3);
+	}
+
+	// Records are persistent, size limit drops the oldest ones
+	{
+		let audit =
+			GossipAuditLog::new(db_root, vec!["SwapMarketplace".to_string()], 30, 2).unwrap();
+		assert_eq!(audit.prune(now).unwrap(), 1);
+		let res = audit.query(&GossipAuditQuery::default()).unwrap();
+		assert_eq!(res.len(), 2);
+		assert_eq!(res[0].timestamp, now - 1);
+	}
+}
@@ -0,0 +1,107 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+
+use crate::core::ser;
+use crate::p2p::gossip_probe::{
+	GossipProbeMessage, GossipProbes, ProbeLatencyStats, PROBE_ECHO_TIMEOUT_MS,
+	PROBE_MAX_MESSAGES_PER_MIN,
+};
+
+#[test]
+fn test_probe_message_ser() {
+	let messages = vec![
+		GossipProbeMessage::Probe { seq: 7 },
+		GossipProbeMessage::Echo {
+			origin: "originonion".to_string(),
+			seq: 7,
+			relayed: true,
+		},
+	];
+	for message in messages {
+		let data = ser::ser_vec(&message, ser::ProtocolVersion(1)).unwrap();
+		let read: GossipProbeMessage =
+			ser::deserialize(&mut &data[..], ser::ProtocolVersion(1)).unwrap();
+		assert_eq!(read, message);
+	}
+	let res: Result<GossipProbeMessage, _> =
+		ser::deserialize(&mut &[5u8, 0, 0][..], ser::ProtocolVersion(1));
+	assert!(res.is_err());
+}
+
+#[test]
+fn test_probe_round_trip() {
+	let mut probes = GossipProbes::new(300);
+	let seq = match probes.next_probe(10_000) {
+		GossipProbeMessage::Probe { seq } => seq,
+		_ => panic!("probe is expected"),
+	};
+	assert!(probes.on_echo("peer1", seq, false, 10_400));
+	assert!(probes.on_echo("peer2", seq, true, 11_000));
+	assert!(probes.on_echo("peer3", seq, true, 12_000));
+	// Duplicate, unknown and late echoes are not counted
+	assert!(!probes.on_echo("peer1", seq, false, 12_000));
+	assert!(!probes.on_echo("peer4", seq + 1, false, 12_000));
+	assert!(!probes.on_echo("peer4", seq, false, 10_000 + PROBE_ECHO_TIMEOUT_MS + 1));
+
+	let stats = probes.stats();
+	assert!(stats.enabled);
+	assert_eq!(stats.probes_sent, 1);
+	assert_eq!(stats.echoes_received, 3);
+	assert_eq!(stats.responders, 3);
+	assert_eq!(stats.all.unwrap().samples, 3);
+	assert_eq!(stats.direct.unwrap().max_ms, 400);
+	let relayed = stats.relayed.unwrap();
+	assert_eq!(relayed.min_ms, 1000);
+	assert_eq!(relayed.max_ms, 2000);
+}
+
+#[test]
+fn test_probe_echo_and_rate_limit() {
+	let mut probes = GossipProbes::new(300);
+	assert_eq!(
+		probes.on_probe("origin", 1, true, 1000),
+		Some(GossipProbeMessage::Echo {
+			origin: "origin".to_string(),
+			seq: 1,
+			relayed: true,
+		})
+	);
+	// Same origin is answered not too often
+	assert_eq!(probes.on_probe("origin", 2, false, 2000), None);
+	assert!(probes.on_probe("other", 1, false, 2000).is_some());
+	assert!(probes.on_probe("origin", 3, false, 60_000).is_some());
+
+	for i in 0..PROBE_MAX_MESSAGES_PER_MIN {
+		assert!(probes.allow_message("spammer", 1000 + i as i64));
+	}
+	assert!(!probes.allow_message("spammer", 2000));
+	assert!(probes.allow_message("origin", 2000));
+	// The limit is per minute
+	assert!(probes.allow_message("spammer", 62_000));
+}
+
+#[test]
+fn test_latency_stats() {
+	assert_eq!(ProbeLatencyStats::from_samples(vec![]), None);
+	let stats = ProbeLatencyStats::from_samples((1..=10).rev().map(|i| i * 100).collect()).unwrap();
+	assert_eq!(stats.samples, 10);
+	assert_eq!(stats.min_ms, 100);
+	assert_eq!(stats.median_ms, 600);
+	assert_eq!(stats.p90_ms, 1000);
+	assert_eq!(stats.max_ms, 1000);
+	assert_eq!(stats.mean_ms, 550);
+}
@@ -0,0 +1,90 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use grin_p2p as p2p;
+
+use crate::p2p::handoff::{self, HandoffState, HANDOFF_VERSION};
+use crate::p2p::types::{Capabilities, PeerAddr, ReasonForBan};
+use crate::p2p::{PeerData, State};
+
+fn free_addr() -> SocketAddr {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	listener.local_addr().unwrap()
+}
+
+#[test]
+fn test_handoff() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_root = dir.path().to_str().unwrap().to_string();
+
+	// Old node listener with SO_REUSEPORT, the new node can bind the same port
+	handoff::set_reuse_port(true);
+	let addr = free_addr();
+	let old_listener = handoff::bind_listener(addr).unwrap();
+	handoff::prebind(addr).unwrap();
+	let new_listener = handoff::bind_listener(addr).unwrap();
+	assert_eq!(new_listener.local_addr().unwrap(), addr);
+	drop(old_listener);
+
+	let peer = PeerData {
+		addr: PeerAddr::Ip(SocketAddr::new(
+			IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+			3414,
+		)),
+		capabilities: Capabilities::FULL_NODE,
+		user_agent: "MW/MWC 4.4.2".to_string(),
+		flags: State::Healthy,
+		last_banned: 0,
+		ban_reason: ReasonForBan::None,
+		last_connected: 100,
+	};
+	let (done_tx, done_rx) = mpsc::channel();
+	let mut signal = handoff::handoff_signal();
+	assert_eq!(signal.try_recv(), Ok(None));
+	let state_peer = peer.clone();
+	handoff::serve_handoff(
+		handoff::handoff_socket_path(&db_root),
+		move || HandoffState {
+			version: HANDOFF_VERSION,
+			peers: vec![state_peer.clone()],
+			txs: vec![],
+		},
+		move || done_tx.send(()).unwrap(),
+	)
+	.unwrap();
+
+	let state = handoff::request_handoff(&handoff::handoff_socket_path(&db_root)).unwrap();
+	assert_eq!(state.peers.len(), 1);
+	assert_eq!(state.peers[0].addr, peer.addr);
+	assert!(state.txs.is_empty());
+
+	// Old node is done once the new node confirmed the handoff
+	done_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+	assert!(handoff::is_handed_off());
+	assert!(!handoff::handoff_socket_path(&db_root).exists());
+	// Listeners are notified, the late ones as well
+	assert_eq!(signal.try_recv(), Ok(Some(())));
+	assert_eq!(handoff::handoff_signal().try_recv(), Ok(Some(())));
+
+	handoff::set_pending_state(state);
+	assert!(handoff::has_pending_state());
+	assert!(handoff::take_pending_state().is_some());
+	assert!(!handoff::has_pending_state());
+}
@@ -0,0 +1,101 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+use grin_util as util;
+
+use crate::p2p::integrity_budget::{
+	self, INTEGRITY_CALL_HISTORY_LEN_LIMIT, INTEGRITY_CALL_MAX_PERIOD,
+};
+use crate::p2p::Libp2pPolicy;
+use crate::util::secp::pedersen::Commitment;
+
+// Rate budget follows the peers rate limit, usage is tracked for the registered kernels only
+#[test]
+fn test_integrity_budget() {
+	let now = 1_600_000_000;
+	assert_eq!(integrity_budget::publish_rate_budget(&[], now), 9);
+	// Messages published long ago don't limit the new ones
+	let old: Vec<i64> = (0..10)
+		.map(|i| now - 1000 + i * INTEGRITY_CALL_MAX_PERIOD)
+		.collect();
+	assert!(integrity_budget::publish_rate_budget(&old, now) > 0);
+	// Burst of the messages exhausts the budget
+	let burst: Vec<i64> = (0..9).map(|i| now - 9 + i).collect();
+	assert_eq!(integrity_budget::publish_rate_budget(&burst, now), 0);
+
+	let kernel1 = Commitment::from_vec(vec![8; 33]);
+	let kernel2 = Commitment::from_vec(vec![9; 33]);
+	assert!(integrity_budget::register_integrity_kernel(kernel1.clone()));
+	assert!(!integrity_budget::register_integrity_kernel(
+		kernel1.clone()
+	));
+
+	for i in 0..12 {
+		integrity_budget::record_integrity_publish(&kernel1, now + i);
+		integrity_budget::record_integrity_publish(&kernel2, now + i);
+	}
+	let kernels = integrity_budget::integrity_kernels();
+	assert_eq!(kernels.len(), 1);
+	assert_eq!(kernels[0].excess, kernel1);
+	assert_eq!(kernels[0].messages_published, 12);
+	assert_eq!(kernels[0].recent_publishes.len(), 10);
+	assert_eq!(
+		integrity_budget::publish_rate_budget(&kernels[0].recent_publishes, now + 12),
+		0
+	);
+
+	assert!(integrity_budget::remove_integrity_kernel(&kernel1));
+	assert!(!integrity_budget::remove_integrity_kernel(&kernel1));
+	assert!(integrity_budget::integrity_kernels().is_empty());
+}
+
+// Policy defaults match the network limits, the topics can have the different rate
+#[test]
+fn test_libp2p_policy() {
+	let mut policy = Libp2pPolicy::default();
+	assert!(policy.validate().is_ok());
+	assert_eq!(
+		policy.min_call_period("SwapMarketplace"),
+		INTEGRITY_CALL_MAX_PERIOD as f64
+	);
+	assert_eq!(
+		policy.call_history_window_secs(),
+		INTEGRITY_CALL_HISTORY_LEN_LIMIT as i64 * INTEGRITY_CALL_MAX_PERIOD
+	);
+
+	policy
+		.topic_rate_multipliers
+		.insert("SwapMarketplace".to_string(), 3.0);
+	policy
+		.topic_rate_multipliers
+		.insert("SlowTopic".to_string(), 0.5);
+	assert_eq!(policy.min_call_period("SwapMarketplace"), 5.0);
+	assert_eq!(policy.min_call_period("SlowTopic"), 30.0);
+	assert_eq!(policy.min_call_period("OtherTopic"), 15.0);
+	// Cleanup keeps the history of the slowest topic
+	assert_eq!(policy.call_history_window_secs(), 300);
+	assert!(policy.validate().is_ok());
+
+	policy
+		.topic_rate_multipliers
+		.insert("BadTopic".to_string(), 0.0);
+	assert!(policy.validate().is_err());
+
+	let policy = Libp2pPolicy {
+		integrity_call_history_len: 1,
+		..Libp2pPolicy::default()
+	};
+	assert!(policy.validate().is_err());
+}
@@ -0,0 +1,71 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+use grin_util as util;
+
+use chrono::{TimeZone, Utc};
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::core::core::hash::Hash;
+use crate::p2p::ReasonForBan;
+use crate::util::clock::{self, VirtualClock};
+use crate::util::StopState;
+
+const ONION: &str = "2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid";
+
+// Libp2p bans are made and expired on the node clock
+#[test]
+fn test_libp2p_ban_expiry_virtual_clock() {
+	let db_root = ".grin_libp2p_ban_expiry";
+	let _ = fs::remove_dir_all(db_root);
+
+	let start = Utc.timestamp(1_600_000_000, 0);
+	let virtual_clock = Arc::new(VirtualClock::new(start));
+	clock::set_local_clock(virtual_clock.clone());
+
+	let server = p2p::Server::new(
+		db_root,
+		p2p::Capabilities::UNKNOWN,
+		p2p::P2PConfig::default(),
+		Arc::new(p2p::DummyAdapter {}),
+		Hash::from_vec(&vec![]),
+		Arc::new(StopState::new()),
+		0,
+		None,
+	)
+	.unwrap();
+	let peers = server.peers.clone();
+
+	let ban = peers
+		.ban_libp2p_peer(ONION, ReasonForBan::ManualBan, Some(100))
+		.unwrap();
+	assert_eq!(ban.banned_at, start.timestamp());
+	assert_eq!(ban.expires_at, Some(start.timestamp() + 100));
+
+	virtual_clock.advance(Duration::from_secs(99));
+	peers.update_libp2p_bans().unwrap();
+	assert_eq!(peers.libp2p_bans().unwrap(), vec![ban]);
+
+	virtual_clock.advance(Duration::from_secs(1));
+	assert!(peers.libp2p_bans().unwrap().is_empty());
+	peers.update_libp2p_bans().unwrap();
+	assert!(peers.unban_libp2p_peer(ONION).is_err());
+
+	clock::reset_local_clock();
+	let _ = fs::remove_dir_all(db_root);
+}
@@ -0,0 +1,100 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+use grin_util::StopState;
+
+use std::fs;
+use std::sync::Arc;
+
+use crate::core::core::hash::Hash;
+use crate::core::ser;
+use crate::p2p::{Libp2pPeerBan, ReasonForBan};
+
+const ONION: &str = "2a6at2obto3uvkpkitqp4wxcg6u36qf534eucbskqciturczzc5suyid";
+
+#[test]
+fn test_libp2p_ban_ser() {
+	for ban in vec![
+		Libp2pPeerBan::new(ONION, ReasonForBan::BadGossip, 1000, Some(2000)),
+		Libp2pPeerBan::new(ONION, ReasonForBan::ManualBan, 1000, None),
+	] {
+		let data = ser::ser_vec(&ban, ser::ProtocolVersion(1)).unwrap();
+		let ban2: Libp2pPeerBan =
+			ser::deserialize(&mut &data[..], ser::ProtocolVersion(1)).unwrap();
+		assert_eq!(ban, ban2);
+	}
+
+	let ban = Libp2pPeerBan::new(
+		&format!("{}.onion", ONION.to_uppercase()),
+		ReasonForBan::ManualBan,
+		1000,
+		Some(2000),
+	);
+	assert_eq!(ban.address, ONION);
+	assert!(!ban.is_expired(1999));
+	assert!(ban.is_expired(2000));
+	assert!(!Libp2pPeerBan::new(ONION, ReasonForBan::ManualBan, 1000, None).is_expired(i64::MAX));
+}
+
+// Bans are persistent and can be removed by the operator
+#[test]
+fn test_libp2p_ban_store() {
+	let db_root = ".grin_libp2p_bans";
+	let _ = fs::remove_dir_all(db_root);
+	let server = p2p::Server::new(
+		db_root,
+		p2p::Capabilities::UNKNOWN,
+		p2p::P2PConfig::default(),
+		Arc::new(p2p::DummyAdapter {}),
+		Hash::from_vec(&vec![]),
+		Arc::new(StopState::new()),
+		0,
+		None,
+	)
+	.unwrap();
+	let peers = server.peers.clone();
+
+	assert!(peers.libp2p_bans().unwrap().is_empty());
+	assert!(peers
+		.ban_libp2p_peer("not an onion", ReasonForBan::ManualBan, None)
+		.is_err());
+	assert!(peers.unban_libp2p_peer(ONION).is_err());
+
+	// Default ban lasts for the ban window
+	let ban = peers
+		.ban_libp2p_peer(&format!("{}.onion", ONION), ReasonForBan::ManualBan, None)
+		.unwrap();
+	assert_eq!(ban.address, ONION);
+	assert_eq!(
+		ban.expires_at,
+		Some(ban.banned_at + p2p::P2PConfig::default().ban_window())
+	);
+	assert_eq!(peers.libp2p_bans().unwrap(), vec![ban]);
+
+	// New ban replaces the old one
+	let ban = peers
+		.ban_libp2p_peer(ONION, ReasonForBan::BadGossip, Some(0))
+		.unwrap();
+	assert_eq!(ban.expires_at, None);
+	peers.update_libp2p_bans().unwrap();
+	assert_eq!(peers.libp2p_bans().unwrap(), vec![ban]);
+
+	peers.unban_libp2p_peer(ONION).unwrap();
+	assert!(peers.libp2p_bans().unwrap().is_empty());
+	assert!(peers.unban_libp2p_peer(ONION).is_err());
+
+	let _ = fs::remove_dir_all(db_root);
+}
@@ -0,0 +1,140 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::libp2p_dial::{
+	classify_dial_error, DialBackoff, DialFailure, DIAL_COOLDOWN_SECS, MAX_DIAL_RETRIES,
+	PEER_BACKOFF_BASE_SECS, PEER_BACKOFF_MAX_SECS, PROXY_BACKOFF_BASE_SECS,
+};
+use std::io;
+
+const ADDRESS: &str = "xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad";
+
+#[test]
+fn test_dial_error_classification() {
+	let err = |kind| io::Error::new(kind, "test");
+	assert_eq!(
+		classify_dial_error(&err(io::ErrorKind::ConnectionRefused)),
+		Some(DialFailure::ProxyDown)
+	);
+	assert_eq!(
+		classify_dial_error(&err(io::ErrorKind::ConnectionReset)),
+		Some(DialFailure::ProxyDown)
+	);
+	assert_eq!(
+		classify_dial_error(&err(io::ErrorKind::TimedOut)),
+		Some(DialFailure::Timeout)
+	);
+	// SOCKS reply errors
+	assert_eq!(
+		classify_dial_error(&err(io::ErrorKind::Other)),
+		Some(DialFailure::TargetUnreachable)
+	);
+	// Cancelled and busy dials are nobody's fault
+	assert_eq!(classify_dial_error(&err(io::ErrorKind::Interrupted)), None);
+	assert_eq!(classify_dial_error(&err(io::ErrorKind::WouldBlock)), None);
+}
+
+#[test]
+fn test_peer_backoff() {
+	let mut backoff = DialBackoff::new();
+	assert!(backoff.is_dial_allowed(ADDRESS, 1000));
+
+	let retry_at = backoff.record_failure(ADDRESS, DialFailure::TargetUnreachable, 1000);
+	assert_eq!(retry_at, 1000 + PEER_BACKOFF_BASE_SECS);
+	// Address form doesn't matter
+	let onion = format!("{}.onion", ADDRESS.to_uppercase());
+	assert!(!backoff.is_dial_allowed(&onion, 1000));
+	assert!(backoff.is_dial_allowed("other", 1000));
+	assert!(backoff.is_dial_allowed(ADDRESS, retry_at));
+	// Failed peer is kept for the retry
+	assert!(backoff.retries(1000).is_empty());
+	assert_eq!(backoff.retries(retry_at), vec![ADDRESS.to_string()]);
+
+	// Backoff doubles up to the max
+	let retry_at = backoff.record_failure(ADDRESS, DialFailure::Timeout, retry_at);
+	assert_eq!(retry_at, 1000 + 3 * PEER_BACKOFF_BASE_SECS);
+	let mut now = retry_at;
+	for _ in 2..MAX_DIAL_RETRIES - 1 {
+		now = backoff.record_failure(ADDRESS, DialFailure::Timeout, now);
+	}
+	let retry_at = backoff.record_failure(ADDRESS, DialFailure::Timeout, now);
+	assert_eq!(retry_at, now + PEER_BACKOFF_MAX_SECS);
+	assert_eq!(backoff.retries(retry_at), vec![ADDRESS.to_string()]);
+
+	// Too many failures, the peer goes to the cooldown list
+	now = retry_at;
+	assert_eq!(
+		backoff.record_failure(ADDRESS, DialFailure::Timeout, now),
+		now + DIAL_COOLDOWN_SECS
+	);
+	assert!(backoff.retries(now + DIAL_COOLDOWN_SECS).is_empty());
+	assert!(!backoff.is_dial_allowed(ADDRESS, now + DIAL_COOLDOWN_SECS - 1));
+	assert!(backoff.is_dial_allowed(ADDRESS, now + DIAL_COOLDOWN_SECS));
+
+	let stats = backoff.stats(now);
+	assert_eq!(stats.attempts, MAX_DIAL_RETRIES as u64 + 1);
+	assert_eq!(stats.target_unreachable, 1);
+	assert_eq!(stats.timeouts, MAX_DIAL_RETRIES as u64);
+	assert_eq!(stats.peers_in_backoff, 1);
+	assert_eq!(stats.peers_in_cooldown, 1);
+
+	// Single attempt after the cooldown
+	now += DIAL_COOLDOWN_SECS;
+	assert_eq!(
+		backoff.record_failure(ADDRESS, DialFailure::TargetUnreachable, now),
+		now + DIAL_COOLDOWN_SECS
+	);
+
+	// Success resets the backoff
+	backoff.record_success(ADDRESS);
+	assert!(backoff.is_dial_allowed(ADDRESS, now));
+	assert_eq!(backoff.stats(now).peers_in_backoff, 0);
+
+	backoff.record_failure(ADDRESS, DialFailure::TargetUnreachable, now);
+	backoff.forget(ADDRESS);
+	assert!(backoff.is_dial_allowed(ADDRESS, now));
+}
+
+#[test]
+fn test_proxy_backoff() {
+	let mut backoff = DialBackoff::new();
+	let until = backoff.record_failure(ADDRESS, DialFailure::ProxyDown, 1000);
+	assert_eq!(until, 1000 + PROXY_BACKOFF_BASE_SECS);
+	assert!(backoff.is_proxy_down(1000));
+	// The peer is not penalized
+	assert!(backoff.is_dial_allowed(ADDRESS, 1000));
+	assert_eq!(backoff.stats(1000).proxy_down_until, Some(until));
+
+	let until = backoff.record_failure(ADDRESS, DialFailure::ProxyDown, until);
+	assert_eq!(until, 1000 + 3 * PROXY_BACKOFF_BASE_SECS);
+	assert!(!backoff.is_proxy_down(until));
+	assert_eq!(backoff.stats(until).proxy_down_until, None);
+
+	// The proxy that reports the unreachable peer is up, next failure starts from the base
+	backoff.record_failure(ADDRESS, DialFailure::TargetUnreachable, until);
+	assert_eq!(
+		backoff.record_failure(ADDRESS, DialFailure::ProxyDown, until),
+		until + PROXY_BACKOFF_BASE_SECS
+	);
+
+	backoff.record_cancelled();
+	backoff.record_busy();
+	let stats = backoff.stats(until);
+	assert_eq!(stats.proxy_down, 3);
+	assert_eq!(stats.cancelled, 1);
+	assert_eq!(stats.busy, 1);
+	assert_eq!(stats.attempts, 4);
+}
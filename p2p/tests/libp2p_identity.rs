@@ -0,0 +1,72 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+use grin_util::StopState;
+
+use std::fs;
+use std::sync::Arc;
+
+use crate::core::core::hash::Hash;
+use crate::p2p::types::PeerAddr;
+use crate::p2p::{PeerData, State};
+
+const ONION_1: &str = "fla6ctzbhhdtslbtqx6pnvqfbkzbiwn7qbvmxn6pvgxgbbtmdsy43bad";
+const ONION_2: &str = "rkdhbxq4wyjtlmvt2g6zzgqpz6xwvbq3apmtl4vbuuexvrn2yynqwpqd";
+
+// Rotated onion key is detected and the old address is forgotten
+#[test]
+fn test_libp2p_identity_change() {
+	let db_root = ".grin_libp2p_identity";
+	let _ = fs::remove_dir_all(db_root);
+	let server = p2p::Server::new(
+		db_root,
+		p2p::Capabilities::UNKNOWN,
+		p2p::P2PConfig::default(),
+		Arc::new(p2p::DummyAdapter {}),
+		Hash::from_vec(&vec![]),
+		Arc::new(StopState::new()),
+		0,
+		None,
+	)
+	.unwrap();
+	let peers = server.peers.clone();
+
+	// First run, nothing to compare with
+	assert!(!peers.check_libp2p_identity(ONION_1).unwrap());
+	assert!(!peers
+		.check_libp2p_identity(&format!("{}.onion", ONION_1))
+		.unwrap());
+
+	// Our old address was learned from the peers
+	let old_addr = PeerAddr::Onion(format!("{}.onion", ONION_1));
+	peers
+		.save_peer(&PeerData {
+			addr: old_addr.clone(),
+			capabilities: p2p::Capabilities::FULL_NODE,
+			user_agent: "MW/MWC test".to_string(),
+			flags: State::Healthy,
+			last_banned: 0,
+			ban_reason: p2p::ReasonForBan::None,
+			last_connected: 0,
+		})
+		.unwrap();
+
+	assert!(peers.check_libp2p_identity(ONION_2).unwrap());
+	assert!(!peers.exists_peer(old_addr).unwrap());
+	assert!(!peers.check_libp2p_identity(ONION_2).unwrap());
+
+	let _ = fs::remove_dir_all(db_root);
+}
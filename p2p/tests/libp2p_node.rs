@@ -0,0 +1,525 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "marketplace")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::executor::block_on;
+use futures::io::Cursor;
+use grin_p2p as p2p;
+use libp2p::core::SimplePushSerializer;
+use libp2p::gossipsub::IdentTopic as Topic;
+use libp2p::request_response::RequestResponseCodec;
+use libp2p::PeerId;
+use serde_json::json;
+
+use crate::p2p::libp2p_connection::{
+	gossipsub_config, relay_nodes, sync_topic_handler, tcp_peers, MessageContext, ReceivedMessage,
+};
+use crate::p2p::libp2p_kad::{
+	build_kademlia, kad_stats, topic_key, DEFAULT_KAD_QUERY_INTERVAL_SECS,
+};
+use crate::p2p::libp2p_query::{QueryCodec, QueryProtocol, HAS_KERNEL_METHOD, MAX_QUERY_SIZE};
+use crate::p2p::libp2p_relay::{relayed_dial_addr, DEFAULT_RELAY_MAX_CONNECTIONS};
+use crate::p2p::libp2p_schema::{
+	decode_integrity_payload, decode_payload, default_payload_schema, integrity_message_version,
+	payload_versions,
+};
+use crate::p2p::{
+	query_handler, read_message_data, register_payload_schema, unregister_payload_schema,
+	Libp2pConfig, Libp2pIdentity, Libp2pNode, Libp2pPeerAccess, Libp2pTransport, PayloadDecoding,
+	PeerAddr, QueryRequest, QueryResponse,
+};
+
+const PEER_A: &str = "fzm2rjfz6kn4vn6loomojmjqasnvjnkhzm4nyuqvqyuk4rcuxvhxqqyd";
+const PEER_B: &str = "xiqz3dvxhdrxu5ypvdqndrzqmytpqy7xnerr3zwgnnl3nk5uhsxwmnqd";
+
+fn handler(_context: &MessageContext, _data: Vec<u8>) -> bool {
+	true
+}
+
+#[test]
+fn test_libp2p_nodes_are_isolated() {
+	let node1 = Arc::new(Libp2pNode::new());
+	let node2 = Arc::new(Libp2pNode::new());
+
+	node1
+		.add_new_peer(&PeerAddr::Onion(format!("{}.onion", PEER_A)))
+		.unwrap();
+	node2
+		.add_new_peer(&PeerAddr::Onion(PEER_B.to_string()))
+		.unwrap();
+	assert_eq!(node1.discovered_peers(), vec![PEER_A.to_string()]);
+	assert_eq!(node2.discovered_peers(), vec![PEER_B.to_string()]);
+
+	// Handle clones share the node
+	let handle = node1.clone();
+	handle
+		.add_topic("NodeTopic", sync_topic_handler(handler))
+		.unwrap();
+	assert_eq!(node1.handled_topics(), vec![Topic::new("NodeTopic").hash()]);
+	assert!(node2.handled_topics().is_empty());
+
+	node1.remove_topic("NodeTopic");
+	assert!(handle.handled_topics().is_empty());
+
+	// Nodes are not running until the swarm is created
+	assert!(!node1.is_running());
+	assert!(node1.connections().is_empty());
+	assert!(node1.this_peer_id().is_none());
+}
+
+fn received_message(peer_id: &str, message: &str) -> ReceivedMessage {
+	ReceivedMessage {
+		timestamp: chrono::Utc::now().timestamp(),
+		peer_id: peer_id.to_string(),
+		topic: "MessagingTopic".to_string(),
+		fee: 1,
+		message: message.to_string(),
+		message_id: String::new(),
+		content_hash: String::new(),
+		source_peer_id: None,
+		propagation_peer_id: String::new(),
+	}
+}
+
+#[test]
+fn test_libp2p_node_messaging_isolated() {
+	let node1 = Libp2pNode::new();
+	let node2 = Libp2pNode::new();
+
+	assert!(node1.listen_topic(&"MessagingTopic".to_string(), 1));
+	// Updating the fee doesn't add another listener
+	assert!(!node1.listen_topic(&"MessagingTopic".to_string(), 2));
+	assert_eq!(node1.listened_topics().len(), 1);
+	assert_eq!(node1.listened_topics()[0].2, 2);
+	assert_eq!(node1.topic_handlers("MessagingTopic").len(), 1);
+	assert!(node2.listened_topics().is_empty());
+	assert!(node2.handled_topics().is_empty());
+
+	node1.inject_received_messages(vec![
+		received_message(PEER_A, "{\"a\":1}"),
+		received_message(PEER_B, "{\"a\":1}"),
+		// The same message from the same peer is kept once
+		received_message(PEER_A, "{\"a\":1}"),
+	]);
+	node2.inject_received_messages(vec![received_message(PEER_B, "{\"b\":2}")]);
+	assert_eq!(node1.received_messages_num(), 2);
+	assert_eq!(node2.received_messages_num(), 1);
+
+	let messages = node1.received_messages(true);
+	assert_eq!(messages.len(), 2);
+	assert_eq!(messages[0].peer_id, PEER_B);
+	assert_eq!(messages[1].peer_id, PEER_A);
+	assert_eq!(node1.received_messages_num(), 0);
+	let messages = node2.received_messages(false);
+	assert_eq!(messages.len(), 1);
+	assert_eq!(messages[0].message, "{\"b\":2}");
+	assert_eq!(node2.received_messages_num(), 1);
+
+	assert!(!node2.stop_listening_topic(&"MessagingTopic".to_string()));
+	assert!(node1.stop_listening_topic(&"MessagingTopic".to_string()));
+	assert!(node1.listened_topics().is_empty());
+	assert!(node1.handled_topics().is_empty());
+	assert!(node1.gossip_diagnostics().is_empty());
+}
+
+#[test]
+fn test_libp2p_node_access_isolated() {
+	let node1 = Libp2pNode::new();
+	let node2 = Libp2pNode::new();
+	assert!(node1.is_peer_allowed(PEER_A) && node1.is_peer_allowed(PEER_B));
+
+	node1.set_peer_access(&vec![Libp2pPeerAccess::new(PEER_A, false)]);
+	assert!(!node1.is_peer_allowed(&format!("{}.onion", PEER_A)));
+	assert!(node1.is_peer_allowed(PEER_B));
+	assert!(node2.is_peer_allowed(PEER_A));
+
+	// With the allowed peers only they are accepted
+	node2.set_peer_access(&vec![Libp2pPeerAccess::new(PEER_B, true)]);
+	assert!(!node2.is_peer_allowed(PEER_A));
+	assert!(node2.is_peer_allowed(PEER_B));
+	assert!(node1.is_peer_allowed(PEER_B));
+
+	// Denied peers are not discovered
+	node1
+		.add_new_peer(&PeerAddr::Onion(PEER_A.to_string()))
+		.unwrap();
+	node2
+		.add_new_peer(&PeerAddr::Onion(PEER_B.to_string()))
+		.unwrap();
+	assert!(node1.discovered_peers().is_empty());
+	assert_eq!(node2.discovered_peers(), vec![PEER_B.to_string()]);
+
+	node1.set_peer_access(&vec![]);
+	assert!(node1.is_peer_allowed(PEER_A));
+	assert!(!node2.is_peer_allowed(PEER_A));
+}
+
+fn other_handler(_context: &MessageContext, _data: Vec<u8>) -> bool {
+	false
+}
+
+#[test]
+fn test_libp2p_topic_handlers() {
+	let node = Libp2pNode::new();
+	let id1 = node
+		.add_topic("SharedTopic", sync_topic_handler(handler))
+		.unwrap();
+	let id2 = node
+		.add_topic("SharedTopic", sync_topic_handler(other_handler))
+		.unwrap();
+	assert_ne!(id1, id2);
+	// Second handler doesn't replace the first one
+	assert_eq!(node.topic_handlers("SharedTopic"), vec![id1, id2]);
+
+	assert!(node.remove_handler("SharedTopic", id1));
+	assert!(!node.remove_handler("SharedTopic", id1));
+	assert_eq!(node.topic_handlers("SharedTopic"), vec![id2]);
+	assert_eq!(node.handled_topics().len(), 1);
+
+	// Topic is dropped with its last handler
+	assert!(node.remove_handler("SharedTopic", id2));
+	assert!(node.handled_topics().is_empty());
+	assert!(!node.remove_handler("OtherTopic", id2));
+}
+
+#[test]
+fn test_libp2p_topic_rules() {
+	let node = Libp2pNode::new();
+	let mut config = Libp2pConfig::default();
+	assert!(config.is_topic_allowed("AnyTopic"));
+
+	config.topic_allowlist = vec!["swap_*".to_string(), "Exact".to_string()];
+	config.topic_denylist = vec!["swap_spam".to_string()];
+	assert!(config.is_topic_allowed("swap_btc"));
+	assert!(config.is_topic_allowed("Exact"));
+	assert!(!config.is_topic_allowed("Exact2"));
+	assert!(!config.is_topic_allowed("swap_spam"));
+	assert!(!config.is_topic_allowed("AnyTopic"));
+
+	node.set_config(config).unwrap();
+	assert!(node
+		.add_topic("swap_btc", sync_topic_handler(handler))
+		.is_ok());
+	assert!(node
+		.add_topic("swap_spam", sync_topic_handler(handler))
+		.is_err());
+	assert!(node
+		.add_topic("AnyTopic", sync_topic_handler(handler))
+		.is_err());
+	assert_eq!(node.handled_topics(), vec![Topic::new("swap_btc").hash()]);
+}
+
+#[test]
+fn test_libp2p_gossipsub_config() {
+	let config: Libp2pConfig = serde_json::from_str("{}").unwrap();
+	assert_eq!(config, Libp2pConfig::default());
+	let gossip = gossipsub_config(&config).unwrap();
+	assert_eq!(gossip.heartbeat_interval(), Duration::from_secs(3));
+
+	let config = Libp2pConfig {
+		heartbeat_interval_ms: 500,
+		mesh_n: Some(8),
+		mesh_n_low: Some(6),
+		mesh_n_high: Some(16),
+		..Libp2pConfig::default()
+	};
+	let gossip = gossipsub_config(&config).unwrap();
+	assert_eq!(gossip.heartbeat_interval(), Duration::from_millis(500));
+	assert_eq!(gossip.mesh_n(), 8);
+	assert_eq!(gossip.mesh_n_high(), 16);
+
+	let node = Libp2pNode::new();
+	node.set_config(config.clone()).unwrap();
+
+	// Mesh sizes must be ordered, invalid config doesn't replace the current one
+	let invalid = Libp2pConfig {
+		mesh_n: Some(20),
+		..config.clone()
+	};
+	assert!(gossipsub_config(&invalid).is_err());
+	assert!(node.set_config(invalid).is_err());
+	assert!(node
+		.set_config(Libp2pConfig {
+			heartbeat_interval_ms: 0,
+			..Libp2pConfig::default()
+		})
+		.is_err());
+	assert_eq!(node.config(), config);
+}
+
+#[test]
+fn test_libp2p_tcp_transport_config() {
+	let config: Libp2pConfig = serde_json::from_str(
+		r#"{"transport": "tor_and_tcp", "tcp_port": 4000, "tcp_peers": ["/ip4/10.0.0.1/tcp/3418/p2p/fzm2rjfz6kn4vn6loomojmjqasnvjnkhzm4nyuqvqyuk4rcuxvhxqqyd"]}"#,
+	)
+	.unwrap();
+	assert_eq!(config.transport, Libp2pTransport::TorAndTcp);
+	assert!(config.transport.has_tor() && config.transport.has_tcp());
+	assert!(!Libp2pTransport::Tcp.has_tor());
+	assert!(!Libp2pTransport::default().has_tcp());
+	assert_eq!(config.tcp_port, 4000);
+
+	let peers = tcp_peers(&config).unwrap();
+	assert_eq!(peers.len(), 1);
+	assert_eq!(peers[0].0.to_string(), "/ip4/10.0.0.1/tcp/3418");
+	assert_eq!(peers[0].1.to_string(), PEER_A);
+
+	let node = Libp2pNode::new();
+	node.set_config(config.clone()).unwrap();
+	// Peer id is required, so the dialed peer is authenticated
+	assert!(node
+		.set_config(Libp2pConfig {
+			tcp_peers: vec!["/ip4/10.0.0.1/tcp/3418".to_string()],
+			..config.clone()
+		})
+		.is_err());
+	assert!(node
+		.set_config(Libp2pConfig {
+			tcp_peers: vec!["/ip4/10.0.0.1/tcp/3418/p2p/not_a_peer".to_string()],
+			..config.clone()
+		})
+		.is_err());
+	assert_eq!(node.config(), config);
+}
+
+#[test]
+fn test_libp2p_relay_config() {
+	let config = Libp2pConfig::default();
+	assert!(!config.relay_hop);
+	assert!(relay_nodes(&config).unwrap().is_empty());
+	assert_eq!(
+		config.relay_limits().max_connections,
+		DEFAULT_RELAY_MAX_CONNECTIONS
+	);
+
+	let config: Libp2pConfig = serde_json::from_str(&format!(
+		r#"{{"relay_hop": true, "relay_nodes": ["/onion3/{}:81/p2p/{}"], "relay_total_bytes_per_sec": 0}}"#,
+		PEER_A, PEER_A
+	))
+	.unwrap();
+	assert!(config.relay_hop);
+	assert_eq!(config.relay_limits().total_bytes_per_sec, 0);
+	let relays = relay_nodes(&config).unwrap();
+	assert_eq!(relays.len(), 1);
+	assert_eq!(relays[0].1.to_string(), PEER_A);
+
+	// Peer behind the relay is dialed through the circuit
+	let peer_id = PeerId::random();
+	let addr = relayed_dial_addr(&relays[0].0, &relays[0].1, &peer_id).unwrap();
+	assert_eq!(
+		addr.to_string(),
+		format!("{}/p2p/{}/p2p-circuit/p2p/{}", relays[0].0, PEER_A, peer_id)
+	);
+
+	let node = Libp2pNode::new();
+	node.set_config(config.clone()).unwrap();
+	assert!(node
+		.set_config(Libp2pConfig {
+			relay_nodes: vec![format!("/onion3/{}:81", PEER_A)],
+			..config.clone()
+		})
+		.is_err());
+	assert_eq!(node.config(), config);
+}
+
+#[test]
+fn test_libp2p_identity_config() {
+	// Onion service key by default, as before the switch
+	assert_eq!(Libp2pConfig::default().identity, Libp2pIdentity::Tor);
+	let config: Libp2pConfig = serde_json::from_str(r#"{"identity": "standalone"}"#).unwrap();
+	assert_eq!(config.identity, Libp2pIdentity::Standalone);
+	assert_eq!(config.transport, Libp2pTransport::Tor);
+	assert!(serde_json::from_str::<Libp2pConfig>(r#"{"identity": "onion"}"#).is_err());
+}
+
+#[test]
+fn test_libp2p_kad_config() {
+	let config = Libp2pConfig::default();
+	assert!(config.kad_discovery);
+	assert_eq!(
+		config.kad_query_interval_secs,
+		DEFAULT_KAD_QUERY_INTERVAL_SECS
+	);
+	let config: Libp2pConfig = serde_json::from_str(r#"{"kad_discovery": false}"#).unwrap();
+	assert!(!config.kad_discovery);
+
+	let peer_id = PeerId::random();
+	assert!(!build_kademlia(&peer_id, false).is_enabled());
+	assert!(build_kademlia(&peer_id, true).is_enabled());
+	assert!(kad_stats().enabled);
+
+	// Topic providers are found by the same key on every node
+	let topic = Topic::new("NodeTopic").hash();
+	assert_eq!(
+		topic_key(&topic),
+		topic_key(&Topic::new("NodeTopic").hash())
+	);
+	assert_ne!(
+		topic_key(&topic),
+		topic_key(&Topic::new("OtherTopic").hash())
+	);
+}
+
+#[test]
+fn test_libp2p_query_handlers() {
+	let node = Libp2pNode::new();
+	let peer_id = PeerId::random();
+	node.add_query_handler(
+		"swap_offer",
+		query_handler(|_peer_id, params| Ok(json!({ "offer": params }))),
+	)
+	.unwrap();
+	// Built-in methods are answered by the node itself
+	assert!(node
+		.add_query_handler(HAS_KERNEL_METHOD, query_handler(|_, _| Ok(json!(true))))
+		.is_err());
+	assert_eq!(node.query_methods(), vec!["swap_offer".to_string()]);
+
+	let request = QueryRequest {
+		method: "swap_offer".to_string(),
+		params: json!("offer1"),
+	};
+	assert_eq!(
+		node.handle_query(&peer_id, request.clone()),
+		Ok(json!({ "offer": "offer1" }))
+	);
+	let unknown = QueryRequest {
+		method: "unknown".to_string(),
+		params: json!(null),
+	};
+	assert!(node.handle_query(&peer_id, unknown).is_err());
+
+	assert!(node.remove_query_handler("swap_offer"));
+	assert!(!node.remove_query_handler("swap_offer"));
+	assert!(node.handle_query(&peer_id, request).is_err());
+}
+
+#[test]
+fn test_libp2p_query_codec() {
+	block_on(async {
+		let mut codec = QueryCodec;
+		let request = QueryRequest {
+			method: HAS_KERNEL_METHOD.to_string(),
+			params: json!("08a8f99853d65cee63c973a78a005f4646b777262440a8bfa090694a339a388865"),
+		};
+		let mut io = Cursor::new(vec![]);
+		codec
+			.write_request(&QueryProtocol, &mut io, request.clone())
+			.await
+			.unwrap();
+		io.set_position(0);
+		assert_eq!(
+			codec.read_request(&QueryProtocol, &mut io).await.unwrap(),
+			request
+		);
+
+		let response: QueryResponse = Err("unknown query method".to_string());
+		let mut io = Cursor::new(vec![]);
+		codec
+			.write_response(&QueryProtocol, &mut io, response.clone())
+			.await
+			.unwrap();
+		io.set_position(0);
+		assert_eq!(
+			codec.read_response(&QueryProtocol, &mut io).await.unwrap(),
+			response
+		);
+
+		// Too large response is replaced with the error, so the requester is not left waiting
+		let response: QueryResponse = Ok(json!("x".repeat(MAX_QUERY_SIZE)));
+		let mut io = Cursor::new(vec![]);
+		codec
+			.write_response(&QueryProtocol, &mut io, response)
+			.await
+			.unwrap();
+		io.set_position(0);
+		assert!(codec
+			.read_response(&QueryProtocol, &mut io)
+			.await
+			.unwrap()
+			.is_err());
+
+		let request = QueryRequest {
+			method: "swap_offer".to_string(),
+			params: json!("x".repeat(MAX_QUERY_SIZE)),
+		};
+		assert!(codec
+			.write_request(&QueryProtocol, &mut Cursor::new(vec![]), request)
+			.await
+			.is_err());
+	});
+}
+
+fn encode_payload(version: u16, header: &[Vec<u8>], data: &[u8]) -> Vec<u8> {
+	let mut ser = SimplePushSerializer::new(version);
+	for field in header {
+		ser.push_vec(field);
+	}
+	ser.push_vec(data);
+	ser.to_vec()
+}
+
+#[test]
+fn test_libp2p_payload_schema() {
+	let version = integrity_message_version();
+	let header = vec![vec![8; 33], vec![1; 32], vec![2; 64]];
+	let message = encode_payload(version, &header, &[1, 2, 3]);
+
+	// Topics without the schema use the integrity message layout
+	let topic = Topic::new("SchemaTopic").hash();
+	assert_eq!(payload_versions(&topic), vec![version]);
+	match decode_payload(&topic, &message) {
+		PayloadDecoding::Decoded(payload) => {
+			assert_eq!(payload.kernel_excess, header[0]);
+			assert_eq!(payload.data, vec![1, 2, 3]);
+		}
+		res => panic!("Unexpected decoding {:?}", res),
+	}
+	assert_eq!(read_message_data(&message), vec![1, 2, 3]);
+
+	// Newer version is unknown, not invalid
+	let message_v2 = encode_payload(version + 1, &header, &[4, 5]);
+	assert_eq!(
+		decode_payload(&topic, &message_v2),
+		PayloadDecoding::UnknownVersion(version + 1)
+	);
+	assert!(read_message_data(&message_v2).is_empty());
+
+	// Known version with the broken header is invalid
+	let broken = encode_payload(version, &[vec![], vec![], vec![]], &[1]);
+	match decode_payload(&topic, &broken) {
+		PayloadDecoding::Invalid(_) => (),
+		res => panic!("Unexpected decoding {:?}", res),
+	}
+
+	register_payload_schema(
+		"SchemaTopic",
+		default_payload_schema().with_version(version + 1, decode_integrity_payload),
+	);
+	assert_eq!(payload_versions(&topic), vec![version, version + 1]);
+	match decode_payload(&topic, &message_v2) {
+		PayloadDecoding::Decoded(payload) => assert_eq!(payload.data, vec![4, 5]),
+		res => panic!("Unexpected decoding {:?}", res),
+	}
+
+	unregister_payload_schema("SchemaTopic");
+	assert_eq!(
+		decode_payload(&topic, &message_v2),
+		PayloadDecoding::UnknownVersion(version + 1)
+	);
+}
@@ -0,0 +1,56 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::libp2p_peer_store::{Libp2pPeerRecord, Libp2pPeerStore, STORED_PEER_EXPIRE_SECS};
+
+fn record(address: &str, last_seen: i64) -> Libp2pPeerRecord {
+	Libp2pPeerRecord {
+		address: address.to_string(),
+		last_seen,
+	}
+}
+
+#[test]
+fn test_libp2p_peer_store() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_root = dir.path().to_str().unwrap();
+	let now = 1_600_000_000;
+
+	{
+		let store = Libp2pPeerStore::new(db_root).unwrap();
+		store
+			.save(&[
+				record("peera", now - 100),
+				record("peerb", now - 10),
+				record("peerc", now - STORED_PEER_EXPIRE_SECS - 1),
+			])
+			.unwrap();
+		// Older last seen time doesn't overwrite the newer one
+		store.save(&[record("peera", now - 1000)]).unwrap();
+		store.save(&[record("peerb", now - 5)]).unwrap();
+	}
+
+	// Peers survive the restart, the expired ones are dropped
+	let store = Libp2pPeerStore::new(db_root).unwrap();
+	assert_eq!(
+		store.load(now).unwrap(),
+		vec![record("peerb", now - 5), record("peera", now - 100)]
+	);
+	assert_eq!(store.load(now).unwrap().len(), 2);
+
+	store.delete("PEERA.onion").unwrap();
+	assert_eq!(store.load(now).unwrap(), vec![record("peerb", now - 5)]);
+}
@@ -0,0 +1,78 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use std::time::{Duration, Instant};
+
+use crate::p2p::libp2p_relay::{relay_stats, BandwidthLimit, RelayBandwidth, RelayLimits};
+
+#[test]
+fn test_bandwidth_limit() {
+	let start = Instant::now();
+	let mut limit = BandwidthLimit::new(1000, start);
+
+	// A second of data goes through at once
+	assert_eq!(limit.consume(1000, start), Duration::from_secs(0));
+	// The rest is paid by the pause
+	assert_eq!(limit.consume(500, start), Duration::from_millis(500));
+	// The debt is refilled over time
+	assert_eq!(
+		limit.consume(0, start + Duration::from_millis(500)),
+		Duration::from_secs(0)
+	);
+	assert_eq!(
+		limit.consume(250, start + Duration::from_millis(750)),
+		Duration::from_secs(0)
+	);
+	// Bucket doesn't grow above a second of data
+	let later = start + Duration::from_secs(10);
+	assert_eq!(limit.consume(1000, later), Duration::from_secs(0));
+	assert_eq!(limit.consume(100, later), Duration::from_millis(100));
+
+	// Zero rate is unlimited
+	let mut unlimited = BandwidthLimit::new(0, start);
+	assert_eq!(unlimited.consume(1_000_000, start), Duration::from_secs(0));
+}
+
+#[test]
+fn test_relay_bandwidth() {
+	let bandwidth = RelayBandwidth::new(RelayLimits {
+		max_connections: 2,
+		connection_bytes_per_sec: 1000,
+		total_bytes_per_sec: 1500,
+	});
+	let now = Instant::now() + Duration::from_secs(1);
+
+	let mut first = bandwidth.open_connection().unwrap();
+	let mut second = bandwidth.open_connection().unwrap();
+	assert_eq!(bandwidth.connections(), 2);
+	// Connections above the limit are refused
+	let refused = relay_stats().refused_connections;
+	assert!(bandwidth.open_connection().is_none());
+	assert_eq!(relay_stats().refused_connections, refused + 1);
+
+	// Own limit of the connection
+	assert_eq!(first.consume(1000, now), Duration::from_secs(0));
+	assert_eq!(first.consume(100, now), Duration::from_millis(100));
+	// Connection within its own limit is paused by the total limit
+	assert_eq!(second.consume(400, now), Duration::from_secs(0));
+	assert_eq!(second.consume(100, now), Duration::from_millis(100));
+
+	// Closed connection frees the slot
+	drop(first);
+	assert_eq!(bandwidth.connections(), 1);
+	assert!(bandwidth.open_connection().is_some());
+	assert_eq!(bandwidth.connections(), 1);
+}
@@ -0,0 +1,239 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "marketplace")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use grin_core::core::hash::Hash;
+use grin_p2p as p2p;
+use libp2p::gossipsub::{MessageId, TopicHash};
+use libp2p::PeerId;
+
+use crate::p2p::libp2p_connection::{sync_topic_handler, topic_handler, MessageContext};
+use crate::p2p::libp2p_sandbox::{HandlerOutcome, HandlerSandbox};
+
+fn context(topic: &str) -> MessageContext {
+	MessageContext {
+		message_id: MessageId::new(topic.as_bytes()),
+		content_hash: Hash::default(),
+		source: None,
+		propagation_source: PeerId::random(),
+		topic: TopicHash::from_raw(topic),
+		sender_address: "".to_string(),
+		fee: 0,
+		received: 0,
+	}
+}
+
+fn valid_handler(_context: &MessageContext, data: Vec<u8>) -> bool {
+	!data.is_empty()
+}
+
+fn panic_handler(_context: &MessageContext, _data: Vec<u8>) -> bool {
+	panic!("broken handler");
+}
+
+fn slow_handler(_context: &MessageContext, _data: Vec<u8>) -> bool {
+	thread::sleep(Duration::from_millis(500));
+	true
+}
+
+fn collect_all(sandbox: &HandlerSandbox, count: usize) -> Vec<(String, HandlerOutcome)> {
+	let mut res = vec![];
+	for _ in 0..100 {
+		res.extend(
+			sandbox
+				.collect()
+				.into_iter()
+				.map(|(context, outcome)| (context.topic.to_string(), outcome)),
+		);
+		if res.len() >= count {
+			break;
+		}
+		thread::sleep(Duration::from_millis(20));
+	}
+	res.sort_by(|a, b| a.0.cmp(&b.0));
+	res
+}
+
+// Handler panics and timeouts are reported, the workers keep running
+#[test]
+fn test_handler_sandbox() {
+	let sandbox = HandlerSandbox::new(2, Duration::from_millis(100));
+	sandbox.submit(
+		sync_topic_handler(valid_handler),
+		context("a_valid"),
+		vec![1],
+	);
+	sandbox.submit(
+		sync_topic_handler(valid_handler),
+		context("b_invalid"),
+		vec![],
+	);
+	sandbox.submit(
+		sync_topic_handler(panic_handler),
+		context("c_panic"),
+		vec![1],
+	);
+	sandbox.submit(sync_topic_handler(slow_handler), context("d_slow"), vec![1]);
+
+	let res = collect_all(&sandbox, 4);
+	assert_eq!(
+		res,
+		vec![
+			("a_valid".to_string(), HandlerOutcome::Done(true)),
+			("b_invalid".to_string(), HandlerOutcome::Done(false)),
+			(
+				"c_panic".to_string(),
+				HandlerOutcome::Panic("broken handler".to_string())
+			),
+			("d_slow".to_string(), HandlerOutcome::Timeout),
+		]
+	);
+	assert_eq!(sandbox.pending_count(), 0);
+
+	// Worker that caught the panic is still alive
+	sandbox.submit(
+		sync_topic_handler(valid_handler),
+		context("e_valid"),
+		vec![1],
+	);
+	sandbox.submit(
+		sync_topic_handler(valid_handler),
+		context("f_valid"),
+		vec![1],
+	);
+	let res = collect_all(&sandbox, 2);
+	assert_eq!(res.len(), 2);
+	assert!(res.iter().all(|(_, o)| *o == HandlerOutcome::Done(true)));
+}
+
+fn accept_handler(_context: &MessageContext, _data: Vec<u8>) -> bool {
+	true
+}
+
+// Every handler of the topic gets the message, a single rejection rejects it
+#[test]
+fn test_handler_sandbox_multiple_handlers() {
+	let sandbox = HandlerSandbox::new(2, Duration::from_millis(500));
+	sandbox.submit_all(
+		vec![
+			sync_topic_handler(accept_handler),
+			sync_topic_handler(valid_handler),
+		],
+		context("a_valid"),
+		vec![1],
+	);
+	sandbox.submit_all(
+		vec![
+			sync_topic_handler(accept_handler),
+			sync_topic_handler(valid_handler),
+		],
+		context("b_invalid"),
+		vec![],
+	);
+	sandbox.submit_all(
+		vec![
+			sync_topic_handler(panic_handler),
+			sync_topic_handler(accept_handler),
+		],
+		context("c_panic"),
+		vec![1],
+	);
+	sandbox.submit_all(
+		vec![
+			sync_topic_handler(panic_handler),
+			sync_topic_handler(valid_handler),
+		],
+		context("d_invalid"),
+		vec![],
+	);
+
+	let res = collect_all(&sandbox, 4);
+	assert_eq!(
+		res,
+		vec![
+			("a_valid".to_string(), HandlerOutcome::Done(true)),
+			("b_invalid".to_string(), HandlerOutcome::Done(false)),
+			(
+				"c_panic".to_string(),
+				HandlerOutcome::Panic("broken handler".to_string())
+			),
+			("d_invalid".to_string(), HandlerOutcome::Done(false)),
+		]
+	);
+}
+
+async fn stuck_handler(_context: MessageContext, _data: Vec<u8>) -> bool {
+	tokio::time::delay_for(Duration::from_secs(60)).await;
+	true
+}
+
+async fn async_panic_handler(_context: MessageContext, _data: Vec<u8>) -> bool {
+	tokio::time::delay_for(Duration::from_millis(10)).await;
+	panic!("broken async handler");
+}
+
+// Closure handlers capture their state and can await without holding the executor
+#[test]
+fn test_handler_sandbox_async_handlers() {
+	let sandbox = HandlerSandbox::new(1, Duration::from_millis(500));
+	let seen = Arc::new(AtomicUsize::new(0));
+
+	let counter = seen.clone();
+	let counting_handler = topic_handler(move |_context, data: Vec<u8>| {
+		let counter = counter.clone();
+		async move {
+			tokio::time::delay_for(Duration::from_millis(50)).await;
+			counter.fetch_add(data.len(), Ordering::Relaxed);
+			true
+		}
+	});
+
+	// Single executor thread, the waiting handlers don't block each other
+	for i in 0..4 {
+		sandbox.submit(
+			counting_handler.clone(),
+			context(&format!("a_count_{}", i)),
+			vec![1, 2],
+		);
+	}
+	sandbox.submit(topic_handler(stuck_handler), context("b_stuck"), vec![1]);
+	sandbox.submit(
+		topic_handler(async_panic_handler),
+		context("c_panic"),
+		vec![1],
+	);
+
+	let res = collect_all(&sandbox, 6);
+	assert_eq!(res.len(), 6);
+	for (topic, outcome) in &res[0..4] {
+		assert!(topic.starts_with("a_count_"));
+		assert_eq!(*outcome, HandlerOutcome::Done(true));
+	}
+	assert_eq!(res[4], ("b_stuck".to_string(), HandlerOutcome::Timeout));
+	assert_eq!(
+		res[5],
+		(
+			"c_panic".to_string(),
+			HandlerOutcome::Panic("broken async handler".to_string())
+		)
+	);
+	assert_eq!(seen.load(Ordering::Relaxed), 8);
+	assert_eq!(sandbox.pending_count(), 0);
+}
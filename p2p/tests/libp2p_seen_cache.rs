@@ -0,0 +1,77 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+
+use crate::core::core::hash::Hashed;
+use crate::p2p::libp2p_seen_cache::{SeenMessageCache, SeenMessageStore};
+
+#[test]
+fn test_libp2p_seen_cache() {
+	let now = 1_600_000_000;
+	let (m1, m2, m3) = (vec![1u8].hash(), vec![2u8].hash(), vec![3u8].hash());
+
+	let mut cache = SeenMessageCache::new(2, 100, false);
+	assert!(!cache.check_and_insert(&m1, now));
+	assert!(cache.check_and_insert(&m1, now + 10));
+	assert!(!cache.check_and_insert(&m2, now + 20));
+
+	// The oldest message is dropped above the capacity
+	assert!(!cache.check_and_insert(&m3, now + 30));
+	assert_eq!(cache.len(), 2);
+	assert!(!cache.check_and_insert(&m1, now + 40));
+	assert!(cache.check_and_insert(&m3, now + 50));
+
+	// Messages expire after the TTL
+	assert!(!cache.check_and_insert(&m3, now + 200));
+
+	// The cache that is not persisted doesn't track the changes
+	assert_eq!(cache.take_changes(), (vec![], vec![]));
+}
+
+#[test]
+fn test_libp2p_seen_store() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_root = dir.path().to_str().unwrap();
+	let now = 1_600_000_000;
+	let (m1, m2, m3) = (vec![1u8].hash(), vec![2u8].hash(), vec![3u8].hash());
+
+	{
+		let store = SeenMessageStore::new(db_root).unwrap();
+		let mut cache = SeenMessageCache::new(2, 100, true);
+		cache.check_and_insert(&m1, now - 150);
+		cache.check_and_insert(&m2, now - 50);
+		let (records, dropped) = cache.take_changes();
+		assert_eq!(records.len(), 2);
+		store.save(&records, &dropped).unwrap();
+
+		cache.check_and_insert(&m3, now - 10);
+		let (records, dropped) = cache.take_changes();
+		assert_eq!(dropped, vec![m1]);
+		store.save(&records, &dropped).unwrap();
+	}
+
+	// Seen messages survive the restart
+	let store = SeenMessageStore::new(db_root).unwrap();
+	let mut cache = SeenMessageCache::new(2, 100, true);
+	cache.load(store.load(now, 100).unwrap(), now);
+	assert_eq!(cache.len(), 2);
+	assert!(cache.check_and_insert(&m2, now));
+	assert!(cache.check_and_insert(&m3, now));
+	assert!(!cache.check_and_insert(&m1, now));
+
+	// Expired records are deleted on load
+	assert_eq!(store.load(now + 1000, 100).unwrap(), vec![]);
+}
@@ -0,0 +1,126 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::libp2p_stats::{
+	get_libp2p_connections_info, get_libp2p_stats, record_connections, record_integrity_fee,
+	record_published, record_received, record_sent, record_validation, reset_libp2p_stats,
+	ConnectedPeer, ValidationResult, MAX_TRACKED_PEERS,
+};
+
+#[test]
+fn test_libp2p_traffic_stats() {
+	reset_libp2p_stats();
+
+	record_received("SwapMarketplace", "peer1", 500);
+	record_validation("SwapMarketplace", "peer1", ValidationResult::Accepted);
+	record_integrity_fee("SwapMarketplace", 10_000_000);
+	record_sent(
+		"SwapMarketplace",
+		&["peer2".to_string(), "peer3".to_string()],
+		500,
+	);
+	record_received("SwapMarketplace", "peer2", 300);
+	record_validation("SwapMarketplace", "peer2", ValidationResult::Rejected);
+	record_received("Other", "peer1", 100);
+	record_validation("Other", "peer1", ValidationResult::Ignored);
+	record_published("Other", &["peer1".to_string()], 200);
+
+	let stats = get_libp2p_stats();
+	assert_eq!(stats.messages_received, 3);
+	assert_eq!(stats.bytes_received, 900);
+	assert_eq!(stats.bytes_sent, 1200);
+	assert_eq!(stats.integrity_messages, 1);
+	assert_eq!(stats.integrity_fees_total, 10_000_000);
+
+	assert_eq!(stats.topics.len(), 2);
+	let other = &stats.topics[0];
+	assert_eq!(other.topic, "Other");
+	assert_eq!(other.ignored, 1);
+	assert_eq!(other.messages_published, 1);
+	assert_eq!(other.bytes_sent, 200);
+	let market = &stats.topics[1];
+	assert_eq!(market.messages_received, 2);
+	assert_eq!(market.accepted, 1);
+	assert_eq!(market.rejected, 1);
+	assert_eq!(market.bytes_received, 800);
+	assert_eq!(market.bytes_sent, 1000);
+	assert_eq!(market.integrity_fees, 10_000_000);
+
+	// The most received bytes first
+	assert_eq!(stats.peers.len(), 3);
+	let peer1 = &stats.peers[0];
+	assert_eq!(peer1.peer_id, "peer1");
+	assert_eq!(peer1.messages_received, 2);
+	assert_eq!(peer1.bytes_received, 600);
+	assert_eq!(peer1.messages_sent, 1);
+	assert_eq!(peer1.bytes_sent, 200);
+	let peer2 = &stats.peers[1];
+	assert_eq!(peer2.peer_id, "peer2");
+	assert_eq!(peer2.rejected, 1);
+	assert_eq!(peer2.bytes_sent, 500);
+
+	// Connections keep the time they were first seen, the traffic comes from the counters
+	let connected = |peer_id: &str, topics: &[&str]| ConnectedPeer {
+		peer_id: peer_id.to_string(),
+		onion_address: Some(format!("{}onion", peer_id)),
+		topics: topics.iter().map(|t| t.to_string()).collect(),
+	};
+	record_connections(vec![connected("peer1", &["SwapMarketplace"])], 1000);
+	record_connections(
+		vec![
+			connected("peer2", &[]),
+			connected("peer1", &["SwapMarketplace", "Other"]),
+		],
+		1010,
+	);
+	let connections = get_libp2p_connections_info(1100);
+	assert_eq!(connections.len(), 2);
+	let conn1 = &connections[0];
+	assert_eq!(conn1.peer_id, "peer1");
+	assert_eq!(conn1.onion_address, Some("peer1onion".to_string()));
+	assert_eq!(conn1.connected_since, 1000);
+	assert_eq!(conn1.age_secs, 100);
+	assert_eq!(conn1.topics, vec!["Other", "SwapMarketplace"]);
+	assert_eq!(conn1.bytes_received, 600);
+	assert_eq!(conn1.bytes_sent, 200);
+	let conn2 = &connections[1];
+	assert_eq!(conn2.peer_id, "peer2");
+	assert_eq!(conn2.connected_since, 1010);
+	assert_eq!(conn2.rejected, 1);
+
+	// Disconnected peer is dropped, the reconnected one starts over
+	record_connections(vec![connected("peer2", &[])], 1020);
+	record_connections(vec![connected("peer2", &[]), connected("peer1", &[])], 1030);
+	let connections = get_libp2p_connections_info(1030);
+	assert_eq!(connections[0].peer_id, "peer2");
+	assert_eq!(connections[1].peer_id, "peer1");
+	assert_eq!(connections[1].connected_since, 1030);
+	assert_eq!(connections[1].age_secs, 0);
+	record_connections(vec![], 1040);
+	assert!(get_libp2p_connections_info(1040).is_empty());
+
+	// Number of the tracked peers is limited
+	for i in 0..MAX_TRACKED_PEERS + 10 {
+		record_received("Other", &format!("flood{}", i), 1);
+	}
+	assert_eq!(get_libp2p_stats().peers.len(), MAX_TRACKED_PEERS);
+
+	reset_libp2p_stats();
+	let stats = get_libp2p_stats();
+	assert_eq!(stats.messages_received, 0);
+	assert!(stats.topics.is_empty());
+	assert!(stats.peers.is_empty());
+}
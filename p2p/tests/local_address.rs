@@ -0,0 +1,93 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::local_address::{
+	advertised_address, is_advertised, ObservedAddresses, ADDRESS_VOTE_EXPIRE_SECS,
+};
+use crate::p2p::types::{P2PConfig, PeerAddr};
+use std::net::{IpAddr, SocketAddr};
+
+fn ip(s: &str) -> IpAddr {
+	s.parse().unwrap()
+}
+
+fn addr(s: &str) -> SocketAddr {
+	s.parse().unwrap()
+}
+
+#[test]
+fn test_observed_address_voting() {
+	let mut observed = ObservedAddresses::new();
+	let external = addr("5.6.7.8:3414");
+	let other = addr("5.6.7.9:3414");
+
+	observed.record(ip("1.2.3.1"), external, 100);
+	observed.record(ip("1.2.3.2"), external, 100);
+	assert_eq!(observed.detected(100), None);
+
+	// The same voter has a single vote
+	observed.record(ip("1.2.3.2"), external, 101);
+	assert_eq!(observed.detected(101), None);
+
+	observed.record(ip("1.2.3.3"), external, 102);
+	assert_eq!(observed.detected(102), Some(external));
+
+	// Local voters and not routable addresses are ignored
+	observed.record(ip("192.168.1.5"), other, 103);
+	observed.record(ip("1.2.3.4"), addr("10.0.0.5:3414"), 103);
+	observed.record(ip("1.2.3.5"), addr("127.0.0.1:3414"), 103);
+	assert_eq!(observed.detected(103), Some(external));
+
+	// The majority wins
+	for i in 10..14 {
+		observed.record(ip(&format!("1.2.3.{}", i)), other, 104);
+	}
+	assert_eq!(observed.detected(104), Some(other));
+
+	// Expired votes are not counted
+	assert_eq!(observed.detected(104 + ADDRESS_VOTE_EXPIRE_SECS), None);
+}
+
+#[test]
+fn test_advertised_address() {
+	let config = P2PConfig {
+		host: "0.0.0.0".parse().unwrap(),
+		port: 3414,
+		detect_external_address: Some(false),
+		..P2PConfig::default()
+	};
+	assert_eq!(
+		advertised_address(&config),
+		PeerAddr::Ip(addr("0.0.0.0:3414"))
+	);
+
+	let explicit = P2PConfig {
+		advertised_address: Some("5.6.7.8:13414".to_string()),
+		..config.clone()
+	};
+	assert_eq!(
+		advertised_address(&explicit),
+		PeerAddr::Ip(addr("5.6.7.8:13414"))
+	);
+
+	let hidden = P2PConfig {
+		advertise_address: Some(false),
+		..explicit
+	};
+	let hidden_addr = advertised_address(&hidden);
+	assert!(!is_advertised(&hidden_addr));
+	assert!(is_advertised(&advertised_address(&config)));
+}
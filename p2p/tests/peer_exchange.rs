@@ -0,0 +1,164 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+
+use crate::core::ser;
+use crate::p2p::peer_exchange::{
+	peer_set_digest, PeerExchangeMessage, PeerExchangeOutcome, PeerExchangeReceiver,
+	PeerExchangeSender, PEER_EXCHANGE_HISTORY, PEER_EXCHANGE_MAX_ENTRIES,
+};
+use std::collections::BTreeSet;
+
+fn peer_set(ids: &[u32]) -> BTreeSet<String> {
+	ids.iter().map(|i| format!("peer{}onion", i)).collect()
+}
+
+// Delivers the request of the receiver to the sender and returns the sender answer
+fn exchange(
+	sender: &mut PeerExchangeSender,
+	receiver: &mut PeerExchangeReceiver,
+	announce: PeerExchangeMessage,
+) -> Vec<PeerExchangeOutcome> {
+	let mut outcomes = vec![];
+	match receiver.on_message("sender", announce, 0) {
+		PeerExchangeOutcome::Publish(PeerExchangeMessage::Request { target, base }) => {
+			assert_eq!(target, "sender");
+			sender.on_request(base);
+			for response in sender.responses() {
+				outcomes.push(receiver.on_message("sender", response, 0));
+			}
+		}
+		outcome => outcomes.push(outcome),
+	}
+	outcomes
+}
+
+#[test]
+fn test_peer_exchange_diff() {
+	let mut sender = PeerExchangeSender::new();
+	let mut receiver = PeerExchangeReceiver::new();
+
+	// First set goes as a diff from the empty set
+	let announce = sender.update(peer_set(&[1, 2, 3])).unwrap();
+	let outcomes = exchange(&mut sender, &mut receiver, announce);
+	assert_eq!(
+		outcomes,
+		vec![PeerExchangeOutcome::NewPeers(
+			peer_set(&[1, 2, 3]).into_iter().collect()
+		)]
+	);
+
+	// Same set, only the digest is exchanged
+	assert!(sender.update(peer_set(&[1, 2, 3])).is_none());
+	let outcomes = exchange(&mut sender, &mut receiver, sender.digest_message());
+	assert_eq!(outcomes, vec![PeerExchangeOutcome::None]);
+
+	// Changes only
+	let announce = sender.update(peer_set(&[2, 3, 4])).unwrap();
+	match receiver.on_message("sender", announce, 0) {
+		PeerExchangeOutcome::Publish(PeerExchangeMessage::Request { base, .. }) => {
+			sender.on_request(base)
+		}
+		o => panic!("Unexpected outcome {:?}", o),
+	}
+	let responses = sender.responses();
+	assert_eq!(responses.len(), 1);
+	match &responses[0] {
+		PeerExchangeMessage::Diff { added, removed, .. } => {
+			assert_eq!(added, &vec!["peer4onion".to_string()]);
+			assert_eq!(removed, &vec!["peer1onion".to_string()]);
+		}
+		m => panic!("Unexpected response {:?}", m),
+	}
+	assert_eq!(
+		receiver.on_message("sender", responses[0].clone(), 0),
+		PeerExchangeOutcome::NewPeers(vec!["peer4onion".to_string()])
+	);
+	assert_eq!(
+		receiver.peers("sender").unwrap(),
+		peer_set(&[2, 3, 4]).into_iter().collect::<Vec<String>>()
+	);
+}
+
+#[test]
+fn test_peer_exchange_full_fallback() {
+	let mut sender = PeerExchangeSender::new();
+	let mut receiver = PeerExchangeReceiver::new();
+	let announce = sender.update(peer_set(&[1])).unwrap();
+	exchange(&mut sender, &mut receiver, announce);
+
+	// The set known by the receiver is out of the sender history
+	let mut last = None;
+	for i in 0..PEER_EXCHANGE_HISTORY as u32 + 1 {
+		last = sender.update(peer_set(&[10 + i]));
+	}
+	let outcomes = exchange(&mut sender, &mut receiver, last.unwrap());
+	assert_eq!(
+		outcomes,
+		vec![PeerExchangeOutcome::NewPeers(vec![format!(
+			"peer{}onion",
+			10 + PEER_EXCHANGE_HISTORY
+		)])]
+	);
+	assert_eq!(
+		receiver.peers("sender").unwrap(),
+		vec![format!("peer{}onion", 10 + PEER_EXCHANGE_HISTORY)]
+	);
+}
+
+#[test]
+fn test_peer_exchange_inconsistent_diff() {
+	let mut receiver = PeerExchangeReceiver::new();
+	let empty = peer_set_digest(&BTreeSet::new());
+	let diff = PeerExchangeMessage::Diff {
+		base: empty,
+		digest: peer_set_digest(&peer_set(&[1, 2])),
+		added: vec!["peer1onion".to_string()],
+		removed: vec![],
+	};
+	// The result doesn't match the digest, the set is requested again
+	assert_eq!(
+		receiver.on_message("sender", diff, 0),
+		PeerExchangeOutcome::Publish(PeerExchangeMessage::Request {
+			target: "sender".to_string(),
+			base: empty,
+		})
+	);
+	assert!(receiver.peers("sender").is_none());
+}
+
+#[test]
+fn test_peer_exchange_ser() {
+	let message = PeerExchangeMessage::Diff {
+		base: peer_set_digest(&peer_set(&[1])),
+		digest: peer_set_digest(&peer_set(&[2])),
+		added: vec!["peer2onion".to_string()],
+		removed: vec!["peer1onion".to_string()],
+	};
+	let data = ser::ser_vec(&message, ser::ProtocolVersion(1)).unwrap();
+	let read: PeerExchangeMessage =
+		ser::deserialize(&mut &data[..], ser::ProtocolVersion(1)).unwrap();
+	assert_eq!(read, message);
+
+	// Giant peer dumps are refused
+	let message = PeerExchangeMessage::Full {
+		digest: peer_set_digest(&BTreeSet::new()),
+		peers: (0..PEER_EXCHANGE_MAX_ENTRIES as u32 + 1)
+			.map(|i| format!("peer{}onion", i))
+			.collect(),
+	};
+	assert!(ser::ser_vec(&message, ser::ProtocolVersion(1)).is_err());
+}
@@ -0,0 +1,93 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+use grin_util as util;
+
+use chrono::{TimeZone, Utc};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::core::core::hash::Hash;
+use crate::core::global;
+use crate::p2p::{Capabilities, PeerAddr, PeerData, ReasonForBan, State};
+use crate::util::clock::{self, VirtualClock};
+use crate::util::StopState;
+
+// Defunct peers are removed from the db once the expiration time passed on the node clock
+#[test]
+fn test_peer_expiry_virtual_clock() {
+	let db_root = ".grin_peer_expiry";
+	let _ = fs::remove_dir_all(db_root);
+
+	let start = Utc.timestamp(1_600_000_000, 0);
+	let virtual_clock = Arc::new(VirtualClock::new(start));
+	clock::set_local_clock(virtual_clock.clone());
+
+	let server = p2p::Server::new(
+		db_root,
+		Capabilities::UNKNOWN,
+		p2p::P2PConfig::default(),
+		Arc::new(p2p::DummyAdapter {}),
+		Hash::from_vec(&vec![]),
+		Arc::new(StopState::new()),
+		0,
+		None,
+	)
+	.unwrap();
+	let peers = server.peers.clone();
+
+	let addr = |i: u8| {
+		PeerAddr::Ip(SocketAddr::new(
+			IpAddr::V4(Ipv4Addr::new(10, 0, 0, i)),
+			3414,
+		))
+	};
+
+	// Ban and connection times are both taken from the node clock
+	peers.add_banned(addr(1), ReasonForBan::ManualBan).unwrap();
+	let banned = peers.get_peer(addr(1)).unwrap();
+	assert_eq!(banned.last_banned, start.timestamp());
+	assert_eq!(banned.last_connected, start.timestamp());
+
+	peers
+		.save_peer(&PeerData {
+			addr: addr(2),
+			capabilities: Capabilities::FULL_NODE,
+			user_agent: "MW/MWC 4.4.2".to_string(),
+			flags: State::Defunct,
+			last_banned: 0,
+			ban_reason: ReasonForBan::None,
+			last_connected: clock::now().timestamp(),
+		})
+		.unwrap();
+
+	virtual_clock.advance(Duration::from_secs(
+		global::PEER_EXPIRATION_REMOVE_TIME as u64 - 1,
+	));
+	peers.remove_expired();
+	assert!(peers.get_peer(addr(2)).is_ok());
+
+	virtual_clock.advance(Duration::from_secs(2));
+	peers.remove_expired();
+	assert!(peers.get_peer(addr(2)).is_err());
+	// Banned peers are not defunct, they stay
+	assert!(peers.get_peer(addr(1)).is_ok());
+
+	clock::reset_local_clock();
+	let _ = fs::remove_dir_all(db_root);
+}
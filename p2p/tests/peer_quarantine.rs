@@ -0,0 +1,56 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::peer_quarantine::PeerQuarantine;
+
+const ADDRESS: &str = "xiqz5kbxwbcb5edwb3hyfyrmp3hmbaxmbusbcpqm4d6bg6slnzsxztad";
+
+#[test]
+fn test_quarantine_expiry() {
+	let mut quarantine = PeerQuarantine::new(100);
+	assert!(quarantine.quarantine(ADDRESS, "invalid message on topic a", 1000));
+	// Address form doesn't matter
+	let onion = format!("{}.onion", ADDRESS.to_uppercase());
+	assert!(quarantine.is_quarantined(&onion, 1050));
+	assert!(!quarantine.is_quarantined("other", 1050));
+
+	// Repeated offense extends the quarantine
+	assert!(!quarantine.quarantine(&onion, "invalid message on topic b", 1050));
+	let peers = quarantine.peers(1050);
+	assert_eq!(peers.len(), 1);
+	assert_eq!(peers[0].address, ADDRESS);
+	assert_eq!(peers[0].offenses, 2);
+	assert_eq!(peers[0].since, 1000);
+	assert_eq!(peers[0].until, 1150);
+	assert_eq!(peers[0].reason, "invalid message on topic b");
+
+	assert!(quarantine.is_quarantined(ADDRESS, 1149));
+	assert!(!quarantine.is_quarantined(ADDRESS, 1150));
+	assert!(quarantine.peers(1150).is_empty());
+}
+
+#[test]
+fn test_quarantine_release_and_disable() {
+	let mut quarantine = PeerQuarantine::new(100);
+	quarantine.quarantine(ADDRESS, "invalid message", 1000);
+	assert!(quarantine.release(ADDRESS));
+	assert!(!quarantine.release(ADDRESS));
+	assert!(!quarantine.is_quarantined(ADDRESS, 1000));
+
+	let mut quarantine = PeerQuarantine::new(0);
+	assert!(!quarantine.quarantine(ADDRESS, "invalid message", 1000));
+	assert!(!quarantine.is_quarantined(ADDRESS, 1000));
+}
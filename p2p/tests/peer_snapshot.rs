@@ -0,0 +1,81 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use grin_p2p as p2p;
+use grin_util as util;
+
+use crate::p2p::types::{Capabilities, PeerAddr, ReasonForBan};
+use crate::p2p::{PeerData, PeerSnapshot, State};
+use crate::util::secp::key::SecretKey;
+use rand::thread_rng;
+
+fn peer(last_octet: u8, flags: State, last_connected: i64) -> PeerData {
+	PeerData {
+		addr: PeerAddr::Ip(SocketAddr::new(
+			IpAddr::V4(Ipv4Addr::new(192, 168, 0, last_octet)),
+			3414,
+		)),
+		capabilities: Capabilities::FULL_NODE,
+		user_agent: "MW/MWC 4.4.2".to_string(),
+		flags,
+		last_banned: 0,
+		ban_reason: ReasonForBan::None,
+		last_connected,
+	}
+}
+
+#[test]
+fn test_peer_snapshot_signature() {
+	let key = SecretKey::new(&mut thread_rng());
+	let peers = vec![
+		peer(1, State::Healthy, 100),
+		peer(2, State::Defunct, 300),
+		peer(3, State::Banned, 200),
+	];
+	let snapshot = PeerSnapshot::create(peers, &key).unwrap();
+
+	// Defunct peers are dropped, recently connected first
+	assert_eq!(snapshot.peers.len(), 2);
+	assert_eq!(snapshot.peers[0].last_connected, 200);
+	assert_eq!(snapshot.peers[1].last_connected, 100);
+
+	assert!(snapshot.verify(None).is_ok());
+	assert!(snapshot.verify(Some(&snapshot.public_key)).is_ok());
+
+	// Snapshot from the json file is still valid
+	let json = serde_json::to_string(&snapshot).unwrap();
+	let restored: PeerSnapshot = serde_json::from_str(&json).unwrap();
+	assert!(restored.verify(None).is_ok());
+
+	// Signed by another key
+	let other = PeerSnapshot::create(
+		vec![peer(1, State::Healthy, 100)],
+		&SecretKey::new(&mut thread_rng()),
+	)
+	.unwrap();
+	assert!(other.verify(Some(&snapshot.public_key)).is_err());
+
+	// Tampered peers
+	let mut tampered = snapshot.clone();
+	tampered.peers[1].flags = State::Healthy;
+	assert!(tampered.verify(None).is_err());
+	let mut tampered = snapshot.clone();
+	tampered.peers.pop();
+	assert!(tampered.verify(None).is_err());
+	let mut tampered = snapshot.clone();
+	tampered.created += 1;
+	assert!(tampered.verify(None).is_err());
+}
@@ -78,3 +78,51 @@ fn test_capabilities() {
 			.contains(p2p::types::Capabilities::TX_KERNEL_HASH)
 	);
 }
+
+#[test]
+fn test_header_first_capability() {
+	let capab = p2p::types::Capabilities::FULL_NODE | p2p::types::Capabilities::HEADER_FIRST;
+	let capab = p2p::types::Capabilities::from_bits_truncate(capab.bits());
+	assert!(capab.contains(p2p::types::Capabilities::HEADER_FIRST));
+	assert!(capab.contains(p2p::types::Capabilities::FULL_NODE));
+	assert!(!p2p::types::Capabilities::FULL_NODE.contains(p2p::types::Capabilities::HEADER_FIRST));
+}
+
+#[test]
+fn test_headers_only_capability() {
+	let capab = p2p::types::Capabilities::HEADER_HIST
+		| p2p::types::Capabilities::PEER_LIST
+		| p2p::types::Capabilities::HEADERS_ONLY;
+	let capab = p2p::types::Capabilities::from_bits_truncate(capab.bits());
+	assert!(capab.contains(p2p::types::Capabilities::HEADERS_ONLY));
+	assert!(!capab.contains(p2p::types::Capabilities::TXHASHSET_HIST));
+	assert!(!p2p::types::Capabilities::FULL_NODE.contains(p2p::types::Capabilities::HEADERS_ONLY));
+	// Minimized fingerprint advertises the full node capabilities only
+	let mut config = p2p::P2PConfig::default();
+	config.minimize_fingerprint = Some(true);
+	assert!(!config
+		.advertised_capabilities(capab)
+		.contains(p2p::types::Capabilities::HEADERS_ONLY));
+}
+
+#[test]
+fn test_user_agent_policy() {
+	assert!(p2p::msg::validate_user_agent(p2p::msg::USER_AGENT).is_ok());
+	assert!(p2p::msg::validate_user_agent("MW/custom node").is_ok());
+	assert!(p2p::msg::validate_user_agent("custom node").is_err());
+	assert!(p2p::msg::validate_user_agent(&format!("MW/{}", "a".repeat(64))).is_err());
+	assert!(p2p::msg::validate_user_agent("MW/node\n").is_err());
+
+	let mut config = p2p::P2PConfig::default();
+	assert_eq!(config.user_agent(), p2p::msg::USER_AGENT);
+	config.minimize_fingerprint = Some(true);
+	assert_eq!(config.user_agent().matches('.').count(), 1);
+	assert_eq!(
+		config.advertised_capabilities(
+			p2p::types::Capabilities::FULL_NODE | p2p::types::Capabilities::HEADER_FIRST
+		),
+		p2p::types::Capabilities::FULL_NODE
+	);
+	config.user_agent = Some("MW/custom node".to_string());
+	assert_eq!(config.user_agent(), "MW/custom node");
+}
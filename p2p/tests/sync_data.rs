@@ -0,0 +1,82 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_p2p as p2p;
+
+use crate::p2p::sync_data::{SyncDataAccounting, SyncDataCaps, SyncStage};
+use crate::p2p::PeerAddr;
+
+fn addr(port: u16) -> PeerAddr {
+	PeerAddr::Ip(format!("10.0.0.1:{}", port).parse().unwrap())
+}
+
+fn accounting() -> SyncDataAccounting {
+	SyncDataAccounting::new(SyncDataCaps {
+		headers: 1000,
+		blocks: 0,
+		txhashset: 5000,
+	})
+}
+
+#[test]
+fn test_sync_data_caps() {
+	let sync_data = accounting();
+	let (a, b) = (addr(1), addr(2));
+
+	sync_data.record(&a, SyncStage::Headers, 600);
+	assert!(!sync_data.is_over_cap(&a, SyncStage::Headers));
+	sync_data.record(&a, SyncStage::Headers, 400);
+	assert!(sync_data.is_over_cap(&a, SyncStage::Headers));
+	assert!(!sync_data.is_over_cap(&b, SyncStage::Headers));
+
+	// Stage without the cap
+	sync_data.record(&a, SyncStage::Blocks, 1_000_000);
+	assert!(!sync_data.is_over_cap(&a, SyncStage::Blocks));
+
+	let peers = sync_data.peers();
+	assert_eq!(peers.len(), 1);
+	assert_eq!(peers[0].addr, a);
+	assert_eq!(peers[0].headers_bytes, 1000);
+	assert_eq!(peers[0].blocks_bytes, 1_000_000);
+	assert_eq!(peers[0].txhashset_bytes, 0);
+	assert_eq!(peers[0].capped_stages, vec![SyncStage::Headers]);
+}
+
+#[test]
+fn test_sync_data_rotation() {
+	let sync_data = accounting();
+	let (a, b) = (addr(1), addr(2));
+	let preferred = |candidates: Vec<PeerAddr>| {
+		sync_data.preferred(SyncStage::Headers, candidates, |p| p.clone())
+	};
+
+	sync_data.record(&a, SyncStage::Headers, 1000);
+	assert_eq!(preferred(vec![a.clone(), b.clone()]), vec![b.clone()]);
+	assert_eq!(preferred(vec![b.clone(), a.clone()]), vec![b.clone()]);
+
+	// Every source is over the cap, new round starts
+	sync_data.record(&b, SyncStage::Headers, 1500);
+	assert_eq!(
+		preferred(vec![a.clone(), b.clone()]),
+		vec![a.clone(), b.clone()]
+	);
+	assert!(!sync_data.is_over_cap(&a, SyncStage::Headers));
+	assert!(!sync_data.is_over_cap(&b, SyncStage::Headers));
+
+	// Totals are kept across the rounds
+	let peers = sync_data.peers();
+	assert_eq!(peers[0].addr, b);
+	assert_eq!(peers[0].headers_bytes, 1500);
+	assert_eq!(peers[1].headers_bytes, 1000);
+}
@@ -0,0 +1,152 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_core as core;
+use grin_p2p as p2p;
+
+use grin_util as util;
+use grin_util::StopState;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::{thread, time};
+
+use crate::core::core::hash::Hash;
+use crate::core::global;
+use crate::core::pow::Difficulty;
+use crate::p2p::types::PeerAddr;
+use crate::p2p::{Capabilities, Peer};
+
+fn open_port() -> u16 {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	listener.local_addr().unwrap().port()
+}
+
+fn start_server(dir: &str, capabilities: Capabilities) -> (Arc<p2p::Server>, p2p::P2PConfig) {
+	let p2p_config = p2p::P2PConfig {
+		host: "127.0.0.1".parse().unwrap(),
+		port: open_port(),
+		peers_allow: None,
+		peers_deny: None,
+		..p2p::P2PConfig::default()
+	};
+	let server = Arc::new(
+		p2p::Server::new(
+			dir,
+			capabilities,
+			p2p_config.clone(),
+			Arc::new(p2p::DummyAdapter {}),
+			Hash::from_vec(&vec![]),
+			Arc::new(StopState::new()),
+			0,
+			None,
+		)
+		.unwrap(),
+	);
+	let p2p_inner = server.clone();
+	let _ = thread::spawn(move || p2p_inner.listen(100_000));
+	(server, p2p_config)
+}
+
+fn connect(client: &p2p::Server, config: &p2p::P2PConfig, port: u16) -> Arc<Peer> {
+	let addr = SocketAddr::new(config.host, port);
+	let socket = TcpStream::connect_timeout(&addr, time::Duration::from_secs(10)).unwrap();
+	let peer = Peer::connect(
+		socket,
+		Capabilities::UNKNOWN,
+		Difficulty::min(),
+		PeerAddr::Ip(SocketAddr::new(config.host, config.port)),
+		&p2p::handshake::Handshake::new(Hash::from_vec(&vec![]), config.clone(), None),
+		Arc::new(p2p::DummyAdapter {}),
+		100_000,
+		None,
+		client.clone(),
+	)
+	.unwrap();
+	Arc::new(peer)
+}
+
+// Archive peers are preferred as the state sync source, peers that served a corrupt
+// txhashset are excluded, every candidate is in the decision trail.
+#[test]
+fn test_state_sync_source_selection() {
+	global::init_global_chain_type(global::ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+
+	let (archive, archive_config) = start_server(
+		".grin_sync_source_archive",
+		Capabilities::FULL_NODE | Capabilities::ARCHIVE,
+	);
+	let (regular, regular_config) =
+		start_server(".grin_sync_source_regular", Capabilities::FULL_NODE);
+	let (client, client_config) = start_server(".grin_sync_source_client", Capabilities::UNKNOWN);
+	thread::sleep(time::Duration::from_secs(1));
+
+	let archive_peer = connect(&client, &client_config, archive_config.port);
+	let regular_peer = connect(&client, &client_config, regular_config.port);
+	assert!(archive_peer
+		.info
+		.capabilities
+		.contains(Capabilities::ARCHIVE));
+	assert!(!regular_peer
+		.info
+		.capabilities
+		.contains(Capabilities::ARCHIVE));
+
+	let (ranked, decision) = client
+		.peers
+		.rank_state_sync_sources(vec![regular_peer.clone(), archive_peer.clone()]);
+	assert_eq!(ranked.len(), 2);
+	assert_eq!(ranked[0].info.addr, archive_peer.info.addr);
+	assert_eq!(decision.selected, Some(archive_peer.info.addr.to_string()));
+	assert_eq!(decision.candidates.len(), 2);
+	assert!(decision.candidates[0].archive);
+	assert!(decision.candidates[0].score > decision.candidates[1].score);
+	assert!(decision.candidates.iter().all(|c| c.excluded.is_none()));
+
+	// Corrupt txhashset is remembered in the peer store
+	client
+		.peers
+		.record_bad_txhashset(archive_peer.info.addr.clone())
+		.unwrap();
+	let record = client.peers.sync_record(archive_peer.info.addr.clone());
+	assert_eq!(record.bad_txhashsets, 1);
+	assert!(record.last_bad_txhashset > 0);
+
+	let (ranked, decision) = client
+		.peers
+		.rank_state_sync_sources(vec![regular_peer.clone(), archive_peer.clone()]);
+	assert_eq!(ranked.len(), 1);
+	assert_eq!(ranked[0].info.addr, regular_peer.info.addr);
+	assert_eq!(decision.selected, Some(regular_peer.info.addr.to_string()));
+	assert_eq!(
+		decision.candidates[0].addr,
+		regular_peer.info.addr.to_string()
+	);
+	assert_eq!(decision.candidates[1].bad_txhashsets, 1);
+	assert!(decision.candidates[1].excluded.is_some());
+
+	// Nothing to select when all candidates served corrupt data
+	let (ranked, decision) = client
+		.peers
+		.rank_state_sync_sources(vec![archive_peer.clone()]);
+	assert!(ranked.is_empty());
+	assert_eq!(decision.selected, None);
+
+	archive_peer.stop();
+	regular_peer.stop();
+	archive.stop();
+	regular.stop();
+	client.stop();
+}
@@ -0,0 +1,140 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "test_vectors")]
+
+use grin_core::global;
+use grin_p2p as p2p;
+
+use p2p::test_vectors::{
+	generate_test_vectors, verify_test_vector, write_test_vectors, TestVectors,
+	TEST_VECTOR_PROTOCOL_VERSIONS,
+};
+
+#[test]
+fn test_vectors_round_trip() {
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let vectors = generate_test_vectors().unwrap();
+	assert!(!vectors.vectors.is_empty());
+	assert_eq!(
+		vectors.vectors.len() % TEST_VECTOR_PROTOCOL_VERSIONS.len(),
+		0
+	);
+	for v in &vectors.vectors {
+		verify_test_vector(v).unwrap();
+	}
+}
+
+#[test]
+fn test_vectors_are_deterministic() {
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	assert_eq!(
+		generate_test_vectors().unwrap(),
+		generate_test_vectors().unwrap()
+	);
+}
+
+#[test]
+fn test_vectors_corrupted_data() {
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let vectors = generate_test_vectors().unwrap();
+	let mut v = vectors
+		.vectors
+		.iter()
+		.find(|v| v.name == "PeerAddrs")
+		.unwrap()
+		.clone();
+	// drop the last byte, the message can't be read any more
+	v.data.truncate(v.data.len() - 2);
+	assert!(verify_test_vector(&v).is_err());
+}
+
+#[test]
+fn test_vectors_json_file() {
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let dir = "target/test_output/test_vectors";
+	let _ = std::fs::remove_dir_all(dir);
+	std::fs::create_dir_all(dir).unwrap();
+	let file_path = format!("{}/vectors.json", dir);
+	write_test_vectors(&file_path).unwrap();
+
+	let json = std::fs::read_to_string(&file_path).unwrap();
+	let vectors: TestVectors = serde_json::from_str(&json).unwrap();
+	assert_eq!(vectors, generate_test_vectors().unwrap());
+	let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_vectors_consensus_messages() {
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let vectors = generate_test_vectors().unwrap();
+	for name in &[
+		"Block",
+		"CompactBlock",
+		"Transaction",
+		"StemTransaction",
+		"GetBlock",
+		"GetCompactBlock",
+		"GetTransaction",
+		"TransactionKernel",
+		"Headers",
+	] {
+		assert_eq!(
+			vectors.vectors.iter().filter(|v| v.name == *name).count(),
+			TEST_VECTOR_PROTOCOL_VERSIONS.len(),
+			"{}",
+			name
+		);
+	}
+}
+
+#[test]
+fn test_vectors_chain_store() {
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let vectors = generate_test_vectors().unwrap();
+	for name in &[
+		"PeerData",
+		"Tip",
+		"BlockSums",
+		"CommitPos",
+		"HashHeight",
+		"OutputIdentifier",
+		"DbBlockHeader",
+		"UptimeRecord",
+		"DeferredRangeproofs",
+		"KernelFeeIndexHead",
+	] {
+		let store_vectors: Vec<_> = vectors.vectors.iter().filter(|v| v.name == *name).collect();
+		assert_eq!(
+			store_vectors.len(),
+			TEST_VECTOR_PROTOCOL_VERSIONS.len(),
+			"{}",
+			name
+		);
+		assert!(
+			store_vectors.iter().all(|v| v.msg_type.is_none()),
+			"{}",
+			name
+		);
+	}
+
+	// Failed flag is the last byte of the deferred rangeproofs record
+	let deferred = vectors
+		.vectors
+		.iter()
+		.find(|v| v.name == "DeferredRangeproofs")
+		.unwrap();
+	assert_eq!(deferred.data.len(), (8 + 8 + 1) * 2);
+	assert!(deferred.data.ends_with("01"));
+}
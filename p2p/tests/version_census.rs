@@ -0,0 +1,76 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use grin_p2p as p2p;
+
+use crate::p2p::types::PeerAddr;
+use crate::p2p::version_census::{
+	parse_node_version, VersionCensus, CENSUS_SAMPLE_SECS, CENSUS_WINDOW_SECS,
+};
+
+fn addr(last_octet: u8) -> PeerAddr {
+	PeerAddr::Ip(SocketAddr::new(
+		IpAddr::V4(Ipv4Addr::new(10, 0, 0, last_octet)),
+		3414,
+	))
+}
+
+#[test]
+fn test_parse_node_version() {
+	assert_eq!(parse_node_version("MW/MWC 4.4.2"), Some((4, 4, 2)));
+	assert_eq!(parse_node_version("MW/MWC 5.0"), Some((5, 0, 0)));
+	assert_eq!(parse_node_version("MW/MWC 5.1.0-beta.1"), Some((5, 1, 0)));
+	assert_eq!(parse_node_version("5.0.0"), Some((5, 0, 0)));
+	assert_eq!(parse_node_version("MW/MWC"), None);
+}
+
+#[test]
+fn test_version_census() {
+	let now = 1_600_000_000;
+	let mut census = VersionCensus::new();
+	census.record(addr(1), "MW/MWC 4.4.2", 3, now);
+	census.record(addr(2), "MW/MWC 4.4.2", 3, now);
+	census.record(addr(3), "MW/MWC 5.0.0", 3, now);
+	// Same peer seen again after the upgrade is counted once, with the new version
+	census.record(addr(4), "MW/MWC 4.4.2", 3, now);
+	census.record(addr(4), "MW/MWC 5.1.0", 4, now + 10);
+
+	let report = census.report(Some("5.0.0"), now + 20);
+	assert_eq!(report.total_peers, 4);
+	assert_eq!(report.user_agents[0].version, "MW/MWC 4.4.2");
+	assert_eq!(report.user_agents[0].peers, 2);
+	assert_eq!(report.user_agents[0].fraction, 0.5);
+	assert_eq!(report.protocol_versions[0].version, "3");
+	assert_eq!(report.protocol_versions[0].peers, 3);
+	assert_eq!(report.upgraded_peers, Some(2));
+	assert_eq!(report.upgraded_fraction, Some(0.5));
+	assert_eq!(report.trend.len(), 1);
+
+	let report = census.report(None, now + 20);
+	assert_eq!(report.upgraded_peers, None);
+
+	// Trend sample every hour
+	census.record(addr(5), "MW/MWC 5.1.0", 4, now + CENSUS_SAMPLE_SECS);
+	let report = census.report(None, now + CENSUS_SAMPLE_SECS);
+	assert_eq!(report.trend.len(), 2);
+	assert_eq!(report.trend[1].total_peers, 4);
+
+	// Peers that are not seen in the window are not counted
+	let later = now + 10 + CENSUS_WINDOW_SECS;
+	let report = census.report(Some("5.0.0"), later);
+	assert_eq!(report.total_peers, 1);
+	assert_eq!(report.upgraded_fraction, Some(1.0));
+}
@@ -83,6 +83,12 @@ impl BlockChain for ChainAdapter {
 			.map_err(|_| PoolError::Other("failed to get block header".into()))
 	}
 
+	fn get_block(&self, hash: &Hash) -> Result<Block, PoolError> {
+		self.chain
+			.get_block(hash)
+			.map_err(|_| PoolError::Other("failed to get block".into()))
+	}
+
 	fn get_block_sums(&self, hash: &Hash) -> Result<BlockSums, PoolError> {
 		self.chain
 			.get_block_sums(hash)
@@ -0,0 +1,112 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Index of the kernel excesses of the recent blocks. Transactions that re-announce
+//! a kernel that was already mined are rejected by the pool before the expensive
+//! validation. The kernel heights are kept for the NRD relative height checks.
+
+use self::core::core::Block;
+use self::util::secp::pedersen::Commitment;
+use grin_core as core;
+use grin_util as util;
+use std::collections::{HashMap, VecDeque};
+
+/// Kernel excesses of the last `window` blocks, indexed by the excess.
+pub struct RecentKernelIndex {
+	window: u64,
+	by_excess: HashMap<Commitment, u64>,
+	by_height: VecDeque<(u64, Vec<Commitment>)>,
+}
+
+impl RecentKernelIndex {
+	/// New empty index that keeps the kernels of the last `window` blocks
+	pub fn new(window: u64) -> RecentKernelIndex {
+		RecentKernelIndex {
+			window,
+			by_excess: HashMap::new(),
+			by_height: VecDeque::new(),
+		}
+	}
+
+	/// Index the kernels of the block that was added to the chain
+	pub fn add_block(&mut self, block: &Block) {
+		let excesses = block.kernels().iter().map(|k| k.excess()).collect();
+		self.add_kernels(block.header.height, excesses);
+	}
+
+	/// Index the kernel excesses at the height. Kernels at this height and above
+	/// belong to the orphaned fork and are dropped first.
+	pub fn add_kernels(&mut self, height: u64, excesses: Vec<Commitment>) {
+		if self.window == 0 {
+			return;
+		}
+		self.rewind_to(|h| h >= height);
+		for excess in &excesses {
+			self.by_excess.insert(*excess, height);
+		}
+		self.by_height.push_back((height, excesses));
+
+		let min_height = height.saturating_sub(self.window - 1);
+		while self.by_height.front().map(|(h, _)| *h < min_height) == Some(true) {
+			if let Some((_, expired)) = self.by_height.pop_front() {
+				self.remove(&expired);
+			}
+		}
+	}
+
+	/// Drop the kernels of the blocks above the fork point, these blocks belong
+	/// to the abandoned branch of the reorg.
+	pub fn rewind(&mut self, fork_height: u64) {
+		self.rewind_to(|h| h > fork_height);
+	}
+
+	/// Number of the recent blocks the index keeps
+	pub fn window(&self) -> u64 {
+		self.window
+	}
+
+	/// Height of the recent block with the kernel, None if the kernel is not known
+	pub fn kernel_height(&self, excess: &Commitment) -> Option<u64> {
+		self.by_excess.get(excess).cloned()
+	}
+
+	/// Is the kernel in one of the recent blocks
+	pub fn contains(&self, excess: &Commitment) -> bool {
+		self.by_excess.contains_key(excess)
+	}
+
+	/// Number of the indexed kernels
+	pub fn len(&self) -> usize {
+		self.by_excess.len()
+	}
+
+	/// Is the index empty
+	pub fn is_empty(&self) -> bool {
+		self.by_excess.is_empty()
+	}
+
+	fn rewind_to<F: Fn(u64) -> bool>(&mut self, orphaned: F) {
+		while self.by_height.back().map(|(h, _)| orphaned(*h)) == Some(true) {
+			if let Some((_, excesses)) = self.by_height.pop_back() {
+				self.remove(&excesses);
+			}
+		}
+	}
+
+	fn remove(&mut self, excesses: &[Commitment]) {
+		for excess in excesses {
+			self.by_excess.remove(excess);
+		}
+	}
+}
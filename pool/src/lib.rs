@@ -30,12 +30,18 @@ extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
+pub mod kernel_index;
 mod pool;
+pub mod relay_policy;
+pub mod spend_filter;
 pub mod transaction_pool;
 pub mod types;
 
+pub use crate::kernel_index::RecentKernelIndex;
 pub use crate::pool::Pool;
+pub use crate::relay_policy::{PolicyStage, RelayPolicy, RelayPolicyConfig, RelayPolicyStats};
 pub use crate::transaction_pool::TransactionPool;
 pub use crate::types::{
-	BlockChain, DandelionConfig, PoolAdapter, PoolConfig, PoolEntry, PoolError, TxSource,
+	BlockChain, DandelionConfig, DandelionEpochInfo, PoolAdapter, PoolConfig, PoolEntry, PoolError,
+	StemAggregationStats, TxRemovalReason, TxSelection, TxSource,
 };
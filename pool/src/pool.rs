@@ -22,8 +22,10 @@ use self::core::core::verifier_cache::VerifierCache;
 use self::core::core::{
 	Block, BlockHeader, BlockSums, Committed, OutputIdentifier, Transaction, TxKernel, Weighting,
 };
+use self::util::secp::pedersen::Commitment;
 use self::util::RwLock;
-use crate::types::{BlockChain, PoolEntry, PoolError};
+use crate::spend_filter::{SpendFilter, DEFAULT_FILTER_BITS};
+use crate::types::{BlockChain, PoolEntry, PoolError, TxRemovalReason, TxSelection};
 use grin_core as core;
 use grin_util as util;
 use std::cmp::Reverse;
@@ -41,6 +43,8 @@ where
 	pub blockchain: Arc<B>,
 	pub verifier_cache: Arc<RwLock<V>>,
 	pub name: String,
+	/// Inputs spent by the entries, rebuilt when the entries are removed
+	spend_filter: SpendFilter,
 }
 
 impl<B, V> Pool<B, V>
@@ -54,6 +58,35 @@ where
 			blockchain: chain,
 			verifier_cache,
 			name,
+			spend_filter: SpendFilter::new(DEFAULT_FILTER_BITS),
+		}
+	}
+
+	/// Input of the transaction that is already spent by the pool entries, None if there
+	/// is no conflict. The filter answers for most of the transactions, the pool entries
+	/// are scanned only if it reports a possible conflict.
+	pub fn find_double_spend(&self, tx: &Transaction) -> Option<Commitment> {
+		let candidates: Vec<Commitment> = tx
+			.inputs_committed()
+			.into_iter()
+			.filter(|c| self.spend_filter.may_contain(c))
+			.collect();
+		if candidates.is_empty() {
+			return None;
+		}
+		self.entries
+			.iter()
+			.flat_map(|x| x.tx.inputs_committed())
+			.find(|c| candidates.contains(c))
+	}
+
+	// Entries were removed, the filter can't forget the inputs
+	fn rebuild_spend_filter(&mut self) {
+		self.spend_filter.clear();
+		for x in &self.entries {
+			for commit in x.tx.inputs_committed() {
+				self.spend_filter.insert(&commit);
+			}
 		}
 	}
 
@@ -65,10 +98,15 @@ where
 
 	/// Query the tx pool for an individual tx matching the given kernel hash.
 	pub fn retrieve_tx_by_kernel_hash(&self, hash: Hash) -> Option<Transaction> {
+		self.retrieve_entry_by_kernel_hash(hash).map(|x| x.tx)
+	}
+
+	/// Query the tx pool for the pool entry with the tx matching the provided kernel hash.
+	pub fn retrieve_entry_by_kernel_hash(&self, hash: Hash) -> Option<PoolEntry> {
 		for x in &self.entries {
 			for k in x.tx.kernels() {
 				if k.hash() == hash {
-					return Some(x.tx.clone());
+					return Some(x.clone());
 				}
 			}
 		}
@@ -120,6 +158,7 @@ where
 	pub fn prepare_mineable_transactions(
 		&self,
 		max_weight: u64,
+		selection: TxSelection,
 	) -> Result<Vec<Transaction>, PoolError> {
 		let weighting = Weighting::AsLimitedTransaction(max_weight);
 
@@ -127,7 +166,7 @@ where
 		//   * maintain dependency ordering
 		//   * maximize cut-through
 		//   * maximize overall fees
-		let txs = self.bucket_transactions(weighting);
+		let txs = self.bucket_transactions(weighting, selection);
 
 		// Iteratively apply the txs to the current chain state,
 		// rejecting any that do not result in a valid state.
@@ -201,6 +240,9 @@ where
 		self.validate_raw_tx(&agg_tx, header, Weighting::NoLimit)?;
 		// If we get here successfully then we can safely add the entry to the pool.
 		self.log_pool_add(&entry, header);
+		for commit in entry.tx.inputs_committed() {
+			self.spend_filter.insert(&commit);
+		}
 		self.entries.push(entry);
 
 		Ok(())
@@ -320,26 +362,43 @@ where
 		})
 	}
 
+	/// Revalidate all the entries, returns the entries that are no longer valid.
 	pub fn reconcile(
 		&mut self,
 		extra_tx: Option<Transaction>,
 		header: &BlockHeader,
-	) -> Result<(), PoolError> {
+	) -> Result<Vec<PoolEntry>, PoolError> {
 		let existing_entries = self.entries.clone();
 		self.entries.clear();
+		self.spend_filter.clear();
+		let mut rejected = vec![];
 		for x in existing_entries {
-			let _ = self.add_to_pool(x, extra_tx.clone(), header);
+			if self
+				.add_to_pool(x.clone(), extra_tx.clone(), header)
+				.is_err()
+			{
+				rejected.push(x);
+			}
 		}
-		Ok(())
+		Ok(rejected)
 	}
 
 	// Use our bucket logic to identify the best transaction for eviction and evict it.
 	// We want to avoid evicting a transaction where another transaction depends on it.
 	// We want to evict a transaction with low fee_to_weight.
-	pub fn evict_transaction(&mut self) {
+	// Returns the evicted entries.
+	pub fn evict_transaction(&mut self) -> Vec<PoolEntry> {
+		let mut evicted = vec![];
 		if let Some(evictable_transaction) = self.bucket_transactions(Weighting::NoLimit).last() {
-			self.entries.retain(|x| x.tx != *evictable_transaction);
+			let (removed, kept): (Vec<_>, Vec<_>) = self
+				.entries
+				.drain(..)
+				.partition(|x| x.tx == *evictable_transaction);
+			self.entries = kept;
+			evicted = removed;
+			self.rebuild_spend_filter();
 		};
+		evicted
 	}
 
 	/// Buckets consist of a vec of txs and track the aggregate fee_to_weight.
@@ -349,7 +408,11 @@ where
 	/// containing the tx it depends on.
 	/// Sorting the buckets by fee_to_weight will therefore preserve dependency ordering,
 	/// maximizing both cut-through and overall fees.
-	fn bucket_transactions(&self, weighting: Weighting) -> Vec<Transaction> {
+	fn bucket_transactions(
+		&self,
+		weighting: Weighting,
+		selection: TxSelection,
+	) -> Vec<Transaction> {
 		let mut tx_buckets: Vec<Bucket> = Vec::new();
 		let mut output_commits = HashMap::new();
 		let mut rejected = HashSet::new();
@@ -441,7 +504,18 @@ where
 		// Txs with highest fee_to_weight will be prioritied.
 		// Aggregation that increases the fee_to_weight of a bucket will prioritize the bucket.
 		// Oldest (based on pool insertion time) will then be prioritized.
-		tx_buckets.sort_unstable_by_key(|x| (Reverse(x.fee_to_weight), x.age_idx));
+		match selection {
+			TxSelection::Greedy => {
+				tx_buckets.sort_unstable_by_key(|x| (Reverse(x.fee_to_weight), x.age_idx))
+			}
+			// Kernel excess of the first tx in the bucket breaks the ties, the buckets
+			// don't depend on the pool insertion order then.
+			TxSelection::Deterministic => tx_buckets.sort_unstable_by(|a, b| {
+				b.fee_to_weight
+					.cmp(&a.fee_to_weight)
+					.then_with(|| a.first_kernel_excess().cmp(b.first_kernel_excess()))
+			}),
+		}
 
 		tx_buckets.into_iter().flat_map(|x| x.raw_txs).collect()
 	}
@@ -467,17 +541,28 @@ where
 
 	/// Quick reconciliation step - we can evict any txs in the pool where
 	/// inputs or kernels intersect with the block.
-	pub fn reconcile_block(&mut self, block: &Block) {
+	/// Returns the evicted entries with the eviction reason.
+	pub fn reconcile_block(&mut self, block: &Block) -> Vec<(PoolEntry, TxRemovalReason)> {
 		// Filter txs in the pool based on the latest block.
 		// Reject any txs where we see a matching tx kernel in the block.
 		// Also reject any txs where we see a conflicting tx,
 		// where an input is spent in a different tx.
 		let block_inputs: Vec<_> = block.inputs().into();
-		self.entries.retain(|x| {
+		let mut removed = vec![];
+		for x in std::mem::replace(&mut self.entries, vec![]) {
 			let tx_inputs: Vec<_> = x.tx.inputs().into();
-			!x.tx.kernels().iter().any(|y| block.kernels().contains(y))
-				&& !tx_inputs.iter().any(|y| block_inputs.contains(y))
-		});
+			if x.tx.kernels().iter().any(|y| block.kernels().contains(y)) {
+				removed.push((x, TxRemovalReason::Block));
+			} else if tx_inputs.iter().any(|y| block_inputs.contains(y)) {
+				removed.push((x, TxRemovalReason::Conflict));
+			} else {
+				self.entries.push(x);
+			}
+		}
+		if !removed.is_empty() {
+			self.rebuild_spend_filter();
+		}
+		removed
 	}
 
 	/// Size of the pool.
@@ -516,6 +601,14 @@ impl Bucket {
 		}
 	}
 
+	fn first_kernel_excess(&self) -> &[u8] {
+		self.raw_txs
+			.first()
+			.and_then(|tx| tx.kernels().first())
+			.map(|k| &k.excess.0[..])
+			.unwrap_or(&[])
+	}
+
 	fn aggregate_with_tx(
 		&self,
 		new_tx: Transaction,
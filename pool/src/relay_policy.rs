@@ -0,0 +1,217 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local relay policy. The rules are not consensus, they are what this node is willing
+//! to keep in its pool and to relay. The pool evaluates them on every tx it accepts,
+//! the relay evaluates them on the txs it builds itself, like the aggregated stem txs.
+//! Every rule counts the txs it rejected.
+
+use self::core::core::Transaction;
+use crate::types::PoolError;
+use grin_core as core;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Relay policy configuration. Unset rules are not applied.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct RelayPolicyConfig {
+	/// Min fee per unit of the tx weight, the same weight the accept_fee_base is
+	/// applied to
+	#[serde(default)]
+	pub min_fee_rate: Option<u64>,
+	/// Max block weight of the tx
+	#[serde(default)]
+	pub max_tx_weight: Option<u64>,
+	/// Max number of the outputs of the tx
+	#[serde(default)]
+	pub max_outputs: Option<usize>,
+	/// Txs with a kernel of these features are rejected: "Plain", "HeightLocked",
+	/// "NoRecentDuplicate"
+	#[serde(default)]
+	pub reject_kernel_features: Vec<String>,
+}
+
+/// Rule of the relay policy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PolicyRule {
+	/// Fee rate is below min_fee_rate
+	MinFeeRate,
+	/// Tx is heavier than max_tx_weight
+	MaxTxWeight,
+	/// Tx has more than max_outputs outputs
+	MaxOutputs,
+	/// Tx has a kernel with the rejected features
+	KernelFeatures,
+}
+
+const POLICY_RULES: [PolicyRule; 4] = [
+	PolicyRule::MinFeeRate,
+	PolicyRule::MaxTxWeight,
+	PolicyRule::MaxOutputs,
+	PolicyRule::KernelFeatures,
+];
+
+impl PolicyRule {
+	/// Name of the rule, as in the config
+	pub fn name(&self) -> &'static str {
+		match self {
+			PolicyRule::MinFeeRate => "min_fee_rate",
+			PolicyRule::MaxTxWeight => "max_tx_weight",
+			PolicyRule::MaxOutputs => "max_outputs",
+			PolicyRule::KernelFeatures => "reject_kernel_features",
+		}
+	}
+
+	fn index(&self) -> usize {
+		POLICY_RULES.iter().position(|r| r == self).unwrap_or(0)
+	}
+}
+
+/// Where the policy is evaluated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PolicyStage {
+	/// Tx is added to the pool
+	Pool,
+	/// Tx built by the node is relayed
+	Relay,
+}
+
+/// Rejections of the rule
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PolicyRuleStats {
+	/// Name of the rule
+	pub rule: String,
+	/// Txs rejected by the pool
+	pub pool_rejected: u64,
+	/// Txs not relayed
+	pub relay_rejected: u64,
+}
+
+/// Relay policy counters
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct RelayPolicyStats {
+	/// Number of the evaluated txs
+	pub evaluated: u64,
+	/// Number of the rejected txs
+	pub rejected: u64,
+	/// Rejections per rule
+	pub rules: Vec<PolicyRuleStats>,
+}
+
+/// Relay policy with its counters
+pub struct RelayPolicy {
+	config: RelayPolicyConfig,
+	evaluated: AtomicU64,
+	pool_rejected: [AtomicU64; 4],
+	relay_rejected: [AtomicU64; 4],
+}
+
+impl RelayPolicy {
+	/// Policy from the config
+	pub fn new(config: RelayPolicyConfig) -> RelayPolicy {
+		RelayPolicy {
+			config,
+			evaluated: AtomicU64::new(0),
+			pool_rejected: Default::default(),
+			relay_rejected: Default::default(),
+		}
+	}
+
+	/// Policy config
+	pub fn config(&self) -> &RelayPolicyConfig {
+		&self.config
+	}
+
+	/// Evaluate the rules, the first broken one rejects the tx
+	pub fn check(&self, tx: &Transaction, stage: PolicyStage) -> Result<(), PoolError> {
+		self.evaluated.fetch_add(1, Ordering::Relaxed);
+		let (rule, reason) = match self.violation(tx) {
+			Some(violation) => violation,
+			None => return Ok(()),
+		};
+		let counters = match stage {
+			PolicyStage::Pool => &self.pool_rejected,
+			PolicyStage::Relay => &self.relay_rejected,
+		};
+		counters[rule.index()].fetch_add(1, Ordering::Relaxed);
+		Err(PoolError::PolicyViolation(format!(
+			"{}, {}",
+			rule.name(),
+			reason
+		)))
+	}
+
+	fn violation(&self, tx: &Transaction) -> Option<(PolicyRule, String)> {
+		if let Some(min_fee_rate) = self.config.min_fee_rate {
+			let threshold = tx.tx_weight().saturating_mul(min_fee_rate);
+			if tx.fee() < threshold {
+				return Some((
+					PolicyRule::MinFeeRate,
+					format!("fee {} is below {}", tx.fee(), threshold),
+				));
+			}
+		}
+		if let Some(max_tx_weight) = self.config.max_tx_weight {
+			let weight = tx.tx_weight_as_block();
+			if weight > max_tx_weight {
+				return Some((
+					PolicyRule::MaxTxWeight,
+					format!("weight {} is above {}", weight, max_tx_weight),
+				));
+			}
+		}
+		if let Some(max_outputs) = self.config.max_outputs {
+			let outputs = tx.outputs().len();
+			if outputs > max_outputs {
+				return Some((
+					PolicyRule::MaxOutputs,
+					format!("{} outputs, max {}", outputs, max_outputs),
+				));
+			}
+		}
+		if !self.config.reject_kernel_features.is_empty() {
+			for kernel in tx.kernels() {
+				let features = kernel.features.as_string();
+				if self
+					.config
+					.reject_kernel_features
+					.iter()
+					.any(|f| f.eq_ignore_ascii_case(&features))
+				{
+					return Some((PolicyRule::KernelFeatures, format!("{} kernel", features)));
+				}
+			}
+		}
+		None
+	}
+
+	/// Counters of the policy
+	pub fn stats(&self) -> RelayPolicyStats {
+		let rules: Vec<PolicyRuleStats> = POLICY_RULES
+			.iter()
+			.map(|r| PolicyRuleStats {
+				rule: r.name().to_string(),
+				pool_rejected: self.pool_rejected[r.index()].load(Ordering::Relaxed),
+				relay_rejected: self.relay_rejected[r.index()].load(Ordering::Relaxed),
+			})
+			.collect();
+		RelayPolicyStats {
+			evaluated: self.evaluated.load(Ordering::Relaxed),
+			rejected: rules
+				.iter()
+				.map(|r| r.pool_rejected + r.relay_rejected)
+				.sum(),
+			rules,
+		}
+	}
+}
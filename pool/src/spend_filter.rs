@@ -0,0 +1,94 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bloom filter over the input commitments spent by the pool transactions. Most of the
+//! new transactions don't conflict with the pool, the filter answers that without
+//! scanning the pool entries. Only the positive answers fall back to the exact lookup.
+//! Bloom filter can't remove the items, the pool rebuilds it when the entries are removed.
+
+use self::util::secp::pedersen::Commitment;
+use grin_util as util;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+/// Default size of the filter in bits. 1M bits keep the false positive rate about 1%
+/// for 100k inputs.
+pub const DEFAULT_FILTER_BITS: usize = 1 << 20;
+/// Number of the bits set for every item
+const FILTER_HASHES: u64 = 4;
+
+/// Bloom filter of the commitments
+pub struct SpendFilter {
+	bits: Vec<u64>,
+	bit_count: u64,
+	items: usize,
+	// Random keys, so the peers can't craft the commitments that saturate the filter
+	hasher: RandomState,
+}
+
+impl SpendFilter {
+	/// Empty filter with the size in bits, rounded up to 64
+	pub fn new(bit_count: usize) -> SpendFilter {
+		let words = (bit_count.max(64) + 63) / 64;
+		SpendFilter {
+			bits: vec![0; words],
+			bit_count: words as u64 * 64,
+			items: 0,
+			hasher: RandomState::new(),
+		}
+	}
+
+	fn bit_indexes(&self, commit: &Commitment) -> impl Iterator<Item = u64> {
+		let mut hasher = self.hasher.build_hasher();
+		commit.hash(&mut hasher);
+		let hash = hasher.finish();
+		// Double hashing, the high and the low halves are the two hashes
+		let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+		let bit_count = self.bit_count;
+		(0..FILTER_HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bit_count)
+	}
+
+	/// Add the commitment
+	pub fn insert(&mut self, commit: &Commitment) {
+		let indexes: Vec<u64> = self.bit_indexes(commit).collect();
+		for i in indexes {
+			self.bits[(i / 64) as usize] |= 1 << (i % 64);
+		}
+		self.items += 1;
+	}
+
+	/// False if the commitment was never added. True if it might be added.
+	pub fn may_contain(&self, commit: &Commitment) -> bool {
+		self.bit_indexes(commit)
+			.all(|i| self.bits[(i / 64) as usize] & (1 << (i % 64)) != 0)
+	}
+
+	/// Remove all the commitments
+	pub fn clear(&mut self) {
+		for word in self.bits.iter_mut() {
+			*word = 0;
+		}
+		self.items = 0;
+	}
+
+	/// Number of the added commitments
+	pub fn len(&self) -> usize {
+		self.items
+	}
+
+	/// True if nothing was added
+	pub fn is_empty(&self) -> bool {
+		self.items == 0
+	}
+}
@@ -25,13 +25,18 @@ use self::core::core::{
 };
 use self::core::global;
 use self::util::RwLock;
+use crate::kernel_index::RecentKernelIndex;
 use crate::pool::Pool;
-use crate::types::{BlockChain, PoolAdapter, PoolConfig, PoolEntry, PoolError, TxSource};
+use crate::relay_policy::{PolicyStage, RelayPolicy};
+use crate::types::{
+	BlockChain, PoolAdapter, PoolConfig, PoolEntry, PoolError, TxRemovalReason, TxSource,
+};
 use chrono::prelude::*;
 use grin_core as core;
 use grin_core::ser;
 use grin_keychain::base58;
 use grin_util as util;
+use grin_util::secp::pedersen::Commitment;
 use lru_cache::LruCache;
 use std::collections::VecDeque;
 use std::sync::Arc;
@@ -58,6 +63,10 @@ where
 	pub adapter: Arc<P>,
 	///the replay attack cache
 	pub replay_verifier_cache: Arc<RwLock<LruCache<[u8; 32], ()>>>,
+	/// Kernels of the recent blocks
+	pub kernel_index: RecentKernelIndex,
+	/// Local relay rules, shared with the relay
+	pub relay_policy: Arc<RelayPolicy>,
 }
 
 impl<B, P, V> TransactionPool<B, P, V>
@@ -74,6 +83,8 @@ where
 		adapter: Arc<P>,
 	) -> Self {
 		TransactionPool {
+			kernel_index: RecentKernelIndex::new(config.recent_kernel_window),
+			relay_policy: Arc::new(RelayPolicy::new(config.relay_policy.clone())),
 			config,
 			txpool: Pool::new(chain.clone(), verifier_cache.clone(), "txpool".to_string()),
 			stempool: Pool::new(
@@ -156,6 +167,33 @@ where
 		Ok(())
 	}
 
+	/// Cheap check of the tx kernels before the validation. Tx with a kernel from a recent
+	/// block can't be accepted, tx with all the kernels in the txpool is redundant.
+	/// NRD kernels are allowed to reuse the excess, they are checked by the validation.
+	fn check_duplicate_kernels(&self, tx: &Transaction) -> Result<(), PoolError> {
+		let kernels = tx.kernels();
+		if kernels
+			.iter()
+			.any(|k| !k.is_nrd() && self.kernel_index.contains(&k.excess()))
+		{
+			return Err(PoolError::DuplicateKernel);
+		}
+		if !kernels.is_empty()
+			&& kernels.iter().all(|k| {
+				self.txpool
+					.retrieve_entry_by_kernel_hash(k.hash())
+					.is_some()
+			}) {
+			return Err(PoolError::DuplicateKernel);
+		}
+		Ok(())
+	}
+
+	/// Is the kernel in one of the recent blocks known to the pool
+	pub fn is_recent_kernel(&self, excess: &Commitment) -> bool {
+		self.kernel_index.contains(excess)
+	}
+
 	/// Add the given tx to the pool, directing it to either the stempool or
 	/// txpool based on stem flag provided.
 	pub fn add_to_pool(
@@ -175,6 +213,9 @@ where
 			return Err(PoolError::DuplicateTx);
 		}
 
+		// Reject the already seen kernels before the expensive validation.
+		self.check_duplicate_kernels(&tx)?;
+
 		// Attempt to deaggregate the tx if not stem tx.
 		let entry = if stem {
 			PoolEntry::new(tx, src)
@@ -183,6 +224,14 @@ where
 		};
 		let ref tx = entry.tx;
 
+		// Reject the double spends of the pool inputs before the expensive validation.
+		// Stem tx is validated against the txpool too.
+		if self.txpool.find_double_spend(tx).is_some()
+			|| (stem && self.stempool.find_double_spend(tx).is_some())
+		{
+			return Err(PoolError::DoubleSpend);
+		}
+
 		// Check this tx is valid based on current header version.
 		// NRD kernels only valid post HF3 and if NRD feature enabled.
 		self.verify_kernel_variants(tx, header)?;
@@ -196,6 +245,11 @@ where
 			return acceptability;
 		}
 
+		// Local relay rules. The aggregated stem txs passed them one by one.
+		if entry.src != TxSource::Fluff {
+			self.relay_policy.check(tx, PolicyStage::Pool)?;
+		}
+
 		// Make sure the transaction is valid before anything else.
 		// Validate tx accounting for max tx weight.
 		tx.validate(Weighting::AsTransaction, self.verifier_cache.clone())
@@ -307,7 +361,9 @@ where
 	// Uses bucket logic to identify the "last" transaction.
 	// No other tx depends on it and it has low fee_to_weight.
 	pub fn evict_from_txpool(&mut self) {
-		self.txpool.evict_transaction()
+		for entry in self.txpool.evict_transaction() {
+			self.adapter.tx_removed(&entry, TxRemovalReason::Eviction);
+		}
 	}
 
 	// Old txs will "age out" after 30 mins.
@@ -332,7 +388,9 @@ where
 			header.hash(),
 		);
 		for entry in entries {
-			let _ = self.add_to_txpool(&entry, header);
+			if self.add_to_txpool(&entry, header).is_ok() {
+				self.adapter.tx_restored(&entry);
+			}
 		}
 		debug!(
 			"reconcile_reorg_cache: block: {:?} ... done.",
@@ -362,9 +420,15 @@ where
 			debug!("---------------- BEFORE END --------------");
 		}
 
+		self.kernel_index.add_block(block);
+
 		// First reconcile the txpool.
-		self.txpool.reconcile_block(block);
-		self.txpool.reconcile(None, &block.header)?;
+		for (entry, reason) in self.txpool.reconcile_block(block) {
+			self.adapter.tx_removed(&entry, reason);
+		}
+		for entry in self.txpool.reconcile(None, &block.header)? {
+			self.adapter.tx_removed(&entry, TxRemovalReason::Conflict);
+		}
 
 		// Now reconcile our stempool, accounting for the updated txpool txs.
 		self.stempool.reconcile_block(block);
@@ -391,11 +455,46 @@ where
 		Ok(())
 	}
 
+	/// Re-index the recent kernels after the reorg to the block. Kernels above the fork
+	/// point belong to the abandoned branch, the blocks of the new branch below this
+	/// block arrived as forks and are indexed from the chain.
+	pub fn reindex_kernels(&mut self, fork_height: u64, block: &Block) -> Result<(), PoolError> {
+		self.kernel_index.rewind(fork_height);
+		if self.kernel_index.window() == 0 {
+			return Ok(());
+		}
+		let min_height = block
+			.header
+			.height
+			.saturating_sub(self.kernel_index.window() - 1)
+			.max(fork_height + 1);
+		let mut branch = vec![];
+		let mut hash = block.header.prev_hash;
+		loop {
+			let header = self.blockchain.get_block_header(&hash)?;
+			if header.height < min_height {
+				break;
+			}
+			hash = header.prev_hash;
+			branch.push(header);
+		}
+		for header in branch.iter().rev() {
+			let b = self.blockchain.get_block(&header.hash())?;
+			self.kernel_index.add_block(&b);
+		}
+		Ok(())
+	}
+
 	/// Retrieve individual transaction for the given kernel hash.
 	pub fn retrieve_tx_by_kernel_hash(&self, hash: Hash) -> Option<Transaction> {
 		self.txpool.retrieve_tx_by_kernel_hash(hash)
 	}
 
+	/// Retrieve the txpool entry with the tx matching the provided kernel hash.
+	pub fn retrieve_entry_by_kernel_hash(&self, hash: Hash) -> Option<PoolEntry> {
+		self.txpool.retrieve_entry_by_kernel_hash(hash)
+	}
+
 	/// Retrieve all transactions matching the provided "compact block"
 	/// based on the kernel set.
 	/// Note: we only look in the txpool for this (stempool is under embargo).
@@ -440,10 +539,39 @@ where
 		self.txpool.size()
 	}
 
+	/// Fluff all the stempool txs. Txs are validated against the txpool, aggregated and
+	/// added to the txpool. Returns the number of the fluffed txs.
+	pub fn fluff_stempool(&mut self) -> Result<usize, PoolError> {
+		let all_entries = self.stempool.entries.clone();
+		if all_entries.is_empty() {
+			return Ok(0);
+		}
+
+		let header = self.chain_head()?;
+
+		let fluffable_txs = {
+			let txpool_tx = self.txpool.all_transactions_aggregate(None)?;
+			let txs: Vec<_> = all_entries.into_iter().map(|x| x.tx).collect();
+			self.stempool
+				.validate_raw_txs(&txs, txpool_tx, &header, Weighting::NoLimit)?
+		};
+		if fluffable_txs.is_empty() {
+			return Ok(0);
+		}
+
+		let agg_tx = transaction::aggregate(&fluffable_txs)?;
+		agg_tx.validate(Weighting::AsTransaction, self.verifier_cache.clone())?;
+
+		self.add_to_pool(TxSource::Fluff, agg_tx, false, &header)?;
+		Ok(fluffable_txs.len())
+	}
+
 	/// Returns a vector of transactions from the txpool so we can build a
 	/// block from them.
 	pub fn prepare_mineable_transactions(&self) -> Result<Vec<Transaction>, PoolError> {
-		self.txpool
-			.prepare_mineable_transactions(self.config.mineable_max_weight)
+		self.txpool.prepare_mineable_transactions(
+			self.config.mineable_max_weight,
+			self.config.mineable_selection,
+		)
 	}
 }
@@ -20,11 +20,14 @@ use self::core::core::block;
 use self::core::core::committed;
 use self::core::core::hash::Hash;
 use self::core::core::transaction::{self, Transaction};
-use self::core::core::{BlockHeader, BlockSums, Inputs, OutputIdentifier};
+use self::core::core::{Block, BlockHeader, BlockSums, Inputs, OutputIdentifier};
+use self::util::clock;
+use crate::relay_policy::RelayPolicyConfig;
 use chrono::prelude::*;
 use failure::Fail;
 use grin_core as core;
 use grin_keychain as keychain;
+use grin_util as util;
 
 /// Dandelion "epoch" length.
 const DANDELION_EPOCH_SECS: u16 = 600;
@@ -44,6 +47,22 @@ const DANDELION_STEM_PROBABILITY: u8 = 90;
 /// If set to false we will stem/fluff our txs as per current epoch.
 const DANDELION_ALWAYS_STEM_OUR_TXS: bool = true;
 
+/// Privacy mode for our (pushed via api) txs. Disabled by default.
+const DANDELION_OWN_TX_PRIVACY: bool = false;
+
+/// Max random delay before the first relay of our txs (privacy mode).
+const DANDELION_OWN_TX_RELAY_DELAY_SECS: u16 = 30;
+
+/// Our txs are not served to the peers for this number of minutes (privacy mode).
+const DANDELION_OWN_TX_PRIVATE_MINS: u16 = 10;
+
+/// Received stem txs are aggregated for this number of seconds before the relay.
+/// Disabled by default.
+const DANDELION_STEM_AGGREGATION_SECS: u16 = 0;
+
+/// Max number of the received stem txs aggregated together.
+const DANDELION_STEM_AGGREGATION_MAX_TXS: u16 = 8;
+
 /// Configuration for "Dandelion".
 /// Note: shared between p2p and pool.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -64,6 +83,25 @@ pub struct DandelionConfig {
 	/// Default to always stem our txs as described in Dandelion++ paper.
 	#[serde(default = "default_dandelion_always_stem_our_txs")]
 	pub always_stem_our_txs: bool,
+	/// Privacy mode for our (pushed via api) txs. First relay is delayed by a random
+	/// interval, the txs are always stemmed (even with fluff), stem peer is selected
+	/// from the Tor peers only and the txs are not served to the peers for the first
+	/// own_tx_private_mins minutes.
+	#[serde(default = "default_dandelion_own_tx_privacy")]
+	pub own_tx_privacy: bool,
+	/// Max random delay before the first relay of our txs in privacy mode.
+	#[serde(default = "default_dandelion_own_tx_relay_delay_secs")]
+	pub own_tx_relay_delay_secs: u16,
+	/// Number of minutes our txs are not served to the peers in privacy mode.
+	#[serde(default = "default_dandelion_own_tx_private_mins")]
+	pub own_tx_private_mins: u16,
+	/// Stem txs received from the peers are aggregated together for this number of
+	/// seconds before the relay in the stem epoch. 0 - relay them right away.
+	#[serde(default = "default_dandelion_stem_aggregation_secs")]
+	pub stem_aggregation_secs: u16,
+	/// Aggregated stem txs are relayed as soon as there are that many of them.
+	#[serde(default = "default_dandelion_stem_aggregation_max_txs")]
+	pub stem_aggregation_max_txs: u16,
 }
 
 impl Default for DandelionConfig {
@@ -74,6 +112,11 @@ impl Default for DandelionConfig {
 			aggregation_secs: default_dandelion_aggregation_secs(),
 			stem_probability: default_dandelion_stem_probability(),
 			always_stem_our_txs: default_dandelion_always_stem_our_txs(),
+			own_tx_privacy: default_dandelion_own_tx_privacy(),
+			own_tx_relay_delay_secs: default_dandelion_own_tx_relay_delay_secs(),
+			own_tx_private_mins: default_dandelion_own_tx_private_mins(),
+			stem_aggregation_secs: default_dandelion_stem_aggregation_secs(),
+			stem_aggregation_max_txs: default_dandelion_stem_aggregation_max_txs(),
 		}
 	}
 }
@@ -98,6 +141,77 @@ fn default_dandelion_always_stem_our_txs() -> bool {
 	DANDELION_ALWAYS_STEM_OUR_TXS
 }
 
+fn default_dandelion_own_tx_privacy() -> bool {
+	DANDELION_OWN_TX_PRIVACY
+}
+
+fn default_dandelion_own_tx_relay_delay_secs() -> u16 {
+	DANDELION_OWN_TX_RELAY_DELAY_SECS
+}
+
+fn default_dandelion_own_tx_private_mins() -> u16 {
+	DANDELION_OWN_TX_PRIVATE_MINS
+}
+
+fn default_dandelion_stem_aggregation_secs() -> u16 {
+	DANDELION_STEM_AGGREGATION_SECS
+}
+
+fn default_dandelion_stem_aggregation_max_txs() -> u16 {
+	DANDELION_STEM_AGGREGATION_MAX_TXS
+}
+
+/// Aggregation of the received stem txs
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StemAggregationStats {
+	/// Number of the relayed batches of the stem txs
+	pub batches: u64,
+	/// Number of the stem txs in the relayed batches
+	pub txs: u64,
+}
+
+impl StemAggregationStats {
+	/// Average number of the stem txs aggregated together
+	pub fn aggregation_factor(&self) -> f64 {
+		if self.batches == 0 {
+			0.0
+		} else {
+			self.txs as f64 / self.batches as f64
+		}
+	}
+}
+
+/// Current Dandelion epoch of the node
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DandelionEpochInfo {
+	/// Unix time when the epoch started, None if no epoch started yet
+	pub start_time: Option<i64>,
+	/// Unix time when the epoch expires
+	pub expiry_time: Option<i64>,
+	/// Stemming (or fluffing) transactions in this epoch
+	pub is_stem: bool,
+	/// Address of the stem relay peer of this epoch
+	pub relay_peer: Option<String>,
+	/// Stem txs are fluffed if they are in the stempool for that long
+	pub embargo_secs: u16,
+}
+
+/// How the transactions are selected for the block template
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum TxSelection {
+	/// Highest fee rate first, the txs with the same fee rate in the pool insertion order
+	Greedy,
+	/// Highest fee rate first, the txs with the same fee rate ordered by the kernel excess.
+	/// Nodes with the same txpool build identical templates.
+	Deterministic,
+}
+
+impl Default for TxSelection {
+	fn default() -> TxSelection {
+		TxSelection::Greedy
+	}
+}
+
 /// Transaction pool configuration
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct PoolConfig {
@@ -124,6 +238,20 @@ pub struct PoolConfig {
 	/// blocks.
 	#[serde(default = "default_mineable_max_weight")]
 	pub mineable_max_weight: u64,
+
+	/// Order of the transactions selected to build a block
+	#[serde(default)]
+	pub mineable_selection: TxSelection,
+
+	/// Number of the recent blocks whose kernels are indexed. Transactions with a kernel
+	/// from these blocks, or with all the kernels already in the pool, are rejected
+	/// before the validation. 0 - disabled.
+	#[serde(default = "default_recent_kernel_window")]
+	pub recent_kernel_window: u64,
+
+	/// Local rules for the txs this node keeps in its pool and relays
+	#[serde(default)]
+	pub relay_policy: RelayPolicyConfig,
 }
 
 impl Default for PoolConfig {
@@ -134,6 +262,9 @@ impl Default for PoolConfig {
 			reorg_cache_timeout: default_reorg_cache_timeout(),
 			max_stempool_size: default_max_stempool_size(),
 			mineable_max_weight: default_mineable_max_weight(),
+			mineable_selection: TxSelection::default(),
+			recent_kernel_window: default_recent_kernel_window(),
+			relay_policy: RelayPolicyConfig::default(),
 		}
 	}
 }
@@ -153,6 +284,9 @@ fn default_max_stempool_size() -> usize {
 fn default_mineable_max_weight() -> u64 {
 	consensus::MAX_BLOCK_WEIGHT
 }
+fn default_recent_kernel_window() -> u64 {
+	consensus::DAY_HEIGHT
+}
 
 /// Represents a single entry in the pool.
 /// A single (possibly aggregated) transaction.
@@ -170,7 +304,7 @@ impl PoolEntry {
 	pub fn new(tx: Transaction, src: TxSource) -> PoolEntry {
 		PoolEntry {
 			src,
-			tx_at: Utc::now(),
+			tx_at: clock::now(),
 			tx,
 		}
 	}
@@ -189,6 +323,7 @@ pub enum TxSource {
 	Fluff,
 	EmbargoExpired,
 	Deaggregate,
+	Handoff,
 }
 
 impl TxSource {
@@ -201,6 +336,20 @@ impl TxSource {
 	}
 }
 
+/// Why the transaction was removed from the txpool.
+/// Note: txpool doesn't expire the transactions, they stay until mined, conflicted or evicted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxRemovalReason {
+	/// The transaction kernel is included in the block.
+	Block,
+	/// The transaction inputs are spent by another transaction, or the transaction
+	/// is no longer valid against the new chain state.
+	Conflict,
+	/// The transaction was evicted to make space for the better paying one.
+	Eviction,
+}
+
 /// Possible errors when interacting with the transaction pool.
 #[derive(Debug, Fail, PartialEq)]
 pub enum PoolError {
@@ -241,6 +390,13 @@ pub enum PoolError {
 	/// Attempt to add a duplicate tx to the pool.
 	#[fail(display = "Tx Pool Duplicate tx")]
 	DuplicateTx,
+	/// Attempt to spend an output that is already spent by a pool transaction.
+	#[fail(display = "Tx Pool Double spend")]
+	DoubleSpend,
+	/// Attempt to add a tx with a kernel that is already in a recent block, or with
+	/// all the kernels already in the pool.
+	#[fail(display = "Tx Pool Duplicate kernel")]
+	DuplicateKernel,
 	/// NRD kernels will not be accepted by the txpool/stempool pre-HF3.
 	#[fail(display = "NRD kernel pre-HF3")]
 	NRDKernelPreHF3,
@@ -250,6 +406,9 @@ pub enum PoolError {
 	/// NRD kernels are not valid if relative_height rule not met.
 	#[fail(display = "NRD kernel relative height")]
 	NRDKernelRelativeHeight,
+	/// Tx breaks the local relay policy.
+	#[fail(display = "Tx Pool Relay policy {}", _0)]
+	PolicyViolation(String),
 	/// Other kinds of error (not yet pulled out into meaningful errors).
 	#[fail(display = "Tx Pool General error {}", _0)]
 	Other(String),
@@ -303,6 +462,7 @@ pub trait BlockChain: Sync + Send {
 	fn chain_head(&self) -> Result<BlockHeader, PoolError>;
 
 	fn get_block_header(&self, hash: &Hash) -> Result<BlockHeader, PoolError>;
+	fn get_block(&self, hash: &Hash) -> Result<Block, PoolError>;
 	fn get_block_sums(&self, hash: &Hash) -> Result<BlockSums, PoolError>;
 	fn replay_attack_check(&self, tx: &Transaction) -> Result<(), PoolError>;
 }
@@ -316,6 +476,15 @@ pub trait PoolAdapter: Send + Sync {
 
 	/// The stem transaction pool has accepted this transactions as valid.
 	fn stem_tx_accepted(&self, entry: &PoolEntry) -> Result<(), PoolError>;
+
+	/// The transaction from the reorg cache is added back to the txpool after the reorg.
+	fn tx_restored(&self, entry: &PoolEntry);
+
+	/// The transaction is removed from the txpool.
+	fn tx_removed(&self, entry: &PoolEntry, reason: TxRemovalReason);
+
+	/// Current Dandelion epoch, None if the adapter doesn't relay the stem txs.
+	fn dandelion_epoch(&self) -> Option<DandelionEpochInfo>;
 }
 
 /// Dummy adapter used as a placeholder for real implementations
@@ -327,4 +496,9 @@ impl PoolAdapter for NoopPoolAdapter {
 	fn stem_tx_accepted(&self, _entry: &PoolEntry) -> Result<(), PoolError> {
 		Ok(())
 	}
+	fn tx_restored(&self, _entry: &PoolEntry) {}
+	fn tx_removed(&self, _entry: &PoolEntry, _reason: TxRemovalReason) {}
+	fn dandelion_epoch(&self) -> Option<DandelionEpochInfo> {
+		None
+	}
 }
@@ -18,7 +18,7 @@ use self::core::core::hash::Hashed;
 use self::core::core::verifier_cache::LruVerifierCache;
 use self::core::global;
 use self::keychain::{ExtKeychain, Keychain};
-use self::pool::PoolError;
+use self::pool::{PoolError, TxSelection};
 use self::util::RwLock;
 use crate::common::*;
 use grin_core as core;
@@ -111,3 +111,53 @@ fn test_transaction_pool_block_building() -> Result<(), PoolError> {
 
 	Ok(())
 }
+
+#[test]
+fn test_deterministic_tx_selection() -> Result<(), PoolError> {
+	util::init_test_logger();
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let keychain: ExtKeychain = Keychain::from_random_seed(false).unwrap();
+
+	let db_root = "target/.deterministic_tx_selection";
+	clean_output_dir(db_root.into());
+
+	let genesis = genesis_block(&keychain);
+	let chain = Arc::new(init_chain(db_root, genesis));
+	add_some_blocks(&chain, 3, &keychain);
+
+	let header_1 = chain.get_header_by_height(1).unwrap();
+	let initial_tx = test_transaction_spending_coinbase(&keychain, &header_1, vec![10, 20, 30]);
+	add_block(&chain, &[initial_tx], &keychain);
+	let header = chain.head_header().unwrap();
+
+	// Same fee rate, the order is defined by the kernel excess only
+	let txs = vec![
+		test_transaction(&keychain, vec![10], vec![8]),
+		test_transaction(&keychain, vec![20], vec![18]),
+		test_transaction(&keychain, vec![30], vec![28]),
+	];
+
+	let mut templates = vec![];
+	for order in &[[0, 1, 2], [2, 0, 1], [1, 2, 0]] {
+		let mut pool = init_transaction_pool(
+			Arc::new(ChainAdapter {
+				chain: chain.clone(),
+			}),
+			Arc::new(RwLock::new(LruVerifierCache::new())),
+		);
+		pool.config.mineable_selection = TxSelection::Deterministic;
+		for i in order {
+			pool.add_to_pool(test_source(), txs[*i].clone(), false, &header)?;
+		}
+		templates.push(pool.prepare_mineable_transactions()?);
+	}
+	assert_eq!(templates[0].len(), 3);
+	assert_eq!(templates[0], templates[1]);
+	assert_eq!(templates[0], templates[2]);
+	assert!(templates[0]
+		.windows(2)
+		.all(|w| w[0].kernels()[0].excess.0[..] < w[1].kernels()[0].excess.0[..]));
+
+	clean_output_dir(db_root.into());
+	Ok(())
+}
@@ -17,7 +17,7 @@
 use self::chain::types::{NoopAdapter, Options};
 use self::chain::Chain;
 use self::core::consensus;
-use self::core::core::hash::Hash;
+use self::core::core::hash::{Hash, Hashed};
 use self::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
 use self::core::core::{
 	Block, BlockHeader, BlockSums, Inputs, KernelFeatures, OutputIdentifier, Transaction, TxKernel,
@@ -28,7 +28,7 @@ use self::core::libtx::{build, reward, ProofBuilder};
 use self::core::pow;
 use self::keychain::{BlindingFactor, ExtKeychain, ExtKeychainPath, Keychain};
 use self::pool::types::*;
-use self::pool::TransactionPool;
+use self::pool::{RelayPolicyConfig, TransactionPool};
 use self::util::RwLock;
 use chrono::Duration;
 use grin_chain as chain;
@@ -78,8 +78,25 @@ where
 	K: Keychain,
 {
 	let prev = chain.head_header().unwrap();
+	let block = prepare_block(chain, &prev, txs, keychain);
+	chain.process_block(block, Options::NONE).unwrap();
+}
+
+/// Mined block on top of the header, the header doesn't have to be the chain head
+pub fn prepare_block<K>(
+	chain: &Chain,
+	prev: &BlockHeader,
+	txs: &[Transaction],
+	keychain: &K,
+) -> Block
+where
+	K: Keychain,
+{
 	let height = prev.height + 1;
-	let next_header_info = consensus::next_difficulty(height, chain.difficulty_iter().unwrap());
+	let next_header_info = consensus::next_difficulty(
+		height,
+		chain::store::DifficultyIter::from(prev.hash(), chain.store()),
+	);
 	let fee = txs.iter().map(|x| x.fee()).sum();
 	let key_id = ExtKeychainPath::new(1, height as u32, 0, 0, 0).to_identifier();
 	let reward = reward::output(
@@ -92,7 +109,7 @@ where
 	)
 	.unwrap();
 
-	let mut block = Block::new(&prev, txs, next_header_info.clone().difficulty, reward).unwrap();
+	let mut block = Block::new(prev, txs, next_header_info.clone().difficulty, reward).unwrap();
 
 	block.header.timestamp = prev.timestamp + Duration::seconds(60);
 	block.header.pow.secondary_scaling = next_header_info.secondary_scaling;
@@ -108,8 +125,7 @@ where
 		edge_bits,
 	)
 	.unwrap();
-
-	chain.process_block(block, Options::NONE).unwrap();
+	block
 }
 
 #[derive(Clone)]
@@ -130,6 +146,12 @@ impl BlockChain for ChainAdapter {
 			.map_err(|e| PoolError::Other(format!("failed to get block header, {}", e)))
 	}
 
+	fn get_block(&self, hash: &Hash) -> Result<Block, PoolError> {
+		self.chain
+			.get_block(hash)
+			.map_err(|e| PoolError::Other(format!("failed to get block, {}", e)))
+	}
+
 	fn get_block_sums(&self, hash: &Hash) -> Result<BlockSums, PoolError> {
 		self.chain
 			.get_block_sums(hash)
@@ -184,6 +206,9 @@ where
 			max_pool_size: 50,
 			max_stempool_size: 50,
 			mineable_max_weight: 10_000,
+			mineable_selection: TxSelection::Greedy,
+			recent_kernel_window: 1_440,
+			relay_policy: RelayPolicyConfig::default(),
 		},
 		chain.clone(),
 		verifier_cache.clone(),
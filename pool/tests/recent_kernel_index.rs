@@ -0,0 +1,150 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::chain::types::Options;
+use self::core::core::hash::Hashed;
+use self::core::core::verifier_cache::LruVerifierCache;
+use self::core::core::Block;
+use self::core::global;
+use self::keychain::{ExtKeychain, Keychain};
+use self::pool::RecentKernelIndex;
+use self::util::secp::pedersen::Commitment;
+use self::util::RwLock;
+use crate::common::*;
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_pool as pool;
+use grin_util as util;
+use std::sync::Arc;
+
+fn excess(i: u8) -> Commitment {
+	Commitment::from_vec(vec![i; 33])
+}
+
+#[test]
+fn test_recent_kernel_index() {
+	let mut index = RecentKernelIndex::new(3);
+	assert!(index.is_empty());
+
+	index.add_kernels(10, vec![excess(1), excess(2)]);
+	index.add_kernels(11, vec![excess(3)]);
+	index.add_kernels(12, vec![]);
+	assert_eq!(index.len(), 3);
+	assert_eq!(index.kernel_height(&excess(1)), Some(10));
+	assert_eq!(index.kernel_height(&excess(3)), Some(11));
+
+	// Block 10 is out of the window now
+	index.add_kernels(13, vec![excess(4)]);
+	assert!(!index.contains(&excess(1)));
+	assert!(!index.contains(&excess(2)));
+	assert!(index.contains(&excess(3)));
+	assert!(index.contains(&excess(4)));
+
+	// Reorg, blocks 12 and 13 are replaced by the fork
+	index.add_kernels(12, vec![excess(5)]);
+	assert!(!index.contains(&excess(4)));
+	assert_eq!(index.kernel_height(&excess(5)), Some(12));
+	assert_eq!(index.kernel_height(&excess(3)), Some(11));
+
+	// Reorg two blocks deep, fork point is at 11
+	index.rewind(11);
+	assert!(!index.contains(&excess(5)));
+	assert!(index.contains(&excess(3)));
+	index.add_kernels(12, vec![excess(6)]);
+	index.add_kernels(13, vec![excess(7)]);
+	assert_eq!(index.len(), 3);
+
+	// Disabled index stays empty
+	let mut index = RecentKernelIndex::new(0);
+	index.add_kernels(10, vec![excess(1)]);
+	assert!(index.is_empty());
+}
+
+fn kernels(block: &Block) -> Vec<Commitment> {
+	block.kernels().iter().map(|k| k.excess()).collect()
+}
+
+// Blocks of the new branch below the reorg block arrive as forks, they are indexed
+// from the chain and the abandoned blocks are dropped.
+#[test]
+fn test_recent_kernel_index_reorg() {
+	util::init_test_logger();
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let keychain: ExtKeychain = Keychain::from_random_seed(false).unwrap();
+	let fork_keychain: ExtKeychain = Keychain::from_random_seed(false).unwrap();
+
+	let db_root = "target/.recent_kernel_index_reorg";
+	clean_output_dir(db_root.into());
+
+	let genesis = genesis_block(&keychain);
+	let chain = Arc::new(init_chain(db_root, genesis));
+	let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
+	let mut pool = init_transaction_pool(
+		Arc::new(ChainAdapter {
+			chain: chain.clone(),
+		}),
+		verifier_cache,
+	);
+
+	// Main branch 1..3 is indexed as the blocks are added
+	let mut abandoned = vec![];
+	for _ in 0..3 {
+		add_block(&chain, &[], &keychain);
+		let block = chain
+			.get_block(&chain.head().unwrap().last_block_h)
+			.unwrap();
+		pool.reconcile_block(&block).unwrap();
+		abandoned.push(block);
+	}
+	let kept = abandoned.remove(0);
+
+	// Fork at height 1 with blocks 2, 3 and 4, the last one is the reorg
+	let mut prev = kept.header.clone();
+	let mut branch = vec![];
+	for _ in 0..3 {
+		let block = prepare_block(&chain, &prev, &[], &fork_keychain);
+		chain.process_block(block.clone(), Options::NONE).unwrap();
+		prev = block.header.clone();
+		branch.push(block);
+	}
+	assert_eq!(chain.head().unwrap().last_block_h, prev.hash());
+
+	let reorg_block = branch.last().unwrap();
+	pool.reindex_kernels(kept.header.height, reorg_block)
+		.unwrap();
+	pool.reconcile_block(reorg_block).unwrap();
+
+	for excess in kernels(&kept) {
+		assert_eq!(pool.kernel_index.kernel_height(&excess), Some(1));
+	}
+	for block in &abandoned {
+		for excess in kernels(block) {
+			assert!(!pool.is_recent_kernel(&excess));
+		}
+	}
+	for block in &branch {
+		for excess in kernels(block) {
+			assert_eq!(
+				pool.kernel_index.kernel_height(&excess),
+				Some(block.header.height)
+			);
+		}
+	}
+
+	// Clean up
+	clean_output_dir(db_root.into());
+}
@@ -0,0 +1,105 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::core::core::KernelFeatures;
+use self::core::global;
+use self::keychain::{ExtKeychain, Keychain};
+use self::pool::types::PoolError;
+use self::pool::{PolicyStage, RelayPolicy, RelayPolicyConfig};
+use crate::common::*;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_pool as pool;
+use grin_util as util;
+
+fn rejected_by(policy: &RelayPolicy, rule: &str) -> (u64, u64) {
+	let stats = policy.stats();
+	let rule = stats.rules.iter().find(|r| r.rule == rule).unwrap();
+	(rule.pool_rejected, rule.relay_rejected)
+}
+
+#[test]
+fn test_relay_policy() {
+	util::init_test_logger();
+	global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+	let keychain: ExtKeychain = Keychain::from_random_seed(false).unwrap();
+
+	let tx = test_transaction(&keychain, vec![100, 200], vec![90, 80, 70]);
+	let fee_rate = tx.fee() / tx.tx_weight();
+
+	// Nothing is set, everything passes
+	let policy = RelayPolicy::new(RelayPolicyConfig::default());
+	assert_eq!(policy.check(&tx, PolicyStage::Pool), Ok(()));
+
+	let policy = RelayPolicy::new(RelayPolicyConfig {
+		min_fee_rate: Some(fee_rate),
+		max_tx_weight: Some(tx.tx_weight_as_block()),
+		max_outputs: Some(3),
+		reject_kernel_features: vec!["HeightLocked".to_string()],
+	});
+	assert_eq!(policy.check(&tx, PolicyStage::Pool), Ok(()));
+
+	let policy = RelayPolicy::new(RelayPolicyConfig {
+		min_fee_rate: Some(fee_rate + 1),
+		..Default::default()
+	});
+	match policy.check(&tx, PolicyStage::Pool) {
+		Err(PoolError::PolicyViolation(_)) => {}
+		res => panic!("unexpected {:?}", res),
+	}
+	assert_eq!(rejected_by(&policy, "min_fee_rate"), (1, 0));
+
+	let policy = RelayPolicy::new(RelayPolicyConfig {
+		max_tx_weight: Some(tx.tx_weight_as_block() - 1),
+		..Default::default()
+	});
+	assert!(policy.check(&tx, PolicyStage::Relay).is_err());
+	assert_eq!(rejected_by(&policy, "max_tx_weight"), (0, 1));
+
+	let policy = RelayPolicy::new(RelayPolicyConfig {
+		max_outputs: Some(2),
+		..Default::default()
+	});
+	assert!(policy.check(&tx, PolicyStage::Pool).is_err());
+	assert!(policy
+		.check(
+			&test_transaction(&keychain, vec![100], vec![90]),
+			PolicyStage::Pool
+		)
+		.is_ok());
+	assert_eq!(rejected_by(&policy, "max_outputs"), (1, 0));
+
+	let policy = RelayPolicy::new(RelayPolicyConfig {
+		reject_kernel_features: vec!["heightlocked".to_string()],
+		..Default::default()
+	});
+	let height_locked = test_transaction_with_kernel_features(
+		&keychain,
+		vec![100],
+		vec![90],
+		KernelFeatures::HeightLocked {
+			fee: 10,
+			lock_height: 99,
+		},
+	);
+	assert!(policy.check(&height_locked, PolicyStage::Pool).is_err());
+	assert!(policy.check(&tx, PolicyStage::Pool).is_ok());
+	assert_eq!(rejected_by(&policy, "reject_kernel_features"), (1, 0));
+
+	let stats = policy.stats();
+	assert_eq!(stats.evaluated, 2);
+	assert_eq!(stats.rejected, 1);
+}
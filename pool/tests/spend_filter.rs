@@ -0,0 +1,47 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use self::pool::spend_filter::SpendFilter;
+use self::util::secp::pedersen::Commitment;
+use grin_pool as pool;
+use grin_util as util;
+
+fn commit(i: u32) -> Commitment {
+	let mut bytes = vec![9u8; 33];
+	bytes[1..5].copy_from_slice(&i.to_be_bytes());
+	Commitment::from_vec(bytes)
+}
+
+#[test]
+fn test_spend_filter() {
+	let mut filter = SpendFilter::new(1 << 16);
+	assert!(filter.is_empty());
+	for i in 0..1000 {
+		filter.insert(&commit(i));
+	}
+	assert_eq!(filter.len(), 1000);
+
+	// No false negatives
+	assert!((0..1000).all(|i| filter.may_contain(&commit(i))));
+
+	// Few false positives, 64k bits for 1000 items is well below 1%
+	let false_positives = (1000..11_000)
+		.filter(|i| filter.may_contain(&commit(*i)))
+		.count();
+	assert!(false_positives < 100, "{} false positives", false_positives);
+
+	filter.clear();
+	assert!(filter.is_empty());
+	assert!(!filter.may_contain(&commit(1)));
+}
@@ -18,7 +18,7 @@ use self::core::core::verifier_cache::LruVerifierCache;
 use self::core::core::{transaction, Weighting};
 use self::core::global;
 use self::keychain::{ExtKeychain, Keychain};
-use self::pool::TxSource;
+use self::pool::{PoolError, TxSource};
 use self::util::RwLock;
 use crate::common::*;
 use grin_core as core;
@@ -104,11 +104,13 @@ fn test_the_transaction_pool() {
 
 	// Test adding a duplicate tx with the same input and outputs.
 	// Note: not the *same* tx, just same underlying inputs/outputs.
+	// Inputs are already spent by tx1, it is rejected before the validation.
 	{
 		let tx1a = test_transaction(&keychain, vec![500, 600], vec![499, 599]);
-		assert!(pool
-			.add_to_pool(test_source(), tx1a, false, &header)
-			.is_err());
+		assert_eq!(
+			pool.add_to_pool(test_source(), tx1a, false, &header),
+			Err(PoolError::DoubleSpend)
+		);
 	}
 
 	// Test adding a tx attempting to spend a non-existent output.
@@ -219,6 +221,18 @@ fn test_the_transaction_pool() {
 			.is_err());
 	}
 
+	// Operator can fluff all the stem txs right away.
+	{
+		let tx = test_transaction(&keychain, vec![596], vec![595]);
+		pool.add_to_pool(test_source(), tx, true, &header).unwrap();
+		assert_eq!(pool.stempool.size(), 1);
+
+		assert_eq!(pool.fluff_stempool().unwrap(), 1);
+		assert!(pool.stempool.is_empty());
+		assert_eq!(pool.total_size(), 7);
+		assert_eq!(pool.fluff_stempool().unwrap(), 0);
+	}
+
 	// Cleanup db directory
 	clean_output_dir(db_root.into());
 }
@@ -15,7 +15,7 @@
 //! Adapters connecting new block, new transaction, and accepted transaction
 //! events to consumers of those events.
 
-use crate::util::RwLock;
+use crate::util::{clock, RwLock};
 use std::fs::File;
 use std::path::PathBuf;
 use std::sync::{Arc, Weak};
@@ -28,7 +28,7 @@ use crate::chain::{
 use std::collections::HashMap;
 use std::sync::Mutex;
 
-use crate::common::hooks::{ChainEvents, NetEvents};
+use crate::common::hooks::{ChainEvents, NetEvents, PoolEvents};
 use crate::common::types::{ChainValidationMode, DandelionEpoch, ServerConfig};
 use crate::core::core::hash::{Hash, Hashed};
 use crate::core::core::transaction::Transaction;
@@ -86,6 +86,31 @@ impl EventCache {
 	}
 }
 
+/// Automated response of the node to the chain error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChainErrorAction {
+	/// The data will be requested again, no need to punish anybody
+	Retry,
+	/// The peer supplied invalid data and should be banned
+	BanPeer,
+	/// Local chain data needs a repair
+	Repair,
+	/// Nothing to do
+	Ignore,
+}
+
+impl ChainErrorAction {
+	/// Response to the error of the class
+	pub fn from_class(class: chain::ErrorClass) -> ChainErrorAction {
+		match class {
+			chain::ErrorClass::Transient => ChainErrorAction::Retry,
+			chain::ErrorClass::BadData => ChainErrorAction::BanPeer,
+			chain::ErrorClass::Corruption => ChainErrorAction::Repair,
+			chain::ErrorClass::Rejected | chain::ErrorClass::Internal => ChainErrorAction::Ignore,
+		}
+	}
+}
+
 /// Implementation of the NetAdapter for the . Gets notified when new
 /// blocks and transactions are received and forwards to the chain and pool
 /// implementations.
@@ -128,7 +153,25 @@ where
 	}
 
 	fn get_transaction(&self, kernel_hash: Hash) -> Option<core::Transaction> {
-		self.tx_pool.read().retrieve_tx_by_kernel_hash(kernel_hash)
+		let entry = self
+			.tx_pool
+			.read()
+			.retrieve_entry_by_kernel_hash(kernel_hash)?;
+		// In privacy mode our txs are not served for a while, peers should not be able
+		// to find out that tx was originated by this node.
+		let dandelion_config = &self.config.dandelion_config;
+		if dandelion_config.own_tx_privacy && entry.src.is_pushed() {
+			let private_until =
+				entry.tx_at + Duration::minutes(dandelion_config.own_tx_private_mins as i64);
+			if clock::now() < private_until {
+				debug!(
+					"get_transaction: not serving our private tx with kernel {}",
+					kernel_hash
+				);
+				return None;
+			}
+		}
+		Some(entry.tx)
 	}
 
 	fn tx_kernel_received(
@@ -271,7 +314,9 @@ where
 				.process_block_header(&cb.header, chain::Options::NONE)
 			{
 				debug!("Invalid compact block header {}: {:?}", cb_hash, e.kind());
-				return Ok(!e.is_bad_data());
+				return Ok(
+					self.on_chain_error("compact_block_received", &e) != ChainErrorAction::BanPeer
+				);
 			}
 
 			let (txs, missing_short_ids) = {
@@ -378,7 +423,7 @@ where
 				bh.hash(),
 				e.kind()
 			);
-			if e.is_bad_data() {
+			if self.on_chain_error("header_received", &e) == ChainErrorAction::BanPeer {
 				return Ok(false);
 			} else {
 				// we got an error when trying to process the block header
@@ -546,7 +591,7 @@ where
 			}
 			Err(e) => {
 				debug!("Block headers refused by chain: {:?}", e);
-				if e.is_bad_data() {
+				if self.on_chain_error("headers_received", &e) == ChainErrorAction::BanPeer {
 					Ok(false)
 				} else {
 					Err(e)
@@ -642,7 +687,7 @@ where
 					.update_txhashset_download(TxHashsetDownloadStats {
 						start_time,
 						prev_update_time: prev.update_time,
-						update_time: Utc::now(),
+						update_time: clock::now(),
 						prev_downloaded_size: prev.downloaded_size,
 						downloaded_size,
 						total_size,
@@ -688,6 +733,7 @@ where
 			Err(e) => {
 				self.chain().clean_txhashset_sandbox();
 				error!("Failed to save txhashset archive: {}", e);
+				self.on_chain_error("txhashset_write", &e);
 				self.sync_state.set_sync_error(e);
 				Ok(false)
 			}
@@ -754,6 +800,38 @@ where
 			.expect("Failed to upgrade weak ref to our chain.")
 	}
 
+	// Respond to the chain error by its class. The caller bans the peer for the bad data,
+	// the other errors are logged with the recovery hint and reported in the status.
+	fn on_chain_error(&self, context: &str, e: &chain::Error) -> ChainErrorAction {
+		let class = e.class();
+		let action = ChainErrorAction::from_class(class);
+		match class {
+			chain::ErrorClass::BadData | chain::ErrorClass::Rejected => {
+				debug!("{}: {}, {:?}", context, e.kind(), action);
+				return action;
+			}
+			chain::ErrorClass::Transient => {
+				warn!("{}: {}. {}", context, e.kind(), class.recovery_hint())
+			}
+			chain::ErrorClass::Corruption | chain::ErrorClass::Internal => {
+				error!("{}: {}. {}", context, e.kind(), class.recovery_hint())
+			}
+		}
+		self.sync_state.set_chain_error(e);
+
+		if action == ChainErrorAction::Repair {
+			match self.chain().repair_heads() {
+				Ok(true) => warn!("{}: chain heads are repaired", context),
+				Ok(false) => warn!(
+					"{}: chain heads are consistent, restart the node to run the full checks",
+					context
+				),
+				Err(e) => error!("{}: unable to repair the chain heads, {}", context, e),
+			}
+		}
+		action
+	}
+
 	// Find the first locator hash that refers to a known header on our main chain.
 	fn find_common_header(&self, locator: &[Hash]) -> Option<BlockHeader> {
 		let header_pmmr = self.chain().header_pmmr();
@@ -825,6 +903,7 @@ where
 							bhash,
 							e.kind()
 						);
+						self.on_chain_error("process_block", &e);
 						Ok(true)
 					}
 				}
@@ -932,6 +1011,21 @@ where
 					peer_info.addr
 				),
 				Some(peer) => {
+					// Headers only relay peers don't serve the bodies, ask another peer
+					let peer = if peer.info.serves_bodies() {
+						peer
+					} else {
+						match self.peers().most_work_body_peer() {
+							Some(peer) => peer,
+							None => {
+								debug!(
+									"send_block_request_to_peer: {:?} is headers only, no other peer for {}",
+									peer_info.addr, h
+								);
+								return;
+							}
+						}
+					};
 					if let Err(e) = f(&peer, h) {
 						error!("send_block_request_to_peer: failed: {:?}", e)
 					}
@@ -972,7 +1066,8 @@ where
 			for hook in &self.hooks {
 				hook.on_block_accepted(b, status);
 			}
-			// If we mined the block then we want to broadcast the compact block.
+			// If we mined the block then we want to broadcast the compact block, peers that
+			// support it get the header first and request the body only if they need it.
 			// If we received the block from another node then broadcast "header first"
 			// to minimize network traffic.
 			if opts.contains(Options::MINE) {
@@ -992,10 +1087,17 @@ where
 		if status.is_next() || status.is_reorg() {
 			let mut tx_pool = self.tx_pool.write();
 
+			// Blocks of the new branch below this one arrived as forks and are not indexed yet
+			if let BlockStatus::Reorg { fork_point, .. } = status {
+				if let Err(e) = tx_pool.reindex_kernels(fork_point.height, b) {
+					warn!("Unable to re-index the kernels after the reorg, {}", e);
+				}
+			}
+
 			let _ = tx_pool.reconcile_block(b);
 
 			// First "age out" any old txs in the reorg_cache.
-			let cutoff = Utc::now() - Duration::minutes(tx_pool.config.reorg_cache_timeout);
+			let cutoff = clock::now() - Duration::minutes(tx_pool.config.reorg_cache_timeout);
 			tx_pool.truncate_reorg_cache(cutoff);
 		}
 
@@ -1003,6 +1105,12 @@ where
 			let _ = self.tx_pool.write().reconcile_reorg_cache(&b.header);
 		}
 	}
+
+	fn deep_reorg_rejected(&self, alert: &chain::DeepReorgAlert) {
+		for hook in &self.hooks {
+			hook.on_deep_reorg_rejected(alert);
+		}
+	}
 }
 
 impl<B, P, V> ChainToPoolAndNetAdapter<B, P, V>
@@ -1042,6 +1150,21 @@ where
 pub struct PoolToNetAdapter {
 	peers: OneTime<Weak<p2p::Peers>>,
 	dandelion_epoch: Arc<RwLock<DandelionEpoch>>,
+	// Our txs waiting for the first relay (privacy mode)
+	delayed_txs: RwLock<Vec<DelayedTx>>,
+	// Received stem txs waiting to be aggregated and relayed, with the time they were received
+	stem_batch: RwLock<Vec<(i64, pool::PoolEntry)>>,
+	stem_aggregation_stats: RwLock<pool::StemAggregationStats>,
+	// Relay rules of the pool, applied to the aggregated stem txs
+	relay_policy: OneTime<Arc<pool::RelayPolicy>>,
+	hooks: Vec<Box<dyn PoolEvents + Send + Sync>>,
+}
+
+/// Our tx with a delayed first relay, always stemmed to the Tor peer
+struct DelayedTx {
+	// Timestamp when tx should be relayed
+	relay_at: i64,
+	entry: pool::PoolEntry,
 }
 
 /// Adapter between the Dandelion monitor and the current Dandelion "epoch".
@@ -1054,6 +1177,13 @@ pub trait DandelionAdapter: Send + Sync {
 
 	/// Transition to the next Dandelion epoch (new stem/fluff state, select new relay peer).
 	fn next_epoch(&self);
+
+	/// Relay our txs with expired delay (privacy mode).
+	fn relay_delayed_txs(&self);
+
+	/// Relay the aggregated batch of the received stem txs if it is full or its
+	/// aggregation window is over.
+	fn relay_stem_batch(&self);
 }
 
 impl DandelionAdapter for PoolToNetAdapter {
@@ -1068,14 +1198,82 @@ impl DandelionAdapter for PoolToNetAdapter {
 	fn next_epoch(&self) {
 		self.dandelion_epoch.write().next_epoch(&self.peers());
 	}
+
+	fn relay_delayed_txs(&self) {
+		let now = clock::now().timestamp();
+		let ready: Vec<DelayedTx> = {
+			let mut delayed_txs = self.delayed_txs.write();
+			if delayed_txs.is_empty() {
+				return;
+			}
+			let (ready, waiting) = delayed_txs.drain(..).partition(|x| x.relay_at <= now);
+			*delayed_txs = waiting;
+			ready
+		};
+
+		for tx in ready {
+			// If stemming failed, tx stays in the stempool and will be fluffed
+			// when the embargo timer expires.
+			let peer = self.dandelion_epoch.read().tor_relay_peer(&self.peers());
+			match peer {
+				Some(peer) => match peer.send_stem_transaction(&tx.entry.tx) {
+					Ok(_) => info!("Privacy mode, relayed our tx to the next Tor peer."),
+					Err(e) => warn!("Privacy mode, stemming our tx failed. {:?}", e),
+				},
+				None => warn!("Privacy mode, no Tor relay peer for our tx."),
+			}
+		}
+	}
+
+	fn relay_stem_batch(&self) {
+		let (window, max_txs) = {
+			let epoch = self.dandelion_epoch.read();
+			(
+				epoch.stem_aggregation_secs(),
+				epoch.stem_aggregation_max_txs(),
+			)
+		};
+		let batch = {
+			let mut stem_batch = self.stem_batch.write();
+			let due = match stem_batch.first() {
+				Some((received, _)) => {
+					*received + window <= clock::now().timestamp() || stem_batch.len() >= max_txs
+				}
+				None => false,
+			};
+			if !due {
+				return;
+			}
+			stem_batch
+				.drain(..)
+				.map(|(_, entry)| entry)
+				.collect::<Vec<_>>()
+		};
+		self.relay_aggregated_stem_txs(batch);
+	}
 }
 
 impl pool::PoolAdapter for PoolToNetAdapter {
 	fn tx_accepted(&self, entry: &pool::PoolEntry) {
+		for hook in &self.hooks {
+			hook.on_tx_added(entry, false);
+		}
+		// In privacy mode our txs are never broadcasted, even the fluffed ones (or the stem
+		// fallback) go to the Tor stem peer first.
+		if entry.src.is_pushed() && self.dandelion_epoch.read().own_tx_privacy() {
+			self.delay_tx(entry);
+			return;
+		}
 		self.peers().broadcast_transaction(&entry.tx);
 	}
 
 	fn stem_tx_accepted(&self, entry: &pool::PoolEntry) -> Result<(), pool::PoolError> {
+		// In privacy mode our txs are always stemmed, with a delay and to the Tor peers only.
+		if entry.src.is_pushed() && self.dandelion_epoch.read().own_tx_privacy() {
+			self.delay_tx(entry);
+			return Ok(());
+		}
+
 		// Take write lock on the current epoch.
 		// We need to be able to update the current relay peer if not currently connected.
 		let mut epoch = self.dandelion_epoch.write();
@@ -1085,6 +1283,25 @@ impl pool::PoolAdapter for PoolToNetAdapter {
 		// If "fluff" epoch then nothing to do right now (fluff via Dandelion monitor).
 		// If node is configured to always stem our (pushed via api) txs then do so.
 		if epoch.is_stem() || (entry.src.is_pushed() && epoch.always_stem_our_txs()) {
+			// Stem txs from the peers are aggregated with the other pending ones first
+			if epoch.is_stem() && !entry.src.is_pushed() && epoch.stem_aggregation_secs() > 0 {
+				let max_txs = epoch.stem_aggregation_max_txs();
+				drop(epoch);
+				let batch_len = {
+					let mut stem_batch = self.stem_batch.write();
+					stem_batch.push((clock::now().timestamp(), entry.clone()));
+					stem_batch.len()
+				};
+				debug!(
+					"Stemming this epoch, tx {} is queued for the aggregation, {} txs queued.",
+					entry.tx.hash(),
+					batch_len
+				);
+				if batch_len >= max_txs {
+					self.relay_stem_batch();
+				}
+				return Ok(());
+			}
 			if let Some(peer) = epoch.relay_peer(&self.peers()) {
 				match peer.send_stem_transaction(&entry.tx) {
 					Ok(_) => {
@@ -1105,17 +1322,113 @@ impl pool::PoolAdapter for PoolToNetAdapter {
 			Ok(())
 		}
 	}
+
+	fn tx_restored(&self, entry: &pool::PoolEntry) {
+		for hook in &self.hooks {
+			hook.on_tx_added(entry, true);
+		}
+	}
+
+	fn tx_removed(&self, entry: &pool::PoolEntry, reason: pool::TxRemovalReason) {
+		for hook in &self.hooks {
+			hook.on_tx_removed(entry, reason);
+		}
+	}
+
+	fn dandelion_epoch(&self) -> Option<pool::DandelionEpochInfo> {
+		Some(self.dandelion_epoch.read().info())
+	}
 }
 
 impl PoolToNetAdapter {
 	/// Create a new pool to net adapter
-	pub fn new(config: pool::DandelionConfig) -> PoolToNetAdapter {
+	pub fn new(
+		config: pool::DandelionConfig,
+		hooks: Vec<Box<dyn PoolEvents + Send + Sync>>,
+	) -> PoolToNetAdapter {
 		PoolToNetAdapter {
 			peers: OneTime::new(),
 			dandelion_epoch: Arc::new(RwLock::new(DandelionEpoch::new(config))),
+			delayed_txs: RwLock::new(vec![]),
+			stem_batch: RwLock::new(vec![]),
+			stem_aggregation_stats: RwLock::new(pool::StemAggregationStats::default()),
+			relay_policy: OneTime::new(),
+			hooks,
 		}
 	}
 
+	/// Relay rules shared with the pool
+	pub fn set_relay_policy(&self, relay_policy: Arc<pool::RelayPolicy>) {
+		self.relay_policy.init(relay_policy);
+	}
+
+	/// Statistics of the received stem txs aggregation
+	pub fn stem_aggregation_stats(&self) -> pool::StemAggregationStats {
+		self.stem_aggregation_stats.read().clone()
+	}
+
+	// Aggregate the received stem txs in random order and relay them as a single tx.
+	// If stemming fails, the txs stay in the stempool and will be fluffed when the
+	// embargo timer expires.
+	fn relay_aggregated_stem_txs(&self, mut entries: Vec<pool::PoolEntry>) {
+		if entries.is_empty() {
+			return;
+		}
+		entries.shuffle(&mut thread_rng());
+		let num_txs = entries.len();
+		let txs: Vec<Transaction> = entries.into_iter().map(|e| e.tx).collect();
+		let txs = if txs.len() > 1 {
+			match core::transaction::aggregate(&txs) {
+				Ok(agg_tx) => match self.check_relay_policy(&agg_tx) {
+					Ok(_) => vec![agg_tx],
+					Err(e) => {
+						debug!("Aggregated stem tx is relayed as {} txs, {}", txs.len(), e);
+						txs
+					}
+				},
+				Err(e) => {
+					warn!("Unable to aggregate {} stem txs, {:?}", txs.len(), e);
+					txs
+				}
+			}
+		} else {
+			txs
+		};
+
+		let peer = self.dandelion_epoch.write().relay_peer(&self.peers());
+		let peer = match peer {
+			Some(peer) => peer,
+			None => {
+				warn!("No relay peer for the aggregated stem txs, they will be fluffed later.");
+				return;
+			}
+		};
+		for tx in &txs {
+			if let Err(e) = peer.send_stem_transaction(tx) {
+				warn!("Stemming the aggregated tx failed. {:?}", e);
+				return;
+			}
+		}
+		let mut stats = self.stem_aggregation_stats.write();
+		stats.batches += txs.len() as u64;
+		stats.txs += num_txs as u64;
+		info!(
+			"Stemming this epoch, relayed {} stem txs as {} tx(s) to next peer. Aggregation factor {:.2}",
+			num_txs,
+			txs.len(),
+			stats.aggregation_factor()
+		);
+	}
+
+	fn check_relay_policy(&self, tx: &Transaction) -> Result<(), pool::PoolError> {
+		if !self.relay_policy.is_init() {
+			return Ok(());
+		}
+		self.relay_policy
+			.borrow()
+			.check(tx, pool::PolicyStage::Relay)
+	}
+
 	/// Setup the p2p server on the adapter
 	pub fn init(&self, peers: Arc<p2p::Peers>) {
 		self.peers.init(Arc::downgrade(&peers));
@@ -1127,6 +1440,20 @@ impl PoolToNetAdapter {
 			.upgrade()
 			.expect("Failed to upgrade weak ref to our peers.")
 	}
+
+	// Queue our tx for the relay with a random delay (privacy mode)
+	fn delay_tx(&self, entry: &pool::PoolEntry) {
+		let delay = self.dandelion_epoch.read().own_tx_relay_delay();
+		info!(
+			"Privacy mode, our tx {} will be relayed in {} secs.",
+			entry.tx.hash(),
+			delay
+		);
+		self.delayed_txs.write().push(DelayedTx {
+			relay_at: clock::now().timestamp() + delay,
+			entry: entry.clone(),
+		});
+	}
 }
 
 /// Implements the view of the  required by the TransactionPool to
@@ -1171,6 +1498,12 @@ impl pool::BlockChain for PoolToChainAdapter {
 			.map_err(|e| pool::PoolError::Other(format!("failed to get block_header, {}", e)))
 	}
 
+	fn get_block(&self, hash: &Hash) -> Result<core::Block, pool::PoolError> {
+		self.chain()
+			.get_block(hash)
+			.map_err(|e| pool::PoolError::Other(format!("failed to get block, {}", e)))
+	}
+
 	fn get_block_sums(&self, hash: &Hash) -> Result<BlockSums, pool::PoolError> {
 		self.chain()
 			.get_block_sums(hash)
@@ -1240,4 +1573,21 @@ mod test {
 		assert_eq!(cache.contains(&hash2_2, true), true);
 		assert_eq!(cache.contains(&hash3, true), false);
 	}
+
+	// Our fluffed tx in privacy mode is stemmed to the Tor peer, not broadcasted. Peers
+	// are not initialized, any broadcast would panic.
+	#[test]
+	fn test_privacy_mode_fluff() {
+		let adapter = PoolToNetAdapter::new(
+			pool::DandelionConfig {
+				own_tx_privacy: true,
+				..pool::DandelionConfig::default()
+			},
+			vec![],
+		);
+		let entry = pool::PoolEntry::new(Transaction::empty(), pool::TxSource::PushApi);
+		adapter.tx_accepted(&entry);
+		adapter.stem_tx_accepted(&entry).unwrap();
+		assert_eq!(adapter.delayed_txs.read().len(), 2);
+	}
 }
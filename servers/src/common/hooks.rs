@@ -16,26 +16,79 @@
 //! callback simply implement the coresponding trait and add it to the init function
 
 extern crate hyper;
-extern crate hyper_rustls;
 extern crate tokio;
 
-use crate::chain::BlockStatus;
-use crate::common::types::{ServerConfig, WebHooksConfig};
+use crate::api::{self, KernelWatchHub, OutboundConnector, TxPoolEvent, TxPoolEventsHub};
+use crate::chain::{BlockStatus, DeepReorgAlert, Tip};
+use crate::common::types::{
+	CanaryNodeState, CanaryStatus, Error, ServerConfig, TipLagAlert, TipLagStatus,
+	WebHookDestination, WebHooksConfig,
+};
 use crate::core::core;
 use crate::core::core::hash::Hashed;
 use crate::p2p::types::PeerAddr;
+use crate::pool;
+use chrono::Utc;
 use futures::TryFutureExt;
-use grin_util::ToHex;
-use hyper::client::HttpConnector;
+use grin_util::{Mutex, ToHex};
 use hyper::header::HeaderValue;
 use hyper::Client;
 use hyper::{Body, Method, Request};
-use hyper_rustls::HttpsConnector;
+use ring::hmac;
 use serde::Serialize;
-use serde_json::{json, to_string};
-use std::time::Duration;
+use serde_json::{json, to_string, Value};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 use tokio::runtime::{Builder, Runtime};
 
+// Web hook event names, used by the destinations event filters
+const TX_RECEIVED_EVENT: &str = "tx_received";
+const HEADER_RECEIVED_EVENT: &str = "header_received";
+const BLOCK_RECEIVED_EVENT: &str = "block_received";
+const BLOCK_ACCEPTED_EVENT: &str = "block_accepted";
+const TIP_LAG_ALERT_EVENT: &str = "tip_lag_alert";
+const DEEP_REORG_REJECTED_EVENT: &str = "deep_reorg_rejected";
+const BLOCK_BATCH_EVENT: &str = "block_batch";
+const REORG_SUMMARY_EVENT: &str = "reorg_summary";
+const KERNEL_WATCH_EVENT: &str = "kernel_watch";
+const CONSENSUS_CANARY_EVENT: &str = "consensus_canary";
+
+const WEBHOOK_EVENTS: [&str; 10] = [
+	TX_RECEIVED_EVENT,
+	HEADER_RECEIVED_EVENT,
+	BLOCK_RECEIVED_EVENT,
+	BLOCK_ACCEPTED_EVENT,
+	TIP_LAG_ALERT_EVENT,
+	DEEP_REORG_REJECTED_EVENT,
+	BLOCK_BATCH_EVENT,
+	REORG_SUMMARY_EVENT,
+	KERNEL_WATCH_EVENT,
+	CONSENSUS_CANARY_EVENT,
+];
+
+/// Header with the hex HMAC-SHA256 signature of the payload
+const SIGNATURE_HEADER: &str = "x-mwc-signature";
+/// Header with the event name
+const EVENT_HEADER: &str = "x-mwc-event";
+
+/// Check the web hook destinations urls and events, so the misconfigured node
+/// doesn't start instead of failing on the first event
+pub fn validate_webhook_config(config: &WebHooksConfig) -> Result<(), Error> {
+	for destination in &config.destinations {
+		Destination::from_config(destination).map_err(Error::Configuration)?;
+	}
+	Ok(())
+}
+
+// Check if any of the destinations accepts any of the events
+fn has_destination(config: &WebHooksConfig, events: &[&str]) -> bool {
+	config
+		.destinations
+		.iter()
+		.any(|d| d.events.is_empty() || d.events.iter().any(|e| events.contains(&e.as_str())))
+}
+
 /// Returns the list of event hooks that will be initialized for network events
 pub fn init_net_hooks(config: &ServerConfig) -> Vec<Box<dyn NetEvents + Send + Sync>> {
 	let mut list: Vec<Box<dyn NetEvents + Send + Sync>> = Vec::new();
@@ -43,22 +96,82 @@ pub fn init_net_hooks(config: &ServerConfig) -> Vec<Box<dyn NetEvents + Send + S
 	if config.webhook_config.block_received_url.is_some()
 		|| config.webhook_config.tx_received_url.is_some()
 		|| config.webhook_config.header_received_url.is_some()
-	{
+		|| has_destination(
+			&config.webhook_config,
+			&[
+				TX_RECEIVED_EVENT,
+				HEADER_RECEIVED_EVENT,
+				BLOCK_RECEIVED_EVENT,
+			],
+		) {
 		list.push(Box::new(WebHook::from_config(&config.webhook_config)));
 	}
 	list
 }
 
 /// Returns the list of event hooks that will be initialized for chain events
-pub fn init_chain_hooks(config: &ServerConfig) -> Vec<Box<dyn ChainEvents + Send + Sync>> {
+pub fn init_chain_hooks(
+	config: &ServerConfig,
+	kernel_watch: Arc<KernelWatchHub>,
+) -> Vec<Box<dyn ChainEvents + Send + Sync>> {
 	let mut list: Vec<Box<dyn ChainEvents + Send + Sync>> = Vec::new();
 	list.push(Box::new(EventLogger));
-	if config.webhook_config.block_accepted_url.is_some() {
+	list.push(Box::new(ForkActivityRecorder));
+	let kernel_watch_webhook = if has_destination(&config.webhook_config, &[KERNEL_WATCH_EVENT]) {
+		Some(WebHook::from_config(&config.webhook_config))
+	} else {
+		None
+	};
+	list.push(Box::new(KernelWatchPublisher {
+		hub: kernel_watch,
+		webhook: kernel_watch_webhook,
+	}));
+	if config.webhook_config.block_accepted_url.is_some()
+		|| has_destination(
+			&config.webhook_config,
+			&[
+				BLOCK_ACCEPTED_EVENT,
+				DEEP_REORG_REJECTED_EVENT,
+				BLOCK_BATCH_EVENT,
+				REORG_SUMMARY_EVENT,
+			],
+		) {
+		let webhook = WebHook::from_config(&config.webhook_config);
+		if config.webhook_config.batch_window_ms > 0 {
+			list.push(Box::new(BatchingWebHook::new(
+				webhook,
+				Duration::from_millis(config.webhook_config.batch_window_ms),
+			)));
+		} else {
+			list.push(Box::new(webhook));
+		}
+	}
+	list
+}
+
+/// Returns the list of event hooks that will be initialized for node alerts
+pub fn init_alert_hooks(config: &ServerConfig) -> Vec<Box<dyn AlertEvents + Send + Sync>> {
+	let mut list: Vec<Box<dyn AlertEvents + Send + Sync>> = Vec::new();
+	list.push(Box::new(EventLogger));
+	if config.webhook_config.tip_lag_alert_url.is_some()
+		|| has_destination(
+			&config.webhook_config,
+			&[TIP_LAG_ALERT_EVENT, CONSENSUS_CANARY_EVENT],
+		) {
 		list.push(Box::new(WebHook::from_config(&config.webhook_config)));
 	}
 	list
 }
 
+/// Returns the list of event hooks that will be initialized for transaction pool events
+pub fn init_pool_hooks(
+	pool_events: Arc<TxPoolEventsHub>,
+) -> Vec<Box<dyn PoolEvents + Send + Sync>> {
+	let mut list: Vec<Box<dyn PoolEvents + Send + Sync>> = Vec::new();
+	list.push(Box::new(TxPoolEventsPublisher { hub: pool_events }));
+	list
+}
+
 #[allow(unused_variables)]
 /// Trait to be implemented by Network Event Hooks
 pub trait NetEvents {
@@ -77,6 +190,73 @@ pub trait NetEvents {
 pub trait ChainEvents {
 	/// Triggers when a new block is accepted by the chain (might be a Reorg or a Fork)
 	fn on_block_accepted(&self, block: &core::Block, status: BlockStatus) {}
+
+	/// Triggers when the chain rejects the reorg deeper than the max reorg depth
+	fn on_deep_reorg_rejected(&self, alert: &DeepReorgAlert) {}
+}
+
+#[allow(unused_variables)]
+/// Trait to be implemented by Transaction Pool Event Hooks
+pub trait PoolEvents {
+	/// Triggers when a transaction is added to the txpool, restored - added back after the reorg
+	fn on_tx_added(&self, entry: &pool::PoolEntry, restored: bool) {}
+
+	/// Triggers when a transaction is removed from the txpool
+	fn on_tx_removed(&self, entry: &pool::PoolEntry, reason: pool::TxRemovalReason) {}
+}
+
+#[allow(unused_variables)]
+/// Trait to be implemented by Node Alert Hooks
+pub trait AlertEvents {
+	/// Triggers when the chain tip lag state is changed
+	fn on_tip_lag_alert(&self, alert: &TipLagAlert) {}
+
+	/// Triggers when the consensus canary state of the remote node is changed
+	fn on_canary_alert(&self, state: &CanaryNodeState) {}
+}
+
+/// Records the reorgs and the fork blocks for the confirmation safety API
+struct ForkActivityRecorder;
+
+impl ChainEvents for ForkActivityRecorder {
+	fn on_block_accepted(&self, _block: &core::Block, status: BlockStatus) {
+		api::record_fork_activity(&status, Utc::now().timestamp());
+	}
+}
+
+/// Tracks the kernel watches, publishes their events to the websocket subscribers
+/// and the webhook destinations
+struct KernelWatchPublisher {
+	hub: Arc<KernelWatchHub>,
+	webhook: Option<WebHook>,
+}
+
+impl ChainEvents for KernelWatchPublisher {
+	fn on_block_accepted(&self, block: &core::Block, status: BlockStatus) {
+		let events = self.hub.block_accepted(block, &status);
+		if let Some(ref webhook) = self.webhook {
+			for event in &events {
+				if !webhook.make_request(event, &None, KERNEL_WATCH_EVENT) {
+					error!("Failed to serialize kernel watch event {:?}", event);
+				}
+			}
+		}
+	}
+}
+
+/// Publishes the txpool delta events to the websocket subscribers
+struct TxPoolEventsPublisher {
+	hub: Arc<TxPoolEventsHub>,
+}
+
+impl PoolEvents for TxPoolEventsPublisher {
+	fn on_tx_added(&self, entry: &pool::PoolEntry, restored: bool) {
+		self.hub.publish(TxPoolEvent::tx_added(entry, restored));
+	}
+
+	fn on_tx_removed(&self, entry: &pool::PoolEntry, reason: pool::TxRemovalReason) {
+		self.hub.publish(TxPoolEvent::tx_removed(entry, reason));
+	}
 }
 
 /// Basic Logger
@@ -187,6 +367,95 @@ fn parse_url(value: &Option<String>) -> Option<hyper::Uri> {
 	}
 }
 
+/// Web hook destination with the parsed url and the signing key
+struct Destination {
+	url: hyper::Uri,
+	/// Accepted events, all events if empty
+	events: Vec<String>,
+	key: Option<hmac::Key>,
+}
+
+impl Destination {
+	fn from_config(config: &WebHookDestination) -> Result<Destination, String> {
+		for event in &config.events {
+			if !WEBHOOK_EVENTS.contains(&event.as_str()) {
+				return Err(format!(
+					"Invalid webhook event {} for {}, expected one of {:?}",
+					event, config.url, WEBHOOK_EVENTS
+				));
+			}
+		}
+		let url: hyper::Uri = config
+			.url
+			.parse()
+			.map_err(|e| format!("Invalid webhook url {}, {}", config.url, e))?;
+		let scheme = url.scheme().map(|s| s.as_str());
+		if scheme != Some("http") && scheme != Some("https") {
+			return Err(format!(
+				"Invalid webhook url scheme {}, expected one of ['http', https']",
+				config.url
+			));
+		}
+		Ok(Destination {
+			url,
+			events: config.events.clone(),
+			key: config
+				.secret
+				.as_ref()
+				.map(|s| hmac::Key::new(hmac::HMAC_SHA256, s.as_bytes())),
+		})
+	}
+
+	fn accepts(&self, event: &str) -> bool {
+		self.events.is_empty() || self.events.iter().any(|e| e == event)
+	}
+}
+
+/// Hex encoded HMAC-SHA256 signature of the payload
+fn payload_signature(key: &hmac::Key, payload: &str) -> String {
+	hmac::sign(key, payload.as_bytes()).as_ref().to_hex()
+}
+
+impl AlertEvents for EventLogger {
+	fn on_tip_lag_alert(&self, alert: &TipLagAlert) {
+		match alert.status {
+			TipLagStatus::Ok => info!(
+				"Chain tip is back in sync at {}, peers at {}",
+				alert.height, alert.peer_height
+			),
+			TipLagStatus::NetworkQuiet => info!(
+				"Chain tip at {} is {} secs old, but peers don't have newer blocks. Network is quiet.",
+				alert.height, alert.tip_age_secs
+			),
+			_ => warn!(
+				"Chain tip lag alert {:?}: tip at {} ({} secs old), peers at {}",
+				alert.status, alert.height, alert.tip_age_secs, alert.peer_height
+			),
+		}
+	}
+
+	fn on_canary_alert(&self, state: &CanaryNodeState) {
+		match state.status {
+			CanaryStatus::Ok => info!(
+				"Consensus canary: chain is the same as at {}, tip at {}, remote tip at {}",
+				state.url, state.height, state.remote_height
+			),
+			CanaryStatus::Diverged => error!(
+				"CRITICAL: Consensus canary: chain diverged from {} since {}, tip at {}, remote tip at {}, fork at {:?}. Possible consensus bug!",
+				state.url,
+				state.diverged_since.unwrap_or(0),
+				state.height,
+				state.remote_height,
+				state.fork_height
+			),
+			_ => warn!(
+				"Consensus canary {:?} for {}: tip at {}, remote tip at {}, fork at {:?}",
+				state.status, state.url, state.height, state.remote_height, state.fork_height
+			),
+		}
+	}
+}
+
 /// A struct that holds the hyper/tokio runtime.
 struct WebHook {
 	/// url to POST transaction data when a new transaction arrives from a peer
@@ -197,8 +466,12 @@ struct WebHook {
 	block_received_url: Option<hyper::Uri>,
 	/// url to POST block data when a new block is accepted by our node (might be a reorg or a fork)
 	block_accepted_url: Option<hyper::Uri>,
-	/// The hyper client to be used for all requests
-	client: Client<HttpsConnector<HttpConnector>>,
+	/// url to POST the chain tip lag alerts
+	tip_lag_alert_url: Option<hyper::Uri>,
+	/// Additional destinations with the event filters
+	destinations: Vec<Destination>,
+	/// The hyper client to be used for all requests, routed by the outbound HTTP settings
+	client: Client<OutboundConnector>,
 	/// The tokio event loop
 	runtime: Runtime,
 }
@@ -210,6 +483,8 @@ impl WebHook {
 		header_received_url: Option<hyper::Uri>,
 		block_received_url: Option<hyper::Uri>,
 		block_accepted_url: Option<hyper::Uri>,
+		tip_lag_alert_url: Option<hyper::Uri>,
+		destinations: Vec<Destination>,
 		nthreads: u16,
 		timeout: u16,
 	) -> WebHook {
@@ -220,16 +495,17 @@ impl WebHook {
 			nthreads, timeout
 		);
 
-		let https = HttpsConnector::new();
 		let client = Client::builder()
 			.pool_idle_timeout(keep_alive)
-			.build::<_, hyper::Body>(https);
+			.build::<_, hyper::Body>(OutboundConnector::new());
 
 		WebHook {
 			tx_received_url,
 			block_received_url,
 			header_received_url,
 			block_accepted_url,
+			tip_lag_alert_url,
+			destinations,
 			client,
 			runtime: Builder::new()
 				.threaded_scheduler()
@@ -247,12 +523,29 @@ impl WebHook {
 			parse_url(&config.header_received_url),
 			parse_url(&config.block_received_url),
 			parse_url(&config.block_accepted_url),
+			parse_url(&config.tip_lag_alert_url),
+			config
+				.destinations
+				.iter()
+				.filter_map(|d| match Destination::from_config(d) {
+					Ok(destination) => Some(destination),
+					Err(e) => {
+						error!("Skipping webhook destination, {}", e);
+						None
+					}
+				})
+				.collect(),
 			config.nthreads,
 			config.timeout,
 		)
 	}
 
-	fn post(&self, url: hyper::Uri, data: String) {
+	fn post(&self, url: hyper::Uri, data: String, event: &'static str, signature: Option<String>) {
+		// The primary node emits the webhooks, the standby would duplicate them
+		if api::is_standby() {
+			debug!("Standby node, {} webhook to {} is not sent", event, url);
+			return;
+		}
 		let mut req = Request::new(Body::from(data));
 		*req.method_mut() = Method::POST;
 		*req.uri_mut() = url.clone();
@@ -260,6 +553,16 @@ impl WebHook {
 			hyper::header::CONTENT_TYPE,
 			HeaderValue::from_static("application/json"),
 		);
+		req.headers_mut()
+			.insert(EVENT_HEADER, HeaderValue::from_static(event));
+		if let Some(signature) = signature {
+			match HeaderValue::from_str(&signature) {
+				Ok(value) => {
+					req.headers_mut().insert(SIGNATURE_HEADER, value);
+				}
+				Err(e) => warn!("Invalid webhook signature header value, {}", e),
+			}
+		}
 
 		let future = self.client.request(req).map_err(move |e| {
 			warn!("Error sending POST request to {}, error: {}", url, e);
@@ -267,57 +570,288 @@ impl WebHook {
 
 		self.runtime.spawn(future);
 	}
-	fn make_request<T: Serialize>(&self, payload: &T, uri: &Option<hyper::Uri>) -> bool {
+	fn make_request<T: Serialize>(
+		&self,
+		payload: &T,
+		uri: &Option<hyper::Uri>,
+		event: &'static str,
+	) -> bool {
+		self.make_request_to(payload, uri, event, |d| d.accepts(event))
+	}
+
+	/// Post the event to the url and to the destinations selected by the filter
+	fn make_request_to<T, F>(
+		&self,
+		payload: &T,
+		uri: &Option<hyper::Uri>,
+		event: &'static str,
+		filter: F,
+	) -> bool
+	where
+		T: Serialize,
+		F: Fn(&Destination) -> bool,
+	{
+		let destinations: Vec<&Destination> =
+			self.destinations.iter().filter(|d| filter(d)).collect();
+		if uri.is_none() && destinations.is_empty() {
+			return true;
+		}
+
+		let payload = match to_string(payload) {
+			Ok(serialized) => serialized,
+			Err(_) => {
+				return false; // print error message
+			}
+		};
 		if let Some(url) = uri {
-			let payload = match to_string(payload) {
-				Ok(serialized) => serialized,
-				Err(_) => {
-					return false; // print error message
-				}
-			};
-			self.post(url.clone(), payload);
+			self.post(url.clone(), payload.clone(), event, None);
+		}
+		for d in destinations {
+			let signature = d.key.as_ref().map(|k| payload_signature(k, &payload));
+			self.post(d.url.clone(), payload.clone(), event, signature);
 		}
 		true
 	}
 }
 
+fn block_status_str(status: &BlockStatus) -> &'static str {
+	match status {
+		BlockStatus::Reorg { .. } => "reorg",
+		BlockStatus::Fork { .. } => "fork",
+		BlockStatus::Next { .. } => "head",
+	}
+}
+
+fn block_accepted_payload(block: &core::Block, status: &BlockStatus) -> Value {
+	// Add additional `depth` field to the JSON in case of reorg
+	if let BlockStatus::Reorg {
+		fork_point,
+		prev_head,
+		..
+	} = status
+	{
+		let depth = prev_head.height.saturating_sub(fork_point.height);
+		json!({
+			"hash": block.header.hash().to_hex(),
+			"status": block_status_str(status),
+			"data": block,
+			"depth": depth
+		})
+	} else {
+		json!({
+			"hash": block.header.hash().to_hex(),
+			"status": block_status_str(status),
+			"data": block
+		})
+	}
+}
+
 impl ChainEvents for WebHook {
 	fn on_block_accepted(&self, block: &core::Block, status: BlockStatus) {
-		let status_str = match status {
-			BlockStatus::Reorg { .. } => "reorg",
-			BlockStatus::Fork { .. } => "fork",
-			BlockStatus::Next { .. } => "head",
-		};
+		let payload = block_accepted_payload(block, &status);
+		if !self.make_request(&payload, &self.block_accepted_url, BLOCK_ACCEPTED_EVENT) {
+			error!(
+				"Failed to serialize block {} at height {}",
+				block.hash(),
+				block.header.height
+			);
+		}
+	}
 
-		// Add additional `depth` field to the JSON in case of reorg
-		let payload = if let BlockStatus::Reorg {
-			fork_point,
-			prev_head,
-			..
-		} = status
-		{
-			let depth = prev_head.height.saturating_sub(fork_point.height);
-			json!({
-				"hash": block.header.hash().to_hex(),
-				"status": status_str,
-				"data": block,
-				"depth": depth
-			})
-		} else {
+	/// Deep reorg alerts are sent to the destinations only
+	fn on_deep_reorg_rejected(&self, alert: &DeepReorgAlert) {
+		if !self.make_request(alert, &None, DEEP_REORG_REJECTED_EVENT) {
+			error!("Failed to serialize deep reorg alert {:?}", alert);
+		}
+	}
+}
+
+/// Block in the batch summary
+#[derive(Debug, Clone, Serialize, PartialEq)]
+struct BlockRef {
+	hash: String,
+	height: u64,
+}
+
+impl BlockRef {
+	fn from_tip(tip: &Tip) -> BlockRef {
+		BlockRef {
+			hash: tip.hash().to_hex(),
+			height: tip.height,
+		}
+	}
+}
+
+/// block_accepted event waiting for the batch delivery
+struct BatchedBlock {
+	block: BlockRef,
+	status: &'static str,
+	/// Fork point and the previous head of the reorg
+	reorg: Option<(BlockRef, BlockRef)>,
+	payload: Value,
+}
+
+/// Events the batch is delivered with. A lone block is delivered as block_accepted,
+/// otherwise the block list goes to block_batch, preceded by reorg_summary if the
+/// batch has reorgs.
+fn batch_events(blocks: Vec<BatchedBlock>) -> Vec<(&'static str, Value)> {
+	if blocks.len() == 1 && blocks[0].reorg.is_none() {
+		let payload = blocks.into_iter().next().map(|b| b.payload).unwrap();
+		return vec![(BLOCK_ACCEPTED_EVENT, payload)];
+	}
+
+	let mut events = vec![];
+	let reorgs: Vec<&(BlockRef, BlockRef)> =
+		blocks.iter().filter_map(|b| b.reorg.as_ref()).collect();
+	if let Some((_, prev_head)) = reorgs.first() {
+		let fork_point = reorgs
+			.iter()
+			.map(|(fork_point, _)| fork_point)
+			.min_by_key(|f| f.height)
+			.unwrap();
+		let new_head = blocks
+			.iter()
+			.rev()
+			.find(|b| b.status != "fork")
+			.map(|b| &b.block);
+		events.push((
+			REORG_SUMMARY_EVENT,
 			json!({
-				"hash": block.header.hash().to_hex(),
-				"status": status_str,
-				"data": block
-			})
+				"fork_point": fork_point,
+				"prev_head": prev_head,
+				"new_head": new_head,
+				"depth": prev_head.height.saturating_sub(fork_point.height),
+				"reorgs": reorgs.len(),
+				"blocks": blocks.len()
+			}),
+		));
+	}
+	let count = blocks.len();
+	let payloads: Vec<Value> = blocks.into_iter().map(|b| b.payload).collect();
+	events.push((
+		BLOCK_BATCH_EVENT,
+		json!({
+			"count": count,
+			"blocks": payloads
+		}),
+	));
+	events
+}
+
+struct BlockBatch {
+	window: Duration,
+	// Start of the current batch and its blocks
+	pending: Mutex<Option<(Instant, Vec<BatchedBlock>)>>,
+}
+
+impl BlockBatch {
+	/// Blocks of the batch that is open longer than the window
+	fn take_expired(&self) -> Option<Vec<BatchedBlock>> {
+		let mut pending = self.pending.lock();
+		let expired = match &*pending {
+			Some((started, _)) => started.elapsed() >= self.window,
+			None => false,
 		};
+		if expired {
+			pending.take().map(|(_, blocks)| blocks)
+		} else {
+			None
+		}
+	}
+}
+
+/// Web hook that coalesces the block_accepted events fired within the batch window.
+/// Destinations with full_fidelity still receive every block_accepted event as is.
+struct BatchingWebHook {
+	webhook: Arc<WebHook>,
+	batch: Arc<BlockBatch>,
+}
+
+impl BatchingWebHook {
+	fn new(webhook: WebHook, window: Duration) -> BatchingWebHook {
+		let webhook = Arc::new(webhook);
+		let batch = Arc::new(BlockBatch {
+			window,
+			pending: Mutex::new(None),
+		});
+
+		let tick = std::cmp::max(window / 4, Duration::from_millis(10));
+		let flush_webhook = webhook.clone();
+		let flush_batch = batch.clone();
+		let res = thread::Builder::new()
+			.name("webhook_batch".to_string())
+			.spawn(move || loop {
+				thread::sleep(tick);
+				if let Some(blocks) = flush_batch.take_expired() {
+					for (event, payload) in batch_events(blocks) {
+						let sent = flush_webhook.make_request_to(
+							&payload,
+							&flush_webhook.block_accepted_url,
+							event,
+							|d| {
+								!d.full_fidelity
+									&& (d.accepts(event) || d.accepts(BLOCK_ACCEPTED_EVENT))
+							},
+						);
+						if !sent {
+							error!("Failed to serialize {} webhook event", event);
+						}
+					}
+				}
+			});
+		if let Err(e) = res {
+			error!("Unable to start webhook batch thread, {}", e);
+		}
 
-		if !self.make_request(&payload, &self.block_accepted_url) {
+		BatchingWebHook { webhook, batch }
+	}
+}
+
+impl ChainEvents for BatchingWebHook {
+	fn on_block_accepted(&self, block: &core::Block, status: BlockStatus) {
+		let payload = block_accepted_payload(block, &status);
+		if !self
+			.webhook
+			.make_request_to(&payload, &None, BLOCK_ACCEPTED_EVENT, |d| {
+				d.full_fidelity && d.accepts(BLOCK_ACCEPTED_EVENT)
+			}) {
 			error!(
 				"Failed to serialize block {} at height {}",
 				block.hash(),
 				block.header.height
 			);
 		}
+
+		let reorg = match &status {
+			BlockStatus::Reorg {
+				fork_point,
+				prev_head,
+				..
+			} => Some((
+				BlockRef::from_tip(fork_point),
+				BlockRef::from_tip(prev_head),
+			)),
+			_ => None,
+		};
+		let batched = BatchedBlock {
+			block: BlockRef {
+				hash: block.hash().to_hex(),
+				height: block.header.height,
+			},
+			status: block_status_str(&status),
+			reorg,
+			payload,
+		};
+		self.batch
+			.pending
+			.lock()
+			.get_or_insert_with(|| (Instant::now(), vec![]))
+			.1
+			.push(batched);
+	}
+
+	fn on_deep_reorg_rejected(&self, alert: &DeepReorgAlert) {
+		self.webhook.on_deep_reorg_rejected(alert);
 	}
 }
 
@@ -328,7 +862,7 @@ impl NetEvents for WebHook {
 			"hash": tx.hash().to_hex(),
 			"data": tx
 		});
-		if !self.make_request(&payload, &self.tx_received_url) {
+		if !self.make_request(&payload, &self.tx_received_url, TX_RECEIVED_EVENT) {
 			error!("Failed to serialize transaction {}", tx.hash());
 		}
 	}
@@ -340,7 +874,7 @@ impl NetEvents for WebHook {
 			"peer": addr,
 			"data": block
 		});
-		if !self.make_request(&payload, &self.block_received_url) {
+		if !self.make_request(&payload, &self.block_received_url, BLOCK_RECEIVED_EVENT) {
 			error!(
 				"Failed to serialize block {} at height {}",
 				block.hash().to_hex(),
@@ -356,7 +890,7 @@ impl NetEvents for WebHook {
 			"peer": addr,
 			"data": header
 		});
-		if !self.make_request(&payload, &self.header_received_url) {
+		if !self.make_request(&payload, &self.header_received_url, HEADER_RECEIVED_EVENT) {
 			error!(
 				"Failed to serialize header {} at height {}",
 				header.hash(),
@@ -365,3 +899,105 @@ impl NetEvents for WebHook {
 		}
 	}
 }
+
+impl AlertEvents for WebHook {
+	/// Triggers when the chain tip lag state is changed
+	fn on_tip_lag_alert(&self, alert: &TipLagAlert) {
+		if !self.make_request(alert, &self.tip_lag_alert_url, TIP_LAG_ALERT_EVENT) {
+			error!("Failed to serialize tip lag alert {:?}", alert);
+		}
+	}
+
+	/// Consensus canary alerts are sent to the destinations only
+	fn on_canary_alert(&self, state: &CanaryNodeState) {
+		if !self.make_request(state, &None, CONSENSUS_CANARY_EVENT) {
+			error!("Failed to serialize consensus canary alert {:?}", state);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_payload_signature() {
+		// RFC 4231, test case 2
+		let key = hmac::Key::new(hmac::HMAC_SHA256, b"Jefe");
+		assert_eq!(
+			payload_signature(&key, "what do ya want for nothing?"),
+			"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+		);
+	}
+
+	#[test]
+	fn test_validate_webhook_config() {
+		let destination = |url: &str, events: &[&str]| WebHookDestination {
+			url: url.to_string(),
+			events: events.iter().map(|e| e.to_string()).collect(),
+			secret: None,
+			full_fidelity: false,
+		};
+		let config = |destinations: Vec<WebHookDestination>| WebHooksConfig {
+			destinations,
+			..WebHooksConfig::default()
+		};
+
+		assert!(validate_webhook_config(&config(vec![
+			destination("http://127.0.0.1:8080/events", &[]),
+			destination("https://example.com/mwc", &[BLOCK_ACCEPTED_EVENT]),
+		]))
+		.is_ok());
+		assert!(validate_webhook_config(&config(vec![destination(
+			"http://127.0.0.1:8080/events",
+			&["block_mined"]
+		)]))
+		.is_err());
+		assert!(
+			validate_webhook_config(&config(vec![destination("ftp://127.0.0.1/events", &[])]))
+				.is_err()
+		);
+		assert!(validate_webhook_config(&config(vec![destination("not a url", &[])])).is_err());
+	}
+
+	fn batched(height: u64, status: &'static str, reorg: Option<(u64, u64)>) -> BatchedBlock {
+		let block_ref = |height: u64| BlockRef {
+			hash: format!("{:064x}", height),
+			height,
+		};
+		BatchedBlock {
+			block: block_ref(height),
+			status,
+			reorg: reorg.map(|(fork, prev_head)| (block_ref(fork), block_ref(prev_head))),
+			payload: json!({ "height": height, "status": status }),
+		}
+	}
+
+	#[test]
+	fn test_batch_events() {
+		// Lone block is delivered as is
+		let events = batch_events(vec![batched(10, "head", None)]);
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].0, BLOCK_ACCEPTED_EVENT);
+
+		// Reorg from 20 to the fork at 15, new chain 16..=22
+		let mut blocks = vec![batched(16, "fork", None), batched(17, "fork", None)];
+		blocks.push(batched(21, "reorg", Some((15, 20))));
+		blocks.push(batched(22, "head", None));
+		let events = batch_events(blocks);
+		assert_eq!(events.len(), 2);
+		assert_eq!(events[0].0, REORG_SUMMARY_EVENT);
+		assert_eq!(events[0].1["fork_point"]["height"], 15);
+		assert_eq!(events[0].1["prev_head"]["height"], 20);
+		assert_eq!(events[0].1["new_head"]["height"], 22);
+		assert_eq!(events[0].1["depth"], 5);
+		assert_eq!(events[1].0, BLOCK_BATCH_EVENT);
+		assert_eq!(events[1].1["count"], 4);
+		assert_eq!(events[1].1["blocks"][3]["height"], 22);
+
+		// Burst without reorgs has no summary
+		let events = batch_events(vec![batched(1, "head", None), batched(2, "head", None)]);
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].0, BLOCK_BATCH_EVENT);
+	}
+}
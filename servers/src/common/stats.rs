@@ -26,8 +26,11 @@ use crate::core::ser::ProtocolVersion;
 
 use chrono::prelude::*;
 
+use crate::api;
 use crate::chain::SyncStatus;
+use crate::common::types::{CanaryNodeState, TipLagAlert};
 use crate::p2p;
+use crate::pool;
 use grin_core::pow::Difficulty;
 
 /// Server state info collection struct, to be passed around into internals
@@ -67,6 +70,18 @@ pub struct ServerStats {
 	pub tx_stats: Option<TxStats>,
 	/// Disk usage in GB
 	pub disk_usage_gb: String,
+	/// Chain tip lag state, None if tip lag monitor is disabled
+	pub tip_lag_alert: Option<TipLagAlert>,
+	/// Consensus canary state of the remote nodes, empty if the canary is disabled
+	pub consensus_canary: Vec<CanaryNodeState>,
+	/// Header first announcements of the mined blocks
+	pub header_first_stats: p2p::HeaderFirstStats,
+	/// Peers dropped for trickling or stalling the messages
+	pub slow_peer_counters: p2p::SlowPeerCounters,
+	/// Onion service PoW defense state, None if the defense is disabled
+	pub tor_pow_defense: Option<api::TorPowDefenseStatus>,
+	/// Libp2p gossip traffic
+	pub libp2p_stats: p2p::Libp2pStats,
 }
 
 /// Chain Statistics
@@ -92,6 +107,10 @@ pub struct TxStats {
 	pub stem_pool_size: usize,
 	/// Number of transaction kernels in the stem pool
 	pub stem_pool_kernels: usize,
+	/// Aggregation of the received stem txs
+	pub stem_aggregation: pool::StemAggregationStats,
+	/// Txs rejected by the relay policy
+	pub relay_policy: pool::RelayPolicyStats,
 }
 /// Struct to return relevant information about stratum workers
 #[derive(Clone, Serialize, Debug)]
@@ -146,6 +165,8 @@ pub struct DiffStats {
 	pub average_block_time: u64,
 	/// Average WINDOW difficulty
 	pub average_difficulty: u64,
+	/// Average WINDOW block weight utilization in percents, None if the blocks are compacted
+	pub average_fullness: Option<f64>,
 	/// WINDOW size
 	pub window_size: u64,
 }
@@ -167,6 +188,8 @@ pub struct DiffBlock {
 	pub secondary_scaling: u32,
 	/// is secondary
 	pub is_secondary: bool,
+	/// Block weight utilization in percents, None if the block is compacted
+	pub fullness: Option<f64>,
 }
 
 /// Struct to return relevant information about peers
@@ -192,6 +215,8 @@ pub struct PeerStats {
 	pub sent_bytes_per_sec: u64,
 	/// Number of bytes we've received from the peer.
 	pub received_bytes_per_sec: u64,
+	/// Ping round trip, read sizes and stalls of the connection
+	pub conn_stats: p2p::PeerConnStats,
 }
 
 impl PartialEq for PeerStats {
@@ -291,6 +316,7 @@ impl PeerStats {
 			last_seen: peer.info.last_seen(),
 			sent_bytes_per_sec: peer.last_min_sent_bytes().unwrap_or(0) / 60,
 			received_bytes_per_sec: peer.last_min_received_bytes().unwrap_or(0) / 60,
+			conn_stats: peer.conn_stats(),
 		}
 	}
 }
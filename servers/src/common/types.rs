@@ -16,20 +16,20 @@
 use std::convert::From;
 use std::sync::Arc;
 
-use chrono::prelude::Utc;
 use rand::prelude::*;
 
 use crate::api;
 use crate::chain;
-use crate::core::global::ChainTypes;
+use crate::core::global::{ChainTypes, ConsensusOverlay};
 use crate::core::{core, libtx, pow};
 use crate::keychain;
 use crate::p2p;
 use crate::pool;
 use crate::pool::types::DandelionConfig;
 use crate::store;
+use crate::util::clock;
 use failure::Fail;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 /// Error type wrapping underlying module errors.
 #[derive(Debug, Fail)]
@@ -162,6 +162,38 @@ pub struct TorConfig {
 	pub tor_external: bool,
 	/// Onion address to use, only applicable with external tor
 	pub onion_address: Option<String>,
+	/// Additional onion service ports that are proxied to the local wallet listeners.
+	/// Key is the onion service port, value is the local listener address, either
+	/// 'host:port' or 'unix:/path/to/socket'. Only applicable with internal tor.
+	#[serde(default)]
+	pub wallet_listener_ports: Option<BTreeMap<String, String>>,
+	/// Enable the Tor onion service PoW defense (HiddenServicePoWDefensesEnabled). Clients
+	/// solve the PoW puzzle to get their introduction requests served first during a DoS.
+	/// Requires Tor 0.4.8+, only applicable with internal tor.
+	#[serde(default)]
+	pub pow_defenses_enabled: bool,
+	/// Rate of the introduction requests Tor serves from the PoW queue (per second)
+	#[serde(default = "default_pow_queue_rate")]
+	pub pow_queue_rate: u32,
+	/// Burst of the introduction requests Tor serves from the PoW queue
+	#[serde(default = "default_pow_queue_burst")]
+	pub pow_queue_burst: u32,
+	/// Number of the inbound onion connections per minute that is considered a flood.
+	/// During the flood the queue rate is lowered, so Tor raises the suggested effort.
+	#[serde(default = "default_pow_flood_threshold")]
+	pub pow_flood_threshold: u32,
+}
+
+fn default_pow_queue_rate() -> u32 {
+	250
+}
+
+fn default_pow_queue_burst() -> u32 {
+	2500
+}
+
+fn default_pow_flood_threshold() -> u32 {
+	120
 }
 
 impl Default for TorConfig {
@@ -171,6 +203,11 @@ impl Default for TorConfig {
 			socks_port: 51234,
 			tor_external: false,
 			onion_address: Some("".to_string()),
+			wallet_listener_ports: None,
+			pow_defenses_enabled: false,
+			pow_queue_rate: default_pow_queue_rate(),
+			pow_queue_burst: default_pow_queue_burst(),
+			pow_flood_threshold: default_pow_flood_threshold(),
 		}
 	}
 }
@@ -207,6 +244,15 @@ pub struct ServerConfig {
 	/// Whether this node is a full archival node or a fast-sync, pruned node
 	pub archive_mode: Option<bool>,
 
+	/// Whether to build the index of kernels by fee in background.
+	/// Needed for the kernel fee queries and the fee histogram API.
+	pub kernel_fee_index: Option<bool>,
+
+	/// Whether to maintain the digest of the UTXO set and report it in the node status.
+	/// Nodes with the same digest agree on the unspent outputs, not just on the headers.
+	#[serde(default)]
+	pub utxo_set_digest: Option<bool>,
+
 	/// Whether to skip the sync timeout on startup
 	/// (To assist testing on solo chains)
 	pub skip_sync_wait: Option<bool>,
@@ -227,6 +273,50 @@ pub struct ServerConfig {
 	/// (Default: none)
 	pub invalid_block_hashes: Option<Vec<String>>,
 
+	/// Reorgs deeper than that are rejected unless allowed by the operator
+	/// with the owner API. 0 - no limit.
+	/// (Default: 0)
+	pub max_reorg_depth: Option<u64>,
+
+	/// Max CPU usage by the PoW verification of the headers during the sync,
+	/// percents of all cores.
+	/// (Default: 100)
+	pub sync_pow_cpu_limit: Option<u8>,
+
+	/// REDUCED VALIDATION, not recommended. Percents of the UTXO rangeproofs verified
+	/// during the state sync, the rest are verified in background after the node
+	/// is online. For the Raspberry Pi class hardware.
+	/// (Default: 100, all rangeproofs are verified)
+	pub rangeproof_spot_check_percent: Option<u8>,
+
+	/// Outbound-only stealth mode. Node doesn't start the p2p listener and the onion
+	/// service, the API must listen on a loopback address.
+	/// (Default: false)
+	pub stealth_mode: Option<bool>,
+
+	/// Zero-downtime upgrade. Listeners are bound with SO_REUSEPORT and the node hands off
+	/// its peers and txpool to the new node started with `--takeover`. Unix only.
+	/// (Default: false)
+	#[serde(default)]
+	pub upgrade_handoff: Option<bool>,
+
+	/// Proxy for the outbound HTTP requests (webhooks, wallet listener calls),
+	/// 'socks5://host:port' or 'http://host:port'
+	#[serde(default)]
+	pub outbound_http_proxy: Option<String>,
+
+	/// Onion only mode, the outbound HTTP requests are never sent directly. Without
+	/// a proxy the requests go through the Tor socks port if Tor is enabled, otherwise
+	/// they are refused. Loopback addresses are always connected directly.
+	/// (Default: false)
+	#[serde(default)]
+	pub outbound_onion_only: Option<bool>,
+
+	/// /readyz reports the node as ready only if its chain is at most that many blocks
+	/// behind the connected peers. (Default: 5)
+	#[serde(default)]
+	pub api_ready_max_blocks_behind: Option<u64>,
+
 	/// Whether to run the TUI
 	/// if enabled, this will disable logging to stdout
 	pub run_tui: Option<bool>,
@@ -248,6 +338,44 @@ pub struct ServerConfig {
 	/// libp2p connection port (will be activated with Tor)
 	pub libp2p_port: Option<u16>,
 
+	/// Onion addresses that are explicitly allowed for libp2p connections. If the list
+	/// is not empty, only allowed peers are dialed and accepted.
+	pub libp2p_allow_list: Option<Vec<String>>,
+
+	/// Onion addresses that are never dialed or accepted by libp2p node
+	pub libp2p_deny_list: Option<Vec<String>>,
+
+	/// Topics of the gossip messages audit log. The accepted messages of these topics
+	/// are recorded. Audit is disabled if the list is empty.
+	#[serde(default)]
+	pub libp2p_audit_topics: Option<Vec<String>>,
+
+	/// Gossip audit records older than that are pruned. Default: 30 days
+	#[serde(default)]
+	pub libp2p_audit_retention_days: Option<u32>,
+
+	/// Max number of the gossip audit records, the oldest are pruned. Default: 100000
+	#[serde(default)]
+	pub libp2p_audit_max_entries: Option<u64>,
+
+	/// Interval of the gossip mesh latency probes in seconds. Probes are disabled if
+	/// it is not set or 0.
+	#[serde(default)]
+	pub libp2p_probe_interval_secs: Option<u64>,
+
+	/// Quarantine period in seconds of the libp2p peers that originated the invalid
+	/// gossip messages. 0 disables the quarantine.
+	#[serde(default)]
+	pub libp2p_quarantine_secs: Option<u64>,
+
+	/// Gossipsub parameters of the libp2p node
+	#[serde(default)]
+	pub libp2p_config: p2p::Libp2pConfig,
+
+	/// Rate limits of the integrity kernels of the libp2p gossip messages
+	#[serde(default)]
+	pub libp2p_policy: p2p::Libp2pPolicy,
+
 	/// Configuration for the peer-to-peer server
 	pub p2p_config: p2p::P2PConfig,
 
@@ -259,6 +387,22 @@ pub struct ServerConfig {
 	#[serde(default)]
 	pub dandelion_config: pool::DandelionConfig,
 
+	/// Foreign API load shedding configuration
+	#[serde(default)]
+	pub foreign_api_load_shedding: api::LoadSheddingConfig,
+
+	/// API server HTTP/2, keep-alive and request size configuration
+	#[serde(default)]
+	pub api_http_config: api::HttpConfig,
+
+	/// Owner API session tokens configuration
+	#[serde(default)]
+	pub owner_api_sessions: api::OwnerSessionConfig,
+
+	/// gRPC server configuration
+	#[serde(default)]
+	pub grpc_config: api::GrpcConfig,
+
 	/// Configuration for the mining daemon
 	#[serde(default)]
 	pub stratum_mining_config: Option<StratumServerConfig>,
@@ -267,9 +411,33 @@ pub struct ServerConfig {
 	#[serde(default)]
 	pub webhook_config: WebHooksConfig,
 
+	/// Chain tip lag alerting configuration
+	#[serde(default)]
+	pub tip_lag_alert_config: TipLagAlertConfig,
+
+	/// Consensus canary configuration
+	#[serde(default)]
+	pub consensus_canary_config: ConsensusCanaryConfig,
+
+	/// Standby (hot spare) node configuration
+	#[serde(default)]
+	pub standby_config: StandbyConfig,
+
+	/// Persistent metrics snapshots configuration
+	#[serde(default)]
+	pub metrics_history_config: MetricsHistoryConfig,
+
+	/// Cold storage (offline snapshot) mode configuration
+	#[serde(default)]
+	pub cold_storage_config: ColdStorageConfig,
+
 	/// Tor Configuration
 	#[serde(default)]
 	pub tor_config: TorConfig,
+
+	/// Consensus parameters overlay, applicable to usernet only
+	#[serde(default)]
+	pub consensus_overlay: ConsensusOverlay,
 }
 
 impl Default for ServerConfig {
@@ -283,14 +451,33 @@ impl Default for ServerConfig {
 			tls_certificate_key: None,
 			p2p_config: p2p::P2PConfig::default(),
 			dandelion_config: pool::DandelionConfig::default(),
+			foreign_api_load_shedding: api::LoadSheddingConfig::default(),
+			api_http_config: api::HttpConfig::default(),
+			owner_api_sessions: api::OwnerSessionConfig::default(),
+			grpc_config: api::GrpcConfig::default(),
 			stratum_mining_config: Some(StratumServerConfig::default()),
+			tip_lag_alert_config: TipLagAlertConfig::default(),
+			consensus_canary_config: ConsensusCanaryConfig::default(),
+			standby_config: StandbyConfig::default(),
+			metrics_history_config: MetricsHistoryConfig::default(),
+			cold_storage_config: ColdStorageConfig::default(),
 			chain_type: ChainTypes::default(),
 			archive_mode: Some(false),
+			kernel_fee_index: Some(false),
+			utxo_set_digest: Some(false),
 			chain_validation_mode: ChainValidationMode::default(),
 			pool_config: pool::PoolConfig::default(),
 			skip_sync_wait: Some(false),
 			header_cache_size: Some(0),
 			invalid_block_hashes: Some(vec![]),
+			max_reorg_depth: Some(0),
+			sync_pow_cpu_limit: Some(100),
+			rangeproof_spot_check_percent: Some(100),
+			stealth_mode: Some(false),
+			upgrade_handoff: Some(false),
+			outbound_http_proxy: None,
+			outbound_onion_only: Some(false),
+			api_ready_max_blocks_behind: Some(5),
 			duration_sync_short: Some(30),
 			duration_sync_long: Some(50),
 			run_tui: Some(true),
@@ -299,8 +486,18 @@ impl Default for ServerConfig {
 			libp2p_enabled: Some(true),
 			libp2p_port: Some(3417),
 			libp2p_topics: None,
+			libp2p_allow_list: None,
+			libp2p_deny_list: None,
+			libp2p_audit_topics: None,
+			libp2p_audit_retention_days: None,
+			libp2p_audit_max_entries: None,
+			libp2p_probe_interval_secs: None,
+			libp2p_quarantine_secs: None,
+			libp2p_config: p2p::Libp2pConfig::default(),
+			libp2p_policy: p2p::Libp2pPolicy::default(),
 			webhook_config: WebHooksConfig::default(),
 			tor_config: TorConfig::default(),
+			consensus_overlay: ConsensusOverlay::default(),
 		}
 	}
 }
@@ -364,6 +561,18 @@ pub struct StratumServerConfig {
 	/// Black list of IPs
 	#[serde(default)]
 	pub ip_black_list: HashSet<String>,
+
+	/// Record the jobs and the submitted shares with the validation results
+	#[serde(default)]
+	pub share_audit: bool,
+
+	/// Stratum log records older than that are pruned
+	#[serde(default = "StratumServerConfig::default_share_audit_retention_days")]
+	pub share_audit_retention_days: u32,
+
+	/// Max number of the stratum log records, the oldest are pruned
+	#[serde(default = "StratumServerConfig::default_share_audit_max_entries")]
+	pub share_audit_max_entries: u64,
 }
 
 impl StratumServerConfig {
@@ -388,6 +597,12 @@ impl StratumServerConfig {
 	fn default_connection_pace_ms() -> i64 {
 		-1
 	}
+	fn default_share_audit_retention_days() -> u32 {
+		api::DEFAULT_STRATUM_AUDIT_RETENTION_DAYS
+	}
+	fn default_share_audit_max_entries() -> u64 {
+		api::DEFAULT_STRATUM_AUDIT_MAX_ENTRIES
+	}
 }
 
 impl Default for StratumServerConfig {
@@ -408,6 +623,9 @@ impl Default for StratumServerConfig {
 			connection_pace_ms: StratumServerConfig::default_connection_pace_ms(),
 			ip_white_list: HashSet::new(),
 			ip_black_list: HashSet::new(),
+			share_audit: false,
+			share_audit_retention_days: StratumServerConfig::default_share_audit_retention_days(),
+			share_audit_max_entries: StratumServerConfig::default_share_audit_max_entries(),
 		}
 	}
 }
@@ -423,12 +641,51 @@ pub struct WebHooksConfig {
 	pub block_received_url: Option<String>,
 	/// url to POST block data when a new block is accepted by our node (might be a reorg or a fork)
 	pub block_accepted_url: Option<String>,
+	/// url to POST the chain tip lag alerts
+	#[serde(default)]
+	pub tip_lag_alert_url: Option<String>,
+	/// Additional destinations with the event filters and the payload signing
+	#[serde(default)]
+	pub destinations: Vec<WebHookDestination>,
 	/// number of worker threads in the tokio runtime
 	#[serde(default = "default_nthreads")]
 	pub nthreads: u16,
 	/// timeout in seconds for the http request
 	#[serde(default = "default_timeout")]
 	pub timeout: u16,
+	/// block_accepted events that fire within this window (milliseconds) are delivered
+	/// as a single block_batch event, plus a reorg_summary event if the batch has reorgs.
+	/// Every event is delivered as is if 0.
+	#[serde(default)]
+	pub batch_window_ms: u64,
+	/// Watched kernels are reported until they have that many confirmations, unless
+	/// the watch has its own depth
+	#[serde(default = "default_kernel_watch_depth")]
+	pub kernel_watch_depth: u64,
+	/// Max number of the tx pool and kernel watch events kept in the event journal for
+	/// the websocket subscribers that resume after the reconnect. Journal is disabled if 0.
+	#[serde(default = "default_event_journal_max_entries")]
+	pub event_journal_max_entries: u64,
+}
+
+/// Web hook destination that receives the selected events
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebHookDestination {
+	/// url to POST the events
+	pub url: String,
+	/// Events to POST: tx_received, header_received, block_received, block_accepted,
+	/// block_batch, reorg_summary, tip_lag_alert, deep_reorg_rejected, kernel_watch. All
+	/// events are posted if the list is empty. Destinations that accept block_accepted
+	/// receive the batch events too.
+	#[serde(default)]
+	pub events: Vec<String>,
+	/// Shared secret. If set, the payload HMAC-SHA256 signature is sent in the
+	/// X-Mwc-Signature header, so the receiver can verify that event came from this node.
+	#[serde(default)]
+	pub secret: Option<String>,
+	/// Receive every block_accepted event as is, even when the events are batched
+	#[serde(default)]
+	pub full_fidelity: bool,
 }
 
 fn default_timeout() -> u16 {
@@ -439,6 +696,14 @@ fn default_nthreads() -> u16 {
 	4
 }
 
+fn default_kernel_watch_depth() -> u64 {
+	10
+}
+
+fn default_event_journal_max_entries() -> u64 {
+	api::DEFAULT_EVENT_JOURNAL_MAX_ENTRIES
+}
+
 impl Default for WebHooksConfig {
 	fn default() -> WebHooksConfig {
 		WebHooksConfig {
@@ -446,12 +711,289 @@ impl Default for WebHooksConfig {
 			header_received_url: None,
 			block_received_url: None,
 			block_accepted_url: None,
+			tip_lag_alert_url: None,
+			destinations: vec![],
 			nthreads: default_nthreads(),
 			timeout: default_timeout(),
+			batch_window_ms: 0,
+			kernel_watch_depth: default_kernel_watch_depth(),
+			event_journal_max_entries: default_event_journal_max_entries(),
 		}
 	}
 }
 
+/// Chain tip lag alerting configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TipLagAlertConfig {
+	/// Whether to monitor the chain tip lag
+	#[serde(default = "default_enable_tip_lag_alerts")]
+	pub enable_tip_lag_alerts: bool,
+	/// Alert if the peers advertise the tip that is more than this number of blocks ahead of us
+	#[serde(default = "default_max_height_lag")]
+	pub max_height_lag: u64,
+	/// Alert if our tip block is older than this number of seconds
+	#[serde(default = "default_max_tip_age_secs")]
+	pub max_tip_age_secs: i64,
+	/// How often the tip lag is checked, in seconds
+	#[serde(default = "default_tip_lag_check_interval_secs")]
+	pub tip_lag_check_interval_secs: u64,
+}
+
+fn default_enable_tip_lag_alerts() -> bool {
+	true
+}
+
+fn default_max_height_lag() -> u64 {
+	5
+}
+
+fn default_max_tip_age_secs() -> i64 {
+	1800
+}
+
+fn default_tip_lag_check_interval_secs() -> u64 {
+	60
+}
+
+impl Default for TipLagAlertConfig {
+	fn default() -> TipLagAlertConfig {
+		TipLagAlertConfig {
+			enable_tip_lag_alerts: default_enable_tip_lag_alerts(),
+			max_height_lag: default_max_height_lag(),
+			max_tip_age_secs: default_max_tip_age_secs(),
+			tip_lag_check_interval_secs: default_tip_lag_check_interval_secs(),
+		}
+	}
+}
+
+/// Chain tip lag state, as seen by the tip lag monitor
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum TipLagStatus {
+	/// Our tip is recent and in line with the peers
+	Ok,
+	/// No connected peers, we can't compare our tip
+	NoPeers,
+	/// Our tip is old, but the peers don't have anything newer. Network is quiet.
+	NetworkQuiet,
+	/// Peers are ahead of us and our tip is moving (catching up)
+	Lagging,
+	/// Peers are ahead of us and our tip is not moving
+	Stuck,
+}
+
+/// Chain tip lag alert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TipLagAlert {
+	/// Current state
+	pub status: TipLagStatus,
+	/// Our tip height
+	pub height: u64,
+	/// Max height advertised by the connected peers
+	pub peer_height: u64,
+	/// Age of our tip block in seconds
+	pub tip_age_secs: i64,
+	/// Time when the node entered this state
+	pub since: i64,
+}
+
+/// Consensus canary configuration. The canary compares our chain with the chains of
+/// the trusted remote nodes, a divergence is an early warning of a consensus bug.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConsensusCanaryConfig {
+	/// Whether to run the consensus canary
+	#[serde(default)]
+	pub enable_consensus_canary: bool,
+	/// Foreign API base urls of the remote nodes, e.g. "http://10.0.0.5:3413"
+	#[serde(default)]
+	pub canary_nodes: Vec<String>,
+	/// Foreign API secret of the remote nodes, if they require one
+	#[serde(default)]
+	pub canary_api_secret: Option<String>,
+	/// How often the chains are compared, in seconds
+	#[serde(default = "default_canary_check_interval_secs")]
+	pub canary_check_interval_secs: u64,
+	/// Number of the recent blocks searched for the fork point
+	#[serde(default = "default_canary_block_window")]
+	pub canary_block_window: u64,
+	/// Tips that differ by more than this number of blocks are reported as lagging
+	#[serde(default = "default_canary_max_height_gap")]
+	pub canary_max_height_gap: u64,
+	/// Divergence that lasts longer than this number of seconds raises the critical alert.
+	/// Short forks are normal and get resolved by the network.
+	#[serde(default = "default_canary_tolerance_secs")]
+	pub canary_tolerance_secs: i64,
+}
+
+fn default_canary_check_interval_secs() -> u64 {
+	120
+}
+
+fn default_canary_block_window() -> u64 {
+	10
+}
+
+fn default_canary_max_height_gap() -> u64 {
+	5
+}
+
+fn default_canary_tolerance_secs() -> i64 {
+	600
+}
+
+impl Default for ConsensusCanaryConfig {
+	fn default() -> ConsensusCanaryConfig {
+		ConsensusCanaryConfig {
+			enable_consensus_canary: false,
+			canary_nodes: vec![],
+			canary_api_secret: None,
+			canary_check_interval_secs: default_canary_check_interval_secs(),
+			canary_block_window: default_canary_block_window(),
+			canary_max_height_gap: default_canary_max_height_gap(),
+			canary_tolerance_secs: default_canary_tolerance_secs(),
+		}
+	}
+}
+
+/// Standby node configuration. The standby node syncs and validates the chain, but
+/// the stratum server, the transaction pushes and the webhooks wait for the promotion.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StandbyConfig {
+	/// Whether the node starts as the standby
+	#[serde(default)]
+	pub standby_mode: bool,
+	/// Foreign API base url of the primary node, e.g. "http://10.0.0.2:3413"
+	#[serde(default)]
+	pub primary_url: Option<String>,
+	/// Foreign API secret of the primary node, if it requires one
+	#[serde(default)]
+	pub primary_api_secret: Option<String>,
+	/// Whether the node is promoted when the primary is down
+	#[serde(default)]
+	pub auto_promote: bool,
+	/// How often the primary is checked, in seconds
+	#[serde(default = "default_primary_check_interval_secs")]
+	pub primary_check_interval_secs: u64,
+	/// Number of the failed checks in a row that means the primary is down
+	#[serde(default = "default_primary_failure_threshold")]
+	pub primary_failure_threshold: u32,
+}
+
+fn default_primary_check_interval_secs() -> u64 {
+	5
+}
+
+fn default_primary_failure_threshold() -> u32 {
+	3
+}
+
+impl Default for StandbyConfig {
+	fn default() -> StandbyConfig {
+		StandbyConfig {
+			standby_mode: false,
+			primary_url: None,
+			primary_api_secret: None,
+			auto_promote: false,
+			primary_check_interval_secs: default_primary_check_interval_secs(),
+			primary_failure_threshold: default_primary_failure_threshold(),
+		}
+	}
+}
+
+/// Persistent metrics snapshots configuration. Snapshots are kept in the chain db
+/// as a ring buffer, the oldest ones are pruned when the limit is reached.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MetricsHistoryConfig {
+	/// Whether the metrics snapshots are persisted
+	#[serde(default = "default_enable_metrics_history")]
+	pub enable_metrics_history: bool,
+	/// How often the snapshot is taken, in seconds
+	#[serde(default = "default_metrics_snapshot_interval_secs")]
+	pub metrics_snapshot_interval_secs: u64,
+	/// Max number of the snapshots that are kept
+	#[serde(default = "default_metrics_history_limit")]
+	pub metrics_history_limit: usize,
+}
+
+fn default_enable_metrics_history() -> bool {
+	true
+}
+
+fn default_metrics_snapshot_interval_secs() -> u64 {
+	60
+}
+
+fn default_metrics_history_limit() -> usize {
+	// one week of the snapshots taken every minute
+	10080
+}
+
+impl Default for MetricsHistoryConfig {
+	fn default() -> MetricsHistoryConfig {
+		MetricsHistoryConfig {
+			enable_metrics_history: default_enable_metrics_history(),
+			metrics_snapshot_interval_secs: default_metrics_snapshot_interval_secs(),
+			metrics_history_limit: default_metrics_history_limit(),
+		}
+	}
+}
+
+/// Cold storage mode configuration. The node serves the exported chain snapshot
+/// read-only and never connects to the network.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ColdStorageConfig {
+	/// Whether the node starts in the cold storage mode
+	#[serde(default)]
+	pub cold_storage_mode: bool,
+	/// Directory of the snapshot, a copy of the chain_data directory (lmdb, header and
+	/// txhashset) of a stopped node
+	#[serde(default)]
+	pub snapshot_dir: Option<String>,
+}
+
+impl Default for ColdStorageConfig {
+	fn default() -> ColdStorageConfig {
+		ColdStorageConfig {
+			cold_storage_mode: false,
+			snapshot_dir: None,
+		}
+	}
+}
+
+/// State of our chain compared with the chain of the remote node
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum CanaryStatus {
+	/// Chains are the same at the common height
+	Ok,
+	/// Remote node doesn't respond
+	Unreachable,
+	/// Chains are the same, but the tips are too far apart
+	Lagging,
+	/// Chains are different, still within the tolerance period
+	Diverging,
+	/// Chains are different for longer than the tolerance period. Critical.
+	Diverged,
+}
+
+/// Comparison of our chain with the chain of the remote node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryNodeState {
+	/// Foreign API url of the remote node
+	pub url: String,
+	/// Current state
+	pub status: CanaryStatus,
+	/// Our tip height
+	pub height: u64,
+	/// Tip height of the remote node
+	pub remote_height: u64,
+	/// Last height where the chains are the same, None if the chains are the same
+	/// at the common height or the fork is deeper than the block window
+	pub fork_height: Option<u64>,
+	/// Time when the chains became different, None if they are the same
+	pub diverged_since: Option<i64>,
+	/// Time when the node entered this state
+	pub since: i64,
+}
+
 /// A node is either "stem" of "fluff" for the duration of a single epoch.
 /// A node also maintains an outbound relay peer for the epoch.
 #[derive(Debug)]
@@ -483,7 +1025,7 @@ impl DandelionEpoch {
 			None => true,
 			Some(start_time) => {
 				let epoch_secs = self.config.epoch_secs;
-				Utc::now().timestamp().saturating_sub(start_time) > epoch_secs as i64
+				clock::now().timestamp().saturating_sub(start_time) > epoch_secs as i64
 			}
 		}
 	}
@@ -492,7 +1034,7 @@ impl DandelionEpoch {
 	/// Select stem/fluff based on configured stem_probability.
 	/// Choose a new outbound stem relay peer.
 	pub fn next_epoch(&mut self, peers: &Arc<p2p::Peers>) {
-		self.start_time = Some(Utc::now().timestamp());
+		self.start_time = Some(clock::now().timestamp());
 		self.relay_peer = peers.outgoing_connected_peers().first().cloned();
 
 		// If stem_probability == 90 then we stem 90% of the time.
@@ -507,6 +1049,17 @@ impl DandelionEpoch {
 		);
 	}
 
+	/// Current state of the epoch for the API
+	pub fn info(&self) -> pool::DandelionEpochInfo {
+		pool::DandelionEpochInfo {
+			start_time: self.start_time,
+			expiry_time: self.start_time.map(|t| t + self.config.epoch_secs as i64),
+			is_stem: self.is_stem,
+			relay_peer: self.relay_peer.as_ref().map(|p| p.info.addr.to_string()),
+			embargo_secs: self.config.embargo_secs,
+		}
+	}
+
 	/// Are we stemming (or fluffing) transactions in this epoch?
 	pub fn is_stem(&self) -> bool {
 		self.is_stem
@@ -517,6 +1070,41 @@ impl DandelionEpoch {
 		self.config.always_stem_our_txs
 	}
 
+	/// Seconds the received stem txs are aggregated for before the relay, 0 - disabled.
+	pub fn stem_aggregation_secs(&self) -> i64 {
+		self.config.stem_aggregation_secs as i64
+	}
+
+	/// Max number of the received stem txs aggregated together.
+	pub fn stem_aggregation_max_txs(&self) -> usize {
+		std::cmp::max(1, self.config.stem_aggregation_max_txs as usize)
+	}
+
+	/// Privacy mode for our (pushed via api) txs?
+	pub fn own_tx_privacy(&self) -> bool {
+		self.config.own_tx_privacy
+	}
+
+	/// Random delay (secs) for the first relay of our txs in privacy mode.
+	pub fn own_tx_relay_delay(&self) -> i64 {
+		let max_delay = self.config.own_tx_relay_delay_secs as i64;
+		rand::thread_rng().gen_range(0, max_delay + 1)
+	}
+
+	/// Random outbound Tor peer to stem our txs in privacy mode.
+	/// Clearnet peers are never used, so the origin of the tx is not linked to our IP.
+	pub fn tor_relay_peer(&self, peers: &Arc<p2p::Peers>) -> Option<Arc<p2p::Peer>> {
+		let tor_peers: Vec<Arc<p2p::Peer>> = peers
+			.outgoing_connected_peers()
+			.into_iter()
+			.filter(|p| match p.info.addr {
+				p2p::PeerAddr::Onion(_) => p.info.serves_bodies(),
+				_ => false,
+			})
+			.collect();
+		tor_peers.choose(&mut rand::thread_rng()).cloned()
+	}
+
 	/// What is our current relay peer?
 	/// If it is not connected then choose a new one.
 	pub fn relay_peer(&mut self, peers: &Arc<p2p::Peers>) -> Option<Arc<p2p::Peer>> {
@@ -534,7 +1122,11 @@ impl DandelionEpoch {
 		}
 
 		if update_relay {
-			self.relay_peer = peers.outgoing_connected_peers().first().cloned();
+			// Headers only relay peers don't take part in the transaction relay
+			self.relay_peer = peers
+				.outgoing_connected_peers()
+				.into_iter()
+				.find(|p| p.info.serves_bodies());
 			info!(
 				"DandelionEpoch: relay_peer: new peer chosen: {:?}",
 				self.relay_peer.clone().map(|p| p.info.addr.clone())
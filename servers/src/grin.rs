@@ -14,7 +14,15 @@
 
 //! Grin P2P / API server
 
+pub mod consensus_canary;
 pub mod dandelion_monitor;
+pub mod kernel_fee_index;
+pub mod metrics_history;
+pub mod rproof_verifier;
 pub mod seed;
 pub mod server;
+pub mod standby_monitor;
+pub mod subsystem_usage;
 pub mod sync;
+pub mod tip_lag_monitor;
+pub mod uptime;
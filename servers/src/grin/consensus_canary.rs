@@ -0,0 +1,270 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Consensus canary. Periodically compares our tip, kernel root and recent block hashes
+//! with the trusted remote nodes through their foreign API. The chains that stay
+//! different for longer than the tolerance period raise the critical alert, that is
+//! an early warning of a consensus bug after the release.
+
+use chrono::Utc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::api;
+use crate::chain;
+use crate::common::hooks::AlertEvents;
+use crate::common::types::{CanaryNodeState, CanaryStatus, ConsensusCanaryConfig};
+use crate::core::core::hash::Hashed;
+use crate::util::{RwLock, StopState, ToHex};
+
+/// Start the consensus canary thread. Current state of every remote node is published
+/// into canary_state, alert hooks are called every time the state of the node is changed.
+pub fn run_consensus_canary(
+	config: ConsensusCanaryConfig,
+	chain: Arc<chain::Chain>,
+	hooks: Vec<Box<dyn AlertEvents + Send + Sync>>,
+	canary_state: Arc<RwLock<Vec<CanaryNodeState>>>,
+	stop_state: Arc<StopState>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+	debug!("Started consensus canary.");
+
+	thread::Builder::new()
+		.name("consensus_canary".to_string())
+		.spawn(move || {
+			let run_interval = Duration::from_secs(config.canary_check_interval_secs);
+			let mut last_run: Option<Instant> = None;
+			loop {
+				if stop_state.is_stopped() {
+					break;
+				}
+
+				if last_run.map(|t| t.elapsed() > run_interval) != Some(false) {
+					for url in &config.canary_nodes {
+						if stop_state.is_stopped() {
+							break;
+						}
+						let comparison = match compare_with_node(&config, &chain, url) {
+							Ok(comparison) => Some(comparison),
+							Err(e) => {
+								debug!("consensus_canary: Unable to query {}, {}", url, e);
+								None
+							}
+						};
+						update_node_state(&config, url, comparison, &hooks, &canary_state);
+					}
+					last_run = Some(Instant::now());
+				}
+
+				thread::sleep(Duration::from_secs(1));
+			}
+		})
+}
+
+/// Result of the chains comparison with the remote node
+#[derive(Debug, Clone, PartialEq)]
+struct ChainComparison {
+	height: u64,
+	remote_height: u64,
+	// Chains are different at the common height
+	diverged: bool,
+	fork_height: Option<u64>,
+}
+
+fn compare_with_node(
+	config: &ConsensusCanaryConfig,
+	chain: &chain::Chain,
+	url: &str,
+) -> Result<ChainComparison, String> {
+	let url = url.trim_end_matches('/');
+	let secret = config.canary_api_secret.clone();
+	let head = chain
+		.head()
+		.map_err(|e| format!("Unable to read chain head, {}", e))?;
+	let remote_tip: api::Tip = api::client::get(&format!("{}/v1/chain", url), secret.clone())
+		.map_err(|e| e.to_string())?;
+
+	// The same block at the common height means the same chain below it
+	let common_height = head.height.min(remote_tip.height);
+	let mut fork_height = None;
+	let mut diverged = false;
+	let min_height = common_height.saturating_sub(config.canary_block_window);
+	let mut height = common_height;
+	loop {
+		let header = chain
+			.get_header_by_height(height)
+			.map_err(|e| format!("Unable to read header at {}, {}", height, e))?;
+		let remote_header: api::BlockHeaderPrintable =
+			api::client::get(&format!("{}/v1/headers/{}", url, height), secret.clone())
+				.map_err(|e| e.to_string())?;
+		if same_block(
+			&header.hash().to_hex(),
+			&header.kernel_root.to_hex(),
+			&remote_header,
+		) {
+			if diverged {
+				fork_height = Some(height);
+			}
+			break;
+		}
+		diverged = true;
+		if height <= min_height {
+			break;
+		}
+		height -= 1;
+	}
+
+	Ok(ChainComparison {
+		height: head.height,
+		remote_height: remote_tip.height,
+		diverged,
+		fork_height,
+	})
+}
+
+fn same_block(hash: &str, kernel_root: &str, remote: &api::BlockHeaderPrintable) -> bool {
+	hash == remote.hash && kernel_root == remote.kernel_root
+}
+
+fn update_node_state(
+	config: &ConsensusCanaryConfig,
+	url: &str,
+	comparison: Option<ChainComparison>,
+	hooks: &[Box<dyn AlertEvents + Send + Sync>],
+	canary_state: &RwLock<Vec<CanaryNodeState>>,
+) {
+	let now = Utc::now().timestamp();
+	let mut states = canary_state.write();
+	let prev = states.iter().position(|s| s.url == url);
+	let prev_state = prev.map(|i| states[i].clone());
+
+	let diverged_since = match &comparison {
+		Some(c) if c.diverged => Some(
+			prev_state
+				.as_ref()
+				.and_then(|s| s.diverged_since)
+				.unwrap_or(now),
+		),
+		Some(_) => None,
+		// Unreachable node doesn't change what we know about the divergence
+		None => prev_state.as_ref().and_then(|s| s.diverged_since),
+	};
+	let status = evaluate_canary(config, comparison.as_ref(), diverged_since, now);
+	let changed = prev_state.as_ref().map(|s| s.status) != Some(status);
+	let since = match &prev_state {
+		Some(s) if !changed => s.since,
+		_ => now,
+	};
+	let (height, remote_height, fork_height) = match &comparison {
+		Some(c) => (c.height, c.remote_height, c.fork_height),
+		None => prev_state
+			.as_ref()
+			.map(|s| (s.height, s.remote_height, s.fork_height))
+			.unwrap_or((0, 0, None)),
+	};
+	let new_state = CanaryNodeState {
+		url: url.to_string(),
+		status,
+		height,
+		remote_height,
+		fork_height,
+		diverged_since,
+		since,
+	};
+	// Initial Ok state is not worth to report
+	if changed && (prev_state.is_some() || status != CanaryStatus::Ok) {
+		for hook in hooks {
+			hook.on_canary_alert(&new_state);
+		}
+	}
+	match prev {
+		Some(i) => states[i] = new_state,
+		None => states.push(new_state),
+	}
+}
+
+/// Status of the remote node.
+/// comparison - result of the chains comparison, None if the node is not reachable.
+/// diverged_since - time when the chains became different, None if they are the same.
+fn evaluate_canary(
+	config: &ConsensusCanaryConfig,
+	comparison: Option<&ChainComparison>,
+	diverged_since: Option<i64>,
+	now: i64,
+) -> CanaryStatus {
+	let comparison = match comparison {
+		Some(c) => c,
+		None => return CanaryStatus::Unreachable,
+	};
+	if let Some(since) = diverged_since {
+		if now - since > config.canary_tolerance_secs {
+			CanaryStatus::Diverged
+		} else {
+			CanaryStatus::Diverging
+		}
+	} else if comparison.height.max(comparison.remote_height)
+		- comparison.height.min(comparison.remote_height)
+		> config.canary_max_height_gap
+	{
+		CanaryStatus::Lagging
+	} else {
+		CanaryStatus::Ok
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn comparison(height: u64, remote_height: u64, diverged: bool) -> ChainComparison {
+		ChainComparison {
+			height,
+			remote_height,
+			diverged,
+			fork_height: None,
+		}
+	}
+
+	#[test]
+	fn test_evaluate_canary() {
+		let config = ConsensusCanaryConfig::default();
+		let now = 10_000;
+
+		assert_eq!(
+			evaluate_canary(&config, None, None, now),
+			CanaryStatus::Unreachable
+		);
+		assert_eq!(
+			evaluate_canary(&config, Some(&comparison(100, 102, false)), None, now),
+			CanaryStatus::Ok
+		);
+		assert_eq!(
+			evaluate_canary(&config, Some(&comparison(110, 100, false)), None, now),
+			CanaryStatus::Lagging
+		);
+		assert_eq!(
+			evaluate_canary(&config, Some(&comparison(100, 100, true)), Some(now), now),
+			CanaryStatus::Diverging
+		);
+		assert_eq!(
+			evaluate_canary(
+				&config,
+				Some(&comparison(100, 100, true)),
+				Some(now - config.canary_tolerance_secs - 1),
+				now
+			),
+			CanaryStatus::Diverged
+		);
+	}
+}
@@ -12,19 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use chrono::prelude::Utc;
 use rand::{thread_rng, Rng};
 use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use crate::common::adapters::DandelionAdapter;
 use crate::core::core::hash::Hashed;
-use crate::core::core::transaction;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::pool::{BlockChain, DandelionConfig, Pool, PoolEntry, PoolError, TxSource};
-use crate::util::StopState;
-use crate::{ServerTxPool, ServerVerifierCache};
+use crate::util::subsystem::{self, SUBSYSTEM_POOL};
+use crate::util::{clock, StopState};
+use crate::ServerTxPool;
 
 /// A process to monitor transactions in the stempool.
 /// With Dandelion, transaction can be broadcasted in stem or fluff phase.
@@ -38,7 +37,6 @@ pub fn monitor_transactions(
 	dandelion_config: DandelionConfig,
 	tx_pool: ServerTxPool,
 	adapter: Arc<dyn DandelionAdapter>,
-	verifier_cache: ServerVerifierCache,
 	stop_state: Arc<StopState>,
 ) -> std::io::Result<thread::JoinHandle<()>> {
 	debug!("Started Dandelion transaction monitor.");
@@ -46,27 +44,24 @@ pub fn monitor_transactions(
 	thread::Builder::new()
 		.name("dandelion".to_string())
 		.spawn(move || {
+			let _usage = subsystem::track_thread(SUBSYSTEM_POOL);
 			let run_interval = Duration::from_secs(10);
-			let mut last_run = Instant::now()
+			let mut last_run = clock::instant()
 				.checked_sub(Duration::from_secs(20))
-				.unwrap_or_else(Instant::now);
+				.unwrap_or_else(clock::instant);
 			loop {
 				// Halt Dandelion monitor if we have been notified that we are stopping.
 				if stop_state.is_stopped() {
 					break;
 				}
 
-				if last_run.elapsed() > run_interval {
+				if clock::instant().duration_since(last_run) > run_interval {
 					if !adapter.is_stem() {
-						let _ = process_fluff_phase(
-							&dandelion_config,
-							&tx_pool,
-							&adapter,
-							&verifier_cache,
-						)
-						.map_err(|e| {
-							error!("dand_mon: Problem processing fluff phase. {}", e);
-						});
+						let _ = process_fluff_phase(&dandelion_config, &tx_pool, &adapter).map_err(
+							|e| {
+								error!("dand_mon: Problem processing fluff phase. {}", e);
+							},
+						);
 					}
 
 					// Now find all expired entries based on embargo timer.
@@ -80,9 +75,14 @@ pub fn monitor_transactions(
 					if adapter.is_expired() {
 						adapter.next_epoch();
 					}
-					last_run = Instant::now();
+					last_run = clock::instant();
 				}
 
+				// Our delayed txs (privacy mode) are checked every second.
+				adapter.relay_delayed_txs();
+				// Received stem txs are aggregated within a short window.
+				adapter.relay_stem_batch();
+
 				// Monitor loops every 10s, but check stop flag every second.
 				thread::sleep(Duration::from_secs(1));
 			}
@@ -96,7 +96,7 @@ where
 	B: BlockChain,
 	V: VerifierCache,
 {
-	let cutoff = Utc::now().timestamp() - cutoff_secs as i64;
+	let cutoff = clock::now().timestamp() - cutoff_secs as i64;
 	pool.entries
 		.iter()
 		.filter(|x| x.tx_at.timestamp() < cutoff)
@@ -108,13 +108,11 @@ fn process_fluff_phase(
 	dandelion_config: &DandelionConfig,
 	tx_pool: &ServerTxPool,
 	adapter: &Arc<dyn DandelionAdapter>,
-	verifier_cache: &ServerVerifierCache,
 ) -> Result<(), PoolError> {
 	// Take a write lock on the txpool for the duration of this processing.
 	let mut tx_pool = tx_pool.write();
 
-	let all_entries = tx_pool.stempool.entries.clone();
-	if all_entries.is_empty() {
+	if tx_pool.stempool.entries.is_empty() {
 		return Ok(());
 	}
 
@@ -128,31 +126,8 @@ fn process_fluff_phase(
 		return Ok(());
 	}
 
-	let header = tx_pool.chain_head()?;
-
-	let fluffable_txs = {
-		let txpool_tx = tx_pool.txpool.all_transactions_aggregate(None)?;
-		let txs: Vec<_> = all_entries.into_iter().map(|x| x.tx).collect();
-		tx_pool.stempool.validate_raw_txs(
-			&txs,
-			txpool_tx,
-			&header,
-			transaction::Weighting::NoLimit,
-		)?
-	};
-
-	debug!(
-		"dand_mon: Found {} txs in local stempool to fluff",
-		fluffable_txs.len()
-	);
-
-	let agg_tx = transaction::aggregate(&fluffable_txs)?;
-	agg_tx.validate(
-		transaction::Weighting::AsTransaction,
-		verifier_cache.clone(),
-	)?;
-
-	tx_pool.add_to_pool(TxSource::Fluff, agg_tx, false, &header)?;
+	let fluffed = tx_pool.fluff_stempool()?;
+	debug!("dand_mon: Fluffed {} txs from local stempool", fluffed);
 	Ok(())
 }
 
@@ -0,0 +1,169 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Background builder of the kernels by fee index. The index is built lazily,
+//! block by block, so the fee queries don't need to read the block bodies.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chain::{self, BlockKernelFees, KernelFeeIndexHead, SyncState};
+use crate::core::core::hash::Hashed;
+use crate::store;
+use crate::util::StopState;
+
+/// Number of blocks indexed in a single db transaction
+const INDEX_BATCH_SIZE: u64 = 500;
+/// Indexing progress is reported every this number of blocks
+const PROGRESS_REPORT_BLOCKS: u64 = 10_000;
+
+/// Start the kernel fee index thread. Index is updated every 10 seconds when
+/// the node is not syncing.
+pub fn run_kernel_fee_index(
+	chain: Arc<chain::Chain>,
+	sync_state: Arc<SyncState>,
+	stop_state: Arc<StopState>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+	debug!("Started kernel fee index builder.");
+
+	thread::Builder::new()
+		.name("kernel_fee_index".to_string())
+		.spawn(move || {
+			let run_interval = Duration::from_secs(10);
+			let mut last_run = Instant::now()
+				.checked_sub(Duration::from_secs(20))
+				.unwrap_or_else(Instant::now);
+			loop {
+				if stop_state.is_stopped() {
+					break;
+				}
+
+				if last_run.elapsed() > run_interval && !sync_state.is_syncing() {
+					if let Err(e) = update_index(&chain, &stop_state) {
+						warn!("kernel_fee_index: Unable to update the index, {}", e);
+					}
+					last_run = Instant::now();
+				}
+
+				thread::sleep(Duration::from_secs(1));
+			}
+		})
+}
+
+/// Read the index head, None if index was never built
+fn read_index_head(chain: &chain::Chain) -> Result<Option<KernelFeeIndexHead>, chain::Error> {
+	match chain.store().kernel_fee_index_head() {
+		Ok(head) => Ok(Some(head)),
+		Err(store::Error::NotFoundErr(_)) => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Drop the index entries that belong to the orphaned blocks.
+fn rewind_index(
+	chain: &chain::Chain,
+	mut index_head: Option<KernelFeeIndexHead>,
+) -> Result<Option<KernelFeeIndexHead>, chain::Error> {
+	let head = chain.head()?;
+	let store = chain.store();
+	while let Some(ih) = index_head.clone() {
+		if ih.height <= head.height && chain.get_header_by_height(ih.height)?.hash() == ih.hash {
+			break;
+		}
+		debug!(
+			"kernel_fee_index: rewinding index entry at height {}",
+			ih.height
+		);
+		index_head = if ih.height > ih.start_height {
+			match store.get_block_kernel_fees(ih.height - 1) {
+				Ok(prev) => Some(KernelFeeIndexHead {
+					start_height: ih.start_height,
+					height: prev.height,
+					hash: prev.hash,
+				}),
+				Err(_) => None,
+			}
+		} else {
+			None
+		};
+		let batch = store.batch()?;
+		batch.delete_block_kernel_fees(ih.height)?;
+		if let Some(new_head) = &index_head {
+			batch.save_kernel_fee_index_head(new_head)?;
+		}
+		batch.commit()?;
+	}
+	Ok(index_head)
+}
+
+fn update_index(chain: &chain::Chain, stop_state: &StopState) -> Result<(), chain::Error> {
+	let index_head = rewind_index(chain, read_index_head(chain)?)?;
+
+	let head = chain.head()?;
+	// Non archive node doesn't have the blocks below the tail
+	let tail_height = chain.tail().map(|t| t.height).unwrap_or(0);
+
+	let (start_height, mut height) = match index_head {
+		Some(ih) if ih.height + 1 >= tail_height => (ih.start_height, ih.height + 1),
+		_ => (tail_height, tail_height),
+	};
+	if height > head.height {
+		return Ok(());
+	}
+
+	info!(
+		"kernel_fee_index: indexing blocks {} - {}",
+		height, head.height
+	);
+
+	let store = chain.store();
+	while height <= head.height {
+		if stop_state.is_stopped() {
+			break;
+		}
+
+		let batch = store.batch()?;
+		let last_height = std::cmp::min(height + INDEX_BATCH_SIZE - 1, head.height);
+		let mut index_head = None;
+		for h in height..=last_height {
+			let header = chain.get_header_by_height(h)?;
+			let block = chain.get_block(&header.hash())?;
+			let fees = BlockKernelFees::from_block(&block);
+			batch.save_block_kernel_fees(&fees)?;
+			index_head = Some(KernelFeeIndexHead {
+				start_height,
+				height: h,
+				hash: fees.hash,
+			});
+		}
+		if let Some(index_head) = index_head {
+			batch.save_kernel_fee_index_head(&index_head)?;
+		}
+		batch.commit()?;
+
+		if last_height / PROGRESS_REPORT_BLOCKS != height / PROGRESS_REPORT_BLOCKS
+			|| last_height == head.height
+		{
+			info!(
+				"kernel_fee_index: indexed up to height {} of {} ({}%)",
+				last_height,
+				head.height,
+				(last_height - start_height + 1) * 100 / (head.height - start_height + 1)
+			);
+		}
+		height = last_height + 1;
+	}
+	Ok(())
+}
@@ -0,0 +1,177 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistent metrics snapshots. Peer counts, tip height, mempool size and block
+//! validation timings are stored into the chain db periodically, so the incident
+//! can be investigated later even if the node wasn't hooked up to external monitoring.
+
+use chrono::Utc;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chain::{self, block_profile, BlockTimings, MetricsSnapshot};
+use crate::common::types::MetricsHistoryConfig;
+use crate::p2p;
+use crate::util::StopState;
+use crate::ServerTxPool;
+
+/// Start the thread that takes the metrics snapshots. Snapshots above the history
+/// limit are pruned, oldest first.
+pub fn run_metrics_history(
+	config: MetricsHistoryConfig,
+	chain: Arc<chain::Chain>,
+	peers: Arc<p2p::Peers>,
+	tx_pool: ServerTxPool,
+	stop_state: Arc<StopState>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+	debug!("Started metrics history.");
+
+	thread::Builder::new()
+		.name("metrics_history".to_string())
+		.spawn(move || {
+			let run_interval = Duration::from_secs(config.metrics_snapshot_interval_secs);
+			let mut last_run = Instant::now();
+			let mut last_snapshot_time = Utc::now().timestamp();
+			// Timestamps of the stored snapshots, oldest first
+			let mut history: VecDeque<i64> = match chain.store().metrics_history(None, None) {
+				Ok(snapshots) => snapshots.iter().map(|s| s.timestamp).collect(),
+				Err(e) => {
+					warn!("metrics_history: Unable to read metrics history, {}", e);
+					VecDeque::new()
+				}
+			};
+			prune_history(&chain, &mut history, config.metrics_history_limit);
+
+			loop {
+				if stop_state.is_stopped() {
+					break;
+				}
+
+				if last_run.elapsed() > run_interval {
+					let snapshot = take_snapshot(&chain, &peers, &tx_pool, last_snapshot_time);
+					let res = chain.store().batch().and_then(|batch| {
+						batch.save_metrics_snapshot(&snapshot)?;
+						batch.commit()
+					});
+					match res {
+						Ok(_) => {
+							if history.back() != Some(&snapshot.timestamp) {
+								history.push_back(snapshot.timestamp);
+							}
+							prune_history(&chain, &mut history, config.metrics_history_limit);
+						}
+						Err(e) => warn!("metrics_history: Unable to save metrics snapshot, {}", e),
+					}
+					last_snapshot_time = snapshot.timestamp;
+					last_run = Instant::now();
+				}
+
+				thread::sleep(Duration::from_secs(1));
+			}
+		})
+}
+
+fn take_snapshot(
+	chain: &chain::Chain,
+	peers: &p2p::Peers,
+	tx_pool: &ServerTxPool,
+	last_snapshot_time: i64,
+) -> MetricsSnapshot {
+	let mut snapshot = MetricsSnapshot {
+		timestamp: Utc::now().timestamp(),
+		..MetricsSnapshot::default()
+	};
+
+	if let Ok(head) = chain.head() {
+		snapshot.height = head.height;
+	}
+	if let Ok(header_head) = chain.header_head() {
+		snapshot.header_height = header_head.height;
+	}
+
+	let connected = peers.connected_peers();
+	snapshot.peers = connected.len() as u32;
+	snapshot.inbound_peers = connected.iter().filter(|p| p.info.is_inbound()).count() as u32;
+	snapshot.outbound_peers = connected.iter().filter(|p| p.info.is_outbound()).count() as u32;
+
+	// Snapshot should not wait for the pool, skip the pool sizes if it is busy
+	if let Some(pool) = tx_pool.try_read_for(Duration::from_millis(500)) {
+		snapshot.txpool_size = pool.txpool.size() as u32;
+		snapshot.stempool_size = pool.stempool.size() as u32;
+	}
+
+	let blocks = block_profile::last_blocks(block_profile::BLOCK_PROFILE_HISTORY);
+	let (count, avg, max) = block_validation_stats(&blocks, last_snapshot_time);
+	snapshot.blocks_validated = count;
+	snapshot.avg_block_validation_us = avg;
+	snapshot.max_block_validation_us = max;
+
+	snapshot
+}
+
+/// Number, average and max processing time of the blocks processed after the given time
+fn block_validation_stats(blocks: &[BlockTimings], since: i64) -> (u32, u64, u64) {
+	let totals: Vec<u64> = blocks
+		.iter()
+		.filter(|b| b.timestamp > since)
+		.map(|b| b.total)
+		.collect();
+	if totals.is_empty() {
+		return (0, 0, 0);
+	}
+	let sum: u64 = totals.iter().sum();
+	let max = totals.iter().max().cloned().unwrap_or(0);
+	(totals.len() as u32, sum / totals.len() as u64, max)
+}
+
+fn prune_history(chain: &chain::Chain, history: &mut VecDeque<i64>, limit: usize) {
+	if history.len() <= limit {
+		return;
+	}
+	let pruned: Vec<i64> = history.drain(..history.len() - limit).collect();
+	let res = chain.store().batch().and_then(|batch| {
+		for timestamp in &pruned {
+			batch.delete_metrics_snapshot(*timestamp)?;
+		}
+		batch.commit()
+	});
+	if let Err(e) = res {
+		warn!("metrics_history: Unable to prune metrics history, {}", e);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn timings(timestamp: i64, total: u64) -> BlockTimings {
+		BlockTimings {
+			timestamp,
+			total,
+			..BlockTimings::default()
+		}
+	}
+
+	#[test]
+	fn test_block_validation_stats() {
+		assert_eq!(block_validation_stats(&[], 100), (0, 0, 0));
+
+		let blocks = vec![timings(130, 3000), timings(120, 1000), timings(100, 9000)];
+		assert_eq!(block_validation_stats(&blocks, 100), (2, 2000, 3000));
+		assert_eq!(block_validation_stats(&blocks, 50), (3, 4333, 9000));
+		assert_eq!(block_validation_stats(&blocks, 130), (0, 0, 0));
+	}
+}
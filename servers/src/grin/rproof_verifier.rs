@@ -0,0 +1,92 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Background verification of the rangeproofs skipped by the spot-check state validation
+//! (reduced validation mode for the low power devices). Rangeproofs are verified in small
+//! chunks when the node is not syncing, so block processing is not delayed much.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::chain::{self, SyncState};
+use crate::core::global;
+use crate::util::StopState;
+
+/// Number of the rangeproofs verified under a single txhashset lock
+const VERIFY_CHUNK_SIZE: usize = 1_000;
+/// Pause between the chunks, the device is expected to be slow
+const CHUNK_PAUSE: Duration = Duration::from_millis(200);
+/// Progress is reported every this number of the chunks
+const PROGRESS_REPORT_CHUNKS: u64 = 50;
+
+/// Start the deferred rangeproofs verification thread.
+pub fn run_rproof_verifier(
+	chain: Arc<chain::Chain>,
+	sync_state: Arc<SyncState>,
+	stop_state: Arc<StopState>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+	debug!("Started deferred rangeproofs verifier.");
+
+	thread::Builder::new()
+		.name("rproof_verifier".to_string())
+		.spawn(move || {
+			let mut chunks = 0;
+			loop {
+				if stop_state.is_stopped() {
+					break;
+				}
+				if sync_state.is_syncing() {
+					thread::sleep(Duration::from_secs(1));
+					continue;
+				}
+
+				match chain.verify_deferred_rangeproofs(VERIFY_CHUNK_SIZE) {
+					Ok(Some(deferred)) => {
+						chunks += 1;
+						if chunks % PROGRESS_REPORT_CHUNKS == 1 {
+							info!(
+								"rproof_verifier: verifying the deferred rangeproofs, output pmmr position {} of {}",
+								deferred.next_pos, deferred.last_pos
+							);
+						}
+						thread::sleep(CHUNK_PAUSE);
+					}
+					Ok(None) => {
+						if chunks > 0 {
+							info!("rproof_verifier: all deferred rangeproofs are verified, the chain state is fully validated");
+							chunks = 0;
+						}
+						thread::sleep(Duration::from_secs(10));
+					}
+					Err(e) => {
+						if chain::rproof_spot_check::is_verification_failed() {
+							error!(
+								"rproof_verifier: INVALID RANGEPROOF in the chain state that was accepted with the spot-check validation, {}. The node state can't be trusted, delete the chain data and sync again with the full validation! Stopping the node...",
+								e
+							);
+							// The failure is saved, the chain is not opened again with this state
+							global::request_server_stop();
+							break;
+						}
+						warn!(
+							"rproof_verifier: Unable to verify the deferred rangeproofs, {}",
+							e
+						);
+						thread::sleep(Duration::from_secs(10));
+					}
+				}
+			}
+		})
+}
@@ -33,8 +33,8 @@ use crate::p2p;
 #[cfg(feature = "marketplace")]
 use crate::p2p::libp2p_connection;
 use crate::p2p::types::PeerAddr;
-use crate::p2p::ChainAdapter;
-use crate::util::StopState;
+use crate::p2p::{dial_scheduler, ChainAdapter, DialLimits, DialPriority};
+use crate::util::{clock, StopState};
 
 pub fn connect_and_monitor(
 	p2p_server: Arc<p2p::Server>,
@@ -57,6 +57,8 @@ pub fn connect_and_monitor(
 			let (tx, rx) = mpsc::channel();
 			let seed_list = seed_list();
 
+			dial_scheduler().set_limits(DialLimits::from_config(&p2p_server.config));
+
 			// check seeds first
 			connect_to_seeds_and_preferred_peers(
 				peers.clone(),
@@ -70,7 +72,7 @@ pub fn connect_and_monitor(
 
 			let mut prev = MIN_DATE.and_hms(0, 0, 0);
 			let mut prev_expire_check = MIN_DATE.and_hms(0, 0, 0);
-			let mut prev_ping = Utc::now();
+			let mut prev_ping = clock::now();
 			let mut start_attempt = 0;
 			let mut connecting_history: HashMap<PeerAddr, DateTime<Utc>> = HashMap::new();
 			loop {
@@ -90,6 +92,15 @@ pub fn connect_and_monitor(
 					peers.connected_peers().len()
 				};
 
+				// start the queued dials if there are free dial slots
+				dispatch_dials(
+					peers.clone(),
+					p2p_server.clone(),
+					capabilities,
+					&mut connecting_history,
+					header_cache_size,
+				);
+
 				if connected_peers == 0 {
 					info!("No peers connected, trying to reconnect to seeds!");
 					connect_to_seeds_and_preferred_peers(
@@ -105,26 +116,24 @@ pub fn connect_and_monitor(
 				}
 
 				// Check for and remove expired peers from the storage
-				if peer_count > 0 && Utc::now() - prev_expire_check > Duration::hours(1) {
+				if peer_count > 0 && clock::now() - prev_expire_check > Duration::hours(1) {
 					peers.remove_expired();
 
-					prev_expire_check = Utc::now();
+					prev_expire_check = clock::now();
 				}
 
 				// make several attempts to get peers as quick as possible
 				// with exponential backoff
-				if Utc::now() - prev > Duration::seconds(cmp::min(20, 1 << start_attempt)) {
+				if clock::now() - prev > Duration::seconds(cmp::min(20, 1 << start_attempt)) {
 					// try to connect to any address sent to the channel
 					listen_for_addrs(
 						peers.clone(),
-						p2p_server.clone(),
-						capabilities,
 						&rx,
-						&mut connecting_history,
-						header_cache_size,
+						&preferred_peers,
+						&seed_list,
 						connect_all,
 					);
-					prev = Utc::now();
+					prev = clock::now();
 					start_attempt = cmp::min(6, start_attempt + 1);
 
 					if peer_count != 0 && connected_peers != 0 {
@@ -155,12 +164,12 @@ pub fn connect_and_monitor(
 				}
 
 				// Ping connected peers on every 10s to monitor peers.
-				if Utc::now() - prev_ping > Duration::seconds(10) {
+				if clock::now() - prev_ping > Duration::seconds(10) {
 					let total_diff = peers.total_difficulty();
 					let total_height = peers.total_height();
 					if let (Ok(total_diff), Ok(total_height)) = (total_diff, total_height) {
 						peers.check_all(total_diff, total_height);
-						prev_ping = Utc::now();
+						prev_ping = clock::now();
 					} else {
 						error!("failed to get peers difficulty and/or height");
 					}
@@ -187,7 +196,7 @@ fn monitor_peers(
 	for x in peers.all_peers() {
 		match x.flags {
 			p2p::State::Banned => {
-				let interval = Utc::now().timestamp() - x.last_banned;
+				let interval = clock::now().timestamp() - x.last_banned;
 				// Unban peer
 				if interval >= config.ban_window() {
 					if let Err(e) = peers.unban_peer(x.addr.clone()) {
@@ -219,6 +228,10 @@ fn monitor_peers(
 		defuncts.len(),
 	);
 
+	if let Err(e) = peers.update_libp2p_bans() {
+		error!("failed to update libp2p peer bans: {:?}", e);
+	}
+
 	// maintenance step first, clean up p2p server peers
 	peers.clean_peers(
 		config.peer_max_inbound_count() as usize,
@@ -316,16 +329,14 @@ fn connect_to_seeds_and_preferred_peers(
 	}
 }
 
-/// Regularly poll a channel receiver for new addresses and initiate a
-/// connection if the max peer count isn't exceeded. A request for more
-/// peers is also automatically sent after connection.
+/// Regularly poll a channel receiver for new addresses and queue them for
+/// a connection if the max peer count isn't exceeded. Dials are started by
+/// `dispatch_dials` within the dial scheduler limits.
 fn listen_for_addrs(
 	peers: Arc<p2p::Peers>,
-	p2p: Arc<p2p::Server>,
-	capab: p2p::Capabilities,
 	rx: &mpsc::Receiver<PeerAddr>,
-	connecting_history: &mut HashMap<PeerAddr, DateTime<Utc>>,
-	header_cache_size: u64,
+	preferred_peers: &[PeerAddr],
+	seed_list: &[PeerAddr],
 	attempt_all: bool,
 ) {
 	// Pull everything currently on the queue off the queue.
@@ -351,11 +362,73 @@ fn listen_for_addrs(
 	}
 	// Note: We drained the rx queue earlier to keep it under control.
 	// Even if there are many addresses to try we will only try a bounded number of them for safety.
-	let connect_min_interval = 30;
 	let max_outbound_attempts = 128;
-	for addr in addrs.into_iter().take(max_outbound_attempts) {
+	let now = clock::now().timestamp();
+	let addrs: Vec<(PeerAddr, DialPriority)> = addrs
+		.into_iter()
+		.take(max_outbound_attempts)
+		.map(|addr| {
+			let priority = dial_priority(&peers, &addr, preferred_peers, seed_list, now);
+			(addr, priority)
+		})
+		.collect();
+	let mut scheduler = dial_scheduler();
+	for (addr, priority) in addrs {
+		scheduler.schedule(addr, priority);
+	}
+}
+
+/// Peers that we were connected to within this period before the restart are dialed first
+const ANCHOR_PEER_PERIOD_SECS: i64 = 24 * 3600;
+
+fn dial_priority(
+	peers: &p2p::Peers,
+	addr: &PeerAddr,
+	preferred_peers: &[PeerAddr],
+	seed_list: &[PeerAddr],
+	now: i64,
+) -> DialPriority {
+	if preferred_peers.contains(addr) {
+		return DialPriority::Anchor;
+	}
+	match peers.get_peer(addr.clone()) {
+		// Known capabilities means that we had a successful handshake with the peer
+		Ok(p) if p.flags == p2p::State::Healthy && p.capabilities != p2p::Capabilities::UNKNOWN => {
+			if now - p.last_connected < ANCHOR_PEER_PERIOD_SECS {
+				DialPriority::Anchor
+			} else {
+				DialPriority::Reputable
+			}
+		}
+		_ => {
+			if seed_list.contains(addr) {
+				DialPriority::Reputable
+			} else {
+				DialPriority::New
+			}
+		}
+	}
+}
+
+/// Start the connections to the queued addresses, as many as the dial scheduler
+/// allows. A request for more peers is automatically sent after connection.
+fn dispatch_dials(
+	peers: Arc<p2p::Peers>,
+	p2p: Arc<p2p::Server>,
+	capab: p2p::Capabilities,
+	connecting_history: &mut HashMap<PeerAddr, DateTime<Utc>>,
+	header_cache_size: u64,
+) {
+	if peers.enough_outbound_peers() {
+		dial_scheduler().clear_queue();
+		return;
+	}
+
+	let connect_min_interval = 30;
+	let addrs = dial_scheduler().next_dials();
+	for addr in addrs {
 		// ignore the duplicate connecting to same peer within 30 seconds
-		let now = Utc::now();
+		let now = clock::now();
 		if let Some(last_connect_time) = connecting_history.get(&addr) {
 			if *last_connect_time + Duration::seconds(connect_min_interval) > now {
 				debug!(
@@ -363,6 +436,7 @@ fn listen_for_addrs(
 					addr,
 					last_connect_time.format("%H:%M:%S%.3f").to_string(),
 				);
+				dial_scheduler().release(&addr.as_key());
 				continue;
 			} else if let Some(history) = connecting_history.get_mut(&addr) {
 				*history = now;
@@ -401,15 +475,16 @@ fn listen_for_addrs(
 									}
 									_ => (),
 								};
-								let _ = peers_c.update_state(addr, p2p::State::Healthy);
+								let _ = peers_c.update_state(addr.clone(), p2p::State::Healthy);
 							}
 						}
 						Err(e) => {
 							debug!("Connection to the peer {} was rejected, {}", addr, e);
-							let _ = peers_c.update_state(addr, p2p::State::Defunct);
+							let _ = peers_c.update_state(addr.clone(), p2p::State::Defunct);
 						}
 					}
 				}
+				dial_scheduler().release(&addr.as_key());
 			})
 			.expect("failed to launch peer_connect thread");
 	}
@@ -417,7 +492,7 @@ fn listen_for_addrs(
 	// shrink the connecting history.
 	// put a threshold here to avoid frequent shrinking in every call
 	if connecting_history.len() > 100 {
-		let now = Utc::now();
+		let now = clock::now();
 		let old: Vec<_> = connecting_history
 			.iter()
 			.filter(|&(_, t)| *t + Duration::seconds(connect_min_interval) < now)
@@ -21,6 +21,7 @@ use crate::util::secp;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::path::{Path, MAIN_SEPARATOR};
 use std::sync::mpsc;
@@ -44,26 +45,38 @@ use crate::chain::{self, SyncState, SyncStatus};
 use crate::common::adapters::{
 	ChainToPoolAndNetAdapter, NetToChainAdapter, PoolToChainAdapter, PoolToNetAdapter,
 };
-use crate::common::hooks::{init_chain_hooks, init_net_hooks};
+use crate::common::hooks::{
+	init_alert_hooks, init_chain_hooks, init_net_hooks, init_pool_hooks, validate_webhook_config,
+};
 use crate::common::stats::{
 	ChainStats, DiffBlock, DiffStats, PeerStats, ServerStateInfo, ServerStats, TxStats,
 };
 
-use crate::common::types::{Error, ServerConfig, StratumServerConfig};
+use crate::common::types::{
+	CanaryNodeState, Error, ServerConfig, StratumServerConfig, TipLagAlert,
+};
 use crate::core::core::hash::Hashed;
 use crate::core::core::verifier_cache::LruVerifierCache;
 use crate::core::ser::ProtocolVersion;
 use crate::core::stratum::connections;
 use crate::core::{consensus, genesis, global, pow};
-use crate::grin::{dandelion_monitor, seed, sync};
+use crate::grin::uptime::UptimeTracker;
+use crate::grin::{
+	consensus_canary, dandelion_monitor, kernel_fee_index, metrics_history, rproof_verifier, seed,
+	standby_monitor, subsystem_usage, sync, tip_lag_monitor,
+};
 use crate::mining::stratumserver;
 use crate::mining::test_miner::Miner;
 use crate::p2p;
+use crate::p2p::handoff;
 use crate::p2p::types::PeerAddr;
 use crate::pool;
+use crate::tor::pow_defense::PowDefense;
 use crate::tor::process as tor_process;
 use crate::util::file::get_first_line;
+use crate::util::subsystem::{self, SUBSYSTEM_LIBP2P, SUBSYSTEM_STRATUM, SUBSYSTEM_TOR};
 use crate::util::{RwLock, StopState};
+use chrono::Utc;
 use futures::channel::oneshot;
 use grin_util::logger::LogEntry;
 use grin_util::secp::SecretKey;
@@ -73,7 +86,7 @@ use std::sync::atomic::Ordering;
 #[cfg(feature = "marketplace")]
 use crate::p2p::libp2p_connection;
 #[cfg(feature = "marketplace")]
-use chrono::Utc;
+use crate::p2p::Libp2pIdentity;
 #[cfg(feature = "marketplace")]
 use grin_core::core::TxKernel;
 #[cfg(feature = "marketplace")]
@@ -83,8 +96,17 @@ use grin_util::secp::constants::SECRET_KEY_SIZE;
 #[cfg(feature = "marketplace")]
 use grin_util::secp::pedersen::Commitment;
 #[cfg(feature = "marketplace")]
+use rand::Rng;
+#[cfg(feature = "marketplace")]
 use std::collections::HashMap;
 
+/// Standalone identity key of the libp2p node, hex. Used without Tor or if configured.
+#[cfg(feature = "marketplace")]
+const LIBP2P_IDENTITY_FILE: &str = "libp2p_identity";
+
+/// Time to wait for the previous node to release the db lock on takeover
+const HANDOFF_LOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
 /// Arcified  thread-safe TransactionPool with type parameters used by server components
 pub type ServerTxPool =
 	Arc<RwLock<pool::TransactionPool<PoolToChainAdapter, PoolToNetAdapter, LruVerifierCache>>>;
@@ -110,11 +132,25 @@ pub struct Server {
 	state_info: ServerStateInfo,
 	/// Stop flag
 	pub stop_state: Arc<StopState>,
+	/// Node uptime and sync stages tracking
+	uptime: Arc<UptimeTracker>,
 	/// Maintain a lock_file so we do not run multiple Grin nodes from same dir.
 	lock_file: Arc<File>,
 	connect_thread: Option<JoinHandle<()>>,
-	sync_thread: JoinHandle<()>,
+	sync_thread: Option<JoinHandle<()>>,
 	dandelion_thread: JoinHandle<()>,
+	kernel_fee_index_thread: Option<JoinHandle<()>>,
+	rproof_verifier_thread: Option<JoinHandle<()>>,
+	/// Current chain tip lag state, None if tip lag monitor is disabled
+	tip_lag_alert: Arc<RwLock<Option<TipLagAlert>>>,
+	tip_lag_thread: Option<JoinHandle<()>>,
+	/// Consensus canary state of the remote nodes, empty if the canary is disabled
+	consensus_canary: Arc<RwLock<Vec<CanaryNodeState>>>,
+	consensus_canary_thread: Option<JoinHandle<()>>,
+	standby_thread: Option<JoinHandle<()>>,
+	metrics_history_thread: Option<JoinHandle<()>>,
+	/// libp2p node thread with its stop flag, the node is stopped when the flag is 0
+	libp2p_thread: Option<(JoinHandle<()>, Arc<std::sync::Mutex<u32>>)>,
 }
 
 impl Server {
@@ -139,7 +175,17 @@ impl Server {
 		}
 
 		grin_chain::pipe::init_invalid_lock_hashes(&config.invalid_block_hashes)?;
+		grin_chain::pipe::init_max_reorg_depth(config.max_reorg_depth);
+		if let Some(depth) = config.max_reorg_depth.filter(|d| *d > 0) {
+			info!("config.max_reorg_depth = {}", depth);
+		}
+		grin_chain::pow_batch::init_cpu_limit(config.sync_pow_cpu_limit);
+		grin_chain::rproof_spot_check::init_sample_percent(config.rangeproof_spot_check_percent);
+
+		let mut config = config;
+		apply_cold_storage(&mut config)?;
 
+		let stealth_mode = config.stealth_mode.unwrap_or(false);
 		let mining_config = config.stratum_mining_config.clone();
 		let enable_test_miner = config.run_test_miner;
 		let test_miner_wallet_url = config.test_miner_wallet_url.clone();
@@ -168,7 +214,13 @@ impl Server {
 		if let Some(c) = mining_config {
 			let enable_stratum_server = c.enable_stratum_server;
 			if let Some(s) = enable_stratum_server {
-				if s {
+				let stratum_addr = c.stratum_server_addr.clone().unwrap_or_default();
+				if s && stealth_mode && !is_loopback_addr(&stratum_addr) {
+					warn!(
+						"Stratum server is not started in stealth mode, it listens on the external address {}",
+						stratum_addr
+					);
+				} else if s {
 					{
 						serv.state_info
 							.stratum_stats
@@ -202,6 +254,15 @@ impl Server {
 			.write(true)
 			.create(true)
 			.open(&path)?;
+		// On takeover the old node releases the lock once it is stopped
+		if handoff::has_pending_state() {
+			let started = time::Instant::now();
+			while lock_file.try_lock_exclusive().is_err()
+				&& started.elapsed() < HANDOFF_LOCK_TIMEOUT
+			{
+				thread::sleep(Duration::from_millis(100));
+			}
+		}
 		lock_file.try_lock_exclusive().map_err(|e| {
 			let mut stderr = std::io::stderr();
 			writeln!(
@@ -215,6 +276,79 @@ impl Server {
 		Ok(Arc::new(lock_file))
 	}
 
+	/// Take over from the running node of the previous version: bind the listeners of
+	/// this node next to the ones of the running node and request its state. The running
+	/// node exits once the state is received, the state is applied when this node starts.
+	/// The running node must be started with the `upgrade_handoff` config option.
+	pub fn prepare_takeover(config: &ServerConfig) -> Result<(), Error> {
+		let mut addrs = vec![];
+		if !config.stealth_mode.unwrap_or(false) {
+			addrs.push(SocketAddr::new(
+				config.p2p_config.host,
+				config.p2p_config.port,
+			));
+		}
+		addrs.push(
+			config
+				.api_http_addr
+				.parse()
+				.map_err(|e| Error::Configuration(format!("Invalid api_http_addr, {}", e)))?,
+		);
+		if let Some(c) = &config.stratum_mining_config {
+			if c.enable_stratum_server.unwrap_or(false) {
+				if let Some(addr) = &c.stratum_server_addr {
+					addrs.push(addr.parse().map_err(|e| {
+						Error::Configuration(format!("Invalid stratum_server_addr, {}", e))
+					})?);
+				}
+			}
+		}
+		for addr in addrs {
+			handoff::prebind(addr).map_err(|e| {
+				Error::Configuration(format!(
+					"Unable to bind {} next to the running node, is upgrade_handoff enabled for it? {}",
+					addr, e
+				))
+			})?;
+		}
+
+		let state = handoff::request_handoff(&handoff::handoff_socket_path(&config.db_root))?;
+		warn!(
+			"Taking over from the running node, received {} peers and {} transactions",
+			state.peers.len(),
+			state.txs.len()
+		);
+		handoff::set_pending_state(state);
+		Ok(())
+	}
+
+	// Apply the state received from the previous node on takeover
+	fn apply_handoff_state(
+		chain: &chain::Chain,
+		peers: &p2p::Peers,
+		tx_pool: &ServerTxPool,
+	) -> Result<(), Error> {
+		let state = match handoff::take_pending_state() {
+			Some(state) => state,
+			None => return Ok(()),
+		};
+		let res = peers.import_peers(&state.peers)?;
+		let header = chain.head_header()?;
+		let mut accepted = 0;
+		let mut tx_pool = tx_pool.write();
+		for tx in state.txs {
+			match tx_pool.add_to_pool(pool::TxSource::Handoff, tx, false, &header) {
+				Ok(_) => accepted += 1,
+				Err(e) => debug!("Handoff transaction is not accepted, {}", e),
+			}
+		}
+		info!(
+			"Handoff state applied, {} peers imported, {} skipped, {} transactions accepted",
+			res.imported, res.skipped, accepted
+		);
+		Ok(())
+	}
+
 	// We don't want allow_to_stop in config because it is too dangerous flag. We don't
 	// want to forget about that, make default e.t.c. That is why it is separated
 
@@ -226,10 +360,32 @@ impl Server {
 		stop_state: Option<Arc<StopState>>,
 		api_chan: &'static mut (oneshot::Sender<()>, oneshot::Receiver<()>),
 	) -> Result<Server, Error> {
+		let mut config = config;
+		apply_cold_storage(&mut config)?;
+		let cold_storage = config.cold_storage_config.cold_storage_mode;
+
 		let header_cache_size = config.header_cache_size.unwrap_or(25_000);
 		//let duration_sync_long = config.duration_sync_long.unwrap_or(150);
 		//let duration_sync_short = config.duration_sync_short.unwrap_or(100);
 
+		if config.upgrade_handoff.unwrap_or(false) {
+			handoff::set_reuse_port(true);
+		}
+
+		// Outbound HTTP requests are routed by these settings
+		let onion_only = config.outbound_onion_only.unwrap_or(false);
+		let proxy = match &config.outbound_http_proxy {
+			Some(url) if !url.is_empty() => Some(api::OutboundProxy::parse(url)?),
+			_ if onion_only && config.tor_config.tor_enabled => Some(api::OutboundProxy::Socks5(
+				format!("127.0.0.1:{}", config.tor_config.socks_port),
+			)),
+			_ => None,
+		};
+		if onion_only && proxy.is_none() {
+			warn!("Onion only mode without a proxy, outbound HTTP requests are refused");
+		}
+		api::set_outbound_http(api::OutboundHttpConfig { proxy, onion_only });
+
 		// Obtain our lock_file or fail immediately with an error.
 		let lock_file = Server::one_grin_at_a_time(&config).map_err(|e| {
 			error!(
@@ -252,24 +408,68 @@ impl Server {
 			Arc::new(StopState::new())
 		};
 
+		validate_webhook_config(&config.webhook_config)?;
+
+		if config.standby_config.standby_mode {
+			warn!("Node is starting in standby mode");
+			api::init_standby(config.standby_config.primary_url.clone());
+		}
+
+		// Chain data of the cold storage node is the snapshot, the rest stays in db_root
+		let chain_root = match &config.cold_storage_config.snapshot_dir {
+			Some(snapshot_dir) if cold_storage => {
+				warn!(
+					"Node is starting in cold storage mode, snapshot {}",
+					snapshot_dir
+				);
+				api::init_cold_storage(snapshot_dir);
+				snapshot_dir.clone()
+			}
+			_ => config.db_root.clone(),
+		};
+
 		// Shared cache for verification results.
 		// We cache rangeproof verification and kernel signature verification.
 		let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
 
 		let pool_adapter = Arc::new(PoolToChainAdapter::new());
-		let pool_net_adapter = Arc::new(PoolToNetAdapter::new(config.dandelion_config.clone()));
+		let pool_events = Arc::new(api::TxPoolEventsHub::new());
+		let pool_net_adapter = Arc::new(PoolToNetAdapter::new(
+			config.dandelion_config.clone(),
+			init_pool_hooks(pool_events.clone()),
+		));
 		let tx_pool = Arc::new(RwLock::new(pool::TransactionPool::new(
 			config.pool_config.clone(),
 			pool_adapter.clone(),
 			verifier_cache.clone(),
 			pool_net_adapter.clone(),
 		)));
+		pool_net_adapter.set_relay_policy(tx_pool.read().relay_policy.clone());
 
 		let sync_state = Arc::new(SyncState::new());
 
+		let kernel_watch = api::kernel_watch_hub();
+		kernel_watch.set_default_depth(config.webhook_config.kernel_watch_depth);
+		if config.webhook_config.event_journal_max_entries > 0 {
+			let journal = api::EventJournal::new(
+				&config.db_root,
+				config.webhook_config.event_journal_max_entries,
+			)
+			.and_then(|journal| {
+				pool_events.set_journal(journal.stream(api::EventStream::TxPool)?);
+				kernel_watch.set_journal(journal.stream(api::EventStream::KernelWatch)?);
+				Ok(())
+			});
+			if let Err(e) = journal {
+				error!(
+					"Unable to open the event journal, events can't be resumed, {}",
+					e
+				);
+			}
+		}
 		let chain_adapter = Arc::new(ChainToPoolAndNetAdapter::new(
 			tx_pool.clone(),
-			init_chain_hooks(&config),
+			init_chain_hooks(&config, kernel_watch),
 		));
 
 		let genesis = match config.chain_type {
@@ -281,17 +481,53 @@ impl Server {
 
 		info!("Starting server, genesis block: {}", genesis.hash());
 
-		let shared_chain = Arc::new(chain::Chain::init(
-			config.db_root.clone(),
-			chain_adapter.clone(),
-			genesis.clone(),
-			pow::verify_size,
-			verifier_cache.clone(),
-			archive_mode,
-		)?);
+		// The snapshot is never modified, it is not repaired if it is inconsistent
+		let shared_chain = if cold_storage {
+			Arc::new(
+				chain::Chain::init_read_only(
+					chain_root,
+					chain_adapter.clone(),
+					genesis.clone(),
+					pow::verify_size,
+					verifier_cache.clone(),
+					archive_mode,
+				)
+				.map_err(|e| {
+					Error::Configuration(format!(
+						"Snapshot can't be served without modification, {}",
+						e
+					))
+				})?,
+			)
+		} else {
+			Arc::new(chain::Chain::init(
+				chain_root,
+				chain_adapter.clone(),
+				genesis.clone(),
+				pow::verify_size,
+				verifier_cache.clone(),
+				archive_mode,
+			)?)
+		};
 
 		pool_adapter.set_chain(shared_chain.clone());
 
+		if config.utxo_set_digest.unwrap_or(false) {
+			match shared_chain.enable_utxo_digest() {
+				Ok(digest) => info!(
+					"UTXO set digest at {} is {}, {} outputs",
+					digest.height, digest.digest, digest.utxo_count
+				),
+				Err(e) => error!("Unable to compute the UTXO set digest, {}", e),
+			}
+		}
+
+		let uptime = if cold_storage {
+			Arc::new(UptimeTracker::start_in_memory(shared_chain.clone()))
+		} else {
+			Arc::new(UptimeTracker::start(shared_chain.clone()))
+		};
+
 		let net_adapter = Arc::new(NetToChainAdapter::new(
 			sync_state.clone(),
 			shared_chain.clone(),
@@ -305,10 +541,42 @@ impl Server {
 		// the problem of old config files
 		// only for capabilities params, doesn't mean
 		// tor _MUST_ be on.
-		let capab = config.p2p_config.capabilities | p2p::Capabilities::TOR_ADDRESS;
+		// Same for the header first block announcements, old config files don't have it.
+		let mut capab = config.p2p_config.capabilities
+			| p2p::Capabilities::TOR_ADDRESS
+			| p2p::Capabilities::HEADER_FIRST;
+		if archive_mode {
+			capab |= p2p::Capabilities::ARCHIVE;
+		}
+		let capab = config.p2p_config.advertised_capabilities(capab);
+
+		if let Some(user_agent) = &config.p2p_config.user_agent {
+			p2p::msg::validate_user_agent(user_agent).map_err(|e| {
+				Error::Configuration(format!("Invalid p2p user_agent '{}', {}", user_agent, e))
+			})?;
+		}
+		if config.p2p_config.minimize_fingerprint() {
+			info!(
+				"Handshake fingerprint is minimized, advertised user agent: {}",
+				config.p2p_config.user_agent()
+			);
+		}
 
 		api::reset_server_onion_address();
 
+		// Stealth mode, node maintains the outbound connections only. No p2p listener,
+		// no onion service and the API is reachable from this host only.
+		let stealth_mode = config.stealth_mode.unwrap_or(false);
+		if stealth_mode {
+			if !is_loopback_addr(&config.api_http_addr) {
+				return Err(Error::Configuration(format!(
+					"In stealth mode api_http_addr must be a loopback address, found {}",
+					config.api_http_addr
+				)));
+			}
+			warn!("Stealth mode, no inbound listeners are started");
+		}
+
 		let (onion_address, tor_secret) = if config.tor_config.tor_enabled {
 			if !config.p2p_config.host.is_loopback() {
 				error!("If Tor is enabled, host must be '127.0.0.1'.");
@@ -317,10 +585,51 @@ impl Server {
 				));
 			}
 
-			if !config.tor_config.tor_external {
+			if stealth_mode {
+				// Tor is needed for the outbound connections only
+				if !config.tor_config.tor_external {
+					println!("Starting TOR, please wait...");
+					let process = Server::init_tor_client(
+						Some(&config.db_root),
+						config.tor_config.socks_port,
+					)?;
+					let stop_state_clone = stop_state.clone();
+					thread::Builder::new()
+						.name("tor_client".to_string())
+						.spawn(move || {
+							let _usage = subsystem::track_thread(SUBSYSTEM_TOR);
+							let _process = process;
+							while !stop_state_clone.is_stopped() {
+								std::thread::sleep(std::time::Duration::from_millis(100));
+							}
+						})?;
+					info!("Tor client is started, no onion service is published");
+				}
+				(None, None)
+			} else if !config.tor_config.tor_external {
 				let stop_state_clone = stop_state.clone();
 				let cloned_config = config.clone();
 
+				let wallet_listener_ports = match &config.tor_config.wallet_listener_ports {
+					Some(ports) => tor_config::parse_wallet_listener_ports(ports)
+						.map_err(|e| Error::Configuration(format!("{}", e)))?,
+					None => vec![],
+				};
+				for (port, listener) in &wallet_listener_ports {
+					info!(
+						"Wallet listener {} is proxied by onion service port {}",
+						listener, port
+					);
+				}
+
+				let pow_config = &config.tor_config;
+				let pow_queue = if pow_config.pow_defenses_enabled {
+					Some((pow_config.pow_queue_rate, pow_config.pow_queue_burst))
+				} else {
+					None
+				};
+				let pow_flood_threshold = pow_config.pow_flood_threshold;
+
 				let (input, output): (Sender<Option<String>>, Receiver<Option<String>>) =
 					mpsc::channel();
 
@@ -329,6 +638,7 @@ impl Server {
 				thread::Builder::new()
 					.name("tor_listener".to_string())
 					.spawn(move || {
+						let _usage = subsystem::track_thread(SUBSYSTEM_TOR);
 						let res = Server::init_tor_listener(
 							&format!(
 								"{}:{}",
@@ -336,8 +646,10 @@ impl Server {
 							),
 							&cloned_config.api_http_addr,
 							cloned_config.libp2p_port.unwrap_or(3417),
+							&wallet_listener_ports,
 							Some(&cloned_config.db_root),
 							cloned_config.tor_config.socks_port,
+							pow_queue,
 						);
 
 						let _ = match res {
@@ -348,11 +660,26 @@ impl Server {
 									.unwrap();
 								input.send(Some(to_hex(&secret.0))).unwrap();
 
+								let mut pow_defense = pow_queue.map(|(rate, burst)| {
+									PowDefense::new(
+										rate,
+										burst,
+										pow_flood_threshold,
+										p2p::loopback_inbound_count(),
+									)
+								});
+								if let Some(ref defense) = pow_defense {
+									api::set_tor_pow_defense(defense.status());
+								}
+
 								loop {
 									std::thread::sleep(std::time::Duration::from_millis(10));
 									if stop_state_clone.is_stopped() {
 										break;
 									}
+									if let Some(ref mut defense) = pow_defense {
+										Server::update_tor_pow_defense(defense, &listener);
+									}
 								}
 								Ok(listener)
 							}
@@ -410,20 +737,45 @@ impl Server {
 			socks_port, config.tor_config.tor_enabled
 		);
 
+		#[cfg_attr(not(feature = "marketplace"), allow(unused_mut))]
+		let mut libp2p_thread = None;
 		#[cfg(feature = "marketplace")]
 		// Initialize libp2p server
-		if config.libp2p_enabled.unwrap_or(true) && onion_address.is_some() && tor_secret.is_some()
-		{
-			let onion_address = onion_address.clone().unwrap();
-			let tor_secret = tor_secret.unwrap();
-			let tor_secret = from_hex(&tor_secret).map_err(|e| {
-				Error::General(format!("Unable to parse secret hex {}, {}", tor_secret, e))
-			})?;
-
+		if let Some(libp2p_secrets) = libp2p_secrets(&config, &onion_address, &tor_secret)? {
 			let libp2p_port = config.libp2p_port;
 			let tor_socks_port = config.tor_config.socks_port;
 			let fee_base = config.pool_config.accept_fee_base;
-			api::set_server_onion_address(&onion_address);
+			if let Some(onion_address) = &onion_address {
+				api::set_server_onion_address(onion_address);
+			}
+
+			libp2p_connection::libp2p_node()
+				.set_config(config.libp2p_config.clone())
+				.map_err(|e| Error::Configuration(format!("Invalid libp2p_config, {}", e)))?;
+			let libp2p_policy = config.libp2p_policy.clone();
+			libp2p_policy
+				.validate()
+				.map_err(|e| Error::Configuration(format!("Invalid libp2p_policy, {}", e)))?;
+
+			// Peers discovered before the restart are loaded when the libp2p node starts
+			p2p::libp2p_peer_store::init_libp2p_peer_store(
+				p2p::libp2p_peer_store::Libp2pPeerStore::new(&config.db_root)?,
+			);
+
+			// Messages handled before the restart are not passed to the handlers again
+			let seen_store = if config.libp2p_config.persist_seen_cache {
+				Some(p2p::libp2p_seen_cache::SeenMessageStore::new(
+					&config.db_root,
+				)?)
+			} else {
+				None
+			};
+			p2p::libp2p_seen_cache::init_seen_message_cache(
+				config.libp2p_config.seen_cache_size,
+				config.libp2p_config.seen_cache_ttl_secs,
+				seen_store,
+				Utc::now().timestamp(),
+			)?;
 
 			let clone_shared_chain = shared_chain.clone();
 			let libp2p_topics = config
@@ -431,9 +783,12 @@ impl Server {
 				.clone()
 				.unwrap_or(vec!["SwapMarketplace".to_string()]);
 
-			thread::Builder::new()
+			let libp2p_stopper = Arc::new(std::sync::Mutex::new(1));
+			let thread_stopper = libp2p_stopper.clone();
+			let handle = thread::Builder::new()
 				.name("libp2p_node".to_string())
 				.spawn(move || {
+					let _usage = subsystem::track_thread(SUBSYSTEM_LIBP2P);
 					let requested_kernel_cache: RwLock<HashMap<Commitment, (TxKernel, u64)>> =
 						RwLock::new(HashMap::new());
 					let last_time_cache_cleanup: RwLock<i64> = RwLock::new(0);
@@ -481,25 +836,29 @@ impl Server {
 							}
 						};
 
-					let mut secret: [u8; SECRET_KEY_SIZE] = [0; SECRET_KEY_SIZE];
-					secret.copy_from_slice(&tor_secret);
+					let mut onion_secret: [u8; SECRET_KEY_SIZE] = [0; SECRET_KEY_SIZE];
+					onion_secret.copy_from_slice(&libp2p_secrets.onion);
+					let mut identity_secret: [u8; SECRET_KEY_SIZE] = [0; SECRET_KEY_SIZE];
+					identity_secret.copy_from_slice(&libp2p_secrets.identity);
 
 					let validation_fn = Arc::new(output_validation_fn);
 
-					let libp2p_stopper = Arc::new(std::sync::Mutex::new(1));
+					let libp2p_node = libp2p_connection::libp2p_node();
 
 					loop {
 						for t in &libp2p_topics {
 							libp2p_connection::add_topic(t, 1);
 						}
 
-						let libp2p_node_runner = libp2p_connection::run_libp2p_node(
+						let libp2p_node_runner = libp2p_node.run(
 							tor_socks_port,
-							&secret,
+							&onion_secret,
+							&identity_secret,
 							libp2p_port.unwrap_or(3417),
 							fee_base,
 							validation_fn.clone(),
-							libp2p_stopper.clone(), // passing new obj, because we never will stop the libp2p process
+							libp2p_policy.clone(),
+							thread_stopper.clone(),
 						);
 
 						info!("Starting gossipsub libp2p server");
@@ -510,15 +869,14 @@ impl Server {
 							Err(e) => error!("Unable to start libp2p node, {}", e),
 						}
 						// Swarm is not valid any more, let's update our global instance.
-						libp2p_connection::reset_libp2p_swarm();
+						libp2p_node.reset_swarm();
 
-						if *libp2p_stopper.lock().unwrap() == 0 {
-							// Should never happen for the node
-							debug_assert!(false);
+						if *thread_stopper.lock().unwrap() == 0 {
 							break;
 						}
 					}
 				})?;
+			libp2p_thread = Some((handle, libp2p_stopper));
 		}
 
 		let p2p_server = Arc::new(p2p::Server::new(
@@ -529,14 +887,65 @@ impl Server {
 			genesis.hash(),
 			stop_state.clone(),
 			socks_port,
-			onion_address,
+			onion_address.clone(),
 		)?);
 
+		// The onion key might be rotated or restored since the last run, libp2p
+		// identity is derived from it
+		#[cfg(feature = "marketplace")]
+		if config.libp2p_enabled.unwrap_or(true) {
+			if let Some(onion_address) = &onion_address {
+				p2p_server.peers.check_libp2p_identity(onion_address)?;
+			}
+		}
+
 		// Initialize various adapters with our dynamic set of connected peers.
 		chain_adapter.init(p2p_server.peers.clone());
 		pool_net_adapter.init(p2p_server.peers.clone());
 		net_adapter.init(p2p_server.peers.clone());
 
+		p2p_server
+			.peers
+			.init_libp2p_peer_access(
+				&config.libp2p_allow_list.clone().unwrap_or(vec![]),
+				&config.libp2p_deny_list.clone().unwrap_or(vec![]),
+			)
+			.map_err(|e| Error::Configuration(format!("Invalid libp2p allow/deny list, {}", e)))?;
+		p2p_server.peers.update_libp2p_bans()?;
+
+		let audit_topics = config.libp2p_audit_topics.clone().unwrap_or(vec![]);
+		if !audit_topics.is_empty() {
+			info!("Gossip audit log is enabled for topics {:?}", audit_topics);
+			p2p::gossip_audit::init_gossip_audit(p2p::gossip_audit::GossipAuditLog::new(
+				&config.db_root,
+				audit_topics,
+				config
+					.libp2p_audit_retention_days
+					.unwrap_or(p2p::gossip_audit::DEFAULT_AUDIT_RETENTION_DAYS),
+				config
+					.libp2p_audit_max_entries
+					.unwrap_or(p2p::gossip_audit::DEFAULT_AUDIT_MAX_ENTRIES),
+			)?);
+		}
+
+		if let Some(interval) = config.libp2p_probe_interval_secs.filter(|i| *i > 0) {
+			info!(
+				"Gossip latency probes are enabled, interval {} seconds",
+				interval
+			);
+			p2p::gossip_probe::init_gossip_probes(interval);
+		}
+
+		if let Some(quarantine_secs) = config.libp2p_quarantine_secs {
+			info!(
+				"Quarantine period of the invalid gossip authors is {} seconds",
+				quarantine_secs
+			);
+			p2p::peer_quarantine::init_peer_quarantine(quarantine_secs);
+		}
+
+		Server::apply_handoff_state(&shared_chain, &p2p_server.peers, &tx_pool)?;
+
 		let mut connect_thread = None;
 
 		if config.p2p_config.seeding_type != p2p::Seeding::Programmatic {
@@ -575,23 +984,35 @@ impl Server {
 		// Defaults to None (optional) in config file.
 		// This translates to false here so we do not skip by default.
 		let skip_sync_wait = config.skip_sync_wait.unwrap_or(false);
-		sync_state.update(SyncStatus::AwaitingPeers(!skip_sync_wait));
-
-		let sync_thread = sync::run_sync(
-			sync_state.clone(),
-			p2p_server.peers.clone(),
-			shared_chain.clone(),
-			stop_state.clone(),
-		)?;
+		let sync_thread = if cold_storage {
+			info!("Sync is not started in cold storage mode");
+			sync_state.update(SyncStatus::NoSync);
+			None
+		} else {
+			sync_state.update(SyncStatus::AwaitingPeers(!skip_sync_wait));
+			Some(sync::run_sync(
+				sync_state.clone(),
+				p2p_server.peers.clone(),
+				shared_chain.clone(),
+				uptime.clone(),
+				stop_state.clone(),
+			)?)
+		};
 
-		let p2p_inner = p2p_server.clone();
-		let _ = thread::Builder::new()
-			.name("p2p-server".to_string())
-			.spawn(move || {
-				if let Err(e) = p2p_inner.listen(header_cache_size) {
-					error!("P2P server failed with erorr: {:?}", e);
-				}
-			})?;
+		if stealth_mode {
+			info!("P2P listener is not started in stealth mode");
+		} else if cold_storage {
+			info!("P2P listener is not started in cold storage mode");
+		} else {
+			let p2p_inner = p2p_server.clone();
+			let _ = thread::Builder::new()
+				.name("p2p-server".to_string())
+				.spawn(move || {
+					if let Err(e) = p2p_inner.listen(header_cache_size) {
+						error!("P2P server failed with erorr: {:?}", e);
+					}
+				})?;
+		}
 
 		info!("Starting rest apis at: {}", &config.api_http_addr);
 		let api_secret = get_first_line(config.api_secret_path.clone());
@@ -610,6 +1031,21 @@ impl Server {
 			}
 		};
 
+		if config.grpc_config.enable_grpc {
+			start_grpc_server(
+				&config.grpc_config,
+				shared_chain.clone(),
+				tx_pool.clone(),
+				p2p_server.peers.clone(),
+				sync_state.clone(),
+				pool_events.clone(),
+				api_secret.clone(),
+				foreign_api_secret.clone(),
+				config.owner_api_sessions.enable_owner_sessions,
+				stop_state.clone(),
+			)?;
+		}
+
 		// TODO fix API shutdown and join this thread
 		api::node_apis(
 			&config.api_http_addr,
@@ -622,6 +1058,11 @@ impl Server {
 			tls_conf,
 			allow_to_stop,
 			stratum_ip_pool,
+			config.foreign_api_load_shedding.clone(),
+			config.api_http_config.clone(),
+			config.api_ready_max_blocks_behind.unwrap_or(5),
+			config.owner_api_sessions.clone(),
+			pool_events,
 			api_chan,
 			stop_state.clone(),
 		)?;
@@ -631,10 +1072,118 @@ impl Server {
 			config.dandelion_config.clone(),
 			tx_pool.clone(),
 			pool_net_adapter,
-			verifier_cache.clone(),
 			stop_state.clone(),
 		)?;
 
+		let kernel_fee_index_thread = if config.kernel_fee_index.unwrap_or(false) {
+			info!("Starting kernel fee index builder");
+			Some(kernel_fee_index::run_kernel_fee_index(
+				shared_chain.clone(),
+				sync_state.clone(),
+				stop_state.clone(),
+			)?)
+		} else {
+			None
+		};
+
+		// Rangeproofs skipped by the spot-check validation, the previous run could have
+		// accepted the state with the spot-check even if it is disabled now
+		let rproof_verifier_thread = if !cold_storage
+			&& (grin_chain::rproof_spot_check::is_enabled()
+				|| shared_chain.deferred_rangeproofs()?.is_some())
+		{
+			info!("Starting deferred rangeproofs verifier");
+			Some(rproof_verifier::run_rproof_verifier(
+				shared_chain.clone(),
+				sync_state.clone(),
+				stop_state.clone(),
+			)?)
+		} else {
+			None
+		};
+
+		let tip_lag_alert = Arc::new(RwLock::new(None));
+		let tip_lag_thread = if config.tip_lag_alert_config.enable_tip_lag_alerts {
+			info!("Starting chain tip lag monitor");
+			Some(tip_lag_monitor::run_tip_lag_monitor(
+				config.tip_lag_alert_config.clone(),
+				shared_chain.clone(),
+				p2p_server.peers.clone(),
+				init_alert_hooks(&config),
+				tip_lag_alert.clone(),
+				stop_state.clone(),
+			)?)
+		} else {
+			None
+		};
+
+		let consensus_canary = Arc::new(RwLock::new(vec![]));
+		let canary_config = &config.consensus_canary_config;
+		let consensus_canary_thread = if canary_config.enable_consensus_canary {
+			if canary_config.canary_nodes.is_empty() {
+				warn!("Consensus canary is enabled, but canary_nodes are not configured");
+				None
+			} else {
+				info!(
+					"Starting consensus canary with {} remote nodes",
+					canary_config.canary_nodes.len()
+				);
+				Some(consensus_canary::run_consensus_canary(
+					canary_config.clone(),
+					shared_chain.clone(),
+					init_alert_hooks(&config),
+					consensus_canary.clone(),
+					stop_state.clone(),
+				)?)
+			}
+		} else {
+			None
+		};
+
+		let standby_config = &config.standby_config;
+		let standby_thread = if standby_config.standby_mode && standby_config.primary_url.is_some()
+		{
+			info!("Starting standby monitor of the primary node");
+			Some(standby_monitor::run_standby_monitor(
+				standby_config.clone(),
+				stop_state.clone(),
+			)?)
+		} else {
+			None
+		};
+
+		let metrics_history_thread = if config.metrics_history_config.enable_metrics_history {
+			info!("Starting metrics history");
+			Some(metrics_history::run_metrics_history(
+				config.metrics_history_config.clone(),
+				shared_chain.clone(),
+				p2p_server.peers.clone(),
+				tx_pool.clone(),
+				stop_state.clone(),
+			)?)
+		} else {
+			None
+		};
+
+		if config.upgrade_handoff.unwrap_or(false) {
+			let peers = p2p_server.peers.clone();
+			let pool = tx_pool.clone();
+			handoff::serve_handoff(
+				handoff::handoff_socket_path(&config.db_root),
+				move || handoff::HandoffState {
+					version: handoff::HANDOFF_VERSION,
+					peers: peers.all_peers(),
+					txs: pool.read().txpool.all_transactions(),
+				},
+				|| global::request_server_stop(),
+			)?;
+		}
+
+		let state_info = ServerStateInfo {
+			..Default::default()
+		};
+		subsystem_usage::register_memory_probes(&shared_chain, &tx_pool, &state_info.stratum_stats);
+
 		warn!("MWC server started.");
 		Ok(Server {
 			config,
@@ -643,14 +1192,22 @@ impl Server {
 			tx_pool,
 			verifier_cache,
 			sync_state,
-			state_info: ServerStateInfo {
-				..Default::default()
-			},
+			state_info,
 			stop_state,
+			uptime,
 			lock_file,
 			connect_thread,
 			sync_thread,
 			dandelion_thread,
+			kernel_fee_index_thread,
+			rproof_verifier_thread,
+			tip_lag_alert,
+			tip_lag_thread,
+			consensus_canary,
+			consensus_canary_thread,
+			standby_thread,
+			metrics_history_thread,
+			libp2p_thread,
 		})
 	}
 
@@ -675,8 +1232,10 @@ impl Server {
 		addr: &str,
 		api_addr: &str,
 		libp2p_port: u16,
+		wallet_listener_ports: &[(u16, String)],
 		tor_base: Option<&str>,
 		socks_port: u16,
+		pow_queue: Option<(u32, u32)>,
 	) -> Result<(tor_process::TorProcess, String, SecretKey), Error> {
 		let mut process = tor_process::TorProcess::new();
 		let tor_dir = if tor_base.is_some() {
@@ -761,9 +1320,11 @@ impl Server {
 			addr,
 			api_addr,
 			libp2p_port,
+			wallet_listener_ports,
 			sec_key_vec,
 			existing_onion,
 			socks_port,
+			pow_queue,
 		)
 		.map_err(|e| ErrorKind::TorConfig(format!("Failed to configure tor, {}", e).into()))
 		.unwrap();
@@ -792,6 +1353,65 @@ impl Server {
 		}
 	}
 
+	// Check the inbound connections flood and apply the new PoW defense queue to the
+	// running Tor listener
+	fn update_tor_pow_defense(defense: &mut PowDefense, listener: &tor_process::TorProcess) {
+		let queue = defense.update(p2p::loopback_inbound_count(), Utc::now().timestamp());
+		if let Some((rate, burst)) = queue {
+			let status = defense.status();
+			warn!(
+				"Onion service PoW defense level is {}, {} inbound connections per minute. Queue rate {}, burst {}",
+				status.level, status.inbound_per_min, rate, burst
+			);
+			match listener.get_torrc_path() {
+				Some(torrc_path) => {
+					if let Err(e) = tor_config::update_torrc_pow_queue(&torrc_path, rate, burst) {
+						error!(
+							"Unable to update PoW defense queue at {}, {}",
+							torrc_path, e
+						);
+					} else if let Err(e) = listener.reload_config() {
+						error!("Unable to reload Tor config, {:?}", e);
+					}
+				}
+				None => error!("Tor listener torrc path is unknown"),
+			}
+			api::set_tor_pow_defense(status);
+		}
+	}
+
+	/// Start the Tor process for the outbound connections only, no onion service
+	/// is published. Used in stealth mode.
+	pub fn init_tor_client(
+		tor_base: Option<&str>,
+		socks_port: u16,
+	) -> Result<tor_process::TorProcess, Error> {
+		let mut process = tor_process::TorProcess::new();
+		let tor_dir = format!("{}/tor/client", tor_base.unwrap_or("~/.mwc/main"));
+		let home_dir = dirs::home_dir()
+			.map(|p| p.to_str().unwrap().to_string())
+			.unwrap_or("~".to_string());
+		let tor_dir = tor_dir.replace("~", &home_dir);
+
+		tor_config::output_tor_client_config(&tor_dir, socks_port)
+			.map_err(|e| Error::Configuration(format!("Failed to configure tor, {}", e)))?;
+
+		info!("Starting Tor client, socks port {}", socks_port);
+
+		let tor_path = PathBuf::from(format!("{}/torrc", tor_dir));
+		let tor_path = fs::canonicalize(&tor_path)?;
+		let tor_path = Server::adjust_canonicalization(tor_path);
+
+		process
+			.torrc_path(&tor_path)
+			.working_dir(&tor_dir)
+			.timeout(200)
+			.completion_percent(100)
+			.launch()
+			.map_err(|_| Error::Configuration("Unable to start tor".to_string()))?;
+		Ok(process)
+	}
+
 	/// Asks the server to connect to a peer at the provided network address.
 	pub fn connect_peer(&self, addr: PeerAddr, header_cache_size: u64) -> Result<(), Error> {
 		self.p2p.connect(addr, header_cache_size)?;
@@ -822,6 +1442,20 @@ impl Server {
 		let proof_size = global::proofsize();
 		let sync_state = self.sync_state.clone();
 
+		if config.share_audit {
+			match api::StratumAuditLog::new(
+				&self.config.db_root,
+				config.share_audit_retention_days,
+				config.share_audit_max_entries,
+			) {
+				Ok(log) => {
+					info!("Stratum jobs and shares log is enabled");
+					api::init_stratum_audit(log);
+				}
+				Err(e) => error!("Unable to open stratum jobs and shares log, {}", e),
+			}
+		}
+
 		let mut stratum_server = stratumserver::StratumServer::new(
 			config,
 			self.chain.clone(),
@@ -830,9 +1464,21 @@ impl Server {
 			self.state_info.stratum_stats.clone(),
 			ip_pool,
 		);
+		let stop_state = self.stop_state.clone();
 		let _ = thread::Builder::new()
 			.name("stratum_server".to_string())
 			.spawn(move || {
+				let _usage = subsystem::track_thread(SUBSYSTEM_STRATUM);
+				// The standby node starts to serve the miners once it is promoted
+				if api::is_standby() {
+					info!("Stratum server is waiting for the standby node promotion");
+					while api::is_standby() {
+						if stop_state.is_stopped() {
+							return;
+						}
+						thread::sleep(Duration::from_secs(1));
+					}
+				}
 				stratum_server.run_loop(edge_bits as u32, proof_size, sync_state);
 			});
 	}
@@ -868,6 +1514,9 @@ impl Server {
 			connection_pace_ms: -1,
 			ip_white_list: HashSet::new(),
 			ip_black_list: HashSet::new(),
+			share_audit: false,
+			share_audit_retention_days: api::DEFAULT_STRATUM_AUDIT_RETENTION_DAYS,
+			share_audit_max_entries: api::DEFAULT_STRATUM_AUDIT_MAX_ENTRIES,
 		};
 
 		let mut miner = Miner::new(
@@ -911,12 +1560,13 @@ impl Server {
 		// for release
 		let diff_stats = {
 			let last_blocks: Vec<consensus::HeaderInfo> =
-				global::difficulty_data_to_vector(self.chain.difficulty_iter()?)
+				global::difficulty_data_to_vector(self.chain.difficulty_window()?)
 					.into_iter()
 					.collect();
 
 			let tip_height = self.head()?.height as i64;
 			let mut height = tip_height as i64 - last_blocks.len() as i64 + 1;
+			let max_weight = global::max_block_weight();
 
 			let diff_entries: Vec<DiffBlock> = last_blocks
 				.windows(2)
@@ -926,6 +1576,14 @@ impl Server {
 
 					height += 1;
 
+					// Synthetic blocks of a new chain have no weight
+					let fullness = self
+						.chain
+						.get_block_header(&next.block_hash)
+						.and_then(|header| self.chain.get_block_weight(&header))
+						.ok()
+						.map(|weight| weight as f64 * 100.0 / max_weight as f64);
+
 					DiffBlock {
 						block_height: height,
 						block_hash: next.block_hash,
@@ -934,18 +1592,26 @@ impl Server {
 						duration: next.timestamp - prev.timestamp,
 						secondary_scaling: next.secondary_scaling,
 						is_secondary: next.is_secondary,
+						fullness,
 					}
 				})
 				.collect();
 
 			let block_time_sum = diff_entries.iter().fold(0, |sum, t| sum + t.duration);
 			let block_diff_sum = diff_entries.iter().fold(0, |sum, d| sum + d.difficulty);
+			let fullness: Vec<f64> = diff_entries.iter().filter_map(|d| d.fullness).collect();
+			let average_fullness = if fullness.is_empty() {
+				None
+			} else {
+				Some(fullness.iter().sum::<f64>() / fullness.len() as f64)
+			};
 			DiffStats {
 				height: height as u64,
 				last_blocks: diff_entries,
-				average_block_time: block_time_sum / (consensus::DIFFICULTY_ADJUST_WINDOW - 1),
-				average_difficulty: block_diff_sum / (consensus::DIFFICULTY_ADJUST_WINDOW - 1),
-				window_size: consensus::DIFFICULTY_ADJUST_WINDOW,
+				average_block_time: block_time_sum / (global::difficulty_adjust_window() - 1),
+				average_difficulty: block_diff_sum / (global::difficulty_adjust_window() - 1),
+				average_fullness,
+				window_size: global::difficulty_adjust_window(),
 			}
 		};
 
@@ -966,6 +1632,8 @@ impl Server {
 			tx_pool_kernels: pool.txpool.kernel_count(),
 			stem_pool_size: pool.stempool.size(),
 			stem_pool_kernels: pool.stempool.kernel_count(),
+			stem_aggregation: pool.adapter.stem_aggregation_stats(),
+			relay_policy: pool.relay_policy.stats(),
 		});
 
 		let head = self.chain.head_header()?;
@@ -1006,6 +1674,12 @@ impl Server {
 			peer_stats: peer_stats,
 			diff_stats: diff_stats,
 			tx_stats: tx_stats,
+			tip_lag_alert: self.tip_lag_alert.read().clone(),
+			consensus_canary: self.consensus_canary.read().clone(),
+			header_first_stats: self.p2p.peers.header_first_stats(),
+			slow_peer_counters: p2p::conn_stats::slow_peer_counters(),
+			tor_pow_defense: api::get_tor_pow_defense(),
+			libp2p_stats: p2p::get_libp2p_stats(),
 		})
 	}
 
@@ -1024,15 +1698,71 @@ impl Server {
 				info!("No active connect_and_monitor thread")
 			}
 
-			match self.sync_thread.join() {
-				Err(e) => error!("failed to join to sync thread: {:?}", e),
-				Ok(_) => info!("sync thread stopped"),
+			if let Some(sync_thread) = self.sync_thread {
+				match sync_thread.join() {
+					Err(e) => error!("failed to join to sync thread: {:?}", e),
+					Ok(_) => info!("sync thread stopped"),
+				}
 			}
 
 			match self.dandelion_thread.join() {
 				Err(e) => error!("failed to join to dandelion_monitor thread: {:?}", e),
 				Ok(_) => info!("dandelion_monitor thread stopped"),
 			}
+
+			if let Some(kernel_fee_index_thread) = self.kernel_fee_index_thread {
+				match kernel_fee_index_thread.join() {
+					Err(e) => error!("failed to join to kernel_fee_index thread: {:?}", e),
+					Ok(_) => info!("kernel_fee_index thread stopped"),
+				}
+			}
+
+			if let Some(rproof_verifier_thread) = self.rproof_verifier_thread {
+				match rproof_verifier_thread.join() {
+					Err(e) => error!("failed to join to rproof_verifier thread: {:?}", e),
+					Ok(_) => info!("rproof_verifier thread stopped"),
+				}
+			}
+
+			if let Some(tip_lag_thread) = self.tip_lag_thread {
+				match tip_lag_thread.join() {
+					Err(e) => error!("failed to join to tip_lag_monitor thread: {:?}", e),
+					Ok(_) => info!("tip_lag_monitor thread stopped"),
+				}
+			}
+
+			if let Some(consensus_canary_thread) = self.consensus_canary_thread {
+				match consensus_canary_thread.join() {
+					Err(e) => error!("failed to join to consensus_canary thread: {:?}", e),
+					Ok(_) => info!("consensus_canary thread stopped"),
+				}
+			}
+
+			if let Some(standby_thread) = self.standby_thread {
+				match standby_thread.join() {
+					Err(e) => error!("failed to join to standby_monitor thread: {:?}", e),
+					Ok(_) => info!("standby_monitor thread stopped"),
+				}
+			}
+
+			if let Some(metrics_history_thread) = self.metrics_history_thread {
+				match metrics_history_thread.join() {
+					Err(e) => error!("failed to join to metrics_history thread: {:?}", e),
+					Ok(_) => info!("metrics_history thread stopped"),
+				}
+			}
+
+			// libp2p node leaves the topics and closes the connections, so the mesh peers
+			// don't wait for the timeout
+			if let Some((libp2p_thread, libp2p_stopper)) = self.libp2p_thread {
+				*libp2p_stopper.lock().unwrap() = 0;
+				match libp2p_thread.join() {
+					Err(e) => error!("failed to join to libp2p_node thread: {:?}", e),
+					Ok(_) => info!("libp2p_node thread stopped"),
+				}
+			}
+
+			self.uptime.stop();
 		}
 		// this call is blocking and makes sure all peers stop, however
 		// we can't be sure that we stopped a listener blocked on accept, so we don't join the p2p thread
@@ -1060,3 +1790,167 @@ impl Server {
 		info!("stop_test_miner - stop",);
 	}
 }
+
+// Keys of the libp2p node
+#[cfg(feature = "marketplace")]
+struct Libp2pSecrets {
+	// Onion service key, the same as the identity for the clearnet only node
+	onion: Vec<u8>,
+	// Key of the PeerId
+	identity: Vec<u8>,
+}
+
+// Keys of the libp2p node, None if libp2p can't run. The identity is the onion service
+// key, unless the standalone identity is configured or the node runs without Tor.
+#[cfg(feature = "marketplace")]
+fn libp2p_secrets(
+	config: &ServerConfig,
+	onion_address: &Option<String>,
+	tor_secret: &Option<String>,
+) -> Result<Option<Libp2pSecrets>, Error> {
+	if !config.libp2p_enabled.unwrap_or(true) {
+		return Ok(None);
+	}
+	let tor_secret = match (onion_address, tor_secret) {
+		(Some(_), Some(secret)) => Some(from_hex(secret).map_err(|e| {
+			Error::General(format!("Unable to parse secret hex {}, {}", secret, e))
+		})?),
+		_ => None,
+	};
+	if tor_secret.is_none() && config.libp2p_config.transport.has_tor() {
+		return Ok(None);
+	}
+	let secrets = match (config.libp2p_config.identity, tor_secret) {
+		(Libp2pIdentity::Tor, Some(tor_secret)) => Libp2pSecrets {
+			onion: tor_secret.clone(),
+			identity: tor_secret,
+		},
+		(_, tor_secret) => {
+			let identity = libp2p_standalone_identity(&config.db_root)?;
+			Libp2pSecrets {
+				onion: tor_secret.unwrap_or(identity.clone()),
+				identity,
+			}
+		}
+	};
+	Ok(Some(secrets))
+}
+
+// Standalone libp2p identity key from the data dir, generated on the first run
+#[cfg(feature = "marketplace")]
+fn libp2p_standalone_identity(db_root: &str) -> Result<Vec<u8>, Error> {
+	let path = Path::new(db_root).join(LIBP2P_IDENTITY_FILE);
+	if let Ok(secret) = fs::read_to_string(&path) {
+		return from_hex(secret.trim()).map_err(|e| {
+			Error::General(format!("Unable to parse libp2p identity {:?}, {}", path, e))
+		});
+	}
+	let secret: [u8; SECRET_KEY_SIZE] = rand::thread_rng().gen();
+	fs::write(&path, to_hex(&secret))
+		.map_err(|e| Error::General(format!("Unable to save libp2p identity {:?}, {}", path, e)))?;
+	info!("New standalone libp2p identity key is generated");
+	Ok(secret.to_vec())
+}
+
+// Cold storage node serves the snapshot only, everything that talks to the network
+// or writes to the chain is turned off
+fn apply_cold_storage(config: &mut ServerConfig) -> Result<(), Error> {
+	if !config.cold_storage_config.cold_storage_mode {
+		return Ok(());
+	}
+	let snapshot_dir = match &config.cold_storage_config.snapshot_dir {
+		Some(dir) if !dir.is_empty() => dir.clone(),
+		_ => {
+			return Err(Error::Configuration(
+				"snapshot_dir must be set in the cold storage mode".to_owned(),
+			))
+		}
+	};
+	for subdir in &["lmdb", "header", "txhashset"] {
+		if !Path::new(&snapshot_dir).join(subdir).is_dir() {
+			return Err(Error::Configuration(format!(
+				"Snapshot {} doesn't have the {} directory, it must be a copy of the chain_data directory",
+				snapshot_dir, subdir
+			)));
+		}
+	}
+	if config.standby_config.standby_mode {
+		return Err(Error::Configuration(
+			"Standby mode can't be combined with the cold storage mode".to_owned(),
+		));
+	}
+
+	config.p2p_config.seeding_type = p2p::Seeding::Programmatic;
+	config.tor_config.tor_enabled = false;
+	config.libp2p_enabled = Some(false);
+	config.upgrade_handoff = Some(false);
+	config.run_test_miner = Some(false);
+	if let Some(c) = config.stratum_mining_config.as_mut() {
+		c.enable_stratum_server = Some(false);
+	}
+	config.tip_lag_alert_config.enable_tip_lag_alerts = false;
+	config.consensus_canary_config.enable_consensus_canary = false;
+	config.kernel_fee_index = Some(false);
+	config.utxo_set_digest = Some(false);
+	config.metrics_history_config.enable_metrics_history = false;
+	Ok(())
+}
+
+// Check if the listener address is reachable from this host only
+fn is_loopback_addr(addr: &str) -> bool {
+	match addr.parse::<SocketAddr>() {
+		Ok(addr) => addr.ip().is_loopback(),
+		Err(_) => false,
+	}
+}
+
+// Start the gRPC server next to the HTTP APIs, with the same secrets
+#[cfg(feature = "grpc")]
+fn start_grpc_server(
+	config: &api::GrpcConfig,
+	chain: Arc<chain::Chain>,
+	tx_pool: ServerTxPool,
+	peers: Arc<p2p::Peers>,
+	sync_state: Arc<SyncState>,
+	pool_events: Arc<api::TxPoolEventsHub>,
+	api_secret: Option<String>,
+	foreign_api_secret: Option<String>,
+	owner_sessions: bool,
+	stop_state: Arc<StopState>,
+) -> Result<(), Error> {
+	info!("Starting gRPC server at: {}", config.grpc_addr);
+	// The server thread stops with the node, like the HTTP API server it is not joined
+	let _ = api::start_grpc_server(
+		&config.grpc_addr,
+		chain,
+		tx_pool,
+		peers,
+		sync_state,
+		pool_events,
+		api_secret,
+		foreign_api_secret,
+		owner_sessions,
+		stop_state,
+	)?;
+	Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+fn start_grpc_server(
+	config: &api::GrpcConfig,
+	_chain: Arc<chain::Chain>,
+	_tx_pool: ServerTxPool,
+	_peers: Arc<p2p::Peers>,
+	_sync_state: Arc<SyncState>,
+	_pool_events: Arc<api::TxPoolEventsHub>,
+	_api_secret: Option<String>,
+	_foreign_api_secret: Option<String>,
+	_owner_sessions: bool,
+	_stop_state: Arc<StopState>,
+) -> Result<(), Error> {
+	warn!(
+		"gRPC server at {} is enabled, but the node is built without the grpc feature",
+		config.grpc_addr
+	);
+	Ok(())
+}
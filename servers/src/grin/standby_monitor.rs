@@ -0,0 +1,107 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Failure detection of the primary node. The standby node polls the foreign API of
+//! the primary and promotes itself when the primary stops responding.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::api;
+use crate::common::types::StandbyConfig;
+use crate::util::StopState;
+
+/// Start the primary monitor thread. The thread exits once the node is promoted.
+pub fn run_standby_monitor(
+	config: StandbyConfig,
+	stop_state: Arc<StopState>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+	debug!("Started standby monitor.");
+
+	thread::Builder::new()
+		.name("standby_monitor".to_string())
+		.spawn(move || {
+			let run_interval = Duration::from_secs(config.primary_check_interval_secs);
+			let mut last_run: Option<Instant> = None;
+			loop {
+				if stop_state.is_stopped() || !api::is_standby() {
+					break;
+				}
+
+				if last_run.map(|t| t.elapsed() >= run_interval) != Some(false) {
+					let reachable = match check_primary(&config) {
+						Ok(()) => true,
+						Err(e) => {
+							warn!("standby_monitor: primary node is not responding, {}", e);
+							false
+						}
+					};
+					let failures = api::record_primary_check(reachable);
+					if should_promote(&config, failures) {
+						warn!(
+							"standby_monitor: {} checks of the primary failed, promoting the node",
+							failures
+						);
+						api::promote_to_primary("failover");
+					}
+					last_run = Some(Instant::now());
+				}
+
+				thread::sleep(Duration::from_secs(1));
+			}
+		})
+}
+
+fn check_primary(config: &StandbyConfig) -> Result<(), String> {
+	let url = match &config.primary_url {
+		Some(url) => url.trim_end_matches('/'),
+		None => return Err("primary_url is not configured".to_string()),
+	};
+	let _tip: api::Tip = api::client::get(
+		&format!("{}/v1/chain", url),
+		config.primary_api_secret.clone(),
+	)
+	.map_err(|e| e.to_string())?;
+	Ok(())
+}
+
+/// True if the primary is down long enough for the failover
+fn should_promote(config: &StandbyConfig, failures: u32) -> bool {
+	config.auto_promote && failures >= config.primary_failure_threshold.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_should_promote() {
+		let mut config = StandbyConfig::default();
+		assert!(!should_promote(&config, 10));
+
+		config.auto_promote = true;
+		assert!(!should_promote(&config, 0));
+		assert!(!should_promote(
+			&config,
+			config.primary_failure_threshold - 1
+		));
+		assert!(should_promote(&config, config.primary_failure_threshold));
+
+		// The single successful check doesn't promote the node
+		config.primary_failure_threshold = 0;
+		assert!(!should_promote(&config, 0));
+		assert!(should_promote(&config, 1));
+	}
+}
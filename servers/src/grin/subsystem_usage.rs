@@ -0,0 +1,104 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Memory probes of the server subsystems. Estimates are the number of the items the
+//! subsystem keeps multiplied by the typical item size, precise accounting would cost
+//! more than the debug report is worth.
+
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use crate::chain;
+use crate::common::stats::StratumStats;
+use crate::core::core::Transaction;
+use crate::p2p::libp2p_seen_cache;
+use crate::util::subsystem::{
+	self, SUBSYSTEM_LIBP2P, SUBSYSTEM_POOL, SUBSYSTEM_STRATUM, SUBSYSTEM_SYNC,
+};
+use crate::ServerTxPool;
+
+/// Input: features and commitment
+const INPUT_BYTES: u64 = 34;
+/// Output: features, commitment and the bulletproof
+const OUTPUT_BYTES: u64 = 717;
+/// Kernel: features, fee, lock height, excess and signature
+const KERNEL_BYTES: u64 = 114;
+/// Orphan block with its header, the typical block is few dozen transactions
+const ORPHAN_BLOCK_BYTES: u64 = 64 * 1024;
+/// Seen message hash with the timestamp, in the map and in the expiration queue
+const SEEN_MESSAGE_BYTES: u64 = 96;
+/// Connected stratum worker: the line buffers, the channel and the worker stats
+const STRATUM_WORKER_BYTES: u64 = 16 * 1024;
+
+/// Estimated size of the transaction in memory
+pub fn tx_size_estimate(tx: &Transaction) -> u64 {
+	tx.inputs().len() as u64 * INPUT_BYTES
+		+ tx.outputs().len() as u64 * OUTPUT_BYTES
+		+ tx.kernels().len() as u64 * KERNEL_BYTES
+}
+
+/// Register the memory probes of sync, pool, libp2p and stratum. Probes hold the weak
+/// references, so they don't keep the chain and the pool alive after the server stop.
+pub fn register_memory_probes(
+	chain: &Arc<chain::Chain>,
+	tx_pool: &ServerTxPool,
+	stratum_stats: &Arc<StratumStats>,
+) {
+	let chain: Weak<chain::Chain> = Arc::downgrade(chain);
+	subsystem::register_memory_probe(
+		SUBSYSTEM_SYNC,
+		Arc::new(move || match chain.upgrade() {
+			Some(chain) => chain.orphans_len() as u64 * ORPHAN_BLOCK_BYTES,
+			None => 0,
+		}),
+	);
+
+	let tx_pool = Arc::downgrade(tx_pool);
+	subsystem::register_memory_probe(
+		SUBSYSTEM_POOL,
+		Arc::new(move || {
+			let tx_pool = match tx_pool.upgrade() {
+				Some(tx_pool) => tx_pool,
+				None => return 0,
+			};
+			// Report should not wait for the busy pool
+			let res = match tx_pool.try_read_for(Duration::from_millis(100)) {
+				Some(pool) => pool
+					.txpool
+					.entries
+					.iter()
+					.chain(pool.stempool.entries.iter())
+					.map(|e| tx_size_estimate(&e.tx))
+					.sum(),
+				None => 0,
+			};
+			res
+		}),
+	);
+
+	subsystem::register_memory_probe(
+		SUBSYSTEM_LIBP2P,
+		Arc::new(|| libp2p_seen_cache::seen_messages_count() as u64 * SEEN_MESSAGE_BYTES),
+	);
+
+	let stratum_stats = Arc::downgrade(stratum_stats);
+	subsystem::register_memory_probe(
+		SUBSYSTEM_STRATUM,
+		Arc::new(move || match stratum_stats.upgrade() {
+			Some(stats) => stats.num_workers.load(Ordering::Relaxed) as u64 * STRATUM_WORKER_BYTES,
+			None => 0,
+		}),
+	);
+}
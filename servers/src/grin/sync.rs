@@ -17,6 +17,7 @@
 mod body_sync;
 mod header_sync;
 mod state_sync;
+mod sync_progress;
 mod syncer;
 
 pub use self::syncer::run_sync;
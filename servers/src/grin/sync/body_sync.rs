@@ -19,12 +19,15 @@ use std::sync::Arc;
 
 use crate::chain::{self, SyncState, SyncStatus};
 use crate::core::core::hash::Hash;
-use crate::p2p;
+use crate::grin::sync::sync_progress::SyncProgressTracker;
+use crate::p2p::{self, SyncStage};
+use crate::util::clock;
 
 pub struct BodySync {
 	chain: Arc<chain::Chain>,
 	peers: Arc<p2p::Peers>,
 	sync_state: Arc<SyncState>,
+	progress: Arc<SyncProgressTracker>,
 
 	blocks_requested: u64,
 
@@ -37,13 +40,15 @@ impl BodySync {
 		sync_state: Arc<SyncState>,
 		peers: Arc<p2p::Peers>,
 		chain: Arc<chain::Chain>,
+		progress: Arc<SyncProgressTracker>,
 	) -> BodySync {
 		BodySync {
 			sync_state,
 			peers,
 			chain,
+			progress,
 			blocks_requested: 0,
-			receive_timeout: Utc::now(),
+			receive_timeout: clock::now(),
 			prev_blocks_received: 0,
 		}
 	}
@@ -89,7 +94,17 @@ impl BodySync {
 
 		hashes.reverse();
 
-		let peers = self.peers.more_work_peers()?;
+		// Peer requested by the user is used for a single body sync cycle.
+		// Headers only relay peers are never asked for the bodies.
+		let peers = match self.peers.sync_peer() {
+			Some(peer) if peer.info.serves_bodies() => {
+				self.peers.clear_sync_peer();
+				vec![peer]
+			}
+			_ => self
+				.peers
+				.preferred_sync_peers(SyncStage::Blocks, self.peers.more_work_body_peers()?),
+		};
 
 		// if we have 5 peers to sync from then ask for 50 blocks total (peer_count *
 		// 10) max will be 80 if all 8 peers are advertising more work
@@ -99,15 +114,27 @@ impl BodySync {
 			chain::MAX_ORPHAN_SIZE.saturating_sub(self.chain.orphans_len()) + 1,
 		);
 
-		let hashes_to_get = hashes
-			.iter()
-			.filter(|x| {
-				// only ask for blocks that we have not yet processed
-				// either successfully stored or in our orphan list
-				self.chain.get_block(x).is_err() && !self.chain.is_orphan(x)
-			})
-			.take(block_count)
-			.collect::<Vec<_>>();
+		// Blocks requested before the restart go first
+		let mut hashes_to_get = if block_count > 0 {
+			self.progress.resume_body_sync_window()
+		} else {
+			vec![]
+		};
+		hashes_to_get.retain(|x| !self.chain.is_orphan(x));
+		for x in hashes.iter() {
+			if hashes_to_get.len() >= block_count {
+				break;
+			}
+			// only ask for blocks that we have not yet processed
+			// either successfully stored or in our orphan list
+			if !hashes_to_get.contains(x)
+				&& self.chain.get_block(x).is_err()
+				&& !self.chain.is_orphan(x)
+			{
+				hashes_to_get.push(*x);
+			}
+		}
+		hashes_to_get.truncate(block_count);
 
 		if !hashes_to_get.is_empty() {
 			let body_head = self.chain.head()?;
@@ -123,12 +150,14 @@ impl BodySync {
 
 			// reinitialize download tracking state
 			self.blocks_requested = 0;
-			self.receive_timeout = Utc::now() + Duration::seconds(6);
+			self.receive_timeout = clock::now() + Duration::seconds(6);
+
+			self.progress.on_blocks_request(&hashes_to_get);
 
 			let mut peers_iter = peers.iter().cycle();
-			for hash in hashes_to_get.clone() {
+			for hash in hashes_to_get {
 				if let Some(peer) = peers_iter.next() {
-					if let Err(e) = peer.send_block_request(*hash, chain::Options::SYNC) {
+					if let Err(e) = peer.send_block_request(hash, chain::Options::SYNC) {
 						debug!("Skipped request to {}: {:?}", peer.info.addr, e);
 						peer.stop();
 					} else {
@@ -147,7 +176,7 @@ impl BodySync {
 		// some blocks have been requested
 		if self.blocks_requested > 0 {
 			// but none received since timeout, ask again
-			let timeout = Utc::now() > self.receive_timeout;
+			let timeout = clock::now() > self.receive_timeout;
 			if timeout && blocks_received <= self.prev_blocks_received {
 				debug!(
 					"body_sync: expecting {} more blocks and none received for a while",
@@ -159,7 +188,7 @@ impl BodySync {
 
 		if blocks_received > self.prev_blocks_received {
 			// some received, update for next check
-			self.receive_timeout = Utc::now() + Duration::seconds(1);
+			self.receive_timeout = clock::now() + Duration::seconds(1);
 			self.blocks_requested = self
 				.blocks_requested
 				.saturating_sub(blocks_received - self.prev_blocks_received);
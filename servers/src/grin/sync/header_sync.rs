@@ -19,12 +19,15 @@ use std::sync::Arc;
 use crate::chain::{self, SyncState, SyncStatus};
 use crate::common::types::Error;
 use crate::core::core::hash::{Hash, Hashed};
-use crate::p2p::{self, types::ReasonForBan, Peer};
+use crate::grin::sync::sync_progress::SyncProgressTracker;
+use crate::p2p::{self, types::ReasonForBan, Peer, SyncStage};
+use crate::util::clock;
 
 pub struct HeaderSync {
 	sync_state: Arc<SyncState>,
 	peers: Arc<p2p::Peers>,
 	chain: Arc<chain::Chain>,
+	progress: Arc<SyncProgressTracker>,
 	prev_header_sync: (DateTime<Utc>, u64, u64),
 	syncing_peer: Option<Arc<Peer>>,
 	stalling_ts: Option<DateTime<Utc>>,
@@ -35,12 +38,14 @@ impl HeaderSync {
 		sync_state: Arc<SyncState>,
 		peers: Arc<p2p::Peers>,
 		chain: Arc<chain::Chain>,
+		progress: Arc<SyncProgressTracker>,
 	) -> HeaderSync {
 		HeaderSync {
 			sync_state,
 			peers,
 			chain,
-			prev_header_sync: (Utc::now(), 0, 0),
+			progress,
+			prev_header_sync: (clock::now(), 0, 0),
 			syncing_peer: None,
 			stalling_ts: None,
 		}
@@ -76,8 +81,23 @@ impl HeaderSync {
 				// in the header chain. Ensure we track the new advertised header chain here
 				// correctly, so reset any previous (and potentially stale) sync_head to match
 				// our last known "good" header_head.
+				// If the node was restarted in the middle of the header sync, we continue
+				// from the sync_head of the previous run.
 				//
-				self.chain.rebuild_sync_mmr(&header_head)?;
+				match self.progress.resume_sync_head() {
+					Some(resume_head) if resume_head.hash() != header_head.hash() => {
+						info!(
+							"sync: resuming header sync from {} at {}",
+							resume_head.hash(),
+							resume_head.height,
+						);
+						if let Err(e) = self.chain.rebuild_sync_mmr(&resume_head) {
+							warn!("sync: unable to resume header sync, {}", e);
+							self.chain.rebuild_sync_mmr(&header_head)?;
+						}
+					}
+					_ => self.chain.rebuild_sync_mmr(&header_head)?,
+				}
 				true
 			}
 			_ => false,
@@ -96,7 +116,7 @@ impl HeaderSync {
 	}
 
 	fn header_sync_due(&mut self, header_head: &chain::Tip) -> bool {
-		let now = Utc::now();
+		let now = clock::now();
 		let (timeout, latest_height, prev_height) = self.prev_header_sync;
 
 		// received all necessary headers, can ask for more
@@ -172,7 +192,18 @@ impl HeaderSync {
 		if let Ok(header_head) = self.chain.header_head() {
 			let difficulty = header_head.total_difficulty;
 
-			if let Some(peer) = self.peers.most_work_peer() {
+			// Requested peer without more headers gives way to the most work peers.
+			// Peers that fed too many headers give way to the others.
+			let peer = self
+				.peers
+				.sync_peer()
+				.filter(|p| p.info.total_difficulty() > difficulty)
+				.or_else(|| {
+					self.peers
+						.preferred_sync_peers(SyncStage::Headers, self.peers.most_work_peers())
+						.pop()
+				});
+			if let Some(peer) = peer {
 				if peer.info.total_difficulty() > difficulty {
 					return self.request_headers(peer);
 				}
@@ -189,6 +220,7 @@ impl HeaderSync {
 				peer.info.addr, locator,
 			);
 
+			self.progress.on_headers_request(&locator);
 			let _ = peer.send_header_request(locator);
 			return Some(peer);
 		}
@@ -19,7 +19,9 @@ use std::sync::Arc;
 use crate::chain::{self, SyncState, SyncStatus};
 use crate::core::core::hash::Hashed;
 use crate::core::global;
-use crate::p2p::{self, Peer};
+use crate::grin::sync::sync_progress::SyncProgressTracker;
+use crate::p2p::{self, Peer, SyncStage};
+use crate::util::clock;
 
 /// Fast sync has 3 "states":
 /// * syncing headers
@@ -31,6 +33,7 @@ pub struct StateSync {
 	sync_state: Arc<SyncState>,
 	peers: Arc<p2p::Peers>,
 	chain: Arc<chain::Chain>,
+	progress: Arc<SyncProgressTracker>,
 
 	prev_state_sync: Option<DateTime<Utc>>,
 	state_sync_peer: Option<Arc<Peer>>,
@@ -44,11 +47,13 @@ impl StateSync {
 		sync_state: Arc<SyncState>,
 		peers: Arc<p2p::Peers>,
 		chain: Arc<chain::Chain>,
+		progress: Arc<SyncProgressTracker>,
 	) -> StateSync {
 		StateSync {
 			sync_state,
 			peers,
 			chain,
+			progress,
 			prev_state_sync: None,
 			state_sync_peer: None,
 			last_logged_time: 0,
@@ -163,7 +168,30 @@ impl StateSync {
 		let mut txhashset_height = header_head.height.saturating_sub(threshold);
 		txhashset_height = txhashset_height.saturating_sub(txhashset_height % archive_interval);
 
-		if let Some(peer) = self.peers.most_work_peer() {
+		let candidates = self
+			.peers
+			.preferred_sync_peers(SyncStage::TxHashSet, self.peers.most_work_body_peers());
+		let (peers, decision) = self.peers.rank_state_sync_sources(candidates);
+		debug!(
+			"state_sync: source {:?} selected from {} candidates",
+			decision.selected,
+			decision.candidates.len()
+		);
+		self.sync_state.set_sync_source_decision(decision);
+		if let Some(peer) = peers.into_iter().next() {
+			// continue with the archive requested before the restart if it is still good
+			if let Some((height, bhash)) = self.progress.resume_txhashset_request() {
+				if height >= txhashset_height {
+					info!(
+						"state_sync: resuming txhashset request for {} at {}",
+						bhash, height
+					);
+					peer.send_txhashset_request(height, bhash)?;
+					self.progress.on_txhashset_request(height, bhash);
+					return Ok(peer);
+				}
+			}
+
 			// ask for txhashset at state_sync_threshold
 			let mut txhashset_head = self
 				.chain
@@ -202,6 +230,8 @@ impl StateSync {
 				error!("state_sync: send_txhashset_request err! {:?}", e);
 				return Err(e);
 			}
+			self.progress
+				.on_txhashset_request(txhashset_head.height, bhash);
 			return Ok(peer);
 		}
 		Err(p2p::Error::PeerException(
@@ -211,13 +241,15 @@ impl StateSync {
 
 	// For now this is a one-time thing (it can be slow) at initial startup.
 	fn state_sync_due(&mut self) -> (bool, bool) {
-		let now = Utc::now();
+		let now = clock::now();
 		let mut download_timeout = false;
 
 		if let SyncStatus::TxHashsetDownload(status) = self.sync_state.status() {
 			if self.last_download_size < status.downloaded_size {
 				self.prev_state_sync = Some(now); // reset the timer
 				self.last_download_size = status.downloaded_size;
+				self.progress
+					.on_txhashset_download(status.downloaded_size, status.total_size);
 
 				let pass_time = now.timestamp() - self.last_logged_time;
 				if pass_time > 5 {
@@ -247,6 +279,7 @@ impl StateSync {
 	}
 
 	fn state_sync_reset(&mut self) {
+		self.progress.on_txhashset_done();
 		self.prev_state_sync = None;
 		self.state_sync_peer = None;
 		self.last_logged_time = 0;
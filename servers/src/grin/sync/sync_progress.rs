@@ -0,0 +1,178 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sync progress persistence. The sync stages save their cursors into the chain db,
+//! so a node that was restarted in the middle of the sync (users with flaky
+//! connections restart often) resumes from where it was stopped.
+
+use std::sync::Arc;
+
+use crate::chain::{self, SyncProgress, Tip};
+use crate::core::core::hash::{Hash, Hashed};
+use crate::util::{clock, Mutex};
+
+/// Progress older than that is considered stale and it is not resumed
+const SYNC_PROGRESS_MAX_AGE: i64 = 24 * 3600;
+/// Txhashset download progress is saved not more often than that
+const DOWNLOAD_SAVE_INTERVAL: i64 = 10;
+
+/// Keeps the sync progress of the running node and persists it into the chain db.
+pub struct SyncProgressTracker {
+	chain: Arc<chain::Chain>,
+	progress: Mutex<Option<SyncProgress>>,
+	// Progress that was loaded at the start, it is consumed by the sync stages
+	resumed: Mutex<Option<SyncProgress>>,
+}
+
+impl SyncProgressTracker {
+	/// Load the progress of the previous node run, if it is still fresh.
+	pub fn load(chain: Arc<chain::Chain>) -> SyncProgressTracker {
+		let stored = match chain.store().sync_progress() {
+			Ok(progress) => progress,
+			Err(e) => {
+				warn!("Unable to read the sync progress, {}", e);
+				None
+			}
+		};
+		let resumed = stored
+			.clone()
+			.filter(|p| clock::now().timestamp() - p.update_time < SYNC_PROGRESS_MAX_AGE);
+		if let Some(p) = &resumed {
+			info!(
+				"sync: resuming the sync of the previous run. Header locator: {:?}, txhashset: {:?} downloaded {}/{}, body sync window: {} blocks",
+				p.header_locator.first(),
+				p.txhashset_request,
+				p.txhashset_downloaded_size,
+				p.txhashset_total_size,
+				p.body_sync_window.len(),
+			);
+		}
+		SyncProgressTracker {
+			chain,
+			progress: Mutex::new(stored),
+			resumed: Mutex::new(resumed),
+		}
+	}
+
+	/// Sync head to continue the header sync from. It is the head of the last headers
+	/// request of the previous run, if we still have this header.
+	pub fn resume_sync_head(&self) -> Option<Tip> {
+		let mut resumed = self.resumed.lock();
+		let locator = std::mem::replace(&mut resumed.as_mut()?.header_locator, vec![]);
+		let hash = locator.first()?;
+		self.chain
+			.get_block_header(hash)
+			.ok()
+			.map(|h| Tip::from_header(&h))
+	}
+
+	/// Txhashset archive that was requested by the previous run, if it is still
+	/// on our header chain.
+	pub fn resume_txhashset_request(&self) -> Option<(u64, Hash)> {
+		let mut resumed = self.resumed.lock();
+		let (height, hash) = resumed.as_mut()?.txhashset_request.take()?;
+		match self.chain.get_header_by_height(height) {
+			Ok(header) if header.hash() == hash => Some((height, hash)),
+			_ => None,
+		}
+	}
+
+	/// Blocks that were requested by the previous run and still not received.
+	pub fn resume_body_sync_window(&self) -> Vec<Hash> {
+		let mut resumed = self.resumed.lock();
+		match resumed.as_mut() {
+			Some(p) => p
+				.body_sync_window
+				.drain(..)
+				.filter(|h| self.chain.get_block(h).is_err())
+				.collect(),
+			None => vec![],
+		}
+	}
+
+	/// Headers are requested with the locator
+	pub fn on_headers_request(&self, locator: &[Hash]) {
+		self.update(|p| p.header_locator = locator.to_vec());
+	}
+
+	/// Txhashset archive is requested
+	pub fn on_txhashset_request(&self, height: u64, hash: Hash) {
+		self.update(|p| {
+			p.txhashset_request = Some((height, hash));
+			p.txhashset_downloaded_size = 0;
+			p.txhashset_total_size = 0;
+		});
+	}
+
+	/// Txhashset download progress, the saves are throttled
+	pub fn on_txhashset_download(&self, downloaded_size: u64, total_size: u64) {
+		let due = match &*self.progress.lock() {
+			Some(p) => clock::now().timestamp() - p.update_time >= DOWNLOAD_SAVE_INTERVAL,
+			None => true,
+		};
+		if due || downloaded_size >= total_size {
+			self.update(|p| {
+				p.txhashset_downloaded_size = downloaded_size;
+				p.txhashset_total_size = total_size;
+			});
+		}
+	}
+
+	/// Txhashset is downloaded and validated, or the state sync is restarted
+	pub fn on_txhashset_done(&self) {
+		self.update(|p| {
+			p.txhashset_request = None;
+			p.txhashset_downloaded_size = 0;
+			p.txhashset_total_size = 0;
+		});
+	}
+
+	/// Blocks are requested by the body sync
+	pub fn on_blocks_request(&self, hashes: &[Hash]) {
+		self.update(|p| p.body_sync_window = hashes.to_vec());
+	}
+
+	/// Sync is done, there is nothing to resume
+	pub fn reset(&self) {
+		self.resumed.lock().take();
+		if self.progress.lock().take().is_none() {
+			return;
+		}
+		let res = self.chain.store().batch().and_then(|batch| {
+			batch.delete_sync_progress()?;
+			batch.commit()
+		});
+		if let Err(e) = res {
+			warn!("Unable to delete the sync progress, {}", e);
+		}
+	}
+
+	fn update<F>(&self, f: F)
+	where
+		F: FnOnce(&mut SyncProgress),
+	{
+		let mut progress = self.progress.lock();
+		let progress = progress.get_or_insert_with(SyncProgress::default);
+		f(progress);
+		progress.update_time = clock::now().timestamp();
+
+		let res = self.chain.store().batch().and_then(|batch| {
+			batch.save_sync_progress(progress)?;
+			batch.commit()
+		});
+		if let Err(e) = res {
+			warn!("Unable to save the sync progress, {}", e);
+		}
+	}
+}
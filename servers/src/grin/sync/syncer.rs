@@ -22,19 +22,24 @@ use crate::core::pow::Difficulty;
 use crate::grin::sync::body_sync::BodySync;
 use crate::grin::sync::header_sync::HeaderSync;
 use crate::grin::sync::state_sync::StateSync;
+use crate::grin::sync::sync_progress::SyncProgressTracker;
+use crate::grin::uptime::UptimeTracker;
 use crate::p2p;
+use crate::util::subsystem::{self, SUBSYSTEM_SYNC};
 use crate::util::StopState;
 
 pub fn run_sync(
 	sync_state: Arc<SyncState>,
 	peers: Arc<p2p::Peers>,
 	chain: Arc<chain::Chain>,
+	uptime: Arc<UptimeTracker>,
 	stop_state: Arc<StopState>,
 ) -> std::io::Result<std::thread::JoinHandle<()>> {
 	thread::Builder::new()
 		.name("sync".to_string())
 		.spawn(move || {
-			let runner = SyncRunner::new(sync_state, peers, chain, stop_state);
+			let _usage = subsystem::track_thread(SUBSYSTEM_SYNC);
+			let runner = SyncRunner::new(sync_state, peers, chain, uptime, stop_state);
 			runner.sync_loop();
 		})
 }
@@ -43,6 +48,7 @@ pub struct SyncRunner {
 	sync_state: Arc<SyncState>,
 	peers: Arc<p2p::Peers>,
 	chain: Arc<chain::Chain>,
+	uptime: Arc<UptimeTracker>,
 	stop_state: Arc<StopState>,
 }
 
@@ -51,12 +57,14 @@ impl SyncRunner {
 		sync_state: Arc<SyncState>,
 		peers: Arc<p2p::Peers>,
 		chain: Arc<chain::Chain>,
+		uptime: Arc<UptimeTracker>,
 		stop_state: Arc<StopState>,
 	) -> SyncRunner {
 		SyncRunner {
 			sync_state,
 			peers,
 			chain,
+			uptime,
 			stop_state,
 		}
 	}
@@ -120,21 +128,27 @@ impl SyncRunner {
 			error!("wait_for_min_peers failed: {:?}", e);
 		}
 
+		// Sync progress of the previous run, if node was restarted in the middle of the sync
+		let progress = Arc::new(SyncProgressTracker::load(self.chain.clone()));
+
 		// Our 3 main sync stages
 		let mut header_sync = HeaderSync::new(
 			self.sync_state.clone(),
 			self.peers.clone(),
 			self.chain.clone(),
+			progress.clone(),
 		);
 		let mut body_sync = BodySync::new(
 			self.sync_state.clone(),
 			self.peers.clone(),
 			self.chain.clone(),
+			progress.clone(),
 		);
 		let mut state_sync = StateSync::new(
 			self.sync_state.clone(),
 			self.peers.clone(),
 			self.chain.clone(),
+			progress.clone(),
 		);
 
 		// Highest height seen on the network, generally useful for a fast test on
@@ -149,6 +163,8 @@ impl SyncRunner {
 
 			thread::sleep(time::Duration::from_millis(10));
 
+			self.uptime.on_sync_status(self.sync_state.status());
+
 			let currently_syncing = self.sync_state.is_syncing();
 
 			// check whether syncing is generally needed, when we compare our state with others
@@ -162,6 +178,7 @@ impl SyncRunner {
 			if !needs_syncing {
 				if currently_syncing {
 					self.sync_state.update(SyncStatus::NoSync);
+					progress.reset();
 
 					// Initial transition out of a "syncing" state and into NoSync.
 					// This triggers a chain compaction to keep out local node tidy.
@@ -222,7 +239,19 @@ impl SyncRunner {
 	fn needs_syncing(&self) -> Result<(bool, u64), chain::Error> {
 		let local_diff = self.chain.head()?.total_difficulty;
 		let mut is_syncing = self.sync_state.is_syncing();
-		let peer = self.peers.most_work_peer();
+		let mut forced_peer = self.peers.sync_peer();
+		if let Some(p) = &forced_peer {
+			if p.info.total_difficulty() <= local_diff {
+				info!(
+					"sync: requested peer {} has no more work than us, ignoring it",
+					p.info.addr
+				);
+				self.peers.clear_sync_peer();
+				forced_peer = None;
+			}
+		}
+		let forced = forced_peer.is_some();
+		let peer = forced_peer.or_else(|| self.peers.most_work_peer());
 
 		let peer_info = if let Some(p) = peer {
 			p.info.clone()
@@ -262,7 +291,13 @@ impl SyncRunner {
 			};
 
 			let peer_diff = peer_info.total_difficulty();
-			if peer_diff > local_diff + threshold {
+			if forced && peer_diff > local_diff {
+				info!(
+					"sync: total_difficulty {}, requested peer {} difficulty {}, enabling sync",
+					local_diff, peer_info.addr, peer_diff,
+				);
+				is_syncing = true;
+			} else if peer_diff > local_diff + threshold {
 				info!(
 					"sync: total_difficulty {}, peer_difficulty {}, threshold {} (last 5 blocks), enabling sync",
 					local_diff,
@@ -0,0 +1,160 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Chain tip lag monitoring. Compares our tip with the tips advertised by the peers
+//! and fires the alerts when the node lags behind, so "network is quiet" can be told
+//! apart from "we are stuck".
+
+use chrono::Utc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chain;
+use crate::common::hooks::AlertEvents;
+use crate::common::types::{TipLagAlert, TipLagAlertConfig, TipLagStatus};
+use crate::p2p;
+use crate::util::{RwLock, StopState};
+
+/// Start the tip lag monitor thread. Current state is published into tip_lag_alert,
+/// alert hooks are called every time the state is changed.
+pub fn run_tip_lag_monitor(
+	config: TipLagAlertConfig,
+	chain: Arc<chain::Chain>,
+	peers: Arc<p2p::Peers>,
+	hooks: Vec<Box<dyn AlertEvents + Send + Sync>>,
+	tip_lag_alert: Arc<RwLock<Option<TipLagAlert>>>,
+	stop_state: Arc<StopState>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+	debug!("Started chain tip lag monitor.");
+
+	thread::Builder::new()
+		.name("tip_lag_monitor".to_string())
+		.spawn(move || {
+			let run_interval = Duration::from_secs(config.tip_lag_check_interval_secs);
+			let mut last_run = Instant::now();
+			// Our tip height at the previous check
+			let mut prev_height: Option<u64> = None;
+			loop {
+				if stop_state.is_stopped() {
+					break;
+				}
+
+				if last_run.elapsed() > run_interval {
+					match chain.head_header() {
+						Ok(head) => {
+							let peer_height = peers
+								.connected_peers()
+								.iter()
+								.map(|p| p.info.height())
+								.max();
+							let tip_age_secs = Utc::now().timestamp() - head.timestamp.timestamp();
+							let status = evaluate_tip_lag(
+								&config,
+								head.height,
+								peer_height,
+								tip_age_secs,
+								prev_height,
+							);
+							prev_height = Some(head.height);
+
+							let mut alert = tip_lag_alert.write();
+							let changed = alert.as_ref().map(|a| a.status) != Some(status);
+							let since = match alert.as_ref() {
+								Some(a) if !changed => a.since,
+								_ => Utc::now().timestamp(),
+							};
+							let new_alert = TipLagAlert {
+								status,
+								height: head.height,
+								peer_height: peer_height.unwrap_or(0),
+								tip_age_secs,
+								since,
+							};
+							// Initial Ok state is not worth to report
+							if changed && (alert.is_some() || status != TipLagStatus::Ok) {
+								for hook in &hooks {
+									hook.on_tip_lag_alert(&new_alert);
+								}
+							}
+							alert.replace(new_alert);
+						}
+						Err(e) => warn!("tip_lag_monitor: Unable to read chain head, {}", e),
+					}
+					last_run = Instant::now();
+				}
+
+				thread::sleep(Duration::from_secs(1));
+			}
+		})
+}
+
+/// Compare our tip with the peers.
+/// peer_height - max height advertised by the connected peers, None if there are no peers.
+/// prev_height - our tip height at the previous check, None for the first check.
+fn evaluate_tip_lag(
+	config: &TipLagAlertConfig,
+	height: u64,
+	peer_height: Option<u64>,
+	tip_age_secs: i64,
+	prev_height: Option<u64>,
+) -> TipLagStatus {
+	let peer_height = match peer_height {
+		Some(h) => h,
+		None => return TipLagStatus::NoPeers,
+	};
+
+	if peer_height.saturating_sub(height) > config.max_height_lag {
+		// Peers are ahead, it is fine as long as we are moving
+		match prev_height {
+			Some(prev_height) if prev_height >= height => TipLagStatus::Stuck,
+			_ => TipLagStatus::Lagging,
+		}
+	} else if tip_age_secs > config.max_tip_age_secs {
+		TipLagStatus::NetworkQuiet
+	} else {
+		TipLagStatus::Ok
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_evaluate_tip_lag() {
+		let config = TipLagAlertConfig::default();
+
+		assert_eq!(
+			evaluate_tip_lag(&config, 100, None, 10, None),
+			TipLagStatus::NoPeers
+		);
+		assert_eq!(
+			evaluate_tip_lag(&config, 100, Some(102), 10, Some(99)),
+			TipLagStatus::Ok
+		);
+		assert_eq!(
+			evaluate_tip_lag(&config, 100, Some(100), 7200, Some(100)),
+			TipLagStatus::NetworkQuiet
+		);
+		assert_eq!(
+			evaluate_tip_lag(&config, 100, Some(200), 7200, Some(90)),
+			TipLagStatus::Lagging
+		);
+		assert_eq!(
+			evaluate_tip_lag(&config, 100, Some(200), 7200, Some(100)),
+			TipLagStatus::Stuck
+		);
+	}
+}
@@ -0,0 +1,208 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Node uptime and sync stages tracking. Every node run is stored in the chain db,
+//! so operators can report the node availability and compare sync durations.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+
+use crate::chain::{self, SyncStageRecord, SyncStatus, UptimeRecord};
+use crate::util::Mutex;
+
+/// Max number of the node runs that we keep in the history
+const UPTIME_HISTORY_LIMIT: usize = 1000;
+
+/// Tracks the current node run and persists it into the chain db.
+pub struct UptimeTracker {
+	chain: Arc<chain::Chain>,
+	record: Mutex<UptimeRecord>,
+	// Current sync stage name and the time when we entered it
+	stage: Mutex<Option<(String, i64)>>,
+	// False if the chain db must not be modified, the run is tracked in memory only
+	persist: bool,
+}
+
+impl UptimeTracker {
+	/// Register the node start. Old records above the history limit are pruned.
+	pub fn start(chain: Arc<chain::Chain>) -> UptimeTracker {
+		let tracker = UptimeTracker {
+			chain,
+			record: Mutex::new(UptimeRecord::new(Utc::now().timestamp())),
+			stage: Mutex::new(None),
+			persist: true,
+		};
+		tracker.prune_history();
+		tracker.assign_seq();
+		tracker.save(&tracker.record.lock());
+		tracker
+	}
+
+	/// Register the node start without writing to the chain db, the history stays
+	/// as it is. Used when the chain data is a read-only snapshot.
+	pub fn start_in_memory(chain: Arc<chain::Chain>) -> UptimeTracker {
+		UptimeTracker {
+			chain,
+			record: Mutex::new(UptimeRecord::new(Utc::now().timestamp())),
+			stage: Mutex::new(None),
+			persist: false,
+		}
+	}
+
+	/// Update the sync stages with a current sync status. Record is saved
+	/// only when the stage is changed.
+	pub fn on_sync_status(&self, status: SyncStatus) {
+		let new_stage = sync_stage_name(&status).map(|name| name.to_string());
+
+		let mut stage = self.stage.lock();
+		let cur_stage = stage.as_ref().map(|(name, _)| name.clone());
+		let sync_done = status == SyncStatus::NoSync;
+
+		let mut record = self.record.lock();
+		if cur_stage == new_stage && !(sync_done && record.sync_done_time == 0) {
+			return;
+		}
+
+		let now = Utc::now().timestamp();
+		if cur_stage != new_stage {
+			if let Some((name, start_time)) = stage.take() {
+				close_stage(&mut record, name, start_time, now);
+			}
+			*stage = new_stage.map(|name| (name, now));
+		}
+		if sync_done && record.sync_done_time == 0 {
+			record.sync_done_time = now;
+		}
+		self.save(&record);
+	}
+
+	/// Register the node stop
+	pub fn stop(&self) {
+		let now = Utc::now().timestamp();
+		let mut record = self.record.lock();
+		if let Some((name, start_time)) = self.stage.lock().take() {
+			close_stage(&mut record, name, start_time, now);
+		}
+		record.stop_time = now;
+		self.save(&record);
+	}
+
+	fn save(&self, record: &UptimeRecord) {
+		if !self.persist {
+			return;
+		}
+		let res = self.chain.store().batch().and_then(|batch| {
+			batch.save_uptime_record(record)?;
+			batch.commit()
+		});
+		if let Err(e) = res {
+			warn!("Unable to save node uptime record, {}", e);
+		}
+	}
+
+	// Restarts within the same second must not overwrite the previous run record
+	fn assign_seq(&self) {
+		let mut record = self.record.lock();
+		let history = self.chain.store().uptime_history().unwrap_or(vec![]);
+		if let Some(seq) = history
+			.iter()
+			.filter(|r| r.start_time == record.start_time)
+			.map(|r| r.seq)
+			.max()
+		{
+			record.seq = seq + 1;
+		}
+	}
+
+	fn prune_history(&self) {
+		let store = self.chain.store();
+		let history = match store.uptime_history() {
+			Ok(history) => history,
+			Err(e) => {
+				warn!("Unable to read node uptime history, {}", e);
+				return;
+			}
+		};
+		if history.len() < UPTIME_HISTORY_LIMIT {
+			return;
+		}
+		let res = store.batch().and_then(|batch| {
+			for r in &history[..history.len() + 1 - UPTIME_HISTORY_LIMIT] {
+				batch.delete_uptime_record(r.start_time, r.seq)?;
+			}
+			batch.commit()
+		});
+		if let Err(e) = res {
+			warn!("Unable to prune node uptime history, {}", e);
+		}
+	}
+}
+
+/// Sync stage name for the sync status. None if node is not syncing.
+fn sync_stage_name(status: &SyncStatus) -> Option<&'static str> {
+	match status {
+		SyncStatus::HeaderSync { .. } => Some("header_sync"),
+		SyncStatus::TxHashsetDownload(_)
+		| SyncStatus::TxHashsetSetup
+		| SyncStatus::TxHashsetKernelsValidation { .. }
+		| SyncStatus::TxHashsetRangeProofsValidation { .. }
+		| SyncStatus::TxHashsetSave
+		| SyncStatus::TxHashsetDone => Some("txhashset"),
+		SyncStatus::BodySync { .. } => Some("body_sync"),
+		_ => None,
+	}
+}
+
+// Node can visit the same stage several times, the durations are accumulated
+fn close_stage(record: &mut UptimeRecord, name: String, start_time: i64, now: i64) {
+	let duration = now - start_time;
+	match record.sync_stages.iter_mut().find(|s| s.stage == name) {
+		Some(s) => s.duration += duration,
+		None => record.sync_stages.push(SyncStageRecord {
+			stage: name,
+			start_time,
+			duration,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_close_stage_accumulates() {
+		let mut record = UptimeRecord::new(100);
+		close_stage(&mut record, "header_sync".to_string(), 110, 150);
+		close_stage(&mut record, "body_sync".to_string(), 150, 200);
+		close_stage(&mut record, "header_sync".to_string(), 200, 205);
+
+		assert_eq!(record.sync_stages.len(), 2);
+		assert_eq!(record.sync_stages[0].stage, "header_sync");
+		assert_eq!(record.sync_stages[0].start_time, 110);
+		assert_eq!(record.sync_stages[0].duration, 45);
+		assert_eq!(record.sync_stages[1].duration, 50);
+	}
+
+	#[test]
+	fn test_sync_stage_name() {
+		assert_eq!(sync_stage_name(&SyncStatus::NoSync), None);
+		assert_eq!(sync_stage_name(&SyncStatus::Initial), None);
+		assert_eq!(
+			sync_stage_name(&SyncStatus::TxHashsetSave),
+			Some("txhashset")
+		);
+	}
+}
@@ -44,6 +44,9 @@ mod mining;
 mod tor;
 
 pub use crate::common::stats::{DiffBlock, PeerStats, ServerStats, StratumStats, WorkerStats};
-pub use crate::common::types::{ServerConfig, StratumServerConfig};
+pub use crate::common::types::{
+	CanaryNodeState, CanaryStatus, ColdStorageConfig, ConsensusCanaryConfig, MetricsHistoryConfig,
+	ServerConfig, StandbyConfig, StratumServerConfig, TipLagAlert, TipLagAlertConfig, TipLagStatus,
+};
 pub use crate::core::global::{FLOONET_DNS_SEEDS, MAINNET_DNS_SEEDS};
 pub use crate::grin::server::{Server, ServerTxPool, ServerVerifierCache};
@@ -28,7 +28,7 @@ use crate::common::types::Error;
 use crate::core::core::{Output, TxKernel};
 use crate::core::libtx::secp_ser;
 use crate::core::libtx::ProofBuilder;
-use crate::core::{consensus, core, global};
+use crate::core::{core, global};
 use crate::keychain::{ExtKeychain, Identifier, Keychain};
 use crate::{ServerTxPool, ServerVerifierCache};
 
@@ -146,8 +146,7 @@ fn build_block(
 	}
 
 	// Determine the difficulty our block should be at.
-	// Note: do not keep the difficulty_iter in scope (it has an active batch).
-	let difficulty = consensus::next_difficulty(head.height + 1, chain.difficulty_iter()?);
+	let difficulty = chain.next_difficulty()?;
 
 	// Extract current "mineable" transactions from the pool.
 	// If this fails for *any* reason then fallback to an empty vec of txs.
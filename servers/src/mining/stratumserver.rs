@@ -15,6 +15,7 @@
 //! Mining Stratum Server
 
 use futures::channel::{mpsc, oneshot};
+use futures::future;
 use futures::pin_mut;
 use futures::{SinkExt, StreamExt, TryStreamExt};
 use tokio::net::TcpListener;
@@ -33,16 +34,19 @@ use std::time::Duration;
 use std::{cmp, thread};
 
 use super::stratum_data::WorkersList;
+use crate::api::{self, StratumJobRecord, StratumShareRecord, StratumShareResult};
 use crate::chain::{self, SyncState};
 use crate::common::stats::StratumStats;
 use crate::common::types::StratumServerConfig;
-use crate::core::core::hash::Hashed;
+use crate::core::core::hash::{Hash, Hashed};
 use crate::core::core::Block;
 use crate::core::stratum::connections;
 use crate::core::{pow, ser};
 use crate::keychain;
 use crate::mining::mine_block;
+use crate::p2p::handoff;
 use crate::util;
+use crate::util::subsystem::{self, SUBSYSTEM_STRATUM};
 use crate::util::ToHex;
 use crate::{ServerTxPool, ServerVerifierCache};
 use std::cmp::min;
@@ -417,6 +421,7 @@ impl Handler {
 				self.id, params.height, params.edge_bits, params.nonce, params.job_id,
 			);
 			self.workers.update_stats(worker_id, |ws| ws.num_stale += 1);
+			self.audit_share(worker_id, &params, 0, None, StratumShareResult::Stale);
 			return Err(RpcError::too_late());
 		}
 
@@ -427,7 +432,7 @@ impl Handler {
 		// Reconstruct the blocks header with this nonce and pow added
 		b.header.pow.proof.edge_bits = params.edge_bits as u8;
 		b.header.pow.nonce = params.nonce;
-		b.header.pow.proof.nonces = params.pow;
+		b.header.pow.proof.nonces = params.pow.clone();
 
 		if !b.header.pow.is_primary() && !b.header.pow.is_secondary() {
 			// Return error status
@@ -437,6 +442,13 @@ impl Handler {
 			);
 			self.workers
 				.update_stats(worker_id, |worker_stats| worker_stats.num_rejected += 1);
+			self.audit_share(
+				worker_id,
+				&params,
+				0,
+				Some(b.hash()),
+				StratumShareResult::InvalidSize,
+			);
 			return Err(RpcError::cannot_validate());
 		}
 
@@ -455,6 +467,13 @@ impl Handler {
 			);
 			self.workers
 				.update_stats(worker_id, |worker_stats| worker_stats.num_rejected += 1);
+			self.audit_share(
+				worker_id,
+				&params,
+				share_difficulty,
+				Some(b.hash()),
+				StratumShareResult::LowDifficulty,
+			);
 			return Err(RpcError::too_low_difficulty());
 		}
 
@@ -477,6 +496,13 @@ impl Handler {
 				);
 				self.workers
 					.update_stats(worker_id, |worker_stats| worker_stats.num_rejected += 1);
+				self.audit_share(
+					worker_id,
+					&params,
+					share_difficulty,
+					Some(b.hash()),
+					StratumShareResult::BlockRejected,
+				);
 				return Err(RpcError::cannot_validate());
 			}
 			share_is_block = true;
@@ -512,6 +538,13 @@ impl Handler {
 				);
 				self.workers
 					.update_stats(worker_id, |worker_stats| worker_stats.num_rejected += 1);
+				self.audit_share(
+					worker_id,
+					&params,
+					share_difficulty,
+					Some(b.hash()),
+					StratumShareResult::InvalidProof,
+				);
 				return Err(RpcError::cannot_validate());
 			}
 		}
@@ -538,6 +571,17 @@ impl Handler {
 
 		self.workers
 			.update_stats(worker_id, |worker_stats| worker_stats.num_accepted += 1);
+		self.audit_share(
+			worker_id,
+			&params,
+			share_difficulty,
+			Some(b.hash()),
+			if share_is_block {
+				StratumShareResult::BlockFound
+			} else {
+				StratumShareResult::Accepted
+			},
+		);
 		let submit_response = if share_is_block {
 			format!("blockfound - {}", b.hash().to_hex())
 		} else {
@@ -549,10 +593,59 @@ impl Handler {
 		));
 	} // handle submit a solution
 
+	// Record the share and its validation result in the stratum log
+	fn audit_share(
+		&self,
+		worker_id: usize,
+		params: &SubmitParams,
+		difficulty: u64,
+		hash: Option<Hash>,
+		result: StratumShareResult,
+	) {
+		let log = match api::stratum_audit() {
+			Some(log) => log,
+			None => return,
+		};
+		let (login, ip) = match self.workers.get_worker(&worker_id) {
+			Some(worker) => (worker.login.clone(), worker.ip.clone()),
+			None => (None, String::new()),
+		};
+		let share = StratumShareRecord {
+			timestamp: Utc::now().timestamp(),
+			worker_id: worker_id as u64,
+			login,
+			ip,
+			height: params.height,
+			job_id: params.job_id,
+			nonce: params.nonce,
+			edge_bits: params.edge_bits,
+			pow: params.pow.clone(),
+			difficulty,
+			hash: hash.map(|h| h.to_hex()),
+			result,
+		};
+		if let Err(e) = log.record_share(&share) {
+			error!("Unable to record the share in the stratum log, {}", e);
+		}
+	}
+
 	fn broadcast_job(&self) {
 		debug!("broadcast job");
 		// Package new block into RpcRequest
 		let job_template = self.build_block_template();
+		if let Some(log) = api::stratum_audit() {
+			let job = StratumJobRecord {
+				timestamp: Utc::now().timestamp(),
+				height: job_template.height,
+				job_id: job_template.job_id,
+				share_difficulty: job_template.difficulty,
+				network_difficulty: self.current_state.read().current_difficulty,
+				pre_pow: job_template.pre_pow.clone(),
+			};
+			if let Err(e) = log.record_job(&job) {
+				error!("Unable to record the job in the stratum log, {}", e);
+			}
+		}
 		let job_template_json = serde_json::to_string(&job_template).unwrap_or("{}".to_string());
 		// Issue #1159 - use a serde_json Value type to avoid extra quoting
 		let job_template_value: Value =
@@ -764,7 +857,11 @@ fn accept_connections(listen_addr: SocketAddr, handler: Arc<Handler>) {
 	}
 
 	let task = async move {
-		let mut listener = match TcpListener::bind(&listen_addr).await {
+		// Listener might be taken over from the previous node on upgrade
+		let listener = handoff::bind_listener(listen_addr)
+			.and_then(|l| l.set_nonblocking(true).map(|_| l))
+			.and_then(TcpListener::from_std);
+		let mut listener = match listener {
 			Ok(listener) => listener,
 			Err(e) => {
 				error!(
@@ -775,6 +872,7 @@ fn accept_connections(listen_addr: SocketAddr, handler: Arc<Handler>) {
 			}
 		};
 
+		let handoff = handoff::handoff_signal();
 		let server = listener
 			.incoming()
 			.filter_map(|s| async { s.map_err(|e| error!("accept error = {:?}", e)).ok() })
@@ -866,7 +964,9 @@ fn accept_connections(listen_addr: SocketAddr, handler: Arc<Handler>) {
 						Result::<_, ()>::Ok(())
 					};
 
+					let usage = subsystem::track_task(SUBSYSTEM_STRATUM);
 					let task = async move {
+						let _usage = usage;
 						pin_mut!(read, write);
 						let rw = futures::future::select(read, write);
 						futures::future::select(rw, kill_switch_receiver).await;
@@ -876,7 +976,11 @@ fn accept_connections(listen_addr: SocketAddr, handler: Arc<Handler>) {
 					tokio::spawn(task);
 				}
 			});
-		server.await
+		// New node took over the listener, stop accepting
+		pin_mut!(server);
+		if let future::Either::Right(_) = future::select(server, handoff).await {
+			info!("Stratum listener is handed off to the new node");
+		}
 	};
 
 	let mut rt = Runtime::new().unwrap();
@@ -946,6 +1050,7 @@ impl StratumServer {
 		let h = handler.clone();
 
 		let _listener_th = thread::spawn(move || {
+			let _usage = subsystem::track_thread(SUBSYSTEM_STRATUM);
 			accept_connections(listen_addr, h);
 		});
 
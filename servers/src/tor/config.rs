@@ -21,6 +21,7 @@ use ed25519_dalek::PublicKey as DalekPublicKey;
 use ed25519_dalek::SecretKey as DalekSecretKey;
 use ed25519_dalek::{ExpandedSecretKey, SECRET_KEY_LENGTH};
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::{Read, Write};
@@ -38,6 +39,10 @@ const TOR_DATA_DIR: &str = "data";
 const AUTH_CLIENTS_DIR: &str = "authorized_clients";
 const HIDDEN_SERVICES_DIR: &str = "onion_service_addresses";
 
+const POW_DEFENSES_ENABLED: &str = "HiddenServicePoWDefensesEnabled";
+const POW_QUEUE_RATE: &str = "HiddenServicePoWQueueRate";
+const POW_QUEUE_BURST: &str = "HiddenServicePoWQueueBurst";
+
 #[cfg(unix)]
 fn set_permissions(file_path: &str) -> Result<(), Error> {
 	use std::os::unix::prelude::*;
@@ -237,14 +242,51 @@ pub fn output_onion_service_config(
 	Ok(address)
 }
 
-/// output torrc file given a list of hidden service directories
+/// Parse the wallet listener ports map from the config. Key is the onion service port,
+/// value is the local listener address. Ports that node is using are not allowed.
+pub fn parse_wallet_listener_ports(
+	ports: &BTreeMap<String, String>,
+) -> Result<Vec<(u16, String)>, Error> {
+	let reserved = [80, 8080, global::get_tor_libp2p_port()];
+	let mut res = vec![];
+	for (port, listener) in ports {
+		let port = port.trim().parse::<u16>().map_err(|e| {
+			ErrorKind::TorConfig(format!(
+				"Invalid wallet listener onion port {}, {}",
+				port, e
+			))
+		})?;
+		if port == 0 || reserved.contains(&port) {
+			return Err(ErrorKind::TorConfig(format!(
+				"Onion port {} can't be used for wallet listener",
+				port
+			))
+			.into());
+		}
+		let listener = listener.trim();
+		if listener.is_empty() || listener.contains(char::is_whitespace) {
+			return Err(ErrorKind::TorConfig(format!(
+				"Invalid wallet listener address '{}' for onion port {}",
+				listener, port
+			))
+			.into());
+		}
+		res.push((port, listener.to_string()));
+	}
+	Ok(res)
+}
+
+/// output torrc file given a list of hidden service directories.
+/// pow_queue - PoW defense queue (rate, burst), PoW defense is disabled if None
 pub fn output_torrc(
 	tor_config_directory: &str,
 	server_p2p_listener_addr: &str,
 	api_listener_addr: &str,
 	libp2p_port: u16,
+	wallet_listener_ports: &[(u16, String)],
 	socks_port: &str,
 	service_dirs: &[String],
+	pow_queue: Option<(u32, u32)>,
 ) -> Result<(), Error> {
 	let torrc_file_path = format!("{}{}{}", tor_config_directory, MAIN_SEPARATOR, TORRC_FILE);
 
@@ -271,6 +313,15 @@ pub fn output_torrc(
 				libp2p_port
 			),
 		);
+		// Wallet listeners are reachable with the same onion address as the node
+		for (port, listener) in wallet_listener_ports {
+			props.add_item("HiddenServicePort", &format!("{} {}", port, listener));
+		}
+		if let Some((rate, burst)) = pow_queue {
+			props.add_item(POW_DEFENSES_ENABLED, "1");
+			props.add_item(POW_QUEUE_RATE, &format!("{}", rate));
+			props.add_item(POW_QUEUE_BURST, &format!("{}", burst));
+		}
 	}
 
 	props.write_to_file(&torrc_file_path)?;
@@ -284,9 +335,11 @@ pub fn output_tor_listener_config(
 	server_p2p_listener_addr: &str,
 	api_listener_addr: &str,
 	libp2p_port: u16,
+	wallet_listener_ports: &[(u16, String)],
 	listener_keys: Option<&[SecretKey]>,
 	onion_address: Option<String>,
 	socks_port: u16,
+	pow_queue: Option<(u32, u32)>,
 ) -> Result<(), Error> {
 	let tor_data_dir = format!("{}{}{}", tor_config_directory, MAIN_SEPARATOR, TOR_DATA_DIR);
 
@@ -312,13 +365,57 @@ pub fn output_tor_listener_config(
 		server_p2p_listener_addr,
 		api_listener_addr,
 		libp2p_port,
+		wallet_listener_ports,
 		&format!("{}", socks_port),
 		&service_dirs,
+		pow_queue,
 	)?;
 
 	Ok(())
 }
 
+/// output tor config for the outbound connections only, no onion service is published
+pub fn output_tor_client_config(tor_config_directory: &str, socks_port: u16) -> Result<(), Error> {
+	let tor_data_dir = format!("{}{}{}", tor_config_directory, MAIN_SEPARATOR, TOR_DATA_DIR);
+
+	// create data directory if it doesn't exist
+	fs::create_dir_all(&tor_data_dir)
+		.map_err(|e| ErrorKind::IO(format!("Unable to create dir {}, {}", tor_data_dir, e)))?;
+
+	output_torrc(
+		tor_config_directory,
+		"",
+		"",
+		0,
+		&[],
+		&format!("{}", socks_port),
+		&[],
+		None,
+	)
+}
+
+/// Update the PoW defense queue rate and burst of the existing torrc file. Tor applies
+/// them after the config reload.
+pub fn update_torrc_pow_queue(torrc_file_path: &str, rate: u32, burst: u32) -> Result<(), Error> {
+	let torrc = fs::read_to_string(torrc_file_path)
+		.map_err(|e| ErrorKind::IO(format!("Unable to read file {}, {}", torrc_file_path, e)))?;
+	let mut res = String::new();
+	for line in torrc.lines() {
+		let name = line.split(' ').next().unwrap_or("");
+		if name == POW_QUEUE_RATE {
+			res.push_str(&format!("{} {}\n", POW_QUEUE_RATE, rate));
+		} else if name == POW_QUEUE_BURST {
+			res.push_str(&format!("{} {}\n", POW_QUEUE_BURST, burst));
+		} else {
+			res.push_str(line);
+			res.push('\n');
+		}
+	}
+	fs::write(torrc_file_path, res)
+		.map_err(|e| ErrorKind::IO(format!("Unable to write file {}, {}", torrc_file_path, e)))?;
+	Ok(())
+}
+
 pub fn _is_tor_address(input: &str) -> Result<(), Error> {
 	match OnionV3Address::try_from(input) {
 		Ok(_) => Ok(()),
@@ -383,14 +480,46 @@ mod tests {
 			"127.0.0.1:3415",
 			"127.0.0.1:3416",
 			1234,
+			&[(3420, "127.0.0.1:3420".to_string())],
 			Some(&[sec_key]),
 			None,
 			0,
+			Some((250, 2500)),
 		)?;
+		let torrc_path = format!("{}{}{}", test_dir, MAIN_SEPARATOR, TORRC_FILE);
+		update_torrc_pow_queue(&torrc_path, 62, 625)?;
+		let torrc = fs::read_to_string(&torrc_path).unwrap();
+		assert!(torrc.contains("HiddenServicePoWDefensesEnabled 1\n"));
+		assert!(torrc.contains("HiddenServicePoWQueueRate 62\n"));
+		assert!(torrc.contains("HiddenServicePoWQueueBurst 625\n"));
+		assert!(!torrc.contains("HiddenServicePoWQueueRate 250"));
 		clean_output_dir(test_dir);
 		Ok(())
 	}
 
+	#[test]
+	fn test_parse_wallet_listener_ports() -> Result<(), Error> {
+		global::set_local_chain_type(global::ChainTypes::AutomatedTesting);
+		let mut ports = BTreeMap::new();
+		ports.insert("3420".to_string(), "127.0.0.1:3415".to_string());
+		ports.insert("3421".to_string(), "unix:/tmp/wallet.sock".to_string());
+		assert_eq!(
+			parse_wallet_listener_ports(&ports)?,
+			vec![
+				(3420, "127.0.0.1:3415".to_string()),
+				(3421, "unix:/tmp/wallet.sock".to_string())
+			]
+		);
+
+		ports.insert("8080".to_string(), "127.0.0.1:3415".to_string());
+		assert!(parse_wallet_listener_ports(&ports).is_err());
+
+		let mut ports = BTreeMap::new();
+		ports.insert("wallet".to_string(), "127.0.0.1:3415".to_string());
+		assert!(parse_wallet_listener_ports(&ports).is_err());
+		Ok(())
+	}
+
 	#[test]
 	fn test_is_tor_address() -> Result<(), Error> {
 		assert!(
@@ -0,0 +1,159 @@
+// Copyright 2020 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Onion service PoW defense effort control. Tor asks the clients for the PoW puzzles
+//! when the intro queue is overloaded, the queue rate and burst define how many
+//! introductions are processed. When the inbound connections flood the onion listener,
+//! the queue is tightened level by level, so the clients have to pay more effort.
+//! When the flood is over, the queue is relaxed back.
+
+use crate::api::TorPowDefenseStatus;
+use chrono::Utc;
+
+/// Max defense level. Every level halves the queue rate and burst.
+pub const POW_DEFENSE_MAX_LEVEL: u8 = 4;
+/// Inbound connections are counted for that window, seconds
+pub const POW_DEFENSE_WINDOW_SECS: i64 = 60;
+/// Number of the calm windows before the level is lowered
+const CALM_WINDOWS_TO_RELAX: u32 = 10;
+
+/// Tracks the inbound connections on the onion listener and adjusts the defense level
+pub struct PowDefense {
+	queue_rate: u32,
+	queue_burst: u32,
+	flood_threshold: u32,
+	level: u8,
+	calm_windows: u32,
+	last_inbound_count: u64,
+	last_inbound_per_min: u32,
+	window_start: i64,
+	since: i64,
+}
+
+impl PowDefense {
+	/// New defense at level 0. inbound_count is the current number of the inbound connections.
+	pub fn new(
+		queue_rate: u32,
+		queue_burst: u32,
+		flood_threshold: u32,
+		inbound_count: u64,
+	) -> Self {
+		let now = Utc::now().timestamp();
+		PowDefense {
+			queue_rate,
+			queue_burst,
+			flood_threshold,
+			level: 0,
+			calm_windows: 0,
+			last_inbound_count: inbound_count,
+			last_inbound_per_min: 0,
+			window_start: now,
+			since: now,
+		}
+	}
+
+	/// Current queue (rate, burst) for the defense level
+	pub fn queue(&self) -> (u32, u32) {
+		(
+			std::cmp::max(1, self.queue_rate >> self.level),
+			std::cmp::max(1, self.queue_burst >> self.level),
+		)
+	}
+
+	/// Check the inbound connections once the window is over. Returns the new
+	/// queue (rate, burst) if the defense level was changed.
+	pub fn update(&mut self, inbound_count: u64, now: i64) -> Option<(u32, u32)> {
+		let elapsed = now - self.window_start;
+		if elapsed < POW_DEFENSE_WINDOW_SECS {
+			return None;
+		}
+		let inbound = inbound_count.saturating_sub(self.last_inbound_count);
+		let per_min = (inbound * 60 / elapsed as u64) as u32;
+		self.last_inbound_count = inbound_count;
+		self.last_inbound_per_min = per_min;
+		self.window_start = now;
+
+		if per_min > self.flood_threshold {
+			self.calm_windows = 0;
+			if self.level < POW_DEFENSE_MAX_LEVEL {
+				self.level += 1;
+				self.since = now;
+				return Some(self.queue());
+			}
+		} else if per_min < self.flood_threshold / 2 {
+			self.calm_windows += 1;
+			if self.level > 0 && self.calm_windows >= CALM_WINDOWS_TO_RELAX {
+				self.calm_windows = 0;
+				self.level -= 1;
+				self.since = now;
+				return Some(self.queue());
+			}
+		} else {
+			self.calm_windows = 0;
+		}
+		None
+	}
+
+	/// Current defense state for the node status
+	pub fn status(&self) -> TorPowDefenseStatus {
+		let (queue_rate, queue_burst) = self.queue();
+		TorPowDefenseStatus {
+			level: self.level,
+			queue_rate,
+			queue_burst,
+			inbound_per_min: self.last_inbound_per_min,
+			since: self.since,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_pow_defense_levels() {
+		let mut defense = PowDefense::new(250, 2500, 120, 0);
+		let start = defense.window_start;
+		assert_eq!(defense.queue(), (250, 2500));
+
+		// Window is not over yet
+		assert_eq!(defense.update(1000, start + 30), None);
+
+		// Flood raises the level every window, up to the max
+		let mut count = 0;
+		let mut time = start;
+		for level in 1..=POW_DEFENSE_MAX_LEVEL {
+			count += 1000;
+			time += POW_DEFENSE_WINDOW_SECS;
+			let queue = defense.update(count, time).unwrap();
+			assert_eq!(queue, (250 >> level, 2500 >> level));
+			assert_eq!(defense.status().level, level);
+		}
+		count += 1000;
+		time += POW_DEFENSE_WINDOW_SECS;
+		assert_eq!(defense.update(count, time), None);
+		assert_eq!(defense.status().inbound_per_min, 1000);
+
+		// Level is lowered after the calm windows
+		for _ in 1..CALM_WINDOWS_TO_RELAX {
+			time += POW_DEFENSE_WINDOW_SECS;
+			assert_eq!(defense.update(count, time), None);
+		}
+		time += POW_DEFENSE_WINDOW_SECS;
+		assert_eq!(defense.update(count, time), Some((250 >> 3, 2500 >> 3)));
+		assert_eq!(defense.status().level, 3);
+		assert_eq!(defense.status().since, time);
+	}
+}
@@ -306,6 +306,27 @@ impl TorProcess {
 		Ok(stdout)
 	}
 
+	/// Path of the torrc file the process is started with
+	pub fn get_torrc_path(&self) -> Option<String> {
+		self.torrc_path.clone()
+	}
+
+	/// Ask Tor to reload the torrc file (SIGHUP). Not supported on Windows.
+	pub fn reload_config(&self) -> Result<(), Error> {
+		match self.process {
+			Some(ref process) => {
+				if get_process(process.id() as i32).kill(Signal::Hangup) {
+					Ok(())
+				} else {
+					Err(Error::Process(
+						"Unable to send reload signal to Tor process".to_string(),
+					))
+				}
+			}
+			None => Err(Error::ProcessNotStarted),
+		}
+	}
+
 	pub fn kill(&mut self) -> Result<(), Error> {
 		if let Some(ref mut process) = self.process {
 			Ok(process
@@ -15,13 +15,16 @@
 /// Grin client commands processing
 use std::net::SocketAddr;
 
+use chrono::{TimeZone, Utc};
 use clap::ArgMatches;
 
 use crate::api::client;
 use crate::api::json_rpc::*;
-use crate::api::types::Status;
+use crate::api::types::{DandelionStatus, Status};
+use crate::api::StandbyStatus;
 use crate::config::GlobalConfig;
 use crate::p2p::types::PeerInfoDisplay;
+use crate::p2p::Libp2pPeerBan;
 use crate::util::file::get_first_line;
 use serde_json::json;
 
@@ -138,6 +141,61 @@ impl HTTPNodeClient {
 		e.reset().unwrap();
 	}
 
+	pub fn show_dandelion_status(&self, redact_relay: bool) {
+		let mut e = term::stdout().unwrap();
+		let params = json!([redact_relay]);
+		match self.send_json_request::<DandelionStatus>("get_dandelion_status", &params) {
+			Ok(status) => {
+				match status.epoch {
+					Some(epoch) => {
+						writeln!(e, "Stem epoch: {}", epoch.is_stem).unwrap();
+						writeln!(e, "Epoch start: {:?}", epoch.start_time).unwrap();
+						writeln!(e, "Epoch expiry: {:?}", epoch.expiry_time).unwrap();
+						writeln!(e, "Relay peer: {:?}", epoch.relay_peer).unwrap();
+					}
+					None => writeln!(e, "Dandelion epoch is not available").unwrap(),
+				}
+				writeln!(e, "Stem transactions: {}", status.stem_txs.len()).unwrap();
+				for tx in status.stem_txs {
+					writeln!(
+						e,
+						"{} ({:?}), received {}, embargo expires {}",
+						tx.tx_hash, tx.src, tx.received, tx.embargo_expiry
+					)
+					.unwrap();
+				}
+			}
+			Err(_) => writeln!(e, "Failed to get Dandelion status").unwrap(),
+		};
+		e.reset().unwrap();
+	}
+
+	pub fn fluff_all(&self) {
+		let mut e = term::stdout().unwrap();
+		match self.send_json_request::<usize>("fluff_all", &serde_json::Value::Null) {
+			Ok(fluffed) => writeln!(e, "Fluffed {} stem transactions", fluffed).unwrap(),
+			Err(_) => writeln!(e, "Failed to fluff stem transactions").unwrap(),
+		};
+		e.reset().unwrap();
+	}
+
+	pub fn promote_to_primary(&self) {
+		let mut e = term::stdout().unwrap();
+		match self
+			.send_json_request::<StandbyStatus>("promote_to_primary", &serde_json::Value::Null)
+		{
+			Ok(status) => writeln!(
+				e,
+				"Node role is {:?}, promoted by {}",
+				status.role,
+				status.promoted_by.unwrap_or_else(|| "nobody".to_string())
+			)
+			.unwrap(),
+			Err(_) => writeln!(e, "Failed to promote the node").unwrap(),
+		};
+		e.reset().unwrap();
+	}
+
 	pub fn verify_chain(&self, assume_valid_rangeproofs_kernels: bool) {
 		let mut e = term::stdout().unwrap();
 		let params = json!([assume_valid_rangeproofs_kernels]);
@@ -178,6 +236,123 @@ impl HTTPNodeClient {
 		};
 		e.reset().unwrap();
 	}
+
+	pub fn ban_libp2p_peer(&self, address: &str, duration_secs: Option<i64>) {
+		let mut e = term::stdout().unwrap();
+		let params = json!([address, duration_secs]);
+		match self.send_json_request::<Libp2pPeerBan>("ban_libp2p_peer", &params) {
+			Ok(ban) => match ban.expires_at {
+				Some(expires_at) => writeln!(
+					e,
+					"Successfully banned libp2p peer {} until {}",
+					ban.address,
+					Utc.timestamp(expires_at, 0)
+				)
+				.unwrap(),
+				None => writeln!(
+					e,
+					"Successfully banned libp2p peer {} permanently",
+					ban.address
+				)
+				.unwrap(),
+			},
+			Err(_) => writeln!(e, "Failed to ban libp2p peer {}", address).unwrap(),
+		};
+		e.reset().unwrap();
+	}
+
+	pub fn unban_libp2p_peer(&self, address: &str) {
+		let mut e = term::stdout().unwrap();
+		let params = json!([address]);
+		match self.send_json_request::<()>("unban_libp2p_peer", &params) {
+			Ok(_) => writeln!(e, "Successfully unbanned libp2p peer {}", address).unwrap(),
+			Err(_) => writeln!(e, "Failed to unban libp2p peer {}", address).unwrap(),
+		};
+		e.reset().unwrap();
+	}
+
+	pub fn list_libp2p_bans(&self) {
+		let mut e = term::stdout().unwrap();
+		match self
+			.send_json_request::<Vec<Libp2pPeerBan>>("get_libp2p_bans", &serde_json::Value::Null)
+		{
+			Ok(bans) => {
+				if bans.is_empty() {
+					writeln!(e, "No banned libp2p peers").unwrap();
+				}
+				for ban in bans {
+					let expires = match ban.expires_at {
+						Some(expires_at) => Utc.timestamp(expires_at, 0).to_string(),
+						None => "never".to_string(),
+					};
+					writeln!(
+						e,
+						"{}  reason: {:?}  banned: {}  expires: {}",
+						ban.address,
+						ban.reason,
+						Utc.timestamp(ban.banned_at, 0),
+						expires
+					)
+					.unwrap();
+				}
+			}
+			Err(_) => writeln!(e, "Failed to get libp2p peer bans").unwrap(),
+		};
+		e.reset().unwrap();
+	}
+
+	pub fn export_peers(&self, file: &str) {
+		let mut e = term::stdout().unwrap();
+		match self.send_json_request::<serde_json::Value>(
+			"export_peer_snapshot",
+			&serde_json::Value::Null,
+		) {
+			Ok(snapshot) => {
+				let res = serde_json::to_string_pretty(&snapshot)
+					.map_err(|e| e.to_string())
+					.and_then(|s| std::fs::write(file, s).map_err(|e| e.to_string()));
+				match res {
+					Ok(_) => writeln!(
+						e,
+						"Peer snapshot is saved into {}, it is signed with the key {}",
+						file, snapshot["public_key"]
+					)
+					.unwrap(),
+					Err(err) => {
+						writeln!(e, "Failed to save peer snapshot into {}, {}", file, err).unwrap()
+					}
+				}
+			}
+			Err(_) => writeln!(e, "Failed to export peer snapshot").unwrap(),
+		};
+		e.reset().unwrap();
+	}
+
+	pub fn import_peers(&self, file: &str, trusted_key: Option<&str>) {
+		let mut e = term::stdout().unwrap();
+		let snapshot = std::fs::read_to_string(file)
+			.map_err(|e| e.to_string())
+			.and_then(|s| serde_json::from_str::<serde_json::Value>(&s).map_err(|e| e.to_string()));
+		let snapshot = match snapshot {
+			Ok(snapshot) => snapshot,
+			Err(err) => {
+				writeln!(e, "Failed to read peer snapshot from {}, {}", file, err).unwrap();
+				e.reset().unwrap();
+				return;
+			}
+		};
+		let params = json!([snapshot, trusted_key]);
+		match self.send_json_request::<serde_json::Value>("import_peer_snapshot", &params) {
+			Ok(res) => writeln!(
+				e,
+				"Imported {} peers, {} skipped",
+				res["imported"], res["skipped"]
+			)
+			.unwrap(),
+			Err(_) => writeln!(e, "Failed to import peer snapshot from {}", file).unwrap(),
+		};
+		e.reset().unwrap();
+	}
 }
 
 pub fn client_command(client_args: &ArgMatches<'_>, global_config: GlobalConfig) -> i32 {
@@ -193,6 +368,15 @@ pub fn client_command(client_args: &ArgMatches<'_>, global_config: GlobalConfig)
 		("listconnectedpeers", Some(_)) => {
 			node_client.list_connected_peers();
 		}
+		("dandelion", Some(args)) => {
+			node_client.show_dandelion_status(args.is_present("redact"));
+		}
+		("fluff-all", Some(_)) => {
+			node_client.fluff_all();
+		}
+		("promote", Some(_)) => {
+			node_client.promote_to_primary();
+		}
 		("verify-chain", Some(args)) => {
 			let assume_valid_rangeproofs_kernels = args.is_present("fast");
 			node_client.verify_chain(assume_valid_rangeproofs_kernels);
@@ -215,6 +399,28 @@ pub fn client_command(client_args: &ArgMatches<'_>, global_config: GlobalConfig)
 				panic!("Invalid peer address format");
 			}
 		}
+		("libp2p-ban", Some(args)) => {
+			let address = args.value_of("address").unwrap();
+			let duration = args.value_of("duration").map(|d| {
+				d.parse::<i64>()
+					.unwrap_or_else(|_| panic!("Invalid ban duration {}", d))
+			});
+			node_client.ban_libp2p_peer(address, duration);
+		}
+		("libp2p-unban", Some(args)) => {
+			node_client.unban_libp2p_peer(args.value_of("address").unwrap());
+		}
+		("libp2p-bans", Some(_)) => {
+			node_client.list_libp2p_bans();
+		}
+		("export-peers", Some(args)) => {
+			let file = args.value_of("file").unwrap();
+			node_client.export_peers(file);
+		}
+		("import-peers", Some(args)) => {
+			let file = args.value_of("file").unwrap();
+			node_client.import_peers(file, args.value_of("key"));
+		}
 		_ => panic!("Unknown client command, use 'grin help client' for details"),
 	}
 	0
@@ -21,6 +21,7 @@ use clap::ArgMatches;
 use ctrlc;
 use futures::channel::oneshot;
 
+use crate::chain;
 use crate::config::GlobalConfig;
 use crate::core::global;
 use crate::p2p::Seeding;
@@ -92,6 +93,42 @@ fn start_server_tui(
 	}
 }
 
+/// Open the chain data in read-only mode and print the chain state. LMDB allows
+/// readers from other processes, so it is safe to run while the node is running.
+fn read_only_command(config: &servers::ServerConfig) -> i32 {
+	let store = match chain::ChainStore::open_read_only(&config.db_root) {
+		Ok(store) => store,
+		Err(e) => {
+			println!(
+				"Unable to open chain data at {} in read-only mode, {}",
+				config.db_root, e
+			);
+			return 1;
+		}
+	};
+	let res = store.head().and_then(|head| {
+		let header_head = store.header_head()?;
+		let tail = store.tail()?;
+		Ok((head, header_head, tail))
+	});
+	match res {
+		Ok((head, header_head, tail)) => {
+			println!("Chain data: {} (read-only)", config.db_root);
+			println!("Chain head: {} at {}", head.last_block_h, head.height);
+			println!(
+				"Header head: {} at {}",
+				header_head.last_block_h, header_head.height
+			);
+			println!("Chain tail: {} at {}", tail.last_block_h, tail.height);
+			0
+		}
+		Err(e) => {
+			println!("Unable to read the chain state, {}", e);
+			1
+		}
+	}
+}
+
 /// Handles the server part of the command line, mostly running, starting and
 /// stopping the Grin blockchain server. Processes all the command line
 /// arguments to build a proper configuration and runs Grin with that
@@ -133,9 +170,21 @@ pub fn server_command(
 			server_config.p2p_config.seeds = Some(PeerAddrs { peers });
 		}
 
+		if let Some(snapshot_dir) = a.value_of("cold_storage") {
+			server_config.cold_storage_config.cold_storage_mode = true;
+			server_config.cold_storage_config.snapshot_dir = Some(snapshot_dir.to_string());
+		}
+
 		allow_to_stop = a.is_present("allow_to_stop");
 	}
 
+	if server_args
+		.map(|a| a.is_present("read_only"))
+		.unwrap_or(false)
+	{
+		return read_only_command(&server_config);
+	}
+
 	if allow_to_stop {
 		warn!("Starting server with activated stop_node API");
 	}
@@ -143,6 +192,12 @@ pub fn server_command(
 	if let Some(a) = server_args {
 		match a.subcommand() {
 			("run", _) => {
+				if a.is_present("takeover") {
+					if let Err(e) = servers::Server::prepare_takeover(&server_config) {
+						println!("Unable to take over from the running node, {}", e);
+						return 1;
+					}
+				}
 				start_server(server_config, logs_rx, allow_to_stop, api_chan);
 			}
 			("", _) => {
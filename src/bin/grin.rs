@@ -154,8 +154,23 @@ fn real_main() -> i32 {
 
 	// Initialize our global chain_type and feature flags (NRD kernel support currently).
 	// These are read via global and not read from config beyond this point.
-	global::init_global_chain_type(config.members.unwrap().server.chain_type);
+	global::init_global_chain_type(config.members.as_ref().unwrap().server.chain_type);
 	info!("Chain: {:?}", global::get_chain_type());
+	let consensus_overlay = config.members.as_ref().unwrap().server.consensus_overlay;
+	if !consensus_overlay.is_empty() {
+		match global::get_chain_type() {
+			global::ChainTypes::Mainnet | global::ChainTypes::Floonet => {
+				warn!("Consensus overlay is applicable to usernet only, it is ignored");
+			}
+			_ => {
+				if let Err(e) = consensus_overlay.validate() {
+					panic!("Invalid consensus overlay, {}", e);
+				}
+				info!("Consensus overlay: {:?}", consensus_overlay);
+				global::init_global_consensus_overlay(consensus_overlay);
+			}
+		}
+	}
 	match global::get_chain_type() {
 		global::ChainTypes::Mainnet => {
 			// Set various mainnet specific feature flags.
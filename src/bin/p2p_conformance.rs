@@ -0,0 +1,144 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Peer protocol conformance tool. Connects to a node and validates that it
+//! follows the MWC p2p protocol: handshake, request/response matrix,
+//! malformed message handling and optionally the ban behavior.
+//! Exit code is non zero if any of the checks failed.
+
+use grin_core as core;
+use grin_p2p as p2p;
+
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::{genesis, global};
+use crate::p2p::conformance::{CheckStatus, ConformanceSuite};
+use clap::{App, Arg};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+fn main() {
+	let exit_code = real_main();
+	std::process::exit(exit_code);
+}
+
+fn real_main() -> i32 {
+	let args =
+		App::new("mwc-p2p-conformance")
+			.version(env!("CARGO_PKG_VERSION"))
+			.about("Validates the MWC peer protocol conformance of a node")
+			.arg(
+				Arg::with_name("target")
+					.help("Address of the node to test, host:port")
+					.required(true)
+					.index(1),
+			)
+			.arg(
+				Arg::with_name("chain")
+					.long("chain")
+					.short("c")
+					.help("Chain the target node runs")
+					.possible_values(&["mainnet", "floonet", "usernet"])
+					.default_value("mainnet")
+					.takes_value(true),
+			)
+			.arg(
+				Arg::with_name("genesis")
+					.long("genesis")
+					.short("g")
+					.help("Genesis block hash, overrides the one of the chain")
+					.takes_value(true),
+			)
+			.arg(
+				Arg::with_name("timeout")
+					.long("timeout")
+					.short("t")
+					.help("Timeout in seconds for connecting and for every expected reply")
+					.default_value("10")
+					.takes_value(true),
+			)
+			.arg(Arg::with_name("ban_checks").long("ban-checks").help(
+				"Also check the ban behavior. The target will ban the host this tool runs from!",
+			))
+			.get_matches();
+
+	let chain_type = match args.value_of("chain").unwrap() {
+		"floonet" => global::ChainTypes::Floonet,
+		"usernet" => global::ChainTypes::UserTesting,
+		_ => global::ChainTypes::Mainnet,
+	};
+	global::init_global_chain_type(chain_type);
+
+	let target = args.value_of("target").unwrap();
+	let target: SocketAddr = match target.to_socket_addrs().map(|mut a| a.next()) {
+		Ok(Some(addr)) => addr,
+		_ => {
+			eprintln!("Unable to resolve target address {}", target);
+			return 2;
+		}
+	};
+
+	let genesis = match args.value_of("genesis") {
+		Some(hex) => match Hash::from_hex(hex) {
+			Ok(hash) => hash,
+			Err(e) => {
+				eprintln!("Invalid genesis hash {}, {:?}", hex, e);
+				return 2;
+			}
+		},
+		None => match chain_type {
+			global::ChainTypes::Mainnet => genesis::genesis_main().hash(),
+			global::ChainTypes::Floonet => genesis::genesis_floo().hash(),
+			_ => genesis::genesis_dev().hash(),
+		},
+	};
+
+	let timeout = match args.value_of("timeout").unwrap().parse::<u64>() {
+		Ok(secs) if secs > 0 => Duration::from_secs(secs),
+		_ => {
+			eprintln!("Invalid timeout, expected a positive number of seconds");
+			return 2;
+		}
+	};
+
+	let mut suite = ConformanceSuite::new(target, genesis);
+	suite.timeout = timeout;
+	suite.ban_checks = args.is_present("ban_checks");
+
+	println!(
+		"Running peer protocol conformance checks against {}, genesis {}",
+		target, genesis
+	);
+	let report = suite.run();
+	for result in &report.results {
+		println!("{}", result);
+	}
+
+	let passed = report
+		.results
+		.iter()
+		.filter(|r| r.status == CheckStatus::Pass)
+		.count();
+	let failed = report.failures().len();
+	println!(
+		"{} passed, {} failed, {} skipped",
+		passed,
+		failed,
+		report.results.len() - passed - failed
+	);
+	if report.passed() {
+		0
+	} else {
+		1
+	}
+}
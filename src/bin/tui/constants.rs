@@ -30,6 +30,8 @@ pub const TABLE_MINING_DIFF_STATUS: &str = "mining_diff_status_table";
 
 // Logs View
 pub const VIEW_LOGS: &str = "logs_view";
+pub const LOGS_BUFFER: &str = "logs_buffer";
+pub const LOGS_SEARCH: &str = "logs_search";
 
 // Mining View
 pub const VIEW_VERSION: &str = "version_view";
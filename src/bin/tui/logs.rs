@@ -12,29 +12,58 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use cursive::theme::{BaseColor, Color, ColorStyle};
+use cursive::direction::{Direction, Orientation};
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::{BaseColor, Color, ColorStyle, Effect};
 use cursive::traits::Identifiable;
 use cursive::view::View;
-use cursive::views::ResizedView;
+use cursive::views::{EditView, LinearLayout, OnEventView, ResizedView, TextView};
 use cursive::{Cursive, Printer};
 
-use crate::tui::constants::VIEW_LOGS;
+use crate::tui::constants::{LOGS_BUFFER, LOGS_SEARCH, MAIN_MENU, VIEW_LOGS};
 use cursive::utils::lines::spans::{LinesIterator, Row};
 use cursive::utils::markup::StyledString;
 use grin_util::logger::LogEntry;
 use log::Level;
 use std::collections::VecDeque;
 
+/// Number of the log lines kept for the filtering and the search
+const LOG_BUFFER_SIZE: usize = 2000;
+
 pub struct TUILogsView;
 
 impl TUILogsView {
 	pub fn create() -> impl View {
-		let logs_view = ResizedView::with_full_screen(LogBufferView::new(200).with_name("logs"));
+		let search_view = LinearLayout::new(Orientation::Horizontal)
+			.child(TextView::new("Search: "))
+			.child(ResizedView::with_full_width(
+				EditView::new()
+					.on_edit(|c, text, _| {
+						let _ = c.call_on_name(LOGS_BUFFER, |t: &mut LogBufferView| {
+							t.set_search(text);
+						});
+					})
+					.on_submit(|c, _| {
+						let _ = c.focus_name(LOGS_BUFFER);
+					})
+					.with_name(LOGS_SEARCH),
+			));
+		let logs_view = LinearLayout::new(Orientation::Vertical)
+			.child(ResizedView::with_full_screen(
+				LogBufferView::new(LOG_BUFFER_SIZE).with_name(LOGS_BUFFER),
+			))
+			.child(search_view)
+			.child(TextView::new(
+				"L: Cycle level  P: Pause/Resume  /: Search  Enter: Apply search  Esc: Back",
+			));
+		let logs_view = OnEventView::new(logs_view).on_pre_event(Key::Esc, |c| {
+			let _ = c.focus_name(MAIN_MENU);
+		});
 		logs_view.with_name(VIEW_LOGS)
 	}
 
 	pub fn update(c: &mut Cursive, entry: LogEntry) {
-		c.call_on_name("logs", |t: &mut LogBufferView| {
+		c.call_on_name(LOGS_BUFFER, |t: &mut LogBufferView| {
 			t.update(entry);
 		});
 	}
@@ -42,25 +71,71 @@ impl TUILogsView {
 
 struct LogBufferView {
 	buffer: VecDeque<LogEntry>,
+	size: usize,
+	// Most verbose level that is displayed
+	max_level: Level,
+	// Lowercase substring the displayed lines must contain
+	search: String,
+	paused: bool,
+	// Number of the entries received since the view was paused, they are not displayed
+	pending: usize,
 }
 
 impl LogBufferView {
 	fn new(size: usize) -> Self {
-		let mut buffer = VecDeque::new();
-		buffer.resize(
+		LogBufferView {
+			buffer: VecDeque::with_capacity(size),
 			size,
-			LogEntry {
-				log: String::new(),
-				level: Level::Info,
-			},
-		);
-
-		LogBufferView { buffer }
+			max_level: Level::Trace,
+			search: String::new(),
+			paused: false,
+			pending: 0,
+		}
 	}
 
 	fn update(&mut self, entry: LogEntry) {
 		self.buffer.push_front(entry);
-		self.buffer.pop_back();
+		if self.buffer.len() > self.size {
+			self.buffer.pop_back();
+		}
+		if self.paused {
+			self.pending = std::cmp::min(self.pending + 1, self.buffer.len());
+		}
+	}
+
+	fn set_search(&mut self, search: &str) {
+		self.search = search.to_lowercase();
+	}
+
+	fn toggle_pause(&mut self) {
+		self.paused = !self.paused;
+		self.pending = 0;
+	}
+
+	fn cycle_level(&mut self) {
+		self.max_level = match self.max_level {
+			Level::Trace => Level::Error,
+			Level::Error => Level::Warn,
+			Level::Warn => Level::Info,
+			Level::Info => Level::Debug,
+			Level::Debug => Level::Trace,
+		};
+	}
+
+	fn is_visible(&self, entry: &LogEntry) -> bool {
+		entry.level <= self.max_level
+			&& (self.search.is_empty() || entry.log.to_lowercase().contains(&self.search))
+	}
+
+	fn status_line(&self) -> String {
+		let mut status = format!("Level: {} and above", self.max_level);
+		if !self.search.is_empty() {
+			status.push_str(&format!("  Search: '{}'", self.search));
+		}
+		if self.paused {
+			status.push_str(&format!("  PAUSED, {} new lines", self.pending));
+		}
+		status
 	}
 
 	fn color(level: Level) -> ColorStyle {
@@ -86,19 +161,55 @@ impl LogBufferView {
 
 impl View for LogBufferView {
 	fn draw(&self, printer: &Printer) {
+		printer.with_effect(Effect::Reverse, |p| {
+			p.print((0, 0), &self.status_line());
+		});
+		// First row is taken by the status line
+		let rows_num = printer.size.y.saturating_sub(1);
 		let mut i = 0;
-		for entry in self.buffer.iter().take(printer.size.y) {
+		for entry in self
+			.buffer
+			.iter()
+			.skip(self.pending)
+			.filter(|e| self.is_visible(e))
+		{
+			if i >= rows_num {
+				break;
+			}
 			printer.with_color(LogBufferView::color(entry.level), |p| {
 				let log_message = StyledString::plain(entry.log.as_str());
 				let mut rows: Vec<Row> = LinesIterator::new(&log_message, printer.size.x).collect();
 				rows.reverse(); // So stack traces are in the right order.
 				for row in rows {
 					for span in row.resolve(&log_message) {
-						p.print((0, p.size.y.saturating_sub(i + 1)), span.content);
+						if i < rows_num {
+							p.print((0, p.size.y.saturating_sub(i + 1)), span.content);
+						}
 						i += 1;
 					}
 				}
 			});
 		}
 	}
+
+	fn take_focus(&mut self, _: Direction) -> bool {
+		true
+	}
+
+	fn on_event(&mut self, event: Event) -> EventResult {
+		match event {
+			Event::Char('l') | Event::Char('L') => {
+				self.cycle_level();
+				EventResult::Consumed(None)
+			}
+			Event::Char('p') | Event::Char('P') => {
+				self.toggle_pause();
+				EventResult::Consumed(None)
+			}
+			Event::Char('/') => EventResult::with_cb(|c| {
+				let _ = c.focus_name(LOGS_SEARCH);
+			}),
+			_ => EventResult::Ignored,
+		}
+	}
 }
@@ -25,8 +25,8 @@ use cursive::views::{
 use cursive::Cursive;
 
 use crate::tui::constants::{
-	MAIN_MENU, ROOT_STACK, SUBMENU_MINING_BUTTON, VIEW_BASIC_STATUS, VIEW_LOGS, VIEW_MINING,
-	VIEW_PEER_SYNC, VIEW_VERSION,
+	LOGS_BUFFER, MAIN_MENU, ROOT_STACK, SUBMENU_MINING_BUTTON, VIEW_BASIC_STATUS, VIEW_LOGS,
+	VIEW_MINING, VIEW_PEER_SYNC, VIEW_VERSION,
 };
 
 pub fn create() -> impl View {
@@ -57,6 +57,8 @@ pub fn create() -> impl View {
 		.set_on_submit(|c: &mut Cursive, v: &str| {
 			if v == VIEW_MINING {
 				let _ = c.focus_name(SUBMENU_MINING_BUTTON);
+			} else if v == VIEW_LOGS {
+				let _ = c.focus_name(LOGS_BUFFER);
 			}
 		});
 	let main_menu = OnEventView::new(main_menu)
@@ -111,6 +111,7 @@ enum DiffColumn {
 	SecondaryScaling,
 	Time,
 	Duration,
+	Fullness,
 }
 
 impl DiffColumn {
@@ -123,6 +124,7 @@ impl DiffColumn {
 			DiffColumn::SecondaryScaling => "Sec. Scaling",
 			DiffColumn::Time => "Block Time",
 			DiffColumn::Duration => "Duration",
+			DiffColumn::Fullness => "Fullness",
 		}
 	}
 }
@@ -145,6 +147,10 @@ impl TableViewItem<DiffColumn> for DiffBlock {
 			DiffColumn::SecondaryScaling => self.secondary_scaling.to_string(),
 			DiffColumn::Time => format!("{}", datetime),
 			DiffColumn::Duration => format!("{}s", self.duration),
+			DiffColumn::Fullness => match self.fullness {
+				Some(fullness) => format!("{:.1}%", fullness),
+				None => "-".to_string(),
+			},
 		}
 	}
 
@@ -160,6 +166,7 @@ impl TableViewItem<DiffColumn> for DiffBlock {
 			DiffColumn::SecondaryScaling => Ordering::Equal,
 			DiffColumn::Time => Ordering::Equal,
 			DiffColumn::Duration => Ordering::Equal,
+			DiffColumn::Fullness => Ordering::Equal,
 		}
 	}
 }
@@ -268,6 +275,11 @@ impl TUIMiningView {
 				LinearLayout::new(Orientation::Horizontal)
 					.child(TextView::new("Average Difficulty: "))
 					.child(TextView::new("").with_name("diff_avg_difficulty")),
+			)
+			.child(
+				LinearLayout::new(Orientation::Horizontal)
+					.child(TextView::new("Average Block Fullness: "))
+					.child(TextView::new("").with_name("diff_avg_fullness")),
 			);
 
 		let diff_table_view = TableView::<DiffBlock, DiffColumn>::new()
@@ -281,7 +293,8 @@ impl TUIMiningView {
 				c.width_percent(10)
 			})
 			.column(DiffColumn::Time, "Block Time", |c| c.width_percent(25))
-			.column(DiffColumn::Duration, "Duration", |c| c.width_percent(25));
+			.column(DiffColumn::Duration, "Duration", |c| c.width_percent(15))
+			.column(DiffColumn::Fullness, "Fullness", |c| c.width_percent(10));
 
 		let mining_difficulty_view = LinearLayout::new(Orientation::Vertical)
 			.child(diff_status_view)
@@ -328,6 +341,12 @@ impl TUIStatusListener for TUIMiningView {
 		c.call_on_name("diff_avg_difficulty", |t: &mut TextView| {
 			t.set_content(stats.diff_stats.average_difficulty.to_string());
 		});
+		c.call_on_name("diff_avg_fullness", |t: &mut TextView| {
+			t.set_content(match stats.diff_stats.average_fullness {
+				Some(fullness) => format!("{:.2}%", fullness),
+				None => "-".to_string(),
+			});
+		});
 
 		let mut diff_stats = stats.diff_stats.last_blocks.clone();
 		diff_stats.reverse();
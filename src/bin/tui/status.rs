@@ -26,7 +26,8 @@ use crate::tui::constants::VIEW_BASIC_STATUS;
 use crate::tui::types::TUIStatusListener;
 
 use crate::chain::SyncStatus;
-use crate::servers::ServerStats;
+use crate::core::core::amount_to_hr_string;
+use crate::servers::{CanaryStatus, ServerStats, TipLagStatus};
 
 const NANO_TO_MILLIS: f64 = 1.0 / 1_000_000.0;
 
@@ -142,6 +143,21 @@ impl TUIStatusView {
 						.child(TextView::new("Disk Usage (GB):              "))
 						.child(TextView::new("0").with_name("disk_usage")),
 				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("Tip Lag:                      "))
+						.child(TextView::new("-").with_name("tip_lag")),
+				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("Consensus Canary:             "))
+						.child(TextView::new("-").with_name("consensus_canary")),
+				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("Libp2p Traffic:               "))
+						.child(TextView::new("-").with_name("libp2p_traffic")),
+				)
 				.child(
 					LinearLayout::new(Orientation::Horizontal).child(TextView::new(
 						"--------------------------------------------------------",
@@ -235,6 +251,17 @@ impl TUIStatusView {
 	}
 }
 
+// Order of the canary states, the worst one is shown
+fn canary_status_severity(status: CanaryStatus) -> u8 {
+	match status {
+		CanaryStatus::Ok => 0,
+		CanaryStatus::Lagging => 1,
+		CanaryStatus::Unreachable => 2,
+		CanaryStatus::Diverging => 3,
+		CanaryStatus::Diverged => 4,
+	}
+}
+
 impl TUIStatusListener for TUIStatusView {
 	fn update(c: &mut Cursive, stats: &ServerStats) {
 		let basic_status = TUIStatusView::update_sync_status(stats.sync_status);
@@ -248,6 +275,55 @@ impl TUIStatusListener for TUIStatusView {
 		c.call_on_name("disk_usage", |t: &mut TextView| {
 			t.set_content(stats.disk_usage_gb.clone());
 		});
+		c.call_on_name("tip_lag", |t: &mut TextView| {
+			t.set_content(match &stats.tip_lag_alert {
+				Some(alert) => match alert.status {
+					TipLagStatus::Ok => "OK".to_string(),
+					TipLagStatus::NoPeers => "No peers".to_string(),
+					TipLagStatus::NetworkQuiet => {
+						format!("Network quiet, tip is {} secs old", alert.tip_age_secs)
+					}
+					TipLagStatus::Lagging | TipLagStatus::Stuck => format!(
+						"{:?}, {} blocks behind the peers",
+						alert.status,
+						alert.peer_height.saturating_sub(alert.height)
+					),
+				},
+				None => "-".to_string(),
+			});
+		});
+		c.call_on_name("consensus_canary", |t: &mut TextView| {
+			let worst = stats
+				.consensus_canary
+				.iter()
+				.max_by_key(|s| canary_status_severity(s.status));
+			t.set_content(match worst {
+				Some(s) => match s.status {
+					CanaryStatus::Ok => format!("OK, {} nodes", stats.consensus_canary.len()),
+					CanaryStatus::Diverged => format!("DIVERGED from {}", s.url),
+					_ => format!("{:?}, {}", s.status, s.url),
+				},
+				None => "-".to_string(),
+			});
+		});
+		c.call_on_name("libp2p_traffic", |t: &mut TextView| {
+			let traffic = &stats.libp2p_stats;
+			t.set_content(
+				if traffic.messages_received == 0 && traffic.bytes_sent == 0 {
+					"-".to_string()
+				} else {
+					let rejected: u64 = traffic.topics.iter().map(|t| t.rejected).sum();
+					format!(
+						"{} msgs ({} rejected), in {:.2} MB, out {:.2} MB, fees {} MWC",
+						traffic.messages_received,
+						rejected,
+						traffic.bytes_received as f64 / 1_000_000.0,
+						traffic.bytes_sent as f64 / 1_000_000.0,
+						amount_to_hr_string(traffic.integrity_fees_total, true)
+					)
+				},
+			);
+		});
 		c.call_on_name("tip_hash", |t: &mut TextView| {
 			t.set_content(stats.chain_stats.last_block_h.to_string() + "...");
 		});
@@ -13,9 +13,31 @@
 // limitations under the License.
 
 //! Storage of core types using LMDB.
+//!
+//! # Read-only mode
+//!
+//! The store can be opened in read-only mode with [`Store::open_read_only`]. It allows
+//! a second process (explorer indexer, analytics job) to read the chain_data while
+//! the node is running and writing into it. LMDB supports a single writer with
+//! multiple readers from the different processes, so the reader never blocks the node.
+//! Rules for the read-only process:
+//! * The environment is never created, the node must initialize the chain_data first.
+//! * Batches can't be created, any write attempt returns `Error::ReadOnlyErr`.
+//! * The database is never resized. When the node grows the map, the read-only store
+//!   adopts the new size on the next read transaction. The size can be adopted only when
+//!   no read transactions of this process are alive, until then the reads fail with
+//!   `MAP_RESIZED`, so the iterators and snapshots must be dropped to recover.
+//! * Keep the read transactions (and iterators) short. LMDB can't reuse the pages that
+//!   are still visible to an open reader, so long living readers make the node db grow.
+//! * Every process takes a slot in the shared reader table. Slots of the crashed
+//!   processes are stale, they are detected and released by [`Store::check_stale_readers`]
+//!   at open and when the reader table is full.
 
 use std::fs;
 use std::marker;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use lmdb_zero as lmdb;
@@ -53,6 +75,9 @@ pub enum Error {
 	/// Other error
 	#[fail(display = "Other Error")]
 	OtherErr(String),
+	/// Write attempt to the store opened in read-only mode
+	#[fail(display = "DB is opened in read-only mode: {}", _0)]
+	ReadOnlyErr(String),
 }
 
 impl From<lmdb::error::Error> for Error {
@@ -83,6 +108,33 @@ pub struct Store {
 	name: String,
 	version: ProtocolVersion,
 	alloc_chunk_size: usize,
+	read_only: bool,
+	// Number of the live read transactions, including the ones of the iterators and snapshots
+	readers: Arc<AtomicUsize>,
+}
+
+/// Keeps the read transaction counted until it is dropped. Must be declared after
+/// the transaction in the structs, so it is dropped after the transaction is closed.
+struct ReaderGuard(Arc<AtomicUsize>);
+
+impl Drop for ReaderGuard {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+/// Read transaction counted by the store
+struct ReadTxn {
+	tx: lmdb::ReadTransaction<'static>,
+	reader: ReaderGuard,
+}
+
+impl Deref for ReadTxn {
+	type Target = lmdb::ReadTransaction<'static>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.tx
+	}
 }
 
 impl Store {
@@ -133,16 +185,67 @@ impl Store {
 			name: db_name,
 			version: DEFAULT_DB_VERSION,
 			alloc_chunk_size,
+			read_only: false,
+			readers: Arc::new(AtomicUsize::new(0)),
 		};
 
-		{
-			let mut w = res.db.write();
-			*w = Some(Arc::new(lmdb::Database::open(
-				res.env.clone(),
-				Some(&res.name),
-				&lmdb::DatabaseOptions::new(lmdb::db::CREATE),
-			)?));
+		res.check_stale_readers()?;
+		res.open()?;
+		Ok(res)
+	}
+
+	/// Open the existing LMDB env under the provided directory in read-only mode.
+	/// Intended for the processes that read the data while the node is running,
+	/// see the module documentation for details.
+	pub fn open_read_only(
+		root_path: &str,
+		env_name: Option<&str>,
+		db_name: Option<&str>,
+		max_readers: Option<u32>,
+	) -> Result<Store, Error> {
+		let name = match env_name {
+			Some(n) => n.to_owned(),
+			None => "lmdb".to_owned(),
+		};
+		let db_name = match db_name {
+			Some(n) => n.to_owned(),
+			None => "lmdb".to_owned(),
+		};
+		let full_path = [root_path.to_owned(), name].join("/");
+		if !Path::new(&full_path).exists() {
+			return Err(Error::FileErr(format!(
+				"DB directory {} doesn't exist, it must be created by the node",
+				full_path
+			)));
+		}
+
+		let mut env_builder = lmdb::EnvBuilder::new()?;
+		env_builder.set_maxdbs(8)?;
+
+		if let Some(max_readers) = max_readers {
+			env_builder.set_maxreaders(max_readers)?;
 		}
+
+		let env =
+			unsafe { env_builder.open(&full_path, lmdb::open::NOTLS | lmdb::open::RDONLY, 0o600)? };
+
+		debug!(
+			"DB {} is opened in read-only mode, mapsize is {}",
+			full_path,
+			env.info()?.mapsize
+		);
+		let res = Store {
+			env: Arc::new(env),
+			db: Arc::new(RwLock::new(None)),
+			name: db_name,
+			version: DEFAULT_DB_VERSION,
+			alloc_chunk_size: 0,
+			read_only: true,
+			readers: Arc::new(AtomicUsize::new(0)),
+		};
+
+		res.check_stale_readers()?;
+		res.open()?;
 		Ok(res)
 	}
 
@@ -159,6 +262,8 @@ impl Store {
 			name: self.name.clone(),
 			version,
 			alloc_chunk_size,
+			read_only: self.read_only,
+			readers: self.readers.clone(),
 		}
 	}
 
@@ -167,17 +272,97 @@ impl Store {
 		self.version
 	}
 
+	/// Whether the store is opened in read-only mode
+	pub fn is_read_only(&self) -> bool {
+		self.read_only
+	}
+
 	/// Opens the database environment
 	pub fn open(&self) -> Result<(), Error> {
 		let mut w = self.db.write();
 		*w = Some(Arc::new(lmdb::Database::open(
 			self.env.clone(),
 			Some(&self.name),
-			&lmdb::DatabaseOptions::new(lmdb::db::CREATE),
+			&self.db_options(),
 		)?));
 		Ok(())
 	}
 
+	// The read-only store must never create the database
+	fn db_options(&self) -> lmdb::DatabaseOptions {
+		if self.read_only {
+			lmdb::DatabaseOptions::defaults()
+		} else {
+			lmdb::DatabaseOptions::new(lmdb::db::CREATE)
+		}
+	}
+
+	/// Release the reader table slots of the processes that are not alive any more.
+	/// Returns the number of the stale readers that were cleared.
+	pub fn check_stale_readers(&self) -> Result<i32, Error> {
+		let stale = self.env.reader_check()?;
+		if stale > 0 {
+			warn!(
+				"Cleared {} stale reader(s) of the DB {}, probably some process that used the DB was terminated",
+				stale, self.name
+			);
+		}
+		Ok(stale)
+	}
+
+	// Start a read transaction. Handles the cases that happen when the DB is shared
+	// between the processes: the map is resized by the writer, or the reader table
+	// is full because of the crashed readers.
+	// The transaction is counted until it is dropped.
+	fn read_transaction(&self) -> Result<ReadTxn, Error> {
+		let res = match self.new_read_transaction() {
+			Err(lmdb::error::Error::Code(lmdb::error::MAP_RESIZED)) => {
+				// The map can be adopted only when there are no transactions in this process.
+				// Holding the lock, so no new transactions are started from this store.
+				let _w = self.db.write();
+				let readers = self.readers.load(Ordering::SeqCst);
+				if readers > 0 {
+					debug!(
+						"DB {} map was resized by the writer, waiting for {} read transaction(s) to finish",
+						self.name, readers
+					);
+					return Err(Error::LmdbErr(lmdb::error::Error::Code(
+						lmdb::error::MAP_RESIZED,
+					)));
+				}
+				debug!("DB {} map was resized by the writer, adopting", self.name);
+				unsafe {
+					self.env.set_mapsize(0)?;
+				}
+				lmdb::ReadTransaction::new(self.env.clone()).map(|tx| self.counted(tx))
+			}
+			Err(lmdb::error::Error::Code(lmdb::error::READERS_FULL)) => {
+				if self.check_stale_readers()? > 0 {
+					self.new_read_transaction()
+				} else {
+					Err(lmdb::error::Error::Code(lmdb::error::READERS_FULL))
+				}
+			}
+			res => res,
+		};
+		Ok(res?)
+	}
+
+	// New counted read transaction. Started under the lock, so the map can't be adopted
+	// before the transaction is counted.
+	fn new_read_transaction(&self) -> lmdb::error::Result<ReadTxn> {
+		let _r = self.db.read();
+		lmdb::ReadTransaction::new(self.env.clone()).map(|tx| self.counted(tx))
+	}
+
+	fn counted(&self, tx: lmdb::ReadTransaction<'static>) -> ReadTxn {
+		self.readers.fetch_add(1, Ordering::SeqCst);
+		ReadTxn {
+			tx,
+			reader: ReaderGuard(self.readers.clone()),
+		}
+	}
+
 	/// Determines whether the environment needs a resize based on a simple percentage threshold
 	pub fn needs_resize(&self) -> Result<bool, Error> {
 		let env_info = self.env.info()?;
@@ -210,6 +395,12 @@ impl Store {
 	/// Increments the database size by as many ALLOC_CHUNK_SIZES
 	/// to give a minimum threshold of free space
 	pub fn do_resize(&self) -> Result<(), Error> {
+		if self.read_only {
+			return Err(Error::ReadOnlyErr(format!(
+				"Unable to resize the DB {}",
+				self.name
+			)));
+		}
 		let env_info = self.env.info()?;
 		let stat = self.env.stat()?;
 		let size_used = stat.psize as usize * env_info.last_pgno;
@@ -235,7 +426,7 @@ impl Store {
 		*w = Some(Arc::new(lmdb::Database::open(
 			self.env.clone(),
 			Some(&self.name),
-			&lmdb::DatabaseOptions::new(lmdb::db::CREATE),
+			&self.db_options(),
 		)?));
 
 		info!(
@@ -250,11 +441,11 @@ impl Store {
 	where
 		F: Fn(&[u8]) -> T,
 	{
+		let txn = self.read_transaction()?;
 		let lock = self.db.read();
 		let db = lock
 			.as_ref()
 			.ok_or_else(|| Error::NotFoundErr("chain db is None".to_string()))?;
-		let txn = lmdb::ReadTransaction::new(self.env.clone())?;
 		let access = txn.access();
 		let res = access.get(db, key);
 		res.map(f).to_opt().map_err(From::from)
@@ -263,11 +454,11 @@ impl Store {
 	/// Gets a `Readable` value from the db, provided its key. Encapsulates
 	/// serialization.
 	pub fn get_ser<T: ser::Readable>(&self, key: &[u8]) -> Result<Option<T>, Error> {
+		let txn = self.read_transaction()?;
 		let lock = self.db.read();
 		let db = lock
 			.as_ref()
 			.ok_or_else(|| Error::NotFoundErr("chain db is None".to_string()))?;
-		let txn = lmdb::ReadTransaction::new(self.env.clone())?;
 		let access = txn.access();
 		self.get_ser_access(key, &access, db.clone())
 	}
@@ -291,11 +482,11 @@ impl Store {
 
 	/// Whether the provided key exists
 	pub fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+		let txn = self.read_transaction()?;
 		let lock = self.db.read();
 		let db = lock
 			.as_ref()
 			.ok_or_else(|| Error::NotFoundErr("chain db is None".to_string()))?;
-		let txn = lmdb::ReadTransaction::new(self.env.clone())?;
 		let access = txn.access();
 		let res: lmdb::error::Result<&lmdb::Ignore> = access.get(db, key);
 		res.to_opt().map(|r| r.is_some()).map_err(From::from)
@@ -304,6 +495,8 @@ impl Store {
 	/// Produces an iterator of (key, value) pairs, where values are `Readable` types
 	/// moving forward from the provided key.
 	pub fn iter<T: ser::Readable>(&self, from: &[u8]) -> Result<SerIterator<T>, Error> {
+		let ReadTxn { tx, reader } = self.read_transaction()?;
+		let tx = Arc::new(tx);
 		let db = self.db.read();
 		let cloned_db = db.as_ref();
 		let cloned_db = if cloned_db.is_some() {
@@ -311,7 +504,6 @@ impl Store {
 		} else {
 			return Err(Error::NotFoundErr("error cloning db".to_string()));
 		};
-		let tx = Arc::new(lmdb::ReadTransaction::new(self.env.clone())?);
 		let cursor = Arc::new(tx.cursor(cloned_db)?);
 		Ok(SerIterator {
 			tx,
@@ -320,11 +512,35 @@ impl Store {
 			prefix: from.to_vec(),
 			version: self.protocol_version(),
 			_marker: marker::PhantomData,
+			_reader: reader,
+		})
+	}
+
+	/// Consistent read-only view of the db. The reads from the snapshot don't see the
+	/// batches committed after the snapshot was taken.
+	pub fn snapshot(&self) -> Result<Snapshot, Error> {
+		let tx = self.read_transaction()?;
+		let db = self
+			.db
+			.read()
+			.as_ref()
+			.ok_or_else(|| Error::NotFoundErr("chain db is None".to_string()))?
+			.clone();
+		Ok(Snapshot {
+			tx,
+			db,
+			version: self.protocol_version(),
 		})
 	}
 
 	/// Builds a new batch to be used with this store.
 	pub fn batch(&self) -> Result<Batch<'_>, Error> {
+		if self.read_only {
+			return Err(Error::ReadOnlyErr(format!(
+				"Unable to start a write batch for the DB {}",
+				self.name
+			)));
+		}
 		// check if the db needs resizing before returning the batch
 		if self.needs_resize()? {
 			self.do_resize()?;
@@ -437,6 +653,37 @@ impl<'a> Batch<'a> {
 	}
 }
 
+/// Read-only view of the db at the moment it was taken. Holds the read transaction,
+/// so the snapshot should not be kept for long.
+pub struct Snapshot {
+	tx: ReadTxn,
+	db: Arc<lmdb::Database<'static>>,
+	version: ProtocolVersion,
+}
+
+impl Snapshot {
+	/// Gets a `Readable` value from the snapshot, provided its key.
+	pub fn get_ser<T: ser::Readable>(&self, key: &[u8]) -> Result<Option<T>, Error> {
+		let access = self.tx.access();
+		let res: lmdb::error::Result<&[u8]> = access.get(&self.db, key);
+		match res.to_opt() {
+			Ok(Some(mut res)) => match ser::deserialize(&mut res, self.version) {
+				Ok(res) => Ok(Some(res)),
+				Err(e) => Err(Error::SerErr(format!("{}", e))),
+			},
+			Ok(None) => Ok(None),
+			Err(e) => Err(From::from(e)),
+		}
+	}
+
+	/// Whether the provided key exists in the snapshot
+	pub fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+		let access = self.tx.access();
+		let res: lmdb::error::Result<&lmdb::Ignore> = access.get(&self.db, key);
+		res.to_opt().map(|r| r.is_some()).map_err(From::from)
+	}
+}
+
 /// An iterator that produces Readable instances back. Wraps the lower level
 /// DBIterator and deserializes the returned values.
 pub struct SerIterator<T>
@@ -449,6 +696,8 @@ where
 	prefix: Vec<u8>,
 	version: ProtocolVersion,
 	_marker: marker::PhantomData<T>,
+	// Keeps the transaction counted, the map is not adopted while the iterator is alive
+	_reader: ReaderGuard,
 }
 
 impl<T> Iterator for SerIterator<T>
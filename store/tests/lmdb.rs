@@ -103,3 +103,34 @@ fn lmdb_allocate() -> Result<(), store::Error> {
 
 	Ok(())
 }
+
+#[test]
+fn lmdb_read_only() -> Result<(), store::Error> {
+	let test_dir = "test_output/lmdb_read_only";
+	setup(test_dir);
+
+	// Read-only mode never creates the environment
+	assert!(store::Store::open_read_only(test_dir, Some("test1"), None, None).is_err());
+
+	let key = store::to_key(b'P', "phat_chunk");
+	{
+		let store = store::Store::new(test_dir, Some("test1"), None, None)?;
+		let batch = store.batch()?;
+		batch.put_ser(&key, &PhatChunkStruct { phatness: 7 })?;
+		batch.commit()?;
+	}
+
+	let store = store::Store::open_read_only(test_dir, Some("test1"), None, None)?;
+	assert!(store.is_read_only());
+	assert_eq!(store.check_stale_readers()?, 0);
+	let chunk: Option<PhatChunkStruct> = store.get_ser(&key)?;
+	assert_eq!(chunk.map(|c| c.phatness), Some(7));
+	assert!(store.exists(&key)?);
+
+	match store.batch() {
+		Err(store::Error::ReadOnlyErr(_)) => {}
+		_ => panic!("Write batch must be rejected in read-only mode"),
+	}
+
+	Ok(())
+}
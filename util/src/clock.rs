@@ -0,0 +1,128 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Clock of the node. The time based logic (sync timeouts, dandelion epochs, integrity
+//! call rate checks, ban expirations) reads the time here instead of calling Utc::now()
+//! and Instant::now() directly, so the tests and the simulation harness can replace the
+//! system clock with the virtual one and fast-forward the time deterministically.
+//!
+//! The clock is selected the same way as the chain type: the thread local clock if it
+//! is set, the global clock otherwise. The global clock is the system clock by default.
+
+use crate::{Mutex, RwLock};
+use chrono::{DateTime, Utc};
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+	/// Current wall clock time
+	fn now(&self) -> DateTime<Utc>;
+	/// Current monotonic time
+	fn instant(&self) -> Instant;
+}
+
+/// System clock, the real time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> DateTime<Utc> {
+		Utc::now()
+	}
+
+	fn instant(&self) -> Instant {
+		Instant::now()
+	}
+}
+
+/// Virtual clock. The time is not moving until it is advanced.
+pub struct VirtualClock {
+	start_time: DateTime<Utc>,
+	start_instant: Instant,
+	elapsed: Mutex<Duration>,
+}
+
+impl VirtualClock {
+	/// Virtual clock that starts at the time
+	pub fn new(start_time: DateTime<Utc>) -> VirtualClock {
+		VirtualClock {
+			start_time,
+			start_instant: Instant::now(),
+			elapsed: Mutex::new(Duration::from_secs(0)),
+		}
+	}
+
+	/// Move the time forward
+	pub fn advance(&self, duration: Duration) {
+		*self.elapsed.lock() += duration;
+	}
+
+	/// Time passed since the start of the clock
+	pub fn elapsed(&self) -> Duration {
+		*self.elapsed.lock()
+	}
+}
+
+impl Clock for VirtualClock {
+	fn now(&self) -> DateTime<Utc> {
+		let elapsed = chrono::Duration::from_std(self.elapsed())
+			.unwrap_or_else(|_| chrono::Duration::max_value());
+		self.start_time + elapsed
+	}
+
+	fn instant(&self) -> Instant {
+		self.start_instant + self.elapsed()
+	}
+}
+
+lazy_static! {
+	static ref GLOBAL_CLOCK: RwLock<Arc<dyn Clock>> = RwLock::new(Arc::new(SystemClock));
+}
+
+thread_local! {
+	static LOCAL_CLOCK: RefCell<Option<Arc<dyn Clock>>> = RefCell::new(None);
+}
+
+/// Set the clock for all threads that don't have the thread local clock
+pub fn set_global_clock(clock: Arc<dyn Clock>) {
+	*GLOBAL_CLOCK.write() = clock;
+}
+
+/// Set the clock on a per-thread basis, for the tests
+pub fn set_local_clock(clock: Arc<dyn Clock>) {
+	LOCAL_CLOCK.with(|c| *c.borrow_mut() = Some(clock));
+}
+
+/// Drop the thread local clock, the global clock is used again
+pub fn reset_local_clock() {
+	LOCAL_CLOCK.with(|c| *c.borrow_mut() = None);
+}
+
+/// Current clock: thread local one, fallback to the global clock
+pub fn clock() -> Arc<dyn Clock> {
+	LOCAL_CLOCK
+		.with(|c| c.borrow().clone())
+		.unwrap_or_else(|| GLOBAL_CLOCK.read().clone())
+}
+
+/// Current wall clock time
+pub fn now() -> DateTime<Utc> {
+	clock().now()
+}
+
+/// Current monotonic time
+pub fn instant() -> Instant {
+	clock().instant()
+}
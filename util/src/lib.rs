@@ -33,7 +33,7 @@ pub use ov3::OnionV3Address;
 pub use ov3::OnionV3Error as OnionV3AddressError;
 
 // Re-export so only has to be included once
-pub use parking_lot::Mutex;
+pub use parking_lot::{Mutex, MutexGuard};
 pub use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 // Re-export so only has to be included once
@@ -68,6 +68,13 @@ pub mod zip;
 mod rate_counter;
 pub use crate::rate_counter::RateCounter;
 
+/// Wall and monotonic clock abstraction, virtual clock for the simulation tests
+pub mod clock;
+pub use crate::clock::{Clock, SystemClock, VirtualClock};
+
+/// Threads, tasks and memory estimates of the node subsystems
+pub mod subsystem;
+
 /// Encapsulation of a RwLock<Option<T>> for one-time initialization.
 /// This implementation will purposefully fail hard if not used
 /// properly, for example if not initialized before being first used
@@ -0,0 +1,176 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resource usage of the node subsystems. Threads and async tasks hold the guard while
+//! they are running, so the counts are always current. Memory is estimated by the probe
+//! that every subsystem registers, estimates are based on the size of the data the
+//! subsystem keeps (pool transactions, cached messages, workers), not on the allocator.
+
+use crate::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Blocks and headers sync
+pub const SUBSYSTEM_SYNC: &str = "sync";
+/// Transaction pool and Dandelion
+pub const SUBSYSTEM_POOL: &str = "pool";
+/// REST and JSON-RPC APIs
+pub const SUBSYSTEM_API: &str = "api";
+/// Stratum mining server
+pub const SUBSYSTEM_STRATUM: &str = "stratum";
+/// Libp2p node and gossip
+pub const SUBSYSTEM_LIBP2P: &str = "libp2p";
+/// Tor process and listener
+pub const SUBSYSTEM_TOR: &str = "tor";
+
+/// Subsystems that are always reported, even if nothing is running
+pub const SUBSYSTEMS: [&str; 6] = [
+	SUBSYSTEM_SYNC,
+	SUBSYSTEM_POOL,
+	SUBSYSTEM_API,
+	SUBSYSTEM_STRATUM,
+	SUBSYSTEM_LIBP2P,
+	SUBSYSTEM_TOR,
+];
+
+/// Memory estimate of the subsystem, in bytes
+pub type MemoryProbe = Arc<dyn Fn() -> u64 + Send + Sync>;
+
+/// Resource usage of the single subsystem
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SubsystemUsage {
+	/// Subsystem name
+	pub name: String,
+	/// Running threads
+	pub threads: u32,
+	/// Running async tasks
+	pub tasks: u32,
+	/// Estimated memory used by the subsystem data, in bytes
+	pub memory_bytes: u64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+	threads: u32,
+	tasks: u32,
+}
+
+lazy_static! {
+	// Running threads and tasks by subsystem
+	static ref SUBSYSTEM_COUNTS: Mutex<HashMap<String, Counts>> = Mutex::new(HashMap::new());
+	// Memory probes by subsystem
+	static ref MEMORY_PROBES: RwLock<HashMap<String, MemoryProbe>> = RwLock::new(HashMap::new());
+}
+
+/// Guard of the running thread or task, the subsystem count is decreased on drop
+pub struct UsageGuard {
+	subsystem: String,
+	is_thread: bool,
+}
+
+impl Drop for UsageGuard {
+	fn drop(&mut self) {
+		if let Some(counts) = SUBSYSTEM_COUNTS.lock().get_mut(&self.subsystem) {
+			if self.is_thread {
+				counts.threads = counts.threads.saturating_sub(1);
+			} else {
+				counts.tasks = counts.tasks.saturating_sub(1);
+			}
+		}
+	}
+}
+
+fn track(subsystem: &str, is_thread: bool) -> UsageGuard {
+	let mut all_counts = SUBSYSTEM_COUNTS.lock();
+	let counts = all_counts.entry(subsystem.to_string()).or_default();
+	if is_thread {
+		counts.threads += 1;
+	} else {
+		counts.tasks += 1;
+	}
+	UsageGuard {
+		subsystem: subsystem.to_string(),
+		is_thread,
+	}
+}
+
+/// Count the current thread as the subsystem thread while the guard is alive.
+/// Call it first thing in the thread closure.
+pub fn track_thread(subsystem: &str) -> UsageGuard {
+	track(subsystem, true)
+}
+
+/// Count the async task of the subsystem while the guard is alive.
+/// Move the guard into the future.
+pub fn track_task(subsystem: &str) -> UsageGuard {
+	track(subsystem, false)
+}
+
+/// Register the memory probe of the subsystem, the previous probe is replaced.
+/// Probe is called by the debug API, it should not wait for the busy locks.
+pub fn register_memory_probe(subsystem: &str, probe: MemoryProbe) {
+	MEMORY_PROBES.write().insert(subsystem.to_string(), probe);
+}
+
+/// Drop the memory probe of the subsystem
+pub fn unregister_memory_probe(subsystem: &str) {
+	MEMORY_PROBES.write().remove(subsystem);
+}
+
+/// Usage of the known subsystems and the ones that registered any thread, task or
+/// probe, sorted by name
+pub fn subsystem_usage() -> Vec<SubsystemUsage> {
+	let counts = SUBSYSTEM_COUNTS.lock().clone();
+	// Probes are called without the lock, they might take a while
+	let probes: Vec<(String, MemoryProbe)> = MEMORY_PROBES
+		.read()
+		.iter()
+		.map(|(name, probe)| (name.clone(), probe.clone()))
+		.collect();
+
+	let mut usage: HashMap<String, SubsystemUsage> = SUBSYSTEMS
+		.iter()
+		.map(|name| {
+			(
+				name.to_string(),
+				SubsystemUsage {
+					name: name.to_string(),
+					..SubsystemUsage::default()
+				},
+			)
+		})
+		.collect();
+	for (name, c) in counts {
+		let u = usage.entry(name.clone()).or_insert_with(|| SubsystemUsage {
+			name,
+			..SubsystemUsage::default()
+		});
+		u.threads = c.threads;
+		u.tasks = c.tasks;
+	}
+	for (name, probe) in probes {
+		let memory_bytes = (probe)();
+		usage
+			.entry(name.clone())
+			.or_insert_with(|| SubsystemUsage {
+				name,
+				..SubsystemUsage::default()
+			})
+			.memory_bytes = memory_bytes;
+	}
+
+	let mut usage: Vec<SubsystemUsage> = usage.into_iter().map(|(_, u)| u).collect();
+	usage.sort_by(|a, b| a.name.cmp(&b.name));
+	usage
+}
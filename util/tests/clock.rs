@@ -0,0 +1,55 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_util as util;
+
+use self::util::clock::{self, Clock, VirtualClock};
+use chrono::{TimeZone, Utc};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn virtual_clock_advance() {
+	let start = Utc.timestamp(1_600_000_000, 0);
+	let clock = VirtualClock::new(start);
+	let instant = clock.instant();
+	assert_eq!(clock.now(), start);
+
+	thread::sleep(Duration::from_millis(10));
+	assert_eq!(clock.now(), start);
+	assert_eq!(clock.instant(), instant);
+
+	clock.advance(Duration::from_secs(3600));
+	assert_eq!(clock.now().timestamp(), 1_600_003_600);
+	assert_eq!(clock.instant() - instant, Duration::from_secs(3600));
+	assert_eq!(clock.elapsed(), Duration::from_secs(3600));
+}
+
+#[test]
+fn local_clock() {
+	let start = Utc.timestamp(1_000_000, 0);
+	let virtual_clock = Arc::new(VirtualClock::new(start));
+	clock::set_local_clock(virtual_clock.clone());
+	assert_eq!(clock::now(), start);
+	virtual_clock.advance(Duration::from_secs(10));
+	assert_eq!(clock::now().timestamp(), 1_000_010);
+
+	// Other threads are using the global system clock
+	let other = thread::spawn(|| clock::now().timestamp()).join().unwrap();
+	assert!(other > 1_500_000_000);
+
+	clock::reset_local_clock();
+	assert!(clock::now().timestamp() > 1_500_000_000);
+}
@@ -0,0 +1,66 @@
+// Copyright 2021 The MWC Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_util as util;
+
+use self::util::subsystem::{self, SubsystemUsage, SUBSYSTEMS, SUBSYSTEM_POOL};
+use std::sync::Arc;
+use std::thread;
+
+fn usage_of(name: &str) -> SubsystemUsage {
+	subsystem::subsystem_usage()
+		.into_iter()
+		.find(|u| u.name == name)
+		.unwrap()
+}
+
+#[test]
+fn subsystem_usage_report() {
+	// Known subsystems are reported even if nothing is running
+	let usage = subsystem::subsystem_usage();
+	for name in SUBSYSTEMS.iter() {
+		assert!(usage.iter().any(|u| u.name == *name));
+	}
+	let mut names: Vec<String> = usage.iter().map(|u| u.name.clone()).collect();
+	names.sort();
+	assert_eq!(
+		names,
+		usage.iter().map(|u| u.name.clone()).collect::<Vec<_>>()
+	);
+
+	// Threads and tasks are counted while the guard is alive
+	let handle = thread::spawn(|| {
+		let _usage = subsystem::track_thread("test_threads");
+		usage_of("test_threads")
+	});
+	assert_eq!(handle.join().unwrap().threads, 1);
+	assert_eq!(usage_of("test_threads").threads, 0);
+
+	let task1 = subsystem::track_task("test_tasks");
+	let task2 = subsystem::track_task("test_tasks");
+	assert_eq!(usage_of("test_tasks").tasks, 2);
+	assert_eq!(usage_of("test_tasks").threads, 0);
+	drop(task1);
+	assert_eq!(usage_of("test_tasks").tasks, 1);
+	drop(task2);
+	assert_eq!(usage_of("test_tasks").tasks, 0);
+
+	// Memory comes from the registered probe, the new probe replaces the old one
+	subsystem::register_memory_probe(SUBSYSTEM_POOL, Arc::new(|| 1000));
+	assert_eq!(usage_of(SUBSYSTEM_POOL).memory_bytes, 1000);
+	subsystem::register_memory_probe(SUBSYSTEM_POOL, Arc::new(|| 2000));
+	assert_eq!(usage_of(SUBSYSTEM_POOL).memory_bytes, 2000);
+	subsystem::unregister_memory_probe(SUBSYSTEM_POOL);
+	assert_eq!(usage_of(SUBSYSTEM_POOL).memory_bytes, 0);
+}